@@ -0,0 +1,785 @@
+//! Curve and fee math of `new_send_swap` pools: pure integer functions the
+//! program calls on-chain and SDKs, bots and aggregators call off-chain to
+//! quote bit-for-bit what it will execute.
+//!
+//! The crate is `no_std`, allocates nothing and has no dependencies.
+//! Failures are `MathError`s, which the program maps onto its own errors.
+//! The `wasm` feature adds `wasm-bindgen` exports for JavaScript (`wasm`),
+//! which need `alloc`; the program never enables it.
+
+#![no_std]
+
+#[cfg(feature = "wasm")]
+extern crate alloc;
+
+use core::fmt;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// Denominator for values expressed in basis points.
+pub const BPS_DENOMINATOR: u16 = 10_000;
+
+/// LP tokens minted for a pool's first deposit (1 LP token with 6 decimals).
+pub const INITIAL_LP_TOKENS: u64 = 1_000_000;
+
+/// Why a calculation was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathError {
+    /// An input is zero or otherwise out of range
+    InvalidAmount,
+    /// A reserve is empty or can't cover the requested output
+    InsufficientLiquidity,
+    /// An intermediate or the result doesn't fit its type
+    ArithmeticOverflow,
+    /// A deposit strays further from the reserve ratio than allowed
+    ImbalanceTooHigh,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MathError::InvalidAmount => "invalid amount",
+            MathError::InsufficientLiquidity => "insufficient liquidity",
+            MathError::ArithmeticOverflow => "arithmetic overflow",
+            MathError::ImbalanceTooHigh => "deposit is too imbalanced",
+        })
+    }
+}
+
+pub type Result<T> = core::result::Result<T, MathError>;
+
+/// Fee and output of a swap, as computed by `compute_swap`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapQuote {
+    pub fee: u64,
+    pub amount_in_after_fee: u64,
+    pub amount_out: u64,
+}
+
+/// How far trading `amount_in` for `amount_out` against the given reserves
+/// moves the spot price (output per input), in basis points of the price
+/// before the trade.
+pub fn price_move_bps(
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in: u64,
+    amount_out: u64,
+) -> Result<u16> {
+    const SCALE: u128 = 1_000_000_000_000;
+    if reserve_in == 0 || reserve_out == 0 {
+        return Err(MathError::InvalidAmount);
+    }
+    let remaining_out = reserve_out
+        .checked_sub(amount_out)
+        .ok_or(MathError::ArithmeticOverflow)?;
+    // The new price over the old, (remaining_out / reserve_out) *
+    // (reserve_in / (reserve_in + amount_in)), in two steps that can't
+    // overflow
+    let kept = remaining_out as u128 * SCALE / reserve_out as u128;
+    let kept = kept * reserve_in as u128 / (reserve_in as u128 + amount_in as u128);
+    Ok(((SCALE - kept) * BPS_DENOMINATOR as u128 / SCALE) as u16)
+}
+
+/// Constant product output for `amount_in` against the given reserves, after
+/// taking the fee in the input token. When `reserve_out * amount_in_after_fee`
+/// overflows a u64, the output is taken from their ratio to nine digits
+/// instead, which rounds it further down.
+pub fn compute_swap(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<SwapQuote> {
+    // Calculate fee using existing fee numerator/denominator
+    let fee = amount_in
+        .checked_mul(fee_numerator)
+        .ok_or(MathError::ArithmeticOverflow)?
+        .checked_div(fee_denominator)
+        .ok_or(MathError::ArithmeticOverflow)?;
+
+    let amount_in_after_fee = amount_in
+        .checked_sub(fee)
+        .ok_or(MathError::ArithmeticOverflow)?;
+
+    // Validate pool has sufficient liquidity
+    if reserve_in == 0 || reserve_out == 0 {
+        return Err(MathError::InsufficientLiquidity);
+    }
+
+    // Calculate amount_out using constant product formula with improved overflow protection
+    // Formula: amount_out = (reserve_out * amount_in_after_fee) / (reserve_in + amount_in_after_fee)
+
+    // First, check if the denominator would overflow
+    let denominator = reserve_in
+        .checked_add(amount_in_after_fee)
+        .ok_or(MathError::ArithmeticOverflow)?;
+
+    let amount_out = match reserve_out.checked_mul(amount_in_after_fee) {
+        Some(numerator) => numerator / denominator,
+        None => {
+            // If direct multiplication would overflow, scale the ratio
+            // amount_in_after_fee / denominator up by a billion, multiply and
+            // scale back down
+            let scale = 1_000_000_000u64;
+
+            let scaled_amount_in = amount_in_after_fee.saturating_mul(scale);
+            let scaled_ratio = scaled_amount_in / denominator;
+            let scaled_amount_out = reserve_out.saturating_mul(scaled_ratio);
+
+            scaled_amount_out / scale
+        }
+    };
+
+    Ok(SwapQuote {
+        fee,
+        amount_in_after_fee,
+        amount_out,
+    })
+}
+
+/// Smallest input for which `compute_swap` returns at least `amount_out`,
+/// inverting the curve and the fee with rounding up. `amount_out` must be
+/// below `reserve_out`.
+pub fn compute_amount_in(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    if amount_out == 0 || reserve_in == 0 {
+        return Err(MathError::InvalidAmount);
+    }
+    if amount_out >= reserve_out {
+        return Err(MathError::InsufficientLiquidity);
+    }
+    if fee_numerator >= fee_denominator {
+        return Err(MathError::InvalidAmount);
+    }
+
+    // amount_in_after_fee = ceil(reserve_in * amount_out / (reserve_out - amount_out))
+    let remaining_out = (reserve_out - amount_out) as u128;
+    let amount_in_after_fee = (reserve_in as u128 * amount_out as u128).div_ceil(remaining_out);
+
+    // The fee is rounded down, so ceil(after_fee * den / (den - num)) leaves
+    // at least `amount_in_after_fee` once it is taken
+    let amount_in = amount_in_after_fee
+        .checked_mul(fee_denominator as u128)
+        .ok_or(MathError::ArithmeticOverflow)?
+        .div_ceil((fee_denominator - fee_numerator) as u128);
+    u64::try_from(amount_in).map_err(|_| MathError::ArithmeticOverflow)
+}
+
+/// Decimals a new pool's LP mint must have for a pair with
+/// `token_a_decimals`/`token_b_decimals`: the larger of the two, so
+/// `compute_lp_tokens` only ever scales amounts up to the LP's decimals and
+/// never rounds a deposit off.
+pub fn lp_mint_decimals(token_a_decimals: u8, token_b_decimals: u8) -> u8 {
+    token_a_decimals.max(token_b_decimals)
+}
+
+/// LP tokens minted for depositing `amount_a`/`amount_b` into reserves
+/// `pool_a`/`pool_b`: `INITIAL_LP_TOKENS` into an empty pool, otherwise the
+/// smaller of the two sides' shares of `lp_supply`, with every amount
+/// brought to `lp_decimals` first.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_lp_tokens(
+    amount_a: u64,
+    amount_b: u64,
+    pool_a: u64,
+    pool_b: u64,
+    lp_supply: u64,
+    lp_decimals: u8,
+    token_a_decimals: u8,
+    token_b_decimals: u8,
+) -> Result<u64> {
+    if pool_a == 0 && pool_b == 0 {
+        return Ok(INITIAL_LP_TOKENS);
+    }
+
+    // Formula: normalized_amount = raw_amount * (10^lp_decimals) / (10^token_decimals)
+    let normalize_amount = |raw_amount: u64, token_decimals: u8| -> Result<u64> {
+        if token_decimals == lp_decimals {
+            Ok(raw_amount)
+        } else if token_decimals > lp_decimals {
+            // Token has more decimals than LP, so divide
+            let divisor = 10u64.pow((token_decimals - lp_decimals) as u32);
+            Ok(raw_amount / divisor)
+        } else {
+            // Token has fewer decimals than LP, so multiply
+            let multiplier = 10u64.pow((lp_decimals - token_decimals) as u32);
+            raw_amount
+                .checked_mul(multiplier)
+                .ok_or(MathError::ArithmeticOverflow)
+        }
+    };
+
+    let normalized_amount_a = normalize_amount(amount_a, token_a_decimals)?;
+    let normalized_amount_b = normalize_amount(amount_b, token_b_decimals)?;
+    let normalized_pool_a = normalize_amount(pool_a, token_a_decimals)?;
+    let normalized_pool_b = normalize_amount(pool_b, token_b_decimals)?;
+
+    // Each side's share of the supply; an empty side's share is zero
+    let share = |normalized_amount: u64, normalized_pool: u64| -> Result<u64> {
+        if normalized_amount == 0 || lp_supply == 0 {
+            return Ok(0);
+        }
+        Ok(normalized_amount
+            .checked_mul(lp_supply)
+            .ok_or(MathError::ArithmeticOverflow)?
+            .checked_div(normalized_pool)
+            .unwrap_or(0))
+    };
+    let lp_tokens_a = share(normalized_amount_a, normalized_pool_a)?;
+    let lp_tokens_b = share(normalized_amount_b, normalized_pool_b)?;
+
+    // Take the minimum to maintain pool balance
+    Ok(lp_tokens_a.min(lp_tokens_b))
+}
+
+/// Largest deposit out of `amount_a`/`amount_b` that matches the reserve
+/// ratio `pool_a`/`pool_b`; an empty pool takes both amounts as they are.
+pub fn compute_optimal_deposit(
+    amount_a: u64,
+    amount_b: u64,
+    pool_a: u64,
+    pool_b: u64,
+) -> (u64, u64) {
+    if pool_a == 0 || pool_b == 0 {
+        return (amount_a, amount_b);
+    }
+    let optimal_b = amount_a as u128 * pool_b as u128 / pool_a as u128;
+    if optimal_b <= amount_b as u128 {
+        (amount_a, optimal_b as u64)
+    } else {
+        // amount_b is the binding side, so this is at most amount_a
+        let optimal_a = amount_b as u128 * pool_a as u128 / pool_b as u128;
+        (optimal_a as u64, amount_b)
+    }
+}
+
+/// Result of `compute_imbalanced_deposit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImbalancedDeposit {
+    pub lp_tokens: u64,
+    /// Fees on the excess side, left in the pool
+    pub imbalance_fee_a: u64,
+    pub imbalance_fee_b: u64,
+}
+
+/// Floor of the square root of `value`.
+pub fn sqrt_u128(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    // Newton's method from an overestimate converges down to the floor
+    let mut x = 1u128 << (128 - value.leading_zeros()).div_ceil(2);
+    loop {
+        let y = (x + value / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// LP tokens for a deposit that may stray from the reserve ratio. The
+/// proportional part is priced like `compute_lp_tokens`. The excess on the
+/// other side is charged the swap fee, as if it were swapped to rebalance,
+/// and the rest is priced as a single-sided deposit,
+/// `supply * (sqrt(reserve * (reserve + excess)) - reserve) / reserve`,
+/// which is never more than swapping part of it and depositing
+/// proportionally would give. The excess may be at most
+/// `max_imbalance_bps` of its side of the deposit.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_imbalanced_deposit(
+    amount_a: u64,
+    amount_b: u64,
+    pool_a: u64,
+    pool_b: u64,
+    lp_supply: u64,
+    lp_decimals: u8,
+    token_a_decimals: u8,
+    token_b_decimals: u8,
+    fee_numerator: u64,
+    fee_denominator: u64,
+    max_imbalance_bps: u16,
+) -> Result<ImbalancedDeposit> {
+    let (proportional_a, proportional_b) =
+        compute_optimal_deposit(amount_a, amount_b, pool_a, pool_b);
+    let proportional_lp = compute_lp_tokens(
+        proportional_a,
+        proportional_b,
+        pool_a,
+        pool_b,
+        lp_supply,
+        lp_decimals,
+        token_a_decimals,
+        token_b_decimals,
+    )?;
+    if pool_a == 0 || pool_b == 0 {
+        return Ok(ImbalancedDeposit {
+            lp_tokens: proportional_lp,
+            imbalance_fee_a: 0,
+            imbalance_fee_b: 0,
+        });
+    }
+
+    let excess_a = amount_a - proportional_a;
+    let excess_b = amount_b - proportional_b;
+    for (excess, amount) in [(excess_a, amount_a), (excess_b, amount_b)] {
+        if excess as u128 * BPS_DENOMINATOR as u128 > amount as u128 * max_imbalance_bps as u128 {
+            return Err(MathError::ImbalanceTooHigh);
+        }
+    }
+
+    let imbalance_fee = |excess: u64| -> Result<u64> {
+        Ok((excess as u128 * fee_numerator as u128)
+            .checked_div(fee_denominator as u128)
+            .ok_or(MathError::ArithmeticOverflow)? as u64)
+    };
+    let imbalance_fee_a = imbalance_fee(excess_a)?;
+    let imbalance_fee_b = imbalance_fee(excess_b)?;
+
+    // Single-sided value of the excess against the reserves and supply left
+    // by the proportional part
+    let supply = lp_supply as u128 + proportional_lp as u128;
+    let single_sided_lp = |excess: u64, reserve: u128| -> Result<u128> {
+        if excess == 0 {
+            return Ok(0);
+        }
+        let grown = sqrt_u128(
+            reserve
+                .checked_mul(reserve + excess as u128)
+                .ok_or(MathError::ArithmeticOverflow)?,
+        );
+        Ok(supply
+            .checked_mul(grown - reserve)
+            .ok_or(MathError::ArithmeticOverflow)?
+            / reserve)
+    };
+    let excess_lp = single_sided_lp(
+        excess_a - imbalance_fee_a,
+        pool_a as u128 + proportional_a as u128,
+    )? + single_sided_lp(
+        excess_b - imbalance_fee_b,
+        pool_b as u128 + proportional_b as u128,
+    )?;
+
+    let lp_tokens = u64::try_from(proportional_lp as u128 + excess_lp)
+        .map_err(|_| MathError::ArithmeticOverflow)?;
+    Ok(ImbalancedDeposit {
+        lp_tokens,
+        imbalance_fee_a,
+        imbalance_fee_b,
+    })
+}
+
+/// Amounts of each token paid out for burning `lp_amount` against reserves
+/// `pool_a`/`pool_b`: its share of `lp_supply` of each, rounded down.
+pub fn compute_withdrawal(
+    lp_amount: u64,
+    pool_a: u64,
+    pool_b: u64,
+    lp_supply: u64,
+) -> Result<(u64, u64)> {
+    if lp_supply == 0 {
+        return Err(MathError::InvalidAmount);
+    }
+
+    // (lp_amount * reserve) / lp_supply, which must not overflow before the
+    // division
+    let share = |reserve: u64| -> Result<u64> {
+        if lp_amount == 0 || reserve == 0 {
+            return Ok(0);
+        }
+        Ok(lp_amount
+            .checked_mul(reserve)
+            .ok_or(MathError::ArithmeticOverflow)?
+            / lp_supply)
+    };
+
+    Ok((share(pool_a)?, share(pool_b)?))
+}
+
+/// Splits a withdrawn `amount` into what is paid out and the `fee_bps`
+/// withdrawal fee left in the pool.
+pub fn apply_withdrawal_fee(amount: u64, fee_bps: u16) -> (u64, u64) {
+    let fee = (amount as u128 * fee_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    (amount - fee, fee)
+}
+
+/// Splits a swap `fee` into the `lp_fee_bps` share left in the pool for the
+/// LPs and the protocol share paid out.
+pub fn split_swap_fee(fee: u64, lp_fee_bps: u16) -> (u64, u64) {
+    let lp_fee = (fee as u128 * lp_fee_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    (lp_fee, fee - lp_fee)
+}
+
+/// Splits a deposited `amount` into what goes into the pool and the
+/// `fee_bps` deposit fee paid to the protocol.
+pub fn apply_deposit_fee(amount: u64, fee_bps: u16) -> (u64, u64) {
+    let fee = (amount as u128 * fee_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    (amount - fee, fee)
+}
+
+/// Splits collected protocol fees into the `share_bps` set aside for
+/// insurance and what is paid to the fee recipients.
+pub fn split_insurance_share(amount: u64, share_bps: u16) -> (u64, u64) {
+    let insurance = (amount as u128 * share_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    (insurance, amount - insurance)
+}
+
+/// `reserve_num / reserve_den` as an unsigned Q64.64 fixed-point number, the
+/// unit of the pool's cumulative prices.
+pub fn q64_price(reserve_num: u64, reserve_den: u64) -> u128 {
+    ((reserve_num as u128) << 64) / reserve_den as u128
+}
+
+/// Price impact of a swap in basis points: how far the execution price
+/// `amount_out / amount_in` falls below the pre-trade spot price
+/// `reserve_out / reserve_in`, fee included. Computed exactly in u128 as
+/// `(amount_in * reserve_out - amount_out * reserve_in) * 10_000 / (amount_in * reserve_out)`.
+/// The division rounds down, and an execution above the spot price counts as
+/// zero.
+pub fn price_impact_bps(amount_in: u64, amount_out: u64, reserve_in: u64, reserve_out: u64) -> u16 {
+    let spot_value = amount_in as u128 * reserve_out as u128;
+    if spot_value == 0 {
+        return 0;
+    }
+    let execution_value = amount_out as u128 * reserve_in as u128;
+    let impact = spot_value.saturating_sub(execution_value) * BPS_DENOMINATOR as u128 / spot_value;
+    impact.min(BPS_DENOMINATOR as u128) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fees `(numerator, denominator)` the exhaustive tests run under: none,
+    /// the default 30 bps and a coarse one that rounds on every unit.
+    const FEES: [(u64, u64); 3] = [(0, 1), (30, 10_000), (1, 3)];
+
+    #[test]
+    fn swaps_never_shrink_the_product() {
+        for (fee_numerator, fee_denominator) in FEES {
+            for reserve_in in 1..=24u64 {
+                for reserve_out in 1..=24u64 {
+                    for amount_in in 0..=48u64 {
+                        let quote = compute_swap(
+                            amount_in,
+                            reserve_in,
+                            reserve_out,
+                            fee_numerator,
+                            fee_denominator,
+                        )
+                        .unwrap();
+                        assert_eq!(quote.fee + quote.amount_in_after_fee, amount_in);
+                        assert!(quote.amount_out < reserve_out);
+                        assert!(
+                            (reserve_in + quote.amount_in_after_fee) as u128
+                                * (reserve_out - quote.amount_out) as u128
+                                >= reserve_in as u128 * reserve_out as u128
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn swap_output_grows_with_the_input() {
+        for (fee_numerator, fee_denominator) in FEES {
+            for reserve_in in 1..=16u64 {
+                for reserve_out in 1..=16u64 {
+                    let mut last = 0;
+                    for amount_in in 0..=64u64 {
+                        let amount_out = compute_swap(
+                            amount_in,
+                            reserve_in,
+                            reserve_out,
+                            fee_numerator,
+                            fee_denominator,
+                        )
+                        .unwrap()
+                        .amount_out;
+                        assert!(amount_out >= last);
+                        last = amount_out;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn amount_in_buys_at_least_the_output() {
+        for (fee_numerator, fee_denominator) in FEES {
+            for reserve_in in 1..=24u64 {
+                for reserve_out in 2..=24u64 {
+                    for amount_out in 1..reserve_out {
+                        let amount_in = compute_amount_in(
+                            amount_out,
+                            reserve_in,
+                            reserve_out,
+                            fee_numerator,
+                            fee_denominator,
+                        )
+                        .unwrap();
+                        let swap = |amount_in| {
+                            compute_swap(
+                                amount_in,
+                                reserve_in,
+                                reserve_out,
+                                fee_numerator,
+                                fee_denominator,
+                            )
+                            .unwrap()
+                            .amount_out
+                        };
+                        assert!(swap(amount_in) >= amount_out);
+                        // Without a fee to round there is no cheaper input
+                        if fee_numerator == 0 {
+                            assert!(swap(amount_in - 1) < amount_out);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn swaps_reject_empty_reserves_and_overflows() {
+        assert_eq!(
+            compute_swap(1, 0, 1, 0, 1),
+            Err(MathError::InsufficientLiquidity)
+        );
+        assert_eq!(
+            compute_swap(1, 1, 0, 0, 1),
+            Err(MathError::InsufficientLiquidity)
+        );
+        assert_eq!(
+            compute_swap(u64::MAX, 1, 1, 2, 1),
+            Err(MathError::ArithmeticOverflow)
+        );
+        assert_eq!(
+            compute_swap(1, 1, 1, 0, 0),
+            Err(MathError::ArithmeticOverflow)
+        );
+        assert_eq!(
+            compute_swap(1, u64::MAX, 1, 0, 1),
+            Err(MathError::ArithmeticOverflow)
+        );
+        assert_eq!(
+            compute_amount_in(1, 1, 1, 0, 1),
+            Err(MathError::InsufficientLiquidity)
+        );
+        assert_eq!(
+            compute_amount_in(1, 1, 2, 1, 1),
+            Err(MathError::InvalidAmount)
+        );
+        assert_eq!(
+            compute_amount_in(u64::MAX - 1, u64::MAX, u64::MAX, 0, 1),
+            Err(MathError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn deposits_then_withdrawals_never_gain() {
+        for pool_a in 1..=8u64 {
+            for pool_b in 1..=8u64 {
+                for lp_supply in 1..=8u64 {
+                    for amount_a in 0..=8u64 {
+                        for amount_b in 0..=8u64 {
+                            let lp_tokens = compute_lp_tokens(
+                                amount_a, amount_b, pool_a, pool_b, lp_supply, 9, 9, 9,
+                            )
+                            .unwrap();
+                            let (out_a, out_b) = compute_withdrawal(
+                                lp_tokens,
+                                pool_a + amount_a,
+                                pool_b + amount_b,
+                                lp_supply + lp_tokens,
+                            )
+                            .unwrap();
+                            assert!(out_a <= amount_a && out_b <= amount_b);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn first_deposit_mints_the_initial_tokens() {
+        assert_eq!(
+            compute_lp_tokens(1, u64::MAX, 0, 0, 0, 9, 6, 9),
+            Ok(INITIAL_LP_TOKENS)
+        );
+    }
+
+    #[test]
+    fn lp_tokens_compare_amounts_at_the_lp_decimals() {
+        // 1 token of each side, 6 and 9 decimals, against reserves of 10 and
+        // 20 tokens: the shares are a tenth and a twentieth of the supply
+        assert_eq!(
+            compute_lp_tokens(
+                1_000_000,
+                1_000_000_000,
+                10_000_000,
+                20_000_000_000,
+                1_000,
+                lp_mint_decimals(6, 9),
+                6,
+                9,
+            ),
+            Ok(50)
+        );
+        // Scaling up past a u64 overflows rather than wrapping
+        assert_eq!(
+            compute_lp_tokens(u64::MAX, 1, 1, 1, 1, 9, 6, 9),
+            Err(MathError::ArithmeticOverflow)
+        );
+        assert_eq!(
+            compute_lp_tokens(u64::MAX, u64::MAX, 1, 1, 2, 9, 9, 9),
+            Err(MathError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn withdrawals_pay_shares_of_the_reserves() {
+        for pool_a in 0..=12u64 {
+            for pool_b in 0..=12u64 {
+                for lp_supply in 1..=12u64 {
+                    assert_eq!(
+                        compute_withdrawal(lp_supply, pool_a, pool_b, lp_supply),
+                        Ok((pool_a, pool_b))
+                    );
+                    for lp_amount in 0..=lp_supply {
+                        let (amount_a, amount_b) =
+                            compute_withdrawal(lp_amount, pool_a, pool_b, lp_supply).unwrap();
+                        let (rest_a, rest_b) =
+                            compute_withdrawal(lp_supply - lp_amount, pool_a, pool_b, lp_supply)
+                                .unwrap();
+                        assert!(amount_a + rest_a <= pool_a && amount_b + rest_b <= pool_b);
+                    }
+                }
+            }
+        }
+        assert_eq!(
+            compute_withdrawal(1, 1, 1, 0),
+            Err(MathError::InvalidAmount)
+        );
+        assert_eq!(
+            compute_withdrawal(u64::MAX, 2, 0, u64::MAX),
+            Err(MathError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn optimal_deposits_keep_the_reserve_ratio() {
+        for pool_a in 1..=10u64 {
+            for pool_b in 1..=10u64 {
+                for amount_a in 0..=20u64 {
+                    for amount_b in 0..=20u64 {
+                        let (a, b) = compute_optimal_deposit(amount_a, amount_b, pool_a, pool_b);
+                        assert!(a <= amount_a && b <= amount_b);
+                        // One side is used in full and the other rounded
+                        // down to the ratio
+                        assert!(
+                            (a == amount_a && b == amount_a * pool_b / pool_a)
+                                || (b == amount_b && a == amount_b * pool_a / pool_b)
+                        );
+                    }
+                }
+            }
+        }
+        assert_eq!(compute_optimal_deposit(3, 5, 0, 0), (3, 5));
+    }
+
+    #[test]
+    fn imbalanced_deposits_charge_the_excess() {
+        let pool = 1_000_000_000;
+        let supply = 1_000_000;
+        // A balanced deposit is priced like `compute_lp_tokens`, with no fee
+        let balanced =
+            compute_imbalanced_deposit(1_000, 1_000, pool, pool, supply, 9, 9, 9, 30, 10_000, 0)
+                .unwrap();
+        assert_eq!(
+            balanced,
+            ImbalancedDeposit {
+                lp_tokens: compute_lp_tokens(1_000, 1_000, pool, pool, supply, 9, 9, 9).unwrap(),
+                imbalance_fee_a: 0,
+                imbalance_fee_b: 0,
+            }
+        );
+        // Half of it excess token A is charged the fee on that half and
+        // earns less than the balanced deposit of the same size would
+        let imbalanced = compute_imbalanced_deposit(
+            20_000_000, 10_000_000, pool, pool, supply, 9, 9, 9, 30, 10_000, 5_000,
+        )
+        .unwrap();
+        assert_eq!(imbalanced.imbalance_fee_a, 30_000);
+        assert_eq!(imbalanced.imbalance_fee_b, 0);
+        let balanced_lp =
+            compute_lp_tokens(15_000_000, 15_000_000, pool, pool, supply, 9, 9, 9).unwrap();
+        assert!(imbalanced.lp_tokens < balanced_lp);
+        assert_eq!(
+            compute_imbalanced_deposit(
+                20_000_000, 10_000_000, pool, pool, supply, 9, 9, 9, 30, 10_000, 4_999,
+            ),
+            Err(MathError::ImbalanceTooHigh)
+        );
+    }
+
+    #[test]
+    fn sqrt_rounds_down() {
+        for value in 0..=10_000u128 {
+            let root = sqrt_u128(value);
+            assert!(root * root <= value && (root + 1) * (root + 1) > value);
+        }
+        for root in [u32::MAX as u128, u64::MAX as u128] {
+            assert_eq!(sqrt_u128(root * root), root);
+            assert_eq!(sqrt_u128(root * root - 1), root - 1);
+        }
+        assert_eq!(sqrt_u128(u128::MAX), u64::MAX as u128);
+    }
+
+    #[test]
+    fn fee_splits_add_up() {
+        for amount in [0, 1, 7, 9_999, 10_001, 1_000_000_007, u64::MAX] {
+            for bps in 0..=BPS_DENOMINATOR {
+                let (kept, fee) = apply_deposit_fee(amount, bps);
+                assert_eq!(kept + fee, amount);
+                assert_eq!(apply_withdrawal_fee(amount, bps), (kept, fee));
+                assert_eq!(split_swap_fee(amount, bps), (fee, kept));
+                assert_eq!(split_insurance_share(amount, bps), (fee, kept));
+            }
+            assert_eq!(apply_deposit_fee(amount, BPS_DENOMINATOR), (0, amount));
+        }
+    }
+
+    #[test]
+    fn prices_and_impact() {
+        assert_eq!(q64_price(2, 1), 2 << 64);
+        assert_eq!(q64_price(1, 3), (1u128 << 64) / 3);
+        // 996,006 out for 1,000,000 in against equal reserves: 39 bps below
+        // spot, fee included, and a 19 bps move of the spot price itself
+        assert_eq!(
+            price_impact_bps(1_000_000, 996_006, 1_000_000_000, 1_000_000_000),
+            39
+        );
+        assert_eq!(price_impact_bps(0, 0, 1, 1), 0);
+        assert_eq!(price_impact_bps(1, 2, 1, 1), 0);
+        assert_eq!(
+            price_move_bps(1_000_000_000, 1_000_000_000, 1_000_000, 996_006),
+            Ok(19)
+        );
+        assert_eq!(price_move_bps(0, 1, 1, 0), Err(MathError::InvalidAmount));
+        assert_eq!(
+            price_move_bps(1, 1, 1, 2),
+            Err(MathError::ArithmeticOverflow)
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! `wasm-bindgen` exports for JavaScript clients, built with the `wasm`
+//! feature. Amounts go in and out as decimal strings, which `BigInt` reads
+//! and writes, since a JavaScript number loses u64 precision. A failing
+//! quote throws an `Error` whose message is the `MathError` variant, such as
+//! `InsufficientLiquidity`.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    apply_deposit_fee, apply_withdrawal_fee, compute_amount_in, compute_lp_tokens, compute_swap,
+    compute_withdrawal, price_impact_bps, MathError,
+};
+
+fn parse_u64(name: &str, value: &str) -> Result<u64, JsError> {
+    value
+        .parse()
+        .map_err(|_| JsError::new(&format!("{name} is not a u64: {value:?}")))
+}
+
+fn math_error(error: MathError) -> JsError {
+    JsError::new(&format!("{error:?}"))
+}
+
+/// Result of `quote_swap_exact_in`, as the program's `quote_swap` returns it.
+#[wasm_bindgen(getter_with_clone)]
+pub struct SwapExactInQuote {
+    pub amount_out: String,
+    pub fee: String,
+    pub price_impact_bps: u16,
+}
+
+/// Result of `quote_remove_liquidity`.
+#[wasm_bindgen(getter_with_clone)]
+pub struct RemoveLiquidityQuote {
+    pub amount_a: String,
+    pub amount_b: String,
+}
+
+/// Output and fee of swapping `amount_in` against the given reserves, with
+/// the pool's fee `fee_numerator / fee_denominator`, like the program's
+/// `quote_swap`.
+#[wasm_bindgen]
+pub fn quote_swap_exact_in(
+    amount_in: &str,
+    reserve_in: &str,
+    reserve_out: &str,
+    fee_numerator: &str,
+    fee_denominator: &str,
+) -> Result<SwapExactInQuote, JsError> {
+    let amount_in = parse_u64("amount_in", amount_in)?;
+    if amount_in == 0 {
+        return Err(math_error(MathError::InvalidAmount));
+    }
+    let reserve_in = parse_u64("reserve_in", reserve_in)?;
+    let reserve_out = parse_u64("reserve_out", reserve_out)?;
+    let quote = compute_swap(
+        amount_in,
+        reserve_in,
+        reserve_out,
+        parse_u64("fee_numerator", fee_numerator)?,
+        parse_u64("fee_denominator", fee_denominator)?,
+    )
+    .map_err(math_error)?;
+    Ok(SwapExactInQuote {
+        amount_out: quote.amount_out.to_string(),
+        fee: quote.fee.to_string(),
+        price_impact_bps: price_impact_bps(amount_in, quote.amount_out, reserve_in, reserve_out),
+    })
+}
+
+/// Input `swap_exact_out` charges for `amount_out` against the given
+/// reserves, with the pool's fee `fee_numerator / fee_denominator`.
+#[wasm_bindgen]
+pub fn quote_swap_exact_out(
+    amount_out: &str,
+    reserve_in: &str,
+    reserve_out: &str,
+    fee_numerator: &str,
+    fee_denominator: &str,
+) -> Result<String, JsError> {
+    let amount_in = compute_amount_in(
+        parse_u64("amount_out", amount_out)?,
+        parse_u64("reserve_in", reserve_in)?,
+        parse_u64("reserve_out", reserve_out)?,
+        parse_u64("fee_numerator", fee_numerator)?,
+        parse_u64("fee_denominator", fee_denominator)?,
+    )
+    .map_err(math_error)?;
+    Ok(amount_in.to_string())
+}
+
+/// LP tokens `add_liquidity` mints for depositing `amount_a`/`amount_b`,
+/// after the pool's `deposit_fee_bps`, like the program's
+/// `quote_add_liquidity`.
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn quote_add_liquidity(
+    amount_a: &str,
+    amount_b: &str,
+    pool_a: &str,
+    pool_b: &str,
+    lp_supply: &str,
+    lp_decimals: u8,
+    token_a_decimals: u8,
+    token_b_decimals: u8,
+    deposit_fee_bps: u16,
+) -> Result<String, JsError> {
+    let (amount_a, _) = apply_deposit_fee(parse_u64("amount_a", amount_a)?, deposit_fee_bps);
+    let (amount_b, _) = apply_deposit_fee(parse_u64("amount_b", amount_b)?, deposit_fee_bps);
+    let lp_tokens = compute_lp_tokens(
+        amount_a,
+        amount_b,
+        parse_u64("pool_a", pool_a)?,
+        parse_u64("pool_b", pool_b)?,
+        parse_u64("lp_supply", lp_supply)?,
+        lp_decimals,
+        token_a_decimals,
+        token_b_decimals,
+    )
+    .map_err(math_error)?;
+    Ok(lp_tokens.to_string())
+}
+
+/// Tokens `remove_liquidity` pays out for burning `lp_amount`, after the
+/// pool's `withdrawal_fee_bps`, like the program's `quote_remove_liquidity`.
+#[wasm_bindgen]
+pub fn quote_remove_liquidity(
+    lp_amount: &str,
+    pool_a: &str,
+    pool_b: &str,
+    lp_supply: &str,
+    withdrawal_fee_bps: u16,
+) -> Result<RemoveLiquidityQuote, JsError> {
+    let lp_amount = parse_u64("lp_amount", lp_amount)?;
+    if lp_amount == 0 {
+        return Err(math_error(MathError::InvalidAmount));
+    }
+    let (amount_a, amount_b) = compute_withdrawal(
+        lp_amount,
+        parse_u64("pool_a", pool_a)?,
+        parse_u64("pool_b", pool_b)?,
+        parse_u64("lp_supply", lp_supply)?,
+    )
+    .map_err(math_error)?;
+    let (amount_a, _) = apply_withdrawal_fee(amount_a, withdrawal_fee_bps);
+    let (amount_b, _) = apply_withdrawal_fee(amount_b, withdrawal_fee_bps);
+    Ok(RemoveLiquidityQuote {
+        amount_a: amount_a.to_string(),
+        amount_b: amount_b.to_string(),
+    })
+}
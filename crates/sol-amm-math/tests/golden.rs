@@ -0,0 +1,144 @@
+//! Golden vectors in `vectors.json`, shared with the program's integration
+//! tests and the wasm bindings' tests: amounts are decimal strings, so
+//! JavaScript reads them without losing precision, and a failing case names
+//! its `MathError` under `error`.
+
+use serde_json::Value;
+use sol_amm_math::*;
+
+fn vectors(section: &str) -> Vec<Value> {
+    let vectors: Value = serde_json::from_str(include_str!("vectors.json")).unwrap();
+    let cases = vectors[section].as_array().unwrap().clone();
+    assert!(!cases.is_empty(), "no {section} vectors");
+    cases
+}
+
+fn u64_at(case: &Value, key: &str) -> u64 {
+    case[key].as_str().unwrap().parse().unwrap()
+}
+
+fn u8_at(case: &Value, key: &str) -> u8 {
+    case[key].as_u64().unwrap() as u8
+}
+
+fn u16_at(case: &Value, key: &str) -> u16 {
+    case[key].as_u64().unwrap() as u16
+}
+
+/// The `error` a case expects, if any.
+fn error_at(case: &Value) -> Option<MathError> {
+    Some(match case.get("error")?.as_str().unwrap() {
+        "InvalidAmount" => MathError::InvalidAmount,
+        "InsufficientLiquidity" => MathError::InsufficientLiquidity,
+        "ArithmeticOverflow" => MathError::ArithmeticOverflow,
+        "ImbalanceTooHigh" => MathError::ImbalanceTooHigh,
+        other => panic!("unknown error {other}"),
+    })
+}
+
+#[test]
+fn swap_exact_in_vectors() {
+    for case in vectors("swap_exact_in") {
+        let amount_in = u64_at(&case, "amount_in");
+        let reserve_in = u64_at(&case, "reserve_in");
+        let reserve_out = u64_at(&case, "reserve_out");
+        let result = compute_swap(
+            amount_in,
+            reserve_in,
+            reserve_out,
+            u64_at(&case, "fee_numerator"),
+            u64_at(&case, "fee_denominator"),
+        );
+        match error_at(&case) {
+            Some(error) => assert_eq!(result, Err(error), "{case}"),
+            None => {
+                let quote = result.unwrap();
+                assert_eq!(
+                    quote,
+                    SwapQuote {
+                        fee: u64_at(&case, "fee"),
+                        amount_in_after_fee: u64_at(&case, "amount_in_after_fee"),
+                        amount_out: u64_at(&case, "amount_out"),
+                    },
+                    "{case}"
+                );
+                assert_eq!(
+                    price_impact_bps(amount_in, quote.amount_out, reserve_in, reserve_out),
+                    u16_at(&case, "price_impact_bps"),
+                    "{case}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn swap_exact_out_vectors() {
+    for case in vectors("swap_exact_out") {
+        let result = compute_amount_in(
+            u64_at(&case, "amount_out"),
+            u64_at(&case, "reserve_in"),
+            u64_at(&case, "reserve_out"),
+            u64_at(&case, "fee_numerator"),
+            u64_at(&case, "fee_denominator"),
+        );
+        match error_at(&case) {
+            Some(error) => assert_eq!(result, Err(error), "{case}"),
+            None => assert_eq!(result, Ok(u64_at(&case, "amount_in")), "{case}"),
+        }
+    }
+}
+
+#[test]
+fn add_liquidity_vectors() {
+    for case in vectors("add_liquidity") {
+        let result = compute_lp_tokens(
+            u64_at(&case, "amount_a"),
+            u64_at(&case, "amount_b"),
+            u64_at(&case, "pool_a"),
+            u64_at(&case, "pool_b"),
+            u64_at(&case, "lp_supply"),
+            u8_at(&case, "lp_decimals"),
+            u8_at(&case, "token_a_decimals"),
+            u8_at(&case, "token_b_decimals"),
+        );
+        match error_at(&case) {
+            Some(error) => assert_eq!(result, Err(error), "{case}"),
+            None => assert_eq!(result, Ok(u64_at(&case, "lp_tokens")), "{case}"),
+        }
+    }
+}
+
+#[test]
+fn remove_liquidity_vectors() {
+    for case in vectors("remove_liquidity") {
+        let result = compute_withdrawal(
+            u64_at(&case, "lp_amount"),
+            u64_at(&case, "pool_a"),
+            u64_at(&case, "pool_b"),
+            u64_at(&case, "lp_supply"),
+        );
+        match error_at(&case) {
+            Some(error) => assert_eq!(result, Err(error), "{case}"),
+            None => assert_eq!(
+                result,
+                Ok((u64_at(&case, "amount_a"), u64_at(&case, "amount_b"))),
+                "{case}"
+            ),
+        }
+    }
+}
+
+#[test]
+fn bps_fee_vectors() {
+    for case in vectors("bps_fee") {
+        let amount = u64_at(&case, "amount");
+        let bps = u16_at(&case, "bps");
+        let share = u64_at(&case, "share");
+        let rest = u64_at(&case, "rest");
+        assert_eq!(apply_deposit_fee(amount, bps), (rest, share), "{case}");
+        assert_eq!(apply_withdrawal_fee(amount, bps), (rest, share), "{case}");
+        assert_eq!(split_swap_fee(amount, bps), (share, rest), "{case}");
+        assert_eq!(split_insurance_share(amount, bps), (share, rest), "{case}");
+    }
+}
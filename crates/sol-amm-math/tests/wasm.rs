@@ -0,0 +1,205 @@
+//! Parity of the `wasm` exports with the native functions on the golden
+//! vectors, run under Node with
+//! `wasm-pack test --node crates/sol-amm-math --features wasm`.
+
+#![cfg(all(feature = "wasm", target_arch = "wasm32"))]
+
+use serde_json::Value;
+use sol_amm_math::wasm::*;
+use sol_amm_math::*;
+use wasm_bindgen::{JsError, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn vectors(section: &str) -> Vec<Value> {
+    let vectors: Value = serde_json::from_str(include_str!("vectors.json")).unwrap();
+    vectors[section].as_array().unwrap().clone()
+}
+
+fn str_at<'a>(case: &'a Value, key: &str) -> &'a str {
+    case[key].as_str().unwrap()
+}
+
+fn u64_at(case: &Value, key: &str) -> u64 {
+    str_at(case, key).parse().unwrap()
+}
+
+fn u8_at(case: &Value, key: &str) -> u8 {
+    case[key].as_u64().unwrap() as u8
+}
+
+/// The message of the `Error` an export throws.
+fn message(error: JsError) -> String {
+    js_sys::Error::from(JsValue::from(error)).message().into()
+}
+
+/// The variant name a failing case expects, as the exports throw it.
+fn expected_error(case: &Value) -> Option<&str> {
+    case.get("error").map(|error| error.as_str().unwrap())
+}
+
+#[wasm_bindgen_test]
+fn swap_exact_in_matches_native() {
+    for case in vectors("swap_exact_in") {
+        let quoted = quote_swap_exact_in(
+            str_at(&case, "amount_in"),
+            str_at(&case, "reserve_in"),
+            str_at(&case, "reserve_out"),
+            str_at(&case, "fee_numerator"),
+            str_at(&case, "fee_denominator"),
+        );
+        let native = compute_swap(
+            u64_at(&case, "amount_in"),
+            u64_at(&case, "reserve_in"),
+            u64_at(&case, "reserve_out"),
+            u64_at(&case, "fee_numerator"),
+            u64_at(&case, "fee_denominator"),
+        );
+        match (quoted, native) {
+            (Ok(quote), Ok(native)) => {
+                assert_eq!(quote.amount_out, native.amount_out.to_string());
+                assert_eq!(quote.amount_out, str_at(&case, "amount_out"));
+                assert_eq!(quote.fee, native.fee.to_string());
+                assert_eq!(
+                    quote.price_impact_bps as u64,
+                    case["price_impact_bps"].as_u64().unwrap()
+                );
+            }
+            (Err(error), Err(native)) => {
+                assert_eq!(message(error), format!("{native:?}"));
+                assert_eq!(expected_error(&case), Some(format!("{native:?}").as_str()));
+            }
+            _ => panic!("wasm and native quotes disagree on {case}"),
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+fn swap_exact_out_matches_native() {
+    for case in vectors("swap_exact_out") {
+        let quoted = quote_swap_exact_out(
+            str_at(&case, "amount_out"),
+            str_at(&case, "reserve_in"),
+            str_at(&case, "reserve_out"),
+            str_at(&case, "fee_numerator"),
+            str_at(&case, "fee_denominator"),
+        );
+        let native = compute_amount_in(
+            u64_at(&case, "amount_out"),
+            u64_at(&case, "reserve_in"),
+            u64_at(&case, "reserve_out"),
+            u64_at(&case, "fee_numerator"),
+            u64_at(&case, "fee_denominator"),
+        );
+        match (quoted, native) {
+            (Ok(amount_in), Ok(native)) => {
+                assert_eq!(amount_in, native.to_string());
+                assert_eq!(amount_in, str_at(&case, "amount_in"));
+            }
+            (Err(error), Err(native)) => {
+                assert_eq!(message(error), format!("{native:?}"));
+                assert_eq!(expected_error(&case), Some(format!("{native:?}").as_str()));
+            }
+            _ => panic!("wasm and native quotes disagree on {case}"),
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+fn add_liquidity_matches_native() {
+    for case in vectors("add_liquidity") {
+        for deposit_fee_bps in [0, 30] {
+            let quoted = quote_add_liquidity(
+                str_at(&case, "amount_a"),
+                str_at(&case, "amount_b"),
+                str_at(&case, "pool_a"),
+                str_at(&case, "pool_b"),
+                str_at(&case, "lp_supply"),
+                u8_at(&case, "lp_decimals"),
+                u8_at(&case, "token_a_decimals"),
+                u8_at(&case, "token_b_decimals"),
+                deposit_fee_bps,
+            );
+            let native = compute_lp_tokens(
+                apply_deposit_fee(u64_at(&case, "amount_a"), deposit_fee_bps).0,
+                apply_deposit_fee(u64_at(&case, "amount_b"), deposit_fee_bps).0,
+                u64_at(&case, "pool_a"),
+                u64_at(&case, "pool_b"),
+                u64_at(&case, "lp_supply"),
+                u8_at(&case, "lp_decimals"),
+                u8_at(&case, "token_a_decimals"),
+                u8_at(&case, "token_b_decimals"),
+            );
+            match (quoted, native) {
+                (Ok(lp_tokens), Ok(native)) => {
+                    assert_eq!(lp_tokens, native.to_string());
+                    if deposit_fee_bps == 0 {
+                        assert_eq!(lp_tokens, str_at(&case, "lp_tokens"));
+                    }
+                }
+                (Err(error), Err(native)) => {
+                    assert_eq!(message(error), format!("{native:?}"));
+                }
+                _ => panic!("wasm and native quotes disagree on {case}"),
+            }
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+fn remove_liquidity_matches_native() {
+    for case in vectors("remove_liquidity") {
+        if u64_at(&case, "lp_amount") == 0 {
+            // The quote refuses to burn nothing, like the program's
+            assert_eq!(
+                message(quote_remove_liquidity("0", "1", "1", "1", 0).err().unwrap()),
+                "InvalidAmount"
+            );
+            continue;
+        }
+        for withdrawal_fee_bps in [0, 30] {
+            let quoted = quote_remove_liquidity(
+                str_at(&case, "lp_amount"),
+                str_at(&case, "pool_a"),
+                str_at(&case, "pool_b"),
+                str_at(&case, "lp_supply"),
+                withdrawal_fee_bps,
+            );
+            let native = compute_withdrawal(
+                u64_at(&case, "lp_amount"),
+                u64_at(&case, "pool_a"),
+                u64_at(&case, "pool_b"),
+                u64_at(&case, "lp_supply"),
+            )
+            .map(|(amount_a, amount_b)| {
+                (
+                    apply_withdrawal_fee(amount_a, withdrawal_fee_bps).0,
+                    apply_withdrawal_fee(amount_b, withdrawal_fee_bps).0,
+                )
+            });
+            match (quoted, native) {
+                (Ok(quote), Ok((amount_a, amount_b))) => {
+                    assert_eq!(quote.amount_a, amount_a.to_string());
+                    assert_eq!(quote.amount_b, amount_b.to_string());
+                    if withdrawal_fee_bps == 0 {
+                        assert_eq!(quote.amount_a, str_at(&case, "amount_a"));
+                        assert_eq!(quote.amount_b, str_at(&case, "amount_b"));
+                    }
+                }
+                (Err(error), Err(native)) => {
+                    assert_eq!(message(error), format!("{native:?}"));
+                }
+                _ => panic!("wasm and native quotes disagree on {case}"),
+            }
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+fn malformed_amounts_are_rejected() {
+    for amount in ["", "-1", "1.5", "18446744073709551616"] {
+        let error = quote_swap_exact_out(amount, "1", "2", "0", "1")
+            .err()
+            .unwrap();
+        assert!(message(error).starts_with("amount_out is not a u64"));
+    }
+}
@@ -0,0 +1,118 @@
+// The IDL instructions generated by `#[program]` still call `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+//! Mock governance program for `new_send_swap` pools, standing in for a
+//! multisig or DAO: a pool's authority can be this program's `authority`
+//! PDA, and the governance admin has it sign `new_send_swap` instructions
+//! through `execute`, the way such programs sign for their vaults.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::invoke_signed;
+use new_send_swap::program::NewSendSwap;
+
+declare_id!("6ZQzXLuUjgmzM5tjapppuX8R16WmwAGVRPACfszY8zeM");
+
+#[error_code]
+pub enum GovernanceError {
+    #[msg("Signer is not the governance admin")]
+    NotAdmin,
+}
+
+#[program]
+pub mod mock_governance {
+    use super::*;
+
+    /// Creates a governance with the signer as its admin. Its authority PDA
+    /// holds no data, so it stays a system account that can pay rent once
+    /// funded.
+    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+        let governance = &mut ctx.accounts.governance;
+        governance.admin = ctx.accounts.admin.key();
+        governance.authority_bump = ctx.bumps.authority;
+        Ok(())
+    }
+
+    /// Runs the `new_send_swap` instruction `data` over the remaining
+    /// accounts, signing for the governance's authority PDA wherever it
+    /// appears. The authority is passed unsigned, as only this program can
+    /// sign for it.
+    pub fn execute<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Execute<'info>>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let authority = ctx.accounts.authority.key();
+        let accounts = ctx
+            .remaining_accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer || account.key() == authority,
+                is_writable: account.is_writable,
+            })
+            .collect();
+
+        let mut account_infos = ctx.remaining_accounts.to_vec();
+        account_infos.push(ctx.accounts.new_send_swap_program.to_account_info());
+
+        let governance = ctx.accounts.governance.key();
+        let seeds = [
+            b"authority".as_ref(),
+            governance.as_ref(),
+            &[ctx.accounts.governance.authority_bump],
+        ];
+        invoke_signed(
+            &Instruction {
+                program_id: new_send_swap::ID,
+                accounts,
+                data,
+            },
+            &account_infos,
+            &[&seeds[..]],
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(init, payer = admin, space = 8 + Governance::LEN)]
+    pub governance: Account<'info, Governance>,
+
+    /// CHECK: only derived here; it signs through `execute`
+    #[account(seeds = [b"authority", governance.key().as_ref()], bump)]
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Execute<'info> {
+    #[account(has_one = admin @ GovernanceError::NotAdmin)]
+    pub governance: Account<'info, Governance>,
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"authority", governance.key().as_ref()],
+        bump = governance.authority_bump,
+    )]
+    pub authority: SystemAccount<'info>,
+
+    pub new_send_swap_program: Program<'info, NewSendSwap>,
+}
+
+/// A governance whose `admin` decides what its authority PDA,
+/// `["authority", governance]`, signs.
+#[account]
+pub struct Governance {
+    pub admin: Pubkey,
+    pub authority_bump: u8,
+}
+
+impl Governance {
+    pub const LEN: usize = 32 + 1;
+}
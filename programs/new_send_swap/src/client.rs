@@ -0,0 +1,269 @@
+//! Helpers for off-chain consumers of the program.
+
+use std::io::{self, Read};
+
+use anchor_lang::Event;
+
+use crate::events::SCHEMA_VERSION;
+
+/// Schema version of the payloads emitted before events carried a
+/// `version`.
+pub const UNVERSIONED_SCHEMA_VERSION: u8 = 0;
+
+/// Decodes an event payload, discriminator first, as `emit!` logs it or
+/// `emit_cpi!` passes it after the event instruction tag, emitted under
+/// schema `version`: `SCHEMA_VERSION` or `UNVERSIONED_SCHEMA_VERSION`, whose
+/// payloads decode with a `version` of 0.
+///
+/// Fields a payload from an older release lacks decode as their default,
+/// zero, `false`, `None` or empty, and fields appended by a newer release
+/// are ignored. A payload cut short in the middle of a field can't be told
+/// apart from an older one, so the trailing fields then read as zero.
+pub fn decode_event<T: Event>(version: u8, data: &[u8]) -> io::Result<T> {
+    let payload = data
+        .strip_prefix(T::DISCRIMINATOR)
+        .ok_or_else(|| invalid("event discriminator mismatch"))?;
+    let prefix: &[u8] = match version {
+        SCHEMA_VERSION => {
+            if payload.first() != Some(&version) {
+                return Err(invalid("event version mismatch"));
+            }
+            &[]
+        }
+        UNVERSIONED_SCHEMA_VERSION => &[UNVERSIONED_SCHEMA_VERSION],
+        _ => return Err(invalid("unsupported event version")),
+    };
+    T::deserialize_reader(&mut prefix.chain(ZeroPadded(payload)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the bytes, then zeros forever, the encoding of a default value of
+/// every type an event holds.
+struct ZeroPadded<'a>(&'a [u8]);
+
+impl Read for ZeroPadded<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0.is_empty() {
+            buf.fill(0);
+            return Ok(buf.len());
+        }
+        self.0.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::*;
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::Discriminator;
+
+    /// `data` with its version byte dropped, as emitted before versioning.
+    fn unversioned<T: Discriminator>(data: &[u8]) -> Vec<u8> {
+        let len = T::DISCRIMINATOR.len();
+        [&data[..len], &data[len + 1..]].concat()
+    }
+
+    fn round_trips<T: Event>() {
+        let mut current = T::DISCRIMINATOR.to_vec();
+        current.push(SCHEMA_VERSION);
+        let event: T = decode_event(SCHEMA_VERSION, &current).unwrap();
+        let data = event.data();
+        assert_eq!(data[T::DISCRIMINATOR.len()], SCHEMA_VERSION);
+        let decoded: T = decode_event(SCHEMA_VERSION, &data).unwrap();
+        assert_eq!(decoded.data(), data);
+
+        let legacy = unversioned::<T>(&data);
+        let decoded: T = decode_event(UNVERSIONED_SCHEMA_VERSION, &legacy).unwrap();
+        let mut expected = data.clone();
+        expected[T::DISCRIMINATOR.len()] = UNVERSIONED_SCHEMA_VERSION;
+        assert_eq!(decoded.data(), expected);
+    }
+
+    macro_rules! round_trip_all {
+        ($($event:ty),* $(,)?) => {
+            $(round_trips::<$event>();)*
+        };
+    }
+
+    #[test]
+    fn every_event_round_trips_at_each_version() {
+        round_trip_all!(
+            PoolCreatedEvent,
+            LiquidityAddedEvent,
+            SwapExecutedEvent,
+            InitialPriceSetEvent,
+            LiquidityRemovedEvent,
+            FlashLoanEvent,
+            FlashLoanRepaidEvent,
+            ReservesSyncedEvent,
+            SurplusSkimmedEvent,
+            SkimEvent,
+            PoolResetEvent,
+            PoolMigratedEvent,
+            ObservationCardinalityIncreasedEvent,
+            DonationEvent,
+            LiquidityMigratedEvent,
+            LockCreatedEvent,
+            LockExtendedEvent,
+            LiquidityUnlockedEvent,
+            LiquidityBurnedEvent,
+            FarmInitializedEvent,
+            EmissionScheduleSetEvent,
+            FarmFundedEvent,
+            StakedEvent,
+            UnstakedEvent,
+            RewardsClaimedEvent,
+            GatingUpdatedEvent,
+            AllowlistUpdatedEvent,
+            TradeStartUpdatedEvent,
+            LaunchLimitSetEvent,
+            FeeModeSetEvent,
+            BuybackEvent,
+            DiscountConfigSetEvent,
+            SwapCommittedEvent,
+            SwapCommitReclaimedEvent,
+            SwapHookSetEvent,
+            MaxImbalanceSetEvent,
+            MetadataUpdatedEvent,
+            CircuitBreakerSetEvent,
+            TradeLimitsSetEvent,
+            MinTradeAmountInSetEvent,
+            CircuitBreakerTrippedEvent,
+            DepositFeeSetEvent,
+            WithdrawalFeeSetEvent,
+            ProtocolFeeRecipientSetEvent,
+            SwapSplitEvent,
+            OrderCreatedEvent,
+            OrderFilledEvent,
+            OrderCancelledEvent,
+            AuthorityTransferProposedEvent,
+            AuthorityTransferredEvent,
+            AuthorityTransferCancelledEvent,
+            PoolRolesSetEvent,
+            AuthorityRenouncedEvent,
+            PoolStatusChangedEvent,
+            PoolClosedEvent,
+            FeeSplitSetEvent,
+            ProtocolFeesCollectedEvent,
+            ProtocolFeesAccruedEvent,
+            ProtocolFeesCollectedInMintEvent,
+            FeeRecipientsSetEvent,
+            ConfigUpdatedEvent,
+            ParamChangeProposedEvent,
+            ParamChangeAppliedEvent,
+            FeeUpdatedEvent,
+            FeeRecipientChangedEvent,
+            ParamChangeCancelledEvent,
+            EmergencyWithdrawEvent,
+            FeeTiersUpdatedEvent,
+            PoolCreatorUpdatedEvent,
+            BadgeIssuedEvent,
+            BadgeRevokedEvent,
+            InsuranceVaultsCreatedEvent,
+            InsuranceDisbursedEvent,
+            VaultMigratedEvent,
+            OracleGuardSetEvent,
+        );
+    }
+
+    fn swap_event() -> SwapExecutedEvent {
+        SwapExecutedEvent {
+            version: SCHEMA_VERSION,
+            pool: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            token_in: Pubkey::new_unique(),
+            token_out: Pubkey::new_unique(),
+            amount_in: 1_000_000,
+            amount_out: 996_006,
+            fee: 3_000,
+            lp_fee: 2_500,
+            protocol_fee: 500,
+            fee_discount_bps: 0,
+            fill_bps: 10_000,
+            timestamp: 1_700_000_000,
+            slot: 250_000_000,
+            reserve_in_after: 1_001_000_000,
+            reserve_out_after: 999_003_994,
+            spot_price_after: 18_385_066_371_316_400_000,
+            reserve_in_before: 1_000_000_000,
+            reserve_out_before: 1_000_000_000,
+            price_impact_bps: 39,
+            sequence: 7,
+            k_before: 1_000_000_000_000_000_000,
+            k_after: 1_000_003_997_994_000_000,
+            memo: Some("INV-2024-0042".to_string()),
+        }
+    }
+
+    #[test]
+    fn swap_event_round_trips_at_each_version() {
+        let event = swap_event();
+        let data = event.data();
+        let decoded: SwapExecutedEvent = decode_event(SCHEMA_VERSION, &data).unwrap();
+        assert_eq!(decoded.data(), data);
+
+        let decoded: SwapExecutedEvent = decode_event(
+            UNVERSIONED_SCHEMA_VERSION,
+            &unversioned::<SwapExecutedEvent>(&data),
+        )
+        .unwrap();
+        assert_eq!(decoded.version, UNVERSIONED_SCHEMA_VERSION);
+        assert_eq!(decoded.k_after, event.k_after);
+        assert_eq!(decoded.memo, event.memo);
+        assert_eq!(decoded.pool, event.pool);
+    }
+
+    #[test]
+    fn old_payload_decodes_with_defaults_for_missing_fields() {
+        let event = swap_event();
+        // A release before `sequence`, `k_before`, `k_after` and `memo`
+        let data = event.data();
+        let old = &data[..data.len() - 8 - 16 - 16 - (1 + 4 + 13)];
+
+        for (version, payload) in [
+            (SCHEMA_VERSION, old.to_vec()),
+            (
+                UNVERSIONED_SCHEMA_VERSION,
+                unversioned::<SwapExecutedEvent>(old),
+            ),
+        ] {
+            let decoded: SwapExecutedEvent = decode_event(version, &payload).unwrap();
+            assert_eq!(decoded.version, version);
+            assert_eq!(decoded.price_impact_bps, event.price_impact_bps);
+            assert_eq!(decoded.reserve_out_before, event.reserve_out_before);
+            assert_eq!(decoded.sequence, 0);
+            assert_eq!(decoded.k_before, 0);
+            assert_eq!(decoded.k_after, 0);
+            assert_eq!(decoded.memo, None);
+        }
+    }
+
+    #[test]
+    fn newer_payload_ignores_appended_fields() {
+        let event = swap_event();
+        let mut data = event.data();
+        data.extend_from_slice(&[0xff; 12]);
+        let decoded: SwapExecutedEvent = decode_event(SCHEMA_VERSION, &data).unwrap();
+        assert_eq!(decoded.k_after, event.k_after);
+    }
+
+    #[test]
+    fn mismatched_payloads_are_rejected() {
+        let data = swap_event().data();
+        // Another event's discriminator
+        assert!(decode_event::<FlashLoanEvent>(SCHEMA_VERSION, &data).is_err());
+        // An unversioned payload read as the current version
+        assert!(decode_event::<SwapExecutedEvent>(
+            SCHEMA_VERSION,
+            &unversioned::<SwapExecutedEvent>(&data)
+        )
+        .is_err());
+        // A version this decoder doesn't know
+        assert!(decode_event::<SwapExecutedEvent>(SCHEMA_VERSION + 1, &data).is_err());
+    }
+}
@@ -0,0 +1,1431 @@
+//! Events the program emits.
+//!
+//! Every event starts with `version`, the `SCHEMA_VERSION` it was emitted
+//! under. Within a version events only gain fields at the end: a decoder
+//! reads a payload from an older release by defaulting the fields it lacks,
+//! as `client::decode_event` does, and ignores fields a newer release
+//! appended. A change that can't be made that way, such as removing,
+//! retyping or reordering a field, bumps `SCHEMA_VERSION`.
+
+use crate::*;
+
+/// Layout version of the events, carried as their leading `version` field.
+/// Payloads from before events were versioned have no such field and count
+/// as version 0.
+pub const SCHEMA_VERSION: u8 = 1;
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolCreatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_a_mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_b_mint: Pubkey,
+    pub pool_index: u16,
+    /// The pool's index in the registry
+    pub registry_index: u64,
+    pub total_fee_bps: u16,
+    pub trade_start_timestamp: i64,
+    pub trade_limits: TradeLimits,
+    /// Lamports the creator paid to the config's treasury
+    pub creation_fee_lamports: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+    /// The creator, who becomes the pool authority
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub lp_mint: Pubkey,
+    pub sequence: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LiquidityAddedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub user: Pubkey,
+    /// The LP token account the LP tokens were minted to
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub lp_recipient: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub lp_tokens_minted: u64,
+    pub pool_token_a_balance: u64,
+    pub pool_token_b_balance: u64,
+    /// Swap fee charged on the excess side of an imbalanced deposit and left
+    /// in the pool
+    pub imbalance_fee_a: u64,
+    pub imbalance_fee_b: u64,
+    /// Deposit fee paid to the protocol, not counted towards the LP minted
+    pub deposit_fee_a: u64,
+    pub deposit_fee_b: u64,
+    /// `user` is the config's treasury, so the LP counts as protocol-owned
+    pub is_protocol: bool,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+    pub sequence: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwapExecutedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub user: Pubkey,
+    /// Token account the output was delivered to
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub recipient: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_in: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_out: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// Fee actually charged, after any holder discount
+    pub fee: u64,
+    /// Part of `fee` left in the pool for the LPs
+    pub lp_fee: u64,
+    /// Part of `fee` owed to the fee recipient, or paid into the fee vault
+    /// on a buyback pool
+    pub protocol_fee: u64,
+    /// Holder discount applied to the pool fee, in basis points
+    pub fee_discount_bps: u16,
+    /// Share of the requested output that was filled, in basis points; only
+    /// below 10,000 for a partial `swap_exact_out`
+    pub fill_bps: u16,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+    /// The pool's reserves of the input and output tokens after the swap, so
+    /// an indexer can resynchronize from any one swap. These are the tracked
+    /// reserves the pool prices with, not the vault balances, which also
+    /// hold protocol fees owed and any donations
+    pub reserve_in_after: u64,
+    pub reserve_out_after: u64,
+    /// `reserve_out_after / reserve_in_after` as Q64.64: the output tokens
+    /// one input token is worth at the new reserves
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub spot_price_after: u128,
+    /// The tracked reserves the swap was priced against
+    pub reserve_in_before: u64,
+    pub reserve_out_before: u64,
+    /// How far the execution price fell below the spot price before the
+    /// swap, fee included, as computed by `price_impact_bps` and reported by
+    /// `quote_swap`
+    pub price_impact_bps: u16,
+    pub sequence: u64,
+    /// The constant product `reserve_in * reserve_out` of the reserves the
+    /// swap was priced against and of those after it. Fees only ever grow
+    /// it, so `k_after < k_before` means something is wrong
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub k_before: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub k_after: u128,
+    /// The memo the swap carried, if any; only `swap` takes one
+    pub memo: Option<String>,
+}
+
+/// The deposit that priced a pool, emitted once per pool along with its
+/// `LiquidityAddedEvent`.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InitialPriceSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub depositor: Pubkey,
+    /// Deposited into the reserves, after any deposit fee; their ratio is
+    /// the launch price
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub lp_minted: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LiquidityRemovedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub user: Pubkey,
+    /// Token accounts the withdrawn tokens were delivered to
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub recipient_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub recipient_b: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub lp_amount: u64,
+    pub pool_token_a_balance: u64,
+    pub pool_token_b_balance: u64,
+    /// Withdrawal fee kept in the pool, already taken out of the amounts
+    pub withdrawal_fee_a: u64,
+    pub withdrawal_fee_b: u64,
+    /// `user` is the config's treasury, so the LP came off the protocol-owned
+    /// amount
+    pub is_protocol: bool,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+    pub sequence: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlashLoanEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub borrower: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlashLoanRepaidEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub repayer: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    pub vault_balance: u64,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReservesSyncedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub reserve_a_before: u64,
+    pub reserve_b_before: u64,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SurplusSkimmedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub to_token_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub to_token_b: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+/// Surplus sent to the config's treasury by `skim_surplus`, with the
+/// reserves and owed fees it left in place.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SkimEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub treasury: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub treasury_token_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub treasury_token_b: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    pub protocol_fees_owed_a: u64,
+    pub protocol_fees_owed_b: u64,
+    pub timestamp: i64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// A drained pool returned to its unseeded state by `reset_pool`.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolResetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub to_token_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub to_token_b: Pubkey,
+    /// Leftovers swept out of the vaults
+    pub amount_a: u64,
+    pub amount_b: u64,
+    /// The reserves that were cleared
+    pub reserve_a_before: u64,
+    pub reserve_b_before: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// A pool brought up to the current account layout by `migrate_pool`.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolMigratedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub payer: Pubkey,
+    /// The pool's layout version before and after
+    pub previous_pool_version: u8,
+    pub pool_version: u8,
+    /// Account data length before and after, discriminator included
+    pub previous_len: u64,
+    pub len: u64,
+    /// The fee converted from the legacy fraction, if it still had one
+    pub total_fee_bps: u16,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// A pool's observation buffer created (from a cardinality of 0) or grown.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObservationCardinalityIncreasedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub payer: Pubkey,
+    pub previous_cardinality: u16,
+    pub cardinality: u16,
+    pub timestamp: i64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DonationEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub donor: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LiquidityMigratedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub source_pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub destination_pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub user: Pubkey,
+    pub lp_burned: u64,
+    /// Withdrawn from the source pool, after its withdrawal fee
+    pub amount_a: u64,
+    pub amount_b: u64,
+    /// Deposited into the destination pool; the rest stayed with the user
+    pub deposited_a: u64,
+    pub deposited_b: u64,
+    /// Destination pool's deposit fee, paid to the protocol
+    pub deposit_fee_a: u64,
+    pub deposit_fee_b: u64,
+    pub lp_minted: u64,
+    /// Each pool's `sequence` after the migration
+    pub source_sequence: u64,
+    pub destination_sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LockCreatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub lock: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub unlock_timestamp: i64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LockExtendedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub lock: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    pub previous_unlock_timestamp: i64,
+    pub unlock_timestamp: i64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LiquidityUnlockedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub lock: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub amount: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+/// LP tokens burned for good. The pool's reserves are untouched, so each of
+/// the `remaining_lp_supply` tokens now redeems for a larger share.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LiquidityBurnedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub user: Pubkey,
+    pub lp_burned: u64,
+    pub total_burned: u64,
+    pub remaining_lp_supply: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FarmInitializedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub farm: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub reward_mint: Pubkey,
+    pub reward_rate_per_second: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmissionScheduleSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub farm: Pubkey,
+    /// Rate in effect now
+    pub reward_rate_per_second: u64,
+    /// Entries still queued, including the new ones
+    pub schedule: Vec<EmissionEntry>,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FarmFundedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub farm: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub funder: Pubkey,
+    pub amount: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StakedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub farm: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub total_staked: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnstakedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub farm: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub total_staked: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RewardsClaimedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub farm: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    pub amount: u64,
+    /// Still owed because the reward vault ran dry
+    pub unpaid: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GatingUpdatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub gated: bool,
+    pub gated_liquidity: bool,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AllowlistUpdatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub wallet: Pubkey,
+    /// `true` when the wallet was added, `false` when removed
+    pub allowed: bool,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TradeStartUpdatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub previous_trade_start_timestamp: i64,
+    pub trade_start_timestamp: i64,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LaunchLimitSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub launch_mint: Pubkey,
+    pub window_seconds: i64,
+    pub max_buy: u64,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeModeSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub fee_mode: FeeMode,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub buyback_mint: Pubkey,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BuybackEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub buyback_mint: Pubkey,
+    /// Fees in the other mint swapped into `buyback_mint`
+    pub fees_spent: u64,
+    pub amount_bought: u64,
+    /// Fees already in `buyback_mint`, burned as they were
+    pub fees_burned: u64,
+    pub total_burned: u64,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiscountConfigSetEvent {
+    pub version: u8,
+    /// Discount mint, or `None` when discounts were turned off
+    #[cfg_attr(feature = "serde", serde(with = "json::option_pubkey"))]
+    pub mint: Option<Pubkey>,
+    pub tiers: Vec<DiscountTier>,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwapCommittedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    pub commit_slot: u64,
+    pub expiry_slot: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwapCommitReclaimedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwapHookSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    /// The default pubkey when the hook was removed
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub hook_program: Pubkey,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaxImbalanceSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub max_imbalance_bps: u16,
+    pub sequence: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetadataUpdatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    pub name: String,
+    pub uri: String,
+    /// Whether this call created the `PoolMetadata` account
+    pub created: bool,
+    pub timestamp: i64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CircuitBreakerSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub previous_threshold_bps: u16,
+    pub breaker_threshold_bps: u16,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TradeLimitsSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub previous: TradeLimits,
+    pub trade_limits: TradeLimits,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MinTradeAmountInSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub previous_a: u64,
+    pub previous_b: u64,
+    pub min_trade_amount_in_a: u64,
+    pub min_trade_amount_in_b: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// A swap refused for moving the price too far, pausing swaps on the pool.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CircuitBreakerTrippedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub user: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_in: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_out: Pubkey,
+    pub amount_in: u64,
+    /// Output the swap would have paid
+    pub amount_out: u64,
+    /// Reserves the swap was priced against
+    pub reserve_in: u64,
+    pub reserve_out: u64,
+    pub price_move_bps: u16,
+    pub breaker_threshold_bps: u16,
+    pub previous_status: u8,
+    pub status: u8,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// Carries the previous fee and when it changed, so watchers can hold
+/// deposit fee changes to a timelock.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DepositFeeSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub previous_deposit_fee_bps: u16,
+    pub deposit_fee_bps: u16,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// Carries the previous fee and when it changed, so watchers can hold
+/// withdrawal fee changes to a timelock.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WithdrawalFeeSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub previous_withdrawal_fee_bps: u16,
+    pub withdrawal_fee_bps: u16,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtocolFeeRecipientSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub protocol_fee_recipient: Pubkey,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+/// Summary of a `swap_split`; each leg also emits its own
+/// `SwapExecutedEvent`.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwapSplitEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub user: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_in: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_out: Pubkey,
+    pub total_amount_in: u64,
+    pub total_amount_out: u64,
+    /// Share of the input sent through each leg, in basis points
+    pub splits: Vec<u16>,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrderCreatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub order: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub maker: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_in_mint: Pubkey,
+    pub amount_in: u64,
+    pub min_price_numerator: u64,
+    pub min_price_denominator: u64,
+    pub expiry: i64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrderFilledEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub order: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub maker: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub filler: Pubkey,
+    /// Swapped for the maker, after the filler reward
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub filler_reward: u64,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrderCancelledEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub order: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub maker: Pubkey,
+    pub amount_in: u64,
+    /// Whether the order had expired when it was cancelled
+    pub expired: bool,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthorityTransferProposedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    /// The proposal this one replaced, or the default pubkey for none
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub previous_pending_authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// `authority` is the new authority, which signed to accept.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthorityTransferredEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub previous_authority: Pubkey,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthorityTransferCancelledEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    /// The pending authority that was dropped
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub cancelled_authority: Pubkey,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// The default pubkey for a role means the pool authority holds it.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolRolesSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub previous_fee_authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub fee_authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub previous_pause_guardian: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pause_guardian: Pubkey,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthorityRenouncedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub previous_authority: Pubkey,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolStatusChangedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub previous_status: u8,
+    pub status: u8,
+    /// Whether the change acknowledged a withdrawal pause
+    pub emergency: bool,
+    pub timestamp: i64,
+    /// The pool authority or pause guardian that signed
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// The LP mint outlives the pool, with its mint authority revoked.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolClosedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_a_mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_b_mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub lp_mint: Pubkey,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeSplitSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub previous_lp_fee_bps: u16,
+    pub lp_fee_bps: u16,
+    pub protocol_fee_bps: u16,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtocolFeesCollectedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub fee_recipient: Pubkey,
+    /// The fee recipient or fee authority that signed
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub collector: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    /// How the amounts were split between the fee recipients
+    pub payouts: Vec<FeePayout>,
+    /// The pool's insurance share of the amounts, moved to its insurance
+    /// vaults rather than paid out
+    pub insurance_a: u64,
+    pub insurance_b: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// A swap's protocol fee booked to the pool's owed counter, for revenue
+/// reconciliation. Not emitted in `BuybackBurn` mode, where the fee goes
+/// straight to the fee vault.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtocolFeesAccruedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    /// The swap's input mint, which the fee is charged in
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// The pool's protocol fees owed in `mint` including this one, so a
+    /// consumer can tell it has missed an accrual
+    pub total_owed: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// One mint's side of a `collect_protocol_fees`, shaped like
+/// `ProtocolFeesAccruedEvent` so revenue reconciles per mint: a mint's
+/// accrued amounts add up to its collected amounts plus the pool's owed
+/// counter. Emitted for each mint with fees owed, alongside the two-sided
+/// `ProtocolFeesCollectedEvent` that has the split between the fee
+/// recipients and the insurance vaults.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtocolFeesCollectedInMintEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub mint: Pubkey,
+    /// Everything owed in `mint` that was collected, insurance share included
+    pub amount: u64,
+    /// The pool's fee recipient
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub recipient: Pubkey,
+    /// When it happened, from the Clock sysvar
+    pub timestamp: i64,
+    pub slot: u64,
+    pub sequence: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeRecipientsSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub previous: Vec<FeeShare>,
+    pub fee_recipients: Vec<FeeShare>,
+    pub timestamp: i64,
+    /// The fee authority that signed
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConfigUpdatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub admin: Pubkey,
+    pub default_protocol_fee_bps: u16,
+    pub paused: bool,
+    pub pool_creation: PoolCreation,
+    pub fee_tiers: Vec<FeeTier>,
+    pub param_change_delay_slots: u64,
+    pub emergency_grace_slots: u64,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub guardian: Pubkey,
+    pub pool_creation_fee_lamports: u64,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub treasury: Pubkey,
+    pub require_badge: bool,
+    pub timestamp: i64,
+    pub lp_metadata_uri: String,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParamChangeProposedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub change: ParamChange,
+    pub effective_slot: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParamChangeAppliedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    /// The value the change replaced
+    pub previous: ParamChange,
+    pub change: ParamChange,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// A pool's fee changed by `apply_param_change`, along with its
+/// `ParamChangeAppliedEvent`. `authority` is the pool authority, which
+/// proposed the change; applying it needs no signer.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeUpdatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    /// Rounded down to whole bps for a pool still on the legacy fraction
+    pub previous_total_fee_bps: u16,
+    pub total_fee_bps: u16,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+/// A pool's fee recipient changed by `apply_param_change`, along with its
+/// `ParamChangeAppliedEvent`. `authority` is the fee authority, which
+/// proposed the change; applying it needs no signer.
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeRecipientChangedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub previous_fee_recipient: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub fee_recipient: Pubkey,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParamChangeCancelledEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub change: ParamChange,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmergencyWithdrawEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    /// The config's guardian that co-signed, or the default pubkey for none
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub guardian: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub destination_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub destination_b: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    /// Vault balances left behind
+    pub remaining_a: u64,
+    pub remaining_b: u64,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    pub fully_paused_slot: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeTiersUpdatedEvent {
+    pub version: u8,
+    pub fee_tiers: Vec<FeeTier>,
+    pub timestamp: i64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolCreatorUpdatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub creator: Pubkey,
+    /// `true` when the creator was added, `false` when removed
+    pub allowed: bool,
+    pub timestamp: i64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BadgeIssuedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub admin: Pubkey,
+    pub timestamp: i64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BadgeRevokedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub admin: Pubkey,
+    pub timestamp: i64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InsuranceVaultsCreatedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub insurance_vault_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub insurance_vault_b: Pubkey,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InsuranceDisbursedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub insurance_vault: Pubkey,
+    /// Token account paid
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub recipient: Pubkey,
+    pub amount: u64,
+    /// What is left in the insurance vault
+    pub remaining: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VaultMigratedEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    pub side: VaultSide,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub old_vault: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub new_vault: Pubkey,
+    /// The old vault's whole balance, moved to the new one
+    pub amount: u64,
+    /// `false` when the old vault's close authority kept the pool from
+    /// closing it; it is left empty
+    pub old_vault_closed: bool,
+    /// The side's reserve and owed protocol fees, now held by the new vault
+    pub reserve: u64,
+    pub protocol_fees_owed: u64,
+    /// Vaults the pool has migrated, this one included
+    pub vault_migrations: u16,
+    pub timestamp: i64,
+    pub sequence: u64,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
+
+#[event]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OracleGuardSetEvent {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub price_feed_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub price_feed_b: Pubkey,
+    /// Zero when the guard was turned off
+    pub max_deviation_bps: u16,
+    pub max_staleness_seconds: u32,
+    pub timestamp: i64,
+    pub sequence: u64,
+    pub oracle_kind: OracleKind,
+    /// The slot it happened in, from the Clock sysvar
+    pub slot: u64,
+}
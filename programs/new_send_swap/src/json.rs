@@ -0,0 +1,340 @@
+//! Field adapters for the `serde` feature, which derives `Serialize` and
+//! `Deserialize` on the accounts and events for off-chain services that
+//! decode them with Borsh and re-emit them as JSON. Pubkeys are written as
+//! base58 strings and u128s as decimal strings, which JavaScript reads
+//! without losing precision.
+
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// A `Pubkey` as its base58 string.
+pub mod pubkey {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(key: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(key)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        Pubkey::from_str(&key).map_err(|_| D::Error::custom(format!("invalid pubkey {key:?}")))
+    }
+}
+
+/// An `Option<Pubkey>` as its base58 string or `null`.
+pub mod option_pubkey {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        key: &Option<Pubkey>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match key {
+            Some(key) => serializer.collect_str(key),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Pubkey>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|key| {
+                Pubkey::from_str(&key)
+                    .map_err(|_| D::Error::custom(format!("invalid pubkey {key:?}")))
+            })
+            .transpose()
+    }
+}
+
+/// A `u128` as its decimal string.
+pub mod u128_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value
+            .parse()
+            .map_err(|_| D::Error::custom(format!("invalid u128 {value:?}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use anchor_lang::{Discriminator, Event};
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use serde_json::Value;
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    /// An account of type `T` decoded from zeroed data.
+    fn zeroed<T: AccountDeserialize + Discriminator>() -> T {
+        let mut data = T::DISCRIMINATOR.to_vec();
+        data.resize(16 * 1024, 0);
+        T::try_deserialize(&mut data.as_slice()).unwrap()
+    }
+
+    fn account_data<T: AccountSerialize>(account: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    /// `account` through JSON and back, checked to encode as before.
+    fn round_trip_account<T: AccountSerialize + Serialize + DeserializeOwned>(
+        account: &T,
+    ) -> Value {
+        let json = serde_json::to_value(account).unwrap();
+        let decoded: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(account_data(&decoded), account_data(account));
+        json
+    }
+
+    /// `event` through JSON and back, checked to encode as before.
+    fn round_trip_event<T: Event + Serialize + DeserializeOwned>(event: &T) -> Value {
+        let json = serde_json::to_value(event).unwrap();
+        let decoded: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(decoded.data(), event.data());
+        json
+    }
+
+    fn fixture(name: &str) -> Value {
+        let path = format!(
+            "{}/../../tests/fixtures/json/{name}",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn sample_pool() -> Pool {
+        let mut pool: Pool = zeroed();
+        pool.token_a_mint = key(1);
+        pool.token_b_mint = key(2);
+        pool.token_a_account = key(3);
+        pool.token_b_account = key(4);
+        pool.lp_mint = key(5);
+        pool.authority = key(6);
+        pool.fee_recipient = key(6);
+        pool.registry_entry = key(7);
+        pool.bump = 254;
+        pool.total_fee_bps = 30;
+        pool.lp_fee_bps = 2_500;
+        pool.protocol_fee_bps = 7_500;
+        pool.reserve_a = 1_000_000_000;
+        pool.reserve_b = 2_000_000_000;
+        pool.fee_mode = FeeMode::BuybackBurn;
+        pool.pending_change = Some(ParamChange::InsuranceDisbursement {
+            amount: 5_000,
+            recipient: key(8),
+        });
+        pool.fee_recipients = vec![
+            FeeShare {
+                recipient: key(9),
+                weight_bps: 6_000,
+            },
+            FeeShare {
+                recipient: key(10),
+                weight_bps: 4_000,
+            },
+        ];
+        pool.price_a_cumulative = 36_893_488_147_419_103_232_000;
+        pool.price_b_cumulative = 9_223_372_036_854_775_808_000;
+        pool.swap_count = u128::MAX;
+        pool.trade_limits = TradeLimits {
+            min_trade_amount: 1_000,
+            max_trade_bps: 500,
+        };
+        pool.lp_decimals = 9;
+        pool.oracle_guard = true;
+        pool.version = 1;
+        pool
+    }
+
+    #[test]
+    fn pool_json_matches_the_fixture() {
+        let json = round_trip_account(&sample_pool());
+        assert_eq!(json, fixture("pool.json"));
+        assert_eq!(json["token_a_mint"], key(1).to_string());
+        assert_eq!(json["swap_count"], u128::MAX.to_string());
+    }
+
+    #[test]
+    fn swap_event_json_matches_the_fixture() {
+        let event = SwapExecutedEvent {
+            version: SCHEMA_VERSION,
+            pool: key(1),
+            user: key(2),
+            recipient: key(3),
+            token_in: key(4),
+            token_out: key(5),
+            amount_in: 1_000_000,
+            amount_out: 996_006,
+            fee: 3_000,
+            lp_fee: 2_500,
+            protocol_fee: 500,
+            fee_discount_bps: 0,
+            fill_bps: 10_000,
+            timestamp: 1_700_000_000,
+            slot: 250_000_000,
+            reserve_in_after: 1_001_000_000,
+            reserve_out_after: 999_003_994,
+            spot_price_after: 18_385_066_371_316_400_000,
+            reserve_in_before: 1_000_000_000,
+            reserve_out_before: 1_000_000_000,
+            price_impact_bps: 39,
+            sequence: 7,
+            k_before: 1_000_000_000_000_000_000,
+            k_after: 1_000_003_997_994_000_000,
+            memo: Some("INV-2024-0042".to_string()),
+        };
+        assert_eq!(
+            round_trip_event(&event),
+            fixture("swap_executed_event.json")
+        );
+    }
+
+    #[test]
+    fn malformed_keys_and_amounts_are_rejected() {
+        let mut json = serde_json::to_value(sample_pool()).unwrap();
+        json["authority"] = "not-a-key".into();
+        assert!(serde_json::from_value::<Pool>(json).is_err());
+
+        let mut json = serde_json::to_value(sample_pool()).unwrap();
+        json["swap_count"] = u64::MAX.into();
+        assert!(serde_json::from_value::<Pool>(json).is_err());
+    }
+
+    macro_rules! round_trip_accounts {
+        ($($account:ty),* $(,)?) => {
+            $(round_trip_account(&zeroed::<$account>());)*
+        };
+    }
+
+    #[test]
+    fn every_account_round_trips() {
+        round_trip_accounts!(
+            Pool,
+            PoolCount,
+            PoolRegistryEntry,
+            PoolStats,
+            Observations,
+            LiquidityLock,
+            SwapCommit,
+            Order,
+            GlobalConfig,
+            CreatorAllowlistEntry,
+            TokenBadge,
+            AllowlistEntry,
+            PoolMetadata,
+            OracleGuard,
+            LaunchPurchase,
+            BurnedLiquidity,
+            Farm,
+            StakePosition,
+        );
+    }
+
+    macro_rules! round_trip_events {
+        ($($event:ty),* $(,)?) => {
+            $({
+                let mut data = <$event>::DISCRIMINATOR.to_vec();
+                data.push(SCHEMA_VERSION);
+                let event: $event =
+                    crate::client::decode_event(SCHEMA_VERSION, &data).unwrap();
+                round_trip_event(&event);
+            })*
+        };
+    }
+
+    #[test]
+    fn every_event_round_trips() {
+        round_trip_events!(
+            PoolCreatedEvent,
+            LiquidityAddedEvent,
+            SwapExecutedEvent,
+            InitialPriceSetEvent,
+            LiquidityRemovedEvent,
+            FlashLoanEvent,
+            FlashLoanRepaidEvent,
+            ReservesSyncedEvent,
+            SurplusSkimmedEvent,
+            SkimEvent,
+            PoolResetEvent,
+            PoolMigratedEvent,
+            ObservationCardinalityIncreasedEvent,
+            DonationEvent,
+            LiquidityMigratedEvent,
+            LockCreatedEvent,
+            LockExtendedEvent,
+            LiquidityUnlockedEvent,
+            LiquidityBurnedEvent,
+            FarmInitializedEvent,
+            EmissionScheduleSetEvent,
+            FarmFundedEvent,
+            StakedEvent,
+            UnstakedEvent,
+            RewardsClaimedEvent,
+            GatingUpdatedEvent,
+            AllowlistUpdatedEvent,
+            TradeStartUpdatedEvent,
+            LaunchLimitSetEvent,
+            FeeModeSetEvent,
+            BuybackEvent,
+            DiscountConfigSetEvent,
+            SwapCommittedEvent,
+            SwapCommitReclaimedEvent,
+            SwapHookSetEvent,
+            MaxImbalanceSetEvent,
+            MetadataUpdatedEvent,
+            CircuitBreakerSetEvent,
+            TradeLimitsSetEvent,
+            MinTradeAmountInSetEvent,
+            CircuitBreakerTrippedEvent,
+            DepositFeeSetEvent,
+            WithdrawalFeeSetEvent,
+            ProtocolFeeRecipientSetEvent,
+            SwapSplitEvent,
+            OrderCreatedEvent,
+            OrderFilledEvent,
+            OrderCancelledEvent,
+            AuthorityTransferProposedEvent,
+            AuthorityTransferredEvent,
+            AuthorityTransferCancelledEvent,
+            PoolRolesSetEvent,
+            AuthorityRenouncedEvent,
+            PoolStatusChangedEvent,
+            PoolClosedEvent,
+            FeeSplitSetEvent,
+            ProtocolFeesCollectedEvent,
+            ProtocolFeesAccruedEvent,
+            ProtocolFeesCollectedInMintEvent,
+            FeeRecipientsSetEvent,
+            ConfigUpdatedEvent,
+            ParamChangeProposedEvent,
+            ParamChangeAppliedEvent,
+            FeeUpdatedEvent,
+            FeeRecipientChangedEvent,
+            ParamChangeCancelledEvent,
+            EmergencyWithdrawEvent,
+            FeeTiersUpdatedEvent,
+            PoolCreatorUpdatedEvent,
+            BadgeIssuedEvent,
+            BadgeRevokedEvent,
+            InsuranceVaultsCreatedEvent,
+            InsuranceDisbursedEvent,
+            VaultMigratedEvent,
+            OracleGuardSetEvent,
+        );
+    }
+}
@@ -1,8 +1,50 @@
+// The IDL instructions generated by `#[program]` still call `AccountInfo::realloc`.
+#![allow(deprecated)]
+
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::memo::{self, BuildMemo, Memo};
+use anchor_spl::metadata::mpl_token_metadata::types::DataV2;
+use anchor_spl::metadata::{self, CreateMetadataAccountsV3, Metadata};
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token_2022::spl_token_2022;
+use anchor_spl::token_2022::spl_token_2022::extension::metadata_pointer::MetadataPointer;
+use anchor_spl::token_2022::spl_token_2022::extension::{
+    BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+};
+use anchor_spl::token_2022::spl_token_2022::instruction::AuthorityType;
+use anchor_spl::token_interface::spl_pod::optional_keys::OptionalNonZeroPubkey;
+use anchor_spl::token_interface::spl_token_metadata_interface::state::TokenMetadata;
+use anchor_spl::token_interface::{
+    self, Mint, TokenAccount, TokenInterface, TokenMetadataInitialize, TransferChecked,
+};
 
 declare_id!("DfMRpbJVP4g3Yi4S4zSmoFaqh7bvywzCjxZpkDKeZnXu");
 
+pub mod client;
+pub mod events;
+#[cfg(feature = "serde")]
+pub mod json;
+
+#[cfg(all(feature = "serde", target_os = "solana"))]
+compile_error!("the `serde` feature is for off-chain builds and can't be enabled on-chain");
+
+pub use events::*;
+pub use sol_amm_math::{
+    apply_deposit_fee, apply_withdrawal_fee, compute_optimal_deposit, lp_mint_decimals,
+    price_impact_bps, q64_price, split_insurance_share, split_swap_fee, ImbalancedDeposit,
+    MathError, SwapQuote, BPS_DENOMINATOR, INITIAL_LP_TOKENS,
+};
+
 #[error_code]
 pub enum AmmError {
     #[msg("Slippage tolerance exceeded")]
@@ -11,621 +53,11414 @@ pub enum AmmError {
     ArithmeticOverflow,
     #[msg("Invalid input amount")]
     InvalidAmount,
+    #[msg("Transaction deadline exceeded")]
+    DeadlineExceeded,
+    #[msg("Pool is locked by an outstanding flash loan")]
+    PoolLocked,
+    #[msg("Flash loan must be repaid by flash_repay later in the same transaction")]
+    FlashRepayMissing,
+    #[msg("No flash loan is outstanding on this pool")]
+    FlashLoanNotActive,
+    #[msg("Flash loan was not fully repaid")]
+    FlashLoanNotRepaid,
+    #[msg("Token account is not one of the pool's vaults")]
+    InvalidPoolTokenAccount,
+    #[msg("Signer is not the pool authority")]
+    Unauthorized,
+    #[msg("Batch must contain between one and MAX_SWAP_BATCH_LEGS legs")]
+    BatchTooLarge,
+    #[msg("Remaining accounts do not match the expected layout")]
+    InvalidRemainingAccounts,
+    #[msg("Basis points must be between 1 and 10000")]
+    InvalidBps,
+    #[msg("Neither side of the swap is the native mint")]
+    NotNativePool,
+    #[msg("Recipient token account does not match the output mint")]
+    InvalidRecipientMint,
+    #[msg("Limit price denominator must be non-zero")]
+    InvalidLimitPrice,
+    #[msg("Execution price is worse than the limit price")]
+    LimitPriceExceeded,
+    #[msg("Migration needs two distinct pools on the same mint pair")]
+    InvalidMigration,
+    #[msg("Fee account does not hold the input mint")]
+    InvalidFeeAccount,
+    #[msg("Pool reserves cannot cover the requested output")]
+    InsufficientLiquidity,
+    #[msg("Unlock timestamp must be later than the current one")]
+    InvalidUnlockTimestamp,
+    #[msg("Liquidity is locked until its unlock timestamp")]
+    LiquidityStillLocked,
+    #[msg("Unstake amount exceeds the staked balance")]
+    InsufficientStake,
+    #[msg("Emission entries must start in the future, after any queued entry")]
+    InvalidEmissionSchedule,
+    #[msg("Emission schedule holds at most MAX_EMISSION_ENTRIES entries")]
+    EmissionScheduleFull,
+    #[msg("Wallet is not on the pool's allowlist")]
+    NotAllowlisted,
+    #[msg("Trading has not started on this pool")]
+    TradingNotStarted,
+    #[msg("Trade start can only be moved earlier")]
+    InvalidTradeStart,
+    #[msg("Launch mint must be one of the pool's mints")]
+    InvalidLaunchConfig,
+    #[msg("Launch limits cannot change once trading has started")]
+    LaunchConfigLocked,
+    #[msg("Buying the launched token during the launch window needs the wallet's launch_purchase account")]
+    MissingLaunchPurchase,
+    #[msg("Purchase exceeds the per-wallet launch limit")]
+    LaunchLimitExceeded,
+    #[msg("The launch window is still open")]
+    LaunchWindowActive,
+    #[msg("Buyback mint must be one of the pool's mints")]
+    InvalidBuybackMint,
+    #[msg("Pool is not in buyback-and-burn fee mode")]
+    NotBuybackPool,
+    #[msg("Discount tiers must be ordered, at most 10,000 bps and fit the config")]
+    InvalidDiscountConfig,
+    #[msg("Discount account must be the trader's, in the discount mint")]
+    InvalidDiscountAccount,
+    #[msg("Commit window must be between 1 and MAX_COMMIT_WINDOW_SLOTS slots")]
+    InvalidCommitWindow,
+    #[msg("Revealed swap does not match the commitment")]
+    InvalidCommitment,
+    #[msg("A committed swap can only be revealed in a later slot")]
+    RevealTooEarly,
+    #[msg("Swap commitment has expired")]
+    CommitExpired,
+    #[msg("Swap commitment has not expired yet")]
+    CommitNotExpired,
+    #[msg("Pool has a swap hook; pass its hook program")]
+    MissingHookProgram,
+    #[msg("Swap hook reentrancy into the same pool is not allowed")]
+    ReentrantSwap,
+    #[msg("Deposit deviates from the pool ratio by more than the tolerance")]
+    ImbalanceTooHigh,
+    #[msg("Imbalance tolerance must be at most 10,000 bps")]
+    InvalidImbalanceTolerance,
+    #[msg("Withdrawal fee is above MAX_WITHDRAWAL_FEE_BPS")]
+    WithdrawalFeeTooHigh,
+    #[msg("Deposit fee is above MAX_DEPOSIT_FEE_BPS")]
+    DepositFeeTooHigh,
+    #[msg("Deposit fee needs the config and the protocol fee recipient's accounts in the pool's mints")]
+    InvalidProtocolFeeAccount,
+    #[msg("unwrap_sol needs the native mint and the temporary wSOL account")]
+    MissingWsolAccount,
+    #[msg("Split needs one to MAX_SWAP_SPLIT_LEGS non-zero shares summing to 10000 bps")]
+    InvalidSplit,
+    #[msg("Split pool is not for the traded mint pair")]
+    SplitPoolMismatch,
+    #[msg("Order input mint is not one of the pool's mints")]
+    InvalidOrderMint,
+    #[msg("Order expiry must be in the future")]
+    InvalidOrderExpiry,
+    #[msg("Order has expired")]
+    OrderExpired,
+    #[msg("Only the maker can cancel an order before it expires")]
+    OrderNotExpired,
+    #[msg("Fee must be at most MAX_FEE_BPS")]
+    InvalidFee,
+    #[msg("Signer is not the pool's pending authority")]
+    NotPendingAuthority,
+    #[msg("No authority transfer is pending")]
+    NoPendingAuthority,
+    #[msg("Pending authority must not be the default pubkey")]
+    InvalidPendingAuthority,
+    #[msg("Pool authority has been renounced")]
+    AuthorityRenounced,
+    #[msg("This operation is paused on the pool")]
+    PoolPaused,
+    #[msg("Pool status has unknown bits set")]
+    InvalidPoolStatus,
+    #[msg("Pausing withdrawals requires the emergency flag")]
+    EmergencyNotAcknowledged,
+    #[msg("Pool still holds tokens or has LP tokens outstanding")]
+    PoolNotEmpty,
+    #[msg("Fee recipient must not be the default pubkey")]
+    InvalidFeeRecipient,
+    #[msg("LP and protocol fee shares must add up to 10,000 bps")]
+    InvalidFeeSplit,
+    #[msg("Signer is not the pool's fee recipient")]
+    NotFeeRecipient,
+    #[msg("Swaps are paused across the protocol")]
+    ProtocolPaused,
+    #[msg("Only the config admin or allowlisted creators may create pools")]
+    PoolCreationRestricted,
+    #[msg("Fee is not one of the config's fee tiers")]
+    UnsupportedFeeTier,
+    #[msg("Fee tiers must be distinct valid fees, at most MAX_FEE_TIERS of them")]
+    InvalidFeeTiers,
+    #[msg("Config admin cannot be the default pubkey")]
+    InvalidAdmin,
+    #[msg("A parameter change is already pending")]
+    ParamChangePending,
+    #[msg("No parameter change is pending")]
+    NoPendingParamChange,
+    #[msg("The parameter change's delay has not passed")]
+    ParamChangeNotReady,
+    #[msg("Emergency withdrawals need every operation on the pool paused")]
+    PoolNotFullyPaused,
+    #[msg("The pool has not been fully paused for the emergency grace period")]
+    EmergencyGracePeriod,
+    #[msg("Emergency withdrawals need the config guardian's signature")]
+    GuardianSignatureRequired,
+    #[msg("Pool creation fee needs the config's treasury, a system account")]
+    InvalidTreasury,
+    #[msg("Creator cannot cover the pool creation fee")]
+    InsufficientCreationFee,
+    #[msg("Fee recipients must be at most MAX_FEE_RECIPIENTS distinct wallets with weights summing to 10,000 bps")]
+    InvalidFeeRecipients,
+    #[msg("Fee payouts need a token account per mint for each fee recipient, in order")]
+    InvalidFeeRecipientAccounts,
+    #[msg("A pending vault migration is carried out by migrate_vault")]
+    VaultMigrationPending,
+    #[msg("Swap moves the price past the pool's circuit breaker threshold")]
+    CircuitBreakerTripped,
+    #[msg("Circuit breaker threshold must be below 10,000 bps")]
+    InvalidBreakerThreshold,
+    #[msg("Surplus goes to token accounts of the config's treasury, which must be set")]
+    InvalidTreasuryAccount,
+    #[msg("Pool name or metadata URI is too long")]
+    MetadataTooLong,
+    #[msg("The pause guardian can only pause operations")]
+    GuardianCannotUnpause,
+    #[msg("Insurance share is above 10,000 basis points")]
+    InvalidInsuranceShare,
+    #[msg("Insurance vaults are missing or not the pool's")]
+    InvalidInsuranceVault,
+    #[msg("A pending insurance disbursement is paid out by disburse_insurance")]
+    InsuranceDisbursementPending,
+    #[msg("Both mints need a token badge to create a pool")]
+    MissingTokenBadge,
+    #[msg("The pool still has LP tokens outstanding")]
+    PoolNotDrained,
+    #[msg("Observation cardinality must grow, up to MAX_OBSERVATION_CARDINALITY")]
+    InvalidObservationCardinality,
+    // No longer returned, `get_twap` fails with `OracleStale` instead; kept
+    // so the codes after it don't shift
+    #[msg("The TWAP window reaches back before the oldest observation")]
+    ObservationTooOld,
+    #[msg("A pool vault holds less than its tracked reserve and owed fees")]
+    VaultBelowReserves,
+    #[msg("The pool uses an old account layout; call migrate_pool first")]
+    PoolNeedsMigration,
+    #[msg("The pool is already on the current account layout")]
+    PoolAlreadyMigrated,
+    #[msg("The account is not a pool of this program")]
+    InvalidPoolAccount,
+    #[msg("The registry entry is missing or not the pool's")]
+    InvalidRegistryEntry,
+    #[msg("The pool's fee is not a whole number of basis points")]
+    FeeNotRepresentable,
+    #[msg("Maximum trade size must be at most 10000 bps of the reserve")]
+    InvalidTradeLimits,
+    #[msg("Trade is below the pool's minimum trade amount")]
+    TradeTooSmall,
+    #[msg("Trade is above the pool's maximum trade size")]
+    TradeTooLarge,
+    #[msg("LP mint decimals must be lp_mint_decimals of the pair")]
+    InvalidLpMintDecimals,
+    #[msg("No token program passed owns the mint")]
+    MissingTokenProgram,
+    #[msg("Mints with transfer fees, transfer hooks or a permanent delegate can't be pooled")]
+    UnsupportedMintExtension,
+    #[msg("LP metadata is signed by the pool, which must be the LP mint's mint authority")]
+    InvalidLpMintAuthority,
+    #[msg("An LP mint's metadata pointer must point at the mint itself")]
+    InvalidMetadataPointer,
+    #[msg("Swap memo is longer than 64 bytes")]
+    MemoTooLong,
+    #[msg("A swap memo needs the memo program")]
+    MissingMemoProgram,
+    #[msg("Oracle guard deviation must be at most 10000 bps, with a non-zero staleness limit")]
+    InvalidOracleGuard,
+    #[msg("The pool's oracle guard and both of its price feeds are required")]
+    MissingOracleAccount,
+    #[msg("Price feed is not a valid account of the guard's oracle kind with a positive price")]
+    InvalidOracleAccount,
+    #[msg("Oracle price is older than the pool's staleness limit")]
+    OracleStale,
+    #[msg("Execution price deviates from the oracle price beyond the pool's band")]
+    OraclePriceDeviation,
+    #[msg("Oracle price's confidence interval is wider than the pool's band")]
+    OracleConfidenceTooWide,
+    #[msg("Input token account holds less than the swap's amount_in")]
+    InsufficientInputBalance,
 }
 
-#[program]
-pub mod new_send_swap {
-    use super::*;
+impl From<MathError> for AmmError {
+    fn from(error: MathError) -> Self {
+        match error {
+            MathError::InvalidAmount => AmmError::InvalidAmount,
+            MathError::InsufficientLiquidity => AmmError::InsufficientLiquidity,
+            MathError::ArithmeticOverflow => AmmError::ArithmeticOverflow,
+            MathError::ImbalanceTooHigh => AmmError::ImbalanceTooHigh,
+        }
+    }
+}
 
-    pub fn initialize_pool(
-        ctx: Context<InitializePool>,
-        fee_numerator: u64,
-        fee_denominator: u64,
-    ) -> Result<()> {
-        let pool = &mut ctx.accounts.pool;
-        pool.token_a_mint = ctx.accounts.token_a_mint.key();
-        pool.token_b_mint = ctx.accounts.token_b_mint.key();
-        pool.token_a_account = ctx.accounts.token_a_account.key();
-        pool.token_b_account = ctx.accounts.token_b_account.key();
-        pool.lp_mint = ctx.accounts.lp_mint.key();
-        pool.fee_numerator = fee_numerator;
-        pool.fee_denominator = fee_denominator;
-        pool.authority = ctx.accounts.authority.key();
-        pool.bump = ctx.bumps.pool;
+/// Fails with `InvalidFee` unless `total_fee_bps` is a valid pool fee of at
+/// most `MAX_FEE_BPS`.
+fn check_fee(total_fee_bps: u16) -> Result<()> {
+    require!(total_fee_bps <= MAX_FEE_BPS, AmmError::InvalidFee);
+    Ok(())
+}
 
-        emit!(PoolCreatedEvent {
-            pool: pool.key(),
-            token_a_mint: pool.token_a_mint,
-            token_b_mint: pool.token_b_mint,
-            fee: fee_numerator as f64 / fee_denominator as f64,
-        });
+/// Checks that `creator` may create a pool charging `total_fee_bps` under
+/// the program config, if there is one yet; in `Allowlist` mode creators
+/// other than the admin need their `creator_entry`.
+fn check_new_pool(
+    config: Option<&GlobalConfig>,
+    creator: &Pubkey,
+    creator_entry: Option<&CreatorAllowlistEntry>,
+    total_fee_bps: u16,
+) -> Result<()> {
+    check_fee(total_fee_bps)?;
+    if let Some(config) = config {
+        let allowed = match config.pool_creation {
+            PoolCreation::Permissionless => true,
+            PoolCreation::AdminOnly => *creator == config.admin,
+            PoolCreation::Allowlist => {
+                *creator == config.admin
+                    || creator_entry.is_some_and(|entry| entry.creator == *creator)
+            }
+        };
+        require!(allowed, AmmError::PoolCreationRestricted);
+    }
+    check_fee_tier(config, total_fee_bps)
+}
 
-        Ok(())
+/// Fails with `MissingTokenBadge` if the config requires badges and either
+/// mint lacks one; the accounts' seeds already tie each badge to its mint.
+fn check_token_badges(
+    config: Option<&GlobalConfig>,
+    token_a_badge: Option<&TokenBadge>,
+    token_b_badge: Option<&TokenBadge>,
+) -> Result<()> {
+    if config.is_some_and(|config| config.require_badge) {
+        require!(
+            token_a_badge.is_some() && token_b_badge.is_some(),
+            AmmError::MissingTokenBadge
+        );
     }
+    Ok(())
+}
 
-    pub fn add_liquidity(
-        ctx: Context<AddLiquidity>,
-        amount_a: u64,
-        amount_b: u64,
-        min_lp_tokens: u64,
-    ) -> Result<()> {
-        let pool = &ctx.accounts.pool;
+/// The token program owning `mint`: `token_program`, or `token_program_b`
+/// in an instruction on a pool pairing an SPL Token mint with a Token-2022
+/// one. Fails with `MissingTokenProgram` if neither does.
+fn token_program_for<'a, 'info>(
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &'a Interface<'info, TokenInterface>,
+    token_program_b: Option<&'a Interface<'info, TokenInterface>>,
+) -> Result<&'a Interface<'info, TokenInterface>> {
+    token_program_owning(mint.to_account_info().owner, token_program, token_program_b)
+}
 
-        // Get pool balances BEFORE transfers
-        let pool_token_a_balance_before = ctx.accounts.pool_token_a.amount;
-        let pool_token_b_balance_before = ctx.accounts.pool_token_b.amount;
+/// `token_program_for` by the owner of a mint or token account.
+fn token_program_owning<'a, 'info>(
+    owner: &Pubkey,
+    token_program: &'a Interface<'info, TokenInterface>,
+    token_program_b: Option<&'a Interface<'info, TokenInterface>>,
+) -> Result<&'a Interface<'info, TokenInterface>> {
+    std::iter::once(token_program)
+        .chain(token_program_b)
+        .find(|program| program.key() == *owner)
+        .ok_or_else(|| error!(AmmError::MissingTokenProgram))
+}
 
-        // Calculate LP tokens based on deposit amounts BEFORE transfers
-        let lp_tokens_to_mint =
-            if pool_token_a_balance_before == 0 && pool_token_b_balance_before == 0 {
-                // Initial liquidity - mint minimum amount for first deposit
-                1_000_000 // 1 LP token with 6 decimals
-            } else {
-                // Subsequent liquidity - proportional to existing pool shares
-                let lp_supply = ctx.accounts.lp_mint.supply;
-                let lp_decimals = ctx.accounts.lp_mint.decimals;
-
-                // Get token decimals from the mint accounts
-                let token_a_decimals = ctx.accounts.token_a_mint.decimals;
-                let token_b_decimals = ctx.accounts.token_b_mint.decimals;
-
-                // Normalize amounts to a common decimal base (using LP token decimals as reference)
-                // Formula: normalized_amount = raw_amount * (10^lp_decimals) / (10^token_decimals)
-                let normalize_amount = |raw_amount: u64, token_decimals: u8| -> Result<u64> {
-                    if token_decimals == lp_decimals {
-                        Ok(raw_amount)
-                    } else if token_decimals > lp_decimals {
-                        // Token has more decimals than LP, so divide
-                        let divisor = 10u64.pow((token_decimals - lp_decimals) as u32);
-                        Ok(raw_amount / divisor)
-                    } else {
-                        // Token has fewer decimals than LP, so multiply
-                        let multiplier = 10u64.pow((lp_decimals - token_decimals) as u32);
-                        if raw_amount > u64::MAX / multiplier {
-                            return err!(AmmError::ArithmeticOverflow);
-                        }
-                        Ok(raw_amount * multiplier)
-                    }
-                };
+/// Symbol of every LP mint's Token Metadata.
+pub const LP_METADATA_SYMBOL: &str = "SOLAMM-LP";
 
-                // Normalize the amounts
-                let normalized_amount_a = normalize_amount(amount_a, token_a_decimals)?;
-                let normalized_amount_b = normalize_amount(amount_b, token_b_decimals)?;
-                let normalized_pool_a =
-                    normalize_amount(pool_token_a_balance_before, token_a_decimals)?;
-                let normalized_pool_b =
-                    normalize_amount(pool_token_b_balance_before, token_b_decimals)?;
-
-                // Calculate LP tokens for token A using normalized amounts
-                let lp_tokens_a = if normalized_pool_a > 0 {
-                    if normalized_amount_a > 0 && lp_supply > 0 {
-                        // Check if multiplication would overflow
-                        if normalized_amount_a > u64::MAX / lp_supply {
-                            return err!(AmmError::ArithmeticOverflow);
-                        }
-                        (normalized_amount_a * lp_supply) / normalized_pool_a
-                    } else {
-                        0
-                    }
-                } else {
-                    0
-                };
+/// Name of the LP mint's Token Metadata for a pool on `token_a_mint` and
+/// `token_b_mint`, each shown by the first four characters of its address,
+/// e.g. `SOLAMM LP So11/EPjF`.
+pub fn lp_metadata_name(token_a_mint: &Pubkey, token_b_mint: &Pubkey) -> String {
+    format!(
+        "SOLAMM LP {}/{}",
+        &token_a_mint.to_string()[..4],
+        &token_b_mint.to_string()[..4]
+    )
+}
 
-                // Calculate LP tokens for token B using normalized amounts
-                let lp_tokens_b = if normalized_pool_b > 0 {
-                    if normalized_amount_b > 0 && lp_supply > 0 {
-                        if normalized_amount_b > u64::MAX / lp_supply {
-                            return err!(AmmError::ArithmeticOverflow);
-                        }
-                        (normalized_amount_b * lp_supply) / normalized_pool_b
-                    } else {
-                        0
-                    }
-                } else {
-                    0
-                };
+/// Writes a new pool's LP metadata, named by `lp_metadata_name` and pointing
+/// at the config's `lp_metadata_uri`, with the pool signing as mint authority
+/// and becoming the update authority. A Token-2022 LP mint whose metadata
+/// pointer points at itself gets it in its token-metadata extension, the
+/// payer topping up its rent; an SPL Token LP mint gets a Token Metadata
+/// account when the Token Metadata program is passed. Nothing otherwise, so
+/// pools can still be created where that program isn't deployed.
+#[allow(clippy::too_many_arguments)]
+fn create_lp_metadata<'info>(
+    pool: &Account<'info, Pool>,
+    lp_mint: &InterfaceAccount<'info, Mint>,
+    lp_metadata: Option<&UncheckedAccount<'info>>,
+    token_metadata_program: Option<&Program<'info, Metadata>>,
+    token_program: &Interface<'info, TokenInterface>,
+    token_program_b: Option<&Interface<'info, TokenInterface>>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    rent: &Sysvar<'info, Rent>,
+    config: Option<&GlobalConfig>,
+) -> Result<()> {
+    let mint_info = lp_mint.to_account_info();
+    let metadata_pointer = if *mint_info.owner == spl_token_2022::ID {
+        let data = mint_info.try_borrow_data()?;
+        let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+        match mint.get_extension::<MetadataPointer>() {
+            Ok(pointer) => Some(Option::<Pubkey>::from(pointer.metadata_address)),
+            Err(_) => return Ok(()),
+        }
+    } else {
+        None
+    };
+    if metadata_pointer.is_none() && token_metadata_program.is_none() {
+        return Ok(());
+    }
+    require!(
+        lp_mint.mint_authority == COption::Some(pool.key()),
+        AmmError::InvalidLpMintAuthority
+    );
 
-                // Take the minimum to maintain pool balance
-                std::cmp::min(lp_tokens_a, lp_tokens_b)
-            };
+    let name = lp_metadata_name(&pool.token_a_mint, &pool.token_b_mint);
+    let symbol = LP_METADATA_SYMBOL.to_string();
+    let uri = config.map_or_else(String::new, |config| config.lp_metadata_uri.clone());
+    let seeds = [
+        b"pool",
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &pool.pool_index.to_le_bytes(),
+        &[pool.bump],
+    ];
+    let signer_seeds = [&seeds[..]];
 
-        // Verify minimum LP tokens
+    if let Some(metadata_address) = metadata_pointer {
         require!(
-            lp_tokens_to_mint >= min_lp_tokens,
-            AmmError::SlippageExceeded
+            metadata_address == Some(lp_mint.key()),
+            AmmError::InvalidMetadataPointer
         );
-
-        // Transfer token A from user to pool
-        let cpi_accounts_a = Transfer {
-            from: ctx.accounts.user_token_a.to_account_info(),
-            to: ctx.accounts.pool_token_a.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
+        let token_metadata = TokenMetadata {
+            update_authority: OptionalNonZeroPubkey(pool.key()),
+            mint: lp_mint.key(),
+            name: name.clone(),
+            symbol: symbol.clone(),
+            uri: uri.clone(),
+            additional_metadata: Vec::new(),
         };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx_a = CpiContext::new(cpi_program.clone(), cpi_accounts_a);
-        token::transfer(cpi_ctx_a, amount_a)?;
+        let new_len = mint_info.data_len() + token_metadata.tlv_size_of()?;
+        let shortfall = Rent::get()?
+            .minimum_balance(new_len)
+            .saturating_sub(mint_info.lamports());
+        if shortfall > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: payer.to_account_info(),
+                        to: mint_info.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        let token_program = token_program_for(lp_mint, token_program, token_program_b)?;
+        return token_interface::token_metadata_initialize(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                TokenMetadataInitialize {
+                    program_id: token_program.to_account_info(),
+                    metadata: mint_info.clone(),
+                    update_authority: pool.to_account_info(),
+                    mint_authority: pool.to_account_info(),
+                    mint: mint_info,
+                },
+                &signer_seeds,
+            ),
+            name,
+            symbol,
+            uri,
+        );
+    }
 
-        // Transfer token B from user to pool
-        let cpi_accounts_b = Transfer {
-            from: ctx.accounts.user_token_b.to_account_info(),
-            to: ctx.accounts.pool_token_b.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        };
-        let cpi_ctx_b = CpiContext::new(cpi_program.clone(), cpi_accounts_b);
-        token::transfer(cpi_ctx_b, amount_b)?;
+    let Some(token_metadata_program) = token_metadata_program else {
+        return Ok(());
+    };
+    let lp_metadata = lp_metadata.ok_or(ErrorCode::AccountNotEnoughKeys)?;
+    metadata::create_metadata_accounts_v3(
+        CpiContext::new_with_signer(
+            token_metadata_program.to_account_info(),
+            CreateMetadataAccountsV3 {
+                metadata: lp_metadata.to_account_info(),
+                mint: mint_info,
+                mint_authority: pool.to_account_info(),
+                payer: payer.to_account_info(),
+                update_authority: pool.to_account_info(),
+                system_program: system_program.to_account_info(),
+                rent: rent.to_account_info(),
+            },
+            &signer_seeds,
+        ),
+        DataV2 {
+            name,
+            symbol,
+            uri,
+            seller_fee_basis_points: 0,
+            creators: None,
+            collection: None,
+            uses: None,
+        },
+        true,
+        true,
+        None,
+    )
+}
 
-        // Mint LP tokens to user
-        let pool_seeds = [
-            b"pool",
-            ctx.accounts.pool.token_a_mint.as_ref(),
-            ctx.accounts.pool.token_b_mint.as_ref(),
-            &[ctx.accounts.pool.bump],
-        ];
-        let signer_seeds = [&pool_seeds[..]];
+/// Fails with `UnsupportedMintExtension` for a Token-2022 mint whose
+/// transfers could deliver less than was sent or need accounts the pool
+/// doesn't pass, or whose permanent delegate could empty the vaults. The
+/// reserves are only ever tracked from the amounts the program moves.
+fn check_mint_extensions(mint: &InterfaceAccount<Mint>) -> Result<()> {
+    let info = mint.to_account_info();
+    if *info.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+    let data = info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    require!(
+        !mint.get_extension_types()?.iter().any(|extension| matches!(
+            extension,
+            ExtensionType::TransferFeeConfig
+                | ExtensionType::TransferHook
+                | ExtensionType::PermanentDelegate
+        )),
+        AmmError::UnsupportedMintExtension
+    );
+    Ok(())
+}
 
-        let cpi_accounts_mint = token::MintTo {
-            mint: ctx.accounts.lp_mint.to_account_info(),
-            to: ctx.accounts.user_lp.to_account_info(),
-            authority: ctx.accounts.pool.to_account_info(),
-        };
-        let cpi_ctx_mint = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts_mint,
-            &signer_seeds,
+/// Charges `creator` the config's pool creation fee, paid to its treasury,
+/// and returns the lamports paid; nothing without a config or with a zero
+/// fee. Failing here reverts the pool's accounts with the rest of the
+/// instruction.
+fn pay_pool_creation_fee<'info>(
+    config: Option<&GlobalConfig>,
+    creator: &Signer<'info>,
+    treasury: Option<&SystemAccount<'info>>,
+    system_program: &Program<'info, System>,
+) -> Result<u64> {
+    let Some(config) = config.filter(|config| config.pool_creation_fee_lamports > 0) else {
+        return Ok(0);
+    };
+    let fee = config.pool_creation_fee_lamports;
+    let treasury = treasury
+        .filter(|treasury| treasury.key() == config.treasury)
+        .ok_or(AmmError::InvalidTreasury)?;
+    require!(creator.lamports() >= fee, AmmError::InsufficientCreationFee);
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: creator.to_account_info(),
+                to: treasury.to_account_info(),
+            },
+        ),
+        fee,
+    )?;
+    Ok(fee)
+}
+
+/// Fails with `UnsupportedFeeTier` unless the fee equals one of the config's
+/// fee tiers; without a config or tiers any valid fee is allowed.
+fn check_fee_tier(config: Option<&GlobalConfig>, total_fee_bps: u16) -> Result<()> {
+    let Some(config) = config.filter(|config| !config.fee_tiers.is_empty()) else {
+        return Ok(());
+    };
+    require!(
+        config
+            .fee_tiers
+            .iter()
+            .any(|tier| tier.fee_bps == total_fee_bps),
+        AmmError::UnsupportedFeeTier
+    );
+    Ok(())
+}
+
+/// Fails with `InvalidFeeTiers` unless `fee_tiers` are at most
+/// `MAX_FEE_TIERS` valid fees, no two of them the same.
+fn check_fee_tiers(fee_tiers: &[FeeTier]) -> Result<()> {
+    require!(fee_tiers.len() <= MAX_FEE_TIERS, AmmError::InvalidFeeTiers);
+    for (index, tier) in fee_tiers.iter().enumerate() {
+        require!(
+            check_fee(tier.fee_bps).is_ok()
+                && !fee_tiers[..index]
+                    .iter()
+                    .any(|other| other.fee_bps == tier.fee_bps),
+            AmmError::InvalidFeeTiers
         );
-        token::mint_to(cpi_ctx_mint, lp_tokens_to_mint)?;
+    }
+    Ok(())
+}
 
-        emit!(LiquidityAddedEvent {
-            pool: pool.key(),
-            user: ctx.accounts.user.key(),
-            amount_a,
-            amount_b,
-            lp_tokens_minted: lp_tokens_to_mint,
-            pool_token_a_balance: ctx.accounts.pool_token_a.amount,
-            pool_token_b_balance: ctx.accounts.pool_token_b.amount,
-        });
+/// Fails with `DeadlineExceeded` once the cluster clock has passed `deadline`
+/// (a unix timestamp). A deadline of zero disables the check.
+fn check_deadline(deadline: i64) -> Result<()> {
+    if deadline != 0 {
+        let now = Clock::get()?.unix_timestamp;
+        require!(now <= deadline, AmmError::DeadlineExceeded);
+    }
+    Ok(())
+}
 
-        Ok(())
+/// Fails with `NotAllowlisted` unless `entry` admits `wallet` to `pool`.
+fn check_allowlist(pool: &Pubkey, wallet: &Pubkey, entry: Option<&AllowlistEntry>) -> Result<()> {
+    let allowed = entry.is_some_and(|entry| entry.pool == *pool && entry.wallet == *wallet);
+    require!(allowed, AmmError::NotAllowlisted);
+    Ok(())
+}
+
+/// Fee discount, in basis points, earned by `owner` holding the balance of
+/// `token_account`; no account means no discount.
+fn fee_discount_bps(
+    config: Option<&GlobalConfig>,
+    owner: &Pubkey,
+    token_account: Option<&TokenAccount>,
+) -> Result<u16> {
+    let Some(token_account) = token_account else {
+        return Ok(0);
+    };
+    let Some(discount_config) = config.and_then(|config| config.discount_config.as_ref()) else {
+        return err!(AmmError::InvalidDiscountAccount);
+    };
+    require!(
+        token_account.owner == *owner && token_account.mint == discount_config.mint,
+        AmmError::InvalidDiscountAccount
+    );
+
+    // Tiers are sorted by threshold, so the last one reached applies
+    Ok(discount_config
+        .tiers
+        .iter()
+        .rev()
+        .find(|tier| token_account.amount >= tier.min_balance)
+        .map_or(0, |tier| tier.discount_bps))
+}
+
+/// `pool`'s fee as a numerator/denominator pair after a discount of
+/// `discount_bps`.
+fn discounted_fee(pool: &Pool, discount_bps: u16) -> Result<(u64, u64)> {
+    let (fee_numerator, fee_denominator) = pool.fee();
+    if discount_bps == 0 {
+        return Ok((fee_numerator, fee_denominator));
     }
+    let numerator = fee_numerator
+        .checked_mul((BPS_DENOMINATOR - discount_bps) as u64)
+        .ok_or(AmmError::ArithmeticOverflow)?;
+    let denominator = fee_denominator
+        .checked_mul(BPS_DENOMINATOR as u64)
+        .ok_or(AmmError::ArithmeticOverflow)?;
+    Ok((numerator, denominator))
+}
 
-    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_amount_out: u64) -> Result<()> {
-        let pool = &ctx.accounts.pool;
+/// The commitment `commit_swap` expects for a swap: the SHA-256 of
+/// `amount_in`, `min_amount_out` (both little-endian), `a_to_b` as one byte
+/// and `salt`.
+pub fn swap_commitment(
+    amount_in: u64,
+    min_amount_out: u64,
+    a_to_b: bool,
+    salt: &[u8; 32],
+) -> [u8; 32] {
+    hashv(&[
+        &amount_in.to_le_bytes(),
+        &min_amount_out.to_le_bytes(),
+        &[a_to_b as u8],
+        salt,
+    ])
+    .to_bytes()
+}
 
-        // Validate input amount
-        require!(amount_in > 0, AmmError::InvalidAmount);
+/// Arguments of the instruction a pool's swap hook receives after each swap,
+/// after the 8-byte `SWAP_HOOK_IX_NAME` discriminator. The accounts are the
+/// pool (read-only) followed by the swap's remaining accounts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SwapHookArgs {
+    pub pool: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub a_to_b: bool,
+}
 
-        // Calculate fee using existing fee numerator/denominator
-        let fee = amount_in
-            .checked_mul(pool.fee_numerator)
-            .ok_or(AmmError::ArithmeticOverflow)?
-            .checked_div(pool.fee_denominator)
-            .ok_or(AmmError::ArithmeticOverflow)?;
+/// Name hashed into the hook instruction's discriminator, matching an Anchor
+/// program's `on_swap` instruction.
+pub const SWAP_HOOK_IX_NAME: &[u8] = b"global:on_swap";
 
-        let amount_in_after_fee = amount_in
-            .checked_sub(fee)
-            .ok_or(AmmError::ArithmeticOverflow)?;
+/// Calls `pool`'s swap hook with the swap's amounts. The pool is saved with
+/// `in_hook` set first, which lets the hook tell it's called from a swap.
+///
+/// The runtime is what refuses a hook re-entering this program: it fails
+/// any A -> B -> A invocation with `ReentrancyNotAllowed` before the inner
+/// instruction runs, so the `ReentrantSwap` checks on `in_hook` are only a
+/// backstop and can't be reached today. For the same reason nothing can
+/// write the pool while the hook runs, as only this program can, so the
+/// in-memory copy stays current: `in_hook` is cleared on it alone and the
+/// handler's exit writes it back. Were that ever to change, that write
+/// would silently overwrite whatever the hook did to the pool.
+fn invoke_swap_hook<'info>(
+    pool: &mut Account<'info, Pool>,
+    hook_program: &AccountInfo<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+    args: SwapHookArgs,
+) -> Result<()> {
+    let mut data = hashv(&[SWAP_HOOK_IX_NAME]).to_bytes()[..8].to_vec();
+    args.serialize(&mut data)?;
+    let mut metas = vec![AccountMeta::new_readonly(pool.key(), false)];
+    let mut infos = vec![pool.to_account_info()];
+    for account in hook_accounts {
+        metas.push(if account.is_writable {
+            AccountMeta::new(account.key(), account.is_signer)
+        } else {
+            AccountMeta::new_readonly(account.key(), account.is_signer)
+        });
+        infos.push(account.clone());
+    }
+    infos.push(hook_program.clone());
 
-        // Get current pool balances
-        let pool_token_in_balance = ctx.accounts.pool_token_in.amount;
-        let pool_token_out_balance = ctx.accounts.pool_token_out.amount;
+    pool.in_hook = true;
+    pool.exit(&crate::ID)?;
+    invoke(
+        &Instruction {
+            program_id: hook_program.key(),
+            accounts: metas,
+            data,
+        },
+        &infos,
+    )?;
+    pool.in_hook = false;
 
-        // Validate pool has sufficient liquidity
-        require!(pool_token_in_balance > 0, AmmError::InvalidAmount);
-        require!(pool_token_out_balance > 0, AmmError::InvalidAmount);
+    Ok(())
+}
 
-        // Calculate amount_out using constant product formula with improved overflow protection
-        // Formula: amount_out = (pool_token_out_balance * amount_in_after_fee) / (pool_token_in_balance + amount_in_after_fee)
+/// Whether `pool`'s launch window, with its per-wallet buy cap, is open.
+fn launch_window_open(pool: &Pool, now: i64) -> bool {
+    pool.launch_max_buy > 0
+        && now
+            < pool
+                .trade_start_timestamp
+                .saturating_add(pool.launch_window_seconds)
+}
 
-        // First, check if the denominator would overflow
-        let denominator = pool_token_in_balance
-            .checked_add(amount_in_after_fee)
-            .ok_or(AmmError::ArithmeticOverflow)?;
+/// Counts a purchase of `amount_out` of `token_out` against the wallet's
+/// launch cap while the launch window is open.
+fn record_launch_purchase(
+    pool: &Pool,
+    token_out: Pubkey,
+    amount_out: u64,
+    purchase: Option<&mut LaunchPurchase>,
+) -> Result<()> {
+    if token_out != pool.launch_mint || !launch_window_open(pool, Clock::get()?.unix_timestamp) {
+        return Ok(());
+    }
+    let purchase = purchase.ok_or(AmmError::MissingLaunchPurchase)?;
+    purchase.purchased = purchase
+        .purchased
+        .checked_add(amount_out)
+        .ok_or(AmmError::ArithmeticOverflow)?;
+    require!(
+        purchase.purchased <= pool.launch_max_buy,
+        AmmError::LaunchLimitExceeded
+    );
+    Ok(())
+}
 
-        // Calculate amount_out using a safer approach
-        let amount_out = if pool_token_out_balance > 0 && amount_in_after_fee > 0 {
-            // Use a more robust calculation that avoids overflow
-            // We'll use a different approach: calculate the ratio first, then multiply
+/// Fails with `LimitPriceExceeded` unless the average execution price
+/// `amount_out / amount_in` is at least `limit_numerator / limit_denominator`.
+/// A limit of 0/0 disables the check.
+fn check_limit_price(
+    amount_in: u64,
+    amount_out: u64,
+    limit_numerator: u64,
+    limit_denominator: u64,
+) -> Result<()> {
+    if limit_numerator == 0 && limit_denominator == 0 {
+        return Ok(());
+    }
+    require!(limit_denominator > 0, AmmError::InvalidLimitPrice);
 
-            // Calculate the ratio: amount_in_after_fee / (pool_token_in_balance + amount_in_after_fee)
-            // This ratio will be between 0 and 1, so it's safe to multiply with pool_token_out_balance
+    // Cross-multiplied in u128, so both sides are exact
+    require!(
+        amount_out as u128 * limit_denominator as u128
+            >= amount_in as u128 * limit_numerator as u128,
+        AmmError::LimitPriceExceeded
+    );
+    Ok(())
+}
 
-            // First, check if the multiplication would overflow
-            if pool_token_out_balance > u64::MAX / amount_in_after_fee {
-                // If direct multiplication would overflow, use a different approach
-                // Calculate: pool_token_out_balance * (amount_in_after_fee / denominator)
-                // But we need to handle the division carefully to maintain precision
+/// Balance of an SPL Token or Token-2022 account.
+fn token_account_amount(account: &AccountInfo) -> Result<u64> {
+    let data = account.try_borrow_data()?;
+    let account = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    Ok(account.base.amount)
+}
 
-                // Use a scaling approach: multiply by a large number, divide, then scale back
-                let scale = 1_000_000_000u64; // 1 billion for precision
+/// Pyth's price receiver program, which owns the `PriceUpdateV2` accounts an
+/// oracle guard reads; the same on every cluster.
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
 
-                // Scale up the calculation to maintain precision
-                let scaled_amount_in = amount_in_after_fee.saturating_mul(scale);
-                let scaled_ratio = scaled_amount_in / denominator;
-                let scaled_amount_out = pool_token_out_balance.saturating_mul(scaled_ratio);
+/// Anchor discriminator of Pyth's `PriceUpdateV2` accounts.
+pub const PYTH_PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
 
-                // Scale back down
-                scaled_amount_out / scale
-            } else {
-                // Safe to do direct calculation
-                let numerator = pool_token_out_balance * amount_in_after_fee;
-                numerator / denominator
-            }
-        } else {
-            0
-        };
+/// Tag of `VerificationLevel::Full` in a `PriceUpdateV2`: the update carries
+/// enough Wormhole signatures to be trusted.
+const PYTH_VERIFICATION_FULL: u8 = 1;
 
-        // Verify minimum amount out
-        require!(amount_out >= min_amount_out, AmmError::SlippageExceeded);
+/// Switchboard's On-Demand program on mainnet, which owns the pull feed
+/// accounts an oracle guard reads.
+pub const SWITCHBOARD_ON_DEMAND_PROGRAM_ID: Pubkey =
+    pubkey!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv");
 
-        // Transfer fee directly from user to owner (before the main transfer)
-        if fee > 0 {
-            let cpi_accounts_fee = Transfer {
-                from: ctx.accounts.user_token_in.to_account_info(),
-                to: ctx.accounts.owner_token_account.to_account_info(),
-                authority: ctx.accounts.user.to_account_info(),
-            };
-            let cpi_ctx_fee = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                cpi_accounts_fee,
-            );
-            token::transfer(cpi_ctx_fee, fee)?;
+/// Anchor discriminator of Switchboard's `PullFeedAccountData` accounts.
+pub const SWITCHBOARD_PULL_FEED_DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+
+/// Offsets in a `PullFeedAccountData`, discriminator included, of
+/// `last_update_timestamp` and of the current result's `value` and
+/// `std_dev`, which follows it.
+const SWITCHBOARD_UPDATE_TIMESTAMP_OFFSET: usize = 2216;
+const SWITCHBOARD_RESULT_OFFSET: usize = 2264;
+
+/// Decimal places of a Switchboard On-Demand result.
+const SWITCHBOARD_DECIMALS: i32 = 18;
+
+/// Oracle network an oracle guard reads its price feeds from.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default, InitSpace,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OracleKind {
+    /// Pyth `PriceUpdateV2` accounts, owned by `PYTH_RECEIVER_PROGRAM_ID`
+    #[default]
+    Pyth,
+    /// Switchboard On-Demand `PullFeedAccountData` accounts, owned by
+    /// `SWITCHBOARD_ON_DEMAND_PROGRAM_ID`
+    SwitchboardOnDemand,
+}
+
+impl OracleKind {
+    /// Reads `feed` in this kind's account format, failing with
+    /// `InvalidOracleAccount` unless the kind's program owns it.
+    pub fn read_price(self, feed: &AccountInfo) -> Result<OraclePrice> {
+        match self {
+            OracleKind::Pyth => read_pyth_price(feed),
+            OracleKind::SwitchboardOnDemand => read_switchboard_price(feed),
         }
+    }
+}
 
-        // Transfer remaining tokens from user to pool (amount_in_after_fee)
-        let cpi_accounts_in = Transfer {
-            from: ctx.accounts.user_token_in.to_account_info(),
-            to: ctx.accounts.pool_token_in.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx_in = CpiContext::new(cpi_program.clone(), cpi_accounts_in);
-        token::transfer(cpi_ctx_in, amount_in_after_fee)?;
+/// A price read from an oracle feed: `price * 10^exponent` units of the quote
+/// currency for one whole token, give or take `conf` in the same units,
+/// published at `publish_time`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OraclePrice {
+    pub price: u64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+}
 
-        // Transfer output tokens from pool to user
-        let cpi_accounts_out = Transfer {
-            from: ctx.accounts.pool_token_out.to_account_info(),
-            to: ctx.accounts.user_token_out.to_account_info(),
-            authority: ctx.accounts.pool.to_account_info(),
-        };
-        let seeds = [
-            b"pool",
-            ctx.accounts.pool.token_a_mint.as_ref(),
-            ctx.accounts.pool.token_b_mint.as_ref(),
-            &[ctx.accounts.pool.bump],
-        ];
-        let signer_seeds = [&seeds[..]];
-        let cpi_ctx_out = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts_out,
-            &signer_seeds,
-        );
-        token::transfer(cpi_ctx_out, amount_out)?;
+impl OraclePrice {
+    /// A price of `value * 10^exponent` give or take `conf`, both scaled down
+    /// by powers of ten until they fit in a u64. Non-positive prices fail
+    /// with `InvalidOracleAccount`.
+    fn normalize(value: i128, conf: i128, exponent: i32, publish_time: i64) -> Result<Self> {
+        require!(value > 0, AmmError::InvalidOracleAccount);
+        let (mut value, mut conf, mut exponent) = (value as u128, conf.unsigned_abs(), exponent);
+        while value > u64::MAX as u128 || conf > u64::MAX as u128 {
+            value /= 10;
+            conf /= 10;
+            exponent += 1;
+        }
+        Ok(Self {
+            price: value as u64,
+            conf: conf as u64,
+            exponent,
+            publish_time,
+        })
+    }
+}
 
-        emit!(SwapExecutedEvent {
-            pool: pool.key(),
-            user: ctx.accounts.user.key(),
-            token_in: ctx.accounts.token_in_mint.key(),
-            token_out: ctx.accounts.token_out_mint.key(),
-            amount_in,
-            amount_out,
-            fee,
-        });
+/// Reads `feed`, which must be a fully verified Pyth `PriceUpdateV2` account
+/// with a positive price; anything else fails with `InvalidOracleAccount`.
+pub fn read_pyth_price(feed: &AccountInfo) -> Result<OraclePrice> {
+    require_keys_eq!(
+        *feed.owner,
+        PYTH_RECEIVER_PROGRAM_ID,
+        AmmError::InvalidOracleAccount
+    );
+    let data = feed.try_borrow_data()?;
+    let mut message = data
+        .strip_prefix(&PYTH_PRICE_UPDATE_DISCRIMINATOR[..])
+        .ok_or(AmmError::InvalidOracleAccount)?;
+    // The write authority, the verification level, which for `Full` has no
+    // payload, and the head of the price message, up to its publish time
+    let (_write_authority, verification_level, _feed_id, price, conf, exponent, publish_time) =
+        <(Pubkey, u8, [u8; 32], i64, u64, i32, i64)>::deserialize(&mut message)
+            .map_err(|_| AmmError::InvalidOracleAccount)?;
+    require!(
+        verification_level == PYTH_VERIFICATION_FULL,
+        AmmError::InvalidOracleAccount
+    );
+    OraclePrice::normalize(price as i128, conf as i128, exponent, publish_time)
+}
 
-        Ok(())
+/// Reads `feed`, which must be a Switchboard On-Demand `PullFeedAccountData`
+/// account with a positive current result, taking its standard deviation as
+/// the confidence; anything else fails with `InvalidOracleAccount`.
+pub fn read_switchboard_price(feed: &AccountInfo) -> Result<OraclePrice> {
+    require_keys_eq!(
+        *feed.owner,
+        SWITCHBOARD_ON_DEMAND_PROGRAM_ID,
+        AmmError::InvalidOracleAccount
+    );
+    let data = feed.try_borrow_data()?;
+    require!(
+        data.starts_with(&SWITCHBOARD_PULL_FEED_DISCRIMINATOR)
+            && data.len() >= SWITCHBOARD_RESULT_OFFSET + 32,
+        AmmError::InvalidOracleAccount
+    );
+    let read_i128 =
+        |offset: usize| i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap());
+    let publish_time = i64::from_le_bytes(
+        data[SWITCHBOARD_UPDATE_TIMESTAMP_OFFSET..SWITCHBOARD_UPDATE_TIMESTAMP_OFFSET + 8]
+            .try_into()
+            .unwrap(),
+    );
+    OraclePrice::normalize(
+        read_i128(SWITCHBOARD_RESULT_OFFSET),
+        read_i128(SWITCHBOARD_RESULT_OFFSET + 16),
+        -SWITCHBOARD_DECIMALS,
+        publish_time,
+    )
+}
+
+/// How far, in basis points and either way, a trade of `amount_in` for
+/// `amount_out` is from the pair price the oracle prices of the two tokens
+/// give. The fee and price impact count towards it. The mints' decimals turn
+/// the per-token prices into per-unit ones; computed exactly in u128, failing
+/// with `ArithmeticOverflow` when the values don't fit.
+pub fn oracle_deviation_bps(
+    amount_in: u64,
+    decimals_in: u8,
+    price_in: OraclePrice,
+    amount_out: u64,
+    decimals_out: u8,
+    price_out: OraclePrice,
+) -> Result<u128> {
+    // Each side's value is `amount * price * 10^(exponent - decimals)`;
+    // the one with the larger power of ten is scaled to the other's
+    let mut value_in = amount_in as u128 * price_in.price as u128;
+    let mut value_out = amount_out as u128 * price_out.price as u128;
+    let exponent_in = price_in.exponent - decimals_in as i32;
+    let exponent_out = price_out.exponent - decimals_out as i32;
+    let scale = 10u128
+        .checked_pow(exponent_in.abs_diff(exponent_out))
+        .ok_or(AmmError::ArithmeticOverflow)?;
+    if exponent_in > exponent_out {
+        value_in = value_in
+            .checked_mul(scale)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+    } else {
+        value_out = value_out
+            .checked_mul(scale)
+            .ok_or(AmmError::ArithmeticOverflow)?;
     }
+    require!(value_in > 0, AmmError::InvalidAmount);
+    Ok(value_in
+        .abs_diff(value_out)
+        .checked_mul(BPS_DENOMINATOR as u128)
+        .ok_or(AmmError::ArithmeticOverflow)?
+        / value_in)
+}
 
-    pub fn remove_liquidity(
-        ctx: Context<RemoveLiquidity>,
-        lp_amount: u64,
-        min_amount_a: u64,
-        min_amount_b: u64,
-    ) -> Result<()> {
-        let pool = &ctx.accounts.pool;
+/// Fails unless `feed_in` and `feed_out` are the guard's feeds for the swap's
+/// input and output, both published within its staleness limit of `now` with
+/// a confidence interval inside its band, and the trade is within that band
+/// of the price they give.
+#[allow(clippy::too_many_arguments)]
+fn check_oracle_guard(
+    guard: &OracleGuard,
+    a_to_b: bool,
+    feed_in: &AccountInfo,
+    feed_out: &AccountInfo,
+    amount_in: u64,
+    decimals_in: u8,
+    amount_out: u64,
+    decimals_out: u8,
+    now: i64,
+) -> Result<()> {
+    let (expected_in, expected_out) = if a_to_b {
+        (guard.price_feed_a, guard.price_feed_b)
+    } else {
+        (guard.price_feed_b, guard.price_feed_a)
+    };
+    require!(
+        feed_in.key() == expected_in && feed_out.key() == expected_out,
+        AmmError::MissingOracleAccount
+    );
+    let price_in = guard.oracle_kind.read_price(feed_in)?;
+    let price_out = guard.oracle_kind.read_price(feed_out)?;
+    for price in [price_in, price_out] {
+        require!(
+            now.saturating_sub(price.publish_time) <= guard.max_staleness_seconds as i64,
+            AmmError::OracleStale
+        );
+        // A price less certain than the band can't tell a trade inside it
+        // from one outside
+        require!(
+            price.conf as u128 * BPS_DENOMINATOR as u128
+                <= price.price as u128 * guard.max_deviation_bps as u128,
+            AmmError::OracleConfidenceTooWide
+        );
+    }
+    let deviation_bps = oracle_deviation_bps(
+        amount_in,
+        decimals_in,
+        price_in,
+        amount_out,
+        decimals_out,
+        price_out,
+    )?;
+    require!(
+        deviation_bps <= guard.max_deviation_bps as u128,
+        AmmError::OraclePriceDeviation
+    );
+    Ok(())
+}
 
-        // Validate input amount
-        require!(lp_amount > 0, AmmError::InvalidAmount);
+/// Maximum number of legs in a single `swap_batch`.
+pub const MAX_SWAP_BATCH_LEGS: usize = 4;
 
-        // Get current pool balances and LP supply
-        let pool_token_a_balance = ctx.accounts.pool_token_a.amount;
-        let pool_token_b_balance = ctx.accounts.pool_token_b.amount;
-        let lp_supply = ctx.accounts.lp_mint.supply;
+/// Accounts per `swap_batch` leg in `remaining_accounts`: pool, pool token A,
+/// pool token B, user token A, user token B, owner token account for the
+/// input mint, token A mint, token B mint.
+pub const SWAP_BATCH_ACCOUNTS_PER_LEG: usize = 8;
 
-        // Validate LP supply is not zero
-        require!(lp_supply > 0, AmmError::InvalidAmount);
+/// Maximum number of pools a single `swap_split` may route through.
+pub const MAX_SWAP_SPLIT_LEGS: usize = 4;
 
-        // Calculate proportional amounts of tokens to return using safer math
-        let amount_a = if lp_amount > 0 && pool_token_a_balance > 0 {
-            // Calculate: (lp_amount * pool_token_a_balance) / lp_supply
-            // Check for overflow before multiplication
-            if lp_amount > u64::MAX / pool_token_a_balance {
-                return err!(AmmError::ArithmeticOverflow);
-            }
-            (lp_amount * pool_token_a_balance) / lp_supply
-        } else {
-            0
-        };
+/// Accounts per `swap_split` leg in `remaining_accounts`: pool, pool token
+/// in, pool token out, owner token account for the input mint.
+pub const SWAP_SPLIT_ACCOUNTS_PER_LEG: usize = 4;
 
-        let amount_b = if lp_amount > 0 && pool_token_b_balance > 0 {
-            // Calculate: (lp_amount * pool_token_b_balance) / lp_supply
-            // Check for overflow before multiplication
-            if lp_amount > u64::MAX / pool_token_b_balance {
-                return err!(AmmError::ArithmeticOverflow);
-            }
-            (lp_amount * pool_token_b_balance) / lp_supply
-        } else {
-            0
-        };
+/// Largest share of the input reserve one `execute_buyback` may spend, in
+/// basis points, so the permissionless crank can't be used for a big trade
+/// around a sandwich.
+pub const MAX_BUYBACK_BPS: u16 = 100;
 
-        // Verify minimum amounts
-        require!(amount_a >= min_amount_a, AmmError::SlippageExceeded);
-        require!(amount_b >= min_amount_b, AmmError::SlippageExceeded);
+/// Where a pool's swap fees go.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default, InitSpace,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FeeMode {
+    /// Left in the vaults as `protocol_fees_owed_a`/`_b` until the pool's
+    /// `fee_recipient` takes it out with `collect_protocol_fees`
+    #[default]
+    Owner,
+    /// Kept in the pool's fee vaults until `execute_buyback` spends them
+    /// on `buyback_mint` and burns it
+    BuybackBurn,
+}
 
-        // Create signer seeds for pool authority
-        let seeds = [
-            b"pool".as_ref(),
-            ctx.accounts.pool.token_a_mint.as_ref(),
-            ctx.accounts.pool.token_b_mint.as_ref(),
-            &[ctx.accounts.pool.bump],
-        ];
-        let signer_seeds = [&seeds[..]];
+/// `Pool::status` bit pausing every swap, including order fills, reveals
+/// and buybacks.
+pub const POOL_STATUS_SWAPS_PAUSED: u8 = 1 << 0;
 
-        // Transfer tokens from pool to user
-        let cpi_accounts_a = Transfer {
-            from: ctx.accounts.pool_token_a.to_account_info(),
-            to: ctx.accounts.user_token_a.to_account_info(),
-            authority: ctx.accounts.pool.to_account_info(),
-        };
-        let cpi_ctx_a = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts_a,
-            &signer_seeds,
-        );
-        token::transfer(cpi_ctx_a, amount_a)?;
+/// `Pool::status` bit pausing deposits, including migrations into the pool.
+pub const POOL_STATUS_DEPOSITS_PAUSED: u8 = 1 << 1;
 
-        let cpi_accounts_b = Transfer {
-            from: ctx.accounts.pool_token_b.to_account_info(),
-            to: ctx.accounts.user_token_b.to_account_info(),
-            authority: ctx.accounts.pool.to_account_info(),
-        };
-        let cpi_ctx_b = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts_b,
-            &signer_seeds,
-        );
-        token::transfer(cpi_ctx_b, amount_b)?;
+/// `Pool::status` bit pausing withdrawals, including migrations out of the
+/// pool. Only set with `emergency`, as it traps the LPs' funds.
+pub const POOL_STATUS_WITHDRAWALS_PAUSED: u8 = 1 << 2;
 
-        // Burn LP tokens - user is the authority for their own tokens
-        let cpi_accounts_burn = token::Burn {
-            mint: ctx.accounts.lp_mint.to_account_info(),
-            from: ctx.accounts.user_lp.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        };
-        let cpi_ctx_burn = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts_burn,
-        );
-        token::burn(cpi_ctx_burn, lp_amount)?;
+/// Every `Pool::status` bit.
+pub const POOL_STATUS_ALL_PAUSED: u8 =
+    POOL_STATUS_SWAPS_PAUSED | POOL_STATUS_DEPOSITS_PAUSED | POOL_STATUS_WITHDRAWALS_PAUSED;
 
-        emit!(LiquidityRemovedEvent {
-            pool: pool.key(),
-            user: ctx.accounts.user.key(),
-            amount_a,
-            amount_b,
-            lp_amount,
-            pool_token_a_balance: ctx.accounts.pool_token_a.amount,
-            pool_token_b_balance: ctx.accounts.pool_token_b.amount,
-        });
+/// Highest withdrawal fee a pool may charge, in basis points (1%).
+pub const MAX_WITHDRAWAL_FEE_BPS: u16 = 100;
 
-        Ok(())
-    }
-}
+/// Highest swap fee a pool may charge, in basis points (10%).
+pub const MAX_FEE_BPS: u16 = 1_000;
 
-#[derive(Accounts)]
-pub struct InitializePool<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + Pool::LEN,
-        seeds = [
-            b"pool",
-            token_a_mint.key().as_ref(),
-            token_b_mint.key().as_ref(),
-        ],
-        bump
-    )]
-    pub pool: Account<'info, Pool>,
+/// Highest deposit fee a pool may charge, in basis points (1%).
+pub const MAX_DEPOSIT_FEE_BPS: u16 = 100;
 
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
+/// Share of an order's input paid from the escrow to whoever fills it, in
+/// basis points (0.1%).
+pub const ORDER_FILLER_REWARD_BPS: u16 = 10;
 
-    #[account(mut)]
-    pub token_a_account: Account<'info, TokenAccount>,
+/// Lamports a `commit_swap` escrows until the commitment is revealed or
+/// reclaimed.
+pub const COMMIT_BOND_LAMPORTS: u64 = 1_000_000;
 
-    #[account(mut)]
-    pub token_b_account: Account<'info, TokenAccount>,
+/// Longest reveal window a `commit_swap` may ask for, in slots.
+pub const MAX_COMMIT_WINDOW_SLOTS: u64 = 1_000;
 
-    #[account(mut)]
-    pub lp_mint: Account<'info, Mint>,
+/// Fixed-point scale of `Farm::acc_reward_per_share`.
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
 
-    #[account(mut)]
-    pub authority: Signer<'info>,
+/// Maximum number of queued entries in a farm's emission schedule.
+pub const MAX_EMISSION_ENTRIES: usize = 8;
 
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+/// A farm's emission rate from `start_timestamp` on.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, InitSpace)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmissionEntry {
+    pub start_timestamp: i64,
+    pub reward_rate: u64,
 }
 
-#[derive(Accounts)]
-pub struct AddLiquidity<'info> {
+/// Most tiers a `DiscountConfig` may hold.
+pub const MAX_DISCOUNT_TIERS: usize = 4;
+
+/// Fee discount granted to holders of at least `min_balance` of the discount
+/// mint.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, InitSpace)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiscountTier {
+    pub min_balance: u64,
+    pub discount_bps: u16,
+}
+
+/// Longest pool display name in `PoolMetadata`, in bytes.
+pub const MAX_POOL_NAME_LEN: usize = 32;
+
+/// Longest off-chain metadata URI in `PoolMetadata`, in bytes.
+pub const MAX_POOL_URI_LEN: usize = 200;
+
+/// Longest memo a swap may carry, in bytes.
+pub const MAX_SWAP_MEMO_LEN: usize = 64;
+
+/// Most wallets a pool's protocol fees may be split between.
+pub const MAX_FEE_RECIPIENTS: usize = 4;
+
+/// A wallet receiving `weight_bps` of a pool's protocol fees.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeShare {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub recipient: Pubkey,
+    pub weight_bps: u16,
+}
+
+/// What one fee recipient was paid by `collect_protocol_fees`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeePayout {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub recipient: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+
+/// Divides `amount` between `shares` by weight, rounding down, with what the
+/// rounding leaves over going to the first so the parts add up to `amount`.
+fn split_by_weight(amount: u64, shares: &[FeeShare]) -> Vec<u64> {
+    let mut parts: Vec<u64> = shares
+        .iter()
+        .map(|share| (amount as u128 * share.weight_bps as u128 / BPS_DENOMINATOR as u128) as u64)
+        .collect();
+    parts[0] += amount - parts.iter().sum::<u64>();
+    parts
+}
+
+/// Fails with `InvalidFeeRecipientAccounts` unless `token_a` and `token_b`
+/// are `share`'s recipient's accounts in the pool's mints.
+fn check_fee_payee(
+    pool: &Pool,
+    share: &FeeShare,
+    token_a: &TokenAccount,
+    token_b: &TokenAccount,
+) -> Result<()> {
+    require!(
+        token_a.owner == share.recipient
+            && token_a.mint == pool.token_a_mint
+            && token_b.owner == share.recipient
+            && token_b.mint == pool.token_b_mint,
+        AmmError::InvalidFeeRecipientAccounts
+    );
+    Ok(())
+}
+
+/// Most fee tiers the program config may allow.
+pub const MAX_FEE_TIERS: usize = 8;
+
+/// Bounds on the input of every swap on a pool; zero turns either off.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default, InitSpace,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TradeLimits {
+    /// The smallest input a swap may trade, keeping dust out of the pool's
+    /// history
+    pub min_trade_amount: u64,
+    /// The largest input a swap may trade, as a share of the input reserve
+    pub max_trade_bps: u16,
+}
+
+impl TradeLimits {
+    /// Fails with `InvalidTradeLimits` unless `max_trade_bps` is at most
+    /// 10,000 bps.
+    pub fn check(&self) -> Result<()> {
+        require!(
+            self.max_trade_bps <= BPS_DENOMINATOR,
+            AmmError::InvalidTradeLimits
+        );
+        Ok(())
+    }
+
+    /// Fails with `TradeTooSmall` or `TradeTooLarge` unless `amount_in` is
+    /// within the limits against `reserve_in`.
+    pub fn check_trade(&self, amount_in: u64, reserve_in: u64) -> Result<()> {
+        require!(amount_in >= self.min_trade_amount, AmmError::TradeTooSmall);
+        if self.max_trade_bps > 0 {
+            let max_trade =
+                reserve_in as u128 * self.max_trade_bps as u128 / BPS_DENOMINATOR as u128;
+            require!(amount_in as u128 <= max_trade, AmmError::TradeTooLarge);
+        }
+        Ok(())
+    }
+}
+
+/// A swap fee pools may charge, in basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, InitSpace)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeTier {
+    pub fee_bps: u16,
+}
+
+/// Most observations a pool's `Observations` buffer may hold; keeps the
+/// account within one realloc and the deserialized buffer within the heap.
+pub const MAX_OBSERVATION_CARDINALITY: u16 = 200;
+
+/// Layout version of new pools. Pools created before `version` existed read
+/// as 0 and are brought up to date by `migrate_pool`; version 2 moved the
+/// fee to `total_fee_bps`.
+pub const POOL_VERSION: u8 = 2;
+
+/// Bytes kept free at the end of `Pool` for fields added later.
+pub const POOL_RESERVED_BYTES: usize = 17;
+
+/// The pool's cumulative prices as of `timestamp`, recorded by a swap.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, InitSpace)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Observation {
+    pub slot: u64,
+    pub timestamp: i64,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub price_a_cumulative: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub price_b_cumulative: u128,
+}
+
+/// Slots a pool parameter change waits before it can be applied when the
+/// program config doesn't set a delay; about a day of 400ms slots.
+pub const DEFAULT_PARAM_CHANGE_DELAY_SLOTS: u64 = 216_000;
+
+/// Slots a pool must stay fully paused before `emergency_withdraw` when the
+/// program config doesn't set a grace period; about an hour of 400ms slots.
+pub const DEFAULT_EMERGENCY_GRACE_SLOTS: u64 = 9_000;
+
+/// One of a pool's two tokens.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VaultSide {
+    A,
+    B,
+}
+
+/// A timelocked change to a pool parameter, with its new value.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParamChange {
+    Fee {
+        total_fee_bps: u16,
+    },
+    FeeRecipient {
+        #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+        fee_recipient: Pubkey,
+    },
+    /// Moves a side's tokens to a fresh vault; carried out by `migrate_vault`
+    /// rather than `apply_param_change`
+    VaultMigration {
+        side: VaultSide,
+    },
+    /// Share of the protocol fees `collect_protocol_fees` sets aside in the
+    /// insurance vaults
+    InsuranceShare {
+        insurance_share_bps: u16,
+    },
+    /// Pays `amount` from the insurance vault in `recipient`'s mint to
+    /// `recipient`, a token account; carried out by `disburse_insurance`
+    InsuranceDisbursement {
+        amount: u64,
+        #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+        recipient: Pubkey,
+    },
+}
+
+impl ParamChange {
+    /// Fails unless the new value is one a pool may take under `config`.
+    fn validate(&self, config: Option<&GlobalConfig>) -> Result<()> {
+        match *self {
+            ParamChange::Fee { total_fee_bps } => {
+                check_fee(total_fee_bps)?;
+                check_fee_tier(config, total_fee_bps)
+            }
+            ParamChange::FeeRecipient { fee_recipient } => {
+                require!(
+                    fee_recipient != Pubkey::default(),
+                    AmmError::InvalidFeeRecipient
+                );
+                Ok(())
+            }
+            ParamChange::InsuranceShare {
+                insurance_share_bps,
+            } => {
+                require!(
+                    insurance_share_bps <= BPS_DENOMINATOR,
+                    AmmError::InvalidInsuranceShare
+                );
+                Ok(())
+            }
+            ParamChange::InsuranceDisbursement { amount, .. } => {
+                require!(amount > 0, AmmError::InvalidAmount);
+                Ok(())
+            }
+            ParamChange::VaultMigration { .. } => Ok(()),
+        }
+    }
+
+    /// The key that may propose or cancel the change: the fee authority for
+    /// the fee recipient, the pool authority for the rest.
+    pub fn role(&self, pool: &Pool) -> Pubkey {
+        match self {
+            ParamChange::FeeRecipient { .. } => pool.fee_authority(),
+            _ => pool.authority,
+        }
+    }
+
+    /// Writes the new value into `pool`, returning the change that would
+    /// restore the old one.
+    fn apply(&self, pool: &mut Pool) -> ParamChange {
+        match *self {
+            ParamChange::Fee { total_fee_bps } => {
+                // A pool still on the legacy fraction reverts to the
+                // nearest bps below it, since that is all `Fee` can hold
+                let previous = ParamChange::Fee {
+                    total_fee_bps: pool.fee_bps_rounded_down(),
+                };
+                pool.set_fee(total_fee_bps);
+                previous
+            }
+            ParamChange::FeeRecipient { fee_recipient } => {
+                let previous = ParamChange::FeeRecipient {
+                    fee_recipient: pool.fee_recipient,
+                };
+                pool.fee_recipient = fee_recipient;
+                previous
+            }
+            ParamChange::InsuranceShare {
+                insurance_share_bps,
+            } => {
+                let previous = ParamChange::InsuranceShare {
+                    insurance_share_bps: pool.insurance_share_bps,
+                };
+                pool.insurance_share_bps = insurance_share_bps;
+                previous
+            }
+            ParamChange::VaultMigration { .. } | ParamChange::InsuranceDisbursement { .. } => *self,
+        }
+    }
+}
+
+/// Who may create pools.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default, InitSpace,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PoolCreation {
+    /// Anyone
+    #[default]
+    Permissionless,
+    /// Only the config admin
+    AdminOnly,
+    /// The config admin and creators added with `add_pool_creator`
+    Allowlist,
+}
+
+/// Changes made by `update_config`; `None` leaves a field as it is.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default)]
+pub struct ConfigUpdate {
+    pub admin: Option<Pubkey>,
+    pub default_protocol_fee_bps: Option<u16>,
+    pub paused: Option<bool>,
+    pub pool_creation: Option<PoolCreation>,
+    pub fee_tiers: Option<Vec<FeeTier>>,
+    pub param_change_delay_slots: Option<u64>,
+    pub emergency_grace_slots: Option<u64>,
+    pub guardian: Option<Pubkey>,
+    pub pool_creation_fee_lamports: Option<u64>,
+    pub treasury: Option<Pubkey>,
+    pub require_badge: Option<bool>,
+    pub lp_metadata_uri: Option<String>,
+}
+
+/// Swap fee discounts for holders of `mint`, with tiers in increasing order
+/// of `min_balance`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, InitSpace)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiscountConfig {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub mint: Pubkey,
+    #[max_len(MAX_DISCOUNT_TIERS)]
+    pub tiers: Vec<DiscountTier>,
+}
+
+/// One swap in a `swap_batch`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SwapLeg {
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+    pub a_to_b: bool,
+}
+
+/// `sol_amm_math::price_move_bps` with its errors mapped to `AmmError`.
+pub fn price_move_bps(
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in: u64,
+    amount_out: u64,
+) -> Result<u16> {
+    Ok(
+        sol_amm_math::price_move_bps(reserve_in, reserve_out, amount_in, amount_out)
+            .map_err(AmmError::from)?,
+    )
+}
+
+/// `sol_amm_math::compute_swap` with its errors mapped to `AmmError`.
+pub fn compute_swap(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<SwapQuote> {
+    Ok(sol_amm_math::compute_swap(
+        amount_in,
+        reserve_in,
+        reserve_out,
+        fee_numerator,
+        fee_denominator,
+    )
+    .map_err(AmmError::from)?)
+}
+
+/// `sol_amm_math::compute_amount_in` with its errors mapped to `AmmError`.
+pub fn compute_amount_in(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    Ok(sol_amm_math::compute_amount_in(
+        amount_out,
+        reserve_in,
+        reserve_out,
+        fee_numerator,
+        fee_denominator,
+    )
+    .map_err(AmmError::from)?)
+}
+
+/// LP tokens the program mints for depositing `amount_a`/`amount_b` into
+/// reserves `pool_a`/`pool_b`, shared by `add_liquidity` and its quote:
+/// `sol_amm_math::compute_lp_tokens` with its errors mapped to `AmmError`.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_lp_tokens(
+    amount_a: u64,
+    amount_b: u64,
+    pool_a: u64,
+    pool_b: u64,
+    lp_supply: u64,
+    lp_decimals: u8,
+    token_a_decimals: u8,
+    token_b_decimals: u8,
+) -> Result<u64> {
+    Ok(sol_amm_math::compute_lp_tokens(
+        amount_a,
+        amount_b,
+        pool_a,
+        pool_b,
+        lp_supply,
+        lp_decimals,
+        token_a_decimals,
+        token_b_decimals,
+    )
+    .map_err(AmmError::from)?)
+}
+
+/// `sol_amm_math::compute_imbalanced_deposit` with its errors mapped to
+/// `AmmError`.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_imbalanced_deposit(
+    amount_a: u64,
+    amount_b: u64,
+    pool_a: u64,
+    pool_b: u64,
+    lp_supply: u64,
+    lp_decimals: u8,
+    token_a_decimals: u8,
+    token_b_decimals: u8,
+    fee_numerator: u64,
+    fee_denominator: u64,
+    max_imbalance_bps: u16,
+) -> Result<ImbalancedDeposit> {
+    Ok(sol_amm_math::compute_imbalanced_deposit(
+        amount_a,
+        amount_b,
+        pool_a,
+        pool_b,
+        lp_supply,
+        lp_decimals,
+        token_a_decimals,
+        token_b_decimals,
+        fee_numerator,
+        fee_denominator,
+        max_imbalance_bps,
+    )
+    .map_err(AmmError::from)?)
+}
+
+/// Amounts of each token paid out for burning `lp_amount` against reserves
+/// `pool_a`/`pool_b`, shared by the removal instructions and their quote:
+/// `sol_amm_math::compute_withdrawal` with its errors mapped to `AmmError`.
+pub fn compute_withdrawal(
+    lp_amount: u64,
+    pool_a: u64,
+    pool_b: u64,
+    lp_supply: u64,
+) -> Result<(u64, u64)> {
+    Ok(
+        sol_amm_math::compute_withdrawal(lp_amount, pool_a, pool_b, lp_supply)
+            .map_err(AmmError::from)?,
+    )
+}
+
+/// Checks the accounts a deposit fee is paid into: both must belong to the
+/// config's protocol fee recipient and be in `pool`'s mints.
+fn protocol_fee_accounts<'a, 'info>(
+    pool: &Pool,
+    config: Option<&Account<'info, GlobalConfig>>,
+    protocol_fee_a: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    protocol_fee_b: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+) -> Result<(
+    &'a InterfaceAccount<'info, TokenAccount>,
+    &'a InterfaceAccount<'info, TokenAccount>,
+)> {
+    let (Some(config), Some(protocol_fee_a), Some(protocol_fee_b)) =
+        (config, protocol_fee_a, protocol_fee_b)
+    else {
+        return err!(AmmError::InvalidProtocolFeeAccount);
+    };
+    let recipient = config.protocol_fee_recipient;
+    require!(
+        recipient != Pubkey::default()
+            && protocol_fee_a.owner == recipient
+            && protocol_fee_b.owner == recipient
+            && protocol_fee_a.mint == pool.token_a_mint
+            && protocol_fee_b.mint == pool.token_b_mint,
+        AmmError::InvalidProtocolFeeAccount
+    );
+    Ok((protocol_fee_a, protocol_fee_b))
+}
+
+/// Whether `wallet` is the config's treasury, whose deposits and withdrawals
+/// count towards a pool's `protocol_lp_amount`.
+fn is_protocol_treasury(config: Option<&GlobalConfig>, wallet: &Pubkey) -> bool {
+    config.is_some_and(|config| config.treasury != Pubkey::default() && config.treasury == *wallet)
+}
+
+/// Result of `quote_swap`, returned to the caller via return data.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct QuoteSwapResult {
+    pub amount_out: u64,
+    pub fee: u64,
+    pub price_impact_bps: u16,
+}
+
+/// Result of `swap` and `swap_exact_out`, returned to the caller via return
+/// data, so a program swapping through CPI can read what it traded.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SwapResult {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+}
+
+impl From<&SwapExecutedEvent> for SwapResult {
+    fn from(event: &SwapExecutedEvent) -> Self {
+        Self {
+            amount_in: event.amount_in,
+            amount_out: event.amount_out,
+            fee: event.fee,
+        }
+    }
+}
+
+/// Result of `quote_add_liquidity`, returned to the caller via return data.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct QuoteAddLiquidityResult {
+    pub lp_tokens: u64,
+}
+
+/// Result of `quote_remove_liquidity`, returned to the caller via return data.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct QuoteRemoveLiquidityResult {
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+
+/// Result of `get_insurance_balance`, returned to the caller via return data.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct InsuranceBalance {
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub insurance_share_bps: u16,
+}
+
+/// Result of `get_spot_price`, returned to the caller via return data. The
+/// prices are Q64.64, like `get_twap`'s.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SpotPriceResult {
+    /// Price of token A in token B, `reserve_b / reserve_a`
+    pub price_a: u128,
+    /// Price of token B in token A, `reserve_a / reserve_b`
+    pub price_b: u128,
+    /// When it was read
+    pub timestamp: i64,
+}
+
+/// Result of `get_twap`, returned to the caller via return data. The prices
+/// are Q64.64, like the pool's cumulative prices.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TwapResult {
+    /// Average price of token A in token B over the window
+    pub price_a: u128,
+    /// Average price of token B in token A over the window
+    pub price_b: u128,
+    /// End of the window
+    pub timestamp: i64,
+}
+
+/// Result of `get_stats`, returned to the caller via return data.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PoolStatsResult {
+    pub cumulative_volume_a: u128,
+    pub cumulative_volume_b: u128,
+    pub cumulative_fees_a: u128,
+    pub cumulative_fees_b: u128,
+    pub swap_count: u128,
+    pub liquidity_event_count: u128,
+    pub created_at_timestamp: i64,
+    pub created_at_slot: u64,
+    pub last_swap_timestamp: i64,
+    pub last_liquidity_change_timestamp: i64,
+}
+
+/// Result of `get_protocol_liquidity`, returned to the caller via return
+/// data.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ProtocolLiquidity {
+    /// The pool's `protocol_lp_amount`
+    pub protocol_lp_amount: u64,
+    /// The rest of the LP supply
+    pub external_lp_amount: u64,
+    pub lp_supply: u64,
+}
+
+/// Brings `farm.acc_reward_per_share` up to `now`, spreading the rewards
+/// emitted since the last update over the LP staked during that time. The
+/// emission is integrated piecewise across every schedule entry that started
+/// in between, however many there are; started entries become the current
+/// rate and leave the queue.
+fn update_farm_rewards(farm: &mut Farm, now: i64) -> Result<()> {
+    if now <= farm.last_update_timestamp {
+        return Ok(());
+    }
+
+    let mut emitted: u128 = 0;
+    let mut segment_start = farm.last_update_timestamp;
+    let mut started = 0;
+    for entry in farm.emission_schedule.iter() {
+        if entry.start_timestamp > now {
+            break;
+        }
+        // Entries are queued after the last update, so they never start
+        // before `segment_start`
+        let elapsed = (entry.start_timestamp - segment_start) as u128;
+        emitted = emitted
+            .checked_add(elapsed * farm.reward_rate_per_second as u128)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        farm.reward_rate_per_second = entry.reward_rate;
+        segment_start = entry.start_timestamp;
+        started += 1;
+    }
+    let elapsed = (now - segment_start) as u128;
+    emitted = emitted
+        .checked_add(elapsed * farm.reward_rate_per_second as u128)
+        .ok_or(AmmError::ArithmeticOverflow)?;
+    farm.emission_schedule.drain(..started);
+
+    if farm.total_staked > 0 {
+        let per_share = emitted
+            .checked_mul(REWARD_PRECISION)
+            .ok_or(AmmError::ArithmeticOverflow)?
+            / farm.total_staked as u128;
+        farm.acc_reward_per_share = farm
+            .acc_reward_per_share
+            .checked_add(per_share)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+    }
+    farm.last_update_timestamp = now;
+    Ok(())
+}
+
+/// Rewards accrued to `amount` staked LP at the farm's current accumulator.
+fn accrued_rewards(farm: &Farm, amount: u64) -> Result<u128> {
+    Ok((amount as u128)
+        .checked_mul(farm.acc_reward_per_share)
+        .ok_or(AmmError::ArithmeticOverflow)?
+        / REWARD_PRECISION)
+}
+
+/// Moves what a position earned since its last interaction into
+/// `pending_rewards`. The farm must be up to date; set `reward_debt` again
+/// after changing the staked amount.
+fn settle_stake_rewards(farm: &Farm, position: &mut StakePosition) -> Result<()> {
+    let accrued = accrued_rewards(farm, position.amount)?;
+    let earned = u64::try_from(accrued.saturating_sub(position.reward_debt))
+        .map_err(|_| error!(AmmError::ArithmeticOverflow))?;
+    position.pending_rewards = position
+        .pending_rewards
+        .checked_add(earned)
+        .ok_or(AmmError::ArithmeticOverflow)?;
+    position.reward_debt = accrued;
+    Ok(())
+}
+
+/// Accounts taking part in a single swap, shared by `swap` and `swap_batch`.
+struct SwapAccounts<'a, 'info> {
+    pool: &'a mut Account<'info, Pool>,
+    user: AccountInfo<'info>,
+    user_token_in: AccountInfo<'info>,
+    user_token_out: AccountInfo<'info>,
+    pool_token_in: &'a InterfaceAccount<'info, TokenAccount>,
+    pool_token_out: &'a InterfaceAccount<'info, TokenAccount>,
+    /// Fee vault for the input mint; only used by `BuybackBurn` pools
+    owner_token_account: Option<AccountInfo<'info>>,
+    token_in_mint: &'a InterfaceAccount<'info, Mint>,
+    token_out_mint: &'a InterfaceAccount<'info, Mint>,
+    token_program: &'a Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    token_program_b: Option<&'a Interface<'info, TokenInterface>>,
+    /// `user`'s allowlist entry, checked when the pool is gated
+    allowlist_entry: Option<&'a AllowlistEntry>,
+    /// `user`'s launch purchases, needed to buy the launched token while the
+    /// launch window is open
+    launch_purchase: Option<&'a mut LaunchPurchase>,
+    /// Discount on the pool fee, in basis points
+    fee_discount_bps: u16,
+    /// The pool's swap hook program, required when the pool has one
+    hook_program: Option<AccountInfo<'info>>,
+    /// Passed on to the swap hook after the pool
+    hook_accounts: &'a [AccountInfo<'info>],
+    /// Seeds `user` signs the input transfers with, when it is a PDA
+    user_signer_seeds: &'a [&'a [&'a [u8]]],
+    /// The program config, once it exists; its pause flag stops every swap
+    config: Option<&'a GlobalConfig>,
+    /// The pool's oracle observations, when the caller passes them
+    observations: Option<&'a mut Observations>,
+    /// The pool's statistics, when the caller passes them
+    stats: Option<&'a mut Account<'info, PoolStats>>,
+    /// The pool's oracle guard and its feeds for token A and token B, needed
+    /// when the pool has one
+    oracle_guard: Option<&'a Account<'info, OracleGuard>>,
+    price_feed_a: Option<AccountInfo<'info>>,
+    price_feed_b: Option<AccountInfo<'info>>,
+}
+
+/// Fills in a `LaunchPurchase` that `init_if_needed` has just created; an
+/// existing one is left as is.
+fn init_launch_purchase(
+    purchase: &mut LaunchPurchase,
+    pool: Pubkey,
+    wallet: Pubkey,
+    payer: Pubkey,
+    bump: Option<u8>,
+) {
+    if purchase.wallet == Pubkey::default() {
+        purchase.pool = pool;
+        purchase.wallet = wallet;
+        purchase.payer = payer;
+        purchase.bump = bump.unwrap_or_default();
+    }
+}
+
+/// What `process_swap` did: the trade, or the pool's circuit breaker tripping
+/// instead, which pauses swaps on the pool without moving any tokens.
+enum SwapOutcome {
+    Executed(SwapExecutedEvent),
+    Tripped(CircuitBreakerTrippedEvent),
+}
+
+impl SwapOutcome {
+    /// The trade's event. A tripped breaker fails with
+    /// `CircuitBreakerTripped`, reverting its pause, for instructions that
+    /// can't succeed without the trade.
+    fn executed(self) -> Result<SwapExecutedEvent> {
+        match self {
+            SwapOutcome::Executed(event) => Ok(event),
+            SwapOutcome::Tripped(_) => err!(AmmError::CircuitBreakerTripped),
+        }
+    }
+
+    /// The trade's event, or `None` once a tripped breaker's event has been
+    /// emitted, for `swap_v2` and `swap_and_create`, which keep the pause by
+    /// succeeding without the trade. They return no data, so a caller can't
+    /// mistake the outcome for a trade.
+    fn unless_tripped(self) -> Option<SwapExecutedEvent> {
+        match self {
+            SwapOutcome::Executed(event) => Some(event),
+            SwapOutcome::Tripped(event) => {
+                emit!(event);
+                None
+            }
+        }
+    }
+}
+
+/// Validates, prices and settles one swap, returning the event to emit.
+fn process_swap(
+    accounts: SwapAccounts,
+    amount_in: u64,
+    min_amount_out: u64,
+) -> Result<SwapOutcome> {
+    let pool = accounts.pool;
+    require!(
+        !accounts.config.is_some_and(|config| config.paused),
+        AmmError::ProtocolPaused
+    );
+    pool.require_active(POOL_STATUS_SWAPS_PAUSED)?;
+    require!(!pool.locked, AmmError::PoolLocked);
+    require!(!pool.in_hook, AmmError::ReentrantSwap);
+    if pool.hook_program != Pubkey::default() {
+        require!(
+            accounts
+                .hook_program
+                .as_ref()
+                .is_some_and(|hook_program| hook_program.key() == pool.hook_program),
+            AmmError::MissingHookProgram
+        );
+    }
+    require!(
+        Clock::get()?.unix_timestamp >= pool.trade_start_timestamp,
+        AmmError::TradingNotStarted
+    );
+    if pool.gated {
+        check_allowlist(&pool.key(), accounts.user.key, accounts.allowlist_entry)?;
+    }
+
+    // Validate input amount
+    require!(amount_in > 0, AmmError::InvalidAmount);
+
+    // The in/out vaults must be the pool's vaults, in either order
+    let pool_token_in_key = accounts.pool_token_in.key();
+    let pool_token_out_key = accounts.pool_token_out.key();
+    let a_to_b = if pool_token_in_key == pool.token_a_account
+        && pool_token_out_key == pool.token_b_account
+    {
+        true
+    } else if pool_token_in_key == pool.token_b_account
+        && pool_token_out_key == pool.token_a_account
+    {
+        false
+    } else {
+        return err!(AmmError::InvalidPoolTokenAccount);
+    };
+    // Dust swaps are refused before any transfer or fee
+    require!(
+        amount_in >= pool.min_trade_amount_in(a_to_b),
+        AmmError::TradeTooSmall
+    );
+    // Buyback pools pay the protocol fee into their fee vault; the others
+    // keep it in the pool vault, owed to the fee recipient
+    let fee_vault = if pool.fee_mode == FeeMode::BuybackBurn {
+        let fee_vault = if a_to_b {
+            pool.fee_vault_a
+        } else {
+            pool.fee_vault_b
+        };
+        let owner_token_account = accounts
+            .owner_token_account
+            .filter(|owner_token_account| owner_token_account.key() == fee_vault)
+            .ok_or(AmmError::InvalidFeeAccount)?;
+        Some(owner_token_account)
+    } else {
+        None
+    };
+
+    let (fee_numerator, fee_denominator) = discounted_fee(pool, accounts.fee_discount_bps)?;
+    let reserve_in = pool.reserve(accounts.pool_token_in);
+    let reserve_out = pool.reserve(accounts.pool_token_out);
+    pool.trade_limits.check_trade(amount_in, reserve_in)?;
+    let SwapQuote {
+        fee,
+        amount_in_after_fee,
+        amount_out,
+    } = compute_swap(
+        amount_in,
+        reserve_in,
+        reserve_out,
+        fee_numerator,
+        fee_denominator,
+    )?;
+
+    if pool.oracle_guard {
+        let (Some(guard), Some(price_feed_a), Some(price_feed_b)) = (
+            accounts.oracle_guard,
+            &accounts.price_feed_a,
+            &accounts.price_feed_b,
+        ) else {
+            return err!(AmmError::MissingOracleAccount);
+        };
+        let (feed_in, feed_out) = if a_to_b {
+            (price_feed_a, price_feed_b)
+        } else {
+            (price_feed_b, price_feed_a)
+        };
+        check_oracle_guard(
+            guard,
+            a_to_b,
+            feed_in,
+            feed_out,
+            amount_in,
+            accounts.token_in_mint.decimals,
+            amount_out,
+            accounts.token_out_mint.decimals,
+            Clock::get()?.unix_timestamp,
+        )?;
+    }
+
+    // Verify minimum amount out
+    require!(amount_out >= min_amount_out, AmmError::SlippageExceeded);
+
+    if pool.breaker_threshold_bps > 0 {
+        let price_move_bps =
+            price_move_bps(reserve_in, reserve_out, amount_in_after_fee, amount_out)?;
+        if price_move_bps > pool.breaker_threshold_bps {
+            // Only a trade that could have gone through pauses the pool, so
+            // naming an amount the trader doesn't hold can't
+            require!(
+                token_account_amount(&accounts.user_token_in)? >= amount_in,
+                AmmError::InsufficientInputBalance
+            );
+            let previous_status = pool.status;
+            pool.set_status(
+                previous_status | POOL_STATUS_SWAPS_PAUSED,
+                Clock::get()?.slot,
+            );
+            let (token_in, token_out) = if a_to_b {
+                (pool.token_a_mint, pool.token_b_mint)
+            } else {
+                (pool.token_b_mint, pool.token_a_mint)
+            };
+            let sequence = pool.next_sequence();
+            return Ok(SwapOutcome::Tripped(CircuitBreakerTrippedEvent {
+                version: SCHEMA_VERSION,
+                pool: pool.key(),
+                user: accounts.user.key(),
+                token_in,
+                token_out,
+                amount_in,
+                amount_out,
+                reserve_in,
+                reserve_out,
+                price_move_bps,
+                breaker_threshold_bps: pool.breaker_threshold_bps,
+                previous_status,
+                status: pool.status,
+                timestamp: Clock::get()?.unix_timestamp,
+                sequence,
+                slot: Clock::get()?.slot,
+            }));
+        }
+    }
+
+    let token_out_mint = if a_to_b {
+        pool.token_b_mint
+    } else {
+        pool.token_a_mint
+    };
+    record_launch_purchase(pool, token_out_mint, amount_out, accounts.launch_purchase)?;
+
+    // Only `amount_in_after_fee` traded against the curve; the LP share of the
+    // fee joins it in the vault, growing the reserves behind every LP token
+    let (lp_fee, protocol_fee) = split_swap_fee(fee, pool.lp_fee_bps);
+    let to_reserves = amount_in_after_fee
+        .checked_add(lp_fee)
+        .ok_or(AmmError::ArithmeticOverflow)?;
+    let mut amount_to_pool = to_reserves;
+    let mut fee_owed = 0;
+    let token_program_in = token_program_for(
+        accounts.token_in_mint,
+        accounts.token_program,
+        accounts.token_program_b,
+    )?
+    .to_account_info();
+    let token_program_out = token_program_for(
+        accounts.token_out_mint,
+        accounts.token_program,
+        accounts.token_program_b,
+    )?
+    .to_account_info();
+    match fee_vault {
+        // Transfer the protocol fee directly from user to the fee vault
+        // (before the main transfer)
+        Some(fee_vault) if protocol_fee > 0 => {
+            let cpi_accounts_fee = TransferChecked {
+                from: accounts.user_token_in.clone(),
+                mint: accounts.token_in_mint.to_account_info(),
+                to: fee_vault,
+                authority: accounts.user.clone(),
+            };
+            let cpi_ctx_fee = CpiContext::new_with_signer(
+                token_program_in.clone(),
+                cpi_accounts_fee,
+                accounts.user_signer_seeds,
+            );
+            token_interface::transfer_checked(
+                cpi_ctx_fee,
+                protocol_fee,
+                accounts.token_in_mint.decimals,
+            )?;
+        }
+        Some(_) => {}
+        None => {
+            fee_owed = protocol_fee;
+            amount_to_pool = amount_to_pool
+                .checked_add(protocol_fee)
+                .ok_or(AmmError::ArithmeticOverflow)?;
+        }
+    }
+
+    // Transfer remaining tokens from user to pool
+    let cpi_accounts_in = TransferChecked {
+        from: accounts.user_token_in,
+        mint: accounts.token_in_mint.to_account_info(),
+        to: accounts.pool_token_in.to_account_info(),
+        authority: accounts.user.clone(),
+    };
+    let cpi_ctx_in = CpiContext::new_with_signer(
+        token_program_in,
+        cpi_accounts_in,
+        accounts.user_signer_seeds,
+    );
+    token_interface::transfer_checked(cpi_ctx_in, amount_to_pool, accounts.token_in_mint.decimals)?;
+
+    // Transfer output tokens from pool to user
+    let recipient = accounts.user_token_out.key();
+    let cpi_accounts_out = TransferChecked {
+        from: accounts.pool_token_out.to_account_info(),
+        mint: accounts.token_out_mint.to_account_info(),
+        to: accounts.user_token_out,
+        authority: pool.to_account_info(),
+    };
+    let seeds = [
+        b"pool",
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &pool.pool_index.to_le_bytes(),
+        &[pool.bump],
+    ];
+    let signer_seeds = [&seeds[..]];
+    let cpi_ctx_out =
+        CpiContext::new_with_signer(token_program_out, cpi_accounts_out, &signer_seeds);
+    token_interface::transfer_checked(cpi_ctx_out, amount_out, accounts.token_out_mint.decimals)?;
+
+    pool.update_oracle(Clock::get()?.unix_timestamp);
+    let (token_in, token_out) = if a_to_b {
+        pool.reserve_a = pool
+            .reserve_a
+            .checked_add(to_reserves)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        pool.reserve_b = pool.reserve_b.saturating_sub(amount_out);
+        pool.protocol_fees_owed_a = pool
+            .protocol_fees_owed_a
+            .checked_add(fee_owed)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        (pool.token_a_mint, pool.token_b_mint)
+    } else {
+        pool.reserve_b = pool
+            .reserve_b
+            .checked_add(to_reserves)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        pool.reserve_a = pool.reserve_a.saturating_sub(amount_out);
+        pool.protocol_fees_owed_b = pool
+            .protocol_fees_owed_b
+            .checked_add(fee_owed)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        (pool.token_b_mint, pool.token_a_mint)
+    };
+    if let Some(observations) = accounts.observations {
+        observations.write(
+            Clock::get()?.slot,
+            pool.last_oracle_timestamp,
+            pool.price_a_cumulative,
+            pool.price_b_cumulative,
+        );
+    }
+
+    if let Some(hook_program) = accounts.hook_program {
+        if pool.hook_program != Pubkey::default() {
+            let args = SwapHookArgs {
+                pool: pool.key(),
+                amount_in,
+                amount_out,
+                a_to_b,
+            };
+            invoke_swap_hook(pool, &hook_program, accounts.hook_accounts, args)?;
+        }
+    }
+
+    let clock = Clock::get()?;
+    pool.record_swap(clock.unix_timestamp);
+    if let Some(stats) = accounts.stats {
+        stats.record_swap(a_to_b, amount_in, amount_out, fee);
+    }
+    pool.check_backing(accounts.pool_token_in)?;
+    pool.check_backing(accounts.pool_token_out)?;
+
+    let (reserve_in_after, reserve_out_after) = if a_to_b {
+        (pool.reserve_a, pool.reserve_b)
+    } else {
+        (pool.reserve_b, pool.reserve_a)
+    };
+    let sequence = pool.next_sequence();
+    if fee_owed > 0 {
+        emit!(ProtocolFeesAccruedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            mint: token_in,
+            amount: fee_owed,
+            total_owed: if a_to_b {
+                pool.protocol_fees_owed_a
+            } else {
+                pool.protocol_fees_owed_b
+            },
+            timestamp: clock.unix_timestamp,
+            sequence,
+            slot: clock.slot,
+        });
+    }
+    Ok(SwapOutcome::Executed(SwapExecutedEvent {
+        version: SCHEMA_VERSION,
+        pool: pool.key(),
+        user: accounts.user.key(),
+        recipient,
+        token_in,
+        token_out,
+        amount_in,
+        amount_out,
+        fee,
+        lp_fee,
+        protocol_fee,
+        fee_discount_bps: accounts.fee_discount_bps,
+        fill_bps: BPS_DENOMINATOR,
+        timestamp: clock.unix_timestamp,
+        slot: clock.slot,
+        reserve_in_after,
+        reserve_out_after,
+        spot_price_after: q64_price(reserve_out_after, reserve_in_after),
+        reserve_in_before: reserve_in,
+        reserve_out_before: reserve_out,
+        price_impact_bps: price_impact_bps(amount_in, amount_out, reserve_in, reserve_out),
+        sequence,
+        k_before: reserve_in as u128 * reserve_out as u128,
+        k_after: reserve_in_after as u128 * reserve_out_after as u128,
+        memo: None,
+    }))
+}
+
+/// Accounts taking part in a deposit, shared by `add_liquidity` and
+/// `add_liquidity_native`.
+struct AddLiquidityAccounts<'a, 'info> {
+    pool: &'a mut Account<'info, Pool>,
+    owner: AccountInfo<'info>,
+    token_a_mint: &'a InterfaceAccount<'info, Mint>,
+    token_b_mint: &'a InterfaceAccount<'info, Mint>,
+    user_token_a: AccountInfo<'info>,
+    user_token_b: AccountInfo<'info>,
+    pool_token_a: &'a InterfaceAccount<'info, TokenAccount>,
+    pool_token_b: &'a InterfaceAccount<'info, TokenAccount>,
+    lp_mint: &'a InterfaceAccount<'info, Mint>,
+    /// Receives the LP tokens
+    user_lp: AccountInfo<'info>,
+    token_program: &'a Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    token_program_b: Option<&'a Interface<'info, TokenInterface>>,
+    /// `owner`'s allowlist entry, checked when deposits are gated
+    allowlist_entry: Option<&'a AllowlistEntry>,
+    /// Needed when the pool charges a deposit fee
+    config: Option<&'a Account<'info, GlobalConfig>>,
+    protocol_fee_a: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    protocol_fee_b: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    /// The pool's statistics, when the caller passes them
+    stats: Option<&'a mut Account<'info, PoolStats>>,
+}
+
+/// Deposits `amount_a`/`amount_b` and mints the LP tokens they are worth,
+/// shared by `add_liquidity`, `add_liquidity_imbalanced` and
+/// `add_liquidity_native`, which emit the returned event.
+fn process_add_liquidity(
+    accounts: AddLiquidityAccounts,
+    amount_a: u64,
+    amount_b: u64,
+    min_lp_tokens: u64,
+    imbalanced: bool,
+) -> Result<LiquidityAddedEvent> {
+    let pool = &accounts.pool;
+    pool.require_active(POOL_STATUS_DEPOSITS_PAUSED)?;
+    require!(!pool.locked, AmmError::PoolLocked);
+    if pool.gated_liquidity {
+        check_allowlist(&pool.key(), accounts.owner.key, accounts.allowlist_entry)?;
+    }
+
+    // The reserves BEFORE the transfers
+    let reserve_a = pool.reserve(accounts.pool_token_a);
+    let reserve_b = pool.reserve(accounts.pool_token_b);
+    let initial_deposit = !pool.initial_price_set && reserve_a == 0 && reserve_b == 0;
+
+    // The deposit fee comes off the top and goes to the protocol
+    let (amount_a, deposit_fee_a) = apply_deposit_fee(amount_a, pool.deposit_fee_bps);
+    let (amount_b, deposit_fee_b) = apply_deposit_fee(amount_b, pool.deposit_fee_bps);
+
+    // Calculate LP tokens based on deposit amounts BEFORE transfers
+    let (fee_numerator, fee_denominator) = pool.fee();
+    let ImbalancedDeposit {
+        lp_tokens: lp_tokens_to_mint,
+        imbalance_fee_a,
+        imbalance_fee_b,
+    } = if imbalanced {
+        compute_imbalanced_deposit(
+            amount_a,
+            amount_b,
+            reserve_a,
+            reserve_b,
+            accounts.lp_mint.supply,
+            accounts.lp_mint.decimals,
+            accounts.token_a_mint.decimals,
+            accounts.token_b_mint.decimals,
+            fee_numerator,
+            fee_denominator,
+            pool.max_imbalance_bps,
+        )?
+    } else {
+        ImbalancedDeposit {
+            lp_tokens: compute_lp_tokens(
+                amount_a,
+                amount_b,
+                reserve_a,
+                reserve_b,
+                accounts.lp_mint.supply,
+                accounts.lp_mint.decimals,
+                accounts.token_a_mint.decimals,
+                accounts.token_b_mint.decimals,
+            )?,
+            imbalance_fee_a: 0,
+            imbalance_fee_b: 0,
+        }
+    };
+
+    // Verify minimum LP tokens
+    require!(
+        lp_tokens_to_mint >= min_lp_tokens,
+        AmmError::SlippageExceeded
+    );
+
+    let token_program_a = token_program_for(
+        accounts.token_a_mint,
+        accounts.token_program,
+        accounts.token_program_b,
+    )?
+    .to_account_info();
+    let token_program_b = token_program_for(
+        accounts.token_b_mint,
+        accounts.token_program,
+        accounts.token_program_b,
+    )?
+    .to_account_info();
+
+    // Transfer token A from owner to pool
+    let cpi_accounts_a = TransferChecked {
+        from: accounts.user_token_a.to_account_info(),
+        mint: accounts.token_a_mint.to_account_info(),
+        to: accounts.pool_token_a.to_account_info(),
+        authority: accounts.owner.to_account_info(),
+    };
+    let cpi_ctx_a = CpiContext::new(token_program_a.clone(), cpi_accounts_a);
+    token_interface::transfer_checked(cpi_ctx_a, amount_a, accounts.token_a_mint.decimals)?;
+
+    // Transfer token B from owner to pool
+    let cpi_accounts_b = TransferChecked {
+        from: accounts.user_token_b.to_account_info(),
+        mint: accounts.token_b_mint.to_account_info(),
+        to: accounts.pool_token_b.to_account_info(),
+        authority: accounts.owner.to_account_info(),
+    };
+    let cpi_ctx_b = CpiContext::new(token_program_b.clone(), cpi_accounts_b);
+    token_interface::transfer_checked(cpi_ctx_b, amount_b, accounts.token_b_mint.decimals)?;
+
+    if pool.deposit_fee_bps > 0 {
+        let (protocol_fee_a, protocol_fee_b) = protocol_fee_accounts(
+            pool,
+            accounts.config,
+            accounts.protocol_fee_a,
+            accounts.protocol_fee_b,
+        )?;
+        for (from, to, mint, token_program, fee) in [
+            (
+                &accounts.user_token_a,
+                protocol_fee_a,
+                accounts.token_a_mint,
+                &token_program_a,
+                deposit_fee_a,
+            ),
+            (
+                &accounts.user_token_b,
+                protocol_fee_b,
+                accounts.token_b_mint,
+                &token_program_b,
+                deposit_fee_b,
+            ),
+        ] {
+            if fee > 0 {
+                let cpi_accounts_fee = TransferChecked {
+                    from: from.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: to.to_account_info(),
+                    authority: accounts.owner.to_account_info(),
+                };
+                token_interface::transfer_checked(
+                    CpiContext::new(token_program.clone(), cpi_accounts_fee),
+                    fee,
+                    mint.decimals,
+                )?;
+            }
+        }
+    }
+
+    // Mint LP tokens to user
+    let pool_seeds = [
+        b"pool",
+        accounts.pool.token_a_mint.as_ref(),
+        accounts.pool.token_b_mint.as_ref(),
+        &accounts.pool.pool_index.to_le_bytes(),
+        &[accounts.pool.bump],
+    ];
+    let signer_seeds = [&pool_seeds[..]];
+
+    let cpi_accounts_mint = token_interface::MintTo {
+        mint: accounts.lp_mint.to_account_info(),
+        to: accounts.user_lp.to_account_info(),
+        authority: accounts.pool.to_account_info(),
+    };
+    let cpi_ctx_mint = CpiContext::new_with_signer(
+        token_program_for(
+            accounts.lp_mint,
+            accounts.token_program,
+            accounts.token_program_b,
+        )?
+        .to_account_info(),
+        cpi_accounts_mint,
+        &signer_seeds,
+    );
+    token_interface::mint_to(cpi_ctx_mint, lp_tokens_to_mint)?;
+
+    let is_protocol =
+        is_protocol_treasury(accounts.config.map(|config| &**config), accounts.owner.key);
+    let pool = accounts.pool;
+    pool.update_oracle(Clock::get()?.unix_timestamp);
+    pool.reserve_a = pool
+        .reserve_a
+        .checked_add(amount_a)
+        .ok_or(AmmError::ArithmeticOverflow)?;
+    pool.reserve_b = pool
+        .reserve_b
+        .checked_add(amount_b)
+        .ok_or(AmmError::ArithmeticOverflow)?;
+    if is_protocol {
+        pool.protocol_lp_amount = pool
+            .protocol_lp_amount
+            .checked_add(lp_tokens_to_mint)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+    }
+
+    pool.initial_price_set = true;
+
+    let clock = Clock::get()?;
+    pool.record_liquidity_event(clock.unix_timestamp);
+    if let Some(stats) = accounts.stats {
+        stats.record_liquidity_event();
+    }
+    pool.check_backing(accounts.pool_token_a)?;
+    pool.check_backing(accounts.pool_token_b)?;
+
+    let sequence = pool.next_sequence();
+    if initial_deposit {
+        emit!(InitialPriceSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            depositor: accounts.owner.key(),
+            amount_a,
+            amount_b,
+            lp_minted: lp_tokens_to_mint,
+            timestamp: clock.unix_timestamp,
+            sequence,
+            slot: clock.slot,
+        });
+    }
+
+    Ok(LiquidityAddedEvent {
+        version: SCHEMA_VERSION,
+        pool: pool.key(),
+        user: accounts.owner.key(),
+        lp_recipient: accounts.user_lp.key(),
+        amount_a,
+        amount_b,
+        lp_tokens_minted: lp_tokens_to_mint,
+        pool_token_a_balance: accounts.pool_token_a.amount,
+        pool_token_b_balance: accounts.pool_token_b.amount,
+        imbalance_fee_a,
+        imbalance_fee_b,
+        deposit_fee_a,
+        deposit_fee_b,
+        is_protocol,
+        timestamp: clock.unix_timestamp,
+        slot: clock.slot,
+        sequence,
+    })
+}
+
+/// Burns `lp_amount` of the user's LP tokens and pays out their share of both
+/// reserves, shared by `remove_liquidity` and `remove_liquidity_bps`, which
+/// emit the returned event.
+fn process_remove_liquidity(
+    accounts: &mut RemoveLiquidity,
+    lp_amount: u64,
+    min_amount_a: u64,
+    min_amount_b: u64,
+    unwrap_sol: bool,
+) -> Result<LiquidityRemovedEvent> {
+    let pool = &accounts.pool;
+    pool.require_active(POOL_STATUS_WITHDRAWALS_PAUSED)?;
+    require!(!pool.locked, AmmError::PoolLocked);
+
+    // With `unwrap_sol` the SOL side goes through the temporary wSOL account
+    let sol_is_a = pool.token_a_mint == native_mint::ID;
+    let wsol_account = if unwrap_sol {
+        require!(
+            sol_is_a || pool.token_b_mint == native_mint::ID,
+            AmmError::NotNativePool
+        );
+        let Some(wsol_account) = &accounts.wsol_account else {
+            return err!(AmmError::MissingWsolAccount);
+        };
+        Some(wsol_account.to_account_info())
+    } else {
+        None
+    };
+
+    // Validate input amount
+    require!(lp_amount > 0, AmmError::InvalidAmount);
+
+    // Get current pool reserves and LP supply
+    let reserve_a = pool.reserve(&accounts.pool_token_a);
+    let reserve_b = pool.reserve(&accounts.pool_token_b);
+    let lp_supply = accounts.lp_mint.supply;
+
+    let (amount_a, amount_b) = compute_withdrawal(lp_amount, reserve_a, reserve_b, lp_supply)?;
+    let (amount_a, withdrawal_fee_a) = apply_withdrawal_fee(amount_a, pool.withdrawal_fee_bps);
+    let (amount_b, withdrawal_fee_b) = apply_withdrawal_fee(amount_b, pool.withdrawal_fee_bps);
+
+    // Verify minimum amounts
+    require!(amount_a >= min_amount_a, AmmError::SlippageExceeded);
+    require!(amount_b >= min_amount_b, AmmError::SlippageExceeded);
+
+    // Create signer seeds for pool authority
+    let seeds = [
+        b"pool".as_ref(),
+        accounts.pool.token_a_mint.as_ref(),
+        accounts.pool.token_b_mint.as_ref(),
+        &accounts.pool.pool_index.to_le_bytes(),
+        &[accounts.pool.bump],
+    ];
+    let signer_seeds = [&seeds[..]];
+
+    // Transfer tokens from pool to user, or to the recipients when given
+    let recipient_token_a = match (&wsol_account, &accounts.recipient_token_a) {
+        (Some(wsol_account), _) if sol_is_a => wsol_account.clone(),
+        (_, Some(recipient_token_a)) => recipient_token_a.to_account_info(),
+        _ => accounts.user_token_a.to_account_info(),
+    };
+    let recipient_token_b = match (&wsol_account, &accounts.recipient_token_b) {
+        (Some(wsol_account), _) if !sol_is_a => wsol_account.clone(),
+        (_, Some(recipient_token_b)) => recipient_token_b.to_account_info(),
+        _ => accounts.user_token_b.to_account_info(),
+    };
+    let recipient_a = recipient_token_a.key();
+    let recipient_b = recipient_token_b.key();
+    let token_program_b = accounts.token_program_b.as_ref();
+
+    let cpi_accounts_a = TransferChecked {
+        from: accounts.pool_token_a.to_account_info(),
+        mint: accounts.token_a_mint.to_account_info(),
+        to: recipient_token_a,
+        authority: accounts.pool.to_account_info(),
+    };
+    let cpi_ctx_a = CpiContext::new_with_signer(
+        token_program_for(
+            &accounts.token_a_mint,
+            &accounts.token_program,
+            token_program_b,
+        )?
+        .to_account_info(),
+        cpi_accounts_a,
+        &signer_seeds,
+    );
+    token_interface::transfer_checked(cpi_ctx_a, amount_a, accounts.token_a_mint.decimals)?;
+
+    let cpi_accounts_b = TransferChecked {
+        from: accounts.pool_token_b.to_account_info(),
+        mint: accounts.token_b_mint.to_account_info(),
+        to: recipient_token_b,
+        authority: accounts.pool.to_account_info(),
+    };
+    let cpi_ctx_b = CpiContext::new_with_signer(
+        token_program_for(
+            &accounts.token_b_mint,
+            &accounts.token_program,
+            token_program_b,
+        )?
+        .to_account_info(),
+        cpi_accounts_b,
+        &signer_seeds,
+    );
+    token_interface::transfer_checked(cpi_ctx_b, amount_b, accounts.token_b_mint.decimals)?;
+
+    // Burn LP tokens - user is the authority for their own tokens
+    let cpi_accounts_burn = token_interface::Burn {
+        mint: accounts.lp_mint.to_account_info(),
+        from: accounts.user_lp.to_account_info(),
+        authority: accounts.user.to_account_info(),
+    };
+    let cpi_ctx_burn = CpiContext::new(
+        token_program_for(&accounts.lp_mint, &accounts.token_program, token_program_b)?
+            .to_account_info(),
+        cpi_accounts_burn,
+    );
+    token_interface::burn(cpi_ctx_burn, lp_amount)?;
+
+    // Closing the temporary account pays out the SOL with its rent; it is
+    // closed even when passed without `unwrap_sol`, so no rent is stranded
+    if let Some(wsol_account) = &accounts.wsol_account {
+        let cpi_accounts_close = token_interface::CloseAccount {
+            account: wsol_account.to_account_info(),
+            destination: accounts.user.to_account_info(),
+            authority: accounts.user.to_account_info(),
+        };
+        let cpi_ctx_close =
+            CpiContext::new(accounts.token_program.to_account_info(), cpi_accounts_close);
+        token_interface::close_account(cpi_ctx_close)?;
+    }
+
+    let is_protocol = is_protocol_treasury(
+        accounts.config.as_deref().map(|config| &**config),
+        accounts.user.key,
+    );
+    let pool = &mut accounts.pool;
+    pool.update_oracle(Clock::get()?.unix_timestamp);
+    pool.reserve_a = pool.reserve_a.saturating_sub(amount_a);
+    pool.reserve_b = pool.reserve_b.saturating_sub(amount_b);
+    if is_protocol {
+        // The treasury may burn LP it was transferred, which was never counted
+        pool.protocol_lp_amount = pool.protocol_lp_amount.saturating_sub(lp_amount);
+    }
+
+    let clock = Clock::get()?;
+    pool.record_liquidity_event(clock.unix_timestamp);
+    if let Some(stats) = &mut accounts.stats {
+        stats.record_liquidity_event();
+    }
+    pool.check_backing(&accounts.pool_token_a)?;
+    pool.check_backing(&accounts.pool_token_b)?;
+
+    let sequence = pool.next_sequence();
+    Ok(LiquidityRemovedEvent {
+        version: SCHEMA_VERSION,
+        pool: pool.key(),
+        user: accounts.user.key(),
+        recipient_a,
+        recipient_b,
+        amount_a,
+        amount_b,
+        lp_amount,
+        pool_token_a_balance: accounts.pool_token_a.amount,
+        pool_token_b_balance: accounts.pool_token_b.amount,
+        withdrawal_fee_a,
+        withdrawal_fee_b,
+        is_protocol,
+        timestamp: clock.unix_timestamp,
+        slot: clock.slot,
+        sequence,
+    })
+}
+
+/// Emits one of the core events (pool creation, liquidity and swaps) through a
+/// self-CPI with `emit_cpi!`, as instruction data that RPC nodes keep when they
+/// truncate the logs. The `log-events` feature also logs it with `emit!` for
+/// indexers still reading the logs. `ctx` must be an `#[event_cpi]` context.
+macro_rules! emit_event {
+    ($ctx:expr, $event:expr) => {{
+        let event = $event;
+        let ctx = &$ctx;
+        #[cfg(feature = "log-events")]
+        emit!(event);
+        emit_cpi!(event);
+    }};
+}
+
+#[program]
+pub mod new_send_swap {
+    use super::*;
+
+    pub fn initialize_pool(
+        ctx: Context<InitializePool>,
+        total_fee_bps: u16,
+        pool_index: u16,
+        trade_start_timestamp: i64,
+        trade_limits: TradeLimits,
+    ) -> Result<()> {
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        check_new_pool(
+            config.as_ref(),
+            ctx.accounts.authority.key,
+            ctx.accounts.creator_allowlist_entry.as_deref(),
+            total_fee_bps,
+        )?;
+        trade_limits.check()?;
+        check_token_badges(
+            config.as_ref(),
+            ctx.accounts.token_a_badge.as_deref(),
+            ctx.accounts.token_b_badge.as_deref(),
+        )?;
+        check_mint_extensions(&ctx.accounts.token_a_mint)?;
+        check_mint_extensions(&ctx.accounts.token_b_mint)?;
+        let creation_fee_lamports = pay_pool_creation_fee(
+            config.as_ref(),
+            &ctx.accounts.authority,
+            ctx.accounts.treasury.as_ref(),
+            &ctx.accounts.system_program,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.token_a_mint = ctx.accounts.token_a_mint.key();
+        pool.token_b_mint = ctx.accounts.token_b_mint.key();
+        pool.token_a_account = ctx.accounts.token_a_account.key();
+        pool.token_b_account = ctx.accounts.token_b_account.key();
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.total_fee_bps = total_fee_bps;
+        pool.authority = ctx.accounts.authority.key();
+        pool.fee_recipient = ctx.accounts.authority.key();
+        pool.protocol_fee_bps = config
+            .as_ref()
+            .map_or(BPS_DENOMINATOR, |config| config.default_protocol_fee_bps);
+        pool.lp_fee_bps = BPS_DENOMINATOR - pool.protocol_fee_bps;
+        pool.bump = ctx.bumps.pool;
+        pool.pool_index = pool_index;
+        pool.trade_start_timestamp = trade_start_timestamp;
+        pool.trade_limits = trade_limits;
+        pool.lp_decimals = ctx.accounts.lp_mint.decimals;
+        pool.version = POOL_VERSION;
+        pool.registry_entry = ctx.accounts.registry_entry.key();
+        let clock = Clock::get()?;
+        pool.created_at_timestamp = clock.unix_timestamp;
+        pool.created_at_slot = clock.slot;
+        let registry_index = ctx.accounts.pool_count.register(
+            ctx.bumps.pool_count,
+            &mut ctx.accounts.registry_entry,
+            ctx.bumps.registry_entry,
+            pool,
+        )?;
+        create_lp_metadata(
+            pool,
+            &ctx.accounts.lp_mint,
+            ctx.accounts.lp_metadata.as_ref(),
+            ctx.accounts.token_metadata_program.as_ref(),
+            &ctx.accounts.token_program,
+            ctx.accounts.token_program_b.as_ref(),
+            &ctx.accounts.authority,
+            &ctx.accounts.system_program,
+            &ctx.accounts.rent,
+            config.as_ref(),
+        )?;
+
+        let sequence = pool.next_sequence();
+        emit_event!(
+            ctx,
+            PoolCreatedEvent {
+                version: SCHEMA_VERSION,
+                pool: pool.key(),
+                token_a_mint: pool.token_a_mint,
+                token_b_mint: pool.token_b_mint,
+                pool_index,
+                registry_index,
+                total_fee_bps,
+                trade_start_timestamp,
+                trade_limits,
+                creation_fee_lamports,
+                timestamp: pool.created_at_timestamp,
+                slot: pool.created_at_slot,
+                authority: pool.authority,
+                lp_mint: pool.lp_mint,
+                sequence,
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Creates the pool and makes its first deposit in one instruction, so the
+    /// empty pool is never observable and cannot be seeded at another price.
+    /// As with `initialize_pool`, swaps open at `trade_start_timestamp`.
+    ///
+    /// The LP mint's mint authority must already be set to the pool PDA.
+    pub fn initialize_pool_with_liquidity(
+        ctx: Context<InitializePoolWithLiquidity>,
+        total_fee_bps: u16,
+        amount_a: u64,
+        amount_b: u64,
+        pool_index: u16,
+        trade_start_timestamp: i64,
+        trade_limits: TradeLimits,
+    ) -> Result<()> {
+        require!(amount_a > 0 && amount_b > 0, AmmError::InvalidAmount);
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        check_new_pool(
+            config.as_ref(),
+            ctx.accounts.authority.key,
+            ctx.accounts.creator_allowlist_entry.as_deref(),
+            total_fee_bps,
+        )?;
+        trade_limits.check()?;
+        check_token_badges(
+            config.as_ref(),
+            ctx.accounts.token_a_badge.as_deref(),
+            ctx.accounts.token_b_badge.as_deref(),
+        )?;
+        check_mint_extensions(&ctx.accounts.token_a_mint)?;
+        check_mint_extensions(&ctx.accounts.token_b_mint)?;
+        let creation_fee_lamports = pay_pool_creation_fee(
+            config.as_ref(),
+            &ctx.accounts.authority,
+            ctx.accounts.treasury.as_ref(),
+            &ctx.accounts.system_program,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.token_a_mint = ctx.accounts.token_a_mint.key();
+        pool.token_b_mint = ctx.accounts.token_b_mint.key();
+        pool.token_a_account = ctx.accounts.token_a_account.key();
+        pool.token_b_account = ctx.accounts.token_b_account.key();
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.total_fee_bps = total_fee_bps;
+        pool.authority = ctx.accounts.authority.key();
+        pool.fee_recipient = ctx.accounts.authority.key();
+        pool.protocol_fee_bps = config
+            .as_ref()
+            .map_or(BPS_DENOMINATOR, |config| config.default_protocol_fee_bps);
+        pool.lp_fee_bps = BPS_DENOMINATOR - pool.protocol_fee_bps;
+        pool.bump = ctx.bumps.pool;
+        pool.pool_index = pool_index;
+        pool.trade_start_timestamp = trade_start_timestamp;
+        pool.trade_limits = trade_limits;
+        pool.lp_decimals = ctx.accounts.lp_mint.decimals;
+        pool.version = POOL_VERSION;
+        pool.registry_entry = ctx.accounts.registry_entry.key();
+        let clock = Clock::get()?;
+        pool.created_at_timestamp = clock.unix_timestamp;
+        pool.created_at_slot = clock.slot;
+        let registry_index = ctx.accounts.pool_count.register(
+            ctx.bumps.pool_count,
+            &mut ctx.accounts.registry_entry,
+            ctx.bumps.registry_entry,
+            pool,
+        )?;
+        create_lp_metadata(
+            pool,
+            &ctx.accounts.lp_mint,
+            ctx.accounts.lp_metadata.as_ref(),
+            ctx.accounts.token_metadata_program.as_ref(),
+            &ctx.accounts.token_program,
+            ctx.accounts.token_program_b.as_ref(),
+            &ctx.accounts.authority,
+            &ctx.accounts.system_program,
+            &ctx.accounts.rent,
+            config.as_ref(),
+        )?;
+
+        let sequence = pool.next_sequence();
+        emit_event!(
+            ctx,
+            PoolCreatedEvent {
+                version: SCHEMA_VERSION,
+                pool: pool.key(),
+                token_a_mint: pool.token_a_mint,
+                token_b_mint: pool.token_b_mint,
+                pool_index,
+                registry_index,
+                total_fee_bps,
+                trade_start_timestamp,
+                trade_limits,
+                creation_fee_lamports,
+                timestamp: pool.created_at_timestamp,
+                slot: pool.created_at_slot,
+                authority: pool.authority,
+                lp_mint: pool.lp_mint,
+                sequence,
+            }
+        );
+
+        let lp_tokens_to_mint = compute_lp_tokens(
+            amount_a,
+            amount_b,
+            ctx.accounts.token_a_account.amount,
+            ctx.accounts.token_b_account.amount,
+            ctx.accounts.lp_mint.supply,
+            ctx.accounts.lp_mint.decimals,
+            ctx.accounts.token_a_mint.decimals,
+            ctx.accounts.token_b_mint.decimals,
+        )?;
+
+        let token_program = &ctx.accounts.token_program;
+        let token_program_b = ctx.accounts.token_program_b.as_ref();
+        token_interface::transfer_checked(
+            CpiContext::new(
+                token_program_for(&ctx.accounts.token_a_mint, token_program, token_program_b)?
+                    .to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token_a.to_account_info(),
+                    mint: ctx.accounts.token_a_mint.to_account_info(),
+                    to: ctx.accounts.token_a_account.to_account_info(),
+                    authority: ctx.accounts.authority.to_account_info(),
+                },
+            ),
+            amount_a,
+            ctx.accounts.token_a_mint.decimals,
+        )?;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                token_program_for(&ctx.accounts.token_b_mint, token_program, token_program_b)?
+                    .to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token_b.to_account_info(),
+                    mint: ctx.accounts.token_b_mint.to_account_info(),
+                    to: ctx.accounts.token_b_account.to_account_info(),
+                    authority: ctx.accounts.authority.to_account_info(),
+                },
+            ),
+            amount_b,
+            ctx.accounts.token_b_mint.decimals,
+        )?;
+
+        let pool_seeds = [
+            b"pool",
+            ctx.accounts.pool.token_a_mint.as_ref(),
+            ctx.accounts.pool.token_b_mint.as_ref(),
+            &ctx.accounts.pool.pool_index.to_le_bytes(),
+            &[ctx.accounts.pool.bump],
+        ];
+        let signer_seeds = [&pool_seeds[..]];
+        token_interface::mint_to(
+            CpiContext::new_with_signer(
+                token_program_for(&ctx.accounts.lp_mint, token_program, token_program_b)?
+                    .to_account_info(),
+                token_interface::MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.user_lp.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            lp_tokens_to_mint,
+        )?;
+
+        ctx.accounts.token_a_account.reload()?;
+        ctx.accounts.token_b_account.reload()?;
+
+        let is_protocol = is_protocol_treasury(config.as_ref(), ctx.accounts.authority.key);
+        let pool = &mut ctx.accounts.pool;
+        pool.reserve_a = amount_a;
+        pool.reserve_b = amount_b;
+        if is_protocol {
+            pool.protocol_lp_amount = lp_tokens_to_mint;
+        }
+        pool.initial_price_set = true;
+        pool.update_oracle(Clock::get()?.unix_timestamp);
+
+        let clock = Clock::get()?;
+        pool.record_liquidity_event(clock.unix_timestamp);
+        pool.check_backing(&ctx.accounts.token_a_account)?;
+        pool.check_backing(&ctx.accounts.token_b_account)?;
+
+        emit!(InitialPriceSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            depositor: ctx.accounts.authority.key(),
+            amount_a,
+            amount_b,
+            lp_minted: lp_tokens_to_mint,
+            timestamp: clock.unix_timestamp,
+            sequence,
+            slot: clock.slot,
+        });
+
+        emit_event!(
+            ctx,
+            LiquidityAddedEvent {
+                version: SCHEMA_VERSION,
+                pool: pool.key(),
+                user: ctx.accounts.authority.key(),
+                lp_recipient: ctx.accounts.user_lp.key(),
+                amount_a,
+                amount_b,
+                lp_tokens_minted: lp_tokens_to_mint,
+                pool_token_a_balance: ctx.accounts.token_a_account.amount,
+                pool_token_b_balance: ctx.accounts.token_b_account.amount,
+                imbalance_fee_a: 0,
+                imbalance_fee_b: 0,
+                deposit_fee_a: 0,
+                deposit_fee_b: 0,
+                is_protocol,
+                timestamp: clock.unix_timestamp,
+                slot: clock.slot,
+                sequence,
+            }
+        );
+
+        Ok(())
+    }
+
+    pub fn add_liquidity(
+        ctx: Context<AddLiquidity>,
+        amount_a: u64,
+        amount_b: u64,
+        min_lp_tokens: u64,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        let accounts = &mut *ctx.accounts;
+        let event = process_add_liquidity(
+            AddLiquidityAccounts {
+                pool: &mut accounts.pool,
+                owner: accounts.owner.to_account_info(),
+                token_a_mint: &accounts.token_a_mint,
+                token_b_mint: &accounts.token_b_mint,
+                user_token_a: accounts.user_token_a.to_account_info(),
+                user_token_b: accounts.user_token_b.to_account_info(),
+                pool_token_a: &accounts.pool_token_a,
+                pool_token_b: &accounts.pool_token_b,
+                lp_mint: &accounts.lp_mint,
+                user_lp: match &accounts.lp_recipient {
+                    Some(lp_recipient) => lp_recipient.to_account_info(),
+                    None => accounts.user_lp.to_account_info(),
+                },
+                token_program: &accounts.token_program,
+                token_program_b: accounts.token_program_b.as_ref(),
+                allowlist_entry: accounts.allowlist_entry.as_deref(),
+                config: accounts.config.as_deref(),
+                protocol_fee_a: accounts.protocol_fee_a.as_deref(),
+                protocol_fee_b: accounts.protocol_fee_b.as_deref(),
+                stats: accounts.stats.as_deref_mut(),
+            },
+            amount_a,
+            amount_b,
+            min_lp_tokens,
+            false,
+        )?;
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    /// `add_liquidity` that accepts amounts off the pool ratio, by up to the
+    /// pool's `max_imbalance_bps` on the excess side, and charges that side
+    /// the swap fee it would pay to rebalance (see
+    /// `compute_imbalanced_deposit`). The fee stays in the pool.
+    pub fn add_liquidity_imbalanced(
+        ctx: Context<AddLiquidity>,
+        amount_a: u64,
+        amount_b: u64,
+        min_lp_tokens: u64,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        let accounts = &mut *ctx.accounts;
+        let event = process_add_liquidity(
+            AddLiquidityAccounts {
+                pool: &mut accounts.pool,
+                owner: accounts.owner.to_account_info(),
+                token_a_mint: &accounts.token_a_mint,
+                token_b_mint: &accounts.token_b_mint,
+                user_token_a: accounts.user_token_a.to_account_info(),
+                user_token_b: accounts.user_token_b.to_account_info(),
+                pool_token_a: &accounts.pool_token_a,
+                pool_token_b: &accounts.pool_token_b,
+                lp_mint: &accounts.lp_mint,
+                user_lp: match &accounts.lp_recipient {
+                    Some(lp_recipient) => lp_recipient.to_account_info(),
+                    None => accounts.user_lp.to_account_info(),
+                },
+                token_program: &accounts.token_program,
+                token_program_b: accounts.token_program_b.as_ref(),
+                allowlist_entry: accounts.allowlist_entry.as_deref(),
+                config: accounts.config.as_deref(),
+                protocol_fee_a: accounts.protocol_fee_a.as_deref(),
+                protocol_fee_b: accounts.protocol_fee_b.as_deref(),
+                stats: accounts.stats.as_deref_mut(),
+            },
+            amount_a,
+            amount_b,
+            min_lp_tokens,
+            true,
+        )?;
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    /// `add_liquidity` for a pool with a native SOL side, taking that side as
+    /// lamports. `amount_sol` is wrapped into a temporary wSOL account and
+    /// only the amounts matching the pool ratio are deposited; the temporary
+    /// account is closed back to the user at the end, which refunds any SOL
+    /// left over along with its rent.
+    pub fn add_liquidity_native(
+        ctx: Context<AddLiquidityNative>,
+        amount_sol: u64,
+        amount_token: u64,
+        min_lp_tokens: u64,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+
+        let sol_is_a = ctx.accounts.pool.token_a_mint == native_mint::ID;
+        require!(
+            sol_is_a || ctx.accounts.pool.token_b_mint == native_mint::ID,
+            AmmError::NotNativePool
+        );
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: ctx.accounts.wsol_account.to_account_info(),
+                },
+            ),
+            amount_sol,
+        )?;
+        token_interface::sync_native(CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::SyncNative {
+                account: ctx.accounts.wsol_account.to_account_info(),
+            },
+        ))?;
+
+        let (amount_a, amount_b, user_token_a, user_token_b) = if sol_is_a {
+            (
+                amount_sol,
+                amount_token,
+                ctx.accounts.wsol_account.to_account_info(),
+                ctx.accounts.user_token.to_account_info(),
+            )
+        } else {
+            (
+                amount_token,
+                amount_sol,
+                ctx.accounts.user_token.to_account_info(),
+                ctx.accounts.wsol_account.to_account_info(),
+            )
+        };
+        let (amount_a, amount_b) = compute_optimal_deposit(
+            amount_a,
+            amount_b,
+            ctx.accounts.pool.reserve(&ctx.accounts.pool_token_a),
+            ctx.accounts.pool.reserve(&ctx.accounts.pool_token_b),
+        );
+        let accounts = &mut *ctx.accounts;
+        let event = process_add_liquidity(
+            AddLiquidityAccounts {
+                pool: &mut accounts.pool,
+                owner: accounts.user.to_account_info(),
+                token_a_mint: &accounts.token_a_mint,
+                token_b_mint: &accounts.token_b_mint,
+                user_token_a,
+                user_token_b,
+                pool_token_a: &accounts.pool_token_a,
+                pool_token_b: &accounts.pool_token_b,
+                lp_mint: &accounts.lp_mint,
+                user_lp: accounts.user_lp.to_account_info(),
+                token_program: &accounts.token_program,
+                token_program_b: accounts.token_program_b.as_ref(),
+                allowlist_entry: accounts.allowlist_entry.as_deref(),
+                config: accounts.config.as_deref(),
+                protocol_fee_a: accounts.protocol_fee_a.as_deref(),
+                protocol_fee_b: accounts.protocol_fee_b.as_deref(),
+                stats: accounts.stats.as_deref_mut(),
+            },
+            amount_a,
+            amount_b,
+            min_lp_tokens,
+            false,
+        )?;
+        emit_event!(ctx, event);
+
+        let cpi_accounts_close = token_interface::CloseAccount {
+            account: ctx.accounts.wsol_account.to_account_info(),
+            destination: ctx.accounts.user.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_ctx_close = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts_close,
+        );
+        token_interface::close_account(cpi_ctx_close)?;
+
+        Ok(())
+    }
+
+    /// `limit_price_numerator / limit_price_denominator` is the worst accepted
+    /// average price, in output per input; pass 0/0 to disable it. The trade
+    /// is returned via `set_return_data` for programs swapping through CPI,
+    /// so a swap tripping the pool's circuit breaker fails with
+    /// `CircuitBreakerTripped` rather than return a trade that didn't happen.
+    ///
+    /// A `memo` of up to `MAX_SWAP_MEMO_LEN` bytes, e.g. an invoice ID, is
+    /// written to the memo program signed by the owner and carried in the
+    /// swap's event.
+    pub fn swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+        limit_price_numerator: u64,
+        limit_price_denominator: u64,
+        deadline: i64,
+        memo: Option<String>,
+    ) -> Result<SwapResult> {
+        check_deadline(deadline)?;
+        if let Some(memo) = &memo {
+            require!(memo.len() <= MAX_SWAP_MEMO_LEN, AmmError::MemoTooLong);
+            require!(
+                ctx.accounts.memo_program.is_some(),
+                AmmError::MissingMemoProgram
+            );
+        }
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        let fee_discount_bps = fee_discount_bps(
+            config.as_ref(),
+            ctx.accounts.owner.key,
+            ctx.accounts.discount_token_account.as_deref(),
+        )?;
+
+        if let (Some(launch_purchase), Some(payer)) =
+            (&mut ctx.accounts.launch_purchase, &ctx.accounts.payer)
+        {
+            init_launch_purchase(
+                launch_purchase,
+                ctx.accounts.pool.key(),
+                ctx.accounts.owner.key(),
+                payer.key(),
+                ctx.bumps.launch_purchase,
+            );
+        }
+
+        let user_token_out = match &ctx.accounts.recipient_token_out {
+            Some(recipient_token_out) => recipient_token_out.to_account_info(),
+            None => ctx.accounts.user_token_out.to_account_info(),
+        };
+        let event = process_swap(
+            SwapAccounts {
+                pool: &mut ctx.accounts.pool,
+                user: ctx.accounts.owner.to_account_info(),
+                user_token_in: ctx.accounts.user_token_in.to_account_info(),
+                user_token_out,
+                pool_token_in: &ctx.accounts.pool_token_in,
+                pool_token_out: &ctx.accounts.pool_token_out,
+                owner_token_account: ctx
+                    .accounts
+                    .owner_token_account
+                    .as_ref()
+                    .map(|account| account.to_account_info()),
+                token_in_mint: &ctx.accounts.token_in_mint,
+                token_out_mint: &ctx.accounts.token_out_mint,
+                token_program: &ctx.accounts.token_program,
+                token_program_b: ctx.accounts.token_program_b.as_ref(),
+                allowlist_entry: ctx.accounts.allowlist_entry.as_deref(),
+                launch_purchase: ctx.accounts.launch_purchase.as_deref_mut(),
+                fee_discount_bps,
+                hook_program: ctx
+                    .accounts
+                    .hook_program
+                    .as_ref()
+                    .map(|hook_program| hook_program.to_account_info()),
+                hook_accounts: ctx.remaining_accounts,
+                user_signer_seeds: &[],
+                config: config.as_ref(),
+                observations: ctx.accounts.observations.as_deref_mut(),
+                stats: ctx.accounts.stats.as_deref_mut(),
+                oracle_guard: ctx.accounts.oracle_guard.as_deref(),
+                price_feed_a: ctx
+                    .accounts
+                    .price_feed_a
+                    .as_ref()
+                    .map(|feed| feed.to_account_info()),
+                price_feed_b: ctx
+                    .accounts
+                    .price_feed_b
+                    .as_ref()
+                    .map(|feed| feed.to_account_info()),
+            },
+            amount_in,
+            min_amount_out,
+        )?
+        .executed()?;
+        check_limit_price(
+            event.amount_in,
+            event.amount_out,
+            limit_price_numerator,
+            limit_price_denominator,
+        )?;
+        if let (Some(memo), Some(memo_program)) = (&memo, &ctx.accounts.memo_program) {
+            memo::build_memo(
+                CpiContext::new(memo_program.to_account_info(), BuildMemo {})
+                    .with_remaining_accounts(vec![ctx.accounts.owner.to_account_info()]),
+                memo.as_bytes(),
+            )?;
+        }
+        let result = SwapResult::from(&event);
+        let event = SwapExecutedEvent { memo, ..event };
+        emit_event!(ctx, event);
+
+        Ok(result)
+    }
+
+    /// Buys exactly `amount_out`, paying at most `max_amount_in`. With
+    /// `allow_partial`, an output that is out of reach (beyond `max_amount_in`
+    /// or the reserves) is filled as far as `max_amount_in` goes instead of
+    /// failing, and the event's `fill_bps` reports the filled share. The trade
+    /// is returned via `set_return_data`, as from `swap`.
+    pub fn swap_exact_out<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+        amount_out: u64,
+        max_amount_in: u64,
+        allow_partial: bool,
+        deadline: i64,
+    ) -> Result<SwapResult> {
+        check_deadline(deadline)?;
+        require!(amount_out > 0, AmmError::InvalidAmount);
+
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        let fee_discount_bps = fee_discount_bps(
+            config.as_ref(),
+            ctx.accounts.owner.key,
+            ctx.accounts.discount_token_account.as_deref(),
+        )?;
+        let (fee_numerator, fee_denominator) =
+            discounted_fee(&ctx.accounts.pool, fee_discount_bps)?;
+        let reserve_out = ctx.accounts.pool.reserve(&ctx.accounts.pool_token_out);
+        let required_in = if amount_out < reserve_out {
+            Some(compute_amount_in(
+                amount_out,
+                ctx.accounts.pool.reserve(&ctx.accounts.pool_token_in),
+                reserve_out,
+                fee_numerator,
+                fee_denominator,
+            )?)
+        } else {
+            None
+        };
+        let (amount_in, min_amount_out) = match (required_in, allow_partial) {
+            (Some(required_in), _) if required_in <= max_amount_in => (required_in, amount_out),
+            // Spend the whole budget on whatever it buys, which must be non-zero
+            (_, true) => (max_amount_in, 1),
+            (Some(_), false) => return err!(AmmError::SlippageExceeded),
+            (None, false) => return err!(AmmError::InsufficientLiquidity),
+        };
+
+        if let (Some(launch_purchase), Some(payer)) =
+            (&mut ctx.accounts.launch_purchase, &ctx.accounts.payer)
+        {
+            init_launch_purchase(
+                launch_purchase,
+                ctx.accounts.pool.key(),
+                ctx.accounts.owner.key(),
+                payer.key(),
+                ctx.bumps.launch_purchase,
+            );
+        }
+
+        let user_token_out = match &ctx.accounts.recipient_token_out {
+            Some(recipient_token_out) => recipient_token_out.to_account_info(),
+            None => ctx.accounts.user_token_out.to_account_info(),
+        };
+        let mut event = process_swap(
+            SwapAccounts {
+                pool: &mut ctx.accounts.pool,
+                user: ctx.accounts.owner.to_account_info(),
+                user_token_in: ctx.accounts.user_token_in.to_account_info(),
+                user_token_out,
+                pool_token_in: &ctx.accounts.pool_token_in,
+                pool_token_out: &ctx.accounts.pool_token_out,
+                owner_token_account: ctx
+                    .accounts
+                    .owner_token_account
+                    .as_ref()
+                    .map(|account| account.to_account_info()),
+                token_in_mint: &ctx.accounts.token_in_mint,
+                token_out_mint: &ctx.accounts.token_out_mint,
+                token_program: &ctx.accounts.token_program,
+                token_program_b: ctx.accounts.token_program_b.as_ref(),
+                allowlist_entry: ctx.accounts.allowlist_entry.as_deref(),
+                launch_purchase: ctx.accounts.launch_purchase.as_deref_mut(),
+                fee_discount_bps,
+                hook_program: ctx
+                    .accounts
+                    .hook_program
+                    .as_ref()
+                    .map(|hook_program| hook_program.to_account_info()),
+                hook_accounts: ctx.remaining_accounts,
+                user_signer_seeds: &[],
+                config: config.as_ref(),
+                observations: ctx.accounts.observations.as_deref_mut(),
+                stats: ctx.accounts.stats.as_deref_mut(),
+                oracle_guard: ctx.accounts.oracle_guard.as_deref(),
+                price_feed_a: ctx
+                    .accounts
+                    .price_feed_a
+                    .as_ref()
+                    .map(|feed| feed.to_account_info()),
+                price_feed_b: ctx
+                    .accounts
+                    .price_feed_b
+                    .as_ref()
+                    .map(|feed| feed.to_account_info()),
+            },
+            amount_in,
+            min_amount_out,
+        )?
+        .executed()?;
+        event.fill_bps = (event.amount_out as u128 * BPS_DENOMINATOR as u128 / amount_out as u128)
+            .min(BPS_DENOMINATOR as u128) as u16;
+        let result = SwapResult::from(&event);
+        emit_event!(ctx, event);
+
+        Ok(result)
+    }
+
+    /// `swap` with accounts in fixed pool order and the direction as an
+    /// argument, so every account can be checked against the pool.
+    pub fn swap_v2(
+        ctx: Context<SwapV2>,
+        amount_in: u64,
+        min_amount_out: u64,
+        a_to_b: bool,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+
+        let (user_token_in, user_token_out, pool_token_in, pool_token_out) = if a_to_b {
+            (
+                ctx.accounts.user_token_a.to_account_info(),
+                ctx.accounts.user_token_b.to_account_info(),
+                &ctx.accounts.pool_token_a,
+                &ctx.accounts.pool_token_b,
+            )
+        } else {
+            (
+                ctx.accounts.user_token_b.to_account_info(),
+                ctx.accounts.user_token_a.to_account_info(),
+                &ctx.accounts.pool_token_b,
+                &ctx.accounts.pool_token_a,
+            )
+        };
+        let (token_in_mint, token_out_mint) = if a_to_b {
+            (&ctx.accounts.token_a_mint, &ctx.accounts.token_b_mint)
+        } else {
+            (&ctx.accounts.token_b_mint, &ctx.accounts.token_a_mint)
+        };
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        let Some(event) = process_swap(
+            SwapAccounts {
+                pool: &mut ctx.accounts.pool,
+                user: ctx.accounts.user.to_account_info(),
+                user_token_in,
+                user_token_out,
+                pool_token_in,
+                pool_token_out,
+                owner_token_account: ctx
+                    .accounts
+                    .owner_token_account
+                    .as_ref()
+                    .map(|account| account.to_account_info()),
+                token_in_mint,
+                token_out_mint,
+                token_program: &ctx.accounts.token_program,
+                token_program_b: ctx.accounts.token_program_b.as_ref(),
+                allowlist_entry: ctx.accounts.allowlist_entry.as_deref(),
+                launch_purchase: None,
+                fee_discount_bps: 0,
+                hook_program: None,
+                hook_accounts: &[],
+                user_signer_seeds: &[],
+                config: config.as_ref(),
+                observations: ctx.accounts.observations.as_deref_mut(),
+                stats: ctx.accounts.stats.as_deref_mut(),
+                oracle_guard: None,
+                price_feed_a: None,
+                price_feed_b: None,
+            },
+            amount_in,
+            min_amount_out,
+        )?
+        .unless_tripped() else {
+            return Ok(());
+        };
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    /// `swap` for wallets that may not hold the output token yet: the owner's
+    /// associated token account for `token_out_mint` is created if it does
+    /// not exist, and used as is if it does. `payer` covers the rent, so a
+    /// relayer can sponsor it while the owner only authorizes the tokens.
+    pub fn swap_and_create(
+        ctx: Context<SwapAndCreate>,
+        amount_in: u64,
+        min_amount_out: u64,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        let Some(event) = process_swap(
+            SwapAccounts {
+                pool: &mut ctx.accounts.pool,
+                user: ctx.accounts.owner.to_account_info(),
+                user_token_in: ctx.accounts.user_token_in.to_account_info(),
+                user_token_out: ctx.accounts.user_token_out.to_account_info(),
+                pool_token_in: &ctx.accounts.pool_token_in,
+                pool_token_out: &ctx.accounts.pool_token_out,
+                owner_token_account: ctx
+                    .accounts
+                    .owner_token_account
+                    .as_ref()
+                    .map(|account| account.to_account_info()),
+                token_in_mint: &ctx.accounts.token_in_mint,
+                token_out_mint: &ctx.accounts.token_out_mint,
+                token_program: &ctx.accounts.token_program,
+                token_program_b: ctx.accounts.token_program_b.as_ref(),
+                allowlist_entry: ctx.accounts.allowlist_entry.as_deref(),
+                launch_purchase: None,
+                fee_discount_bps: 0,
+                hook_program: None,
+                hook_accounts: &[],
+                user_signer_seeds: &[],
+                config: config.as_ref(),
+                observations: ctx.accounts.observations.as_deref_mut(),
+                stats: ctx.accounts.stats.as_deref_mut(),
+                oracle_guard: None,
+                price_feed_a: None,
+                price_feed_b: None,
+            },
+            amount_in,
+            min_amount_out,
+        )?
+        .unless_tripped() else {
+            return Ok(());
+        };
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    /// Swaps against a pool with a native SOL side without the user holding
+    /// wSOL. Lamports are wrapped into a temporary wSOL account for SOL input,
+    /// and the temporary account is closed back to the user at the end, which
+    /// returns its rent along with any wSOL output as lamports.
+    pub fn swap_native(
+        ctx: Context<SwapNative>,
+        amount_in: u64,
+        min_amount_out: u64,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+
+        let sol_in = ctx.accounts.pool_token_in.mint == native_mint::ID;
+        require!(
+            sol_in || ctx.accounts.pool_token_out.mint == native_mint::ID,
+            AmmError::NotNativePool
+        );
+
+        if sol_in {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.user.to_account_info(),
+                        to: ctx.accounts.wsol_account.to_account_info(),
+                    },
+                ),
+                amount_in,
+            )?;
+            token_interface::sync_native(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::SyncNative {
+                    account: ctx.accounts.wsol_account.to_account_info(),
+                },
+            ))?;
+        }
+
+        let (user_token_in, user_token_out) = if sol_in {
+            (
+                ctx.accounts.wsol_account.to_account_info(),
+                ctx.accounts.user_token.to_account_info(),
+            )
+        } else {
+            (
+                ctx.accounts.user_token.to_account_info(),
+                ctx.accounts.wsol_account.to_account_info(),
+            )
+        };
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        let event = process_swap(
+            SwapAccounts {
+                pool: &mut ctx.accounts.pool,
+                user: ctx.accounts.user.to_account_info(),
+                user_token_in,
+                user_token_out,
+                pool_token_in: &ctx.accounts.pool_token_in,
+                pool_token_out: &ctx.accounts.pool_token_out,
+                owner_token_account: ctx
+                    .accounts
+                    .owner_token_account
+                    .as_ref()
+                    .map(|account| account.to_account_info()),
+                token_in_mint: &ctx.accounts.token_in_mint,
+                token_out_mint: &ctx.accounts.token_out_mint,
+                token_program: &ctx.accounts.token_program,
+                token_program_b: ctx.accounts.token_program_b.as_ref(),
+                allowlist_entry: ctx.accounts.allowlist_entry.as_deref(),
+                launch_purchase: None,
+                fee_discount_bps: 0,
+                hook_program: None,
+                hook_accounts: &[],
+                user_signer_seeds: &[],
+                config: config.as_ref(),
+                observations: None,
+                stats: None,
+                oracle_guard: None,
+                price_feed_a: None,
+                price_feed_b: None,
+            },
+            amount_in,
+            min_amount_out,
+        )?
+        .executed()?;
+
+        let cpi_accounts_close = token_interface::CloseAccount {
+            account: ctx.accounts.wsol_account.to_account_info(),
+            destination: ctx.accounts.user.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_ctx_close = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts_close,
+        );
+        token_interface::close_account(cpi_ctx_close)?;
+
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    pub fn swap_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapBatch<'info>>,
+        legs: Vec<SwapLeg>,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+
+        require!(
+            !legs.is_empty() && legs.len() <= MAX_SWAP_BATCH_LEGS,
+            AmmError::BatchTooLarge
+        );
+        require!(
+            ctx.remaining_accounts.len() == legs.len() * SWAP_BATCH_ACCOUNTS_PER_LEG,
+            AmmError::InvalidRemainingAccounts
+        );
+
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        // Legs run in order; any failure (e.g. slippage) reverts the batch
+        for (leg, accounts) in legs
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(SWAP_BATCH_ACCOUNTS_PER_LEG))
+        {
+            let mut pool = Account::<Pool>::try_from(&accounts[0])?;
+            let pool_token_a = InterfaceAccount::<TokenAccount>::try_from(&accounts[1])?;
+            let pool_token_b = InterfaceAccount::<TokenAccount>::try_from(&accounts[2])?;
+            let token_a_mint = InterfaceAccount::<Mint>::try_from(&accounts[6])?;
+            let token_b_mint = InterfaceAccount::<Mint>::try_from(&accounts[7])?;
+            let (pool_token_in, pool_token_out, user_token_in, user_token_out) = if leg.a_to_b {
+                (&pool_token_a, &pool_token_b, &accounts[3], &accounts[4])
+            } else {
+                (&pool_token_b, &pool_token_a, &accounts[4], &accounts[3])
+            };
+            let (token_in_mint, token_out_mint) = if leg.a_to_b {
+                (&token_a_mint, &token_b_mint)
+            } else {
+                (&token_b_mint, &token_a_mint)
+            };
+
+            let event = process_swap(
+                SwapAccounts {
+                    pool: &mut pool,
+                    user: ctx.accounts.user.to_account_info(),
+                    user_token_in: user_token_in.clone(),
+                    user_token_out: user_token_out.clone(),
+                    pool_token_in,
+                    pool_token_out,
+                    owner_token_account: Some(accounts[5].clone()),
+                    token_in_mint,
+                    token_out_mint,
+                    token_program: &ctx.accounts.token_program,
+                    token_program_b: ctx.accounts.token_program_b.as_ref(),
+                    // Gated pools can't be reached through a batch, nor can
+                    // launch-window buys
+                    allowlist_entry: None,
+                    launch_purchase: None,
+                    fee_discount_bps: 0,
+                    hook_program: None,
+                    hook_accounts: &[],
+                    user_signer_seeds: &[],
+                    config: config.as_ref(),
+                    observations: None,
+                    stats: None,
+                    oracle_guard: None,
+                    price_feed_a: None,
+                    price_feed_b: None,
+                },
+                leg.amount_in,
+                leg.min_amount_out,
+            )?
+            .executed()?;
+            // Persist now so a later leg against the same pool sees this one
+            pool.exit(&crate::ID)?;
+            emit_event!(ctx, event);
+        }
+
+        Ok(())
+    }
+
+    /// Splits `total_amount_in` across pools of the same mint pair by
+    /// `splits` (in basis points, summing to 10,000) and checks the combined
+    /// output against `min_total_out`. Each leg's pool and vaults come in
+    /// `remaining_accounts` (see `SWAP_SPLIT_ACCOUNTS_PER_LEG`); the last leg
+    /// takes the rounding remainder so the whole input is spent.
+    pub fn swap_split<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapSplit<'info>>,
+        total_amount_in: u64,
+        min_total_out: u64,
+        splits: Vec<u16>,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+
+        require!(
+            !splits.is_empty()
+                && splits.len() <= MAX_SWAP_SPLIT_LEGS
+                && splits.iter().all(|&bps| bps > 0)
+                && splits.iter().map(|&bps| bps as u32).sum::<u32>() == BPS_DENOMINATOR as u32,
+            AmmError::InvalidSplit
+        );
+        require!(
+            ctx.remaining_accounts.len() == splits.len() * SWAP_SPLIT_ACCOUNTS_PER_LEG,
+            AmmError::InvalidRemainingAccounts
+        );
+
+        let mint_in = ctx.accounts.user_token_in.mint;
+        let mint_out = ctx.accounts.user_token_out.mint;
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        let mut remaining_in = total_amount_in;
+        let mut total_amount_out: u64 = 0;
+        for (i, (&bps, accounts)) in splits
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(SWAP_SPLIT_ACCOUNTS_PER_LEG))
+            .enumerate()
+        {
+            let mut pool = Account::<Pool>::try_from(&accounts[0])?;
+            require!(
+                (pool.token_a_mint == mint_in && pool.token_b_mint == mint_out)
+                    || (pool.token_a_mint == mint_out && pool.token_b_mint == mint_in),
+                AmmError::SplitPoolMismatch
+            );
+            let pool_token_in = InterfaceAccount::<TokenAccount>::try_from(&accounts[1])?;
+            let pool_token_out = InterfaceAccount::<TokenAccount>::try_from(&accounts[2])?;
+
+            let amount_in = if i == splits.len() - 1 {
+                remaining_in
+            } else {
+                (total_amount_in as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64
+            };
+            remaining_in -= amount_in;
+
+            let event = process_swap(
+                SwapAccounts {
+                    pool: &mut pool,
+                    user: ctx.accounts.user.to_account_info(),
+                    user_token_in: ctx.accounts.user_token_in.to_account_info(),
+                    user_token_out: ctx.accounts.user_token_out.to_account_info(),
+                    pool_token_in: &pool_token_in,
+                    pool_token_out: &pool_token_out,
+                    owner_token_account: Some(accounts[3].clone()),
+                    token_in_mint: &ctx.accounts.token_in_mint,
+                    token_out_mint: &ctx.accounts.token_out_mint,
+                    token_program: &ctx.accounts.token_program,
+                    token_program_b: ctx.accounts.token_program_b.as_ref(),
+                    // Like a batch, a split can't reach gated pools or make
+                    // launch-window buys
+                    allowlist_entry: None,
+                    launch_purchase: None,
+                    fee_discount_bps: 0,
+                    hook_program: None,
+                    hook_accounts: &[],
+                    user_signer_seeds: &[],
+                    config: config.as_ref(),
+                    observations: None,
+                    stats: None,
+                    oracle_guard: None,
+                    price_feed_a: None,
+                    price_feed_b: None,
+                },
+                amount_in,
+                0,
+            )?
+            .executed()?;
+            // Persist now so a later leg against the same pool sees this one
+            pool.exit(&crate::ID)?;
+            total_amount_out = total_amount_out
+                .checked_add(event.amount_out)
+                .ok_or(AmmError::ArithmeticOverflow)?;
+            emit_event!(ctx, event);
+        }
+
+        require!(
+            total_amount_out >= min_total_out,
+            AmmError::SlippageExceeded
+        );
+
+        emit!(SwapSplitEvent {
+            version: SCHEMA_VERSION,
+            user: ctx.accounts.user.key(),
+            token_in: mint_in,
+            token_out: mint_out,
+            total_amount_in,
+            total_amount_out,
+            splits,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Read-only quote using exactly the math `swap` executes. The result is
+    /// returned via `set_return_data`, so clients can simulate this
+    /// instruction and read it back.
+    pub fn quote_swap(
+        ctx: Context<QuoteSwap>,
+        amount_in: u64,
+        a_to_b: bool,
+    ) -> Result<QuoteSwapResult> {
+        let pool = &ctx.accounts.pool;
+        require!(amount_in > 0, AmmError::InvalidAmount);
+
+        let reserve_a = pool.reserve(&ctx.accounts.pool_token_a);
+        let reserve_b = pool.reserve(&ctx.accounts.pool_token_b);
+        let (reserve_in, reserve_out) = if a_to_b {
+            (reserve_a, reserve_b)
+        } else {
+            (reserve_b, reserve_a)
+        };
+        let (fee_numerator, fee_denominator) = pool.fee();
+        let quote = compute_swap(
+            amount_in,
+            reserve_in,
+            reserve_out,
+            fee_numerator,
+            fee_denominator,
+        )?;
+
+        Ok(QuoteSwapResult {
+            amount_out: quote.amount_out,
+            fee: quote.fee,
+            price_impact_bps: price_impact_bps(
+                amount_in,
+                quote.amount_out,
+                reserve_in,
+                reserve_out,
+            ),
+        })
+    }
+
+    /// Read-only preview of the LP tokens `add_liquidity` would mint.
+    pub fn quote_add_liquidity(
+        ctx: Context<QuoteAddLiquidity>,
+        amount_a: u64,
+        amount_b: u64,
+    ) -> Result<QuoteAddLiquidityResult> {
+        let deposit_fee_bps = ctx.accounts.pool.deposit_fee_bps;
+        let (amount_a, _) = apply_deposit_fee(amount_a, deposit_fee_bps);
+        let (amount_b, _) = apply_deposit_fee(amount_b, deposit_fee_bps);
+        let lp_tokens = compute_lp_tokens(
+            amount_a,
+            amount_b,
+            ctx.accounts.pool.reserve(&ctx.accounts.pool_token_a),
+            ctx.accounts.pool.reserve(&ctx.accounts.pool_token_b),
+            ctx.accounts.lp_mint.supply,
+            ctx.accounts.lp_mint.decimals,
+            ctx.accounts.token_a_mint.decimals,
+            ctx.accounts.token_b_mint.decimals,
+        )?;
+
+        Ok(QuoteAddLiquidityResult { lp_tokens })
+    }
+
+    /// Read-only preview of the tokens `remove_liquidity` would pay out.
+    pub fn quote_remove_liquidity(
+        ctx: Context<QuoteRemoveLiquidity>,
+        lp_amount: u64,
+    ) -> Result<QuoteRemoveLiquidityResult> {
+        require!(lp_amount > 0, AmmError::InvalidAmount);
+
+        let (amount_a, amount_b) = compute_withdrawal(
+            lp_amount,
+            ctx.accounts.pool.reserve(&ctx.accounts.pool_token_a),
+            ctx.accounts.pool.reserve(&ctx.accounts.pool_token_b),
+            ctx.accounts.lp_mint.supply,
+        )?;
+        let withdrawal_fee_bps = ctx.accounts.pool.withdrawal_fee_bps;
+        let (amount_a, _) = apply_withdrawal_fee(amount_a, withdrawal_fee_bps);
+        let (amount_b, _) = apply_withdrawal_fee(amount_b, withdrawal_fee_bps);
+
+        Ok(QuoteRemoveLiquidityResult { amount_a, amount_b })
+    }
+
+    /// With `unwrap_sol` the SOL side of a native pool is paid out as
+    /// lamports: it is withdrawn into a temporary wSOL account which is closed
+    /// back to the user, returning its rent along with the SOL.
+    pub fn remove_liquidity(
+        ctx: Context<RemoveLiquidity>,
+        lp_amount: u64,
+        min_amount_a: u64,
+        min_amount_b: u64,
+        unwrap_sol: bool,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        let event = process_remove_liquidity(
+            ctx.accounts,
+            lp_amount,
+            min_amount_a,
+            min_amount_b,
+            unwrap_sol,
+        )?;
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    pub fn remove_liquidity_bps(
+        ctx: Context<RemoveLiquidity>,
+        bps: u16,
+        min_amount_a: u64,
+        min_amount_b: u64,
+        close_lp_account: bool,
+        deadline: i64,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+        require!(bps > 0 && bps <= BPS_DENOMINATOR, AmmError::InvalidBps);
+
+        // Read the position on-chain so the share is exact at execution time
+        let lp_amount = (ctx.accounts.user_lp.amount as u128)
+            .checked_mul(bps as u128)
+            .ok_or(AmmError::ArithmeticOverflow)?
+            / BPS_DENOMINATOR as u128;
+        let event = process_remove_liquidity(
+            ctx.accounts,
+            lp_amount as u64,
+            min_amount_a,
+            min_amount_b,
+            false,
+        )?;
+        emit_event!(ctx, event);
+
+        // Return the LP account's rent to the user once the position is gone
+        ctx.accounts.user_lp.reload()?;
+        if close_lp_account && ctx.accounts.user_lp.amount == 0 {
+            let cpi_accounts_close = token_interface::CloseAccount {
+                account: ctx.accounts.user_lp.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            let cpi_ctx_close = CpiContext::new(
+                token_program_for(
+                    &ctx.accounts.lp_mint,
+                    &ctx.accounts.token_program,
+                    ctx.accounts.token_program_b.as_ref(),
+                )?
+                .to_account_info(),
+                cpi_accounts_close,
+            );
+            token_interface::close_account(cpi_ctx_close)?;
+        }
+
+        Ok(())
+    }
+
+    /// Lends `amount` of one vault's tokens until the `flash_repay` that
+    /// must follow in the same transaction. Paused like a swap: by the
+    /// pool's `POOL_STATUS_SWAPS_PAUSED` bit and the config's pause flag.
+    pub fn flash_loan(ctx: Context<FlashLoan>, amount: u64, token_a: bool) -> Result<()> {
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        require!(
+            !config.is_some_and(|config| config.paused),
+            AmmError::ProtocolPaused
+        );
+        let pool = &ctx.accounts.pool;
+        pool.require_active(POOL_STATUS_SWAPS_PAUSED)?;
+        require!(!pool.locked, AmmError::PoolLocked);
+        require!(amount > 0, AmmError::InvalidAmount);
+
+        let expected_vault = if token_a {
+            pool.token_a_account
+        } else {
+            pool.token_b_account
+        };
+        require_keys_eq!(
+            ctx.accounts.pool_token.key(),
+            expected_vault,
+            AmmError::InvalidPoolTokenAccount
+        );
+
+        // The loan is only safe if a flash_repay for this pool runs after us in
+        // the same transaction; if it fails or is missing, everything reverts.
+        let instructions = ctx.accounts.instructions.to_account_info();
+        let current_index = load_current_index_checked(&instructions)? as usize;
+        let mut repay_found = false;
+        let mut index = current_index + 1;
+        while let Ok(ix) = load_instruction_at_checked(index, &instructions) {
+            if ix.program_id == crate::ID
+                && ix.data.starts_with(instruction::FlashRepay::DISCRIMINATOR)
+                && ix.accounts.first().map(|meta| meta.pubkey) == Some(pool.key())
+            {
+                repay_found = true;
+                break;
+            }
+            index += 1;
+        }
+        require!(repay_found, AmmError::FlashRepayMissing);
+
+        // Fee uses the pool's swap fee rate, rounded up so every loan pays
+        let (fee_numerator, fee_denominator) = pool.fee();
+        let fee = (amount as u128)
+            .checked_mul(fee_numerator as u128)
+            .and_then(|n| n.checked_add((fee_denominator as u128).saturating_sub(1)))
+            .and_then(|n| n.checked_div(fee_denominator as u128))
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        let fee = u64::try_from(fee).map_err(|_| AmmError::ArithmeticOverflow)?;
+
+        let required_balance = ctx
+            .accounts
+            .pool_token
+            .amount
+            .checked_add(fee)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.locked = true;
+        pool.flash_loan_token_a = token_a;
+        pool.flash_loan_required_balance = required_balance;
+        pool.flash_loan_fee = fee;
+
+        // Transfer the borrowed tokens from the vault to the borrower
+        let seeds = [
+            b"pool".as_ref(),
+            ctx.accounts.pool.token_a_mint.as_ref(),
+            ctx.accounts.pool.token_b_mint.as_ref(),
+            &ctx.accounts.pool.pool_index.to_le_bytes(),
+            &[ctx.accounts.pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.pool_token.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
+            to: ctx.accounts.borrower_token.to_account_info(),
+            authority: ctx.accounts.pool.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            &signer_seeds,
+        );
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+
+        let sequence = ctx.accounts.pool.next_sequence();
+        emit!(FlashLoanEvent {
+            version: SCHEMA_VERSION,
+            pool: ctx.accounts.pool.key(),
+            borrower: ctx.accounts.borrower.key(),
+            token_mint: ctx.accounts.pool_token.mint,
+            amount,
+            fee,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn flash_repay(ctx: Context<FlashRepay>, amount: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(pool.locked, AmmError::FlashLoanNotActive);
+
+        let expected_vault = if pool.flash_loan_token_a {
+            pool.token_a_account
+        } else {
+            pool.token_b_account
+        };
+        require_keys_eq!(
+            ctx.accounts.pool_token.key(),
+            expected_vault,
+            AmmError::InvalidPoolTokenAccount
+        );
+
+        // Transfer the repayment from the repayer to the vault
+        if amount > 0 {
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.repayer_token.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.pool_token.to_account_info(),
+                authority: ctx.accounts.repayer.to_account_info(),
+            };
+            let cpi_ctx =
+                CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+            token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.token_mint.decimals)?;
+        }
+
+        // Tokens may also have been returned directly, so check the balance
+        ctx.accounts.pool_token.reload()?;
+        let vault_balance = ctx.accounts.pool_token.amount;
+        require!(
+            vault_balance >= pool.flash_loan_required_balance,
+            AmmError::FlashLoanNotRepaid
+        );
+
+        // The fee stays in the vault and accrues to LPs
+        let pool = &mut ctx.accounts.pool;
+        pool.update_oracle(Clock::get()?.unix_timestamp);
+        if pool.flash_loan_token_a {
+            pool.reserve_a = pool
+                .reserve_a
+                .checked_add(pool.flash_loan_fee)
+                .ok_or(AmmError::ArithmeticOverflow)?;
+        } else {
+            pool.reserve_b = pool
+                .reserve_b
+                .checked_add(pool.flash_loan_fee)
+                .ok_or(AmmError::ArithmeticOverflow)?;
+        }
+        pool.check_backing(&ctx.accounts.pool_token)?;
+        pool.locked = false;
+        pool.flash_loan_token_a = false;
+        pool.flash_loan_required_balance = 0;
+        pool.flash_loan_fee = 0;
+
+        let sequence = pool.next_sequence();
+        emit!(FlashLoanRepaidEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            repayer: ctx.accounts.repayer.key(),
+            token_mint: ctx.accounts.pool_token.mint,
+            amount,
+            vault_balance,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn sync(ctx: Context<SyncReserves>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(!pool.locked, AmmError::PoolLocked);
+
+        // Credit anything sent straight to the vaults to the LPs
+        let reserve_a_before = pool.reserve_a;
+        let reserve_b_before = pool.reserve_b;
+        pool.update_oracle(Clock::get()?.unix_timestamp);
+        pool.reserve_a = pool.available(&ctx.accounts.pool_token_a);
+        pool.reserve_b = pool.available(&ctx.accounts.pool_token_b);
+
+        pool.check_backing(&ctx.accounts.pool_token_a)?;
+        pool.check_backing(&ctx.accounts.pool_token_b)?;
+
+        let sequence = pool.next_sequence();
+        emit!(ReservesSyncedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            reserve_a_before,
+            reserve_b_before,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn skim(ctx: Context<Skim>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(!pool.locked, AmmError::PoolLocked);
+
+        // Only the excess above tracked reserves and owed protocol fees
+        // leaves the vaults
+        let amount_a = pool.surplus(&ctx.accounts.pool_token_a);
+        let amount_b = pool.surplus(&ctx.accounts.pool_token_b);
+
+        let seeds = [
+            b"pool".as_ref(),
+            ctx.accounts.pool.token_a_mint.as_ref(),
+            ctx.accounts.pool.token_b_mint.as_ref(),
+            &ctx.accounts.pool.pool_index.to_le_bytes(),
+            &[ctx.accounts.pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+
+        if amount_a > 0 {
+            let cpi_accounts_a = TransferChecked {
+                from: ctx.accounts.pool_token_a.to_account_info(),
+                mint: ctx.accounts.token_a_mint.to_account_info(),
+                to: ctx.accounts.to_token_a.to_account_info(),
+                authority: ctx.accounts.pool.to_account_info(),
+            };
+            let cpi_ctx_a = CpiContext::new_with_signer(
+                token_program_for(
+                    &ctx.accounts.token_a_mint,
+                    &ctx.accounts.token_program,
+                    ctx.accounts.token_program_b.as_ref(),
+                )?
+                .to_account_info(),
+                cpi_accounts_a,
+                &signer_seeds,
+            );
+            token_interface::transfer_checked(
+                cpi_ctx_a,
+                amount_a,
+                ctx.accounts.token_a_mint.decimals,
+            )?;
+        }
+
+        if amount_b > 0 {
+            let cpi_accounts_b = TransferChecked {
+                from: ctx.accounts.pool_token_b.to_account_info(),
+                mint: ctx.accounts.token_b_mint.to_account_info(),
+                to: ctx.accounts.to_token_b.to_account_info(),
+                authority: ctx.accounts.pool.to_account_info(),
+            };
+            let cpi_ctx_b = CpiContext::new_with_signer(
+                token_program_for(
+                    &ctx.accounts.token_b_mint,
+                    &ctx.accounts.token_program,
+                    ctx.accounts.token_program_b.as_ref(),
+                )?
+                .to_account_info(),
+                cpi_accounts_b,
+                &signer_seeds,
+            );
+            token_interface::transfer_checked(
+                cpi_ctx_b,
+                amount_b,
+                ctx.accounts.token_b_mint.decimals,
+            )?;
+        }
+
+        pool.check_backing(&ctx.accounts.pool_token_a)?;
+        pool.check_backing(&ctx.accounts.pool_token_b)?;
+
+        emit!(SurplusSkimmedEvent {
+            version: SCHEMA_VERSION,
+            pool: ctx.accounts.pool.key(),
+            authority: ctx.accounts.authority.key(),
+            to_token_a: ctx.accounts.to_token_a.key(),
+            to_token_b: ctx.accounts.to_token_b.key(),
+            amount_a,
+            amount_b,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sends the vaults' surplus, tokens that arrived outside the program's
+    /// flows, to the config's treasury. As with `skim`, the surplus is worked
+    /// out from the vault balances at the time, so the tracked reserves and
+    /// owed protocol fees stay in the vaults whatever ran before it.
+    pub fn skim_surplus(ctx: Context<SkimSurplus>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let pool = &accounts.pool;
+        require!(!pool.locked, AmmError::PoolLocked);
+        require!(!pool.in_hook, AmmError::ReentrantSwap);
+        let treasury = GlobalConfig::load(&accounts.config)?
+            .map(|config| config.treasury)
+            .filter(|treasury| *treasury != Pubkey::default())
+            .ok_or(AmmError::InvalidTreasuryAccount)?;
+        require!(
+            accounts.treasury_token_a.owner == treasury
+                && accounts.treasury_token_b.owner == treasury,
+            AmmError::InvalidTreasuryAccount
+        );
+
+        let amount_a = pool.surplus(&accounts.pool_token_a);
+        let amount_b = pool.surplus(&accounts.pool_token_b);
+        let seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &pool.pool_index.to_le_bytes(),
+            &[pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+        for (vault, mint, destination, amount) in [
+            (
+                &accounts.pool_token_a,
+                &accounts.token_a_mint,
+                &accounts.treasury_token_a,
+                amount_a,
+            ),
+            (
+                &accounts.pool_token_b,
+                &accounts.token_b_mint,
+                &accounts.treasury_token_b,
+                amount_b,
+            ),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+            let cpi_accounts = TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: destination.to_account_info(),
+                authority: pool.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                token_program_for(
+                    mint,
+                    &accounts.token_program,
+                    accounts.token_program_b.as_ref(),
+                )?
+                .to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+            token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)?;
+        }
+
+        pool.check_backing(&accounts.pool_token_a)?;
+        pool.check_backing(&accounts.pool_token_b)?;
+
+        emit!(SkimEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            authority: accounts.authority.key(),
+            treasury,
+            treasury_token_a: accounts.treasury_token_a.key(),
+            treasury_token_b: accounts.treasury_token_b.key(),
+            amount_a,
+            amount_b,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+            protocol_fees_owed_a: pool.protocol_fees_owed_a,
+            protocol_fees_owed_b: pool.protocol_fees_owed_b,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Returns a drained pool, one with no LP tokens left, to the state of a
+    /// fresh one: whatever the last LPs left behind in the vaults, such as
+    /// withdrawal fees, goes to `to_token_a` / `to_token_b`, and the reserves
+    /// and cumulative prices are cleared. Swaps then fail with `InsufficientLiquidity`, and the next
+    /// deposit takes the initial path, setting the price and minting
+    /// `INITIAL_LP_TOKENS`, instead of minting nothing against the leftovers.
+    /// Owed protocol fees stay in place for collection.
+    pub fn reset_pool(ctx: Context<ResetPool>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(!pool.locked, AmmError::PoolLocked);
+        require!(ctx.accounts.lp_mint.supply == 0, AmmError::PoolNotDrained);
+
+        let amount_a = pool.available(&ctx.accounts.pool_token_a);
+        let amount_b = pool.available(&ctx.accounts.pool_token_b);
+
+        let seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &pool.pool_index.to_le_bytes(),
+            &[pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+        for (vault, mint, to, amount) in [
+            (
+                &ctx.accounts.pool_token_a,
+                &ctx.accounts.token_a_mint,
+                &ctx.accounts.to_token_a,
+                amount_a,
+            ),
+            (
+                &ctx.accounts.pool_token_b,
+                &ctx.accounts.token_b_mint,
+                &ctx.accounts.to_token_b,
+                amount_b,
+            ),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+            let cpi_accounts = TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: pool.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                token_program_for(
+                    mint,
+                    &ctx.accounts.token_program,
+                    ctx.accounts.token_program_b.as_ref(),
+                )?
+                .to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+            token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)?;
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        let reserve_a_before = pool.reserve_a;
+        let reserve_b_before = pool.reserve_b;
+        pool.reserve_a = 0;
+        pool.reserve_b = 0;
+        pool.price_a_cumulative = 0;
+        pool.price_b_cumulative = 0;
+        pool.last_oracle_timestamp = 0;
+
+        pool.check_backing(&ctx.accounts.pool_token_a)?;
+        pool.check_backing(&ctx.accounts.pool_token_b)?;
+
+        let sequence = pool.next_sequence();
+        emit!(PoolResetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            authority: ctx.accounts.authority.key(),
+            to_token_a: ctx.accounts.to_token_a.key(),
+            to_token_b: ctx.accounts.to_token_b.key(),
+            amount_a,
+            amount_b,
+            reserve_a_before,
+            reserve_b_before,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Adds tokens to the reserves without minting LP tokens, raising the
+    /// value of every existing LP share. No deposit ratio is enforced.
+    pub fn donate(ctx: Context<Donate>, amount_a: u64, amount_b: u64) -> Result<()> {
+        require!(!ctx.accounts.pool.locked, AmmError::PoolLocked);
+        require!(amount_a > 0 || amount_b > 0, AmmError::InvalidAmount);
+
+        if amount_a > 0 {
+            let cpi_accounts_a = TransferChecked {
+                from: ctx.accounts.donor_token_a.to_account_info(),
+                mint: ctx.accounts.token_a_mint.to_account_info(),
+                to: ctx.accounts.pool_token_a.to_account_info(),
+                authority: ctx.accounts.donor.to_account_info(),
+            };
+            let cpi_ctx_a = CpiContext::new(
+                token_program_for(
+                    &ctx.accounts.token_a_mint,
+                    &ctx.accounts.token_program,
+                    ctx.accounts.token_program_b.as_ref(),
+                )?
+                .to_account_info(),
+                cpi_accounts_a,
+            );
+            token_interface::transfer_checked(
+                cpi_ctx_a,
+                amount_a,
+                ctx.accounts.token_a_mint.decimals,
+            )?;
+        }
+        if amount_b > 0 {
+            let cpi_accounts_b = TransferChecked {
+                from: ctx.accounts.donor_token_b.to_account_info(),
+                mint: ctx.accounts.token_b_mint.to_account_info(),
+                to: ctx.accounts.pool_token_b.to_account_info(),
+                authority: ctx.accounts.donor.to_account_info(),
+            };
+            let cpi_ctx_b = CpiContext::new(
+                token_program_for(
+                    &ctx.accounts.token_b_mint,
+                    &ctx.accounts.token_program,
+                    ctx.accounts.token_program_b.as_ref(),
+                )?
+                .to_account_info(),
+                cpi_accounts_b,
+            );
+            token_interface::transfer_checked(
+                cpi_ctx_b,
+                amount_b,
+                ctx.accounts.token_b_mint.decimals,
+            )?;
+        }
+
+        // Track the donation so it belongs to the LPs rather than being skimmable
+        let pool = &mut ctx.accounts.pool;
+        pool.update_oracle(Clock::get()?.unix_timestamp);
+        pool.reserve_a = pool
+            .reserve_a
+            .checked_add(amount_a)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        pool.reserve_b = pool
+            .reserve_b
+            .checked_add(amount_b)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+
+        pool.check_backing(&ctx.accounts.pool_token_a)?;
+        pool.check_backing(&ctx.accounts.pool_token_b)?;
+
+        let sequence = pool.next_sequence();
+        emit!(DonationEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            donor: ctx.accounts.donor.key(),
+            amount_a,
+            amount_b,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Moves `lp_amount` of the user's position from one pool to another on
+    /// the same mint pair: the source LP is burned, the underlying is paid out
+    /// to the user's token accounts and deposited into the destination pool.
+    /// When the pools' ratios differ only the amounts matching the destination
+    /// ratio are deposited; the leftover stays in the user's token accounts.
+    pub fn migrate_liquidity(
+        ctx: Context<MigrateLiquidity>,
+        lp_amount: u64,
+        min_lp_out: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .source_pool
+            .require_active(POOL_STATUS_WITHDRAWALS_PAUSED)?;
+        ctx.accounts
+            .destination_pool
+            .require_active(POOL_STATUS_DEPOSITS_PAUSED)?;
+        require!(
+            !ctx.accounts.source_pool.locked && !ctx.accounts.destination_pool.locked,
+            AmmError::PoolLocked
+        );
+        require!(lp_amount > 0, AmmError::InvalidAmount);
+        if ctx.accounts.destination_pool.gated_liquidity {
+            check_allowlist(
+                &ctx.accounts.destination_pool.key(),
+                ctx.accounts.user.key,
+                ctx.accounts.destination_allowlist_entry.as_deref(),
+            )?;
+        }
+
+        // Withdraw from the source pool, less its withdrawal fee
+        let (amount_a, amount_b) = compute_withdrawal(
+            lp_amount,
+            ctx.accounts
+                .source_pool
+                .reserve(&ctx.accounts.source_pool_token_a),
+            ctx.accounts
+                .source_pool
+                .reserve(&ctx.accounts.source_pool_token_b),
+            ctx.accounts.source_lp_mint.supply,
+        )?;
+        let withdrawal_fee_bps = ctx.accounts.source_pool.withdrawal_fee_bps;
+        let (amount_a, _) = apply_withdrawal_fee(amount_a, withdrawal_fee_bps);
+        let (amount_b, _) = apply_withdrawal_fee(amount_b, withdrawal_fee_bps);
+
+        let source_seeds = [
+            b"pool".as_ref(),
+            ctx.accounts.source_pool.token_a_mint.as_ref(),
+            ctx.accounts.source_pool.token_b_mint.as_ref(),
+            &ctx.accounts.source_pool.pool_index.to_le_bytes(),
+            &[ctx.accounts.source_pool.bump],
+        ];
+        let source_signer_seeds = [&source_seeds[..]];
+        let token_program_a = token_program_for(
+            &ctx.accounts.token_a_mint,
+            &ctx.accounts.token_program,
+            ctx.accounts.token_program_b.as_ref(),
+        )?
+        .to_account_info();
+        let token_program_b = token_program_for(
+            &ctx.accounts.token_b_mint,
+            &ctx.accounts.token_program,
+            ctx.accounts.token_program_b.as_ref(),
+        )?
+        .to_account_info();
+
+        let cpi_accounts_a = TransferChecked {
+            from: ctx.accounts.source_pool_token_a.to_account_info(),
+            mint: ctx.accounts.token_a_mint.to_account_info(),
+            to: ctx.accounts.user_token_a.to_account_info(),
+            authority: ctx.accounts.source_pool.to_account_info(),
+        };
+        let cpi_ctx_a = CpiContext::new_with_signer(
+            token_program_a.clone(),
+            cpi_accounts_a,
+            &source_signer_seeds,
+        );
+        token_interface::transfer_checked(cpi_ctx_a, amount_a, ctx.accounts.token_a_mint.decimals)?;
+
+        let cpi_accounts_b = TransferChecked {
+            from: ctx.accounts.source_pool_token_b.to_account_info(),
+            mint: ctx.accounts.token_b_mint.to_account_info(),
+            to: ctx.accounts.user_token_b.to_account_info(),
+            authority: ctx.accounts.source_pool.to_account_info(),
+        };
+        let cpi_ctx_b = CpiContext::new_with_signer(
+            token_program_b.clone(),
+            cpi_accounts_b,
+            &source_signer_seeds,
+        );
+        token_interface::transfer_checked(cpi_ctx_b, amount_b, ctx.accounts.token_b_mint.decimals)?;
+
+        let cpi_accounts_burn = token_interface::Burn {
+            mint: ctx.accounts.source_lp_mint.to_account_info(),
+            from: ctx.accounts.user_source_lp.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_ctx_burn = CpiContext::new(
+            token_program_for(
+                &ctx.accounts.source_lp_mint,
+                &ctx.accounts.token_program,
+                ctx.accounts.token_program_b.as_ref(),
+            )?
+            .to_account_info(),
+            cpi_accounts_burn,
+        );
+        token_interface::burn(cpi_ctx_burn, lp_amount)?;
+
+        let source_pool = &mut ctx.accounts.source_pool;
+        source_pool.update_oracle(Clock::get()?.unix_timestamp);
+        source_pool.reserve_a = source_pool.reserve_a.saturating_sub(amount_a);
+        source_pool.reserve_b = source_pool.reserve_b.saturating_sub(amount_b);
+        source_pool.record_liquidity_event(Clock::get()?.unix_timestamp);
+
+        // Deposit into the destination pool at its own ratio
+        let destination_reserve_a = ctx
+            .accounts
+            .destination_pool
+            .reserve(&ctx.accounts.destination_pool_token_a);
+        let destination_reserve_b = ctx
+            .accounts
+            .destination_pool
+            .reserve(&ctx.accounts.destination_pool_token_b);
+        let (deposit_a, deposit_b) = compute_optimal_deposit(
+            amount_a,
+            amount_b,
+            destination_reserve_a,
+            destination_reserve_b,
+        );
+        let deposit_fee_bps = ctx.accounts.destination_pool.deposit_fee_bps;
+        let (deposit_a, deposit_fee_a) = apply_deposit_fee(deposit_a, deposit_fee_bps);
+        let (deposit_b, deposit_fee_b) = apply_deposit_fee(deposit_b, deposit_fee_bps);
+        let lp_tokens_to_mint = compute_lp_tokens(
+            deposit_a,
+            deposit_b,
+            destination_reserve_a,
+            destination_reserve_b,
+            ctx.accounts.destination_lp_mint.supply,
+            ctx.accounts.destination_lp_mint.decimals,
+            ctx.accounts.token_a_mint.decimals,
+            ctx.accounts.token_b_mint.decimals,
+        )?;
+        require!(lp_tokens_to_mint >= min_lp_out, AmmError::SlippageExceeded);
+
+        let cpi_accounts_a = TransferChecked {
+            from: ctx.accounts.user_token_a.to_account_info(),
+            mint: ctx.accounts.token_a_mint.to_account_info(),
+            to: ctx.accounts.destination_pool_token_a.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_ctx_a = CpiContext::new(token_program_a.clone(), cpi_accounts_a);
+        token_interface::transfer_checked(
+            cpi_ctx_a,
+            deposit_a,
+            ctx.accounts.token_a_mint.decimals,
+        )?;
+
+        let cpi_accounts_b = TransferChecked {
+            from: ctx.accounts.user_token_b.to_account_info(),
+            mint: ctx.accounts.token_b_mint.to_account_info(),
+            to: ctx.accounts.destination_pool_token_b.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_ctx_b = CpiContext::new(token_program_b.clone(), cpi_accounts_b);
+        token_interface::transfer_checked(
+            cpi_ctx_b,
+            deposit_b,
+            ctx.accounts.token_b_mint.decimals,
+        )?;
+
+        if deposit_fee_bps > 0 {
+            let (protocol_fee_a, protocol_fee_b) = protocol_fee_accounts(
+                &ctx.accounts.destination_pool,
+                ctx.accounts.config.as_deref(),
+                ctx.accounts.protocol_fee_a.as_deref(),
+                ctx.accounts.protocol_fee_b.as_deref(),
+            )?;
+            for (from, mint, token_program, to, fee) in [
+                (
+                    &ctx.accounts.user_token_a,
+                    &ctx.accounts.token_a_mint,
+                    &token_program_a,
+                    protocol_fee_a,
+                    deposit_fee_a,
+                ),
+                (
+                    &ctx.accounts.user_token_b,
+                    &ctx.accounts.token_b_mint,
+                    &token_program_b,
+                    protocol_fee_b,
+                    deposit_fee_b,
+                ),
+            ] {
+                if fee > 0 {
+                    let cpi_accounts_fee = TransferChecked {
+                        from: from.to_account_info(),
+                        mint: mint.to_account_info(),
+                        to: to.to_account_info(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    };
+                    let cpi_ctx_fee = CpiContext::new(token_program.clone(), cpi_accounts_fee);
+                    token_interface::transfer_checked(cpi_ctx_fee, fee, mint.decimals)?;
+                }
+            }
+        }
+
+        let destination_seeds = [
+            b"pool".as_ref(),
+            ctx.accounts.destination_pool.token_a_mint.as_ref(),
+            ctx.accounts.destination_pool.token_b_mint.as_ref(),
+            &ctx.accounts.destination_pool.pool_index.to_le_bytes(),
+            &[ctx.accounts.destination_pool.bump],
+        ];
+        let destination_signer_seeds = [&destination_seeds[..]];
+
+        let cpi_accounts_mint = token_interface::MintTo {
+            mint: ctx.accounts.destination_lp_mint.to_account_info(),
+            to: ctx.accounts.user_destination_lp.to_account_info(),
+            authority: ctx.accounts.destination_pool.to_account_info(),
+        };
+        let cpi_ctx_mint = CpiContext::new_with_signer(
+            token_program_for(
+                &ctx.accounts.destination_lp_mint,
+                &ctx.accounts.token_program,
+                ctx.accounts.token_program_b.as_ref(),
+            )?
+            .to_account_info(),
+            cpi_accounts_mint,
+            &destination_signer_seeds,
+        );
+        token_interface::mint_to(cpi_ctx_mint, lp_tokens_to_mint)?;
+
+        let destination_pool = &mut ctx.accounts.destination_pool;
+        destination_pool.update_oracle(Clock::get()?.unix_timestamp);
+        destination_pool.reserve_a = destination_pool
+            .reserve_a
+            .checked_add(deposit_a)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        destination_pool.reserve_b = destination_pool
+            .reserve_b
+            .checked_add(deposit_b)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+
+        destination_pool.record_liquidity_event(Clock::get()?.unix_timestamp);
+        destination_pool.check_backing(&ctx.accounts.destination_pool_token_a)?;
+        destination_pool.check_backing(&ctx.accounts.destination_pool_token_b)?;
+        let destination_sequence = destination_pool.next_sequence();
+        let source_pool = &mut ctx.accounts.source_pool;
+        source_pool.check_backing(&ctx.accounts.source_pool_token_a)?;
+        source_pool.check_backing(&ctx.accounts.source_pool_token_b)?;
+        let source_sequence = source_pool.next_sequence();
+
+        emit!(LiquidityMigratedEvent {
+            version: SCHEMA_VERSION,
+            source_pool: ctx.accounts.source_pool.key(),
+            destination_pool: ctx.accounts.destination_pool.key(),
+            user: ctx.accounts.user.key(),
+            lp_burned: lp_amount,
+            amount_a,
+            amount_b,
+            deposited_a: deposit_a,
+            deposited_b: deposit_b,
+            deposit_fee_a,
+            deposit_fee_b,
+            lp_minted: lp_tokens_to_mint,
+            source_sequence,
+            destination_sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Moves `amount` LP tokens into an escrow held by a new `LiquidityLock`
+    /// PDA until `unlock_timestamp`. `lock_index` lets one owner hold several
+    /// locks on the same pool at once.
+    pub fn lock_liquidity(
+        ctx: Context<LockLiquidity>,
+        lock_index: u64,
+        amount: u64,
+        unlock_timestamp: i64,
+    ) -> Result<()> {
+        require!(amount > 0, AmmError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        require!(unlock_timestamp > now, AmmError::InvalidUnlockTimestamp);
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.user_lp.to_account_info(),
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.lock_vault.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.lp_mint.decimals)?;
+
+        let lock = &mut ctx.accounts.lock;
+        lock.owner = ctx.accounts.owner.key();
+        lock.pool = ctx.accounts.pool.key();
+        lock.amount = amount;
+        lock.unlock_timestamp = unlock_timestamp;
+        lock.lock_index = lock_index;
+        lock.bump = ctx.bumps.lock;
+
+        emit!(LockCreatedEvent {
+            version: SCHEMA_VERSION,
+            lock: lock.key(),
+            owner: lock.owner,
+            pool: lock.pool,
+            amount,
+            unlock_timestamp,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Moves a lock's unlock time later; it can never be brought forward.
+    pub fn extend_lock(ctx: Context<ExtendLock>, unlock_timestamp: i64) -> Result<()> {
+        let lock = &mut ctx.accounts.lock;
+        require!(
+            unlock_timestamp > lock.unlock_timestamp,
+            AmmError::InvalidUnlockTimestamp
+        );
+        let previous_unlock_timestamp = lock.unlock_timestamp;
+        lock.unlock_timestamp = unlock_timestamp;
+
+        emit!(LockExtendedEvent {
+            version: SCHEMA_VERSION,
+            lock: lock.key(),
+            owner: lock.owner,
+            previous_unlock_timestamp,
+            unlock_timestamp,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Returns a lock's LP tokens to its owner once `unlock_timestamp` has
+    /// passed, closing the escrow and the lock account.
+    pub fn unlock_liquidity(ctx: Context<UnlockLiquidity>) -> Result<()> {
+        let lock = &ctx.accounts.lock;
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= lock.unlock_timestamp, AmmError::LiquidityStillLocked);
+
+        let seeds = [
+            b"lock",
+            lock.pool.as_ref(),
+            lock.owner.as_ref(),
+            &lock.lock_index.to_le_bytes(),
+            &[lock.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.lock_vault.to_account_info(),
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.user_lp.to_account_info(),
+            authority: lock.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            &signer_seeds,
+        );
+        token_interface::transfer_checked(cpi_ctx, lock.amount, ctx.accounts.lp_mint.decimals)?;
+
+        let cpi_accounts_close = token_interface::CloseAccount {
+            account: ctx.accounts.lock_vault.to_account_info(),
+            destination: ctx.accounts.owner.to_account_info(),
+            authority: lock.to_account_info(),
+        };
+        let cpi_ctx_close = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts_close,
+            &signer_seeds,
+        );
+        token_interface::close_account(cpi_ctx_close)?;
+
+        emit!(LiquidityUnlockedEvent {
+            version: SCHEMA_VERSION,
+            lock: lock.key(),
+            owner: lock.owner,
+            pool: lock.pool,
+            amount: lock.amount,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Burns the caller's LP tokens for good and adds them to the pool's
+    /// `BurnedLiquidity` tally, an on-chain proof-of-burn. Burning does not
+    /// lock the reserves: they stay in the pool, so every remaining LP token
+    /// redeems for proportionally more.
+    pub fn burn_lp_permanently(ctx: Context<BurnLpPermanently>, lp_amount: u64) -> Result<()> {
+        require!(lp_amount > 0, AmmError::InvalidAmount);
+
+        let cpi_accounts_burn = token_interface::Burn {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            from: ctx.accounts.user_lp.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_ctx_burn = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts_burn,
+        );
+        token_interface::burn(cpi_ctx_burn, lp_amount)?;
+
+        let burned = &mut ctx.accounts.burned_liquidity;
+        burned.pool = ctx.accounts.pool.key();
+        burned.bump = ctx.bumps.burned_liquidity;
+        burned.total_burned = burned
+            .total_burned
+            .checked_add(lp_amount)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+
+        ctx.accounts.lp_mint.reload()?;
+        emit!(LiquidityBurnedEvent {
+            version: SCHEMA_VERSION,
+            pool: burned.pool,
+            user: ctx.accounts.user.key(),
+            lp_burned: lp_amount,
+            total_burned: burned.total_burned,
+            remaining_lp_supply: ctx.accounts.lp_mint.supply,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Creates the pool's liquidity mining farm, emitting
+    /// `reward_rate_per_second` reward tokens across all staked LP. Rewards
+    /// are paid from the farm's reward vault, which `fund_farm` tops up.
+    pub fn initialize_farm(
+        ctx: Context<InitializeFarm>,
+        reward_rate_per_second: u64,
+    ) -> Result<()> {
+        let farm = &mut ctx.accounts.farm;
+        farm.pool = ctx.accounts.pool.key();
+        farm.authority = ctx.accounts.authority.key();
+        farm.lp_mint = ctx.accounts.lp_mint.key();
+        farm.reward_mint = ctx.accounts.reward_mint.key();
+        farm.stake_vault = ctx.accounts.stake_vault.key();
+        farm.reward_vault = ctx.accounts.reward_vault.key();
+        farm.reward_rate_per_second = reward_rate_per_second;
+        farm.acc_reward_per_share = 0;
+        farm.last_update_timestamp = Clock::get()?.unix_timestamp;
+        farm.total_staked = 0;
+        farm.emission_schedule = Vec::new();
+        farm.bump = ctx.bumps.farm;
+
+        emit!(FarmInitializedEvent {
+            version: SCHEMA_VERSION,
+            farm: farm.key(),
+            pool: farm.pool,
+            reward_mint: farm.reward_mint,
+            reward_rate_per_second,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Queues emission rate changes (e.g. halvings) on a farm. Each entry must
+    /// start in the future and after the last queued one; started entries are
+    /// applied by the reward accounting and cannot be changed.
+    pub fn set_emission_schedule(
+        ctx: Context<SetEmissionSchedule>,
+        entries: Vec<EmissionEntry>,
+    ) -> Result<()> {
+        let farm = &mut ctx.accounts.farm;
+        let now = Clock::get()?.unix_timestamp;
+        // Settle the past at the old rates, dropping started entries
+        update_farm_rewards(farm, now)?;
+
+        require!(
+            farm.emission_schedule.len() + entries.len() <= MAX_EMISSION_ENTRIES,
+            AmmError::EmissionScheduleFull
+        );
+        let mut last_start = farm
+            .emission_schedule
+            .last()
+            .map_or(now, |entry| entry.start_timestamp);
+        for entry in &entries {
+            require!(
+                entry.start_timestamp > last_start,
+                AmmError::InvalidEmissionSchedule
+            );
+            last_start = entry.start_timestamp;
+        }
+        farm.emission_schedule.extend(entries);
+
+        emit!(EmissionScheduleSetEvent {
+            version: SCHEMA_VERSION,
+            farm: farm.key(),
+            reward_rate_per_second: farm.reward_rate_per_second,
+            schedule: farm.emission_schedule.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Adds reward tokens to a farm's reward vault.
+    pub fn fund_farm(ctx: Context<FundFarm>, amount: u64) -> Result<()> {
+        require!(amount > 0, AmmError::InvalidAmount);
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.funder_reward_token.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
+            to: ctx.accounts.reward_vault.to_account_info(),
+            authority: ctx.accounts.funder.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.reward_mint.decimals)?;
+
+        emit!(FarmFundedEvent {
+            version: SCHEMA_VERSION,
+            farm: ctx.accounts.farm.key(),
+            funder: ctx.accounts.funder.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Moves LP tokens into the farm's escrow; they earn rewards from now on.
+    pub fn stake(ctx: Context<Stake>, lp_amount: u64) -> Result<()> {
+        require!(lp_amount > 0, AmmError::InvalidAmount);
+
+        let farm = &mut ctx.accounts.farm;
+        update_farm_rewards(farm, Clock::get()?.unix_timestamp)?;
+
+        let position = &mut ctx.accounts.position;
+        position.farm = farm.key();
+        position.owner = ctx.accounts.owner.key();
+        position.bump = ctx.bumps.position;
+        settle_stake_rewards(farm, position)?;
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.user_lp.to_account_info(),
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.stake_vault.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, lp_amount, ctx.accounts.lp_mint.decimals)?;
+
+        position.amount = position
+            .amount
+            .checked_add(lp_amount)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        position.reward_debt = accrued_rewards(farm, position.amount)?;
+        farm.total_staked = farm
+            .total_staked
+            .checked_add(lp_amount)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+
+        emit!(StakedEvent {
+            version: SCHEMA_VERSION,
+            farm: farm.key(),
+            owner: position.owner,
+            amount: lp_amount,
+            total_staked: farm.total_staked,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Returns staked LP tokens from the farm's escrow. Rewards earned so far
+    /// stay pending for `claim_rewards`.
+    pub fn unstake(ctx: Context<Unstake>, lp_amount: u64) -> Result<()> {
+        require!(lp_amount > 0, AmmError::InvalidAmount);
+
+        let farm = &mut ctx.accounts.farm;
+        update_farm_rewards(farm, Clock::get()?.unix_timestamp)?;
+
+        let position = &mut ctx.accounts.position;
+        require!(lp_amount <= position.amount, AmmError::InsufficientStake);
+        settle_stake_rewards(farm, position)?;
+
+        position.amount -= lp_amount;
+        position.reward_debt = accrued_rewards(farm, position.amount)?;
+        farm.total_staked = farm
+            .total_staked
+            .checked_sub(lp_amount)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+
+        let seeds = [b"farm", farm.pool.as_ref(), &[farm.bump]];
+        let signer_seeds = [&seeds[..]];
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.stake_vault.to_account_info(),
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.user_lp.to_account_info(),
+            authority: farm.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            &signer_seeds,
+        );
+        token_interface::transfer_checked(cpi_ctx, lp_amount, ctx.accounts.lp_mint.decimals)?;
+
+        emit!(UnstakedEvent {
+            version: SCHEMA_VERSION,
+            farm: farm.key(),
+            owner: position.owner,
+            amount: lp_amount,
+            total_staked: farm.total_staked,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Pays out a position's pending rewards. If the reward vault runs dry,
+    /// the claim pays what the vault holds and the rest stays pending.
+    pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+        let farm = &mut ctx.accounts.farm;
+        update_farm_rewards(farm, Clock::get()?.unix_timestamp)?;
+
+        let position = &mut ctx.accounts.position;
+        settle_stake_rewards(farm, position)?;
+
+        let amount = position
+            .pending_rewards
+            .min(ctx.accounts.reward_vault.amount);
+        position.pending_rewards -= amount;
+
+        if amount > 0 {
+            let seeds = [b"farm", farm.pool.as_ref(), &[farm.bump]];
+            let signer_seeds = [&seeds[..]];
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.reward_vault.to_account_info(),
+                mint: ctx.accounts.reward_mint.to_account_info(),
+                to: ctx.accounts.user_reward_token.to_account_info(),
+                authority: farm.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+            token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.reward_mint.decimals)?;
+        }
+
+        emit!(RewardsClaimedEvent {
+            version: SCHEMA_VERSION,
+            farm: farm.key(),
+            owner: position.owner,
+            amount,
+            unpaid: position.pending_rewards,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Schedules a change to the pool's swap fee or fee recipient, validated
+    /// as it would be at creation. It can be applied once the config's
+    /// `param_change_delay_slots` have passed, giving LPs time to react; only
+    /// one change may be pending at a time.
+    pub fn propose_param_change(
+        ctx: Context<ProposeParamChange>,
+        change: ParamChange,
+    ) -> Result<()> {
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        change.validate(config.as_ref())?;
+        let pool = &mut ctx.accounts.pool;
+        require!(pool.pending_change.is_none(), AmmError::ParamChangePending);
+
+        let delay = config.map_or(DEFAULT_PARAM_CHANGE_DELAY_SLOTS, |config| {
+            config.param_change_delay_slots
+        });
+        let effective_slot = Clock::get()?
+            .slot
+            .checked_add(delay)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+        pool.pending_change = Some(change);
+        pool.pending_change_slot = effective_slot;
+
+        let sequence = pool.next_sequence();
+        emit!(ParamChangeProposedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            change,
+            effective_slot,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Enacts the pool's pending parameter change once its slot is reached.
+    /// Anyone may call this; the value is checked again against the config.
+    pub fn apply_param_change(ctx: Context<ApplyParamChange>) -> Result<()> {
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        let pool = &mut ctx.accounts.pool;
+        let change = pool.pending_change.ok_or(AmmError::NoPendingParamChange)?;
+        require!(
+            !matches!(change, ParamChange::VaultMigration { .. }),
+            AmmError::VaultMigrationPending
+        );
+        require!(
+            !matches!(change, ParamChange::InsuranceDisbursement { .. }),
+            AmmError::InsuranceDisbursementPending
+        );
+        require!(
+            Clock::get()?.slot >= pool.pending_change_slot,
+            AmmError::ParamChangeNotReady
+        );
+        change.validate(config.as_ref())?;
+
+        let authority = pool.pending_change_role();
+        let previous = change.apply(pool);
+        pool.pending_change = None;
+        pool.pending_change_slot = 0;
+
+        let timestamp = Clock::get()?.unix_timestamp;
+        let sequence = pool.next_sequence();
+        match (previous, change) {
+            (
+                ParamChange::Fee {
+                    total_fee_bps: previous_total_fee_bps,
+                },
+                ParamChange::Fee { total_fee_bps },
+            ) => emit!(FeeUpdatedEvent {
+                version: SCHEMA_VERSION,
+                pool: pool.key(),
+                authority,
+                previous_total_fee_bps,
+                total_fee_bps,
+                timestamp,
+                sequence,
+                slot: Clock::get()?.slot,
+            }),
+            (
+                ParamChange::FeeRecipient {
+                    fee_recipient: previous_fee_recipient,
+                },
+                ParamChange::FeeRecipient { fee_recipient },
+            ) => emit!(FeeRecipientChangedEvent {
+                version: SCHEMA_VERSION,
+                pool: pool.key(),
+                authority,
+                previous_fee_recipient,
+                fee_recipient,
+                timestamp,
+                sequence,
+                slot: Clock::get()?.slot,
+            }),
+            _ => {}
+        }
+        emit!(ParamChangeAppliedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous,
+            change,
+            timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Drops the pool's pending parameter change.
+    pub fn cancel_param_change(ctx: Context<CancelParamChange>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let change = pool
+            .pending_change
+            .take()
+            .ok_or(AmmError::NoPendingParamChange)?;
+        pool.pending_change_slot = 0;
+
+        let sequence = pool.next_sequence();
+        emit!(ParamChangeCancelledEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            change,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Moves all of `side`'s tokens into a fresh vault owned by the pool PDA
+    /// and points the pool at it, for recovering from a vault that can't be
+    /// trusted any more (say, one with a close authority set). It must first
+    /// be proposed as `ParamChange::VaultMigration` and waits out the same
+    /// delay. The old vault is closed, its rent going to the authority,
+    /// unless its close authority is someone other than the pool; then it is
+    /// left empty.
+    pub fn migrate_vault(ctx: Context<MigrateVault>, side: VaultSide) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let pool = &accounts.pool;
+        require!(!pool.locked, AmmError::PoolLocked);
+        require!(
+            pool.pending_change == Some(ParamChange::VaultMigration { side }),
+            AmmError::NoPendingParamChange
+        );
+        require!(
+            Clock::get()?.slot >= pool.pending_change_slot,
+            AmmError::ParamChangeNotReady
+        );
+
+        let seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &pool.pool_index.to_le_bytes(),
+            &[pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+        let amount = accounts.old_vault.amount;
+        if amount > 0 {
+            let cpi_accounts = TransferChecked {
+                from: accounts.old_vault.to_account_info(),
+                mint: accounts.mint.to_account_info(),
+                to: accounts.new_vault.to_account_info(),
+                authority: pool.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+            token_interface::transfer_checked(cpi_ctx, amount, accounts.mint.decimals)?;
+        }
+
+        let old_vault_closed = match accounts.old_vault.close_authority {
+            COption::Some(close_authority) => close_authority == pool.key(),
+            COption::None => true,
+        };
+        if old_vault_closed {
+            let cpi_accounts_close = token_interface::CloseAccount {
+                account: accounts.old_vault.to_account_info(),
+                destination: accounts.authority.to_account_info(),
+                authority: pool.to_account_info(),
+            };
+            let cpi_ctx_close = CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                cpi_accounts_close,
+                &signer_seeds,
+            );
+            token_interface::close_account(cpi_ctx_close)?;
+        }
+
+        let old_vault = accounts.old_vault.key();
+        let new_vault = accounts.new_vault.key();
+        let mint = accounts.mint.key();
+        let pool = &mut ctx.accounts.pool;
+        let (reserve, protocol_fees_owed) = match side {
+            VaultSide::A => {
+                pool.token_a_account = new_vault;
+                (pool.reserve_a, pool.protocol_fees_owed_a)
+            }
+            VaultSide::B => {
+                pool.token_b_account = new_vault;
+                (pool.reserve_b, pool.protocol_fees_owed_b)
+            }
+        };
+        pool.pending_change = None;
+        pool.pending_change_slot = 0;
+        pool.vault_migrations = pool
+            .vault_migrations
+            .checked_add(1)
+            .ok_or(AmmError::ArithmeticOverflow)?;
+
+        pool.check_backing(&ctx.accounts.new_vault)?;
+
+        let sequence = pool.next_sequence();
+        emit!(VaultMigratedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            authority: ctx.accounts.authority.key(),
+            side,
+            mint,
+            old_vault,
+            new_vault,
+            amount,
+            old_vault_closed,
+            reserve,
+            protocol_fees_owed,
+            vault_migrations: pool.vault_migrations,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Creates the pool's insurance vaults, token accounts owned by the pool
+    /// PDA that `collect_protocol_fees` moves the `insurance_share_bps` share
+    /// of the protocol fees into. The share itself starts at zero and is set
+    /// with `ParamChange::InsuranceShare`. Tokens in the vaults are never
+    /// part of the reserves; they leave only through `disburse_insurance`.
+    pub fn create_insurance_vaults(ctx: Context<CreateInsuranceVaults>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.insurance_vault_a = ctx.accounts.insurance_vault_a.key();
+        pool.insurance_vault_b = ctx.accounts.insurance_vault_b.key();
+
+        let sequence = pool.next_sequence();
+        emit!(InsuranceVaultsCreatedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            insurance_vault_a: pool.insurance_vault_a,
+            insurance_vault_b: pool.insurance_vault_b,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Pays `amount` from the insurance vault in `recipient`'s mint to
+    /// `recipient`, say to compensate LPs after an incident. It must first
+    /// be proposed as `ParamChange::InsuranceDisbursement` with the same
+    /// amount and recipient, and waits out the same delay.
+    pub fn disburse_insurance(
+        ctx: Context<DisburseInsurance>,
+        amount: u64,
+        recipient: Pubkey,
+    ) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let pool = &accounts.pool;
+        require!(
+            pool.pending_change == Some(ParamChange::InsuranceDisbursement { amount, recipient }),
+            AmmError::NoPendingParamChange
+        );
+        require!(
+            Clock::get()?.slot >= pool.pending_change_slot,
+            AmmError::ParamChangeNotReady
+        );
+        require!(
+            accounts.insurance_vault.amount >= amount,
+            AmmError::InsufficientLiquidity
+        );
+
+        let seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &pool.pool_index.to_le_bytes(),
+            &[pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+        let cpi_accounts = TransferChecked {
+            from: accounts.insurance_vault.to_account_info(),
+            mint: accounts.mint.to_account_info(),
+            to: accounts.recipient_token.to_account_info(),
+            authority: pool.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            accounts.token_program.to_account_info(),
+            cpi_accounts,
+            &signer_seeds,
+        );
+        token_interface::transfer_checked(cpi_ctx, amount, accounts.mint.decimals)?;
+
+        let mint = accounts.insurance_vault.mint;
+        let insurance_vault = accounts.insurance_vault.key();
+        let remaining = accounts.insurance_vault.amount - amount;
+        let pool = &mut ctx.accounts.pool;
+        pool.pending_change = None;
+        pool.pending_change_slot = 0;
+
+        let sequence = pool.next_sequence();
+        emit!(InsuranceDisbursedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            authority: ctx.accounts.authority.key(),
+            mint,
+            insurance_vault,
+            recipient,
+            amount,
+            remaining,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the insurance vaults' balances via return data.
+    pub fn get_insurance_balance(ctx: Context<GetInsuranceBalance>) -> Result<InsuranceBalance> {
+        Ok(InsuranceBalance {
+            amount_a: ctx.accounts.insurance_vault_a.amount,
+            amount_b: ctx.accounts.insurance_vault_b.amount,
+            insurance_share_bps: ctx.accounts.pool.insurance_share_bps,
+        })
+    }
+
+    /// Creates the pool's oracle observation buffer with room for one
+    /// observation, recording the current cumulative prices. Anyone may call
+    /// it, paying the rent.
+    pub fn initialize_observations(ctx: Context<InitializeObservations>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        pool.update_oracle(now);
+
+        let observations = &mut ctx.accounts.observations;
+        observations.pool = pool.key();
+        observations.cardinality = 1;
+        observations.bump = ctx.bumps.observations;
+        observations.observations = vec![Observation {
+            slot: Clock::get()?.slot,
+            timestamp: pool.last_oracle_timestamp,
+            price_a_cumulative: pool.price_a_cumulative,
+            price_b_cumulative: pool.price_b_cumulative,
+        }];
+
+        emit!(ObservationCardinalityIncreasedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            payer: ctx.accounts.payer.key(),
+            previous_cardinality: 0,
+            cardinality: 1,
+            timestamp: now,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Grows the pool's observation buffer to `new_size` entries, the payer
+    /// covering the extra rent. The buffer fills the new room as swaps come
+    /// in, so a longer window is only available once it has.
+    pub fn increase_observation_cardinality(
+        ctx: Context<IncreaseObservationCardinality>,
+        new_size: u16,
+    ) -> Result<()> {
+        let observations = &mut ctx.accounts.observations;
+        require!(
+            new_size > observations.cardinality && new_size <= MAX_OBSERVATION_CARDINALITY,
+            AmmError::InvalidObservationCardinality
+        );
+        let previous_cardinality = observations.cardinality;
+        observations.cardinality = new_size;
+
+        emit!(ObservationCardinalityIncreasedEvent {
+            version: SCHEMA_VERSION,
+            pool: observations.pool,
+            payer: ctx.accounts.payer.key(),
+            previous_cardinality,
+            cardinality: new_size,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the pool's spot prices, the ratios of its tracked reserves, as
+    /// Q64.64 via return data, for programs reading the price by CPI. Fails
+    /// with `InsufficientLiquidity` while either reserve is empty.
+    pub fn get_spot_price(ctx: Context<GetSpotPrice>) -> Result<SpotPriceResult> {
+        let pool = &ctx.accounts.pool;
+        require!(
+            pool.reserve_a > 0 && pool.reserve_b > 0,
+            AmmError::InsufficientLiquidity
+        );
+        Ok(SpotPriceResult {
+            price_a: q64_price(pool.reserve_b, pool.reserve_a),
+            price_b: q64_price(pool.reserve_a, pool.reserve_b),
+            timestamp: Clock::get()?.unix_timestamp,
+        })
+    }
+
+    /// Returns the pool's time-weighted average prices over the last
+    /// `seconds_ago` seconds via return data. Fails with `OracleStale` when
+    /// the window starts before the oldest observation.
+    pub fn get_twap(ctx: Context<GetTwap>, seconds_ago: u32) -> Result<TwapResult> {
+        require!(seconds_ago > 0, AmmError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let current = ctx.accounts.pool.cumulative_prices(now);
+        let (past_a, past_b) = ctx.accounts.observations.cumulative_prices_at(
+            now - seconds_ago as i64,
+            now,
+            current,
+        )?;
+        Ok(TwapResult {
+            price_a: current.0.wrapping_sub(past_a) / seconds_ago as u128,
+            price_b: current.1.wrapping_sub(past_b) / seconds_ago as u128,
+            timestamp: now,
+        })
+    }
+
+    /// Creates the pool's `PoolStats`, moving over the totals the pool kept
+    /// itself before they had their own account. Anyone may call it, paying
+    /// the rent.
+    pub fn init_stats(ctx: Context<InitStats>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let stats = &mut ctx.accounts.stats;
+        stats.pool = pool.key();
+        stats.bump = ctx.bumps.stats;
+        stats.cumulative_volume_a = std::mem::take(&mut pool.cumulative_volume_a);
+        stats.cumulative_volume_b = std::mem::take(&mut pool.cumulative_volume_b);
+        stats.cumulative_fees_a = std::mem::take(&mut pool.cumulative_fees_a);
+        stats.cumulative_fees_b = std::mem::take(&mut pool.cumulative_fees_b);
+        stats.swap_count = std::mem::take(&mut pool.swap_count);
+        stats.liquidity_event_count = std::mem::take(&mut pool.liquidity_event_count);
+        Ok(())
+    }
+
+    /// Returns the pool's running volume, fee and activity totals from its
+    /// `PoolStats`, its age and when it was last active via return data.
+    pub fn get_stats(ctx: Context<GetStats>) -> Result<PoolStatsResult> {
+        let pool = &ctx.accounts.pool;
+        let stats = &ctx.accounts.stats;
+        Ok(PoolStatsResult {
+            cumulative_volume_a: stats.cumulative_volume_a,
+            cumulative_volume_b: stats.cumulative_volume_b,
+            cumulative_fees_a: stats.cumulative_fees_a,
+            cumulative_fees_b: stats.cumulative_fees_b,
+            swap_count: stats.swap_count,
+            liquidity_event_count: stats.liquidity_event_count,
+            created_at_timestamp: pool.created_at_timestamp,
+            created_at_slot: pool.created_at_slot,
+            last_swap_timestamp: pool.last_swap_timestamp,
+            last_liquidity_change_timestamp: pool.last_liquidity_change_timestamp,
+        })
+    }
+
+    /// Returns how much of the LP supply the config's treasury minted through
+    /// the program, and the rest, via return data.
+    pub fn get_protocol_liquidity(ctx: Context<GetProtocolLiquidity>) -> Result<ProtocolLiquidity> {
+        let protocol_lp_amount = ctx.accounts.pool.protocol_lp_amount;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        Ok(ProtocolLiquidity {
+            protocol_lp_amount,
+            external_lp_amount: lp_supply.saturating_sub(protocol_lp_amount),
+            lp_supply,
+        })
+    }
+
+    /// Brings a pool created before `POOL_VERSION` up to date and bumps its
+    /// `version`. A shorter account grows to the current layout, with `payer`
+    /// funding the extra rent and the fields added since reading as zero,
+    /// which each of them treats as its default; a legacy fee fraction moves
+    /// to `total_fee_bps`. A fee that isn't a whole number of basis points
+    /// fails with `FeeNotRepresentable` unless the pool authority pays and
+    /// sets `round_fee_down` to take the one below. Permissionless otherwise.
+    /// Swaps, deposits and withdrawals fail with `PoolNeedsMigration` until
+    /// it has run, and pools from before `version` cannot be loaded at all.
+    pub fn migrate_pool(ctx: Context<MigratePool>, round_fee_down: bool) -> Result<()> {
+        let pool_info = ctx.accounts.pool.to_account_info();
+        require!(
+            pool_info
+                .try_borrow_data()?
+                .starts_with(Pool::DISCRIMINATOR),
+            AmmError::InvalidPoolAccount
+        );
+
+        let previous_len = pool_info.data_len();
+        let len = 8 + Pool::INIT_SPACE;
+        if previous_len < len {
+            let rent = Rent::get()?
+                .minimum_balance(len)
+                .saturating_sub(pool_info.lamports());
+            if rent > 0 {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: ctx.accounts.payer.to_account_info(),
+                            to: pool_info.clone(),
+                        },
+                    ),
+                    rent,
+                )?;
+            }
+            pool_info.resize(len)?;
+        }
+
+        let mut pool = Box::new(Pool::try_deserialize(
+            &mut &pool_info.try_borrow_data()?[..],
+        )?);
+        require!(pool.version < POOL_VERSION, AmmError::PoolAlreadyMigrated);
+        if round_fee_down {
+            require_keys_eq!(
+                ctx.accounts.payer.key(),
+                pool.authority,
+                pool.authority_error()
+            );
+        }
+        let previous_version = pool.version;
+        pool.convert_fee_to_bps(round_fee_down)?;
+        pool.version = POOL_VERSION;
+        let sequence = pool.next_sequence();
+        pool.try_serialize(&mut &mut pool_info.try_borrow_mut_data()?[..])?;
+
+        emit!(PoolMigratedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool_info.key(),
+            payer: ctx.accounts.payer.key(),
+            previous_pool_version: previous_version,
+            pool_version: POOL_VERSION,
+            previous_len: previous_len as u64,
+            len: len as u64,
+            total_fee_bps: pool.total_fee_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Splits the swap fee between the LPs, whose share stays in the pool,
+    /// and the protocol, whose share is paid out as before. New pools pay the
+    /// whole fee to the protocol.
+    pub fn set_fee_split(
+        ctx: Context<SetFeeSplit>,
+        lp_fee_bps: u16,
+        protocol_fee_bps: u16,
+    ) -> Result<()> {
+        require!(
+            lp_fee_bps as u32 + protocol_fee_bps as u32 == BPS_DENOMINATOR as u32,
+            AmmError::InvalidFeeSplit
+        );
+        let pool = &mut ctx.accounts.pool;
+        let previous_lp_fee_bps = pool.lp_fee_bps;
+        pool.lp_fee_bps = lp_fee_bps;
+        pool.protocol_fee_bps = protocol_fee_bps;
+
+        let sequence = pool.next_sequence();
+        emit!(FeeSplitSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous_lp_fee_bps,
+            lp_fee_bps,
+            protocol_fee_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Splits the protocol fees `collect_protocol_fees` pays out between up to
+    /// `MAX_FEE_RECIPIENTS` wallets by weight, in basis points summing to
+    /// 10,000. An empty list pays everything to the fee recipient again. The
+    /// fee recipient still signs for collections either way.
+    pub fn set_fee_recipients(
+        ctx: Context<SetFeeRecipients>,
+        fee_recipients: Vec<FeeShare>,
+    ) -> Result<()> {
+        let valid = fee_recipients.is_empty()
+            || (fee_recipients.len() <= MAX_FEE_RECIPIENTS
+                && fee_recipients.iter().enumerate().all(|(index, share)| {
+                    share.recipient != Pubkey::default()
+                        && share.weight_bps > 0
+                        && fee_recipients[..index]
+                            .iter()
+                            .all(|other| other.recipient != share.recipient)
+                })
+                && fee_recipients
+                    .iter()
+                    .map(|share| share.weight_bps as u32)
+                    .sum::<u32>()
+                    == BPS_DENOMINATOR as u32);
+        require!(valid, AmmError::InvalidFeeRecipients);
+
+        let pool = &mut ctx.accounts.pool;
+        let previous = std::mem::replace(&mut pool.fee_recipients, fee_recipients);
+
+        let sequence = pool.next_sequence();
+        emit!(FeeRecipientsSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous,
+            fee_recipients: pool.fee_recipients.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+            authority: ctx.accounts.authority.key(),
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// First step of an authority transfer: records `new_authority` as
+    /// pending. The current authority stays in control until the new key
+    /// signs `accept_authority`, so a mistyped key can simply be replaced or
+    /// cancelled.
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        require!(
+            new_authority != Pubkey::default(),
+            AmmError::InvalidPendingAuthority
+        );
+        let pool = &mut ctx.accounts.pool;
+        let previous_pending_authority = pool.pending_authority;
+        pool.pending_authority = new_authority;
+
+        let sequence = pool.next_sequence();
+        emit!(AuthorityTransferProposedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            authority: pool.authority,
+            previous_pending_authority,
+            pending_authority: new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Signed by the pending authority to take over the pool.
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let previous_authority = pool.authority;
+        pool.authority = pool.pending_authority;
+        pool.pending_authority = Pubkey::default();
+
+        let sequence = pool.next_sequence();
+        emit!(AuthorityTransferredEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            authority: pool.authority,
+            previous_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Hands the fee and pause roles to other keys, so the authority's key
+    /// can stay cold: `fee_authority` collects protocol fees and changes who
+    /// receives them, and `pause_guardian` can pause but not unpause the
+    /// pool. The default pubkey hands a role back to the authority.
+    pub fn set_pool_roles(
+        ctx: Context<SetPoolRoles>,
+        fee_authority: Pubkey,
+        pause_guardian: Pubkey,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let previous_fee_authority = pool.fee_authority;
+        let previous_pause_guardian = pool.pause_guardian;
+        pool.fee_authority = fee_authority;
+        pool.pause_guardian = pause_guardian;
+
+        let sequence = pool.next_sequence();
+        emit!(PoolRolesSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous_fee_authority,
+            fee_authority,
+            previous_pause_guardian,
+            pause_guardian,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Gives up the pool authority for good, leaving the pool's parameters
+    /// fixed. Swaps, liquidity and the other permissionless instructions are
+    /// unaffected; every authority instruction fails with
+    /// `AuthorityRenounced` from then on.
+    pub fn renounce_authority(ctx: Context<RenounceAuthority>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        // Nobody could unpause withdrawals afterwards
+        pool.require_active(POOL_STATUS_WITHDRAWALS_PAUSED)?;
+        let previous_authority = pool.authority;
+        pool.authority = Pubkey::default();
+        // Nor can the other roles outlive it
+        pool.fee_authority = Pubkey::default();
+        pool.pause_guardian = Pubkey::default();
+        // A pending transfer could otherwise still be accepted, and a pending
+        // parameter change still applied
+        pool.pending_authority = Pubkey::default();
+        let timestamp = Clock::get()?.unix_timestamp;
+        let sequence = pool.next_sequence();
+        if let Some(change) = pool.pending_change.take() {
+            pool.pending_change_slot = 0;
+            emit!(ParamChangeCancelledEvent {
+                version: SCHEMA_VERSION,
+                pool: pool.key(),
+                change,
+                timestamp,
+                sequence,
+                slot: Clock::get()?.slot,
+            });
+        }
+
+        emit!(AuthorityRenouncedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous_authority,
+            timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Drops a pending authority transfer.
+    pub fn cancel_authority_transfer(ctx: Context<CancelAuthorityTransfer>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(
+            pool.pending_authority != Pubkey::default(),
+            AmmError::NoPendingAuthority
+        );
+        let cancelled_authority = pool.pending_authority;
+        pool.pending_authority = Pubkey::default();
+
+        let sequence = pool.next_sequence();
+        emit!(AuthorityTransferCancelledEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            authority: pool.authority,
+            cancelled_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the share of every deposit, up to `MAX_DEPOSIT_FEE_BPS`, paid to
+    /// the config's protocol fee recipient before LP tokens are computed.
+    pub fn set_deposit_fee(ctx: Context<SetDepositFee>, deposit_fee_bps: u16) -> Result<()> {
+        require!(
+            deposit_fee_bps <= MAX_DEPOSIT_FEE_BPS,
+            AmmError::DepositFeeTooHigh
+        );
+        let pool = &mut ctx.accounts.pool;
+        let previous_deposit_fee_bps = pool.deposit_fee_bps;
+        pool.deposit_fee_bps = deposit_fee_bps;
+
+        let sequence = pool.next_sequence();
+        emit!(DepositFeeSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous_deposit_fee_bps,
+            deposit_fee_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the share of every withdrawal, up to `MAX_WITHDRAWAL_FEE_BPS`, that
+    /// stays in the pool for the remaining LPs.
+    pub fn set_withdrawal_fee(
+        ctx: Context<SetWithdrawalFee>,
+        withdrawal_fee_bps: u16,
+    ) -> Result<()> {
+        require!(
+            withdrawal_fee_bps <= MAX_WITHDRAWAL_FEE_BPS,
+            AmmError::WithdrawalFeeTooHigh
+        );
+        let pool = &mut ctx.accounts.pool;
+        let previous_withdrawal_fee_bps = pool.withdrawal_fee_bps;
+        pool.withdrawal_fee_bps = withdrawal_fee_bps;
+
+        let sequence = pool.next_sequence();
+        emit!(WithdrawalFeeSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous_withdrawal_fee_bps,
+            withdrawal_fee_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets how far `add_liquidity_imbalanced` deposits may stray from the
+    /// pool ratio; 0 (the default) only accepts proportional deposits.
+    pub fn set_max_imbalance(ctx: Context<SetMaxImbalance>, max_imbalance_bps: u16) -> Result<()> {
+        require!(
+            max_imbalance_bps <= BPS_DENOMINATOR,
+            AmmError::InvalidImbalanceTolerance
+        );
+        let pool = &mut ctx.accounts.pool;
+        pool.max_imbalance_bps = max_imbalance_bps;
+
+        let sequence = pool.next_sequence();
+        emit!(MaxImbalanceSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            max_imbalance_bps,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the circuit breaker's threshold: a swap moving the spot price by
+    /// more than `breaker_threshold_bps` doesn't trade. Through `swap_v2` or
+    /// `swap_and_create` it pauses swaps on the pool, until the authority
+    /// unpauses them; the other swaps fail. Zero turns it off.
+    pub fn set_circuit_breaker(
+        ctx: Context<SetCircuitBreaker>,
+        breaker_threshold_bps: u16,
+    ) -> Result<()> {
+        require!(
+            breaker_threshold_bps < BPS_DENOMINATOR,
+            AmmError::InvalidBreakerThreshold
+        );
+        let pool = &mut ctx.accounts.pool;
+        let previous_threshold_bps = pool.breaker_threshold_bps;
+        pool.breaker_threshold_bps = breaker_threshold_bps;
+
+        let sequence = pool.next_sequence();
+        emit!(CircuitBreakerSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous_threshold_bps,
+            breaker_threshold_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the smallest input a swap may trade, and the largest as a share
+    /// of the input reserve; zero turns either limit off.
+    pub fn set_trade_limits(ctx: Context<SetTradeLimits>, trade_limits: TradeLimits) -> Result<()> {
+        trade_limits.check()?;
+        let pool = &mut ctx.accounts.pool;
+        let previous = pool.trade_limits;
+        pool.trade_limits = trade_limits;
+
+        let sequence = pool.next_sequence();
+        emit!(TradeLimitsSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous,
+            trade_limits,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the smallest input a swap may put in of each token; a swap below
+    /// it fails with `TradeTooSmall` before any transfer or fee is taken.
+    /// Zero, the default, lets any amount through.
+    pub fn set_min_trade_amount_in(
+        ctx: Context<SetMinTradeAmountIn>,
+        min_trade_amount_in_a: u64,
+        min_trade_amount_in_b: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let previous_a = pool.min_trade_amount_in_a;
+        let previous_b = pool.min_trade_amount_in_b;
+        pool.min_trade_amount_in_a = min_trade_amount_in_a;
+        pool.min_trade_amount_in_b = min_trade_amount_in_b;
+
+        let sequence = pool.next_sequence();
+        emit!(MinTradeAmountInSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous_a,
+            previous_b,
+            min_trade_amount_in_a,
+            min_trade_amount_in_b,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Guards the pool's swaps with the `oracle_kind` feeds `price_feed_a`
+    /// and `price_feed_b`: a swap whose execution price is more than
+    /// `max_deviation_bps` from their pair price, or that finds either feed
+    /// published over `max_staleness_seconds` ago, fails. The `OracleGuard`
+    /// account is created on first use with the authority paying the rent.
+    /// A `max_deviation_bps` of zero turns the guard off.
+    pub fn set_oracle_guard(
+        ctx: Context<SetOracleGuard>,
+        oracle_kind: OracleKind,
+        max_deviation_bps: u16,
+        max_staleness_seconds: u32,
+    ) -> Result<()> {
+        let enabled = max_deviation_bps > 0;
+        if enabled {
+            require!(
+                max_deviation_bps <= BPS_DENOMINATOR && max_staleness_seconds > 0,
+                AmmError::InvalidOracleGuard
+            );
+            oracle_kind.read_price(&ctx.accounts.price_feed_a)?;
+            oracle_kind.read_price(&ctx.accounts.price_feed_b)?;
+        }
+        let guard = &mut ctx.accounts.oracle_guard;
+        guard.pool = ctx.accounts.pool.key();
+        guard.oracle_kind = oracle_kind;
+        guard.price_feed_a = ctx.accounts.price_feed_a.key();
+        guard.price_feed_b = ctx.accounts.price_feed_b.key();
+        guard.max_deviation_bps = max_deviation_bps;
+        guard.max_staleness_seconds = max_staleness_seconds;
+        guard.bump = ctx.bumps.oracle_guard;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.oracle_guard = enabled;
+        let sequence = pool.next_sequence();
+        emit!(OracleGuardSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            price_feed_a: guard.price_feed_a,
+            price_feed_b: guard.price_feed_b,
+            max_deviation_bps,
+            max_staleness_seconds,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            oracle_kind,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the pool's display name and off-chain metadata URI, creating its
+    /// `PoolMetadata` account on first use with the authority paying the
+    /// rent. Kept apart from the pool so swaps never load it.
+    pub fn set_pool_metadata(
+        ctx: Context<SetPoolMetadata>,
+        name: String,
+        uri: String,
+    ) -> Result<()> {
+        require!(
+            name.len() <= MAX_POOL_NAME_LEN && uri.len() <= MAX_POOL_URI_LEN,
+            AmmError::MetadataTooLong
+        );
+        let metadata = &mut ctx.accounts.metadata;
+        let created = metadata.pool == Pubkey::default();
+        metadata.pool = ctx.accounts.pool.key();
+        metadata.name = name;
+        metadata.uri = uri;
+        metadata.bump = ctx.bumps.metadata;
+
+        emit!(MetadataUpdatedEvent {
+            version: SCHEMA_VERSION,
+            pool: metadata.pool,
+            authority: ctx.accounts.authority.key(),
+            name: metadata.name.clone(),
+            uri: metadata.uri.clone(),
+            created,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Turns the allowlist on or off for swaps (`gated`) and, separately, for
+    /// deposits (`gated_liquidity`). Withdrawals are never gated.
+    pub fn set_gating(ctx: Context<SetGating>, gated: bool, gated_liquidity: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.gated = gated;
+        pool.gated_liquidity = gated_liquidity;
+
+        let sequence = pool.next_sequence();
+        emit!(GatingUpdatedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            gated,
+            gated_liquidity,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Brings a pool's trade start forward; it can never be pushed back.
+    pub fn set_trade_start(ctx: Context<SetTradeStart>, trade_start_timestamp: i64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(
+            trade_start_timestamp < pool.trade_start_timestamp,
+            AmmError::InvalidTradeStart
+        );
+        let previous_trade_start_timestamp = pool.trade_start_timestamp;
+        pool.trade_start_timestamp = trade_start_timestamp;
+
+        let sequence = pool.next_sequence();
+        emit!(TradeStartUpdatedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous_trade_start_timestamp,
+            trade_start_timestamp,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Caps how much of `launch_mint` each wallet may buy during the first
+    /// `window_seconds` after the trade start; a `max_buy` of zero turns the
+    /// cap off. Only allowed before trading starts.
+    pub fn set_launch_limit(
+        ctx: Context<SetLaunchLimit>,
+        launch_mint: Pubkey,
+        window_seconds: i64,
+        max_buy: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(
+            Clock::get()?.unix_timestamp < pool.trade_start_timestamp,
+            AmmError::LaunchConfigLocked
+        );
+        require!(
+            (launch_mint == pool.token_a_mint || launch_mint == pool.token_b_mint)
+                && window_seconds >= 0,
+            AmmError::InvalidLaunchConfig
+        );
+        pool.launch_mint = launch_mint;
+        pool.launch_window_seconds = window_seconds;
+        pool.launch_max_buy = max_buy;
+
+        let sequence = pool.next_sequence();
+        emit!(LaunchLimitSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            launch_mint,
+            window_seconds,
+            max_buy,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a wallet's `LaunchPurchase` once the launch window is over and
+    /// refunds the rent to whoever paid it. Permissionless.
+    pub fn close_launch_purchase(ctx: Context<CloseLaunchPurchase>) -> Result<()> {
+        require!(
+            !launch_window_open(&ctx.accounts.pool, Clock::get()?.unix_timestamp),
+            AmmError::LaunchWindowActive
+        );
+        Ok(())
+    }
+
+    /// Pays the protocol fees owed out of the vaults and resets the counters;
+    /// the LPs' reserves are untouched. Without `fee_recipients` everything
+    /// goes to token accounts of the fee recipient's choosing. Otherwise each
+    /// side is split by weight, any rounding remainder going to the first
+    /// recipient: `recipient_token_a`/`_b` must then be the first recipient's,
+    /// and the remaining accounts a token A and a token B account for each
+    /// further recipient, in order.
+    pub fn collect_protocol_fees<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CollectProtocolFees<'info>>,
+    ) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let pool = &accounts.pool;
+        require!(!pool.locked, AmmError::PoolLocked);
+        let amount_a = pool.protocol_fees_owed_a;
+        let amount_b = pool.protocol_fees_owed_b;
+        let (insurance_a, paid_a) = split_insurance_share(amount_a, pool.insurance_share_bps);
+        let (insurance_b, paid_b) = split_insurance_share(amount_b, pool.insurance_share_bps);
+
+        let shares = pool.fee_shares();
+        require!(
+            ctx.remaining_accounts.len() == 2 * (shares.len() - 1),
+            AmmError::InvalidFeeRecipientAccounts
+        );
+        let mut payees = vec![(
+            accounts.recipient_token_a.to_account_info(),
+            accounts.recipient_token_b.to_account_info(),
+        )];
+        // Only a sole fee recipient collecting for itself picks any accounts;
+        // otherwise each payee's accounts must be its own
+        let collector = accounts.fee_recipient.key();
+        if !pool.fee_recipients.is_empty() || collector != pool.fee_recipient {
+            check_fee_payee(
+                pool,
+                &shares[0],
+                &accounts.recipient_token_a,
+                &accounts.recipient_token_b,
+            )?;
+        }
+        for (share, pair) in shares[1..].iter().zip(ctx.remaining_accounts.chunks(2)) {
+            let token_a = InterfaceAccount::<TokenAccount>::try_from(&pair[0])?;
+            let token_b = InterfaceAccount::<TokenAccount>::try_from(&pair[1])?;
+            check_fee_payee(pool, share, &token_a, &token_b)?;
+            payees.push((pair[0].clone(), pair[1].clone()));
+        }
+        let amounts_a = split_by_weight(paid_a, &shares);
+        let amounts_b = split_by_weight(paid_b, &shares);
+
+        let seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &pool.pool_index.to_le_bytes(),
+            &[pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+        let mut payouts = Vec::with_capacity(shares.len());
+        for (index, (recipient_a, recipient_b)) in payees.into_iter().enumerate() {
+            for (vault, mint, recipient, amount) in [
+                (
+                    &accounts.pool_token_a,
+                    &accounts.token_a_mint,
+                    recipient_a,
+                    amounts_a[index],
+                ),
+                (
+                    &accounts.pool_token_b,
+                    &accounts.token_b_mint,
+                    recipient_b,
+                    amounts_b[index],
+                ),
+            ] {
+                if amount == 0 {
+                    continue;
+                }
+                let cpi_accounts = TransferChecked {
+                    from: vault.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: recipient,
+                    authority: pool.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(
+                    token_program_for(
+                        mint,
+                        &accounts.token_program,
+                        accounts.token_program_b.as_ref(),
+                    )?
+                    .to_account_info(),
+                    cpi_accounts,
+                    &signer_seeds,
+                );
+                token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)?;
+            }
+            payouts.push(FeePayout {
+                recipient: shares[index].recipient,
+                amount_a: amounts_a[index],
+                amount_b: amounts_b[index],
+            });
+        }
+        if insurance_a > 0 || insurance_b > 0 {
+            let (Some(insurance_vault_a), Some(insurance_vault_b)) =
+                (&accounts.insurance_vault_a, &accounts.insurance_vault_b)
+            else {
+                return err!(AmmError::InvalidInsuranceVault);
+            };
+            for (vault, mint, insurance_vault, amount) in [
+                (
+                    &accounts.pool_token_a,
+                    &accounts.token_a_mint,
+                    insurance_vault_a,
+                    insurance_a,
+                ),
+                (
+                    &accounts.pool_token_b,
+                    &accounts.token_b_mint,
+                    insurance_vault_b,
+                    insurance_b,
+                ),
+            ] {
+                if amount == 0 {
+                    continue;
+                }
+                let cpi_accounts = TransferChecked {
+                    from: vault.to_account_info(),
+                    mint: mint.to_account_info(),
+                    to: insurance_vault.to_account_info(),
+                    authority: pool.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(
+                    token_program_for(
+                        mint,
+                        &accounts.token_program,
+                        accounts.token_program_b.as_ref(),
+                    )?
+                    .to_account_info(),
+                    cpi_accounts,
+                    &signer_seeds,
+                );
+                token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)?;
+            }
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.protocol_fees_owed_a = 0;
+        pool.protocol_fees_owed_b = 0;
+
+        pool.check_backing(&ctx.accounts.pool_token_a)?;
+        pool.check_backing(&ctx.accounts.pool_token_b)?;
+
+        let sequence = pool.next_sequence();
+        for (mint, amount) in [(pool.token_a_mint, amount_a), (pool.token_b_mint, amount_b)] {
+            if amount > 0 {
+                emit!(ProtocolFeesCollectedInMintEvent {
+                    version: SCHEMA_VERSION,
+                    pool: pool.key(),
+                    mint,
+                    amount,
+                    recipient: pool.fee_recipient,
+                    timestamp: Clock::get()?.unix_timestamp,
+                    slot: Clock::get()?.slot,
+                    sequence,
+                });
+            }
+        }
+        emit!(ProtocolFeesCollectedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            fee_recipient: pool.fee_recipient,
+            collector,
+            amount_a,
+            amount_b,
+            payouts,
+            insurance_a,
+            insurance_b,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Chooses where swap fees go. In `BuybackBurn` mode, swaps must pay their
+    /// fee into the pool's fee vault for the input mint (created here), and
+    /// `execute_buyback` later turns the fees into `buyback_mint` and burns it.
+    pub fn set_fee_mode(
+        ctx: Context<SetFeeMode>,
+        fee_mode: FeeMode,
+        buyback_mint: Pubkey,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        if fee_mode == FeeMode::BuybackBurn {
+            require!(
+                buyback_mint == pool.token_a_mint || buyback_mint == pool.token_b_mint,
+                AmmError::InvalidBuybackMint
+            );
+        }
+        pool.fee_mode = fee_mode;
+        pool.buyback_mint = buyback_mint;
+        pool.fee_vault_a = ctx.accounts.fee_vault_a.key();
+        pool.fee_vault_b = ctx.accounts.fee_vault_b.key();
+
+        let sequence = pool.next_sequence();
+        emit!(FeeModeSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            fee_mode,
+            buyback_mint,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless crank for `BuybackBurn` pools: burns the fees already
+    /// held in `buyback_mint`, and swaps the other fee vault into
+    /// `buyback_mint` through the pool (fee-free) and burns the proceeds. Each
+    /// crank spends at most `MAX_BUYBACK_BPS` of the input reserve; the rest
+    /// stays for later cranks.
+    pub fn execute_buyback(ctx: Context<ExecuteBuyback>) -> Result<()> {
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        require!(
+            !config.is_some_and(|config| config.paused),
+            AmmError::ProtocolPaused
+        );
+        let pool = &ctx.accounts.pool;
+        pool.require_active(POOL_STATUS_SWAPS_PAUSED)?;
+        require!(!pool.locked, AmmError::PoolLocked);
+        require!(
+            pool.fee_mode == FeeMode::BuybackBurn,
+            AmmError::NotBuybackPool
+        );
+
+        let buy_b = pool.buyback_mint == pool.token_b_mint;
+        let (fee_vault_in, fee_vault_out, pool_token_in, pool_token_out) = if buy_b {
+            (
+                &ctx.accounts.fee_vault_a,
+                &ctx.accounts.fee_vault_b,
+                &ctx.accounts.pool_token_a,
+                &ctx.accounts.pool_token_b,
+            )
+        } else {
+            (
+                &ctx.accounts.fee_vault_b,
+                &ctx.accounts.fee_vault_a,
+                &ctx.accounts.pool_token_b,
+                &ctx.accounts.pool_token_a,
+            )
+        };
+
+        let reserve_in = pool.reserve(pool_token_in);
+        let reserve_out = pool.reserve(pool_token_out);
+        let max_amount_in =
+            (reserve_in as u128 * MAX_BUYBACK_BPS as u128 / BPS_DENOMINATOR as u128) as u64;
+        let amount_in = fee_vault_in.amount.min(max_amount_in);
+        let amount_bought = if amount_in > 0 {
+            compute_swap(amount_in, reserve_in, reserve_out, 0, 1)?.amount_out
+        } else {
+            0
+        };
+        let fees_burned = fee_vault_out.amount;
+
+        let seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &pool.pool_index.to_le_bytes(),
+            &[pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+        let token_program_in = token_program_for(
+            &ctx.accounts.fee_mint,
+            &ctx.accounts.token_program,
+            ctx.accounts.token_program_b.as_ref(),
+        )?
+        .to_account_info();
+        let token_program = token_program_for(
+            &ctx.accounts.buyback_mint,
+            &ctx.accounts.token_program,
+            ctx.accounts.token_program_b.as_ref(),
+        )?
+        .to_account_info();
+        let pool_info = pool.to_account_info();
+
+        if amount_in > 0 {
+            let cpi_accounts = TransferChecked {
+                from: fee_vault_in.to_account_info(),
+                mint: ctx.accounts.fee_mint.to_account_info(),
+                to: pool_token_in.to_account_info(),
+                authority: pool_info.clone(),
+            };
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(token_program_in, cpi_accounts, &signer_seeds),
+                amount_in,
+                ctx.accounts.fee_mint.decimals,
+            )?;
+        }
+        // The bought tokens are burned straight out of the pool's vault
+        for (from, amount) in [
+            (pool_token_out, amount_bought),
+            (fee_vault_out, fees_burned),
+        ] {
+            if amount > 0 {
+                let cpi_accounts = token_interface::Burn {
+                    mint: ctx.accounts.buyback_mint.to_account_info(),
+                    from: from.to_account_info(),
+                    authority: pool_info.clone(),
+                };
+                token_interface::burn(
+                    CpiContext::new_with_signer(token_program.clone(), cpi_accounts, &signer_seeds),
+                    amount,
+                )?;
+            }
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.update_oracle(Clock::get()?.unix_timestamp);
+        if buy_b {
+            pool.reserve_a = pool
+                .reserve_a
+                .checked_add(amount_in)
+                .ok_or(AmmError::ArithmeticOverflow)?;
+            pool.reserve_b = pool.reserve_b.saturating_sub(amount_bought);
+        } else {
+            pool.reserve_b = pool
+                .reserve_b
+                .checked_add(amount_in)
+                .ok_or(AmmError::ArithmeticOverflow)?;
+            pool.reserve_a = pool.reserve_a.saturating_sub(amount_bought);
+        }
+
+        pool.check_backing(&ctx.accounts.pool_token_a)?;
+        pool.check_backing(&ctx.accounts.pool_token_b)?;
+
+        let sequence = pool.next_sequence();
+        emit!(BuybackEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            buyback_mint: pool.buyback_mint,
+            fees_spent: amount_in,
+            amount_bought,
+            fees_burned,
+            total_burned: amount_bought
+                .checked_add(fees_burned)
+                .ok_or(AmmError::ArithmeticOverflow)?,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// First half of a commit-reveal swap: records `commitment` (see
+    /// `swap_commitment`) and escrows `COMMIT_BOND_LAMPORTS`, keeping the
+    /// trade's size and direction hidden until `reveal_swap`. The swap may be
+    /// revealed from the next slot through `window_slots` slots after this one.
+    pub fn commit_swap(
+        ctx: Context<CommitSwap>,
+        commitment: [u8; 32],
+        window_slots: u64,
+    ) -> Result<()> {
+        require!(
+            (1..=MAX_COMMIT_WINDOW_SLOTS).contains(&window_slots),
+            AmmError::InvalidCommitWindow
+        );
+
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.owner.to_account_info(),
+                    to: ctx.accounts.swap_commit.to_account_info(),
+                },
+            ),
+            COMMIT_BOND_LAMPORTS,
+        )?;
+
+        let commit_slot = Clock::get()?.slot;
+        let swap_commit = &mut ctx.accounts.swap_commit;
+        swap_commit.pool = ctx.accounts.pool.key();
+        swap_commit.owner = ctx.accounts.owner.key();
+        swap_commit.commitment = commitment;
+        swap_commit.commit_slot = commit_slot;
+        swap_commit.expiry_slot = commit_slot + window_slots;
+        swap_commit.bump = ctx.bumps.swap_commit;
+
+        emit!(SwapCommittedEvent {
+            version: SCHEMA_VERSION,
+            pool: swap_commit.pool,
+            owner: swap_commit.owner,
+            commit_slot,
+            expiry_slot: swap_commit.expiry_slot,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Second half of a commit-reveal swap: checks the preimage against the
+    /// commitment and executes it at the current reserves, then closes the
+    /// commitment and returns the bond and rent to the owner.
+    pub fn reveal_swap(
+        ctx: Context<RevealSwap>,
+        amount_in: u64,
+        min_amount_out: u64,
+        a_to_b: bool,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        let swap_commit = &ctx.accounts.swap_commit;
+        let slot = Clock::get()?.slot;
+        require!(slot > swap_commit.commit_slot, AmmError::RevealTooEarly);
+        require!(slot <= swap_commit.expiry_slot, AmmError::CommitExpired);
+        require!(
+            swap_commitment(amount_in, min_amount_out, a_to_b, &salt) == swap_commit.commitment,
+            AmmError::InvalidCommitment
+        );
+
+        let (user_token_in, user_token_out, pool_token_in, pool_token_out) = if a_to_b {
+            (
+                ctx.accounts.user_token_a.to_account_info(),
+                ctx.accounts.user_token_b.to_account_info(),
+                &ctx.accounts.pool_token_a,
+                &ctx.accounts.pool_token_b,
+            )
+        } else {
+            (
+                ctx.accounts.user_token_b.to_account_info(),
+                ctx.accounts.user_token_a.to_account_info(),
+                &ctx.accounts.pool_token_b,
+                &ctx.accounts.pool_token_a,
+            )
+        };
+        let (token_in_mint, token_out_mint) = if a_to_b {
+            (&ctx.accounts.token_a_mint, &ctx.accounts.token_b_mint)
+        } else {
+            (&ctx.accounts.token_b_mint, &ctx.accounts.token_a_mint)
+        };
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        let event = process_swap(
+            SwapAccounts {
+                pool: &mut ctx.accounts.pool,
+                user: ctx.accounts.owner.to_account_info(),
+                user_token_in,
+                user_token_out,
+                pool_token_in,
+                pool_token_out,
+                owner_token_account: ctx
+                    .accounts
+                    .owner_token_account
+                    .as_ref()
+                    .map(|account| account.to_account_info()),
+                token_in_mint,
+                token_out_mint,
+                token_program: &ctx.accounts.token_program,
+                token_program_b: ctx.accounts.token_program_b.as_ref(),
+                allowlist_entry: ctx.accounts.allowlist_entry.as_deref(),
+                launch_purchase: None,
+                fee_discount_bps: 0,
+                hook_program: None,
+                hook_accounts: &[],
+                user_signer_seeds: &[],
+                config: config.as_ref(),
+                observations: None,
+                stats: None,
+                oracle_guard: None,
+                price_feed_a: None,
+                price_feed_b: None,
+            },
+            amount_in,
+            min_amount_out,
+        )?
+        .executed()?;
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    /// Closes an expired, unrevealed commitment, returning its bond and rent
+    /// to the owner. Anyone may call it.
+    pub fn reclaim_swap_commit(ctx: Context<ReclaimSwapCommit>) -> Result<()> {
+        require!(
+            Clock::get()?.slot > ctx.accounts.swap_commit.expiry_slot,
+            AmmError::CommitNotExpired
+        );
+
+        emit!(SwapCommitReclaimedEvent {
+            version: SCHEMA_VERSION,
+            pool: ctx.accounts.swap_commit.pool,
+            owner: ctx.accounts.swap_commit.owner,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Escrows `amount_in` of the maker's `token_in_mint` in an `Order` that
+    /// anyone may fill against the pool until `expiry`, as long as the
+    /// maker receives at least `min_price_numerator / min_price_denominator`
+    /// output per input. `order_index` lets one maker hold several orders on
+    /// the same pool.
+    pub fn create_order(
+        ctx: Context<CreateOrder>,
+        order_index: u64,
+        amount_in: u64,
+        min_price_numerator: u64,
+        min_price_denominator: u64,
+        expiry: i64,
+    ) -> Result<()> {
+        require!(amount_in > 0, AmmError::InvalidAmount);
+        require!(min_price_denominator > 0, AmmError::InvalidLimitPrice);
+        require!(
+            expiry > Clock::get()?.unix_timestamp,
+            AmmError::InvalidOrderExpiry
+        );
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.maker_token_in.to_account_info(),
+            mint: ctx.accounts.token_in_mint.to_account_info(),
+            to: ctx.accounts.order_vault.to_account_info(),
+            authority: ctx.accounts.maker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount_in, ctx.accounts.token_in_mint.decimals)?;
+
+        let order = &mut ctx.accounts.order;
+        order.maker = ctx.accounts.maker.key();
+        order.pool = ctx.accounts.pool.key();
+        order.token_in_mint = ctx.accounts.token_in_mint.key();
+        order.amount_in = amount_in;
+        order.min_price_numerator = min_price_numerator;
+        order.min_price_denominator = min_price_denominator;
+        order.expiry = expiry;
+        order.order_index = order_index;
+        order.bump = ctx.bumps.order;
+
+        emit!(OrderCreatedEvent {
+            version: SCHEMA_VERSION,
+            order: order.key(),
+            maker: order.maker,
+            pool: order.pool,
+            token_in_mint: order.token_in_mint,
+            amount_in,
+            min_price_numerator,
+            min_price_denominator,
+            expiry,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Swaps an order's escrow against the pool for the maker, paying the
+    /// filler `ORDER_FILLER_REWARD_BPS` of the input. Fails unless the
+    /// maker's output for the whole input meets the order's price. The order
+    /// and its escrow are closed back to the maker.
+    pub fn fill_order(ctx: Context<FillOrder>) -> Result<()> {
+        let order = &ctx.accounts.order;
+        require!(
+            Clock::get()?.unix_timestamp < order.expiry,
+            AmmError::OrderExpired
+        );
+
+        let filler_reward = (order.amount_in as u128 * ORDER_FILLER_REWARD_BPS as u128
+            / BPS_DENOMINATOR as u128) as u64;
+        let amount_in = order.amount_in - filler_reward;
+
+        let order_index = order.order_index.to_le_bytes();
+        let seeds = [
+            b"order".as_ref(),
+            order.pool.as_ref(),
+            order.maker.as_ref(),
+            &order_index,
+            &[order.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+
+        let config = GlobalConfig::load(&ctx.accounts.config)?;
+        let event = process_swap(
+            SwapAccounts {
+                pool: &mut ctx.accounts.pool,
+                user: ctx.accounts.order.to_account_info(),
+                user_token_in: ctx.accounts.order_vault.to_account_info(),
+                user_token_out: ctx.accounts.maker_token_out.to_account_info(),
+                pool_token_in: &ctx.accounts.pool_token_in,
+                pool_token_out: &ctx.accounts.pool_token_out,
+                owner_token_account: ctx
+                    .accounts
+                    .owner_token_account
+                    .as_ref()
+                    .map(|account| account.to_account_info()),
+                token_in_mint: &ctx.accounts.token_in_mint,
+                token_out_mint: &ctx.accounts.token_out_mint,
+                token_program: &ctx.accounts.token_program,
+                token_program_b: ctx.accounts.token_program_b.as_ref(),
+                // The order PDA can't be allowlisted or make launch-window
+                // buys
+                allowlist_entry: None,
+                launch_purchase: None,
+                fee_discount_bps: 0,
+                hook_program: None,
+                hook_accounts: &[],
+                user_signer_seeds: &signer_seeds,
+                config: config.as_ref(),
+                observations: None,
+                stats: None,
+                oracle_guard: None,
+                price_feed_a: None,
+                price_feed_b: None,
+            },
+            amount_in,
+            0,
+        )?
+        .executed()?;
+        require!(
+            event.token_in == ctx.accounts.order.token_in_mint,
+            AmmError::InvalidOrderMint
+        );
+        check_limit_price(
+            ctx.accounts.order.amount_in,
+            event.amount_out,
+            ctx.accounts.order.min_price_numerator,
+            ctx.accounts.order.min_price_denominator,
+        )?;
+
+        let token_program_in = token_program_for(
+            &ctx.accounts.token_in_mint,
+            &ctx.accounts.token_program,
+            ctx.accounts.token_program_b.as_ref(),
+        )?
+        .to_account_info();
+        if filler_reward > 0 {
+            let cpi_accounts_reward = TransferChecked {
+                from: ctx.accounts.order_vault.to_account_info(),
+                mint: ctx.accounts.token_in_mint.to_account_info(),
+                to: ctx.accounts.filler_token_in.to_account_info(),
+                authority: ctx.accounts.order.to_account_info(),
+            };
+            let cpi_ctx_reward = CpiContext::new_with_signer(
+                token_program_in.clone(),
+                cpi_accounts_reward,
+                &signer_seeds,
+            );
+            token_interface::transfer_checked(
+                cpi_ctx_reward,
+                filler_reward,
+                ctx.accounts.token_in_mint.decimals,
+            )?;
+        }
+
+        let cpi_accounts_close = token_interface::CloseAccount {
+            account: ctx.accounts.order_vault.to_account_info(),
+            destination: ctx.accounts.maker.to_account_info(),
+            authority: ctx.accounts.order.to_account_info(),
+        };
+        let cpi_ctx_close =
+            CpiContext::new_with_signer(token_program_in, cpi_accounts_close, &signer_seeds);
+        token_interface::close_account(cpi_ctx_close)?;
+
+        emit!(OrderFilledEvent {
+            version: SCHEMA_VERSION,
+            order: ctx.accounts.order.key(),
+            maker: ctx.accounts.order.maker,
+            filler: ctx.accounts.filler.key(),
+            amount_in,
+            amount_out: event.amount_out,
+            filler_reward,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    /// Returns an order's escrow to the maker and closes it. The maker may
+    /// cancel at any time; anyone else only once the order has expired. Rent
+    /// always goes back to the maker.
+    pub fn cancel_order(ctx: Context<CancelOrder>) -> Result<()> {
+        let order = &ctx.accounts.order;
+        let expired = Clock::get()?.unix_timestamp >= order.expiry;
+        require!(
+            expired || ctx.accounts.authority.key() == order.maker,
+            AmmError::OrderNotExpired
+        );
+
+        let order_index = order.order_index.to_le_bytes();
+        let seeds = [
+            b"order".as_ref(),
+            order.pool.as_ref(),
+            order.maker.as_ref(),
+            &order_index,
+            &[order.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.order_vault.to_account_info(),
+            mint: ctx.accounts.token_in_mint.to_account_info(),
+            to: ctx.accounts.maker_token_in.to_account_info(),
+            authority: order.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            &signer_seeds,
+        );
+        token_interface::transfer_checked(
+            cpi_ctx,
+            order.amount_in,
+            ctx.accounts.token_in_mint.decimals,
+        )?;
+
+        let cpi_accounts_close = token_interface::CloseAccount {
+            account: ctx.accounts.order_vault.to_account_info(),
+            destination: ctx.accounts.maker.to_account_info(),
+            authority: order.to_account_info(),
+        };
+        let cpi_ctx_close = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts_close,
+            &signer_seeds,
+        );
+        token_interface::close_account(cpi_ctx_close)?;
+
+        emit!(OrderCancelledEvent {
+            version: SCHEMA_VERSION,
+            order: order.key(),
+            maker: order.maker,
+            amount_in: order.amount_in,
+            expired,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the program `swap` calls after every swap on this pool, or clears
+    /// it with the default pubkey. Other swap instructions can't reach a pool
+    /// with a hook.
+    pub fn set_swap_hook(ctx: Context<SetSwapHook>, hook_program: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.hook_program = hook_program;
+
+        let sequence = pool.next_sequence();
+        emit!(SwapHookSetEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            hook_program,
+            sequence,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Replaces the pool's `POOL_STATUS_*_PAUSED` bits. Swaps and deposits
+    /// can be paused freely; pausing withdrawals traps the LPs' funds, so it
+    /// also needs `emergency` set. The pause guardian may only set more bits,
+    /// leaving unpausing to the authority.
+    pub fn set_pool_status(ctx: Context<SetPoolStatus>, status: u8, emergency: bool) -> Result<()> {
+        require!(
+            status & !POOL_STATUS_ALL_PAUSED == 0,
+            AmmError::InvalidPoolStatus
+        );
+        require!(
+            status & POOL_STATUS_WITHDRAWALS_PAUSED == 0 || emergency,
+            AmmError::EmergencyNotAcknowledged
+        );
+        let pool = &mut ctx.accounts.pool;
+        let previous_status = pool.status;
+        require!(
+            ctx.accounts.authority.key() == pool.authority
+                || status & previous_status == previous_status,
+            AmmError::GuardianCannotUnpause
+        );
+        pool.set_status(status, Clock::get()?.slot);
+
+        let sequence = pool.next_sequence();
+        emit!(PoolStatusChangedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            previous_status,
+            status,
+            emergency,
+            timestamp: Clock::get()?.unix_timestamp,
+            authority: ctx.accounts.authority.key(),
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Moves tokens out of the vaults to rescue accounts of the authority's
+    /// choosing, for when a bug puts the pool's funds at risk. The pool must
+    /// have had every operation paused for the config's
+    /// `emergency_grace_slots`, so LPs see it coming, and the config's
+    /// guardian must co-sign if it has one. The reserves and owed protocol
+    /// fees shrink to what is left in the vaults.
+    pub fn emergency_withdraw(
+        ctx: Context<EmergencyWithdraw>,
+        amount_a: u64,
+        amount_b: u64,
+    ) -> Result<()> {
+        require!(amount_a > 0 || amount_b > 0, AmmError::InvalidAmount);
+        let accounts = &ctx.accounts;
+        let pool = &accounts.pool;
+        require!(!pool.locked, AmmError::PoolLocked);
+        require!(
+            pool.status == POOL_STATUS_ALL_PAUSED,
+            AmmError::PoolNotFullyPaused
+        );
+
+        let config = GlobalConfig::load(&accounts.config)?;
+        let (grace_slots, guardian) = config.map_or(
+            (DEFAULT_EMERGENCY_GRACE_SLOTS, Pubkey::default()),
+            |config| (config.emergency_grace_slots, config.guardian),
+        );
+        let slot = Clock::get()?.slot;
+        require!(
+            slot >= pool.fully_paused_slot.saturating_add(grace_slots),
+            AmmError::EmergencyGracePeriod
+        );
+        if guardian != Pubkey::default() {
+            require!(
+                accounts
+                    .guardian
+                    .as_ref()
+                    .is_some_and(|signer| signer.key() == guardian),
+                AmmError::GuardianSignatureRequired
+            );
+        }
+        require!(
+            amount_a <= accounts.pool_token_a.amount && amount_b <= accounts.pool_token_b.amount,
+            AmmError::InvalidAmount
+        );
+
+        let seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &pool.pool_index.to_le_bytes(),
+            &[pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+        for (vault, mint, destination, amount) in [
+            (
+                &accounts.pool_token_a,
+                &accounts.token_a_mint,
+                &accounts.destination_a,
+                amount_a,
+            ),
+            (
+                &accounts.pool_token_b,
+                &accounts.token_b_mint,
+                &accounts.destination_b,
+                amount_b,
+            ),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+            let cpi_accounts = TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: destination.to_account_info(),
+                authority: pool.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                token_program_for(
+                    mint,
+                    &accounts.token_program,
+                    accounts.token_program_b.as_ref(),
+                )?
+                .to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+            token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)?;
+        }
+
+        let remaining_a = accounts.pool_token_a.amount - amount_a;
+        let remaining_b = accounts.pool_token_b.amount - amount_b;
+        let pool = &mut ctx.accounts.pool;
+        pool.protocol_fees_owed_a = pool.protocol_fees_owed_a.min(remaining_a);
+        pool.protocol_fees_owed_b = pool.protocol_fees_owed_b.min(remaining_b);
+        pool.update_oracle(Clock::get()?.unix_timestamp);
+        pool.reserve_a = pool.reserve_a.min(remaining_a - pool.protocol_fees_owed_a);
+        pool.reserve_b = pool.reserve_b.min(remaining_b - pool.protocol_fees_owed_b);
+
+        pool.check_backing(&ctx.accounts.pool_token_a)?;
+        pool.check_backing(&ctx.accounts.pool_token_b)?;
+
+        let sequence = pool.next_sequence();
+        emit!(EmergencyWithdrawEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            authority: ctx.accounts.authority.key(),
+            guardian,
+            destination_a: ctx.accounts.destination_a.key(),
+            destination_b: ctx.accounts.destination_b.key(),
+            amount_a,
+            amount_b,
+            remaining_a,
+            remaining_b,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+            fully_paused_slot: pool.fully_paused_slot,
+            slot,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Closes an abandoned pool, returning the rent of the Pool account, its
+    /// vaults and any buyback fee vaults to the authority. Every vault must be
+    /// empty and the LP supply zero; burned LP tokens already left the supply,
+    /// and no minimum liquidity is locked at creation, so nothing else needs
+    /// to be accounted for. The SPL token program can't close a mint, so the
+    /// LP mint stays behind with its mint authority revoked, and the pool's
+    /// registry entry is marked `closed`.
+    pub fn close_pool(ctx: Context<ClosePool>) -> Result<()> {
+        let sequence = ctx.accounts.pool.next_sequence();
+        let accounts = &ctx.accounts;
+        let pool = &accounts.pool;
+        require!(!pool.locked, AmmError::PoolLocked);
+        require!(
+            accounts.pool_token_a.amount == 0
+                && accounts.pool_token_b.amount == 0
+                && accounts.lp_mint.supply == 0,
+            AmmError::PoolNotEmpty
+        );
+
+        let seeds = [
+            b"pool".as_ref(),
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            &pool.pool_index.to_le_bytes(),
+            &[pool.bump],
+        ];
+        let signer_seeds = [&seeds[..]];
+
+        let mut vaults = vec![
+            accounts.pool_token_a.to_account_info(),
+            accounts.pool_token_b.to_account_info(),
+        ];
+        for (fee_vault, expected) in [
+            (&accounts.fee_vault_a, pool.fee_vault_a),
+            (&accounts.fee_vault_b, pool.fee_vault_b),
+        ] {
+            if expected == Pubkey::default() {
+                continue;
+            }
+            let fee_vault = fee_vault.as_ref().ok_or(AmmError::InvalidFeeAccount)?;
+            require!(fee_vault.amount == 0, AmmError::PoolNotEmpty);
+            vaults.push(fee_vault.to_account_info());
+        }
+        for vault in vaults {
+            let token_program = token_program_owning(
+                vault.owner,
+                &accounts.token_program,
+                accounts.token_program_b.as_ref(),
+            )?
+            .to_account_info();
+            let cpi_accounts_close = token_interface::CloseAccount {
+                account: vault,
+                destination: accounts.authority.to_account_info(),
+                authority: pool.to_account_info(),
+            };
+            let cpi_ctx_close =
+                CpiContext::new_with_signer(token_program, cpi_accounts_close, &signer_seeds);
+            token_interface::close_account(cpi_ctx_close)?;
+        }
+
+        let cpi_accounts_authority = token_interface::SetAuthority {
+            current_authority: pool.to_account_info(),
+            account_or_mint: accounts.lp_mint.to_account_info(),
+        };
+        let cpi_ctx_authority = CpiContext::new_with_signer(
+            token_program_for(
+                &accounts.lp_mint,
+                &accounts.token_program,
+                accounts.token_program_b.as_ref(),
+            )?
+            .to_account_info(),
+            cpi_accounts_authority,
+            &signer_seeds,
+        );
+        token_interface::set_authority(cpi_ctx_authority, AuthorityType::MintTokens, None)?;
+
+        emit!(PoolClosedEvent {
+            version: SCHEMA_VERSION,
+            pool: pool.key(),
+            authority: accounts.authority.key(),
+            token_a_mint: pool.token_a_mint,
+            token_b_mint: pool.token_b_mint,
+            lp_mint: pool.lp_mint,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
+            slot: Clock::get()?.slot,
+        });
+
+        if ctx.accounts.pool.registry_entry != Pubkey::default() {
+            ctx.accounts
+                .registry_entry
+                .as_mut()
+                .ok_or(AmmError::InvalidRegistryEntry)?
+                .closed = true;
+        }
+
+        Ok(())
+    }
+
+    /// Creates the program-wide config with `admin` in charge of it, or hands
+    /// an existing one to a new admin; only the program's upgrade authority
+    /// may do this. A new config starts with the defaults pools had without
+    /// one: permissionless creation, any fee, the whole fee to the protocol,
+    /// no guardian.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, admin: Pubkey) -> Result<()> {
+        require!(admin != Pubkey::default(), AmmError::InvalidAdmin);
+        let config = &mut ctx.accounts.config;
+        if config.admin == Pubkey::default() {
+            config.default_protocol_fee_bps = BPS_DENOMINATOR;
+            config.param_change_delay_slots = DEFAULT_PARAM_CHANGE_DELAY_SLOTS;
+            config.emergency_grace_slots = DEFAULT_EMERGENCY_GRACE_SLOTS;
+        }
+        config.admin = admin;
+        config.bump = ctx.bumps.config;
+        Ok(())
+    }
+
+    /// Changes any of the config's governance settings; only the admin may.
+    /// The pause flag stops swaps on every pool, while creation mode, fee
+    /// tiers and the default protocol fee share apply to pools created (or
+    /// fees changed) from now on, so existing pools keep their settings. The
+    /// delay applies to parameter changes proposed from now on, while the
+    /// emergency grace period and guardian apply to every emergency
+    /// withdrawal; the default pubkey removes the guardian. A pool creation
+    /// fee needs a treasury to be paid to. Requiring token badges only
+    /// affects pools created from now on.
+    pub fn update_config(ctx: Context<UpdateConfig>, update: ConfigUpdate) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        if let Some(admin) = update.admin {
+            require!(admin != Pubkey::default(), AmmError::InvalidAdmin);
+            config.admin = admin;
+        }
+        if let Some(default_protocol_fee_bps) = update.default_protocol_fee_bps {
+            require!(
+                default_protocol_fee_bps <= BPS_DENOMINATOR,
+                AmmError::InvalidFeeSplit
+            );
+            config.default_protocol_fee_bps = default_protocol_fee_bps;
+        }
+        if let Some(paused) = update.paused {
+            config.paused = paused;
+        }
+        if let Some(pool_creation) = update.pool_creation {
+            config.pool_creation = pool_creation;
+        }
+        if let Some(fee_tiers) = update.fee_tiers {
+            check_fee_tiers(&fee_tiers)?;
+            config.fee_tiers = fee_tiers;
+        }
+        if let Some(param_change_delay_slots) = update.param_change_delay_slots {
+            config.param_change_delay_slots = param_change_delay_slots;
+        }
+        if let Some(emergency_grace_slots) = update.emergency_grace_slots {
+            config.emergency_grace_slots = emergency_grace_slots;
+        }
+        if let Some(guardian) = update.guardian {
+            config.guardian = guardian;
+        }
+        if let Some(pool_creation_fee_lamports) = update.pool_creation_fee_lamports {
+            config.pool_creation_fee_lamports = pool_creation_fee_lamports;
+        }
+        if let Some(treasury) = update.treasury {
+            config.treasury = treasury;
+        }
+        if let Some(require_badge) = update.require_badge {
+            config.require_badge = require_badge;
+        }
+        if let Some(lp_metadata_uri) = update.lp_metadata_uri {
+            require!(
+                lp_metadata_uri.len() <= MAX_POOL_URI_LEN,
+                AmmError::MetadataTooLong
+            );
+            config.lp_metadata_uri = lp_metadata_uri;
+        }
+        require!(
+            config.pool_creation_fee_lamports == 0 || config.treasury != Pubkey::default(),
+            AmmError::InvalidTreasury
+        );
+
+        emit!(ConfigUpdatedEvent {
+            version: SCHEMA_VERSION,
+            admin: config.admin,
+            default_protocol_fee_bps: config.default_protocol_fee_bps,
+            paused: config.paused,
+            pool_creation: config.pool_creation,
+            fee_tiers: config.fee_tiers.clone(),
+            param_change_delay_slots: config.param_change_delay_slots,
+            emergency_grace_slots: config.emergency_grace_slots,
+            guardian: config.guardian,
+            pool_creation_fee_lamports: config.pool_creation_fee_lamports,
+            treasury: config.treasury,
+            require_badge: config.require_badge,
+            timestamp: Clock::get()?.unix_timestamp,
+            lp_metadata_uri: config.lp_metadata_uri.clone(),
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Removes `remove` from the config's fee tiers, then adds `add`; only the
+    /// admin may. Every removed tier must be in the list. New pools and fee
+    /// changes must use one of the resulting tiers, while existing pools keep
+    /// their fees. An empty list allows any fee again.
+    pub fn update_fee_tiers(
+        ctx: Context<UpdateFeeTiers>,
+        add: Vec<FeeTier>,
+        remove: Vec<FeeTier>,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        for tier in remove {
+            let index = config
+                .fee_tiers
+                .iter()
+                .position(|other| other.fee_bps == tier.fee_bps)
+                .ok_or(AmmError::UnsupportedFeeTier)?;
+            config.fee_tiers.remove(index);
+        }
+        config.fee_tiers.extend(add);
+        check_fee_tiers(&config.fee_tiers)?;
+
+        emit!(FeeTiersUpdatedEvent {
+            version: SCHEMA_VERSION,
+            fee_tiers: config.fee_tiers.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Lets `creator` create pools while the config is in `Allowlist` mode.
+    pub fn add_pool_creator(ctx: Context<AddPoolCreator>, creator: Pubkey) -> Result<()> {
+        let entry = &mut ctx.accounts.creator_allowlist_entry;
+        entry.creator = creator;
+        entry.bump = ctx.bumps.creator_allowlist_entry;
+
+        emit!(PoolCreatorUpdatedEvent {
+            version: SCHEMA_VERSION,
+            creator,
+            allowed: true,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a creator's allowlist entry, refunding its rent to the admin.
+    /// Pools the creator already made are unaffected.
+    pub fn remove_pool_creator(ctx: Context<RemovePoolCreator>) -> Result<()> {
+        emit!(PoolCreatorUpdatedEvent {
+            version: SCHEMA_VERSION,
+            creator: ctx.accounts.creator_allowlist_entry.creator,
+            allowed: false,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Vouches for `mint`, letting pools with it be created while the config
+    /// has `require_badge` set.
+    pub fn issue_token_badge(ctx: Context<IssueTokenBadge>) -> Result<()> {
+        let badge = &mut ctx.accounts.token_badge;
+        badge.mint = ctx.accounts.mint.key();
+        badge.bump = ctx.bumps.token_badge;
+
+        emit!(BadgeIssuedEvent {
+            version: SCHEMA_VERSION,
+            mint: badge.mint,
+            admin: ctx.accounts.admin.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a mint's badge, refunding its rent to the admin. While the
+    /// config requires badges no new pool can use the mint, but pools already
+    /// made with it are unaffected.
+    pub fn revoke_token_badge(ctx: Context<RevokeTokenBadge>) -> Result<()> {
+        emit!(BadgeRevokedEvent {
+            version: SCHEMA_VERSION,
+            mint: ctx.accounts.token_badge.mint,
+            admin: ctx.accounts.admin.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the wallet whose token accounts receive protocol fees.
+    pub fn set_protocol_fee_recipient(
+        ctx: Context<SetProtocolFeeRecipient>,
+        protocol_fee_recipient: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.config.protocol_fee_recipient = protocol_fee_recipient;
+
+        emit!(ProtocolFeeRecipientSetEvent {
+            version: SCHEMA_VERSION,
+            protocol_fee_recipient,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Sets or clears (`None`) the fee discount tiers for holders of a
+    /// designated token. Tiers must have strictly increasing thresholds.
+    pub fn set_discount_config(
+        ctx: Context<SetDiscountConfig>,
+        discount_config: Option<DiscountConfig>,
+    ) -> Result<()> {
+        if let Some(discount_config) = &discount_config {
+            require!(
+                discount_config.tiers.len() <= MAX_DISCOUNT_TIERS,
+                AmmError::InvalidDiscountConfig
+            );
+            require!(
+                discount_config
+                    .tiers
+                    .iter()
+                    .all(|tier| tier.discount_bps <= BPS_DENOMINATOR),
+                AmmError::InvalidDiscountConfig
+            );
+            require!(
+                discount_config
+                    .tiers
+                    .windows(2)
+                    .all(|pair| pair[0].min_balance < pair[1].min_balance),
+                AmmError::InvalidDiscountConfig
+            );
+        }
+
+        emit!(DiscountConfigSetEvent {
+            version: SCHEMA_VERSION,
+            mint: discount_config
+                .as_ref()
+                .map(|discount_config| discount_config.mint),
+            tiers: discount_config
+                .as_ref()
+                .map(|discount_config| discount_config.tiers.clone())
+                .unwrap_or_default(),
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+        ctx.accounts.config.discount_config = discount_config;
+
+        Ok(())
+    }
+
+    /// Admits `wallet` to a gated pool by creating its allowlist entry.
+    pub fn add_to_allowlist(ctx: Context<AddToAllowlist>, wallet: Pubkey) -> Result<()> {
+        let entry = &mut ctx.accounts.allowlist_entry;
+        entry.pool = ctx.accounts.pool.key();
+        entry.wallet = wallet;
+        entry.bump = ctx.bumps.allowlist_entry;
+
+        emit!(AllowlistUpdatedEvent {
+            version: SCHEMA_VERSION,
+            pool: entry.pool,
+            wallet,
+            allowed: true,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a wallet's allowlist entry, refunding its rent to the authority.
+    /// The wallet is rejected from the next transaction on.
+    pub fn remove_from_allowlist(ctx: Context<RemoveFromAllowlist>) -> Result<()> {
+        emit!(AllowlistUpdatedEvent {
+            version: SCHEMA_VERSION,
+            pool: ctx.accounts.pool.key(),
+            wallet: ctx.accounts.allowlist_entry.wallet,
+            allowed: false,
+            timestamp: Clock::get()?.unix_timestamp,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(total_fee_bps: u16, pool_index: u16)]
+pub struct InitializePool<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [
+            b"pool",
+            token_a_mint.key().as_ref(),
+            token_b_mint.key().as_ref(),
+            pool_index.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = lp_mint.decimals
+            == lp_mint_decimals(token_a_mint.decimals, token_b_mint.decimals)
+            @ AmmError::InvalidLpMintDecimals
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + PoolCount::INIT_SPACE,
+        seeds = [b"pool_count"],
+        bump,
+    )]
+    pub pool_count: Box<Account<'info, PoolCount>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PoolRegistryEntry::INIT_SPACE,
+        seeds = [b"registry", pool_count.count.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub registry_entry: Box<Account<'info, PoolRegistryEntry>>,
+
+    /// The config's treasury; required while it charges a pool creation fee
+    #[account(mut)]
+    pub treasury: Option<SystemAccount<'info>>,
+
+    /// Required from creators other than the admin in `Allowlist` mode
+    #[account(seeds = [b"creator_allowlist", authority.key().as_ref()], bump)]
+    pub creator_allowlist_entry: Option<Account<'info, CreatorAllowlistEntry>>,
+
+    /// Required for both mints while the config has `require_badge` set
+    #[account(seeds = [b"token_badge", token_a_mint.key().as_ref()], bump)]
+    pub token_a_badge: Option<Account<'info, TokenBadge>>,
+
+    #[account(seeds = [b"token_badge", token_b_mint.key().as_ref()], bump)]
+    pub token_b_badge: Option<Account<'info, TokenBadge>>,
+
+    /// CHECK: the LP mint's Token Metadata PDA, created and checked by the
+    /// Token Metadata program; required with it
+    #[account(mut)]
+    pub lp_metadata: Option<UncheckedAccount<'info>>,
+
+    /// Creates an SPL Token LP mint's Token Metadata when passed
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one, or whose LP mint is under the other
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(
+    total_fee_bps: u16,
+    amount_a: u64,
+    amount_b: u64,
+    pool_index: u16,
+)]
+pub struct InitializePoolWithLiquidity<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [
+            b"pool",
+            token_a_mint.key().as_ref(),
+            token_b_mint.key().as_ref(),
+            pool_index.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = token_a_mint,
+        token::authority = pool,
+    )]
+    pub token_a_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = token_b_mint,
+        token::authority = pool,
+    )]
+    pub token_b_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        mint::authority = pool,
+        constraint = lp_mint.decimals
+            == lp_mint_decimals(token_a_mint.decimals, token_b_mint.decimals)
+            @ AmmError::InvalidLpMintDecimals
+    )]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut, token::mint = token_a_mint)]
+    pub user_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = token_b_mint)]
+    pub user_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = lp_mint)]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + PoolCount::INIT_SPACE,
+        seeds = [b"pool_count"],
+        bump,
+    )]
+    pub pool_count: Box<Account<'info, PoolCount>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PoolRegistryEntry::INIT_SPACE,
+        seeds = [b"registry", pool_count.count.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub registry_entry: Box<Account<'info, PoolRegistryEntry>>,
+
+    /// The config's treasury; required while it charges a pool creation fee
+    #[account(mut)]
+    pub treasury: Option<SystemAccount<'info>>,
+
+    /// Required from creators other than the admin in `Allowlist` mode
+    #[account(seeds = [b"creator_allowlist", authority.key().as_ref()], bump)]
+    pub creator_allowlist_entry: Option<Account<'info, CreatorAllowlistEntry>>,
+
+    /// Required for both mints while the config has `require_badge` set
+    #[account(seeds = [b"token_badge", token_a_mint.key().as_ref()], bump)]
+    pub token_a_badge: Option<Account<'info, TokenBadge>>,
+
+    #[account(seeds = [b"token_badge", token_b_mint.key().as_ref()], bump)]
+    pub token_b_badge: Option<Account<'info, TokenBadge>>,
+
+    /// CHECK: the LP mint's Token Metadata PDA, created and checked by the
+    /// Token Metadata program; required with it
+    #[account(mut)]
+    pub lp_metadata: Option<UncheckedAccount<'info>>,
+
+    /// Creates an SPL Token LP mint's Token Metadata when passed
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one, or whose LP mint is under the other
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// Token authority for the deposit; pays no rent, so a relayer can act as
+    /// the transaction fee payer
+    pub owner: Signer<'info>,
+
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub user_lp: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives the LP tokens instead of `user_lp` when present, e.g. a lock
+    /// escrow; the deposit still comes from `owner`
+    #[account(mut, token::mint = lp_mint)]
+    pub lp_recipient: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The owner's allowlist entry; required when deposits are gated
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>,
+
+    /// Program config naming the protocol fee recipient and treasury;
+    /// required with the protocol fee accounts when the pool charges a
+    /// deposit fee, and for the treasury's deposits to count as
+    /// protocol-owned
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Box<Account<'info, GlobalConfig>>>,
+
+    /// The protocol fee recipient's token A account
+    #[account(mut)]
+    pub protocol_fee_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The protocol fee recipient's token B account
+    #[account(mut)]
+    pub protocol_fee_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The pool's statistics; the deposit counts in them when present
+    #[account(mut, seeds = [b"stats", pool.key().as_ref()], bump = stats.bump)]
+    pub stats: Option<Box<Account<'info, PoolStats>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AddLiquidityNative<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(address = native_mint::ID)]
+    pub native_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// Temporary wSOL account, created for this deposit and closed before it
+    /// ends
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"wsol", user.key().as_ref()],
+        bump,
+        token::mint = native_mint,
+        token::authority = user,
+    )]
+    pub wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The user's account for the non-SOL side of the pool
+    #[account(mut)]
+    pub user_token: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The user's allowlist entry; required when deposits are gated
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>,
+
+    /// Program config naming the protocol fee recipient and treasury;
+    /// required with the protocol fee accounts when the pool charges a
+    /// deposit fee, and for the treasury's deposits to count as
+    /// protocol-owned
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Box<Account<'info, GlobalConfig>>>,
+
+    /// The protocol fee recipient's token A account
+    #[account(mut)]
+    pub protocol_fee_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The protocol fee recipient's token B account
+    #[account(mut)]
+    pub protocol_fee_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The pool's statistics; the deposit counts in them when present
+    #[account(mut, seeds = [b"stats", pool.key().as_ref()], bump = stats.bump)]
+    pub stats: Option<Box<Account<'info, PoolStats>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// Token authority for the input and fee; pays no rent, so a relayer can
+    /// act as the transaction fee payer
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub token_in_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub token_out_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub user_token_in: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user_token_out: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub pool_token_in: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub pool_token_out: InterfaceAccount<'info, TokenAccount>,
+
+    /// Fee vault for the input mint; required on `BuybackBurn` pools only
+    #[account(mut)]
+    pub owner_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Receives the output instead of `user_token_out` when present
+    #[account(
+        mut,
+        constraint = recipient_token_out.mint == token_out_mint.key()
+            @ AmmError::InvalidRecipientMint,
+    )]
+    pub recipient_token_out: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The caller's allowlist entry; required when the pool is gated
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>,
+
+    /// Pays the rent of `launch_purchase` when it is created
+    #[account(mut)]
+    pub payer: Option<Signer<'info>>,
+
+    /// The owner's purchases during the launch window, created on first use;
+    /// needed to buy the launched token while the window is open
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + LaunchPurchase::INIT_SPACE,
+        seeds = [b"launch", pool.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub launch_purchase: Option<Account<'info, LaunchPurchase>>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet. Holds the pause flag and the fee discount tiers
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    /// The owner's account in the discount mint; its balance picks the fee
+    /// discount tier
+    pub discount_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: the pool's swap hook, called after the swap with the remaining
+    /// accounts; required when the pool has one
+    #[account(address = pool.hook_program @ AmmError::MissingHookProgram)]
+    pub hook_program: Option<UncheckedAccount<'info>>,
+
+    /// The pool's oracle observations; the swap records one when present
+    #[account(
+        mut,
+        seeds = [b"observations", pool.key().as_ref()],
+        bump = observations.bump,
+    )]
+    pub observations: Option<Account<'info, Observations>>,
+
+    /// The pool's statistics; the swap adds to them when present
+    #[account(mut, seeds = [b"stats", pool.key().as_ref()], bump = stats.bump)]
+    pub stats: Option<Box<Account<'info, PoolStats>>>,
+
+    /// The pool's oracle guard; required, with both of its price feeds, when
+    /// the pool has one
+    #[account(
+        seeds = [b"oracle_guard", pool.key().as_ref()],
+        bump = oracle_guard.bump,
+    )]
+    pub oracle_guard: Option<Box<Account<'info, OracleGuard>>>,
+
+    /// CHECK: the guard's price feed for token A, checked against it and
+    /// read by `OracleKind::read_price`
+    pub price_feed_a: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: the guard's price feed for token B, as `price_feed_a`
+    pub price_feed_b: Option<UncheckedAccount<'info>>,
+
+    /// Required for a swap with a memo
+    pub memo_program: Option<Program<'info, Memo>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Option<Program<'info, System>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(amount_in: u64, min_amount_out: u64, a_to_b: bool)]
+pub struct SwapV2<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, token::mint = pool.token_a_mint)]
+    pub user_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = pool.token_b_mint)]
+    pub user_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Fee vault on `BuybackBurn` pools; holds the input mint for the chosen
+    /// direction
+    #[account(
+        mut,
+        constraint = owner_token_account.mint
+            == if a_to_b { pool.token_a_mint } else { pool.token_b_mint }
+            @ AmmError::InvalidFeeAccount,
+    )]
+    pub owner_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The caller's allowlist entry; required when the pool is gated
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    /// The pool's oracle observations; the swap records one when present
+    #[account(
+        mut,
+        seeds = [b"observations", pool.key().as_ref()],
+        bump = observations.bump,
+    )]
+    pub observations: Option<Account<'info, Observations>>,
+
+    /// The pool's statistics; the swap adds to them when present
+    #[account(mut, seeds = [b"stats", pool.key().as_ref()], bump = stats.bump)]
+    pub stats: Option<Box<Account<'info, PoolStats>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SwapAndCreate<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    /// Token authority for the input and fee
+    pub owner: Signer<'info>,
+
+    /// Pays the rent if the output account is created; may be `owner`
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_in_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub token_out_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub user_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The owner's associated token account for the output mint
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = token_out_mint,
+        associated_token::authority = owner,
+    )]
+    pub user_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub pool_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub pool_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub owner_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The caller's allowlist entry; required when the pool is gated
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    /// The pool's oracle observations; the swap records one when present
+    #[account(
+        mut,
+        seeds = [b"observations", pool.key().as_ref()],
+        bump = observations.bump,
+    )]
+    pub observations: Option<Account<'info, Observations>>,
+
+    /// The pool's statistics; the swap adds to them when present
+    #[account(mut, seeds = [b"stats", pool.key().as_ref()], bump = stats.bump)]
+    pub stats: Option<Box<Account<'info, PoolStats>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SwapNative<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(address = native_mint::ID)]
+    pub native_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub token_in_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_out_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// Temporary wSOL account, created for this swap and closed before it ends
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"wsol", user.key().as_ref()],
+        bump,
+        token::mint = native_mint,
+        token::authority = user,
+    )]
+    pub wsol_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The user's account for the non-SOL side of the pool
+    #[account(mut)]
+    pub user_token: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub pool_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub pool_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub owner_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The caller's allowlist entry; required when the pool is gated
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SwapBatch<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SwapSplit<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub user_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_in_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_out_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteSwap<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteAddLiquidity<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// The mints are needed for their decimals, which the LP math normalizes by
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteRemoveLiquidity<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub user_lp: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives token A instead of `user_token_a` when present
+    #[account(
+        mut,
+        constraint = recipient_token_a.mint == pool.token_a_mint
+            @ AmmError::InvalidRecipientMint,
+    )]
+    pub recipient_token_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Receives token B instead of `user_token_b` when present
+    #[account(
+        mut,
+        constraint = recipient_token_b.mint == pool.token_b_mint
+            @ AmmError::InvalidRecipientMint,
+    )]
+    pub recipient_token_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(address = native_mint::ID)]
+    pub native_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    /// Temporary wSOL account for `unwrap_sol`, created for this withdrawal
+    /// and closed before it ends
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"wsol", user.key().as_ref()],
+        bump,
+        token::mint = native_mint,
+        token::authority = user,
+    )]
+    pub wsol_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Program config naming the treasury; required for the treasury's
+    /// withdrawals to come off the protocol-owned amount
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Box<Account<'info, GlobalConfig>>>,
+
+    /// The pool's statistics; the withdrawal counts in them when present
+    #[account(mut, seeds = [b"stats", pool.key().as_ref()], bump = stats.bump)]
+    pub stats: Option<Box<Account<'info, PoolStats>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Option<Program<'info, System>>,
+}
+
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub borrower: Signer<'info>,
+
+    #[account(mut)]
+    pub pool_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = pool_token.mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub borrower_token: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: the instructions sysvar, used to find the matching flash_repay
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet. Holds the pause flag
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub repayer: Signer<'info>,
+
+    #[account(mut)]
+    pub pool_token: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = pool_token.mint)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub repayer_token: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SyncReserves<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct Skim<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token_a_mint)]
+    pub to_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token_b_mint)]
+    pub to_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct SkimSurplus<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    /// Owned by the config's treasury
+    #[account(mut, token::mint = pool.token_a_mint)]
+    pub treasury_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    /// Owned by the config's treasury
+    #[account(mut, token::mint = pool.token_b_mint)]
+    pub treasury_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct ResetPool<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token_a_mint)]
+    pub to_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token_b_mint)]
+    pub to_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct Donate<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub donor: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub donor_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub donor_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateLiquidity<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            source_pool.token_a_mint.as_ref(),
+            source_pool.token_b_mint.as_ref(),
+            source_pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = source_pool.bump,
+    )]
+    pub source_pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            destination_pool.token_a_mint.as_ref(),
+            destination_pool.token_b_mint.as_ref(),
+            destination_pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = destination_pool.bump,
+        constraint = destination_pool.key() != source_pool.key()
+            && destination_pool.token_a_mint == source_pool.token_a_mint
+            && destination_pool.token_b_mint == source_pool.token_b_mint
+            @ AmmError::InvalidMigration,
+    )]
+    pub destination_pool: Box<Account<'info, Pool>>,
+
+    #[account(address = source_pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(address = source_pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// Receives the withdrawal, funds the deposit and keeps any leftover
+    #[account(mut, token::mint = token_a_mint)]
+    pub user_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = token_b_mint)]
+    pub user_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = source_pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub source_pool_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = source_pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub source_pool_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = source_pool.lp_mint)]
+    pub source_lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub user_source_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = destination_pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub destination_pool_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = destination_pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub destination_pool_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = destination_pool.lp_mint)]
+    pub destination_lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub user_destination_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The user's allowlist entry on the destination pool; required when its
+    /// deposits are gated
+    pub destination_allowlist_entry: Option<Account<'info, AllowlistEntry>>,
+
+    /// Program config naming the protocol fee recipient; required with the
+    /// protocol fee accounts when the destination pool charges a deposit fee
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Box<Account<'info, GlobalConfig>>>,
+
+    /// The protocol fee recipient's token A account
+    #[account(mut)]
+    pub protocol_fee_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The protocol fee recipient's token B account
+    #[account(mut)]
+    pub protocol_fee_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+#[instruction(lock_index: u64)]
+pub struct LockLiquidity<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + LiquidityLock::INIT_SPACE,
+        seeds = [
+            b"lock",
+            pool.key().as_ref(),
+            owner.key().as_ref(),
+            lock_index.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub lock: Box<Account<'info, LiquidityLock>>,
+
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// Escrow for the locked LP tokens, owned by the lock PDA
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"lock_vault", lock.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = lock,
+    )]
+    pub lock_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = lp_mint)]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendLock<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(mut, has_one = owner @ AmmError::Unauthorized)]
+    pub lock: Account<'info, LiquidityLock>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockLiquidity<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner @ AmmError::Unauthorized,
+        close = owner,
+        seeds = [
+            b"lock",
+            lock.pool.as_ref(),
+            owner.key().as_ref(),
+            lock.lock_index.to_le_bytes().as_ref(),
+        ],
+        bump = lock.bump,
+    )]
+    pub lock: Box<Account<'info, LiquidityLock>>,
+
+    #[account(mut, seeds = [b"lock_vault", lock.key().as_ref()], bump)]
+    pub lock_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(address = lock_vault.mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, token::mint = lock_vault.mint)]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct BurnLpPermanently<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Created by the first burn on the pool, paid for by that burner
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + BurnedLiquidity::INIT_SPACE,
+        seeds = [b"burned", pool.key().as_ref()],
+        bump,
+    )]
+    pub burned_liquidity: Box<Account<'info, BurnedLiquidity>>,
+
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, token::mint = lp_mint)]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeFarm<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Farm::INIT_SPACE,
+        seeds = [b"farm", pool.key().as_ref()],
+        bump,
+    )]
+    pub farm: Box<Account<'info, Farm>>,
+
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub reward_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// Escrow for staked LP tokens, owned by the farm PDA
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"farm_stake", farm.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = farm,
+    )]
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Reward tokens paid out to stakers, owned by the farm PDA
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"farm_reward", farm.key().as_ref()],
+        bump,
+        token::mint = reward_mint,
+        token::authority = farm,
+        token::token_program = reward_token_program,
+    )]
+    pub reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The reward mint's token program, `token_program` being the LP mint's
+    pub reward_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct SetEmissionSchedule<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, has_one = authority @ AmmError::Unauthorized)]
+    pub farm: Box<Account<'info, Farm>>,
+}
+
+#[derive(Accounts)]
+pub struct FundFarm<'info> {
+    pub funder: Signer<'info>,
+
+    #[account(has_one = reward_vault @ AmmError::InvalidPoolTokenAccount)]
+    pub farm: Account<'info, Farm>,
+
+    #[account(address = farm.reward_mint)]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub funder_reward_token: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut, has_one = stake_vault @ AmmError::InvalidPoolTokenAccount)]
+    pub farm: Box<Account<'info, Farm>>,
+
+    #[account(address = farm.lp_mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + StakePosition::INIT_SPACE,
+        seeds = [b"stake", farm.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub position: Box<Account<'info, StakePosition>>,
+
+    #[account(mut)]
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = farm.lp_mint)]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Unstake<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(mut, has_one = stake_vault @ AmmError::InvalidPoolTokenAccount)]
+    pub farm: Box<Account<'info, Farm>>,
+
+    #[account(address = farm.lp_mint)]
+    pub lp_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"stake", farm.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+    )]
+    pub position: Box<Account<'info, StakePosition>>,
+
+    #[account(mut)]
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = farm.lp_mint)]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(mut, has_one = reward_vault @ AmmError::InvalidPoolTokenAccount)]
+    pub farm: Box<Account<'info, Farm>>,
+
+    #[account(address = farm.reward_mint)]
+    pub reward_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"stake", farm.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+    )]
+    pub position: Box<Account<'info, StakePosition>>,
+
+    #[account(mut)]
+    pub reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = farm.reward_mint)]
+    pub user_reward_token: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(change: ParamChange)]
+pub struct ProposeParamChange<'info> {
+    #[account(
+        mut,
+        constraint = change.role(&pool) == authority.key() @ pool.role_error(change.role(&pool)),
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// The pool authority, or the fee authority for a fee recipient change
+    pub authority: Signer<'info>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyParamChange<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelParamChange<'info> {
+    #[account(
+        mut,
+        constraint = pool.pending_change_role() == authority.key()
+            @ pool.role_error(pool.pending_change_role()),
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// Whoever may propose the pending change
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(side: VaultSide)]
+pub struct MigrateVault<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(address = pool.mint(side))]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, address = pool.vault(side) @ AmmError::InvalidPoolTokenAccount)]
+    pub old_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Replaces `old_vault`, owned by the pool PDA
+    #[account(
+        init,
+        payer = authority,
+        seeds = [
+            b"vault",
+            pool.key().as_ref(),
+            mint.key().as_ref(),
+            pool.vault_migrations.to_le_bytes().as_ref(),
+        ],
+        bump,
+        token::mint = mint,
+        token::authority = pool,
+    )]
+    pub new_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateInsuranceVaults<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// Holds the token A insurance fund, owned by the pool PDA
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"insurance", pool.key().as_ref(), token_a_mint.key().as_ref()],
+        bump,
+        token::mint = token_a_mint,
+        token::authority = pool,
+    )]
+    pub insurance_vault_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Holds the token B insurance fund, owned by the pool PDA
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"insurance", pool.key().as_ref(), token_b_mint.key().as_ref()],
+        bump,
+        token::mint = token_b_mint,
+        token::authority = pool,
+        token::token_program = token_b_program,
+    )]
+    pub insurance_vault_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Token B's token program, `token_program` being token A's; the same
+    /// program unless the pool pairs an SPL Token mint with a Token-2022 one
+    pub token_b_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, recipient: Pubkey)]
+pub struct DisburseInsurance<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    pub authority: Signer<'info>,
+
+    /// The insurance vault in `recipient_token`'s mint
+    #[account(
+        mut,
+        constraint = (insurance_vault.key() == pool.insurance_vault_a
+            || insurance_vault.key() == pool.insurance_vault_b)
+            && insurance_vault.key() != Pubkey::default() @ AmmError::InvalidInsuranceVault,
+    )]
+    pub insurance_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(address = insurance_vault.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        address = recipient,
+        token::mint = insurance_vault.mint,
+    )]
+    pub recipient_token: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct GetInsuranceBalance<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(address = pool.insurance_vault_a @ AmmError::InvalidInsuranceVault)]
+    pub insurance_vault_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = pool.insurance_vault_b @ AmmError::InvalidInsuranceVault)]
+    pub insurance_vault_b: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeObservations<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Observations::space(1),
+        seeds = [b"observations", pool.key().as_ref()],
+        bump,
+    )]
+    pub observations: Account<'info, Observations>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_size: u16)]
+pub struct IncreaseObservationCardinality<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"observations", observations.pool.as_ref()],
+        bump = observations.bump,
+        realloc = 8 + Observations::space(new_size),
+        realloc::payer = payer,
+        realloc::zero = false,
+    )]
+    pub observations: Account<'info, Observations>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetSpotPrice<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct GetTwap<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(seeds = [b"observations", pool.key().as_ref()], bump = observations.bump)]
+    pub observations: Account<'info, Observations>,
+}
+
+#[derive(Accounts)]
+pub struct InitStats<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PoolStats::INIT_SPACE,
+        seeds = [b"stats", pool.key().as_ref()],
+        bump,
+    )]
+    pub stats: Account<'info, PoolStats>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetStats<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(seeds = [b"stats", pool.key().as_ref()], bump = stats.bump)]
+    pub stats: Account<'info, PoolStats>,
+}
+
+#[derive(Accounts)]
+pub struct GetProtocolLiquidity<'info> {
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePool<'info> {
+    /// CHECK: an old-layout pool that `Account<Pool>` cannot deserialize;
+    /// `migrate_pool` checks the discriminator
+    #[account(mut, owner = crate::ID @ AmmError::InvalidPoolAccount)]
+    pub pool: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeRecipients<'info> {
+    #[account(
+        mut,
+        constraint = pool.fee_authority() == authority.key()
+            @ pool.role_error(pool.fee_authority()),
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// The pool's fee authority
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeSplit<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        constraint = pool.pending_authority == new_authority.key() @ AmmError::NotPendingAuthority,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAuthorityTransfer<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RenounceAuthority<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetDepositFee<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetWithdrawalFee<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMaxImbalance<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetCircuitBreaker<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTradeLimits<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMinTradeAmountIn<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPoolMetadata<'info> {
+    #[account(has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + PoolMetadata::INIT_SPACE,
+        seeds = [b"metadata", pool.key().as_ref()],
+        bump,
+    )]
+    pub metadata: Account<'info, PoolMetadata>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetOracleGuard<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + OracleGuard::INIT_SPACE,
+        seeds = [b"oracle_guard", pool.key().as_ref()],
+        bump,
+    )]
+    pub oracle_guard: Account<'info, OracleGuard>,
+
+    /// CHECK: price feed for token A, read by `OracleKind::read_price`
+    pub price_feed_a: UncheckedAccount<'info>,
+
+    /// CHECK: price feed for token B, read by `OracleKind::read_price`
+    pub price_feed_b: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetGating<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTradeStart<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CommitSwap<'info> {
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// One outstanding commitment per owner and pool
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + SwapCommit::INIT_SPACE,
+        seeds = [b"commit", pool.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub swap_commit: Account<'info, SwapCommit>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(amount_in: u64, min_amount_out: u64, a_to_b: bool)]
+pub struct RevealSwap<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        has_one = pool,
+        has_one = owner,
+        seeds = [b"commit", pool.key().as_ref(), owner.key().as_ref()],
+        bump = swap_commit.bump,
+    )]
+    pub swap_commit: Box<Account<'info, SwapCommit>>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, token::mint = pool.token_a_mint)]
+    pub user_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = pool.token_b_mint)]
+    pub user_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Fee vault on `BuybackBurn` pools; holds the input mint for the
+    /// revealed direction
+    #[account(
+        mut,
+        constraint = owner_token_account.mint
+            == if a_to_b { pool.token_a_mint } else { pool.token_b_mint }
+            @ AmmError::InvalidFeeAccount,
+    )]
+    pub owner_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// The caller's allowlist entry; required when the pool is gated
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimSwapCommit<'info> {
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+        seeds = [b"commit", swap_commit.pool.as_ref(), owner.key().as_ref()],
+        bump = swap_commit.bump,
+    )]
+    pub swap_commit: Account<'info, SwapCommit>,
+
+    /// CHECK: receives the bond and rent; must be the commitment's owner
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_index: u64)]
+pub struct CreateOrder<'info> {
     #[account(
         seeds = [
             b"pool",
             pool.token_a_mint.as_ref(),
             pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
         ],
         bump = pool.bump,
     )]
-    pub pool: Account<'info, Pool>,
+    pub pool: Box<Account<'info, Pool>>,
 
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub maker: Signer<'info>,
+
+    #[account(
+        init,
+        payer = maker,
+        space = 8 + Order::INIT_SPACE,
+        seeds = [
+            b"order",
+            pool.key().as_ref(),
+            maker.key().as_ref(),
+            order_index.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
 
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
+    #[account(
+        constraint = token_in_mint.key() == pool.token_a_mint
+            || token_in_mint.key() == pool.token_b_mint
+            @ AmmError::InvalidOrderMint,
+    )]
+    pub token_in_mint: Box<InterfaceAccount<'info, Mint>>,
 
-    #[account(mut)]
-    pub user_token_a: Account<'info, TokenAccount>,
+    /// Escrow for the order's input, owned by the order PDA
+    #[account(
+        init,
+        payer = maker,
+        seeds = [b"order_vault", order.key().as_ref()],
+        bump,
+        token::mint = token_in_mint,
+        token::authority = order,
+    )]
+    pub order_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, token::mint = token_in_mint)]
+    pub maker_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FillOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        close = maker,
+        has_one = pool,
+        has_one = maker,
+    )]
+    pub order: Box<Account<'info, Order>>,
 
+    /// CHECK: receives the escrow's and the order's rent; must be the order's
+    /// maker
     #[account(mut)]
-    pub user_token_b: Account<'info, TokenAccount>,
+    pub maker: UncheckedAccount<'info>,
+
+    #[account(mut, seeds = [b"order_vault", order.key().as_ref()], bump)]
+    pub order_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(address = order.token_in_mint)]
+    pub token_in_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_out_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// Receives the output; must belong to the maker
+    #[account(mut, token::authority = maker)]
+    pub maker_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(mut)]
-    pub pool_token_a: Account<'info, TokenAccount>,
+    pub pool_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(mut)]
-    pub pool_token_b: Account<'info, TokenAccount>,
+    pub pool_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(mut)]
-    pub lp_mint: Account<'info, Mint>,
+    pub owner_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub filler: Signer<'info>,
+
+    /// Receives the filler reward, in the order's input mint
+    #[account(mut, token::mint = order.token_in_mint)]
+    pub filler_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
 
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(mut, close = maker, has_one = maker)]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: receives the escrow's and the order's rent; must be the order's
+    /// maker
     #[account(mut)]
-    pub user_lp: Account<'info, TokenAccount>,
+    pub maker: UncheckedAccount<'info>,
+
+    #[account(mut, seeds = [b"order_vault", order.key().as_ref()], bump)]
+    pub order_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(address = order.token_in_mint)]
+    pub token_in_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = order.token_in_mint,
+        token::authority = maker,
+    )]
+    pub maker_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The maker, or anyone once the order has expired
+    pub authority: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct Swap<'info> {
+pub struct SetSwapHook<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPoolStatus<'info> {
+    #[account(
+        mut,
+        constraint = pool.authority == authority.key() || pool.pause_guardian() == authority.key()
+            @ pool.authority_error(),
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// The pool authority, or its pause guardian to pause more operations
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPoolRoles<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub authority: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    /// Required when the config has a guardian
+    pub guardian: Option<Signer<'info>>,
+
+    /// CHECK: the program config PDA, read with GlobalConfig::load; it may not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token_a_mint)]
+    pub destination_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token_b_mint)]
+    pub destination_b: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePool<'info> {
     #[account(
+        mut,
+        close = authority,
         seeds = [
             b"pool",
             pool.token_a_mint.as_ref(),
             pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
         ],
         bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
     )]
     pub pool: Account<'info, Pool>,
 
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    /// Required once the pool has had buyback fee vaults created
+    #[account(mut, address = pool.fee_vault_a @ AmmError::InvalidFeeAccount)]
+    pub fee_vault_a: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool.fee_vault_b @ AmmError::InvalidFeeAccount)]
+    pub fee_vault_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Required unless the pool predates the registry
+    #[account(mut, address = pool.registry_entry @ AmmError::InvalidRegistryEntry)]
+    pub registry_entry: Option<Account<'info, PoolRegistryEntry>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + GlobalConfig::INIT_SPACE,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key())
+            @ AmmError::Unauthorized,
+    )]
+    pub program: Program<'info, crate::program::NewSendSwap>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ AmmError::Unauthorized,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ AmmError::Unauthorized,
+    )]
+    pub config: Account<'info, GlobalConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFeeTiers<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ AmmError::Unauthorized,
+    )]
+    pub config: Account<'info, GlobalConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(creator: Pubkey)]
+pub struct AddPoolCreator<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ AmmError::Unauthorized,
+    )]
+    pub config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + CreatorAllowlistEntry::INIT_SPACE,
+        seeds = [b"creator_allowlist", creator.as_ref()],
+        bump,
+    )]
+    pub creator_allowlist_entry: Account<'info, CreatorAllowlistEntry>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemovePoolCreator<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ AmmError::Unauthorized,
+    )]
+    pub config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"creator_allowlist", creator_allowlist_entry.creator.as_ref()],
+        bump = creator_allowlist_entry.bump,
+    )]
+    pub creator_allowlist_entry: Account<'info, CreatorAllowlistEntry>,
+}
+
+#[derive(Accounts)]
+pub struct IssueTokenBadge<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ AmmError::Unauthorized,
+    )]
+    pub config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + TokenBadge::INIT_SPACE,
+        seeds = [b"token_badge", mint.key().as_ref()],
+        bump,
+    )]
+    pub token_badge: Account<'info, TokenBadge>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeTokenBadge<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ AmmError::Unauthorized,
+    )]
+    pub config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        close = admin,
+        seeds = [b"token_badge", token_badge.mint.as_ref()],
+        bump = token_badge.bump,
+    )]
+    pub token_badge: Account<'info, TokenBadge>,
+}
+
+#[derive(Accounts)]
+pub struct SetDiscountConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ AmmError::Unauthorized,
+    )]
+    pub config: Account<'info, GlobalConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetProtocolFeeRecipient<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ AmmError::Unauthorized,
+    )]
+    pub config: Account<'info, GlobalConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        constraint = pool.fee_recipient == fee_recipient.key()
+            || pool.fee_authority() == fee_recipient.key() @ AmmError::NotFeeRecipient,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// The pool's fee recipient, or its fee authority
+    pub fee_recipient: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token_a_mint)]
+    pub recipient_token_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token_b_mint)]
+    pub recipient_token_b: InterfaceAccount<'info, TokenAccount>,
+
+    /// Required while the pool has an insurance share
+    #[account(mut, address = pool.insurance_vault_a @ AmmError::InvalidInsuranceVault)]
+    pub insurance_vault_a: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut, address = pool.insurance_vault_b @ AmmError::InvalidInsuranceVault)]
+    pub insurance_vault_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeMode<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+        has_one = authority @ pool.authority_error(),
+    )]
+    pub pool: Box<Account<'info, Pool>>,
 
     #[account(mut)]
-    pub token_in_mint: Account<'info, Mint>,
+    pub authority: Signer<'info>,
+
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// Holds token A fees in buyback mode, owned by the pool PDA
+    #[account(
+        init_if_needed,
+        payer = authority,
+        seeds = [b"fee_vault", pool.key().as_ref(), token_a_mint.key().as_ref()],
+        bump,
+        token::mint = token_a_mint,
+        token::authority = pool,
+    )]
+    pub fee_vault_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Holds token B fees in buyback mode, owned by the pool PDA
+    #[account(
+        init_if_needed,
+        payer = authority,
+        seeds = [b"fee_vault", pool.key().as_ref(), token_b_mint.key().as_ref()],
+        bump,
+        token::mint = token_b_mint,
+        token::authority = pool,
+        token::token_program = token_b_program,
+    )]
+    pub fee_vault_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Token B's token program, `token_program` being token A's; the same
+    /// program unless the pool pairs an SPL Token mint with a Token-2022 one
+    pub token_b_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteBuyback<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool.pool_index.to_le_bytes().as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut, address = pool.buyback_mint @ AmmError::InvalidBuybackMint)]
+    pub buyback_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// The pool's other mint, the one the fees are spent in
+    #[account(
+        address = if pool.buyback_mint == pool.token_b_mint {
+            pool.token_a_mint
+        } else {
+            pool.token_b_mint
+        } @ AmmError::InvalidBuybackMint
+    )]
+    pub fee_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut, address = pool.fee_vault_a @ AmmError::InvalidFeeAccount)]
+    pub fee_vault_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    #[account(mut)]
-    pub token_out_mint: Account<'info, Mint>,
+    #[account(mut, address = pool.fee_vault_b @ AmmError::InvalidFeeAccount)]
+    pub fee_vault_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    #[account(mut)]
-    pub user_token_in: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.token_a_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    #[account(mut)]
-    pub user_token_out: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.token_b_account @ AmmError::InvalidPoolTokenAccount)]
+    pub pool_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    #[account(mut)]
-    pub pool_token_in: Account<'info, TokenAccount>,
+    /// CHECK: the program config PDA, read with `GlobalConfig::load`; it may
+    /// not exist yet
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
 
-    #[account(mut)]
-    pub pool_token_out: Account<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// The token program `token_program` isn't, for a pool pairing an SPL
+    /// Token mint with a Token-2022 one
+    pub token_program_b: Option<Interface<'info, TokenInterface>>,
+}
 
-    #[account(mut)]
-    pub owner_token_account: Account<'info, TokenAccount>,
+#[derive(Accounts)]
+pub struct SetLaunchLimit<'info> {
+    #[account(mut, has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
 
-    pub token_program: Program<'info, Token>,
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct RemoveLiquidity<'info> {
+pub struct CloseLaunchPurchase<'info> {
+    pub pool: Account<'info, Pool>,
+
     #[account(
-        seeds = [
-            b"pool",
-            pool.token_a_mint.as_ref(),
-            pool.token_b_mint.as_ref(),
-        ],
-        bump = pool.bump,
+        mut,
+        close = payer,
+        has_one = pool,
+        has_one = payer,
+        seeds = [b"launch", pool.key().as_ref(), launch_purchase.wallet.as_ref()],
+        bump = launch_purchase.bump,
     )]
-    pub pool: Account<'info, Pool>,
+    pub launch_purchase: Account<'info, LaunchPurchase>,
 
+    /// CHECK: receives the rent; must be the account that paid it
     #[account(mut)]
-    pub user: Signer<'info>,
-
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
+    pub payer: UncheckedAccount<'info>,
+}
 
-    #[account(mut)]
-    pub user_token_a: Account<'info, TokenAccount>,
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct AddToAllowlist<'info> {
+    #[account(has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
 
     #[account(mut)]
-    pub user_token_b: Account<'info, TokenAccount>,
+    pub authority: Signer<'info>,
 
-    #[account(mut)]
-    pub pool_token_a: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + AllowlistEntry::INIT_SPACE,
+        seeds = [b"allowlist", pool.key().as_ref(), wallet.as_ref()],
+        bump,
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
 
-    #[account(mut)]
-    pub pool_token_b: Account<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+}
 
-    #[account(mut)]
-    pub lp_mint: Account<'info, Mint>,
+#[derive(Accounts)]
+pub struct RemoveFromAllowlist<'info> {
+    #[account(has_one = authority @ pool.authority_error())]
+    pub pool: Account<'info, Pool>,
 
     #[account(mut)]
-    pub user_lp: Account<'info, TokenAccount>,
+    pub authority: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"allowlist", pool.key().as_ref(), allowlist_entry.wallet.as_ref()],
+        bump = allowlist_entry.bump,
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
 }
 
 #[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
 pub struct Pool {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub token_a_mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub token_b_mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub token_a_account: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub token_b_account: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub lp_mint: Pubkey,
+    /// The swap fee as a fraction, on pools created before `total_fee_bps`;
+    /// zero once the fee is in bps, when only `total_fee_bps` applies
     pub fee_numerator: u64,
     pub fee_denominator: u64,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub authority: Pubkey,
     pub bump: u8,
+    /// Set while a flash loan is outstanding; blocks swaps and liquidity changes
+    pub locked: bool,
+    pub flash_loan_token_a: bool,
+    /// Vault balance the outstanding flash loan must restore (pre-balance + fee)
+    pub flash_loan_required_balance: u64,
+    pub flash_loan_fee: u64,
+    /// Reserves as tracked by program flows; vault balances above these are
+    /// surplus that `sync` credits to LPs or `skim` sweeps out
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    /// Distinguishes pools on the same mint pair (e.g. different fee tiers);
+    /// part of the pool PDA seeds
+    pub pool_index: u16,
+    /// Only allowlisted wallets may swap
+    pub gated: bool,
+    /// Only allowlisted wallets may add liquidity
+    pub gated_liquidity: bool,
+    /// Swaps fail with `TradingNotStarted` before this unix timestamp;
+    /// liquidity can be managed at any time. Zero opens trading immediately
+    pub trade_start_timestamp: i64,
+    /// Token whose purchases are capped per wallet during the launch window
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub launch_mint: Pubkey,
+    /// Length of the launch window, from `trade_start_timestamp`
+    pub launch_window_seconds: i64,
+    /// Most of `launch_mint` one wallet may buy during the window; zero
+    /// disables the cap
+    pub launch_max_buy: u64,
+    pub fee_mode: FeeMode,
+    /// Token bought and burned with the fees in `BuybackBurn` mode
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub buyback_mint: Pubkey,
+    /// Fee vaults for `BuybackBurn` mode, owned by the pool PDA
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub fee_vault_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub fee_vault_b: Pubkey,
+    /// Program called after every swap, or the default pubkey for none
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub hook_program: Pubkey,
+    /// Saved as set while the swap hook runs, so the hook can tell it's
+    /// called from a swap; see `invoke_swap_hook`
+    pub in_hook: bool,
+    /// Largest excess share of an `add_liquidity_imbalanced` deposit side,
+    /// in basis points
+    pub max_imbalance_bps: u16,
+    /// Share of every withdrawal left in the pool, in basis points
+    pub withdrawal_fee_bps: u16,
+    /// Share of every deposit paid to the protocol, in basis points
+    pub deposit_fee_bps: u16,
+    /// Key proposed by `propose_authority` that may `accept_authority`, or
+    /// the default pubkey when no transfer is pending
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pending_authority: Pubkey,
+    /// `POOL_STATUS_*_PAUSED` bits of the operations paused by
+    /// `set_pool_status`; zero, the default, leaves everything enabled
+    pub status: u8,
+    /// Wallet that may collect the protocol fees in `Owner` fee mode; the
+    /// creating authority until changed with `propose_param_change`
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub fee_recipient: Pubkey,
+    /// Share of each swap fee left in the vault for the LPs, in basis points
+    pub lp_fee_bps: u16,
+    /// Share of each swap fee paid out in the fee mode, in basis points;
+    /// `lp_fee_bps + protocol_fee_bps` is always 10,000
+    pub protocol_fee_bps: u16,
+    /// Protocol fees held in the vaults for `collect_protocol_fees`; not part
+    /// of the reserves that price swaps and back the LP tokens
+    pub protocol_fees_owed_a: u64,
+    pub protocol_fees_owed_b: u64,
+    /// Change scheduled by `propose_param_change`, if any
+    pub pending_change: Option<ParamChange>,
+    /// Slot from which `pending_change` may be applied
+    pub pending_change_slot: u64,
+    /// Slot since which every operation has been paused, for the
+    /// `emergency_withdraw` grace period; zero while any is enabled
+    pub fully_paused_slot: u64,
+    /// Wallets `collect_protocol_fees` splits the protocol fees between;
+    /// empty pays them all to `fee_recipient`'s choice of accounts
+    #[max_len(MAX_FEE_RECIPIENTS)]
+    pub fee_recipients: Vec<FeeShare>,
+    /// Vaults replaced by `migrate_vault`; part of the next new vault's seeds
+    pub vault_migrations: u16,
+    /// Largest spot price move one swap may cause, in basis points; a bigger
+    /// one trips the circuit breaker, pausing swaps. Zero disables it
+    pub breaker_threshold_bps: u16,
+    /// Key that collects the protocol fees and changes who receives them, or
+    /// the default pubkey to leave that to `authority`
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub fee_authority: Pubkey,
+    /// Key that may pause operations but not unpause them, or the default
+    /// pubkey to leave that to `authority`
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pause_guardian: Pubkey,
+    /// Share of the protocol fees set aside in the insurance vaults at each
+    /// collection, in basis points; changed only through the timelock
+    pub insurance_share_bps: u16,
+    /// Vaults created by `create_insurance_vaults`, or the default pubkey
+    /// before then
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub insurance_vault_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub insurance_vault_b: Pubkey,
+    /// Sums of the price of A in B and of B in A, as Q64.64, times the
+    /// seconds each held, for TWAPs between two samples. They wrap on
+    /// overflow, so consumers take differences modulo 2^128
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub price_a_cumulative: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub price_b_cumulative: u128,
+    /// When the cumulative prices were last brought up to date
+    pub last_oracle_timestamp: i64,
+    /// The `PoolStats` totals as the pool kept them before they had their
+    /// own account; nothing adds to them any more, and `init_stats` moves
+    /// them over, leaving zero
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub cumulative_volume_a: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub cumulative_volume_b: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub cumulative_fees_a: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub cumulative_fees_b: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub swap_count: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub liquidity_event_count: u128,
+    /// `POOL_VERSION` when the pool was created or last migrated
+    pub version: u8,
+    /// The pool's `PoolRegistryEntry`, or the default pubkey for pools
+    /// created before the registry
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub registry_entry: Pubkey,
+    /// When the pool was created; zero for pools created before these were
+    /// tracked
+    pub created_at_timestamp: i64,
+    pub created_at_slot: u64,
+    /// When the pool last swapped, and last had a deposit or withdrawal;
+    /// zero until it does
+    pub last_swap_timestamp: i64,
+    pub last_liquidity_change_timestamp: i64,
+    /// The swap fee in basis points, split between the LPs and the protocol
+    /// by `lp_fee_bps` / `protocol_fee_bps`
+    pub total_fee_bps: u16,
+    pub trade_limits: TradeLimits,
+    /// `lp_mint`'s decimals, `lp_mint_decimals` of the pair, so quotes
+    /// needn't load the mint; zero for pools created before it was recorded
+    pub lp_decimals: u8,
+    /// LP minted to, less LP burned from, the config's treasury by the
+    /// deposit and withdrawal instructions. Only mints and burns through the
+    /// program count: LP the treasury transfers in or out isn't tracked
+    pub protocol_lp_amount: u64,
+    /// Smallest input a swap may put in of token A and of token B, zero for
+    /// none; kept per side, unlike the trade limits' `min_trade_amount`, as
+    /// the mints' units differ
+    pub min_trade_amount_in_a: u64,
+    pub min_trade_amount_in_b: u64,
+    /// Set by the first deposit, which priced the pool and emitted
+    /// `InitialPriceSetEvent`. A drained pool re-seeded after `reset_pool`
+    /// keeps it, so the event fires once per pool; pools that took deposits
+    /// before it existed get it from their next deposit
+    pub initial_price_set: bool,
+    /// Counts the instructions that changed the pool, each event about the
+    /// pool carrying the count after its instruction, so indexers can order
+    /// a pool's events and spot missed ones by a gap
+    pub sequence: u64,
+    /// Swaps must pass the checks of the pool's `OracleGuard`, set by
+    /// `set_oracle_guard`
+    pub oracle_guard: bool,
+    /// Zeroed headroom so later fields fit without growing the account. New
+    /// fields go before it, shrinking it by their size, and must treat zero
+    /// as their default
+    pub reserved: [u8; POOL_RESERVED_BYTES],
 }
 
 impl Pool {
-    pub const LEN: usize = 32 + 32 + 32 + 32 + 32 + 8 + 8 + 32 + 1;
+    /// Error for a failed `has_one = authority` check: `AuthorityRenounced`
+    /// once the authority is renounced, since no key can sign for it then.
+    pub fn authority_error(&self) -> AmmError {
+        self.role_error(self.authority)
+    }
+
+    /// Error for a signer that doesn't hold `role`, one of the pool's role
+    /// keys; as for `authority_error`, `AuthorityRenounced` once nobody does.
+    pub fn role_error(&self, role: Pubkey) -> AmmError {
+        if role == Pubkey::default() {
+            AmmError::AuthorityRenounced
+        } else {
+            AmmError::Unauthorized
+        }
+    }
+
+    /// The key holding the fee role: `fee_authority`, or `authority` when
+    /// none is set, as for pools from before the roles were split.
+    pub fn fee_authority(&self) -> Pubkey {
+        if self.fee_authority == Pubkey::default() {
+            self.authority
+        } else {
+            self.fee_authority
+        }
+    }
+
+    /// The key holding the pause role: `pause_guardian`, or `authority` when
+    /// none is set.
+    pub fn pause_guardian(&self) -> Pubkey {
+        if self.pause_guardian == Pubkey::default() {
+            self.authority
+        } else {
+            self.pause_guardian
+        }
+    }
+
+    /// The swap fee as a numerator/denominator pair: `total_fee_bps` out of
+    /// `BPS_DENOMINATOR`, or the legacy fraction until `migrate_pool`
+    /// converts it.
+    pub fn fee(&self) -> (u64, u64) {
+        if self.fee_denominator == 0 {
+            (self.total_fee_bps as u64, BPS_DENOMINATOR as u64)
+        } else {
+            (self.fee_numerator, self.fee_denominator)
+        }
+    }
+
+    /// `fee()` in whole basis points, rounded down.
+    pub fn fee_bps_rounded_down(&self) -> u16 {
+        let (fee_numerator, fee_denominator) = self.fee();
+        (fee_numerator as u128 * BPS_DENOMINATOR as u128 / fee_denominator as u128) as u16
+    }
+
+    /// Sets the swap fee to `total_fee_bps`, retiring any legacy fraction.
+    pub fn set_fee(&mut self, total_fee_bps: u16) {
+        self.total_fee_bps = total_fee_bps;
+        self.fee_numerator = 0;
+        self.fee_denominator = 0;
+    }
+
+    /// Moves a legacy fee fraction to `total_fee_bps`. Fails with
+    /// `FeeNotRepresentable` if it isn't a whole number of basis points,
+    /// unless `round_down` accepts the nearest one below.
+    pub fn convert_fee_to_bps(&mut self, round_down: bool) -> Result<()> {
+        let (fee_numerator, fee_denominator) = self.fee();
+        let total_fee_bps = self.fee_bps_rounded_down();
+        require!(
+            round_down
+                || total_fee_bps as u128 * fee_denominator as u128
+                    == fee_numerator as u128 * BPS_DENOMINATOR as u128,
+            AmmError::FeeNotRepresentable
+        );
+        self.set_fee(total_fee_bps);
+        Ok(())
+    }
+
+    /// Fails with `PoolPaused` if any of the `POOL_STATUS_*_PAUSED` bits in
+    /// `paused` is set, or with `PoolNeedsMigration` on an old layout.
+    pub fn require_active(&self, paused: u8) -> Result<()> {
+        require!(self.version == POOL_VERSION, AmmError::PoolNeedsMigration);
+        require!(self.status & paused == 0, AmmError::PoolPaused);
+        Ok(())
+    }
+
+    /// Replaces `status`, starting the `emergency_withdraw` grace period at
+    /// `slot` if this pauses every operation.
+    pub fn set_status(&mut self, status: u8, slot: u64) {
+        if status != POOL_STATUS_ALL_PAUSED {
+            self.fully_paused_slot = 0;
+        } else if self.status != POOL_STATUS_ALL_PAUSED {
+            self.fully_paused_slot = slot;
+        }
+        self.status = status;
+    }
+
+    /// The key that may cancel `pending_change`; the pool authority when
+    /// nothing is pending.
+    pub fn pending_change_role(&self) -> Pubkey {
+        self.pending_change
+            .map_or(self.authority, |change| change.role(self))
+    }
+
+    /// The weighted wallets protocol fees are paid to: `fee_recipients`, or
+    /// all of it to `fee_recipient` when none are set.
+    pub fn fee_shares(&self) -> Vec<FeeShare> {
+        if self.fee_recipients.is_empty() {
+            vec![FeeShare {
+                recipient: self.fee_recipient,
+                weight_bps: BPS_DENOMINATOR,
+            }]
+        } else {
+            self.fee_recipients.clone()
+        }
+    }
+
+    /// The vault holding `side`'s tokens.
+    pub fn vault(&self, side: VaultSide) -> Pubkey {
+        match side {
+            VaultSide::A => self.token_a_account,
+            VaultSide::B => self.token_b_account,
+        }
+    }
+
+    /// The mint of `side`'s token.
+    pub fn mint(&self, side: VaultSide) -> Pubkey {
+        match side {
+            VaultSide::A => self.token_a_mint,
+            VaultSide::B => self.token_b_mint,
+        }
+    }
+
+    /// Adds the reserves' prices, held since `last_oracle_timestamp`, to the
+    /// cumulative prices, Uniswap V2 style; called before every change to
+    /// the reserves, so each price is weighted by how long it stood. Nothing
+    /// accrues while a side is empty or before the first update.
+    pub fn update_oracle(&mut self, now: i64) {
+        if now <= self.last_oracle_timestamp {
+            return;
+        }
+        (self.price_a_cumulative, self.price_b_cumulative) = self.cumulative_prices(now);
+        self.last_oracle_timestamp = now;
+    }
+
+    /// The cumulative prices `update_oracle(now)` would store, without
+    /// storing them.
+    pub fn cumulative_prices(&self, now: i64) -> (u128, u128) {
+        let elapsed = now.saturating_sub(self.last_oracle_timestamp);
+        if elapsed <= 0
+            || self.last_oracle_timestamp == 0
+            || self.reserve_a == 0
+            || self.reserve_b == 0
+        {
+            return (self.price_a_cumulative, self.price_b_cumulative);
+        }
+        let elapsed = elapsed as u128;
+        (
+            self.price_a_cumulative
+                .wrapping_add(q64_price(self.reserve_b, self.reserve_a).wrapping_mul(elapsed)),
+            self.price_b_cumulative
+                .wrapping_add(q64_price(self.reserve_a, self.reserve_b).wrapping_mul(elapsed)),
+        )
+    }
+
+    /// What `vault` holds beyond its side's tracked reserve and owed protocol
+    /// fees: tokens sent to it outside the program's flows.
+    pub fn surplus(&self, vault: &InterfaceAccount<TokenAccount>) -> u64 {
+        self.available(vault).saturating_sub(self.reserve(vault))
+    }
+
+    /// The tracked reserve of `vault`'s side: what prices swaps and backs the
+    /// LP tokens, so tokens sent straight to the vault move neither until
+    /// `sync` credits them.
+    pub fn reserve(&self, vault: &InterfaceAccount<TokenAccount>) -> u64 {
+        if vault.key() == self.token_a_account {
+            self.reserve_a
+        } else if vault.key() == self.token_b_account {
+            self.reserve_b
+        } else {
+            0
+        }
+    }
+
+    /// Fails with `VaultBelowReserves` unless `vault`'s balance covers its
+    /// side's tracked reserve and owed protocol fees, the invariant every
+    /// instruction leaves behind. The balance is read from the account data,
+    /// so transfers earlier in the instruction count without a reload.
+    pub fn check_backing(&self, vault: &InterfaceAccount<TokenAccount>) -> Result<()> {
+        let (reserve, owed) = if vault.key() == self.token_a_account {
+            (self.reserve_a, self.protocol_fees_owed_a)
+        } else if vault.key() == self.token_b_account {
+            (self.reserve_b, self.protocol_fees_owed_b)
+        } else {
+            return Ok(());
+        };
+        require!(
+            anchor_spl::token::accessor::amount(&vault.to_account_info())? as u128
+                >= reserve as u128 + owed as u128,
+            AmmError::VaultBelowReserves
+        );
+        Ok(())
+    }
+
+    /// The smallest input a swap may put in on its `a_to_b` side.
+    pub fn min_trade_amount_in(&self, a_to_b: bool) -> u64 {
+        if a_to_b {
+            self.min_trade_amount_in_a
+        } else {
+            self.min_trade_amount_in_b
+        }
+    }
+
+    /// Notes a swap at `now`.
+    pub fn record_swap(&mut self, now: i64) {
+        self.last_swap_timestamp = now;
+    }
+
+    /// Notes a deposit or withdrawal at `now`.
+    pub fn record_liquidity_event(&mut self, now: i64) {
+        self.last_liquidity_change_timestamp = now;
+    }
+
+    /// Advances `sequence` for the instruction changing the pool, returning
+    /// the value its events carry.
+    pub fn next_sequence(&mut self) -> u64 {
+        self.sequence = self.sequence.wrapping_add(1);
+        self.sequence
+    }
+
+    /// `vault`'s balance less the protocol fees owed out of it.
+    pub fn available(&self, vault: &InterfaceAccount<TokenAccount>) -> u64 {
+        let owed = if vault.key() == self.token_a_account {
+            self.protocol_fees_owed_a
+        } else if vault.key() == self.token_b_account {
+            self.protocol_fees_owed_b
+        } else {
+            0
+        };
+        vault.amount.saturating_sub(owed)
+    }
 }
 
-#[event]
-pub struct PoolCreatedEvent {
+/// How many pools have been created, and so the registry index of the next
+/// one. PDA of `["pool_count"]`, created with the first pool.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct PoolCount {
+    pub count: u64,
+    pub bump: u8,
+}
+
+impl PoolCount {
+    /// Fills in `entry` for `pool` under the next index and counts the pool,
+    /// returning its index.
+    pub fn register(
+        &mut self,
+        bump: u8,
+        entry: &mut Account<PoolRegistryEntry>,
+        entry_bump: u8,
+        pool: &Account<Pool>,
+    ) -> Result<u64> {
+        let index = self.count;
+        self.count = index.checked_add(1).ok_or(AmmError::ArithmeticOverflow)?;
+        self.bump = bump;
+        entry.index = index;
+        entry.pool = pool.key();
+        entry.token_a_mint = pool.token_a_mint;
+        entry.token_b_mint = pool.token_b_mint;
+        entry.bump = entry_bump;
+        Ok(index)
+    }
+}
+
+/// A pool's slot in the registry, so routers can enumerate pools by walking
+/// the indexes below `PoolCount::count` rather than scanning the program's
+/// accounts. PDA of `["registry", index]`, with the index as a little-endian
+/// u64.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct PoolRegistryEntry {
+    pub index: u64,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub token_a_mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub token_b_mint: Pubkey,
-    pub fee: f64,
+    /// Set by `close_pool`; the entry stays so the indexes remain contiguous
+    pub closed: bool,
+    pub bump: u8,
 }
 
-#[event]
-pub struct LiquidityAddedEvent {
+/// Running totals for analytics, kept off `Pool` so that swaps which don't
+/// pass it write no more than before. PDA of `["stats", pool]`, created by
+/// `init_stats`; the swaps, deposits and withdrawals that take it add to it
+/// when they are given it. Every total wraps on overflow.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct PoolStats {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub pool: Pubkey,
-    pub user: Pubkey,
-    pub amount_a: u64,
-    pub amount_b: u64,
-    pub lp_tokens_minted: u64,
-    pub pool_token_a_balance: u64,
-    pub pool_token_b_balance: u64,
+    pub bump: u8,
+    /// Each token's volume, swapped in or out
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub cumulative_volume_a: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub cumulative_volume_b: u128,
+    /// Swap fees charged in each input token, LP and protocol shares together
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub cumulative_fees_a: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub cumulative_fees_b: u128,
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub swap_count: u128,
+    /// Deposits and withdrawals
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub liquidity_event_count: u128,
+}
+
+impl PoolStats {
+    /// Adds a swap of `amount_in` for `amount_out`, charged `fee` of the
+    /// input.
+    pub fn record_swap(&mut self, a_to_b: bool, amount_in: u64, amount_out: u64, fee: u64) {
+        let (volume_in, volume_out, fees_in) = if a_to_b {
+            (
+                &mut self.cumulative_volume_a,
+                &mut self.cumulative_volume_b,
+                &mut self.cumulative_fees_a,
+            )
+        } else {
+            (
+                &mut self.cumulative_volume_b,
+                &mut self.cumulative_volume_a,
+                &mut self.cumulative_fees_b,
+            )
+        };
+        *volume_in = volume_in.wrapping_add(amount_in as u128);
+        *volume_out = volume_out.wrapping_add(amount_out as u128);
+        *fees_in = fees_in.wrapping_add(fee as u128);
+        self.swap_count = self.swap_count.wrapping_add(1);
+    }
+
+    /// Counts a deposit or withdrawal.
+    pub fn record_liquidity_event(&mut self) {
+        self.liquidity_event_count = self.liquidity_event_count.wrapping_add(1);
+    }
+}
+
+/// A ring buffer of a pool's cumulative prices, written by swaps at most once
+/// per slot and read by `get_twap`. `observations` grows up to `cardinality`
+/// entries and then wraps, `index` pointing at the newest. PDA of
+/// `["observations", pool]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct Observations {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub index: u16,
+    pub cardinality: u16,
+    pub bump: u8,
+    /// Grown with `realloc`, so `INIT_SPACE` counts it empty and `space`
+    /// adds the entries
+    #[max_len(0)]
+    pub observations: Vec<Observation>,
+}
+
+impl Observations {
+    /// Space for a buffer of `cardinality` observations.
+    pub fn space(cardinality: u16) -> usize {
+        Observations::INIT_SPACE + cardinality as usize * Observation::INIT_SPACE
+    }
+
+    /// Records the cumulative prices as of `timestamp` after the newest
+    /// observation, overwriting the oldest once the buffer is full. A second
+    /// write in the same slot is dropped.
+    pub fn write(
+        &mut self,
+        slot: u64,
+        timestamp: i64,
+        price_a_cumulative: u128,
+        price_b_cumulative: u128,
+    ) {
+        if self.observations[self.index as usize].slot == slot {
+            return;
+        }
+        let observation = Observation {
+            slot,
+            timestamp,
+            price_a_cumulative,
+            price_b_cumulative,
+        };
+        let next = self.index as usize + 1;
+        if next < self.observations.len() {
+            self.observations[next] = observation;
+            self.index = next as u16;
+        } else if next < self.cardinality as usize {
+            self.observations.push(observation);
+            self.index = next as u16;
+        } else {
+            self.observations[0] = observation;
+            self.index = 0;
+        }
+    }
+
+    /// The cumulative prices at `target`, interpolated between the
+    /// observations around it; past the newest observation they run on to
+    /// `current`, the pool's cumulative prices at `now`.
+    pub fn cumulative_prices_at(
+        &self,
+        target: i64,
+        now: i64,
+        current: (u128, u128),
+    ) -> Result<(u128, u128)> {
+        let len = self.observations.len();
+        let oldest = (self.index as usize + 1) % len;
+        let at = |i: usize| &self.observations[(oldest + i) % len];
+        require!(at(0).timestamp <= target, AmmError::OracleStale);
+
+        // The last observation at or before `target`
+        let (mut low, mut high) = (1, len);
+        while low < high {
+            let mid = (low + high) / 2;
+            if at(mid).timestamp <= target {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let before = at(low - 1);
+        if before.timestamp == target {
+            return Ok((before.price_a_cumulative, before.price_b_cumulative));
+        }
+        let (after_timestamp, after_a, after_b) = if low < len {
+            let after = at(low);
+            (
+                after.timestamp,
+                after.price_a_cumulative,
+                after.price_b_cumulative,
+            )
+        } else {
+            (now, current.0, current.1)
+        };
+        let span = (after_timestamp - before.timestamp) as u128;
+        let offset = (target - before.timestamp) as u128;
+        let interpolate = |before: u128, after: u128| {
+            before.wrapping_add((after.wrapping_sub(before) / span).wrapping_mul(offset))
+        };
+        Ok((
+            interpolate(before.price_a_cumulative, after_a),
+            interpolate(before.price_b_cumulative, after_b),
+        ))
+    }
+}
+
+/// LP tokens held in escrow until `unlock_timestamp`, created by
+/// `lock_liquidity`. PDA of `["lock", pool, owner, lock_index]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct LiquidityLock {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub unlock_timestamp: i64,
+    pub lock_index: u64,
+    pub bump: u8,
+}
+
+/// A hidden swap awaiting `reveal_swap`, holding the owner's bond. PDA of
+/// `["commit", pool, owner]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct SwapCommit {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    /// `swap_commitment` of the swap to be revealed
+    pub commitment: [u8; 32],
+    pub commit_slot: u64,
+    /// Last slot the swap may be revealed in
+    pub expiry_slot: u64,
+    pub bump: u8,
 }
 
-#[event]
-pub struct SwapExecutedEvent {
+/// A maker's escrowed limit order against a pool. PDA of
+/// `["order", pool, maker, order_index]`; its escrow is
+/// `["order_vault", order]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct Order {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub maker: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub pool: Pubkey,
-    pub user: Pubkey,
-    pub token_in: Pubkey,
-    pub token_out: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub token_in_mint: Pubkey,
     pub amount_in: u64,
-    pub amount_out: u64,
-    pub fee: u64,
+    /// Least output per input the maker accepts
+    pub min_price_numerator: u64,
+    pub min_price_denominator: u64,
+    pub expiry: i64,
+    pub order_index: u64,
+    pub bump: u8,
+}
+
+/// Program-wide settings. PDA of `["config"]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct GlobalConfig {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub admin: Pubkey,
+    pub discount_config: Option<DiscountConfig>,
+    pub bump: u8,
+    /// Owner of the token accounts protocol fees are paid to
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub protocol_fee_recipient: Pubkey,
+    /// `protocol_fee_bps` new pools start with; the LPs get the rest
+    pub default_protocol_fee_bps: u16,
+    /// Stops swaps on every pool
+    pub paused: bool,
+    pub pool_creation: PoolCreation,
+    /// Swap fees pools may be created with or changed to; empty allows any
+    /// fee up to `MAX_FEE_BPS`
+    #[max_len(MAX_FEE_TIERS)]
+    pub fee_tiers: Vec<FeeTier>,
+    /// Slots a pool parameter change waits between proposal and application
+    pub param_change_delay_slots: u64,
+    /// Slots a pool must stay fully paused before `emergency_withdraw`
+    pub emergency_grace_slots: u64,
+    /// Second signer `emergency_withdraw` needs, or the default pubkey for
+    /// none
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub guardian: Pubkey,
+    /// Lamports charged for creating a pool, paid to `treasury`
+    pub pool_creation_fee_lamports: u64,
+    /// System account pool creation fees are paid to
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub treasury: Pubkey,
+    /// Only lets pools be created when both mints have a `TokenBadge`
+    pub require_badge: bool,
+    /// URI new pools' LP mint metadata points at
+    #[max_len(MAX_POOL_URI_LEN)]
+    pub lp_metadata_uri: String,
+}
+
+impl GlobalConfig {
+    /// Reads the config from its PDA, or `None` if it hasn't been created
+    /// yet, in which case pools run on the defaults.
+    pub fn load(account: &AccountInfo) -> Result<Option<Self>> {
+        if account.data_is_empty() {
+            return Ok(None);
+        }
+        require_keys_eq!(
+            *account.owner,
+            crate::ID,
+            ErrorCode::AccountOwnedByWrongProgram
+        );
+        Ok(Some(Self::try_deserialize(
+            &mut &account.try_borrow_data()?[..],
+        )?))
+    }
+}
+
+/// Lets `creator` create pools in the config's `Allowlist` mode. PDA of
+/// `["creator_allowlist", creator]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct CreatorAllowlistEntry {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub creator: Pubkey,
+    pub bump: u8,
+}
+
+/// Vouches for `mint` as a pool token while the config requires badges.
+/// PDA of `["token_badge", mint]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct TokenBadge {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub mint: Pubkey,
+    pub bump: u8,
 }
 
-#[event]
-pub struct LiquidityRemovedEvent {
+/// Marks `wallet` as allowed on a gated pool. PDA of
+/// `["allowlist", pool, wallet]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct AllowlistEntry {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
     pub pool: Pubkey,
-    pub user: Pubkey,
-    pub amount_a: u64,
-    pub amount_b: u64,
-    pub lp_amount: u64,
-    pub pool_token_a_balance: u64,
-    pub pool_token_b_balance: u64,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub wallet: Pubkey,
+    pub bump: u8,
+}
+
+/// A pool's display name and off-chain metadata URI, for indexers and UIs.
+/// PDA of `["metadata", pool]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct PoolMetadata {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[max_len(MAX_POOL_NAME_LEN)]
+    pub name: String,
+    #[max_len(MAX_POOL_URI_LEN)]
+    pub uri: String,
+    pub bump: u8,
+}
+
+/// A pool's oracle price guard: while the pool's `oracle_guard` flag is set,
+/// a swap must execute within `max_deviation_bps` of the pair price given by
+/// the oracle feeds of its two tokens, each published at most
+/// `max_staleness_seconds` before it. PDA of `["oracle_guard", pool]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct OracleGuard {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    /// Account format of the feeds, and the program that must own them
+    pub oracle_kind: OracleKind,
+    /// Feeds pricing token A and token B in the same quote currency
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub price_feed_a: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub price_feed_b: Pubkey,
+    pub max_deviation_bps: u16,
+    pub max_staleness_seconds: u32,
+    pub bump: u8,
+}
+
+/// A wallet's purchases of the launched token during a pool's launch window.
+/// PDA of `["launch", pool, wallet]`, closed by `close_launch_purchase` once
+/// the window is over.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct LaunchPurchase {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub wallet: Pubkey,
+    /// Paid the rent and gets it back on close
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub payer: Pubkey,
+    pub purchased: u64,
+    pub bump: u8,
+}
+
+/// Running total of LP tokens destroyed through `burn_lp_permanently` for a
+/// pool. PDA of `["burned", pool]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct BurnedLiquidity {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    pub total_burned: u64,
+    pub bump: u8,
+}
+
+/// Liquidity mining for one pool's LP token. PDA of `["farm", pool]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct Farm {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub pool: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub authority: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub lp_mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub reward_mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub stake_vault: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub reward_vault: Pubkey,
+    pub reward_rate_per_second: u64,
+    /// Rewards earned per staked LP token since the farm started, scaled by
+    /// `REWARD_PRECISION`; advanced lazily on every interaction
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub acc_reward_per_share: u128,
+    pub last_update_timestamp: i64,
+    pub total_staked: u64,
+    pub bump: u8,
+    /// Queued rate changes, in start order; each replaces
+    /// `reward_rate_per_second` once it starts
+    #[max_len(MAX_EMISSION_ENTRIES)]
+    pub emission_schedule: Vec<EmissionEntry>,
+}
+
+/// One owner's stake in a farm. PDA of `["stake", farm, owner]`.
+#[account]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(InitSpace)]
+pub struct StakePosition {
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub farm: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "json::pubkey"))]
+    pub owner: Pubkey,
+    pub amount: u64,
+    /// `amount * acc_reward_per_share` at the last settlement, already counted
+    #[cfg_attr(feature = "serde", serde(with = "json::u128_string"))]
+    pub reward_debt: u128,
+    /// Earned but not yet paid out
+    pub pending_rewards: u64,
+    pub bump: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zeroed<T: AccountDeserialize + Discriminator>(space: usize) -> T {
+        let mut data = T::DISCRIMINATOR.to_vec();
+        data.resize(8 + space, 0);
+        T::try_deserialize(&mut data.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn full_pool_fills_its_space() {
+        let mut pool: Pool = zeroed(Pool::INIT_SPACE);
+        pool.pending_change = Some(ParamChange::InsuranceDisbursement {
+            amount: u64::MAX,
+            recipient: Pubkey::new_unique(),
+        });
+        pool.fee_recipients = (0..MAX_FEE_RECIPIENTS)
+            .map(|_| FeeShare {
+                recipient: Pubkey::new_unique(),
+                weight_bps: 1,
+            })
+            .collect();
+
+        let mut data = Vec::new();
+        pool.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + Pool::INIT_SPACE);
+    }
+
+    #[test]
+    fn oracle_deviation_accounts_for_exponents_and_decimals() {
+        let price = |price, exponent| OraclePrice {
+            price,
+            conf: 0,
+            exponent,
+            publish_time: 0,
+        };
+        // $2.00 of a 9-decimal token for $1.00 of a 6-decimal one
+        let two_dollars = price(200_000_000, -8);
+        let one_dollar = price(1_000_000, -6);
+        let deviation = |amount_out| {
+            oracle_deviation_bps(1_000_000_000, 9, two_dollars, amount_out, 6, one_dollar).unwrap()
+        };
+        assert_eq!(deviation(2_000_000), 0);
+        assert_eq!(deviation(1_980_000), 100);
+        assert_eq!(deviation(2_030_000), 150);
+        assert_eq!(deviation(0), 10_000);
+    }
+
+    #[test]
+    fn oracle_prices_scale_down_to_fit() {
+        // $25.50 ± $0.05 with Switchboard's 18 decimals
+        let price =
+            OraclePrice::normalize(25_500_000_000_000_000_000, 50_000_000_000_000_000, -18, 7)
+                .unwrap();
+        assert_eq!(
+            price,
+            OraclePrice {
+                price: 2_550_000_000_000_000_000,
+                conf: 5_000_000_000_000_000,
+                exponent: -17,
+                publish_time: 7,
+            }
+        );
+        assert!(OraclePrice::normalize(0, 0, -8, 7).is_err());
+    }
+
+    #[test]
+    fn math_errors_map_to_program_errors() {
+        assert_eq!(
+            compute_swap(1, 0, 1, 30, 10_000).unwrap_err(),
+            AmmError::InsufficientLiquidity.into()
+        );
+        assert_eq!(
+            compute_amount_in(0, 1, 2, 30, 10_000).unwrap_err(),
+            AmmError::InvalidAmount.into()
+        );
+        assert_eq!(
+            compute_withdrawal(u64::MAX, 2, 0, u64::MAX).unwrap_err(),
+            AmmError::ArithmeticOverflow.into()
+        );
+        assert_eq!(
+            compute_imbalanced_deposit(2, 1, 1, 1, 1, 9, 9, 9, 30, 10_000, 0).unwrap_err(),
+            AmmError::ImbalanceTooHigh.into()
+        );
+        // The wrappers return exactly what the math crate does
+        assert_eq!(
+            compute_swap(1_000_000, 1_000_000_000, 1_000_000_000, 30, 10_000).unwrap(),
+            sol_amm_math::compute_swap(1_000_000, 1_000_000_000, 1_000_000_000, 30, 10_000)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn pool_layout_is_unchanged() {
+        assert_eq!(Pool::INIT_SPACE, 1121);
+    }
 }
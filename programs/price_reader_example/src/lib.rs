@@ -0,0 +1,95 @@
+// The IDL instructions generated by `#[program]` still call `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+//! Example program reading a `new_send_swap` pool's prices by CPI: it takes
+//! the spot price and a TWAP from the pool's view instructions, without
+//! reading its reserves or vaults, and records them.
+
+use anchor_lang::prelude::*;
+use new_send_swap::cpi::accounts::{GetSpotPrice, GetTwap};
+use new_send_swap::program::NewSendSwap;
+
+declare_id!("DqweVEav3g7BLw65k3J4W3GwgLAkHBngkDumAFSXFzv");
+
+#[program]
+pub mod price_reader_example {
+    use super::*;
+
+    /// Records the pool's spot prices and its average prices over the last
+    /// `seconds_ago` seconds in the pool's `PriceRecord`.
+    pub fn record_prices(ctx: Context<RecordPrices>, seconds_ago: u32) -> Result<()> {
+        let swap_program = ctx.accounts.swap_program.to_account_info();
+        let spot = new_send_swap::cpi::get_spot_price(CpiContext::new(
+            swap_program.clone(),
+            GetSpotPrice {
+                pool: ctx.accounts.pool.to_account_info(),
+            },
+        ))?
+        .get();
+        let twap = new_send_swap::cpi::get_twap(
+            CpiContext::new(
+                swap_program,
+                GetTwap {
+                    pool: ctx.accounts.pool.to_account_info(),
+                    observations: ctx.accounts.observations.to_account_info(),
+                },
+            ),
+            seconds_ago,
+        )?
+        .get();
+
+        let record = &mut ctx.accounts.record;
+        record.pool = ctx.accounts.pool.key();
+        record.spot_price_a = spot.price_a;
+        record.spot_price_b = spot.price_b;
+        record.twap_price_a = twap.price_a;
+        record.twap_price_b = twap.price_b;
+        record.seconds_ago = seconds_ago;
+        record.timestamp = twap.timestamp;
+        record.bump = ctx.bumps.record;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct RecordPrices<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + PriceRecord::LEN,
+        seeds = [b"prices", pool.key().as_ref()],
+        bump,
+    )]
+    pub record: Account<'info, PriceRecord>,
+
+    /// CHECK: checked by the swap program
+    pub pool: UncheckedAccount<'info>,
+
+    /// CHECK: the pool's observations, checked by the swap program
+    pub observations: UncheckedAccount<'info>,
+
+    pub swap_program: Program<'info, NewSendSwap>,
+    pub system_program: Program<'info, System>,
+}
+
+/// The prices last read from a pool, as Q64.64. PDA of `["prices", pool]`.
+#[account]
+pub struct PriceRecord {
+    pub pool: Pubkey,
+    /// Token A in token B and token B in token A, from `get_spot_price`
+    pub spot_price_a: u128,
+    pub spot_price_b: u128,
+    /// The same over the last `seconds_ago` seconds, from `get_twap`
+    pub twap_price_a: u128,
+    pub twap_price_b: u128,
+    pub seconds_ago: u32,
+    pub timestamp: i64,
+    pub bump: u8,
+}
+
+impl PriceRecord {
+    pub const LEN: usize = 32 + 16 * 4 + 4 + 8 + 1;
+}
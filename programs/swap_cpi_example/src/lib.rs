@@ -0,0 +1,159 @@
+// The IDL instructions generated by `#[program]` still call `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+//! Example program swapping through `new_send_swap` pools by CPI: it reads
+//! the swap's result from return data, checks it against what its output
+//! account received and records it.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use new_send_swap::cpi::accounts::Swap;
+use new_send_swap::program::NewSendSwap;
+use new_send_swap::SwapResult;
+
+declare_id!("2M6wy6gttmZC8ExnjM83LEcfhvL8awsH8KoMSRFHRSdA");
+
+#[error_code]
+pub enum ExampleError {
+    #[msg("The swap's returned amount_out doesn't match the output received")]
+    AmountMismatch,
+}
+
+#[program]
+pub mod swap_cpi_example {
+    use super::*;
+
+    /// Swaps `amount_in` on the pool through `new_send_swap::swap` and
+    /// records the returned result in the user's receipt, failing if its
+    /// `amount_out` isn't what `user_token_out` received.
+    pub fn swap_through(
+        ctx: Context<SwapThrough>,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        let balance_before = ctx.accounts.user_token_out.amount;
+
+        let accounts = &ctx.accounts;
+        let result = new_send_swap::cpi::swap(
+            CpiContext::new(
+                accounts.swap_program.to_account_info(),
+                Swap {
+                    pool: accounts.pool.to_account_info(),
+                    owner: accounts.user.to_account_info(),
+                    token_in_mint: accounts.token_in_mint.to_account_info(),
+                    token_out_mint: accounts.token_out_mint.to_account_info(),
+                    user_token_in: accounts.user_token_in.to_account_info(),
+                    user_token_out: accounts.user_token_out.to_account_info(),
+                    pool_token_in: accounts.pool_token_in.to_account_info(),
+                    pool_token_out: accounts.pool_token_out.to_account_info(),
+                    owner_token_account: None,
+                    recipient_token_out: None,
+                    allowlist_entry: None,
+                    payer: None,
+                    launch_purchase: None,
+                    config: accounts.config.to_account_info(),
+                    discount_token_account: None,
+                    hook_program: None,
+                    observations: None,
+                    stats: None,
+                    oracle_guard: None,
+                    price_feed_a: None,
+                    price_feed_b: None,
+                    memo_program: None,
+                    token_program: accounts.token_program.to_account_info(),
+                    token_program_b: None,
+                    system_program: None,
+                    event_authority: accounts.event_authority.to_account_info(),
+                    program: accounts.swap_program.to_account_info(),
+                },
+            ),
+            amount_in,
+            min_amount_out,
+            0,
+            0,
+            0,
+            None,
+        )?
+        .get();
+
+        ctx.accounts.user_token_out.reload()?;
+        require_eq!(
+            ctx.accounts.user_token_out.amount - balance_before,
+            result.amount_out,
+            ExampleError::AmountMismatch
+        );
+
+        let receipt = &mut ctx.accounts.receipt;
+        receipt.user = ctx.accounts.user.key();
+        receipt.swap_count += 1;
+        receipt.last_swap = result;
+        receipt.bump = ctx.bumps.receipt;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SwapThrough<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + SwapReceipt::LEN,
+        seeds = [b"receipt", user.key().as_ref()],
+        bump,
+    )]
+    pub receipt: Account<'info, SwapReceipt>,
+
+    /// CHECK: checked by the swap program
+    #[account(mut)]
+    pub pool: UncheckedAccount<'info>,
+
+    /// CHECK: checked by the swap program
+    #[account(mut)]
+    pub token_in_mint: UncheckedAccount<'info>,
+
+    /// CHECK: checked by the swap program
+    #[account(mut)]
+    pub token_out_mint: UncheckedAccount<'info>,
+
+    /// CHECK: checked by the swap program
+    #[account(mut)]
+    pub user_token_in: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub user_token_out: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: checked by the swap program
+    #[account(mut)]
+    pub pool_token_in: UncheckedAccount<'info>,
+
+    /// CHECK: checked by the swap program
+    #[account(mut)]
+    pub pool_token_out: UncheckedAccount<'info>,
+
+    /// CHECK: the swap program's config PDA, checked by it
+    pub config: UncheckedAccount<'info>,
+
+    /// CHECK: the swap program's event authority, checked by it
+    pub event_authority: UncheckedAccount<'info>,
+
+    pub swap_program: Program<'info, NewSendSwap>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// The last swap a user made through this program. PDA of
+/// `["receipt", user]`.
+#[account]
+pub struct SwapReceipt {
+    pub user: Pubkey,
+    pub swap_count: u64,
+    pub last_swap: SwapResult,
+    pub bump: u8,
+}
+
+impl SwapReceipt {
+    pub const LEN: usize = 32 + 8 + (8 + 8 + 8) + 1;
+}
@@ -0,0 +1,165 @@
+// The IDL instructions generated by `#[program]` still call `AccountInfo::realloc`.
+#![allow(deprecated)]
+
+//! Example swap hook for `new_send_swap` pools: records every swap against
+//! the pool it was set up for. Its mode can also be switched to reject swaps
+//! or to try re-entering the pool, to exercise the swap program's handling
+//! of failing and re-entrant hooks.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::InstructionData;
+use new_send_swap::{Pool, SwapHookArgs};
+
+declare_id!("4f1wK1a62zYRevsiwGS5FFYmdF6oDe1UDB81vi5LVdyN");
+
+#[error_code]
+pub enum HookError {
+    #[msg("Hook was not called from inside a swap on its pool")]
+    NotInSwap,
+    #[msg("Hook rejected the swap")]
+    HookRejected,
+}
+
+#[program]
+pub mod swap_hook_example {
+    use super::*;
+
+    /// Creates the hook's state for `pool`, in `Record` mode.
+    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+        let hook_state = &mut ctx.accounts.hook_state;
+        hook_state.pool = ctx.accounts.pool.key();
+        hook_state.authority = ctx.accounts.authority.key();
+        hook_state.mode = HookMode::Record;
+        hook_state.bump = ctx.bumps.hook_state;
+        Ok(())
+    }
+
+    pub fn set_mode(ctx: Context<SetMode>, mode: HookMode) -> Result<()> {
+        ctx.accounts.hook_state.mode = mode;
+        Ok(())
+    }
+
+    /// Called by the swap program after each swap on the pool. In `Reenter`
+    /// mode the remaining accounts are those of a `swap` on the same pool,
+    /// which the hook tries to run.
+    pub fn on_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OnSwap<'info>>,
+        args: SwapHookArgs,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.pool.in_hook && args.pool == ctx.accounts.pool.key(),
+            HookError::NotInSwap
+        );
+
+        let hook_state = &mut ctx.accounts.hook_state;
+        match hook_state.mode {
+            HookMode::Record => {
+                hook_state.swap_count += 1;
+                hook_state.last_amount_in = args.amount_in;
+                hook_state.last_amount_out = args.amount_out;
+                hook_state.last_a_to_b = args.a_to_b;
+            }
+            HookMode::Reject => return err!(HookError::HookRejected),
+            HookMode::Reenter => {
+                let accounts = ctx
+                    .remaining_accounts
+                    .iter()
+                    .map(|account| {
+                        if account.is_writable {
+                            AccountMeta::new(account.key(), account.is_signer)
+                        } else {
+                            AccountMeta::new_readonly(account.key(), account.is_signer)
+                        }
+                    })
+                    .collect();
+                let data = new_send_swap::instruction::Swap {
+                    amount_in: args.amount_in,
+                    min_amount_out: 0,
+                    limit_price_numerator: 0,
+                    limit_price_denominator: 0,
+                    deadline: 0,
+                    memo: None,
+                }
+                .data();
+                invoke(
+                    &Instruction {
+                        program_id: new_send_swap::ID,
+                        accounts,
+                        data,
+                    },
+                    ctx.remaining_accounts,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + HookState::LEN,
+        seeds = [b"hook_state", pool.key().as_ref()],
+        bump,
+    )]
+    pub hook_state: Account<'info, HookState>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetMode<'info> {
+    #[account(mut, has_one = authority)]
+    pub hook_state: Account<'info, HookState>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OnSwap<'info> {
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds = [b"hook_state", pool.key().as_ref()],
+        bump = hook_state.bump,
+    )]
+    pub hook_state: Account<'info, HookState>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HookMode {
+    /// Record each swap in the hook state
+    Record,
+    /// Fail, which fails the swap
+    Reject,
+    /// Try to swap on the same pool again from inside the hook
+    Reenter,
+}
+
+/// What the hook has seen of its pool's swaps. PDA of `["hook_state", pool]`.
+#[account]
+pub struct HookState {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub mode: HookMode,
+    pub swap_count: u64,
+    pub last_amount_in: u64,
+    pub last_amount_out: u64,
+    pub last_a_to_b: bool,
+    pub bump: u8,
+}
+
+impl HookState {
+    pub const LEN: usize = 32 + 32 + 1 + 8 + 8 + 8 + 1 + 1;
+}
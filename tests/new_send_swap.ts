@@ -1,28 +1,54 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
   AuthorityType,
   createAccount,
   createAssociatedTokenAccount,
+  createInitializeMetadataPointerInstruction,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
   createMint,
+  createSyncNativeInstruction,
+  createWrappedNativeAccount,
+  ExtensionType,
+  getAssociatedTokenAddressSync,
   getMint,
+  getMintLen,
+  getTokenMetadata,
+  getOrCreateAssociatedTokenAccount,
   mintTo,
+  NATIVE_MINT,
   setAuthority,
+  TOKEN_2022_PROGRAM_ID,
   TOKEN_PROGRAM_ID,
+  transfer,
 } from "@solana/spl-token";
 import {
   Keypair,
   PublicKey,
   SystemProgram,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
   SYSVAR_RENT_PUBKEY,
+  Transaction,
 } from "@solana/web3.js";
 import { assert } from "chai";
+import { createHash } from "crypto";
+import { readFileSync } from "fs";
 import { NewSendSwap } from "../target/types/new_send_swap";
+import { SwapHookExample } from "../target/types/swap_hook_example";
+import { MockGovernance } from "../target/types/mock_governance";
+import { SwapCpiExample } from "../target/types/swap_cpi_example";
+import { PriceReaderExample } from "../target/types/price_reader_example";
 
 describe("new_send_swap - Comprehensive Test Suite", () => {
   // Create a new keypair for the test
   const payer = Keypair.generate();
 
+  // The configured wallet, which deployed the program and is its upgrade
+  // authority
+  const deployer = anchor.getProvider() as anchor.AnchorProvider;
+
   // Setup provider with the payer
   const provider = new anchor.AnchorProvider(
     anchor.getProvider().connection,
@@ -32,6 +58,14 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
   anchor.setProvider(provider);
 
   const program = anchor.workspace.newSendSwap as Program<NewSendSwap>;
+  const hookProgram = anchor.workspace
+    .swapHookExample as Program<SwapHookExample>;
+  const governanceProgram = anchor.workspace
+    .mockGovernance as Program<MockGovernance>;
+  const cpiProgram = anchor.workspace
+    .swapCpiExample as Program<SwapCpiExample>;
+  const readerProgram = anchor.workspace
+    .priceReaderExample as Program<PriceReaderExample>;
 
   // Global variables
   let tokenAMint: PublicKey;
@@ -159,6 +193,449 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
     return new anchor.BN(value.toString());
   };
 
+  // Helper function to derive a pool PDA; `poolIndex` separates pools on the
+  // same mint pair
+  const findPoolAddress = (
+    mintA: PublicKey,
+    mintB: PublicKey,
+    poolIndex: number = 0
+  ) => {
+    const index = Buffer.alloc(2);
+    index.writeUInt16LE(poolIndex);
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mintA.toBuffer(), mintB.toBuffer(), index],
+      program.programId
+    )[0];
+  };
+
+  // Helper function to derive a pool's `PoolStats` PDA
+  const findStatsAddress = (pool: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("stats"), pool.toBuffer()],
+      program.programId
+    )[0];
+
+  // Helper function to create a pool's stats account, paid for by the payer
+  const initStats = (pool: PublicKey) =>
+    program.methods
+      .initStats()
+      .accounts({
+        pool,
+        payer: payer.publicKey,
+        stats: findStatsAddress(pool),
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+  // Helper function to derive the registry entry at `index`
+  const findRegistryEntry = (index: number) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("registry"),
+        new anchor.BN(index).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  const poolCountAddress = PublicKey.findProgramAddressSync(
+    [Buffer.from("pool_count")],
+    program.programId
+  )[0];
+
+  // Helper function to read how many pools the registry holds
+  const getPoolCount = async () => {
+    const poolCount = await program.account.poolCount.fetchNullable(
+      poolCountAddress
+    );
+    return poolCount ? poolCount.count.toNumber() : 0;
+  };
+
+  // Helper function to derive the registry entry the next pool is written to
+  const nextRegistryEntry = async () => findRegistryEntry(await getPoolCount());
+
+  // Trade limits that allow swaps of any size
+  const noTradeLimits = { minTradeAmount: new anchor.BN(0), maxTradeBps: 0 };
+
+  // Helper function to create and seed a fresh pool, on new mints unless
+  // existing ones are given
+  const createSeededPool = async (
+    seedAmountA: number,
+    seedAmountB: number,
+    feeBps: number = 30,
+    existing?: { mintA: PublicKey; mintB: PublicKey; poolIndex: number },
+    tradeStartTimestamp: number = 0,
+    tradeLimits: typeof noTradeLimits = noTradeLimits
+  ) => {
+    let mintA = existing?.mintA;
+    let mintB = existing?.mintB;
+    if (!existing) {
+      mintA = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      mintB = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+    }
+    const poolIndex = existing?.poolIndex ?? 0;
+    const poolLpMint = await createMint(
+      provider.connection,
+      payer,
+      payer.publicKey,
+      null,
+      9
+    );
+    const pool = findPoolAddress(mintA, mintB, poolIndex);
+    const registryEntry = await nextRegistryEntry();
+    const vaultA = await createAccount(
+      provider.connection,
+      payer,
+      mintA,
+      pool,
+      Keypair.generate()
+    );
+    const vaultB = await createAccount(
+      provider.connection,
+      payer,
+      mintB,
+      pool,
+      Keypair.generate()
+    );
+
+    await program.methods
+      .initializePool(
+        feeBps,
+        poolIndex,
+        new anchor.BN(tradeStartTimestamp),
+        tradeLimits
+      )
+      .accounts({
+        pool,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        tokenAAccount: vaultA,
+        tokenBAccount: vaultB,
+        lpMint: poolLpMint,
+        authority: payer.publicKey,
+        registryEntry,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+    await setAuthority(
+      provider.connection,
+      payer,
+      poolLpMint,
+      payer.publicKey,
+      AuthorityType.MintTokens,
+      pool
+    );
+
+    const payerTokenA = await fundAccount(mintA, payer.publicKey, seedAmountA);
+    const payerTokenB = await fundAccount(mintB, payer.publicKey, seedAmountB);
+    const payerLp = await fundAccount(poolLpMint, payer.publicKey, 0);
+
+    const seedSignature = await program.methods
+      .addLiquidity(
+        new anchor.BN(seedAmountA),
+        new anchor.BN(seedAmountB),
+        new anchor.BN(0),
+        new anchor.BN(0)
+      )
+      .accounts({
+        pool,
+        owner: payer.publicKey,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        userTokenA: payerTokenA,
+        userTokenB: payerTokenB,
+        poolTokenA: vaultA,
+        poolTokenB: vaultB,
+        lpMint: poolLpMint,
+        userLp: payerLp,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    return {
+      pool,
+      mintA,
+      mintB,
+      lpMint: poolLpMint,
+      vaultA,
+      vaultB,
+      payerTokenA,
+      payerTokenB,
+      payerLp,
+      registryEntry,
+      seedSignature,
+    };
+  };
+
+  // Helper function to get (or create) an ATA and mint tokens into it; for
+  // the native mint the payer's SOL is wrapped instead
+  const fundAccount = async (
+    mint: PublicKey,
+    owner: PublicKey,
+    amount: number
+  ) => {
+    const account = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mint,
+      owner
+    );
+    if (amount > 0 && mint.equals(NATIVE_MINT)) {
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: payer.publicKey,
+            toPubkey: account.address,
+            lamports: amount,
+          }),
+          createSyncNativeInstruction(account.address)
+        )
+      );
+    } else if (amount > 0) {
+      await mintTo(
+        provider.connection,
+        payer,
+        mint,
+        account.address,
+        payer.publicKey,
+        amount
+      );
+    }
+    return account.address;
+  };
+
+  // Helper function to read the cluster's current unix timestamp
+  const getClusterTime = async () => {
+    const slot = await provider.connection.getSlot();
+    return await provider.connection.getBlockTime(slot);
+  };
+
+  // Helper function to wait until the cluster clock reaches `timestamp`
+  const waitUntil = async (timestamp: number) => {
+    while ((await getClusterTime()) < timestamp) {
+      await sleep(1000);
+    }
+  };
+
+  // Helper function to credit tokens sent straight to a pool's vaults to its
+  // reserves, which alone price swaps
+  const syncReserves = (
+    pool: PublicKey,
+    poolTokenA: PublicKey,
+    poolTokenB: PublicKey
+  ) => program.methods.sync().accounts({ pool, poolTokenA, poolTokenB }).rpc();
+
+  // Helper function to wait until the cluster reaches `slot`
+  const waitForSlot = async (slot: number) => {
+    while ((await provider.connection.getSlot()) < slot) {
+      await sleep(400);
+    }
+  };
+
+  // Helper function to decode the first event of the given name emitted by a
+  // confirmed transaction
+  // Prefix of the self-CPIs `emit_cpi!` makes, `EVENT_IX_TAG_LE` in Anchor
+  const EVENT_IX_TAG = Buffer.from("e445a52e51cb9a1d", "hex");
+
+  // Helper function to decode the events a transaction logged with `emit!`,
+  // then those it emitted as self-CPIs with `emit_cpi!`
+  const getEvents = async (signature: string) => {
+    const transaction = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const events = [...parser.parseLogs(transaction.meta.logMessages)];
+
+    const keys = transaction.transaction.message.getAccountKeys({
+      accountKeysFromLookups: transaction.meta.loadedAddresses,
+    });
+    for (const inner of transaction.meta.innerInstructions ?? []) {
+      for (const ix of inner.instructions) {
+        const data = Buffer.from(anchor.utils.bytes.bs58.decode(ix.data));
+        if (
+          keys.get(ix.programIdIndex).equals(program.programId) &&
+          data.subarray(0, 8).equals(EVENT_IX_TAG)
+        ) {
+          const base64 = data.subarray(8).toString("base64");
+          const event = program.coder.events.decode(base64);
+          if (event) {
+            events.push(event);
+          }
+        }
+      }
+    }
+    return events;
+  };
+
+  const getEvent = async (signature: string, name: string) => {
+    for (const event of await getEvents(signature)) {
+      if (event.name === name) {
+        return event.data;
+      }
+    }
+    throw new Error(`No ${name} in transaction ${signature}`);
+  };
+
+  // Helper function to assert a transaction fails with the given error code
+  const expectError = async (promise: Promise<unknown>, code: string) => {
+    try {
+      await promise;
+    } catch (error) {
+      assert.include(error.toString(), code);
+      return;
+    }
+    assert.fail(`Expected transaction to fail with ${code}`);
+  };
+
+  const configAddress = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  )[0];
+
+  // Helper function to create the program config, or take it over, with the
+  // payer as admin; signed by the deployer as the program's upgrade authority
+  const ensureConfig = async () => {
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+    const instruction = await program.methods
+      .initializeConfig(payer.publicKey)
+      .accounts({
+        config: configAddress,
+        authority: deployer.wallet.publicKey,
+        program: program.programId,
+        programData,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    await deployer.sendAndConfirm(new Transaction().add(instruction));
+  };
+
+  // Helper function to change some of the program config's settings, leaving
+  // the rest as they are
+  const updateConfig = (update: object, admin: Keypair = payer) =>
+    program.methods
+      .updateConfig({
+        admin: null,
+        defaultProtocolFeeBps: null,
+        paused: null,
+        poolCreation: null,
+        feeTiers: null,
+        paramChangeDelaySlots: null,
+        emergencyGraceSlots: null,
+        guardian: null,
+        poolCreationFeeLamports: null,
+        treasury: null,
+        requireBadge: null,
+        lpMetadataUri: null,
+        ...update,
+      })
+      .accounts({ config: configAddress, admin: admin.publicKey })
+      .signers([admin])
+      .rpc();
+
+  // Helper function to let pool parameter changes apply without a delay
+  const disableParamChangeDelay = async () => {
+    await ensureConfig();
+    await updateConfig({ paramChangeDelaySlots: new anchor.BN(0) });
+  };
+
+  // Helper function to propose a pool parameter change and apply it right
+  // away, which needs `disableParamChangeDelay` first; returns the signature
+  // of the application
+  const changeParam = async (
+    pool: PublicKey,
+    change: object,
+    authority: Keypair = payer
+  ) => {
+    await program.methods
+      .proposeParamChange(change)
+      .accounts({ pool, authority: authority.publicKey })
+      .signers([authority])
+      .rpc();
+    return program.methods.applyParamChange().accounts({ pool }).rpc();
+  };
+
+  // Helper function to set up fresh mints and vaults and return the
+  // `initialize_pool` call making `creator` the pool's authority, passing
+  // `treasury` for the config's pool creation fee and `creatorAllowlistEntry`
+  // in its allowlist creation mode
+  const prepareCreatePool = async (
+    creator: Keypair,
+    feeBps: number = 30,
+    treasury: PublicKey | null = null,
+    creatorAllowlistEntry: PublicKey | null = null
+  ) => {
+    const newMint = () =>
+      createMint(provider.connection, payer, payer.publicKey, null, 9);
+    const mintA = await newMint();
+    const mintB = await newMint();
+    const poolLpMint = await newMint();
+    const pool = findPoolAddress(mintA, mintB);
+    const newVault = (mint: PublicKey) =>
+      createAccount(
+        provider.connection,
+        payer,
+        mint,
+        pool,
+        Keypair.generate()
+      );
+    const vaultA = await newVault(mintA);
+    const vaultB = await newVault(mintB);
+
+    const builder = program.methods
+      .initializePool(feeBps, 0, new anchor.BN(0), noTradeLimits)
+      .accounts({
+        pool,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        tokenAAccount: vaultA,
+        tokenBAccount: vaultB,
+        lpMint: poolLpMint,
+        authority: creator.publicKey,
+        registryEntry: await nextRegistryEntry(),
+        treasury,
+        creatorAllowlistEntry,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([creator]);
+    return { pool, builder };
+  };
+
+  // Helper function to create a pool on fresh mints with `creator` as its
+  // authority; see `prepareCreatePool`
+  const createPoolAs = async (
+    creator: Keypair,
+    feeBps: number = 30,
+    treasury: PublicKey | null = null,
+    creatorAllowlistEntry: PublicKey | null = null
+  ) => {
+    const { pool, builder } = await prepareCreatePool(
+      creator,
+      feeBps,
+      treasury,
+      creatorAllowlistEntry
+    );
+    return { pool, signature: await builder.rpc() };
+  };
+
   describe("Pool Initialization", () => {
     it("Should initialize the pool with correct parameters", async () => {
       // Ensure payer has sufficient SOL
@@ -188,10 +665,7 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       );
 
       // Derive pool address
-      [poolAddress] = PublicKey.findProgramAddressSync(
-        [Buffer.from("pool"), tokenAMint.toBuffer(), tokenBMint.toBuffer()],
-        program.programId
-      );
+      poolAddress = findPoolAddress(tokenAMint, tokenBMint);
 
       // Create pool token accounts
       const poolTokenAKeypair = Keypair.generate();
@@ -215,8 +689,10 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       // Initialize pool with 0.3% fee
       await program.methods
         .initializePool(
-          new anchor.BN(3), // fee numerator (0.3%)
-          new anchor.BN(1000) // fee denominator
+          30, // total fee in bps (0.3%)
+          0, // pool index
+          new anchor.BN(0), // trade start (open immediately)
+          noTradeLimits // no minimum or maximum trade size
         )
         .accounts({
           pool: poolAddress,
@@ -226,6 +702,7 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
           tokenBAccount: poolTokenBAccount,
           lpMint,
           authority: payer.publicKey,
+          registryEntry: await nextRegistryEntry(),
           tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
           rent: SYSVAR_RENT_PUBKEY,
@@ -250,8 +727,7 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       assert.ok(poolAccount.tokenAAccount.equals(poolTokenAAccount));
       assert.ok(poolAccount.tokenBAccount.equals(poolTokenBAccount));
       assert.ok(poolAccount.lpMint.equals(lpMint));
-      assert.equal(poolAccount.feeNumerator.toNumber(), 3);
-      assert.equal(poolAccount.feeDenominator.toNumber(), 1000);
+      assert.equal(poolAccount.totalFeeBps, 30);
       assert.ok(poolAccount.authority.equals(payer.publicKey));
     });
 
@@ -284,12 +760,14 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         invalidTokenBKeypair
       );
 
-      // Test with fee denominator = 0 (should fail)
+      // Test with a fee above MAX_FEE_BPS (should fail)
       try {
         await program.methods
           .initializePool(
-            new anchor.BN(3),
-            new anchor.BN(0) // Invalid: division by zero
+            1_001, // Invalid: above the 10% cap
+            0,
+            new anchor.BN(0),
+            noTradeLimits
           )
           .accounts({
             pool: invalidPoolAddress,
@@ -299,15 +777,16 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
             tokenBAccount: invalidTokenBAccount,
             lpMint,
             authority: payer.publicKey,
+            registryEntry: await nextRegistryEntry(),
             tokenProgram: TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
             rent: SYSVAR_RENT_PUBKEY,
           })
           .signers([payer])
           .rpc();
-        assert.fail("Should have failed with zero denominator");
+        assert.fail("Should have failed with an excessive fee");
       } catch (error) {
-        console.log("✓ Correctly failed with zero denominator");
+        console.log("✓ Correctly failed with an excessive fee");
       }
     });
   });
@@ -387,10 +866,10 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       );
 
       await program.methods
-        .addLiquidity(amountA, amountB, new anchor.BN(0))
+        .addLiquidity(amountA, amountB, new anchor.BN(0), new anchor.BN(0))
         .accounts({
           pool: poolAddress,
-          user: payer.publicKey,
+          owner: payer.publicKey,
           tokenAMint,
           tokenBMint,
           userTokenA: userTokenAAccount,
@@ -440,10 +919,10 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       const poolBalanceBBefore = await getTokenBalance(poolTokenBAccount);
 
       await program.methods
-        .addLiquidity(amountA, amountB, new anchor.BN(0))
+        .addLiquidity(amountA, amountB, new anchor.BN(0), new anchor.BN(0))
         .accounts({
           pool: poolAddress,
-          user: payer.publicKey,
+          owner: payer.publicKey,
           tokenAMint,
           tokenBMint,
           userTokenA: userTokenAAccount,
@@ -496,10 +975,10 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       const poolBalanceBBefore = await getTokenBalance(poolTokenBAccount);
 
       await program.methods
-        .addLiquidity(amountA, amountB, new anchor.BN(0))
+        .addLiquidity(amountA, amountB, new anchor.BN(0), new anchor.BN(0))
         .accounts({
           pool: poolAddress,
-          user: payer.publicKey,
+          owner: payer.publicKey,
           tokenAMint,
           tokenBMint,
           userTokenA: userTokenAAccount,
@@ -527,10 +1006,15 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
     it("Should fail with zero amounts", async () => {
       try {
         await program.methods
-          .addLiquidity(new anchor.BN(0), new anchor.BN(0), new anchor.BN(0))
+          .addLiquidity(
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0)
+          )
           .accounts({
             pool: poolAddress,
-            user: payer.publicKey,
+            owner: payer.publicKey,
             tokenAMint,
             tokenBMint,
             userTokenA: userTokenAAccount,
@@ -572,10 +1056,15 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       );
 
       await program.methods
-        .addLiquidity(initialAmountA, initialAmountB, new anchor.BN(0))
+        .addLiquidity(
+          initialAmountA,
+          initialAmountB,
+          new anchor.BN(0),
+          new anchor.BN(0)
+        )
         .accounts({
           pool: poolAddress,
-          user: payer.publicKey,
+          owner: payer.publicKey,
           tokenAMint,
           tokenBMint,
           userTokenA: userTokenAAccount,
@@ -615,11 +1104,12 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
           .addLiquidity(
             additionalAmountA,
             additionalAmountB,
-            new anchor.BN(1_000_000_000) // Very high min LP tokens (should fail)
+            new anchor.BN(1_000_000_000), // Very high min LP tokens (should fail)
+            new anchor.BN(0)
           )
           .accounts({
             pool: poolAddress,
-            user: payer.publicKey,
+            owner: payer.publicKey,
             tokenAMint,
             tokenBMint,
             userTokenA: userTokenAAccount,
@@ -713,16 +1203,24 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         payer.publicKey,
         liquidityAmount.toNumber()
       );
+      await syncReserves(poolAddress, poolTokenAAccount, poolTokenBAccount);
 
       // Try swapping 1 token (smallest unit)
       const swapAmount = new anchor.BN(1);
       const minAmountOut = new anchor.BN(0);
 
       await program.methods
-        .swap(swapAmount, minAmountOut)
+        .swap(
+          swapAmount,
+          minAmountOut,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
         .accounts({
           pool: poolAddress,
-          user: user.publicKey,
+          owner: user.publicKey,
           tokenInMint: tokenAMint,
           tokenOutMint: tokenBMint,
           userTokenIn: userTokenA,
@@ -766,16 +1264,24 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         payer.publicKey,
         liquidityAmount.toNumber()
       );
+      await syncReserves(poolAddress, poolTokenAAccount, poolTokenBAccount);
 
       // Try swapping large amount - use much smaller amount to avoid overflow
       const swapAmount = new anchor.BN(1_000_000); // 1 million tokens (further reduced)
       const minAmountOut = new anchor.BN(0);
 
       await program.methods
-        .swap(swapAmount, minAmountOut)
+        .swap(
+          swapAmount,
+          minAmountOut,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
         .accounts({
           pool: poolAddress,
-          user: user.publicKey,
+          owner: user.publicKey,
           tokenInMint: tokenAMint,
           tokenOutMint: tokenBMint,
           userTokenIn: userTokenA,
@@ -797,10 +1303,17 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
 
       try {
         await program.methods
-          .swap(new anchor.BN(0), new anchor.BN(0))
+          .swap(
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            null
+          )
           .accounts({
             pool: poolAddress,
-            user: user.publicKey,
+            owner: user.publicKey,
             tokenInMint: tokenAMint,
             tokenOutMint: tokenBMint,
             userTokenIn: userTokenA,
@@ -842,16 +1355,24 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         payer.publicKey,
         liquidityAmount.toNumber()
       );
+      await syncReserves(poolAddress, poolTokenAAccount, poolTokenBAccount);
 
       const swapAmount = new anchor.BN(100_000_000);
       const minAmountOut = new anchor.BN(1_000_000_000); // Unrealistically high
 
       try {
         await program.methods
-          .swap(swapAmount, minAmountOut)
+          .swap(
+            swapAmount,
+            minAmountOut,
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            null
+          )
           .accounts({
             pool: poolAddress,
-            user: user.publicKey,
+            owner: user.publicKey,
             tokenInMint: tokenAMint,
             tokenOutMint: tokenBMint,
             userTokenIn: userTokenA,
@@ -893,16 +1414,24 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         payer.publicKey,
         liquidityAmount.toNumber()
       );
+      await syncReserves(poolAddress, poolTokenAAccount, poolTokenBAccount);
 
       // Try swapping small amount
       const swapAmount = new anchor.BN(100_000); // 0.0001 tokens
       const minAmountOut = new anchor.BN(0);
 
       await program.methods
-        .swap(swapAmount, minAmountOut)
+        .swap(
+          swapAmount,
+          minAmountOut,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
         .accounts({
           pool: poolAddress,
-          user: user.publicKey,
+          owner: user.publicKey,
           tokenInMint: tokenAMint,
           tokenOutMint: tokenBMint,
           userTokenIn: userTokenA,
@@ -947,10 +1476,10 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       );
 
       await program.methods
-        .addLiquidity(amountA, amountB, new anchor.BN(0))
+        .addLiquidity(amountA, amountB, new anchor.BN(0), new anchor.BN(0))
         .accounts({
           pool: poolAddress,
-          user: payer.publicKey,
+          owner: payer.publicKey,
           tokenAMint,
           tokenBMint,
           userTokenA: userTokenAAccount,
@@ -968,7 +1497,13 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
     it("Should fail with zero LP amount", async () => {
       try {
         await program.methods
-          .removeLiquidity(new anchor.BN(0), new anchor.BN(0), new anchor.BN(0))
+          .removeLiquidity(
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            false,
+            new anchor.BN(0)
+          )
           .accounts({
             pool: poolAddress,
             user: payer.publicKey,
@@ -1021,7 +1556,13 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         .div(new anchor.BN(100));
 
       await program.methods
-        .removeLiquidity(removeAmount, minTokenA, minTokenB)
+        .removeLiquidity(
+          removeAmount,
+          minTokenA,
+          minTokenB,
+          false,
+          new anchor.BN(0)
+        )
         .accounts({
           pool: poolAddress,
           user: payer.publicKey,
@@ -1055,7 +1596,13 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
 
       try {
         await program.methods
-          .removeLiquidity(removeAmount, minTokenA, minTokenB)
+          .removeLiquidity(
+            removeAmount,
+            minTokenA,
+            minTokenB,
+            false,
+            new anchor.BN(0)
+          )
           .accounts({
             pool: poolAddress,
             user: payer.publicKey,
@@ -1090,11 +1637,12 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         .addLiquidity(
           new anchor.BN(1_000_000_000),
           new anchor.BN(1_000_000_000),
+          new anchor.BN(0),
           new anchor.BN(0)
         )
         .accounts({
           pool: poolAddress,
-          user: user.publicKey,
+          owner: user.publicKey,
           tokenAMint,
           tokenBMint,
           userTokenA,
@@ -1125,6 +1673,7 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         payer.publicKey,
         10_000_000_000 // Add 10 billion tokens to pool
       );
+      await syncReserves(poolAddress, poolTokenAAccount, poolTokenBAccount);
 
       // Ensure user has sufficient tokens for all swaps
       await mintTo(
@@ -1139,17 +1688,23 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       // Perform multiple swaps rapidly - use smaller amounts to avoid overflow
       for (let i = 0; i < 5; i++) {
         await program.methods
-          .swap(new anchor.BN(10_000), new anchor.BN(0)) // 0.00001 tokens (further reduced)
+          .swap(
+            new anchor.BN(10_000),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            null
+          ) // 0.00001 tokens (further reduced)
           .accounts({
             pool: poolAddress,
-            user: user.publicKey,
+            owner: user.publicKey,
             tokenInMint: tokenAMint,
             tokenOutMint: tokenBMint,
             userTokenIn: userTokenA,
             userTokenOut: userTokenB,
             poolTokenIn: poolTokenAAccount,
             poolTokenOut: poolTokenBAccount,
-            ownerTokenAccount: userTokenA, // Use same account for fees
             tokenProgram: TOKEN_PROGRAM_ID,
           })
           .signers([user])
@@ -1175,10 +1730,15 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       // This should either succeed or fail gracefully without overflow
       try {
         await program.methods
-          .addLiquidity(maxSafeAmount, maxSafeAmount, new anchor.BN(0))
+          .addLiquidity(
+            maxSafeAmount,
+            maxSafeAmount,
+            new anchor.BN(0),
+            new anchor.BN(0)
+          )
           .accounts({
             pool: poolAddress,
-            user: user.publicKey,
+            owner: user.publicKey,
             tokenAMint,
             tokenBMint,
             userTokenA,
@@ -1210,11 +1770,12 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         .addLiquidity(
           new anchor.BN(1_000_000_000),
           new anchor.BN(1_000_000_000),
+          new anchor.BN(0),
           new anchor.BN(0)
         )
         .accounts({
           pool: poolAddress,
-          user: user.publicKey,
+          owner: user.publicKey,
           tokenAMint,
           tokenBMint,
           userTokenA,
@@ -1228,13 +1789,6 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         .signers([user])
         .rpc();
 
-      const ownerTokenAccount = await createAssociatedTokenAccount(
-        provider.connection,
-        payer,
-        tokenAMint,
-        payer.publicKey
-      );
-
       // Ensure user has sufficient tokens for testing
       await mintTo(
         provider.connection,
@@ -1262,8 +1816,12 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
         payer.publicKey,
         1_000_000_000_000 // Add 1 trillion tokens to pool
       );
+      await syncReserves(poolAddress, poolTokenAAccount, poolTokenBAccount);
 
-      const initialOwnerBalance = await getTokenBalance(ownerTokenAccount);
+      // The protocol fee accrues in the pool until it is collected
+      const owedFees = async () =>
+        (await program.account.pool.fetch(poolAddress)).protocolFeesOwedA;
+      const initialOwedFees = await owedFees();
 
       // Test different swap amounts
       const testAmounts = [
@@ -1279,24 +1837,29 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
           .div(new anchor.BN(1000));
 
         await program.methods
-          .swap(amount, new anchor.BN(0))
+          .swap(
+            amount,
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            null
+          )
           .accounts({
             pool: poolAddress,
-            user: user.publicKey,
+            owner: user.publicKey,
             tokenInMint: tokenAMint,
             tokenOutMint: tokenBMint,
             userTokenIn: userTokenA,
             userTokenOut: userTokenB,
             poolTokenIn: poolTokenAAccount,
             poolTokenOut: poolTokenBAccount,
-            ownerTokenAccount,
             tokenProgram: TOKEN_PROGRAM_ID,
           })
           .signers([user])
           .rpc();
 
-        const finalOwnerBalance = await getTokenBalance(ownerTokenAccount);
-        const actualFee = finalOwnerBalance.sub(initialOwnerBalance);
+        const actualFee = (await owedFees()).sub(initialOwedFees);
 
         // Allow small tolerance for rounding
         const tolerance = expectedFee.div(new anchor.BN(100)); // 1% tolerance
@@ -1309,4 +1872,12611 @@ describe("new_send_swap - Comprehensive Test Suite", () => {
       }
     });
   });
+
+  describe("Deadline Enforcement", () => {
+    let ownerTokenAccount: PublicKey;
+
+    before(async () => {
+      ownerTokenAccount = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          payer,
+          tokenAMint,
+          payer.publicKey
+        )
+      ).address;
+    });
+
+    const swapAccounts = (
+      user: PublicKey,
+      userTokenA: PublicKey,
+      userTokenB: PublicKey
+    ) => ({
+      pool: poolAddress,
+      owner: user,
+      tokenInMint: tokenAMint,
+      tokenOutMint: tokenBMint,
+      userTokenIn: userTokenA,
+      userTokenOut: userTokenB,
+      poolTokenIn: poolTokenAAccount,
+      poolTokenOut: poolTokenBAccount,
+      ownerTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    it("Should reject a swap after its deadline", async () => {
+      const { user, userTokenA, userTokenB } = await createUserWithTokens(
+        1_000_000_000,
+        0
+      );
+      const now = await getClusterTime();
+
+      await expectError(
+        program.methods
+          .swap(
+            new anchor.BN(1_000_000),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(now - 60),
+            null
+          )
+          .accounts(swapAccounts(user.publicKey, userTokenA, userTokenB))
+          .signers([user])
+          .rpc(),
+        "DeadlineExceeded"
+      );
+
+      // Nothing moved
+      const balanceA = await getTokenBalance(userTokenA);
+      assert.equal(balanceA.toString(), "1000000000");
+    });
+
+    it("Should execute a swap before its deadline", async () => {
+      const { user, userTokenA, userTokenB } = await createUserWithTokens(
+        1_000_000_000,
+        0
+      );
+      const now = await getClusterTime();
+
+      await program.methods
+        .swap(
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(now + 60),
+          null
+        )
+        .accounts(swapAccounts(user.publicKey, userTokenA, userTokenB))
+        .signers([user])
+        .rpc();
+
+      const balanceB = await getTokenBalance(userTokenB);
+      assert.ok(balanceB.gt(new anchor.BN(0)));
+    });
+
+    it("Should reject add_liquidity after its deadline", async () => {
+      const { user, userTokenA, userTokenB, userLp } =
+        await createUserWithTokens(1_000_000_000, 1_000_000_000);
+      const now = await getClusterTime();
+
+      await expectError(
+        program.methods
+          .addLiquidity(
+            new anchor.BN(1_000_000),
+            new anchor.BN(1_000_000),
+            new anchor.BN(0),
+            new anchor.BN(now - 60)
+          )
+          .accounts({
+            pool: poolAddress,
+            owner: user.publicKey,
+            tokenAMint,
+            tokenBMint,
+            userTokenA,
+            userTokenB,
+            poolTokenA: poolTokenAAccount,
+            poolTokenB: poolTokenBAccount,
+            lpMint,
+            userLp,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user])
+          .rpc(),
+        "DeadlineExceeded"
+      );
+    });
+
+    it("Should honor deadlines on add and remove liquidity", async () => {
+      const { user, userTokenA, userTokenB, userLp } =
+        await createUserWithTokens(1_000_000_000, 1_000_000_000);
+      const liquidityAccounts = {
+        pool: poolAddress,
+        tokenAMint,
+        tokenBMint,
+        userTokenA,
+        userTokenB,
+        poolTokenA: poolTokenAAccount,
+        poolTokenB: poolTokenBAccount,
+        lpMint,
+        userLp,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+      const now = await getClusterTime();
+
+      await program.methods
+        .addLiquidity(
+          new anchor.BN(1_000_000),
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(now + 60)
+        )
+        .accounts({ ...liquidityAccounts, owner: user.publicKey })
+        .signers([user])
+        .rpc();
+
+      const lpBalance = await getTokenBalance(userLp);
+      assert.ok(lpBalance.gt(new anchor.BN(0)));
+
+      await expectError(
+        program.methods
+          .removeLiquidity(
+            lpBalance,
+            new anchor.BN(0),
+            new anchor.BN(0),
+            false,
+            new anchor.BN(now - 60)
+          )
+          .accounts({ ...liquidityAccounts, user: user.publicKey })
+          .signers([user])
+          .rpc(),
+        "DeadlineExceeded"
+      );
+
+      await program.methods
+        .removeLiquidity(
+          lpBalance,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          false,
+          new anchor.BN(now + 60)
+        )
+        .accounts({ ...liquidityAccounts, user: user.publicKey })
+        .signers([user])
+        .rpc();
+
+      const lpBalanceAfter = await getTokenBalance(userLp);
+      assert.equal(lpBalanceAfter.toString(), "0");
+    });
+  });
+
+  describe("Flash Loans", () => {
+    const flashLoanIx = (
+      borrower: PublicKey,
+      borrowerToken: PublicKey,
+      amount: anchor.BN
+    ) =>
+      program.methods
+        .flashLoan(amount, true)
+        .accounts({
+          tokenMint: tokenAMint,
+          pool: poolAddress,
+          borrower,
+          poolToken: poolTokenAAccount,
+          borrowerToken,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .instruction();
+
+    const flashRepayIx = (
+      repayer: PublicKey,
+      repayerToken: PublicKey,
+      amount: anchor.BN
+    ) =>
+      program.methods
+        .flashRepay(amount)
+        .accounts({
+          tokenMint: tokenAMint,
+          pool: poolAddress,
+          repayer,
+          poolToken: poolTokenAAccount,
+          repayerToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .instruction();
+
+    // Fee is the pool's swap fee (3/1000), rounded up
+    const loanAmount = new anchor.BN(1_000_000);
+    const loanFee = new anchor.BN(3_000);
+
+    // Borrows `loanAmount` and repays it with its fee in one transaction
+    const borrowAndRepay = async (user: Keypair, userTokenA: PublicKey) =>
+      provider.sendAndConfirm(
+        new Transaction().add(
+          await flashLoanIx(user.publicKey, userTokenA, loanAmount),
+          await flashRepayIx(
+            user.publicKey,
+            userTokenA,
+            loanAmount.add(loanFee)
+          )
+        ),
+        [user]
+      );
+
+    it("Should lend and accept repayment in the same transaction", async () => {
+      const { user, userTokenA } = await createUserWithTokens(10_000_000, 0);
+      const vaultBefore = await getTokenBalance(poolTokenAAccount);
+
+      const tx = new Transaction().add(
+        await flashLoanIx(user.publicKey, userTokenA, loanAmount),
+        await flashRepayIx(user.publicKey, userTokenA, loanAmount.add(loanFee))
+      );
+      await provider.sendAndConfirm(tx, [user]);
+
+      const vaultAfter = await getTokenBalance(poolTokenAAccount);
+      assert.equal(vaultAfter.sub(vaultBefore).toString(), loanFee.toString());
+
+      const poolAccount = await program.account.pool.fetch(poolAddress);
+      assert.isFalse(poolAccount.locked);
+    });
+
+    it("Should fail when the loan is under-repaid", async () => {
+      const { user, userTokenA } = await createUserWithTokens(10_000_000, 0);
+
+      const tx = new Transaction().add(
+        await flashLoanIx(user.publicKey, userTokenA, loanAmount),
+        await flashRepayIx(user.publicKey, userTokenA, loanAmount)
+      );
+      await expectError(
+        provider.sendAndConfirm(tx, [user]),
+        "FlashLoanNotRepaid"
+      );
+    });
+
+    it("Should fail without a matching flash_repay", async () => {
+      const { user, userTokenA } = await createUserWithTokens(10_000_000, 0);
+
+      const tx = new Transaction().add(
+        await flashLoanIx(user.publicKey, userTokenA, loanAmount)
+      );
+      await expectError(
+        provider.sendAndConfirm(tx, [user]),
+        "FlashRepayMissing"
+      );
+    });
+
+    it("Should reject nested flash loans", async () => {
+      const { user, userTokenA } = await createUserWithTokens(10_000_000, 0);
+
+      const tx = new Transaction().add(
+        await flashLoanIx(user.publicKey, userTokenA, loanAmount),
+        await flashLoanIx(user.publicKey, userTokenA, loanAmount),
+        await flashRepayIx(
+          user.publicKey,
+          userTokenA,
+          loanAmount.add(loanFee).muln(2)
+        )
+      );
+      await expectError(provider.sendAndConfirm(tx, [user]), "PoolLocked");
+    });
+
+    it("Should block swaps while a loan is outstanding", async () => {
+      const { user, userTokenA, userTokenB } = await createUserWithTokens(
+        10_000_000,
+        0
+      );
+      const ownerTokenAccount = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          payer,
+          tokenAMint,
+          payer.publicKey
+        )
+      ).address;
+
+      const swapIx = await program.methods
+        .swap(
+          new anchor.BN(100_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
+        .accounts({
+          pool: poolAddress,
+          owner: user.publicKey,
+          tokenInMint: tokenAMint,
+          tokenOutMint: tokenBMint,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          poolTokenIn: poolTokenAAccount,
+          poolTokenOut: poolTokenBAccount,
+          ownerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .instruction();
+
+      const tx = new Transaction().add(
+        await flashLoanIx(user.publicKey, userTokenA, loanAmount),
+        swapIx,
+        await flashRepayIx(user.publicKey, userTokenA, loanAmount.add(loanFee))
+      );
+      await expectError(provider.sendAndConfirm(tx, [user]), "PoolLocked");
+    });
+
+    it("Should not lend while the pool's swaps are paused", async () => {
+      const { user, userTokenA } = await createUserWithTokens(10_000_000, 0);
+      const loan = () => borrowAndRepay(user, userTokenA);
+      const setPoolStatus = (status: number) =>
+        program.methods
+          .setPoolStatus(status, status === 7)
+          .accounts({ pool: poolAddress, authority: payer.publicKey })
+          .rpc();
+
+      // Swaps paused alone, then every operation as in an emergency
+      for (const status of [1, 7]) {
+        await setPoolStatus(status);
+        await expectError(loan(), "PoolPaused");
+      }
+      await setPoolStatus(0);
+      await loan();
+    });
+
+    it("Should not lend while the protocol is paused", async () => {
+      const { user, userTokenA } = await createUserWithTokens(10_000_000, 0);
+      const loan = () => borrowAndRepay(user, userTokenA);
+
+      await ensureConfig();
+      await updateConfig({ paused: true });
+      await expectError(loan(), "ProtocolPaused");
+      await updateConfig({ paused: false });
+      await loan();
+    });
+  });
+
+  describe("Sync and Skim", () => {
+    it("Should credit a direct vault transfer to reserves on sync", async () => {
+      await mintTo(
+        provider.connection,
+        payer,
+        tokenAMint,
+        poolTokenAAccount,
+        payer.publicKey,
+        5_000_000
+      );
+
+      await program.methods
+        .sync()
+        .accounts({
+          pool: poolAddress,
+          poolTokenA: poolTokenAAccount,
+          poolTokenB: poolTokenBAccount,
+        })
+        .rpc();
+
+      const poolAccount = await program.account.pool.fetch(poolAddress);
+      const vaultA = await getTokenBalance(poolTokenAAccount);
+      const vaultB = await getTokenBalance(poolTokenBAccount);
+      // Protocol fees owed from earlier swaps stay out of the reserves
+      assert.equal(
+        poolAccount.reserveA.add(poolAccount.protocolFeesOwedA).toString(),
+        vaultA.toString()
+      );
+      assert.equal(
+        poolAccount.reserveB.add(poolAccount.protocolFeesOwedB).toString(),
+        vaultB.toString()
+      );
+    });
+
+    it("Should skim only the surplus above tracked reserves", async () => {
+      const toTokenA = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          payer,
+          tokenAMint,
+          payer.publicKey
+        )
+      ).address;
+      const toTokenB = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          payer,
+          tokenBMint,
+          payer.publicKey
+        )
+      ).address;
+
+      const donation = 7_000_000;
+      await mintTo(
+        provider.connection,
+        payer,
+        tokenBMint,
+        poolTokenBAccount,
+        payer.publicKey,
+        donation
+      );
+
+      const before = await program.account.pool.fetch(poolAddress);
+      const toBBefore = await getTokenBalance(toTokenB);
+
+      await program.methods
+        .skim()
+        .accounts({
+          tokenAMint,
+          tokenBMint,
+          pool: poolAddress,
+          authority: payer.publicKey,
+          poolTokenA: poolTokenAAccount,
+          poolTokenB: poolTokenBAccount,
+          toTokenA,
+          toTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const after = await program.account.pool.fetch(poolAddress);
+      const toBAfter = await getTokenBalance(toTokenB);
+      const vaultB = await getTokenBalance(poolTokenBAccount);
+
+      assert.equal(toBAfter.sub(toBBefore).toString(), donation.toString());
+      assert.equal(
+        vaultB.toString(),
+        after.reserveB.add(after.protocolFeesOwedB).toString()
+      );
+      assert.equal(after.reserveA.toString(), before.reserveA.toString());
+      assert.equal(after.reserveB.toString(), before.reserveB.toString());
+    });
+
+    it("Should reject skim from a non-authority", async () => {
+      const { user, userTokenA, userTokenB } = await createUserWithTokens(
+        0,
+        0
+      );
+
+      await expectError(
+        program.methods
+          .skim()
+          .accounts({
+            tokenAMint,
+            tokenBMint,
+            pool: poolAddress,
+            authority: user.publicKey,
+            poolTokenA: poolTokenAAccount,
+            poolTokenB: poolTokenBAccount,
+            toTokenA: userTokenA,
+            toTokenB: userTokenB,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("Batch Swaps", () => {
+    const createBatch = async (count: number) => {
+      const user = Keypair.generate();
+      await ensureSolBalance(user);
+      const legs = [];
+      for (let i = 0; i < count; i++) {
+        const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+        const userTokenA = await fundAccount(
+          seeded.mintA,
+          user.publicKey,
+          10_000_000
+        );
+        const userTokenB = await fundAccount(seeded.mintB, user.publicKey, 0);
+        legs.push({ ...seeded, userTokenA, userTokenB });
+      }
+      const remainingAccounts = legs.flatMap((leg) =>
+        [
+          leg.pool,
+          leg.vaultA,
+          leg.vaultB,
+          leg.userTokenA,
+          leg.userTokenB,
+          leg.payerTokenA,
+        ]
+          .map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+          .concat(
+            [leg.mintA, leg.mintB].map((pubkey) => ({
+              pubkey,
+              isSigner: false,
+              isWritable: false,
+            }))
+          )
+      );
+      return { user, legs, remainingAccounts };
+    };
+
+    it("Should execute every leg of a 3-pool batch", async () => {
+      const { user, legs, remainingAccounts } = await createBatch(3);
+      const amountIn = new anchor.BN(1_000_000);
+
+      await program.methods
+        .swapBatch(
+          legs.map(() => ({
+            amountIn,
+            minAmountOut: new anchor.BN(1),
+            aToB: true,
+          })),
+          new anchor.BN(0)
+        )
+        .accounts({ user: user.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+        .remainingAccounts(remainingAccounts)
+        .signers([user])
+        .rpc();
+
+      for (const leg of legs) {
+        const balanceA = await getTokenBalance(leg.userTokenA);
+        const balanceB = await getTokenBalance(leg.userTokenB);
+        assert.equal(balanceA.toString(), "9000000");
+        assert.ok(balanceB.gt(new anchor.BN(0)));
+      }
+    });
+
+    it("Should revert the whole batch when the last leg fails slippage", async () => {
+      const { user, legs, remainingAccounts } = await createBatch(3);
+      const amountIn = new anchor.BN(1_000_000);
+
+      await expectError(
+        program.methods
+          .swapBatch(
+            legs.map((_, i) => ({
+              amountIn,
+              minAmountOut:
+                i === legs.length - 1 ? amountIn : new anchor.BN(1),
+              aToB: true,
+            })),
+            new anchor.BN(0)
+          )
+          .accounts({ user: user.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+          .remainingAccounts(remainingAccounts)
+          .signers([user])
+          .rpc(),
+        "SlippageExceeded"
+      );
+
+      // Earlier legs were rolled back too
+      for (const leg of legs) {
+        const balanceA = await getTokenBalance(leg.userTokenA);
+        const balanceB = await getTokenBalance(leg.userTokenB);
+        assert.equal(balanceA.toString(), "10000000");
+        assert.equal(balanceB.toString(), "0");
+      }
+    });
+
+    it("Should reject batches above the leg limit", async () => {
+      const user = Keypair.generate();
+      const leg = {
+        amountIn: new anchor.BN(1),
+        minAmountOut: new anchor.BN(0),
+        aToB: true,
+      };
+
+      await expectError(
+        program.methods
+          .swapBatch([leg, leg, leg, leg, leg], new anchor.BN(0))
+          .accounts({ user: user.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+          .signers([user])
+          .rpc(),
+        "BatchTooLarge"
+      );
+    });
+  });
+
+  describe("Remove Liquidity by Basis Points", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+
+    const removeBps = (bps: number, closeLpAccount: boolean) =>
+      program.methods
+        .removeLiquidityBps(
+          bps,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          closeLpAccount,
+          new anchor.BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+    });
+
+    it("Should withdraw 1 bps of the position", async () => {
+      const lpBefore = await getTokenBalance(seeded.payerLp);
+      await removeBps(1, false);
+      const lpAfter = await getTokenBalance(seeded.payerLp);
+      assert.equal(
+        lpBefore.sub(lpAfter).toString(),
+        lpBefore.muln(1).divn(10_000).toString()
+      );
+    });
+
+    it("Should withdraw 50% of the position", async () => {
+      const lpBefore = await getTokenBalance(seeded.payerLp);
+      await removeBps(5_000, false);
+      const lpAfter = await getTokenBalance(seeded.payerLp);
+      assert.equal(
+        lpBefore.sub(lpAfter).toString(),
+        lpBefore.muln(5_000).divn(10_000).toString()
+      );
+    });
+
+    it("Should reject 0 and more than 10000 bps", async () => {
+      await expectError(removeBps(0, false), "InvalidBps");
+      await expectError(removeBps(10_001, false), "InvalidBps");
+    });
+
+    it("Should withdraw 100% and close the LP account", async () => {
+      const lamportsBefore = await provider.connection.getBalance(
+        payer.publicKey
+      );
+      await removeBps(10_000, true);
+
+      const lpAccount = await provider.connection.getAccountInfo(
+        seeded.payerLp
+      );
+      assert.isNull(lpAccount);
+      const vaultA = await getTokenBalance(seeded.vaultA);
+      const vaultB = await getTokenBalance(seeded.vaultB);
+      assert.equal(vaultA.toString(), "0");
+      assert.equal(vaultB.toString(), "0");
+
+      // Rent came back to the user, net of the transaction fee
+      const lamportsAfter = await provider.connection.getBalance(
+        payer.publicKey
+      );
+      assert.ok(lamportsAfter > lamportsBefore);
+    });
+  });
+
+  describe("Swap Quotes", () => {
+    it("Should quote exactly what swap executes", async () => {
+      const seeded = await createSeededPool(1_000_000_000, 3_000_000_000);
+      const user = Keypair.generate();
+      await ensureSolBalance(user);
+      const userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        10_000_000_000
+      );
+      const userTokenB = await fundAccount(
+        seeded.mintB,
+        user.publicKey,
+        10_000_000_000
+      );
+      const feeTokenA = seeded.payerTokenA;
+      const feeTokenB = seeded.payerTokenB;
+
+      const amounts = [1_000, 1_000_000, 100_000_000];
+      for (const aToB of [true, false]) {
+        for (const amount of amounts) {
+          const amountIn = new anchor.BN(amount);
+          const quote = await program.methods
+            .quoteSwap(amountIn, aToB)
+            .accounts({
+              pool: seeded.pool,
+              poolTokenA: seeded.vaultA,
+              poolTokenB: seeded.vaultB,
+            })
+            .view();
+
+          const userTokenOut = aToB ? userTokenB : userTokenA;
+          const outBefore = await getTokenBalance(userTokenOut);
+          await program.methods
+            .swap(
+              amountIn,
+              new anchor.BN(0),
+              new anchor.BN(0),
+              new anchor.BN(0),
+              new anchor.BN(0),
+              null
+            )
+            .accounts({
+              pool: seeded.pool,
+              owner: user.publicKey,
+              tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+              tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+              userTokenIn: aToB ? userTokenA : userTokenB,
+              userTokenOut,
+              poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+              poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+              ownerTokenAccount: aToB ? feeTokenA : feeTokenB,
+              tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .signers([user])
+            .rpc();
+          const outAfter = await getTokenBalance(userTokenOut);
+
+          assert.equal(
+            outAfter.sub(outBefore).toString(),
+            quote.amountOut.toString(),
+            `amount_out mismatch for ${amount} (aToB=${aToB})`
+          );
+          assert.equal(
+            quote.fee.toString(),
+            amountIn.muln(3).divn(1000).toString()
+          );
+          assert.isAtMost(quote.priceImpactBps, 10_000);
+        }
+      }
+    });
+  });
+
+  describe("Liquidity Quotes", () => {
+    it("Should quote the first deposit and a subsequent deposit", async () => {
+      const mintA = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const mintB = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const poolLpMint = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const pool = findPoolAddress(mintA, mintB);
+      const vaultA = await createAccount(
+        provider.connection,
+        payer,
+        mintA,
+        pool,
+        Keypair.generate()
+      );
+      const vaultB = await createAccount(
+        provider.connection,
+        payer,
+        mintB,
+        pool,
+        Keypair.generate()
+      );
+      await program.methods
+        .initializePool(30, 0, new anchor.BN(0), noTradeLimits)
+        .accounts({
+          pool,
+          tokenAMint: mintA,
+          tokenBMint: mintB,
+          tokenAAccount: vaultA,
+          tokenBAccount: vaultB,
+          lpMint: poolLpMint,
+          authority: payer.publicKey,
+          registryEntry: await nextRegistryEntry(),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+      await setAuthority(
+        provider.connection,
+        payer,
+        poolLpMint,
+        payer.publicKey,
+        AuthorityType.MintTokens,
+        pool
+      );
+
+      const userTokenA = await fundAccount(
+        mintA,
+        payer.publicKey,
+        10_000_000_000
+      );
+      const userTokenB = await fundAccount(
+        mintB,
+        payer.publicKey,
+        10_000_000_000
+      );
+      const userLp = await fundAccount(poolLpMint, payer.publicKey, 0);
+      const quoteAccounts = {
+        pool,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        poolTokenA: vaultA,
+        poolTokenB: vaultB,
+        lpMint: poolLpMint,
+      };
+      const liquidityAccounts = {
+        ...quoteAccounts,
+        userTokenA,
+        userTokenB,
+        userLp,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+
+      for (const [amountA, amountB] of [
+        [1_000_000_000, 2_000_000_000],
+        [500_000_000, 700_000_000],
+      ]) {
+        const quote = await program.methods
+          .quoteAddLiquidity(new anchor.BN(amountA), new anchor.BN(amountB))
+          .accounts(quoteAccounts)
+          .view();
+
+        const lpBefore = await getTokenBalance(userLp);
+        await program.methods
+          .addLiquidity(
+            new anchor.BN(amountA),
+            new anchor.BN(amountB),
+            new anchor.BN(0),
+            new anchor.BN(0)
+          )
+          .accounts({ ...liquidityAccounts, owner: payer.publicKey })
+          .rpc();
+        const lpAfter = await getTokenBalance(userLp);
+
+        assert.equal(
+          lpAfter.sub(lpBefore).toString(),
+          quote.lpTokens.toString()
+        );
+      }
+
+      // Withdrawal quote matches remove_liquidity
+      const lpAmount = (await getTokenBalance(userLp)).divn(3);
+      const removeQuote = await program.methods
+        .quoteRemoveLiquidity(lpAmount)
+        .accounts({
+          pool,
+          poolTokenA: vaultA,
+          poolTokenB: vaultB,
+          lpMint: poolLpMint,
+        })
+        .view();
+
+      const aBefore = await getTokenBalance(userTokenA);
+      const bBefore = await getTokenBalance(userTokenB);
+      await program.methods
+        .removeLiquidity(
+          lpAmount,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          false,
+          new anchor.BN(0)
+        )
+        .accounts({ ...liquidityAccounts, user: payer.publicKey })
+        .rpc();
+      const aAfter = await getTokenBalance(userTokenA);
+      const bAfter = await getTokenBalance(userTokenB);
+
+      assert.equal(
+        aAfter.sub(aBefore).toString(),
+        removeQuote.amountA.toString()
+      );
+      assert.equal(
+        bAfter.sub(bBefore).toString(),
+        removeQuote.amountB.toString()
+      );
+    });
+  });
+
+  describe("Atomic Pool Initialization", () => {
+    it("Should create and seed a pool in one instruction", async () => {
+      const mintA = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const mintB = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const poolLpMint = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const pool = findPoolAddress(mintA, mintB);
+      const vaultA = await createAccount(
+        provider.connection,
+        payer,
+        mintA,
+        pool,
+        Keypair.generate()
+      );
+      const vaultB = await createAccount(
+        provider.connection,
+        payer,
+        mintB,
+        pool,
+        Keypair.generate()
+      );
+      // The PDA address is known up front, so the LP mint can be handed over
+      // before the pool account exists
+      await setAuthority(
+        provider.connection,
+        payer,
+        poolLpMint,
+        payer.publicKey,
+        AuthorityType.MintTokens,
+        pool
+      );
+
+      const userTokenA = await fundAccount(
+        mintA,
+        payer.publicKey,
+        2_000_000_000
+      );
+      const userTokenB = await fundAccount(
+        mintB,
+        payer.publicKey,
+        2_000_000_000
+      );
+      const userLp = await fundAccount(poolLpMint, payer.publicKey, 0);
+
+      const initialize = () =>
+        program.methods
+          .initializePoolWithLiquidity(
+            30,
+            new anchor.BN(1_000_000_000),
+            new anchor.BN(500_000_000),
+            0,
+            new anchor.BN(0),
+            noTradeLimits
+          )
+          .accounts({
+            pool,
+            tokenAMint: mintA,
+            tokenBMint: mintB,
+            tokenAAccount: vaultA,
+            tokenBAccount: vaultB,
+            lpMint: poolLpMint,
+            authority: payer.publicKey,
+            registryEntry: await nextRegistryEntry(),
+            userTokenA,
+            userTokenB,
+            userLp,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: SYSVAR_RENT_PUBKEY,
+          })
+          .rpc();
+
+      await initialize();
+
+      const poolAccount = await program.account.pool.fetch(pool);
+      assert.equal(
+        poolAccount.authority.toString(),
+        payer.publicKey.toString()
+      );
+      assert.equal(poolAccount.reserveA.toString(), "1000000000");
+      assert.equal(poolAccount.reserveB.toString(), "500000000");
+      assert.equal((await getTokenBalance(vaultA)).toString(), "1000000000");
+      assert.equal((await getTokenBalance(vaultB)).toString(), "500000000");
+      assert.equal((await getTokenBalance(userLp)).toString(), "1000000");
+
+      // The pool PDA now exists, so a second initialization cannot succeed
+      try {
+        await initialize();
+        assert.fail("Second initialization should fail");
+      } catch (error) {
+        const logs = (error.logs ?? []).join("\n");
+        assert.include(logs + error.toString(), "already in use");
+      }
+    });
+  });
+
+  describe("Native SOL Swaps", () => {
+    const user = Keypair.generate();
+    let mintB: PublicKey;
+    let pool: PublicKey;
+    let vaultSol: PublicKey;
+    let vaultB: PublicKey;
+    let payerWsol: PublicKey;
+    let payerTokenB: PublicKey;
+    let userTokenB: PublicKey;
+    let wsolAccount: PublicKey;
+
+    before(async () => {
+      await ensureSolBalance(payer, 1_500_000_000);
+      await ensureSolBalance(user);
+
+      mintB = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const poolLpMint = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      pool = findPoolAddress(NATIVE_MINT, mintB);
+      vaultSol = await createAccount(
+        provider.connection,
+        payer,
+        NATIVE_MINT,
+        pool,
+        Keypair.generate()
+      );
+      vaultB = await createAccount(
+        provider.connection,
+        payer,
+        mintB,
+        pool,
+        Keypair.generate()
+      );
+      await setAuthority(
+        provider.connection,
+        payer,
+        poolLpMint,
+        payer.publicKey,
+        AuthorityType.MintTokens,
+        pool
+      );
+
+      payerWsol = await createWrappedNativeAccount(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        500_000_000
+      );
+      payerTokenB = await fundAccount(mintB, payer.publicKey, 1_000_000_000);
+      const payerLp = await fundAccount(poolLpMint, payer.publicKey, 0);
+
+      await program.methods
+        .initializePoolWithLiquidity(
+          30,
+          new anchor.BN(500_000_000),
+          new anchor.BN(1_000_000_000),
+          0,
+          new anchor.BN(0),
+          noTradeLimits
+        )
+        .accounts({
+          pool,
+          tokenAMint: NATIVE_MINT,
+          tokenBMint: mintB,
+          tokenAAccount: vaultSol,
+          tokenBAccount: vaultB,
+          lpMint: poolLpMint,
+          authority: payer.publicKey,
+          registryEntry: await nextRegistryEntry(),
+          userTokenA: payerWsol,
+          userTokenB: payerTokenB,
+          userLp: payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+
+      userTokenB = await fundAccount(mintB, user.publicKey, 100_000_000);
+      [wsolAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("wsol"), user.publicKey.toBuffer()],
+        program.programId
+      );
+    });
+
+    // Runs a native swap and returns the user's lamport change, net of the
+    // transaction fee
+    const swapNative = async (amountIn: anchor.BN, solIn: boolean) => {
+      const lamportsBefore = await provider.connection.getBalance(
+        user.publicKey
+      );
+      const signature = await program.methods
+        .swapNative(amountIn, new anchor.BN(0), new anchor.BN(0))
+        .accounts({
+          tokenInMint: solIn ? NATIVE_MINT : mintB,
+          tokenOutMint: solIn ? mintB : NATIVE_MINT,
+          pool,
+          user: user.publicKey,
+          nativeMint: NATIVE_MINT,
+          wsolAccount,
+          userToken: userTokenB,
+          poolTokenIn: solIn ? vaultSol : vaultB,
+          poolTokenOut: solIn ? vaultB : vaultSol,
+          ownerTokenAccount: solIn ? payerWsol : payerTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([user])
+        .rpc();
+      const transaction = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const lamportsAfter = await provider.connection.getBalance(
+        user.publicKey
+      );
+
+      // The temporary wSOL account is always closed again
+      assert.isNull(await provider.connection.getAccountInfo(wsolAccount));
+
+      return lamportsAfter - lamportsBefore + transaction.meta.fee;
+    };
+
+    it("Should swap SOL for tokens", async () => {
+      const amountIn = new anchor.BN(10_000_000);
+      const quote = await program.methods
+        .quoteSwap(amountIn, true)
+        .accounts({ pool, poolTokenA: vaultSol, poolTokenB: vaultB })
+        .view();
+      const tokenBBefore = await getTokenBalance(userTokenB);
+
+      const lamportDelta = await swapNative(amountIn, true);
+
+      // Only the input leaves the wallet; the temporary account's rent is
+      // refunded in full
+      assert.equal(lamportDelta, -amountIn.toNumber());
+      const tokenBAfter = await getTokenBalance(userTokenB);
+      assert.equal(
+        tokenBAfter.sub(tokenBBefore).toString(),
+        quote.amountOut.toString()
+      );
+    });
+
+    it("Should swap tokens for SOL", async () => {
+      const amountIn = new anchor.BN(20_000_000);
+      const quote = await program.methods
+        .quoteSwap(amountIn, false)
+        .accounts({ pool, poolTokenA: vaultSol, poolTokenB: vaultB })
+        .view();
+      const tokenBBefore = await getTokenBalance(userTokenB);
+
+      const lamportDelta = await swapNative(amountIn, false);
+
+      // The wSOL output is unwrapped and the rent comes back with it
+      assert.equal(lamportDelta, quote.amountOut.toNumber());
+      const tokenBAfter = await getTokenBalance(userTokenB);
+      assert.equal(
+        tokenBBefore.sub(tokenBAfter).toString(),
+        amountIn.toString()
+      );
+    });
+
+    it("Should reject pools without a native side", async () => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      const userTokenOther = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        1_000_000
+      );
+      await expectError(
+        program.methods
+          .swapNative(new anchor.BN(1_000), new anchor.BN(0), new anchor.BN(0))
+          .accounts({
+            tokenInMint: seeded.mintA,
+            tokenOutMint: seeded.mintB,
+            pool: seeded.pool,
+            user: user.publicKey,
+            nativeMint: NATIVE_MINT,
+            wsolAccount,
+            userToken: userTokenOther,
+            poolTokenIn: seeded.vaultA,
+            poolTokenOut: seeded.vaultB,
+            ownerTokenAccount: seeded.payerTokenA,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: SYSVAR_RENT_PUBKEY,
+          })
+          .signers([user])
+          .rpc(),
+        "NotNativePool"
+      );
+    });
+  });
+
+  describe("Swap Recipient", () => {
+    it("Should deliver swap output to a third party's account", async () => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      const user = Keypair.generate();
+      const merchant = Keypair.generate();
+      await ensureSolBalance(user);
+      const userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        10_000_000
+      );
+      const userTokenB = await fundAccount(seeded.mintB, user.publicKey, 0);
+      const merchantTokenB = await fundAccount(
+        seeded.mintB,
+        merchant.publicKey,
+        0
+      );
+      const merchantTokenA = await fundAccount(
+        seeded.mintA,
+        merchant.publicKey,
+        0
+      );
+
+      const swapAccounts = {
+        pool: seeded.pool,
+        owner: user.publicKey,
+        tokenInMint: seeded.mintA,
+        tokenOutMint: seeded.mintB,
+        userTokenIn: userTokenA,
+        userTokenOut: userTokenB,
+        poolTokenIn: seeded.vaultA,
+        poolTokenOut: seeded.vaultB,
+        ownerTokenAccount: seeded.payerTokenA,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+      const amountIn = new anchor.BN(1_000_000);
+      const quote = await program.methods
+        .quoteSwap(amountIn, true)
+        .accounts({
+          pool: seeded.pool,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+        })
+        .view();
+
+      await program.methods
+        .swap(
+          amountIn,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
+        .accounts({ ...swapAccounts, recipientTokenOut: merchantTokenB })
+        .signers([user])
+        .rpc();
+
+      // The merchant receives the output; the user only pays the input
+      assert.equal(
+        (await getTokenBalance(merchantTokenB)).toString(),
+        quote.amountOut.toString()
+      );
+      assert.equal((await getTokenBalance(userTokenB)).toString(), "0");
+      assert.equal(
+        (await getTokenBalance(userTokenA)).toString(),
+        (10_000_000 - 1_000_000).toString()
+      );
+
+      // A recipient account for the wrong mint is rejected
+      await expectError(
+        program.methods
+          .swap(
+            amountIn,
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            null
+          )
+          .accounts({ ...swapAccounts, recipientTokenOut: merchantTokenA })
+          .signers([user])
+          .rpc(),
+        "InvalidRecipientMint"
+      );
+    });
+  });
+
+  describe("Remove Liquidity Recipients", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+
+    const removeAccounts = () => ({
+      pool: seeded.pool,
+      user: payer.publicKey,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      userTokenA: seeded.payerTokenA,
+      userTokenB: seeded.payerTokenB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+      userLp: seeded.payerLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+    });
+
+    it("Should route withdrawn tokens to a separate wallet", async () => {
+      const wallet = Keypair.generate();
+      const walletTokenA = await fundAccount(
+        seeded.mintA,
+        wallet.publicKey,
+        0
+      );
+      const walletTokenB = await fundAccount(
+        seeded.mintB,
+        wallet.publicKey,
+        0
+      );
+      const userABefore = await getTokenBalance(seeded.payerTokenA);
+      const userBBefore = await getTokenBalance(seeded.payerTokenB);
+      const lpAmount = (await getTokenBalance(seeded.payerLp)).divn(4);
+
+      await program.methods
+        .removeLiquidity(
+          lpAmount,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          false,
+          new anchor.BN(0)
+        )
+        .accounts({
+          ...removeAccounts(),
+          recipientTokenA: walletTokenA,
+          recipientTokenB: walletTokenB,
+        })
+        .rpc();
+
+      assert.equal(
+        (await getTokenBalance(walletTokenA)).toString(),
+        "250000000"
+      );
+      assert.equal(
+        (await getTokenBalance(walletTokenB)).toString(),
+        "250000000"
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA)).toString(),
+        userABefore.toString()
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenB)).toString(),
+        userBBefore.toString()
+      );
+    });
+
+    it("Should pay the user when no recipients are given", async () => {
+      const userABefore = await getTokenBalance(seeded.payerTokenA);
+      const userBBefore = await getTokenBalance(seeded.payerTokenB);
+      const lpAmount = (await getTokenBalance(seeded.payerLp)).divn(3);
+
+      await program.methods
+        .removeLiquidity(
+          lpAmount,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          false,
+          new anchor.BN(0)
+        )
+        .accounts(removeAccounts())
+        .rpc();
+
+      const userAAfter = await getTokenBalance(seeded.payerTokenA);
+      const userBAfter = await getTokenBalance(seeded.payerTokenB);
+      assert.equal(userAAfter.sub(userABefore).toString(), "250000000");
+      assert.equal(userBAfter.sub(userBBefore).toString(), "250000000");
+    });
+
+    it("Should reject a recipient for the wrong mint", async () => {
+      await expectError(
+        program.methods
+          .removeLiquidity(
+            new anchor.BN(1_000),
+            new anchor.BN(0),
+            new anchor.BN(0),
+            false,
+            new anchor.BN(0)
+          )
+          .accounts({
+            ...removeAccounts(),
+            recipientTokenA: seeded.payerTokenB,
+          })
+          .rpc(),
+        "InvalidRecipientMint"
+      );
+    });
+  });
+
+  describe("Limit Price Swaps", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let userTokenA: PublicKey;
+    let userTokenB: PublicKey;
+    const user = Keypair.generate();
+    const amountIn = new anchor.BN(1_000_000);
+    const u64Max = new anchor.BN("18446744073709551615");
+
+    const swapWithLimit = (numerator: anchor.BN, denominator: anchor.BN) =>
+      program.methods
+        .swap(
+          amountIn,
+          new anchor.BN(0),
+          numerator,
+          denominator,
+          new anchor.BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    const quoteAmountOut = async () =>
+      (
+        await program.methods
+          .quoteSwap(amountIn, true)
+          .accounts({
+            pool: seeded.pool,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+          })
+          .view()
+      ).amountOut;
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 2_000_000_000);
+      await ensureSolBalance(user);
+      userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        100_000_000
+      );
+      userTokenB = await fundAccount(seeded.mintB, user.publicKey, 0);
+    });
+
+    it("Should fill at exactly the limit price", async () => {
+      const amountOut = await quoteAmountOut();
+      await swapWithLimit(amountOut, amountIn);
+    });
+
+    it("Should reject a limit just above the execution price", async () => {
+      const amountOut = await quoteAmountOut();
+      // (amount_out * k + 1) / (amount_in * k) is above the execution price by
+      // less than one unit of output
+      const k = new anchor.BN(1_000_000);
+      await expectError(
+        swapWithLimit(amountOut.mul(k).addn(1), amountIn.mul(k)),
+        "LimitPriceExceeded"
+      );
+    });
+
+    it("Should fill just below the limit price", async () => {
+      const amountOut = await quoteAmountOut();
+      const k = new anchor.BN(1_000_000);
+      await swapWithLimit(amountOut.mul(k).subn(1), amountIn.mul(k));
+    });
+
+    it("Should handle extreme ratios", async () => {
+      await swapWithLimit(new anchor.BN(1), u64Max);
+      await expectError(
+        swapWithLimit(u64Max, new anchor.BN(1)),
+        "LimitPriceExceeded"
+      );
+    });
+
+    it("Should reject a zero denominator unless disabled", async () => {
+      await expectError(
+        swapWithLimit(new anchor.BN(1), new anchor.BN(0)),
+        "InvalidLimitPrice"
+      );
+      await swapWithLimit(new anchor.BN(0), new anchor.BN(0));
+    });
+  });
+
+  describe("Donations", () => {
+    it("Should raise the value of each LP share without minting", async () => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      const donor = Keypair.generate();
+      await ensureSolBalance(donor);
+      const donorTokenA = await fundAccount(
+        seeded.mintA,
+        donor.publicKey,
+        100_000_000
+      );
+      const donorTokenB = await fundAccount(
+        seeded.mintB,
+        donor.publicKey,
+        50_000_000
+      );
+
+      const supplyBefore = (await getMint(provider.connection, seeded.lpMint))
+        .supply;
+      const vaultABefore = await getTokenBalance(seeded.vaultA);
+      const vaultBBefore = await getTokenBalance(seeded.vaultB);
+
+      await program.methods
+        .donate(new anchor.BN(100_000_000), new anchor.BN(50_000_000))
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          donor: donor.publicKey,
+          donorTokenA,
+          donorTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([donor])
+        .rpc();
+
+      const supplyAfter = (await getMint(provider.connection, seeded.lpMint))
+        .supply;
+      const vaultAAfter = await getTokenBalance(seeded.vaultA);
+      const vaultBAfter = await getTokenBalance(seeded.vaultB);
+
+      // No LP is minted, so the same supply now backs larger reserves
+      assert.equal(supplyAfter.toString(), supplyBefore.toString());
+      const supply = bigintToBN(supplyAfter);
+      assert.ok(vaultAAfter.div(supply).gt(vaultABefore.div(supply)));
+      assert.ok(vaultBAfter.div(supply).gt(vaultBBefore.div(supply)));
+
+      // The donation is tracked, so there is nothing left to skim
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.reserveA.toString(), vaultAAfter.toString());
+      assert.equal(poolAccount.reserveB.toString(), vaultBAfter.toString());
+    });
+  });
+
+  describe("Liquidity Migration", () => {
+    let source: Awaited<ReturnType<typeof createSeededPool>>;
+    let destination: Awaited<ReturnType<typeof createSeededPool>>;
+
+    const migrateAccounts = (
+      from: typeof source,
+      to: typeof destination
+    ) => ({
+      user: payer.publicKey,
+      sourcePool: from.pool,
+      destinationPool: to.pool,
+      tokenAMint: from.mintA,
+      tokenBMint: from.mintB,
+      userTokenA: from.payerTokenA,
+      userTokenB: from.payerTokenB,
+      sourcePoolTokenA: from.vaultA,
+      sourcePoolTokenB: from.vaultB,
+      sourceLpMint: from.lpMint,
+      userSourceLp: from.payerLp,
+      destinationPoolTokenA: to.vaultA,
+      destinationPoolTokenB: to.vaultB,
+      destinationLpMint: to.lpMint,
+      userDestinationLp: to.payerLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    before(async () => {
+      // A 0.3% pool at 1:1 and a 0.05% pool at 1:2 on the same pair
+      source = await createSeededPool(1_000_000_000, 1_000_000_000, 30);
+      destination = await createSeededPool(
+        500_000_000,
+        1_000_000_000,
+        5,
+        { mintA: source.mintA, mintB: source.mintB, poolIndex: 1 }
+      );
+    });
+
+    it("Should move a position between fee tiers without stranding tokens", async () => {
+      const snapshot = async () => ({
+        userA: await getTokenBalance(source.payerTokenA),
+        userB: await getTokenBalance(source.payerTokenB),
+        sourceA: await getTokenBalance(source.vaultA),
+        sourceB: await getTokenBalance(source.vaultB),
+        destinationA: await getTokenBalance(destination.vaultA),
+        destinationB: await getTokenBalance(destination.vaultB),
+        destinationLp: await getTokenBalance(destination.payerLp),
+      });
+      const before = await snapshot();
+      const lpAmount = (await getTokenBalance(source.payerLp)).divn(2);
+
+      await program.methods
+        .migrateLiquidity(lpAmount, new anchor.BN(0))
+        .accounts(migrateAccounts(source, destination))
+        .rpc();
+
+      const after = await snapshot();
+      const withdrawnA = before.sourceA.sub(after.sourceA);
+      const withdrawnB = before.sourceB.sub(after.sourceB);
+      const depositedA = after.destinationA.sub(before.destinationA);
+      const depositedB = after.destinationB.sub(before.destinationB);
+      const leftoverA = after.userA.sub(before.userA);
+      const leftoverB = after.userB.sub(before.userB);
+
+      // Half of the source pool comes out; the destination takes it at 1:2
+      assert.equal(withdrawnA.toString(), "500000000");
+      assert.equal(withdrawnB.toString(), "500000000");
+      assert.equal(depositedA.toString(), "250000000");
+      assert.equal(depositedB.toString(), "500000000");
+
+      // Every withdrawn token ends up in the destination or back with the user
+      assert.equal(withdrawnA.toString(), depositedA.add(leftoverA).toString());
+      assert.equal(withdrawnB.toString(), depositedB.add(leftoverB).toString());
+
+      assert.equal(
+        after.destinationLp.sub(before.destinationLp).toString(),
+        "500000"
+      );
+    });
+
+    it("Should reject migrating into the source pool", async () => {
+      await expectError(
+        program.methods
+          .migrateLiquidity(new anchor.BN(1_000), new anchor.BN(0))
+          .accounts(migrateAccounts(source, source))
+          .rpc(),
+        "InvalidMigration"
+      );
+    });
+
+    it("Should enforce min_lp_out", async () => {
+      await expectError(
+        program.methods
+          .migrateLiquidity(new anchor.BN(1_000), new anchor.BN(1_000_000_000))
+          .accounts(migrateAccounts(source, destination))
+          .rpc(),
+        "SlippageExceeded"
+      );
+    });
+  });
+
+  describe("Swap With Output Account Creation", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+
+    const swapAndCreate = (
+      user: Keypair,
+      userTokenIn: PublicKey,
+      userTokenOut: PublicKey,
+      feePayer: Keypair = user
+    ) =>
+      program.methods
+        .swapAndCreate(
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          payer: feePayer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn,
+          userTokenOut,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers(feePayer === user ? [user] : [user, feePayer])
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+    });
+
+    it("Should create the output ATA for a fresh wallet", async () => {
+      const user = Keypair.generate();
+      await ensureSolBalance(user);
+      const userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        10_000_000
+      );
+      const userTokenB = getAssociatedTokenAddressSync(
+        seeded.mintB,
+        user.publicKey
+      );
+      assert.isNull(await provider.connection.getAccountInfo(userTokenB));
+
+      await swapAndCreate(user, userTokenA, userTokenB);
+      const firstBalance = await getTokenBalance(userTokenB);
+      assert.ok(firstBalance.gtn(0));
+
+      // The existing ATA is reused as is on the next swap
+      await swapAndCreate(user, userTokenA, userTokenB);
+      const secondBalance = await getTokenBalance(userTokenB);
+      assert.ok(secondBalance.gt(firstBalance));
+    });
+
+    it("Should leave pre-existing non-ATA accounts to swap", async () => {
+      const user = Keypair.generate();
+      await ensureSolBalance(user);
+      const userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        10_000_000
+      );
+      const nonAtaTokenB = await createAccount(
+        provider.connection,
+        payer,
+        seeded.mintB,
+        user.publicKey,
+        Keypair.generate()
+      );
+
+      await expectError(
+        swapAndCreate(user, userTokenA, nonAtaTokenB),
+        "AccountNotAssociatedTokenAccount"
+      );
+
+      // The plain swap still accepts any token account
+      await program.methods
+        .swap(
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: userTokenA,
+          userTokenOut: nonAtaTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+      assert.ok((await getTokenBalance(nonAtaTokenB)).gtn(0));
+    });
+
+    it("Should let a relayer pay for the output ATA", async () => {
+      // The owner holds tokens but no SOL; only its token authority is used
+      const user = Keypair.generate();
+      const relayer = Keypair.generate();
+      await ensureSolBalance(relayer);
+      const userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        10_000_000
+      );
+      const userTokenB = getAssociatedTokenAddressSync(
+        seeded.mintB,
+        user.publicKey
+      );
+      const relayerBefore = await provider.connection.getBalance(
+        relayer.publicKey
+      );
+
+      await swapAndCreate(user, userTokenA, userTokenB, relayer);
+
+      assert.ok((await getTokenBalance(userTokenB)).gtn(0));
+      assert.equal(await provider.connection.getBalance(user.publicKey), 0);
+      const rent = (await provider.connection.getAccountInfo(userTokenB))
+        .lamports;
+      const relayerAfter = await provider.connection.getBalance(
+        relayer.publicKey
+      );
+      assert.ok(relayerBefore - relayerAfter >= rent);
+    });
+  });
+
+  describe("Swap V2", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let userTokenA: PublicKey;
+    let userTokenB: PublicKey;
+    const user = Keypair.generate();
+
+    const swapV2 = (
+      amountIn: anchor.BN,
+      aToB: boolean,
+      overrides: Record<string, PublicKey> = {}
+    ) =>
+      program.methods
+        .swapV2(amountIn, new anchor.BN(0), aToB, new anchor.BN(0))
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          user: user.publicKey,
+          userTokenA,
+          userTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          ownerTokenAccount: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...overrides,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 2_000_000_000);
+      await ensureSolBalance(user);
+      userTokenA = await fundAccount(seeded.mintA, user.publicKey, 50_000_000);
+      userTokenB = await fundAccount(seeded.mintB, user.publicKey, 50_000_000);
+    });
+
+    for (const aToB of [true, false]) {
+      it(`Should swap ${aToB ? "A to B" : "B to A"} with fixed account order`, async () => {
+        const amountIn = new anchor.BN(1_000_000);
+        const quote = await program.methods
+          .quoteSwap(amountIn, aToB)
+          .accounts({
+            pool: seeded.pool,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+          })
+          .view();
+        const inAccount = aToB ? userTokenA : userTokenB;
+        const outAccount = aToB ? userTokenB : userTokenA;
+        const inBefore = await getTokenBalance(inAccount);
+        const outBefore = await getTokenBalance(outAccount);
+
+        await swapV2(amountIn, aToB);
+
+        const inAfter = await getTokenBalance(inAccount);
+        const outAfter = await getTokenBalance(outAccount);
+        assert.equal(inBefore.sub(inAfter).toString(), amountIn.toString());
+        assert.equal(
+          outAfter.sub(outBefore).toString(),
+          quote.amountOut.toString()
+        );
+      });
+    }
+
+    it("Should reject user accounts wired backwards", async () => {
+      await expectError(
+        swapV2(new anchor.BN(1_000_000), true, {
+          userTokenA: userTokenB,
+          userTokenB: userTokenA,
+        }),
+        "ConstraintTokenMint"
+      );
+    });
+
+    it("Should reject a fee account for the output mint", async () => {
+      await expectError(
+        swapV2(new anchor.BN(1_000_000), true, {
+          ownerTokenAccount: seeded.payerTokenB,
+        }),
+        "InvalidFeeAccount"
+      );
+    });
+  });
+
+  describe("Exact Output Swaps", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let userTokenA: PublicKey;
+    let userTokenB: PublicKey;
+    const user = Keypair.generate();
+
+    const swapExactOut = (
+      amountOut: anchor.BN,
+      maxAmountIn: anchor.BN,
+      allowPartial: boolean
+    ) =>
+      program.methods
+        .swapExactOut(amountOut, maxAmountIn, allowPartial, new anchor.BN(0))
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    // Runs an exact-out swap and returns the token deltas and its event
+    const runSwap = async (
+      amountOut: anchor.BN,
+      maxAmountIn: anchor.BN,
+      allowPartial: boolean
+    ) => {
+      const inBefore = await getTokenBalance(userTokenA);
+      const outBefore = await getTokenBalance(userTokenB);
+      const signature = await swapExactOut(
+        amountOut,
+        maxAmountIn,
+        allowPartial
+      );
+      const event = await getEvent(signature, "swapExecutedEvent");
+      return {
+        paid: inBefore.sub(await getTokenBalance(userTokenA)),
+        received: (await getTokenBalance(userTokenB)).sub(outBefore),
+        fillBps: event.fillBps as number,
+      };
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 2_000_000_000);
+      await ensureSolBalance(user);
+      userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        5_000_000_000
+      );
+      userTokenB = await fundAccount(seeded.mintB, user.publicKey, 0);
+    });
+
+    it("Should fill the full amount within max_amount_in", async () => {
+      const amountOut = new anchor.BN(1_000_000);
+      const { paid, received, fillBps } = await runSwap(
+        amountOut,
+        new anchor.BN(100_000_000),
+        false
+      );
+
+      assert.ok(received.gte(amountOut));
+      // At 1:2 and 0.3% fee, 1,000,000 B costs just over 500,000 A
+      assert.ok(paid.gtn(500_000) && paid.ltn(505_000));
+      assert.equal(fillBps, 10_000);
+    });
+
+    it("Should partially fill at the input cap", async () => {
+      const amountOut = new anchor.BN(10_000_000);
+      const maxAmountIn = new anchor.BN(1_000_000);
+      await expectError(
+        swapExactOut(amountOut, maxAmountIn, false),
+        "SlippageExceeded"
+      );
+
+      const { paid, received, fillBps } = await runSwap(
+        amountOut,
+        maxAmountIn,
+        true
+      );
+
+      // The whole budget is spent and buys about a fifth of the request
+      assert.equal(paid.toString(), maxAmountIn.toString());
+      assert.ok(received.gtn(0) && received.lt(amountOut));
+      assert.equal(fillBps, received.muln(10_000).div(amountOut).toNumber());
+    });
+
+    it("Should partially fill an output beyond the reserves", async () => {
+      const reserveOut = await getTokenBalance(seeded.vaultB);
+      const amountOut = reserveOut.addn(1);
+      const maxAmountIn = new anchor.BN(1_000_000_000);
+      await expectError(
+        swapExactOut(amountOut, maxAmountIn, false),
+        "InsufficientLiquidity"
+      );
+
+      const { paid, received, fillBps } = await runSwap(
+        amountOut,
+        maxAmountIn,
+        true
+      );
+
+      // Doubling the input reserve buys about half of the output reserve
+      assert.equal(paid.toString(), maxAmountIn.toString());
+      assert.ok(received.lt(reserveOut));
+      assert.ok(fillBps > 4_900 && fillBps < 5_100);
+      assert.ok((await getTokenBalance(seeded.vaultB)).gtn(0));
+    });
+  });
+
+  describe("Liquidity Locks", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+
+    const findLock = (lockIndex: number) => {
+      const index = Buffer.alloc(8);
+      index.writeBigUInt64LE(BigInt(lockIndex));
+      return PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("lock"),
+          seeded.pool.toBuffer(),
+          payer.publicKey.toBuffer(),
+          index,
+        ],
+        program.programId
+      )[0];
+    };
+
+    const findLockVault = (lock: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("lock_vault"), lock.toBuffer()],
+        program.programId
+      )[0];
+
+    const lockLiquidity = (
+      lockIndex: number,
+      amount: number,
+      unlockTimestamp: number
+    ) => {
+      const lock = findLock(lockIndex);
+      return program.methods
+        .lockLiquidity(
+          new anchor.BN(lockIndex),
+          new anchor.BN(amount),
+          new anchor.BN(unlockTimestamp)
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          lock,
+          lpMint: seeded.lpMint,
+          lockVault: findLockVault(lock),
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+    };
+
+    const extendLock = (lockIndex: number, unlockTimestamp: number) =>
+      program.methods
+        .extendLock(new anchor.BN(unlockTimestamp))
+        .accounts({ owner: payer.publicKey, lock: findLock(lockIndex) })
+        .rpc();
+
+    const unlockLiquidity = (lockIndex: number) => {
+      const lock = findLock(lockIndex);
+      return program.methods
+        .unlockLiquidity()
+        .accounts({
+          lpMint: seeded.lpMint,
+          owner: payer.publicKey,
+          lock,
+          lockVault: findLockVault(lock),
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+    });
+
+    it("Should escrow LP tokens and reject an early unlock", async () => {
+      const now = await getClusterTime();
+      const lpBefore = await getTokenBalance(seeded.payerLp);
+
+      await expectError(
+        lockLiquidity(0, 100_000, now - 1),
+        "InvalidUnlockTimestamp"
+      );
+      await lockLiquidity(0, 100_000, now + 3600);
+
+      const lock = await program.account.liquidityLock.fetch(findLock(0));
+      assert.ok(lock.owner.equals(payer.publicKey));
+      assert.ok(lock.pool.equals(seeded.pool));
+      assert.equal(lock.amount.toString(), "100000");
+      assert.equal(lock.unlockTimestamp.toNumber(), now + 3600);
+      assert.equal(
+        (await getTokenBalance(findLockVault(findLock(0)))).toString(),
+        "100000"
+      );
+      assert.equal(
+        lpBefore.sub(await getTokenBalance(seeded.payerLp)).toString(),
+        "100000"
+      );
+
+      await expectError(unlockLiquidity(0), "LiquidityStillLocked");
+    });
+
+    it("Should extend a lock but never shorten it", async () => {
+      const current = (await program.account.liquidityLock.fetch(findLock(0)))
+        .unlockTimestamp;
+
+      await extendLock(0, current.toNumber() + 3600);
+      const extended = await program.account.liquidityLock.fetch(findLock(0));
+      assert.equal(
+        extended.unlockTimestamp.toNumber(),
+        current.toNumber() + 3600
+      );
+
+      await expectError(
+        extendLock(0, current.toNumber()),
+        "InvalidUnlockTimestamp"
+      );
+      await expectError(
+        extendLock(0, extended.unlockTimestamp.toNumber()),
+        "InvalidUnlockTimestamp"
+      );
+    });
+
+    it("Should unlock once the unlock timestamp has passed", async () => {
+      // A second concurrent lock for the same owner, with a short time lock
+      const unlockTimestamp = (await getClusterTime()) + 2;
+      await lockLiquidity(1, 50_000, unlockTimestamp);
+      const lpBefore = await getTokenBalance(seeded.payerLp);
+
+      while ((await getClusterTime()) < unlockTimestamp) {
+        await sleep(1000);
+      }
+      await unlockLiquidity(1);
+
+      assert.equal(
+        (await getTokenBalance(seeded.payerLp)).sub(lpBefore).toString(),
+        "50000"
+      );
+      assert.isNull(await provider.connection.getAccountInfo(findLock(1)));
+      assert.isNull(
+        await provider.connection.getAccountInfo(findLockVault(findLock(1)))
+      );
+
+      // The first lock is untouched
+      await expectError(unlockLiquidity(0), "LiquidityStillLocked");
+    });
+  });
+
+  describe("Permanent LP Burns", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let burnedLiquidity: PublicKey;
+
+    const burnLp = (lpAmount: number) =>
+      program.methods
+        .burnLpPermanently(new anchor.BN(lpAmount))
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          burnedLiquidity,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    const quoteRemove = (lpAmount: number) =>
+      program.methods
+        .quoteRemoveLiquidity(new anchor.BN(lpAmount))
+        .accounts({
+          pool: seeded.pool,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+        })
+        .view();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      [burnedLiquidity] = PublicKey.findProgramAddressSync(
+        [Buffer.from("burned"), seeded.pool.toBuffer()],
+        program.programId
+      );
+    });
+
+    it("Should accumulate the burned total across burns", async () => {
+      const lpBefore = await getTokenBalance(seeded.payerLp);
+
+      await burnLp(100_000);
+      let burned = await program.account.burnedLiquidity.fetch(
+        burnedLiquidity
+      );
+      assert.ok(burned.pool.equals(seeded.pool));
+      assert.equal(burned.totalBurned.toString(), "100000");
+
+      const signature = await burnLp(150_000);
+      burned = await program.account.burnedLiquidity.fetch(burnedLiquidity);
+      assert.equal(burned.totalBurned.toString(), "250000");
+
+      const event = await getEvent(signature, "liquidityBurnedEvent");
+      assert.equal(event.lpBurned.toString(), "150000");
+      assert.equal(event.totalBurned.toString(), "250000");
+      assert.equal(event.remainingLpSupply.toString(), "750000");
+
+      // The tokens are gone from the wallet and the mint supply
+      assert.equal(
+        lpBefore.sub(await getTokenBalance(seeded.payerLp)).toString(),
+        "250000"
+      );
+      const supply = (await getMint(provider.connection, seeded.lpMint))
+        .supply;
+      assert.equal(supply.toString(), "750000");
+    });
+
+    it("Should make the remaining LP redeem for more", async () => {
+      // 750,000 LP now claim the full reserves of 1,000,000,000 each
+      const quote = await quoteRemove(75_000);
+      assert.equal(quote.amountA.toString(), "100000000");
+      assert.equal(quote.amountB.toString(), "100000000");
+
+      const userTokenA = await fundAccount(seeded.mintA, payer.publicKey, 0);
+      const userTokenB = await fundAccount(seeded.mintB, payer.publicKey, 0);
+      const aBefore = await getTokenBalance(userTokenA);
+      await program.methods
+        .removeLiquidity(
+          new anchor.BN(75_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          false,
+          new anchor.BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA,
+          userTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.equal(
+        (await getTokenBalance(userTokenA)).sub(aBefore).toString(),
+        "100000000"
+      );
+    });
+
+    it("Should reject a zero burn", async () => {
+      await expectError(burnLp(0), "InvalidAmount");
+    });
+  });
+
+  describe("Liquidity Mining", () => {
+    const rewardRate = 1_000;
+
+    // Creates a farm on a fresh pool, funded with `funding` reward tokens
+    const createFarm = async (funding: number) => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      const rewardMint = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        6
+      );
+      const [farm] = PublicKey.findProgramAddressSync(
+        [Buffer.from("farm"), seeded.pool.toBuffer()],
+        program.programId
+      );
+      const [stakeVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("farm_stake"), farm.toBuffer()],
+        program.programId
+      );
+      const [rewardVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("farm_reward"), farm.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .initializeFarm(new anchor.BN(rewardRate))
+        .accounts({
+          pool: seeded.pool,
+          authority: payer.publicKey,
+          farm,
+          lpMint: seeded.lpMint,
+          rewardMint,
+          stakeVault,
+          rewardVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rewardTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+      const payerReward = await fundAccount(
+        rewardMint,
+        payer.publicKey,
+        1_000_000_000
+      );
+      const fund = (amount: number) =>
+        program.methods
+          .fundFarm(new anchor.BN(amount))
+          .accounts({
+            rewardMint,
+            funder: payer.publicKey,
+            farm,
+            rewardVault,
+            funderRewardToken: payerReward,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+      await fund(funding);
+
+      return { seeded, rewardMint, farm, stakeVault, rewardVault, fund };
+    };
+
+    type TestFarm = Awaited<ReturnType<typeof createFarm>>;
+
+    // A staker holding `lpAmount` LP tokens of the farm's pool
+    const createStaker = async (testFarm: TestFarm, lpAmount: number) => {
+      const user = Keypair.generate();
+      await ensureSolBalance(user);
+      const userLp = await fundAccount(
+        testFarm.seeded.lpMint,
+        user.publicKey,
+        0
+      );
+      await transfer(
+        provider.connection,
+        payer,
+        testFarm.seeded.payerLp,
+        userLp,
+        payer,
+        lpAmount
+      );
+      const userReward = await fundAccount(
+        testFarm.rewardMint,
+        user.publicKey,
+        0
+      );
+      const [position] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("stake"),
+          testFarm.farm.toBuffer(),
+          user.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+      return { user, userLp, userReward, position };
+    };
+
+    type Staker = Awaited<ReturnType<typeof createStaker>>;
+
+    const stakeIx = (testFarm: TestFarm, staker: Staker, amount: number) =>
+      program.methods
+        .stake(new anchor.BN(amount))
+        .accounts({
+          lpMint: testFarm.seeded.lpMint,
+          owner: staker.user.publicKey,
+          farm: testFarm.farm,
+          position: staker.position,
+          stakeVault: testFarm.stakeVault,
+          userLp: staker.userLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .instruction();
+
+    const unstake = (testFarm: TestFarm, staker: Staker, amount: number) =>
+      program.methods
+        .unstake(new anchor.BN(amount))
+        .accounts({
+          lpMint: testFarm.seeded.lpMint,
+          owner: staker.user.publicKey,
+          farm: testFarm.farm,
+          position: staker.position,
+          stakeVault: testFarm.stakeVault,
+          userLp: staker.userLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([staker.user])
+        .rpc();
+
+    const claimIx = (testFarm: TestFarm, staker: Staker) =>
+      program.methods
+        .claimRewards()
+        .accounts({
+          rewardMint: testFarm.rewardMint,
+          owner: staker.user.publicKey,
+          farm: testFarm.farm,
+          position: staker.position,
+          rewardVault: testFarm.rewardVault,
+          userRewardToken: staker.userReward,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .instruction();
+
+    // Sends the given staker instructions in one transaction, so they all
+    // see the same clock
+    const sendTogether = async (
+      instructions: anchor.web3.TransactionInstruction[],
+      stakers: Staker[]
+    ) => {
+      await provider.sendAndConfirm(
+        new Transaction().add(...instructions),
+        stakers.map((staker) => staker.user)
+      );
+    };
+
+    const lastUpdate = async (testFarm: TestFarm) =>
+      (
+        await program.account.farm.fetch(testFarm.farm)
+      ).lastUpdateTimestamp.toNumber();
+
+    const waitSeconds = async (seconds: number) =>
+      waitUntil((await getClusterTime()) + seconds);
+
+    const setEmissionSchedule = (
+      testFarm: TestFarm,
+      entries: [number, number][],
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .setEmissionSchedule(
+          entries.map(([startTimestamp, rewardRate]) => ({
+            startTimestamp: new anchor.BN(startTimestamp),
+            rewardRate: new anchor.BN(rewardRate),
+          }))
+        )
+        .accounts({ authority: authority.publicKey, farm: testFarm.farm })
+        .signers([authority])
+        .rpc();
+
+    let testFarm: TestFarm;
+    let alice: Staker;
+    let bob: Staker;
+    let stakedAt: number;
+    let firstClaimAt: number;
+
+    before(async () => {
+      testFarm = await createFarm(100_000_000);
+      alice = await createStaker(testFarm, 100_000);
+      bob = await createStaker(testFarm, 300_000);
+    });
+
+    it("Should split rewards in proportion to stake", async () => {
+      await sendTogether(
+        [
+          await stakeIx(testFarm, alice, 100_000),
+          await stakeIx(testFarm, bob, 300_000),
+        ],
+        [alice, bob]
+      );
+      stakedAt = await lastUpdate(testFarm);
+      assert.equal(
+        (await getTokenBalance(testFarm.stakeVault)).toString(),
+        "400000"
+      );
+
+      await waitSeconds(3);
+      await sendTogether(
+        [await claimIx(testFarm, alice), await claimIx(testFarm, bob)],
+        [alice, bob]
+      );
+      firstClaimAt = await lastUpdate(testFarm);
+
+      // Alice holds a quarter of the stake and Bob three quarters
+      const emitted = rewardRate * (firstClaimAt - stakedAt);
+      assert.ok(emitted > 0);
+      assert.equal(
+        (await getTokenBalance(alice.userReward)).toNumber(),
+        emitted / 4
+      );
+      assert.equal(
+        (await getTokenBalance(bob.userReward)).toNumber(),
+        (emitted * 3) / 4
+      );
+    });
+
+    it("Should stop rewards on unstake and keep them pending", async () => {
+      await expectError(
+        unstake(testFarm, alice, 100_001),
+        "InsufficientStake"
+      );
+
+      await waitSeconds(2);
+      await unstake(testFarm, alice, 100_000);
+      const unstakedAt = await lastUpdate(testFarm);
+      assert.equal((await getTokenBalance(alice.userLp)).toString(), "100000");
+
+      await waitSeconds(2);
+      const aliceBefore = await getTokenBalance(alice.userReward);
+      const bobBefore = await getTokenBalance(bob.userReward);
+      await sendTogether(
+        [await claimIx(testFarm, alice), await claimIx(testFarm, bob)],
+        [alice, bob]
+      );
+      const claimedAt = await lastUpdate(testFarm);
+
+      // Alice earned her quarter until she left; Bob then earned everything,
+      // less rounding in the per-share accumulator
+      const shared = rewardRate * (unstakedAt - firstClaimAt);
+      const solo = rewardRate * (claimedAt - unstakedAt);
+      assert.equal(
+        (await getTokenBalance(alice.userReward)).sub(aliceBefore).toNumber(),
+        shared / 4
+      );
+      assert.approximately(
+        (await getTokenBalance(bob.userReward)).sub(bobBefore).toNumber(),
+        (shared * 3) / 4 + solo,
+        1
+      );
+    });
+
+    it("Should pay up to the vault balance when it runs dry", async () => {
+      const dryFarm = await createFarm(500);
+      const carol = await createStaker(dryFarm, 100_000);
+      await sendTogether([await stakeIx(dryFarm, carol, 100_000)], [carol]);
+
+      await waitSeconds(2);
+      await sendTogether([await claimIx(dryFarm, carol)], [carol]);
+
+      // Only the 500 in the vault are paid; the rest is still owed
+      assert.equal((await getTokenBalance(carol.userReward)).toString(), "500");
+      assert.equal(
+        (await getTokenBalance(dryFarm.rewardVault)).toString(),
+        "0"
+      );
+      const owed = (await program.account.stakePosition.fetch(carol.position))
+        .pendingRewards;
+      assert.ok(owed.gtn(0));
+
+      // Once refunded, the owed rewards are paid on the next claim
+      await dryFarm.fund(1_000_000);
+      await sendTogether([await claimIx(dryFarm, carol)], [carol]);
+      assert.ok((await getTokenBalance(carol.userReward)).gte(owed.addn(500)));
+    });
+
+    it("Should halve the emission rate mid-staking-period", async () => {
+      const halvingFarm = await createFarm(100_000_000);
+      const dave = await createStaker(halvingFarm, 100_000);
+      await sendTogether([await stakeIx(halvingFarm, dave, 100_000)], [dave]);
+      const stakedAt = await lastUpdate(halvingFarm);
+
+      const halvingAt = stakedAt + 4;
+      await setEmissionSchedule(halvingFarm, [[halvingAt, rewardRate / 2]]);
+      await waitUntil(halvingAt + 2);
+      await sendTogether([await claimIx(halvingFarm, dave)], [dave]);
+      const claimedAt = await lastUpdate(halvingFarm);
+
+      // Dave is the only staker: full rate until the halving, half after
+      const expected =
+        rewardRate * (halvingAt - stakedAt) +
+        (rewardRate / 2) * (claimedAt - halvingAt);
+      assert.equal(
+        (await getTokenBalance(dave.userReward)).toNumber(),
+        expected
+      );
+    });
+
+    it("Should integrate several schedule entries without interaction", async () => {
+      const scheduledFarm = await createFarm(100_000_000);
+      const erin = await createStaker(scheduledFarm, 100_000);
+      await sendTogether([await stakeIx(scheduledFarm, erin, 100_000)], [erin]);
+      const stakedAt = await lastUpdate(scheduledFarm);
+
+      const firstAt = stakedAt + 5;
+      const secondAt = stakedAt + 8;
+      await setEmissionSchedule(scheduledFarm, [
+        [firstAt, 500],
+        [secondAt, 250],
+      ]);
+
+      // Nobody touches the farm until both entries have started
+      await waitUntil(secondAt + 2);
+      await sendTogether([await claimIx(scheduledFarm, erin)], [erin]);
+      const claimedAt = await lastUpdate(scheduledFarm);
+
+      const expected =
+        rewardRate * (firstAt - stakedAt) +
+        500 * (secondAt - firstAt) +
+        250 * (claimedAt - secondAt);
+      assert.equal(
+        (await getTokenBalance(erin.userReward)).toNumber(),
+        expected
+      );
+
+      // Both entries were consumed into the current rate
+      const farmAccount = await program.account.farm.fetch(scheduledFarm.farm);
+      assert.equal(farmAccount.rewardRatePerSecond.toNumber(), 250);
+      assert.equal(farmAccount.emissionSchedule.length, 0);
+    });
+
+    it("Should reject invalid emission schedules", async () => {
+      const now = await getClusterTime();
+
+      await expectError(
+        setEmissionSchedule(testFarm, [[now - 10, 500]]),
+        "InvalidEmissionSchedule"
+      );
+      await expectError(
+        setEmissionSchedule(testFarm, [
+          [now + 200, 500],
+          [now + 100, 250],
+        ]),
+        "InvalidEmissionSchedule"
+      );
+      await expectError(
+        setEmissionSchedule(testFarm, [[now + 100, 500]], alice.user),
+        "Unauthorized"
+      );
+
+      // Queued entries can only be followed by later ones
+      await setEmissionSchedule(testFarm, [[now + 100, 500]]);
+      await expectError(
+        setEmissionSchedule(testFarm, [[now + 50, 250]]),
+        "InvalidEmissionSchedule"
+      );
+
+      const tooMany: [number, number][] = Array.from(
+        { length: 8 },
+        (_, i) => [now + 200 + i, 100]
+      );
+      await expectError(
+        setEmissionSchedule(testFarm, tooMany),
+        "EmissionScheduleFull"
+      );
+    });
+  });
+
+  describe("Gated Pools", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const alice = Keypair.generate();
+    const stranger = Keypair.generate();
+
+    const findAllowlistEntry = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("allowlist"), seeded.pool.toBuffer(), wallet.toBuffer()],
+        program.programId
+      )[0];
+
+    const setGating = (gated: boolean, gatedLiquidity: boolean) =>
+      program.methods
+        .setGating(gated, gatedLiquidity)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+    const addToAllowlist = (wallet: PublicKey, authority: Keypair = payer) =>
+      program.methods
+        .addToAllowlist(wallet)
+        .accounts({
+          pool: seeded.pool,
+          authority: authority.publicKey,
+          allowlistEntry: findAllowlistEntry(wallet),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+    const fund = (mint: PublicKey, user: Keypair, amount: number) =>
+      fundAccount(mint, user.publicKey, amount);
+
+    const swapAs = async (user: Keypair, allowlistEntry: PublicKey | null) =>
+      program.methods
+        .swap(
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: await fund(seeded.mintA, user, 1_000_000),
+          userTokenOut: await fund(seeded.mintB, user, 0),
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          allowlistEntry,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    const depositAs = async (user: Keypair) =>
+      program.methods
+        .addLiquidity(
+          new anchor.BN(1_000_000),
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: await fund(seeded.mintA, user, 1_000_000),
+          userTokenB: await fund(seeded.mintB, user, 1_000_000),
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: await fund(seeded.lpMint, user, 0),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await ensureSolBalance(alice);
+      await ensureSolBalance(stranger);
+      await setGating(true, false);
+      await addToAllowlist(alice.publicKey);
+    });
+
+    it("Should let an allowlisted wallet swap", async () => {
+      const entry = await program.account.allowlistEntry.fetch(
+        findAllowlistEntry(alice.publicKey)
+      );
+      assert.ok(entry.pool.equals(seeded.pool));
+      assert.ok(entry.wallet.equals(alice.publicKey));
+
+      await swapAs(alice, findAllowlistEntry(alice.publicKey));
+    });
+
+    it("Should reject a stranger", async () => {
+      await expectError(swapAs(stranger, null), "NotAllowlisted");
+      // Someone else's entry does not help
+      await expectError(
+        swapAs(stranger, findAllowlistEntry(alice.publicKey)),
+        "NotAllowlisted"
+      );
+    });
+
+    it("Should only let the authority manage the allowlist", async () => {
+      await expectError(
+        addToAllowlist(stranger.publicKey, stranger),
+        "Unauthorized"
+      );
+    });
+
+    it("Should gate deposits separately from swaps", async () => {
+      // Swaps are gated but deposits are not
+      await depositAs(stranger);
+
+      await setGating(true, true);
+      await expectError(depositAs(stranger), "NotAllowlisted");
+    });
+
+    it("Should apply a removal immediately", async () => {
+      const entry = findAllowlistEntry(alice.publicKey);
+      await program.methods
+        .removeFromAllowlist()
+        .accounts({
+          pool: seeded.pool,
+          authority: payer.publicKey,
+          allowlistEntry: entry,
+        })
+        .rpc();
+      assert.isNull(await provider.connection.getAccountInfo(entry));
+
+      await expectError(swapAs(alice, null), "NotAllowlisted");
+
+      // Ungating reopens the pool to everyone
+      await setGating(false, false);
+      await swapAs(stranger, null);
+    });
+  });
+
+  describe("Trading Start", () => {
+    const user = Keypair.generate();
+
+    type SeededPool = Awaited<ReturnType<typeof createSeededPool>>;
+
+    const swapOn = async (seeded: SeededPool) => {
+      const userTokenIn = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        1_000_000
+      );
+      const userTokenOut = await fundAccount(seeded.mintB, user.publicKey, 0);
+      return program.methods
+        .swap(
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn,
+          userTokenOut,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+    };
+
+    const setTradeStart = (pool: PublicKey, tradeStartTimestamp: number) =>
+      program.methods
+        .setTradeStart(new anchor.BN(tradeStartTimestamp))
+        .accounts({ pool, authority: payer.publicKey })
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(user);
+    });
+
+    it("Should open swaps exactly at the trade start", async () => {
+      const tradeStart = (await getClusterTime()) + 4;
+      // The liquidity is seeded ahead of the launch
+      const seeded = await createSeededPool(
+        1_000_000_000,
+        1_000_000_000,
+        30,
+        undefined,
+        tradeStart
+      );
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.tradeStartTimestamp.toNumber(), tradeStart);
+
+      // Retry each second across the boundary; the first success must not
+      // come before the trade start
+      let succeededAt: number;
+      while (succeededAt === undefined) {
+        const now = await getClusterTime();
+        try {
+          const signature = await swapOn(seeded);
+          const transaction = await provider.connection.getTransaction(
+            signature,
+            { commitment: "confirmed", maxSupportedTransactionVersion: 0 }
+          );
+          succeededAt = transaction.blockTime;
+        } catch (error) {
+          assert.include(error.toString(), "TradingNotStarted");
+          assert.isBelow(now, tradeStart + 2);
+          await sleep(500);
+        }
+      }
+      assert.isAtLeast(succeededAt, tradeStart);
+      assert.isAtMost(succeededAt, tradeStart + 2);
+    });
+
+    it("Should only move the trade start earlier", async () => {
+      const tradeStart = (await getClusterTime()) + 3600;
+      const seeded = await createSeededPool(
+        1_000_000_000,
+        1_000_000_000,
+        30,
+        undefined,
+        tradeStart
+      );
+      await expectError(swapOn(seeded), "TradingNotStarted");
+
+      await expectError(
+        setTradeStart(seeded.pool, tradeStart + 60),
+        "InvalidTradeStart"
+      );
+
+      // Bringing the launch forward to now opens trading
+      await setTradeStart(seeded.pool, await getClusterTime());
+      await swapOn(seeded);
+    });
+  });
+
+  describe("Launch Buy Limits", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let tradeStart: number;
+    let userTokenA: PublicKey;
+    let userTokenB: PublicKey;
+    let launchPurchase: PublicKey;
+    const user = Keypair.generate();
+    const windowSeconds = 15;
+    const maxBuy = new anchor.BN(2_500_000);
+
+    const setLaunchLimit = () =>
+      program.methods
+        .setLaunchLimit(seeded.mintB, new anchor.BN(windowSeconds), maxBuy)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+    // Buys token B (the launched token) with 1,000,000 of token A
+    const buy = (withLaunchPurchase: boolean = true) =>
+      program.methods
+        .swap(
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          payer: withLaunchPurchase ? user.publicKey : null,
+          launchPurchase: withLaunchPurchase ? launchPurchase : null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: withLaunchPurchase ? SystemProgram.programId : null,
+        })
+        .signers([user])
+        .rpc();
+
+    const closeLaunchPurchase = () =>
+      program.methods
+        .closeLaunchPurchase()
+        .accounts({ pool: seeded.pool, launchPurchase, payer: user.publicKey })
+        .rpc();
+
+    before(async () => {
+      tradeStart = (await getClusterTime()) + 10;
+      seeded = await createSeededPool(
+        1_000_000_000,
+        1_000_000_000,
+        30,
+        undefined,
+        tradeStart
+      );
+      await setLaunchLimit();
+
+      await ensureSolBalance(user);
+      userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        10_000_000
+      );
+      userTokenB = await fundAccount(seeded.mintB, user.publicKey, 0);
+      [launchPurchase] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("launch"),
+          seeded.pool.toBuffer(),
+          user.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+    });
+
+    it("Should cap cumulative purchases per wallet in the window", async () => {
+      await waitUntil(tradeStart);
+      await expectError(setLaunchLimit(), "LaunchConfigLocked");
+      await expectError(buy(false), "MissingLaunchPurchase");
+
+      // The purchase account is created on the first buy
+      assert.isNull(await provider.connection.getAccountInfo(launchPurchase));
+      await buy();
+      await buy();
+
+      // Two buys of ~996,000 fit under the cap; a third would not
+      const received = await getTokenBalance(userTokenB);
+      const purchase = await program.account.launchPurchase.fetch(
+        launchPurchase
+      );
+      assert.ok(purchase.wallet.equals(user.publicKey));
+      assert.equal(purchase.purchased.toString(), received.toString());
+      await expectError(buy(), "LaunchLimitExceeded");
+    });
+
+    it("Should keep the purchase account until the window ends", async () => {
+      await expectError(closeLaunchPurchase(), "LaunchWindowActive");
+    });
+
+    it("Should lift the cap and refund the rent after the window", async () => {
+      await waitUntil(tradeStart + windowSeconds);
+
+      // No purchase account or cap is needed any more
+      await buy(false);
+
+      const rent = (await provider.connection.getAccountInfo(launchPurchase))
+        .lamports;
+      const lamportsBefore = await provider.connection.getBalance(
+        user.publicKey
+      );
+      await closeLaunchPurchase();
+      assert.isNull(await provider.connection.getAccountInfo(launchPurchase));
+      assert.equal(
+        await provider.connection.getBalance(user.publicKey),
+        lamportsBefore + rent
+      );
+    });
+  });
+
+  describe("Buyback And Burn", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let feeVaultA: PublicKey;
+    let feeVaultB: PublicKey;
+
+    const findFeeVault = (mint: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("fee_vault"), seeded.pool.toBuffer(), mint.toBuffer()],
+        program.programId
+      )[0];
+
+    const swapInto = (
+      amountIn: number,
+      aToB: boolean,
+      ownerTokenAccount: PublicKey
+    ) =>
+      program.methods
+        .swap(
+          new anchor.BN(amountIn),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          ownerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const executeBuyback = () =>
+      program.methods
+        .executeBuyback()
+        .accounts({
+          feeMint: seeded.mintA,
+          pool: seeded.pool,
+          buybackMint: seeded.mintB,
+          feeVaultA,
+          feeVaultB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      // A 10% fee so a couple of swaps build up more than one crank's worth
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000, 1_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 300_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 10_000_000);
+      feeVaultA = findFeeVault(seeded.mintA);
+      feeVaultB = findFeeVault(seeded.mintB);
+    });
+
+    it("Should not crank a pool in owner fee mode", async () => {
+      await program.methods
+        .setFeeMode({ owner: {} }, PublicKey.default)
+        .accounts({
+          pool: seeded.pool,
+          authority: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          feeVaultA,
+          feeVaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenBProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+      await expectError(executeBuyback(), "NotBuybackPool");
+    });
+
+    it("Should reject a buyback mint outside the pool", async () => {
+      await expectError(
+        program.methods
+          .setFeeMode({ buybackBurn: {} }, Keypair.generate().publicKey)
+          .accounts({
+            pool: seeded.pool,
+            authority: payer.publicKey,
+            tokenAMint: seeded.mintA,
+            tokenBMint: seeded.mintB,
+            feeVaultA,
+            feeVaultB,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            tokenBProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: SYSVAR_RENT_PUBKEY,
+          })
+          .rpc(),
+        "InvalidBuybackMint"
+      );
+    });
+
+    it("Should accumulate swap fees in the fee vaults", async () => {
+      await program.methods
+        .setFeeMode({ buybackBurn: {} }, seeded.mintB)
+        .accounts({
+          pool: seeded.pool,
+          authority: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          feeVaultA,
+          feeVaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenBProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+
+      await expectError(
+        swapInto(1_000_000, true, seeded.payerTokenA),
+        "InvalidFeeAccount"
+      );
+
+      await swapInto(100_000_000, true, feeVaultA);
+      await swapInto(100_000_000, true, feeVaultA);
+      await swapInto(10_000_000, false, feeVaultB);
+
+      assert.equal((await getTokenBalance(feeVaultA)).toString(), "20000000");
+      assert.equal((await getTokenBalance(feeVaultB)).toString(), "1000000");
+    });
+
+    it("Should buy back and burn the target mint", async () => {
+      const vaultABefore = await getTokenBalance(seeded.vaultA);
+      const vaultBBefore = await getTokenBalance(seeded.vaultB);
+      const supplyBefore = (await getMint(provider.connection, seeded.mintB))
+        .supply;
+
+      const signature = await executeBuyback();
+      const event = await getEvent(signature, "buybackEvent");
+
+      // The crank is capped at 1% of the input reserve
+      const expectedSpent = vaultABefore.muln(100).divn(10_000);
+      const expectedBought = expectedSpent
+        .mul(vaultBBefore)
+        .div(vaultABefore.add(expectedSpent));
+      assert.equal(event.feesSpent.toString(), expectedSpent.toString());
+      assert.equal(event.amountBought.toString(), expectedBought.toString());
+      assert.equal(event.feesBurned.toString(), "1000000");
+      assert.equal(
+        event.totalBurned.toString(),
+        expectedBought.addn(1_000_000).toString()
+      );
+
+      const supplyAfter = (await getMint(provider.connection, seeded.mintB))
+        .supply;
+      assert.equal(
+        (supplyBefore - supplyAfter).toString(),
+        event.totalBurned.toString()
+      );
+      assert.equal(
+        (await getTokenBalance(feeVaultA)).toString(),
+        new anchor.BN(20_000_000).sub(expectedSpent).toString()
+      );
+      assert.equal((await getTokenBalance(feeVaultB)).toString(), "0");
+
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        pool.reserveA.toString(),
+        (await getTokenBalance(seeded.vaultA)).toString()
+      );
+      assert.equal(
+        pool.reserveB.toString(),
+        (await getTokenBalance(seeded.vaultB)).toString()
+      );
+    });
+
+    it("Should spend the remaining fees on later cranks", async () => {
+      await executeBuyback();
+      assert.equal((await getTokenBalance(feeVaultA)).toString(), "0");
+    });
+  });
+
+  describe("Holder Fee Discounts", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let discountMint: PublicKey;
+    let userTokenA: PublicKey;
+    let userTokenB: PublicKey;
+    let userDiscount: PublicKey;
+    let payerDiscount: PublicKey;
+    const user = Keypair.generate();
+    const tiers = [
+      { minBalance: new anchor.BN(1_000), discountBps: 1_000 },
+      { minBalance: new anchor.BN(5_000), discountBps: 2_500 },
+      { minBalance: new anchor.BN(10_000), discountBps: 5_000 },
+    ];
+
+    const setDiscountConfig = (
+      discountConfig: { mint: PublicKey; tiers: typeof tiers } | null
+    ) =>
+      program.methods
+        .setDiscountConfig(discountConfig)
+        .accounts({ config: configAddress, admin: payer.publicKey })
+        .rpc();
+
+    // Swaps 1,000,000 of token A at the pool's 1% fee and returns the event
+    const swapWithDiscount = async (discountTokenAccount: PublicKey | null) => {
+      const signature = await program.methods
+        .swap(
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          discountTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+      return await getEvent(signature, "swapExecutedEvent");
+    };
+
+    const mintDiscount = (amount: number) =>
+      mintTo(
+        provider.connection,
+        payer,
+        discountMint,
+        userDiscount,
+        payer,
+        amount
+      );
+
+    const expectDiscount = async (discountBps: number) => {
+      const event = await swapWithDiscount(userDiscount);
+      assert.equal(event.feeDiscountBps, discountBps);
+      assert.equal(
+        event.fee.toString(),
+        Math.floor((10_000 * (10_000 - discountBps)) / 10_000).toString()
+      );
+    };
+
+    before(async () => {
+      await ensureConfig();
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000, 100);
+      discountMint = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        0
+      );
+
+      await ensureSolBalance(user);
+      userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        100_000_000
+      );
+      userTokenB = await fundAccount(seeded.mintB, user.publicKey, 0);
+      userDiscount = await fundAccount(discountMint, user.publicKey, 0);
+      payerDiscount = await fundAccount(
+        discountMint,
+        payer.publicKey,
+        1_000_000
+      );
+    });
+
+    it("Should reject tiers out of order", async () => {
+      await expectError(
+        setDiscountConfig({
+          mint: discountMint,
+          tiers: [tiers[1], tiers[0]],
+        }),
+        "InvalidDiscountConfig"
+      );
+    });
+
+    it("Should charge the full fee without a discount account", async () => {
+      await setDiscountConfig({ mint: discountMint, tiers });
+
+      const event = await swapWithDiscount(null);
+      assert.equal(event.feeDiscountBps, 0);
+      assert.equal(event.fee.toString(), "10000");
+    });
+
+    it("Should apply each tier from its threshold on", async () => {
+      await mintDiscount(999);
+      await expectDiscount(0);
+      await mintDiscount(1);
+      await expectDiscount(1_000);
+      await mintDiscount(3_999);
+      await expectDiscount(1_000);
+      await mintDiscount(1);
+      await expectDiscount(2_500);
+      await mintDiscount(4_999);
+      await expectDiscount(2_500);
+      await mintDiscount(1);
+      await expectDiscount(5_000);
+    });
+
+    it("Should not grant a discount for someone else's account", async () => {
+      await expectError(
+        swapWithDiscount(payerDiscount),
+        "InvalidDiscountAccount"
+      );
+    });
+
+    it("Should reject a discount account in another mint", async () => {
+      await expectError(swapWithDiscount(userTokenB), "InvalidDiscountAccount");
+    });
+
+    it("Should stop discounting once the config is cleared", async () => {
+      await setDiscountConfig(null);
+      await expectError(
+        swapWithDiscount(userDiscount),
+        "InvalidDiscountAccount"
+      );
+      const event = await swapWithDiscount(null);
+      assert.equal(event.fee.toString(), "10000");
+    });
+  });
+
+  describe("Commit-Reveal Swaps", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let userTokenA: PublicKey;
+    let userTokenB: PublicKey;
+    let swapCommit: PublicKey;
+    const user = Keypair.generate();
+    const amountIn = new anchor.BN(5_000_000);
+    const minAmountOut = new anchor.BN(1_000_000);
+    const salt = Array.from(Keypair.generate().publicKey.toBytes());
+
+    // sha256(amount_in le8 || min_amount_out le8 || a_to_b || salt)
+    const commitmentOf = (
+      amount: anchor.BN,
+      minOut: anchor.BN,
+      aToB: boolean,
+      commitSalt: number[]
+    ) =>
+      Array.from(
+        createHash("sha256")
+          .update(amount.toArrayLike(Buffer, "le", 8))
+          .update(minOut.toArrayLike(Buffer, "le", 8))
+          .update(Buffer.from([aToB ? 1 : 0]))
+          .update(Buffer.from(commitSalt))
+          .digest()
+      );
+
+    const commitIx = (windowSlots: number) =>
+      program.methods
+        .commitSwap(
+          commitmentOf(amountIn, minAmountOut, true, salt),
+          new anchor.BN(windowSlots)
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: user.publicKey,
+          swapCommit,
+          systemProgram: SystemProgram.programId,
+        })
+        .instruction();
+
+    const revealIx = (revealSalt: number[] = salt) =>
+      program.methods
+        .revealSwap(amountIn, minAmountOut, true, revealSalt)
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          owner: user.publicKey,
+          swapCommit,
+          userTokenA,
+          userTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .instruction();
+
+    const send = async (
+      ...instructions: Promise<anchor.web3.TransactionInstruction>[]
+    ) =>
+      provider.sendAndConfirm(
+        new Transaction().add(...(await Promise.all(instructions))),
+        [user]
+      );
+
+    const commitSlot = async () =>
+      (
+        await program.account.swapCommit.fetch(swapCommit)
+      ).commitSlot.toNumber();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await ensureSolBalance(user);
+      userTokenA = await fundAccount(
+        seeded.mintA,
+        user.publicKey,
+        100_000_000
+      );
+      userTokenB = await fundAccount(seeded.mintB, user.publicKey, 0);
+      [swapCommit] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("commit"),
+          seeded.pool.toBuffer(),
+          user.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+    });
+
+    it("Should not reveal in the same slot as the commit", async () => {
+      await expectError(send(commitIx(50), revealIx()), "RevealTooEarly");
+    });
+
+    it("Should reject a preimage that doesn't match", async () => {
+      await send(commitIx(50));
+      await waitForSlot((await commitSlot()) + 1);
+
+      const wrongSalt = [...salt];
+      wrongSalt[0] ^= 1;
+      await expectError(send(revealIx(wrongSalt)), "InvalidCommitment");
+    });
+
+    it("Should execute the revealed swap and return the bond", async () => {
+      const rent = (await provider.connection.getAccountInfo(swapCommit))
+        .lamports;
+      const lamportsBefore = await provider.connection.getBalance(
+        user.publicKey
+      );
+      const userABefore = await getTokenBalance(userTokenA);
+
+      await send(revealIx());
+
+      assert.equal(
+        userABefore.sub(await getTokenBalance(userTokenA)).toString(),
+        amountIn.toString()
+      );
+      assert.isTrue(
+        (await getTokenBalance(userTokenB)).gte(minAmountOut),
+        "Expected at least the committed minimum output"
+      );
+      assert.isNull(await provider.connection.getAccountInfo(swapCommit));
+      // The provider wallet pays the transaction fee
+      assert.equal(
+        await provider.connection.getBalance(user.publicKey),
+        lamportsBefore + rent
+      );
+    });
+
+    it("Should let an expired commitment be reclaimed, not revealed", async () => {
+      const lamportsBefore = await provider.connection.getBalance(
+        user.publicKey
+      );
+      await send(commitIx(2));
+      const reclaim = () =>
+        program.methods
+          .reclaimSwapCommit()
+          .accounts({ swapCommit, owner: user.publicKey })
+          .rpc();
+      await expectError(reclaim(), "CommitNotExpired");
+
+      await waitForSlot((await commitSlot()) + 3);
+      await expectError(send(revealIx()), "CommitExpired");
+
+      await reclaim();
+      assert.isNull(await provider.connection.getAccountInfo(swapCommit));
+      assert.equal(
+        await provider.connection.getBalance(user.publicKey),
+        lamportsBefore
+      );
+    });
+  });
+
+  describe("Swap Hooks", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let hookState: PublicKey;
+
+    const swapAccounts = () => ({
+      pool: seeded.pool,
+      owner: payer.publicKey,
+      tokenInMint: seeded.mintA,
+      tokenOutMint: seeded.mintB,
+      userTokenIn: seeded.payerTokenA,
+      userTokenOut: seeded.payerTokenB,
+      poolTokenIn: seeded.vaultA,
+      poolTokenOut: seeded.vaultB,
+      ownerTokenAccount: seeded.payerTokenA,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    const swapMethod = (amountIn: number) =>
+      program.methods.swap(
+        new anchor.BN(amountIn),
+        new anchor.BN(0),
+        new anchor.BN(0),
+        new anchor.BN(0),
+        new anchor.BN(0),
+        null
+      );
+
+    // Swaps through the hook, which gets `hookState` and then `extra`
+    const hookedSwap = (
+      amountIn: number,
+      extra: anchor.web3.AccountMeta[] = []
+    ) =>
+      swapMethod(amountIn)
+        .accounts({ ...swapAccounts(), hookProgram: hookProgram.programId })
+        .remainingAccounts([
+          { pubkey: hookState, isSigner: false, isWritable: true },
+          ...extra,
+        ])
+        .rpc();
+
+    const setMode = (
+      mode: Parameters<typeof hookProgram.methods.setMode>[0]
+    ) =>
+      hookProgram.methods
+        .setMode(mode)
+        .accounts({ hookState, authority: payer.publicKey })
+        .rpc();
+
+    const setSwapHook = (hook: PublicKey) =>
+      program.methods
+        .setSwapHook(hook)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      [hookState] = PublicKey.findProgramAddressSync(
+        [Buffer.from("hook_state"), seeded.pool.toBuffer()],
+        hookProgram.programId
+      );
+      await hookProgram.methods
+        .initialize()
+        .accounts({
+          pool: seeded.pool,
+          hookState,
+          authority: payer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      await setSwapHook(hookProgram.programId);
+    });
+
+    it("Should require the hook program on a hooked pool", async () => {
+      await expectError(
+        swapMethod(1_000_000).accounts(swapAccounts()).rpc(),
+        "MissingHookProgram"
+      );
+      await expectError(
+        program.methods
+          .swapV2(
+            new anchor.BN(1_000_000),
+            new anchor.BN(0),
+            true,
+            new anchor.BN(0)
+          )
+          .accounts({
+            tokenAMint: seeded.mintA,
+            tokenBMint: seeded.mintB,
+            pool: seeded.pool,
+            user: payer.publicKey,
+            userTokenA: seeded.payerTokenA,
+            userTokenB: seeded.payerTokenB,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+            ownerTokenAccount: seeded.payerTokenA,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc(),
+        "MissingHookProgram"
+      );
+    });
+
+    it("Should call the hook with the swap's amounts", async () => {
+      const signature = await hookedSwap(1_000_000);
+      const event = await getEvent(signature, "swapExecutedEvent");
+
+      const state = await hookProgram.account.hookState.fetch(hookState);
+      assert.equal(state.swapCount.toNumber(), 1);
+      assert.equal(state.lastAmountIn.toString(), "1000000");
+      assert.equal(state.lastAmountOut.toString(), event.amountOut.toString());
+      assert.isTrue(state.lastAToB);
+
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.isFalse(pool.inHook);
+    });
+
+    it("Should fail the swap when the hook fails", async () => {
+      await setMode({ reject: {} });
+      const balanceBefore = await getTokenBalance(seeded.payerTokenA);
+
+      await expectError(hookedSwap(1_000_000), "HookRejected");
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA)).toString(),
+        balanceBefore.toString()
+      );
+    });
+
+    it("Should reject a hook re-entering the pool", async () => {
+      await setMode({ reenter: {} });
+      // The hook replays a swap on the same pool with these accounts
+      const inner = await swapMethod(1_000)
+        .accounts({ ...swapAccounts(), hookProgram: hookProgram.programId })
+        .remainingAccounts([
+          { pubkey: hookState, isSigner: false, isWritable: true },
+        ])
+        .instruction();
+
+      try {
+        await hookedSwap(1_000_000, inner.keys);
+        assert.fail("Expected the re-entrant swap to fail");
+      } catch (error) {
+        // The runtime refuses the call back into the swap program before it
+        // runs, so the pool's own `ReentrantSwap` check is never reached
+        assert.include(
+          error.toString(),
+          "Cross-program invocation reentrancy not allowed"
+        );
+        assert.notInclude(error.toString(), "ReentrantSwap");
+      }
+      const state = await hookProgram.account.hookState.fetch(hookState);
+      assert.equal(state.swapCount.toNumber(), 1);
+    });
+
+    it("Should swap normally once the hook is removed", async () => {
+      await setMode({ record: {} });
+      await setSwapHook(PublicKey.default);
+      await swapMethod(1_000_000).accounts(swapAccounts()).rpc();
+
+      const state = await hookProgram.account.hookState.fetch(hookState);
+      assert.equal(state.swapCount.toNumber(), 1);
+    });
+  });
+
+  describe("Imbalanced Deposits", () => {
+    type SeededPool = Awaited<ReturnType<typeof createSeededPool>>;
+    type Depositor = { tokenA: PublicKey; tokenB: PublicKey; lp: PublicKey };
+    let imbalancedPool: SeededPool;
+    let swapPool: SeededPool;
+    let imbalancedDepositor: Depositor;
+    let swapDepositor: Depositor;
+    const user = Keypair.generate();
+    const BN = anchor.BN;
+
+    const depositAccounts = (seeded: SeededPool, depositor: Depositor) => ({
+      pool: seeded.pool,
+      owner: user.publicKey,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      userTokenA: depositor.tokenA,
+      userTokenB: depositor.tokenB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+      userLp: depositor.lp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    const addImbalanced = (amountA: number, amountB: number) =>
+      program.methods
+        .addLiquidityImbalanced(
+          new BN(amountA),
+          new BN(amountB),
+          new BN(0),
+          new BN(0)
+        )
+        .accounts(depositAccounts(imbalancedPool, imbalancedDepositor))
+        .signers([user])
+        .rpc();
+
+    const setMaxImbalance = (bps: number) =>
+      program.methods
+        .setMaxImbalance(bps)
+        .accounts({ pool: imbalancedPool.pool, authority: payer.publicKey })
+        .rpc();
+
+    // Off-chain copies of the pool math, with the default 0.3% fee paid out
+    // of the pool to the fee account
+    const swapOut = (
+      amountIn: anchor.BN,
+      reserveIn: anchor.BN,
+      reserveOut: anchor.BN
+    ) => {
+      const afterFee = amountIn.sub(amountIn.muln(3).divn(1000));
+      return {
+        afterFee,
+        amountOut: afterFee.mul(reserveOut).div(reserveIn.add(afterFee)),
+      };
+    };
+    const optimalDeposit = (
+      amountA: anchor.BN,
+      amountB: anchor.BN,
+      reserveA: anchor.BN,
+      reserveB: anchor.BN
+    ) => {
+      const optimalB = amountA.mul(reserveB).div(reserveA);
+      return optimalB.lte(amountB)
+        ? [amountA, optimalB]
+        : [amountB.mul(reserveA).div(reserveB), amountB];
+    };
+
+    const makeDepositor = async (seeded: SeededPool) => ({
+      tokenA: await fundAccount(seeded.mintA, user.publicKey, 60_000_000),
+      tokenB: await fundAccount(seeded.mintB, user.publicKey, 40_000_000),
+      lp: await fundAccount(seeded.lpMint, user.publicKey, 0),
+    });
+
+    before(async () => {
+      imbalancedPool = await createSeededPool(1_000_000_000, 1_000_000_000);
+      swapPool = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await ensureSolBalance(user);
+      imbalancedDepositor = await makeDepositor(imbalancedPool);
+      swapDepositor = await makeDepositor(swapPool);
+    });
+
+    it("Should only accept proportional deposits by default", async () => {
+      await expectError(
+        addImbalanced(60_000_000, 40_000_000),
+        "ImbalanceTooHigh"
+      );
+    });
+
+    it("Should cap the tolerance at 10,000 bps", async () => {
+      await expectError(setMaxImbalance(10_001), "InvalidImbalanceTolerance");
+    });
+
+    it("Should reject a deposit beyond the tolerance", async () => {
+      await setMaxImbalance(4_000);
+      // 60 of the 80 token A are excess, 7,500 bps
+      await expectError(
+        addImbalanced(80_000_000, 20_000_000),
+        "ImbalanceTooHigh"
+      );
+    });
+
+    it("Should charge the swap fee on the excess side", async () => {
+      const vaultABefore = await getTokenBalance(imbalancedPool.vaultA);
+
+      // 20,000,000 of the 60,000,000 token A are excess, 3,333 bps
+      const signature = await addImbalanced(60_000_000, 40_000_000);
+      const event = await getEvent(signature, "liquidityAddedEvent");
+      assert.equal(event.imbalanceFeeA.toString(), "60000");
+      assert.equal(event.imbalanceFeeB.toString(), "0");
+
+      // The whole deposit, fee included, stays in the pool
+      const vaultAAfter = await getTokenBalance(imbalancedPool.vaultA);
+      assert.equal(vaultAAfter.sub(vaultABefore).toString(), "60000000");
+      assert.equal(
+        (await getTokenBalance(imbalancedDepositor.lp)).toString(),
+        event.lpTokensMinted.toString()
+      );
+    });
+
+    it("Should not beat swapping to the pool ratio first", async () => {
+      const reserveA = await getTokenBalance(swapPool.vaultA);
+      const reserveB = await getTokenBalance(swapPool.vaultB);
+      const supply = new BN(
+        (await getMint(provider.connection, swapPool.lpMint)).supply.toString()
+      );
+      const amountA = new BN(60_000_000);
+      const amountB = new BN(40_000_000);
+
+      // Find the swap of token A that gives the most LP afterwards
+      let best = { swapIn: new BN(0), lp: new BN(-1) };
+      for (let step = 0; step <= 40; step++) {
+        const swapIn = new BN(9_000_000 + step * 50_000);
+        const { afterFee, amountOut } = swapOut(swapIn, reserveA, reserveB);
+        const [depositA, depositB] = optimalDeposit(
+          amountA.sub(swapIn),
+          amountB.add(amountOut),
+          reserveA.add(afterFee),
+          reserveB.sub(amountOut)
+        );
+        const lp = BN.min(
+          depositA.mul(supply).div(reserveA.add(afterFee)),
+          depositB.mul(supply).div(reserveB.sub(amountOut))
+        );
+        if (lp.gt(best.lp)) {
+          best = { swapIn, lp };
+        }
+      }
+
+      await program.methods
+        .swap(best.swapIn, new BN(0), new BN(0), new BN(0), new BN(0), null)
+        .accounts({
+          pool: swapPool.pool,
+          owner: user.publicKey,
+          tokenInMint: swapPool.mintA,
+          tokenOutMint: swapPool.mintB,
+          userTokenIn: swapDepositor.tokenA,
+          userTokenOut: swapDepositor.tokenB,
+          poolTokenIn: swapPool.vaultA,
+          poolTokenOut: swapPool.vaultB,
+          ownerTokenAccount: swapPool.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+      const [depositA, depositB] = optimalDeposit(
+        await getTokenBalance(swapDepositor.tokenA),
+        await getTokenBalance(swapDepositor.tokenB),
+        await getTokenBalance(swapPool.vaultA),
+        await getTokenBalance(swapPool.vaultB)
+      );
+      await program.methods
+        .addLiquidity(depositA, depositB, new BN(0), new BN(0))
+        .accounts(depositAccounts(swapPool, swapDepositor))
+        .signers([user])
+        .rpc();
+
+      const imbalancedLp = await getTokenBalance(imbalancedDepositor.lp);
+      const swapPathLp = await getTokenBalance(swapDepositor.lp);
+      assert.isTrue(
+        imbalancedLp.lte(swapPathLp),
+        `Imbalanced deposit got ${imbalancedLp} LP, swapping got ${swapPathLp}`
+      );
+    });
+  });
+
+  describe("Withdrawal Fees", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+
+    const setWithdrawalFee = (bps: number) =>
+      program.methods
+        .setWithdrawalFee(bps)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+    const removeLiquidity = (lpAmount: number, minAmount: number = 0) =>
+      program.methods
+        .removeLiquidity(
+          new anchor.BN(lpAmount),
+          new anchor.BN(minAmount),
+          new anchor.BN(minAmount),
+          false,
+          new anchor.BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const lpSupply = async () =>
+      new anchor.BN(
+        (await getMint(provider.connection, seeded.lpMint)).supply.toString()
+      );
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+    });
+
+    it("Should pay out the full share without a fee", async () => {
+      const balanceBefore = await getTokenBalance(seeded.payerTokenA);
+      const signature = await removeLiquidity(10_000, 10_000_000);
+      const event = await getEvent(signature, "liquidityRemovedEvent");
+
+      assert.equal(event.amountA.toString(), "10000000");
+      assert.equal(event.withdrawalFeeA.toString(), "0");
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA))
+          .sub(balanceBefore)
+          .toString(),
+        "10000000"
+      );
+    });
+
+    it("Should cap the withdrawal fee at 1%", async () => {
+      await expectError(setWithdrawalFee(101), "WithdrawalFeeTooHigh");
+    });
+
+    it("Should announce the change with the previous fee", async () => {
+      const signature = await setWithdrawalFee(50);
+      const event = await getEvent(signature, "withdrawalFeeSetEvent");
+      assert.equal(event.previousWithdrawalFeeBps, 0);
+      assert.equal(event.withdrawalFeeBps, 50);
+      assert.isAbove(event.timestamp.toNumber(), 0);
+    });
+
+    it("Should hold the fee against the minimum amounts", async () => {
+      const supply = await lpSupply();
+      const reserveA = await getTokenBalance(seeded.vaultA);
+      const gross = reserveA.muln(100_000).div(supply);
+      await expectError(
+        removeLiquidity(100_000, gross.toNumber()),
+        "SlippageExceeded"
+      );
+    });
+
+    it("Should leave the fee in the pool for the remaining LPs", async () => {
+      const supplyBefore = await lpSupply();
+      const reserveABefore = await getTokenBalance(seeded.vaultA);
+      const balanceBefore = await getTokenBalance(seeded.payerTokenA);
+
+      const signature = await removeLiquidity(100_000);
+      const event = await getEvent(signature, "liquidityRemovedEvent");
+
+      const gross = reserveABefore.muln(100_000).div(supplyBefore);
+      const fee = gross.muln(50).divn(10_000);
+      assert.equal(event.withdrawalFeeA.toString(), fee.toString());
+      assert.equal(event.amountA.toString(), gross.sub(fee).toString());
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA))
+          .sub(balanceBefore)
+          .toString(),
+        gross.sub(fee).toString()
+      );
+
+      // Token A per LP token went up for everyone who stayed
+      const supplyAfter = await lpSupply();
+      const reserveAAfter = await getTokenBalance(seeded.vaultA);
+      assert.isTrue(
+        reserveAAfter.mul(supplyBefore).gt(reserveABefore.mul(supplyAfter))
+      );
+    });
+  });
+
+  describe("Deposit Fees", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let treasuryTokenA: PublicKey;
+    let treasuryTokenB: PublicKey;
+    const treasury = Keypair.generate();
+    const BN = anchor.BN;
+
+    const setDepositFee = (bps: number) =>
+      program.methods
+        .setDepositFee(bps)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+    const quoteAccounts = () => ({
+      pool: seeded.pool,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+    });
+
+    const quote = async (amount: number) =>
+      (
+        await program.methods
+          .quoteAddLiquidity(new BN(amount), new BN(amount))
+          .accounts(quoteAccounts())
+          .view()
+      ).lpTokens as anchor.BN;
+
+    const addLiquidity = (
+      amount: number,
+      feeAccounts: {
+        config: PublicKey | null;
+        protocolFeeA: PublicKey | null;
+        protocolFeeB: PublicKey | null;
+      },
+      minLpTokens: anchor.BN = new BN(0)
+    ) =>
+      program.methods
+        .addLiquidity(new BN(amount), new BN(amount), minLpTokens, new BN(0))
+        .accounts({
+          ...quoteAccounts(),
+          owner: payer.publicKey,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...feeAccounts,
+        })
+        .rpc();
+
+    const withFees = () => ({
+      config: configAddress,
+      protocolFeeA: treasuryTokenA,
+      protocolFeeB: treasuryTokenB,
+    });
+    const withoutFees = {
+      config: null,
+      protocolFeeA: null,
+      protocolFeeB: null,
+    };
+
+    before(async () => {
+      await ensureConfig();
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 1_000_000_000);
+      treasuryTokenA = await fundAccount(seeded.mintA, treasury.publicKey, 0);
+      treasuryTokenB = await fundAccount(seeded.mintB, treasury.publicKey, 0);
+      await program.methods
+        .setProtocolFeeRecipient(treasury.publicKey)
+        .accounts({ config: configAddress, admin: payer.publicKey })
+        .rpc();
+    });
+
+    it("Should take no fee by default", async () => {
+      const vaultABefore = await getTokenBalance(seeded.vaultA);
+      const expectedLp = await quote(10_000_000);
+
+      const signature = await addLiquidity(10_000_000, withoutFees);
+      const event = await getEvent(signature, "liquidityAddedEvent");
+
+      assert.equal(event.depositFeeA.toString(), "0");
+      assert.equal(event.depositFeeB.toString(), "0");
+      assert.equal(event.lpTokensMinted.toString(), expectedLp.toString());
+      assert.equal(
+        (await getTokenBalance(seeded.vaultA)).sub(vaultABefore).toString(),
+        "10000000"
+      );
+    });
+
+    it("Should cap the deposit fee at 1%", async () => {
+      await expectError(setDepositFee(101), "DepositFeeTooHigh");
+    });
+
+    it("Should announce the change with the previous fee", async () => {
+      const signature = await setDepositFee(25);
+      const event = await getEvent(signature, "depositFeeSetEvent");
+      assert.equal(event.previousDepositFeeBps, 0);
+      assert.equal(event.depositFeeBps, 25);
+      assert.isAbove(event.timestamp.toNumber(), 0);
+    });
+
+    it("Should require the protocol fee accounts", async () => {
+      await expectError(
+        addLiquidity(10_000_000, withoutFees),
+        "InvalidProtocolFeeAccount"
+      );
+      await expectError(
+        addLiquidity(10_000_000, {
+          config: configAddress,
+          protocolFeeA: seeded.payerTokenA,
+          protocolFeeB: seeded.payerTokenB,
+        }),
+        "InvalidProtocolFeeAccount"
+      );
+    });
+
+    it("Should hold min_lp_tokens against the post-fee LP", async () => {
+      const expectedLp = await quote(10_000_000);
+      await expectError(
+        addLiquidity(10_000_000, withFees(), expectedLp.addn(1)),
+        "SlippageExceeded"
+      );
+    });
+
+    for (const bps of [1, 25, 100]) {
+      it(`Should route a ${bps} bps fee to the protocol`, async () => {
+        await setDepositFee(bps);
+        const amount = 12_345_678;
+        const fee = Math.floor((amount * bps) / 10_000);
+
+        const vaultABefore = await getTokenBalance(seeded.vaultA);
+        const treasuryABefore = await getTokenBalance(treasuryTokenA);
+        const treasuryBBefore = await getTokenBalance(treasuryTokenB);
+        const expectedLp = await quote(amount);
+
+        const signature = await addLiquidity(amount, withFees(), expectedLp);
+        const event = await getEvent(signature, "liquidityAddedEvent");
+
+        assert.equal(event.depositFeeA.toString(), fee.toString());
+        assert.equal(event.depositFeeB.toString(), fee.toString());
+        assert.equal(event.amountA.toString(), (amount - fee).toString());
+        assert.equal(event.lpTokensMinted.toString(), expectedLp.toString());
+        assert.equal(
+          (await getTokenBalance(treasuryTokenA))
+            .sub(treasuryABefore)
+            .toString(),
+          fee.toString()
+        );
+        assert.equal(
+          (await getTokenBalance(treasuryTokenB))
+            .sub(treasuryBBefore)
+            .toString(),
+          fee.toString()
+        );
+        assert.equal(
+          (await getTokenBalance(seeded.vaultA)).sub(vaultABefore).toString(),
+          (amount - fee).toString()
+        );
+      });
+    }
+
+    it("Should skip the fee accounts once the fee is back to zero", async () => {
+      await setDepositFee(0);
+      const signature = await addLiquidity(10_000_000, withoutFees);
+      const event = await getEvent(signature, "liquidityAddedEvent");
+      assert.equal(event.depositFeeA.toString(), "0");
+      assert.equal(event.amountA.toString(), "10000000");
+    });
+  });
+
+  describe("Native SOL Deposits", () => {
+    const user = Keypair.generate();
+    const BN = anchor.BN;
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let userTokenB: PublicKey;
+    let userLp: PublicKey;
+    let wsolAccount: PublicKey;
+
+    const nativeAccounts = (pool: typeof seeded, userToken: PublicKey) => ({
+      pool: pool.pool,
+      user: user.publicKey,
+      tokenAMint: pool.mintA,
+      tokenBMint: pool.mintB,
+      nativeMint: NATIVE_MINT,
+      wsolAccount,
+      userToken,
+      poolTokenA: pool.vaultA,
+      poolTokenB: pool.vaultB,
+      lpMint: pool.lpMint,
+      userLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      rent: SYSVAR_RENT_PUBKEY,
+    });
+
+    // Runs a native deposit and returns its event and the user's lamport
+    // change; the payer covers the transaction fee
+    const addNative = async (amountSol: number, amountToken: number) => {
+      const lamportsBefore = await provider.connection.getBalance(
+        user.publicKey
+      );
+      const signature = await program.methods
+        .addLiquidityNative(
+          new BN(amountSol),
+          new BN(amountToken),
+          new BN(0),
+          new BN(0)
+        )
+        .accounts(nativeAccounts(seeded, userTokenB))
+        .signers([user])
+        .rpc();
+      const lamportsAfter = await provider.connection.getBalance(
+        user.publicKey
+      );
+
+      // The temporary wSOL account is always closed again
+      assert.isNull(await provider.connection.getAccountInfo(wsolAccount));
+
+      return {
+        event: await getEvent(signature, "liquidityAddedEvent"),
+        lamportDelta: lamportsAfter - lamportsBefore,
+      };
+    };
+
+    before(async () => {
+      await ensureSolBalance(payer, 1_500_000_000);
+      await ensureSolBalance(user);
+      const mintB = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      // 1 SOL : 2 token B
+      seeded = await createSeededPool(500_000_000, 1_000_000_000, 30, {
+        mintA: NATIVE_MINT,
+        mintB,
+        poolIndex: 0,
+      });
+      userTokenB = await fundAccount(mintB, user.publicKey, 100_000_000);
+      userLp = await fundAccount(seeded.lpMint, user.publicKey, 0);
+      [wsolAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("wsol"), user.publicKey.toBuffer()],
+        program.programId
+      );
+    });
+
+    it("Should deposit SOL at the exact pool ratio", async () => {
+      const vaultSolBefore = await getTokenBalance(seeded.vaultA);
+      const tokenBBefore = await getTokenBalance(userTokenB);
+      const lpBefore = await getTokenBalance(userLp);
+      const expectedLp = (
+        await program.methods
+          .quoteAddLiquidity(new BN(10_000_000), new BN(20_000_000))
+          .accounts({
+            pool: seeded.pool,
+            tokenAMint: seeded.mintA,
+            tokenBMint: seeded.mintB,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+            lpMint: seeded.lpMint,
+          })
+          .view()
+      ).lpTokens;
+
+      const { event, lamportDelta } = await addNative(10_000_000, 20_000_000);
+
+      // Only the deposit leaves the wallet; the temporary account's rent is
+      // refunded in full
+      assert.equal(lamportDelta, -10_000_000);
+      assert.equal(event.amountA.toString(), "10000000");
+      assert.equal(event.amountB.toString(), "20000000");
+      assert.equal(
+        (await getTokenBalance(seeded.vaultA)).sub(vaultSolBefore).toString(),
+        "10000000"
+      );
+      assert.equal(
+        tokenBBefore.sub(await getTokenBalance(userTokenB)).toString(),
+        "20000000"
+      );
+      assert.equal(
+        (await getTokenBalance(userLp)).sub(lpBefore).toString(),
+        expectedLp.toString()
+      );
+    });
+
+    it("Should refund the SOL the pool ratio doesn't use", async () => {
+      const { event, lamportDelta } = await addNative(30_000_000, 20_000_000);
+
+      assert.equal(event.amountA.toString(), "10000000");
+      assert.equal(lamportDelta, -10_000_000);
+    });
+
+    it("Should leave unused tokens in the user's account", async () => {
+      const tokenBBefore = await getTokenBalance(userTokenB);
+
+      const { event, lamportDelta } = await addNative(10_000_000, 50_000_000);
+
+      assert.equal(event.amountB.toString(), "20000000");
+      assert.equal(lamportDelta, -10_000_000);
+      assert.equal(
+        tokenBBefore.sub(await getTokenBalance(userTokenB)).toString(),
+        "20000000"
+      );
+    });
+
+    it("Should reject pools without a native side", async () => {
+      const other = await createSeededPool(1_000_000_000, 1_000_000_000);
+      const userTokenOther = await fundAccount(
+        other.mintB,
+        user.publicKey,
+        1_000_000
+      );
+      await expectError(
+        program.methods
+          .addLiquidityNative(
+            new BN(1_000_000),
+            new BN(1_000_000),
+            new BN(0),
+            new BN(0)
+          )
+          .accounts({
+            ...nativeAccounts(other, userTokenOther),
+            userLp: await fundAccount(other.lpMint, user.publicKey, 0),
+          })
+          .signers([user])
+          .rpc(),
+        "NotNativePool"
+      );
+    });
+  });
+
+  describe("Native SOL Withdrawals", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let wsolAccount: PublicKey;
+
+    const removeLiquidity = (
+      pool: typeof seeded,
+      unwrapSol: boolean,
+      unwrapAccounts: { nativeMint: PublicKey; wsolAccount: PublicKey } | null
+    ) =>
+      program.methods
+        .removeLiquidity(
+          new anchor.BN(10_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          unwrapSol,
+          new anchor.BN(0)
+        )
+        .accounts({
+          pool: pool.pool,
+          user: payer.publicKey,
+          tokenAMint: pool.mintA,
+          tokenBMint: pool.mintB,
+          userTokenA: pool.payerTokenA,
+          userTokenB: pool.payerTokenB,
+          poolTokenA: pool.vaultA,
+          poolTokenB: pool.vaultB,
+          lpMint: pool.lpMint,
+          userLp: pool.payerLp,
+          nativeMint: unwrapAccounts?.nativeMint ?? null,
+          wsolAccount: unwrapAccounts?.wsolAccount ?? null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(payer, 1_500_000_000);
+      const mintB = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      seeded = await createSeededPool(500_000_000, 1_000_000_000, 30, {
+        mintA: NATIVE_MINT,
+        mintB,
+        poolIndex: 0,
+      });
+      [wsolAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from("wsol"), payer.publicKey.toBuffer()],
+        program.programId
+      );
+    });
+
+    it("Should deliver the SOL side as lamports", async () => {
+      const lamportsBefore = await provider.connection.getBalance(
+        payer.publicKey
+      );
+      const wsolBefore = await getTokenBalance(seeded.payerTokenA);
+      const tokenBBefore = await getTokenBalance(seeded.payerTokenB);
+
+      const signature = await removeLiquidity(seeded, true, {
+        nativeMint: NATIVE_MINT,
+        wsolAccount,
+      });
+      const event = await getEvent(signature, "liquidityRemovedEvent");
+      const transaction = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+
+      // The temporary account's rent comes back with the SOL, so only the
+      // transaction fee is missing
+      const lamportsAfter = await provider.connection.getBalance(
+        payer.publicKey
+      );
+      assert.equal(
+        lamportsAfter - lamportsBefore,
+        event.amountA.toNumber() - transaction.meta.fee
+      );
+      assert.equal(event.recipientA.toBase58(), wsolAccount.toBase58());
+      assert.isNull(await provider.connection.getAccountInfo(wsolAccount));
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA)).toString(),
+        wsolBefore.toString()
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenB))
+          .sub(tokenBBefore)
+          .toString(),
+        event.amountB.toString()
+      );
+    });
+
+    it("Should keep paying out wSOL without the flag", async () => {
+      const wsolBefore = await getTokenBalance(seeded.payerTokenA);
+      const signature = await removeLiquidity(seeded, false, null);
+      const event = await getEvent(signature, "liquidityRemovedEvent");
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA))
+          .sub(wsolBefore)
+          .toString(),
+        event.amountA.toString()
+      );
+    });
+
+    it("Should require the temporary wSOL account", async () => {
+      await expectError(
+        removeLiquidity(seeded, true, null),
+        "MissingWsolAccount"
+      );
+    });
+
+    it("Should reject unwrapping on pools without a native side", async () => {
+      const other = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await expectError(
+        removeLiquidity(other, true, { nativeMint: NATIVE_MINT, wsolAccount }),
+        "NotNativePool"
+      );
+    });
+  });
+
+  describe("LP Recipients", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let beneficiaryLp: PublicKey;
+    const beneficiary = Keypair.generate();
+
+    const addLiquidity = (lpRecipient: PublicKey | null) =>
+      program.methods
+        .addLiquidity(
+          new anchor.BN(10_000_000),
+          new anchor.BN(10_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          lpRecipient,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+      beneficiaryLp = await fundAccount(
+        seeded.lpMint,
+        beneficiary.publicKey,
+        0
+      );
+    });
+
+    it("Should mint LP to the owner by default", async () => {
+      const lpBefore = await getTokenBalance(seeded.payerLp);
+      const signature = await addLiquidity(null);
+      const event = await getEvent(signature, "liquidityAddedEvent");
+
+      assert.equal(event.lpRecipient.toBase58(), seeded.payerLp.toBase58());
+      assert.equal(
+        (await getTokenBalance(seeded.payerLp)).sub(lpBefore).toString(),
+        event.lpTokensMinted.toString()
+      );
+    });
+
+    it("Should mint LP to a non-signer's account", async () => {
+      const payerLpBefore = await getTokenBalance(seeded.payerLp);
+      const tokenABefore = await getTokenBalance(seeded.payerTokenA);
+
+      const signature = await addLiquidity(beneficiaryLp);
+      const event = await getEvent(signature, "liquidityAddedEvent");
+
+      assert.equal(event.user.toBase58(), payer.publicKey.toBase58());
+      assert.equal(event.lpRecipient.toBase58(), beneficiaryLp.toBase58());
+      assert.isTrue(event.lpTokensMinted.gtn(0));
+      assert.equal(
+        (await getTokenBalance(beneficiaryLp)).toString(),
+        event.lpTokensMinted.toString()
+      );
+      // The tokens still come from the owner, who gets no LP
+      assert.equal(
+        tokenABefore.sub(await getTokenBalance(seeded.payerTokenA)).toString(),
+        "10000000"
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.payerLp)).toString(),
+        payerLpBefore.toString()
+      );
+    });
+
+    it("Should reject a recipient for another mint", async () => {
+      await expectError(
+        addLiquidity(seeded.payerTokenA),
+        "ConstraintTokenMint"
+      );
+    });
+  });
+
+  describe("Split Swaps", () => {
+    let cheapPool: Awaited<ReturnType<typeof createSeededPool>>;
+    let defaultPool: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+
+    const legAccounts = (seeded: typeof cheapPool) => [
+      { pubkey: seeded.pool, isWritable: true, isSigner: false },
+      { pubkey: seeded.vaultA, isWritable: true, isSigner: false },
+      { pubkey: seeded.vaultB, isWritable: true, isSigner: false },
+      { pubkey: seeded.payerTokenA, isWritable: true, isSigner: false },
+    ];
+
+    const swapSplit = (
+      amountIn: number,
+      minTotalOut: number,
+      splits: number[],
+      pools: (typeof cheapPool)[]
+    ) =>
+      program.methods
+        .swapSplit(new BN(amountIn), new BN(minTotalOut), splits, new BN(0))
+        .accounts({
+          tokenInMint: cheapPool.mintA,
+          tokenOutMint: cheapPool.mintB,
+          user: payer.publicKey,
+          userTokenIn: cheapPool.payerTokenA,
+          userTokenOut: cheapPool.payerTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(pools.flatMap(legAccounts))
+        .rpc();
+
+    const quote = async (seeded: typeof cheapPool, amountIn: number) =>
+      (
+        await program.methods
+          .quoteSwap(new BN(amountIn), true)
+          .accounts({
+            pool: seeded.pool,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+          })
+          .view()
+      ).amountOut as anchor.BN;
+
+    before(async () => {
+      // A 0.05% and a 0.3% pool on the same pair
+      cheapPool = await createSeededPool(1_000_000_000, 1_000_000_000, 5);
+      defaultPool = await createSeededPool(
+        1_000_000_000,
+        1_000_000_000,
+        30,
+        { mintA: cheapPool.mintA, mintB: cheapPool.mintB, poolIndex: 1 }
+      );
+      await fundAccount(cheapPool.mintA, payer.publicKey, 1_000_000_000);
+    });
+
+    it("Should beat either pool alone for a large trade", async () => {
+      const amountIn = 200_000_000;
+      const cheapAlone = await quote(cheapPool, amountIn);
+      const defaultAlone = await quote(defaultPool, amountIn);
+      const expectedCheapLeg = await quote(cheapPool, 120_000_000);
+      const expectedDefaultLeg = await quote(defaultPool, 80_000_000);
+      const tokenBBefore = await getTokenBalance(cheapPool.payerTokenB);
+
+      const signature = await swapSplit(amountIn, 0, [6_000, 4_000], [
+        cheapPool,
+        defaultPool,
+      ]);
+
+      const events = await getEvents(signature);
+      const legs = events.filter((e) => e.name === "swapExecutedEvent");
+      assert.equal(legs.length, 2);
+      assert.equal(legs[0].data.pool.toBase58(), cheapPool.pool.toBase58());
+      assert.equal(legs[0].data.amountIn.toString(), "120000000");
+      assert.equal(
+        legs[0].data.amountOut.toString(),
+        expectedCheapLeg.toString()
+      );
+      assert.equal(legs[1].data.pool.toBase58(), defaultPool.pool.toBase58());
+      assert.equal(legs[1].data.amountIn.toString(), "80000000");
+      assert.equal(
+        legs[1].data.amountOut.toString(),
+        expectedDefaultLeg.toString()
+      );
+
+      const summary = await getEvent(signature, "swapSplitEvent");
+      const totalOut = expectedCheapLeg.add(expectedDefaultLeg);
+      assert.equal(summary.totalAmountIn.toString(), amountIn.toString());
+      assert.equal(summary.totalAmountOut.toString(), totalOut.toString());
+      assert.deepEqual(summary.splits, [6_000, 4_000]);
+      assert.isTrue(totalOut.gt(cheapAlone));
+      assert.isTrue(totalOut.gt(defaultAlone));
+      assert.equal(
+        (await getTokenBalance(cheapPool.payerTokenB))
+          .sub(tokenBBefore)
+          .toString(),
+        totalOut.toString()
+      );
+    });
+
+    it("Should give the last leg the rounding remainder", async () => {
+      const signature = await swapSplit(1_000_001, 0, [3_333, 6_667], [
+        cheapPool,
+        defaultPool,
+      ]);
+      const legs = (await getEvents(signature)).filter(
+        (e) => e.name === "swapExecutedEvent"
+      );
+      assert.equal(legs[0].data.amountIn.toString(), "333300");
+      assert.equal(legs[1].data.amountIn.toString(), "666701");
+    });
+
+    it("Should reject splits that don't add up to 10,000 bps", async () => {
+      await expectError(
+        swapSplit(1_000_000, 0, [5_000, 4_000], [cheapPool, defaultPool]),
+        "InvalidSplit"
+      );
+      await expectError(
+        swapSplit(1_000_000, 0, [10_000, 0], [cheapPool, defaultPool]),
+        "InvalidSplit"
+      );
+    });
+
+    it("Should reject a pool for another pair", async () => {
+      const other = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await expectError(
+        swapSplit(1_000_000, 0, [5_000, 5_000], [cheapPool, other]),
+        "SplitPoolMismatch"
+      );
+    });
+
+    it("Should hold the combined output against min_total_out", async () => {
+      await expectError(
+        swapSplit(1_000_000, 1_000_000, [5_000, 5_000], [
+          cheapPool,
+          defaultPool,
+        ]),
+        "SlippageExceeded"
+      );
+    });
+  });
+
+  describe("Limit Orders", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let makerTokenA: PublicKey;
+    let makerTokenB: PublicKey;
+    let fillerTokenA: PublicKey;
+    const maker = Keypair.generate();
+    const filler = Keypair.generate();
+    const BN = anchor.BN;
+
+    const orderAddress = (orderIndex: number) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("order"),
+          seeded.pool.toBuffer(),
+          maker.publicKey.toBuffer(),
+          new BN(orderIndex).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      )[0];
+
+    const vaultAddress = (orderIndex: number) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("order_vault"), orderAddress(orderIndex).toBuffer()],
+        program.programId
+      )[0];
+
+    const createOrder = (
+      orderIndex: number,
+      amountIn: number,
+      minPriceNumerator: number,
+      minPriceDenominator: number,
+      expiry: number
+    ) =>
+      program.methods
+        .createOrder(
+          new BN(orderIndex),
+          new BN(amountIn),
+          new BN(minPriceNumerator),
+          new BN(minPriceDenominator),
+          new BN(expiry)
+        )
+        .accounts({
+          pool: seeded.pool,
+          maker: maker.publicKey,
+          order: orderAddress(orderIndex),
+          tokenInMint: seeded.mintA,
+          orderVault: vaultAddress(orderIndex),
+          makerTokenIn: makerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([maker])
+        .rpc();
+
+    const fillOrder = (orderIndex: number) =>
+      program.methods
+        .fillOrder()
+        .accounts({
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          pool: seeded.pool,
+          order: orderAddress(orderIndex),
+          maker: maker.publicKey,
+          orderVault: vaultAddress(orderIndex),
+          makerTokenOut: makerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          filler: filler.publicKey,
+          fillerTokenIn: fillerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([filler])
+        .rpc();
+
+    const cancelOrder = (orderIndex: number, authority: Keypair) =>
+      program.methods
+        .cancelOrder()
+        .accounts({
+          tokenInMint: seeded.mintA,
+          order: orderAddress(orderIndex),
+          maker: maker.publicKey,
+          orderVault: vaultAddress(orderIndex),
+          makerTokenIn: makerTokenA,
+          authority: authority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await ensureSolBalance(maker);
+      makerTokenA = await fundAccount(
+        seeded.mintA,
+        maker.publicKey,
+        100_000_000
+      );
+      makerTokenB = await fundAccount(seeded.mintB, maker.publicKey, 0);
+      fillerTokenA = await fundAccount(seeded.mintA, filler.publicKey, 0);
+    });
+
+    it("Should escrow the maker's input", async () => {
+      const balanceBefore = await getTokenBalance(makerTokenA);
+      // At least 1.1 token B per token A, well above the pool price
+      const signature = await createOrder(
+        0,
+        10_000_000,
+        11,
+        10,
+        (await getClusterTime()) + 600
+      );
+      const event = await getEvent(signature, "orderCreatedEvent");
+
+      assert.equal(event.order.toBase58(), orderAddress(0).toBase58());
+      assert.equal(event.amountIn.toString(), "10000000");
+      assert.equal(
+        (await getTokenBalance(vaultAddress(0))).toString(),
+        "10000000"
+      );
+      assert.equal(
+        balanceBefore.sub(await getTokenBalance(makerTokenA)).toString(),
+        "10000000"
+      );
+    });
+
+    it("Should reject an expiry in the past", async () => {
+      await expectError(
+        createOrder(9, 1_000_000, 1, 1, (await getClusterTime()) - 1),
+        "InvalidOrderExpiry"
+      );
+    });
+
+    it("Should not fill below the maker's price", async () => {
+      await expectError(fillOrder(0), "LimitPriceExceeded");
+    });
+
+    it("Should fill once the pool price crosses the limit", async () => {
+      // Buying token A with token B raises its price
+      await fundAccount(seeded.mintB, payer.publicKey, 200_000_000);
+      await program.methods
+        .swap(
+          new BN(200_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintB,
+          tokenOutMint: seeded.mintA,
+          userTokenIn: seeded.payerTokenB,
+          userTokenOut: seeded.payerTokenA,
+          poolTokenIn: seeded.vaultB,
+          poolTokenOut: seeded.vaultA,
+          ownerTokenAccount: seeded.payerTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // The filler keeps 0.1% of the input
+      const expectedOut = (
+        await program.methods
+          .quoteSwap(new BN(9_990_000), true)
+          .accounts({
+            pool: seeded.pool,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+          })
+          .view()
+      ).amountOut as anchor.BN;
+      const makerLamportsBefore = await provider.connection.getBalance(
+        maker.publicKey
+      );
+
+      const signature = await fillOrder(0);
+      const event = await getEvent(signature, "orderFilledEvent");
+
+      assert.equal(event.amountIn.toString(), "9990000");
+      assert.equal(event.fillerReward.toString(), "10000");
+      assert.equal(event.amountOut.toString(), expectedOut.toString());
+      assert.isTrue(expectedOut.muln(10).gte(new BN(110_000_000)));
+      assert.equal(
+        (await getTokenBalance(makerTokenB)).toString(),
+        expectedOut.toString()
+      );
+      assert.equal((await getTokenBalance(fillerTokenA)).toString(), "10000");
+
+      // The order and its escrow are closed, with the rent back to the maker
+      assert.isNull(await provider.connection.getAccountInfo(orderAddress(0)));
+      assert.isNull(await provider.connection.getAccountInfo(vaultAddress(0)));
+      assert.isAbove(
+        await provider.connection.getBalance(maker.publicKey),
+        makerLamportsBefore
+      );
+    });
+
+    it("Should let the maker cancel at any time", async () => {
+      await createOrder(1, 5_000_000, 10, 1, (await getClusterTime()) + 600);
+      const balanceBefore = await getTokenBalance(makerTokenA);
+
+      const signature = await cancelOrder(1, maker);
+      const event = await getEvent(signature, "orderCancelledEvent");
+
+      assert.isFalse(event.expired);
+      assert.equal(
+        (await getTokenBalance(makerTokenA)).sub(balanceBefore).toString(),
+        "5000000"
+      );
+      assert.isNull(await provider.connection.getAccountInfo(orderAddress(1)));
+    });
+
+    it("Should let anyone cancel an expired order for the maker", async () => {
+      const expiry = (await getClusterTime()) + 3;
+      await createOrder(2, 5_000_000, 1, 1, expiry);
+      await expectError(cancelOrder(2, filler), "OrderNotExpired");
+
+      await waitUntil(expiry);
+      await expectError(fillOrder(2), "OrderExpired");
+
+      const balanceBefore = await getTokenBalance(makerTokenA);
+      const makerLamportsBefore = await provider.connection.getBalance(
+        maker.publicKey
+      );
+      const signature = await cancelOrder(2, filler);
+      const event = await getEvent(signature, "orderCancelledEvent");
+
+      assert.isTrue(event.expired);
+      assert.equal(
+        (await getTokenBalance(makerTokenA)).sub(balanceBefore).toString(),
+        "5000000"
+      );
+      assert.isAbove(
+        await provider.connection.getBalance(maker.publicKey),
+        makerLamportsBefore
+      );
+    });
+  });
+
+  describe("Fee Updates", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+
+    const updateFee = (totalFeeBps: number, authority: Keypair = payer) =>
+      changeParam(seeded.pool, { fee: { totalFeeBps } }, authority);
+
+    const swapFee = async () => {
+      const signature = await program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      return (await getEvent(signature, "swapExecutedEvent")).fee.toString();
+    };
+
+    before(async () => {
+      await disableParamChangeDelay();
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 10_000_000);
+    });
+
+    it("Should let the authority change the fee", async () => {
+      assert.equal(await swapFee(), "3000");
+
+      const signature = await updateFee(100);
+      const event = await getEvent(signature, "paramChangeAppliedEvent");
+      assert.equal(event.previous.fee.totalFeeBps, 30);
+      assert.equal(event.change.fee.totalFeeBps, 100);
+      const feeEvent = await getEvent(signature, "feeUpdatedEvent");
+      assert.equal(feeEvent.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(feeEvent.authority.toBase58(), payer.publicKey.toBase58());
+      assert.equal(feeEvent.previousTotalFeeBps, 30);
+      assert.equal(feeEvent.totalFeeBps, 100);
+      assert.equal(feeEvent.timestamp.toString(), event.timestamp.toString());
+
+      // The next swap pays the new fee
+      assert.equal(await swapFee(), "10000");
+    });
+
+    it("Should reject anyone but the authority", async () => {
+      await expectError(updateFee(50, outsider), "Unauthorized");
+    });
+
+    it("Should reject a fee above the cap", async () => {
+      await expectError(updateFee(1_001), "InvalidFee");
+      await updateFee(1_000);
+    });
+  });
+
+  describe("Authority Transfers", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const newAuthority = Keypair.generate();
+    const outsider = Keypair.generate();
+
+    const proposeAuthority = (pending: PublicKey, authority: Keypair) =>
+      program.methods
+        .proposeAuthority(pending)
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const acceptAuthority = (signer: Keypair) =>
+      program.methods
+        .acceptAuthority()
+        .accounts({ pool: seeded.pool, newAuthority: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    const cancelTransfer = (authority: Keypair) =>
+      program.methods
+        .cancelAuthorityTransfer()
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+    });
+
+    it("Should only let the authority propose a transfer", async () => {
+      await expectError(
+        proposeAuthority(outsider.publicKey, outsider),
+        "Unauthorized"
+      );
+      await expectError(
+        proposeAuthority(PublicKey.default, payer),
+        "InvalidPendingAuthority"
+      );
+    });
+
+    it("Should hand over the pool once the pending key accepts", async () => {
+      const proposed = await proposeAuthority(newAuthority.publicKey, payer);
+      const proposedEvent = await getEvent(
+        proposed,
+        "authorityTransferProposedEvent"
+      );
+      assert.equal(proposedEvent.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(
+        proposedEvent.authority.toBase58(),
+        payer.publicKey.toBase58()
+      );
+      assert.equal(
+        proposedEvent.previousPendingAuthority.toBase58(),
+        PublicKey.default.toBase58()
+      );
+      assert.equal(
+        proposedEvent.pendingAuthority.toBase58(),
+        newAuthority.publicKey.toBase58()
+      );
+      assert.isAbove(proposedEvent.timestamp.toNumber(), 0);
+      assert.isAbove(proposedEvent.slot.toNumber(), 0);
+
+      // The current authority keeps control until the transfer is accepted
+      let poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        poolAccount.authority.toBase58(),
+        payer.publicKey.toBase58()
+      );
+
+      await expectError(acceptAuthority(outsider), "NotPendingAuthority");
+
+      const accepted = await acceptAuthority(newAuthority);
+      const acceptedEvent = await getEvent(
+        accepted,
+        "authorityTransferredEvent"
+      );
+      assert.equal(acceptedEvent.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(
+        acceptedEvent.previousAuthority.toBase58(),
+        payer.publicKey.toBase58()
+      );
+      assert.equal(
+        acceptedEvent.authority.toBase58(),
+        newAuthority.publicKey.toBase58()
+      );
+      assert.isAbove(acceptedEvent.timestamp.toNumber(), 0);
+      assert.isAbove(acceptedEvent.slot.toNumber(), 0);
+
+      poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        poolAccount.authority.toBase58(),
+        newAuthority.publicKey.toBase58()
+      );
+      assert.isTrue(poolAccount.pendingAuthority.equals(PublicKey.default));
+
+      // The old authority is locked out, the new one is in
+      await expectError(
+        program.methods
+          .setDepositFee(10)
+          .accounts({ pool: seeded.pool, authority: payer.publicKey })
+          .rpc(),
+        "Unauthorized"
+      );
+      await program.methods
+        .setDepositFee(0)
+        .accounts({ pool: seeded.pool, authority: newAuthority.publicKey })
+        .signers([newAuthority])
+        .rpc();
+    });
+
+    it("Should let the authority cancel a pending transfer", async () => {
+      await proposeAuthority(outsider.publicKey, newAuthority);
+
+      await expectError(cancelTransfer(outsider), "Unauthorized");
+      const signature = await cancelTransfer(newAuthority);
+      const event = await getEvent(
+        signature,
+        "authorityTransferCancelledEvent"
+      );
+      assert.equal(
+        event.cancelledAuthority.toBase58(),
+        outsider.publicKey.toBase58()
+      );
+
+      await expectError(acceptAuthority(outsider), "NotPendingAuthority");
+      await expectError(cancelTransfer(newAuthority), "NoPendingAuthority");
+
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        poolAccount.authority.toBase58(),
+        newAuthority.publicKey.toBase58()
+      );
+    });
+  });
+
+  describe("Renounced Authority", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+
+    const renounceAuthority = (authority: Keypair = payer) =>
+      program.methods
+        .renounceAuthority()
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const findFeeVault = (mint: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("fee_vault"), seeded.pool.toBuffer(), mint.toBuffer()],
+        program.programId
+      )[0];
+
+    const findPda = (seed: string, key: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from(seed), key.toBuffer()],
+        program.programId
+      )[0];
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 10_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 10_000_000);
+    });
+
+    it("Should only let the authority renounce", async () => {
+      await expectError(renounceAuthority(outsider), "Unauthorized");
+    });
+
+    it("Should renounce the authority and drop a pending transfer", async () => {
+      await program.methods
+        .proposeAuthority(outsider.publicKey)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+      const signature = await renounceAuthority();
+      const event = await getEvent(signature, "authorityRenouncedEvent");
+      assert.equal(
+        event.previousAuthority.toBase58(),
+        payer.publicKey.toBase58()
+      );
+
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.isTrue(poolAccount.authority.equals(PublicKey.default));
+      assert.isTrue(poolAccount.pendingAuthority.equals(PublicKey.default));
+
+      // The proposed key can no longer take the pool over
+      await expectError(
+        program.methods
+          .acceptAuthority()
+          .accounts({ pool: seeded.pool, newAuthority: outsider.publicKey })
+          .signers([outsider])
+          .rpc(),
+        "NotPendingAuthority"
+      );
+    });
+
+    it("Should block every authority instruction", async () => {
+      const authorityOnly = { pool: seeded.pool, authority: payer.publicKey };
+      const rewardMint = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        6
+      );
+      const farm = findPda("farm", seeded.pool);
+
+      const blocked = [
+        program.methods.renounceAuthority().accounts(authorityOnly),
+        program.methods
+          .proposeAuthority(payer.publicKey)
+          .accounts(authorityOnly),
+        program.methods.cancelAuthorityTransfer().accounts(authorityOnly),
+        program.methods
+          .proposeParamChange({
+            fee: { totalFeeBps: 50 },
+          })
+          .accounts(authorityOnly),
+        program.methods.cancelParamChange().accounts(authorityOnly),
+        program.methods.setDepositFee(10).accounts(authorityOnly),
+        program.methods.setWithdrawalFee(10).accounts(authorityOnly),
+        program.methods.setMaxImbalance(100).accounts(authorityOnly),
+        program.methods.setGating(true, true).accounts(authorityOnly),
+        program.methods.setTradeStart(new BN(0)).accounts(authorityOnly),
+        program.methods.setSwapHook(PublicKey.default).accounts(authorityOnly),
+        program.methods
+          .setLaunchLimit(seeded.mintB, new BN(60), new BN(1))
+          .accounts(authorityOnly),
+        program.methods
+          .setFeeMode({ buybackBurn: {} }, seeded.mintB)
+          .accounts({
+            ...authorityOnly,
+            tokenAMint: seeded.mintA,
+            tokenBMint: seeded.mintB,
+            feeVaultA: findFeeVault(seeded.mintA),
+            feeVaultB: findFeeVault(seeded.mintB),
+            tokenProgram: TOKEN_PROGRAM_ID,
+            tokenBProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: SYSVAR_RENT_PUBKEY,
+          }),
+        program.methods.skim().accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          ...authorityOnly,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          toTokenA: seeded.payerTokenA,
+          toTokenB: seeded.payerTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }),
+        program.methods.addToAllowlist(outsider.publicKey).accounts({
+          ...authorityOnly,
+          allowlistEntry: PublicKey.findProgramAddressSync(
+            [
+              Buffer.from("allowlist"),
+              seeded.pool.toBuffer(),
+              outsider.publicKey.toBuffer(),
+            ],
+            program.programId
+          )[0],
+          systemProgram: SystemProgram.programId,
+        }),
+        program.methods.initializeFarm(new BN(1)).accounts({
+          ...authorityOnly,
+          farm,
+          lpMint: seeded.lpMint,
+          rewardMint,
+          stakeVault: findPda("farm_stake", farm),
+          rewardVault: findPda("farm_reward", farm),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rewardTokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        }),
+      ];
+
+      for (const instruction of blocked) {
+        await expectError(instruction.rpc(), "AuthorityRenounced");
+      }
+    });
+
+    it("Should keep swaps and liquidity working", async () => {
+      await program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(1),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const lpBefore = await getTokenBalance(seeded.payerLp);
+      await program.methods
+        .addLiquidity(
+          new BN(1_000_000),
+          new BN(1_000_000),
+          new BN(1),
+          new BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const minted = (await getTokenBalance(seeded.payerLp)).sub(lpBefore);
+      assert.isTrue(minted.gtn(0));
+
+      await program.methods
+        .removeLiquidity(minted, new BN(1), new BN(1), false, new BN(0))
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.equal(
+        (await getTokenBalance(seeded.payerLp)).toString(),
+        lpBefore.toString()
+      );
+    });
+  });
+
+  describe("Pool Status", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+    const SWAPS_PAUSED = 1;
+    const DEPOSITS_PAUSED = 2;
+    const WITHDRAWALS_PAUSED = 4;
+
+    const setPoolStatus = (
+      status: number,
+      emergency: boolean,
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .setPoolStatus(status, emergency)
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const swap = () =>
+      program.methods
+        .swap(new BN(10_000), new BN(0), new BN(0), new BN(0), new BN(0), null)
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          ownerTokenAccount: seeded.payerTokenA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const liquidityAccounts = () => ({
+      pool: seeded.pool,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      userTokenA: seeded.payerTokenA,
+      userTokenB: seeded.payerTokenB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+      userLp: seeded.payerLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    const deposit = () =>
+      program.methods
+        .addLiquidity(new BN(10_000), new BN(10_000), new BN(0), new BN(0))
+        .accounts({ ...liquidityAccounts(), owner: payer.publicKey })
+        .rpc();
+
+    const withdraw = () =>
+      program.methods
+        .removeLiquidity(new BN(1_000), new BN(0), new BN(0), false, new BN(0))
+        .accounts({ ...liquidityAccounts(), user: payer.publicKey })
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 10_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 10_000_000);
+    });
+
+    it("Should start with everything enabled", async () => {
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.status, 0);
+      await swap();
+      await deposit();
+      await withdraw();
+    });
+
+    it("Should only let the authority change the status", async () => {
+      await expectError(
+        setPoolStatus(SWAPS_PAUSED, false, outsider),
+        "Unauthorized"
+      );
+      await expectError(setPoolStatus(8, false), "InvalidPoolStatus");
+    });
+
+    it("Should require the emergency flag to pause withdrawals", async () => {
+      await expectError(
+        setPoolStatus(WITHDRAWALS_PAUSED, false),
+        "EmergencyNotAcknowledged"
+      );
+      await expectError(
+        setPoolStatus(SWAPS_PAUSED | WITHDRAWALS_PAUSED, false),
+        "EmergencyNotAcknowledged"
+      );
+    });
+
+    it("Should pause each operation independently", async () => {
+      const operations: [number, () => Promise<string>][] = [
+        [SWAPS_PAUSED, swap],
+        [DEPOSITS_PAUSED, deposit],
+        [WITHDRAWALS_PAUSED, withdraw],
+      ];
+
+      for (let status = 0; status <= 7; status++) {
+        const emergency = (status & WITHDRAWALS_PAUSED) !== 0;
+        const signature = await setPoolStatus(status, emergency);
+        const event = await getEvent(signature, "poolStatusChangedEvent");
+        assert.equal(event.status, status);
+        assert.equal(event.emergency, emergency);
+
+        for (const [bit, operation] of operations) {
+          if (status & bit) {
+            await expectError(operation(), "PoolPaused");
+          } else {
+            await operation();
+          }
+        }
+      }
+    });
+
+    it("Should not renounce with withdrawals paused", async () => {
+      await setPoolStatus(WITHDRAWALS_PAUSED, true);
+      await expectError(
+        program.methods
+          .renounceAuthority()
+          .accounts({ pool: seeded.pool, authority: payer.publicKey })
+          .rpc(),
+        "PoolPaused"
+      );
+
+      const signature = await setPoolStatus(0, false);
+      const event = await getEvent(signature, "poolStatusChangedEvent");
+      assert.equal(event.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(event.authority.toBase58(), payer.publicKey.toBase58());
+      assert.equal(event.previousStatus, WITHDRAWALS_PAUSED);
+      assert.equal(event.status, 0);
+      assert.isAbove(event.timestamp.toNumber(), 0);
+      await withdraw();
+    });
+  });
+
+  describe("Closing Pools", () => {
+    type SeededPool = Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+
+    const closePool = (seeded: SeededPool, authority: Keypair = payer) =>
+      program.methods
+        .closePool()
+        .accounts({
+          pool: seeded.pool,
+          authority: authority.publicKey,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          registryEntry: seeded.registryEntry,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+    // Withdraws every LP token, leaving both vaults empty
+    const drain = async (seeded: SeededPool) =>
+      program.methods
+        .removeLiquidity(
+          await getTokenBalance(seeded.payerLp),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    it("Should close a drained pool and return the rent", async () => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await drain(seeded);
+      await expectError(closePool(seeded, outsider), "Unauthorized");
+
+      const balanceBefore = await provider.connection.getBalance(
+        payer.publicKey
+      );
+      const signature = await closePool(seeded);
+      const event = await getEvent(signature, "poolClosedEvent");
+      assert.equal(event.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(event.lpMint.toBase58(), seeded.lpMint.toBase58());
+
+      for (const account of [seeded.pool, seeded.vaultA, seeded.vaultB]) {
+        assert.isNull(await provider.connection.getAccountInfo(account));
+      }
+      // The rent of three accounts outweighs the transaction fee
+      assert.isAbove(
+        await provider.connection.getBalance(payer.publicKey),
+        balanceBefore
+      );
+
+      // The LP mint stays behind, but can never mint again
+      const lpMint = await getMint(provider.connection, seeded.lpMint);
+      assert.isNull(lpMint.mintAuthority);
+      assert.equal(lpMint.supply.toString(), "0");
+    });
+
+    it("Should not close a pool with LP tokens outstanding", async () => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await expectError(closePool(seeded), "PoolNotEmpty");
+    });
+
+    it("Should not close a pool with dust in a vault", async () => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await drain(seeded);
+      await mintTo(
+        provider.connection,
+        payer,
+        seeded.mintB,
+        seeded.vaultB,
+        payer,
+        1
+      );
+
+      await expectError(closePool(seeded), "PoolNotEmpty");
+    });
+  });
+
+  describe("Fee Recipients", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const trader = Keypair.generate();
+    const recipient = Keypair.generate();
+    let traderTokenA: PublicKey;
+    let traderTokenB: PublicKey;
+    const BN = anchor.BN;
+
+    // Swaps 1,000,000 for the trader, returning the protocol fee charged
+    const swap = async (aToB: boolean) => {
+      const signature = await program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: trader.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? traderTokenA : traderTokenB,
+          userTokenOut: aToB ? traderTokenB : traderTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([trader])
+        .rpc();
+      return (await getEvent(signature, "swapExecutedEvent")).protocolFee;
+    };
+
+    const setFeeRecipient = (wallet: PublicKey, authority: Keypair = payer) =>
+      changeParam(
+        seeded.pool,
+        { feeRecipient: { feeRecipient: wallet } },
+        authority
+      );
+
+    const collect = (
+      wallet: Keypair,
+      recipientTokenA: PublicKey,
+      recipientTokenB: PublicKey
+    ) =>
+      program.methods
+        .collectProtocolFees()
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          feeRecipient: wallet.publicKey,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          recipientTokenA,
+          recipientTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([wallet])
+        .rpc();
+
+    // Swaps both ways, then checks `wallet` collects exactly what accrued
+    const expectFeesCollectedBy = async (
+      wallet: Keypair,
+      walletTokenA: PublicKey,
+      walletTokenB: PublicKey
+    ) => {
+      const feeA = await swap(true);
+      const feeB = await swap(false);
+      assert.isTrue(feeA.gtn(0) && feeB.gtn(0));
+
+      const beforeA = await getTokenBalance(walletTokenA);
+      const beforeB = await getTokenBalance(walletTokenB);
+      const signature = await collect(wallet, walletTokenA, walletTokenB);
+      const event = await getEvent(signature, "protocolFeesCollectedEvent");
+      assert.equal(event.feeRecipient.toBase58(), wallet.publicKey.toBase58());
+      assert.equal(event.amountA.toString(), feeA.toString());
+      assert.equal(event.amountB.toString(), feeB.toString());
+      assert.equal(
+        (await getTokenBalance(walletTokenA)).sub(beforeA).toString(),
+        feeA.toString()
+      );
+      assert.equal(
+        (await getTokenBalance(walletTokenB)).sub(beforeB).toString(),
+        feeB.toString()
+      );
+    };
+
+    before(async () => {
+      await disableParamChangeDelay();
+      await ensureSolBalance(trader);
+      await ensureSolBalance(recipient);
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      traderTokenA = await fundAccount(
+        seeded.mintA,
+        trader.publicKey,
+        10_000_000
+      );
+      traderTokenB = await fundAccount(
+        seeded.mintB,
+        trader.publicKey,
+        10_000_000
+      );
+    });
+
+    it("Should default the fee recipient to the pool creator", async () => {
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        poolAccount.feeRecipient.toBase58(),
+        payer.publicKey.toBase58()
+      );
+
+      await expectFeesCollectedBy(
+        payer,
+        seeded.payerTokenA,
+        seeded.payerTokenB
+      );
+    });
+
+    it("Should only let the fee recipient collect", async () => {
+      await swap(true);
+      await expectError(
+        collect(trader, traderTokenA, traderTokenB),
+        "NotFeeRecipient"
+      );
+      // Recipient accounts must match the pool's mints
+      await expectError(
+        collect(payer, seeded.payerTokenB, seeded.payerTokenA),
+        "ConstraintTokenMint"
+      );
+    });
+
+    it("Should rotate the fee recipient", async () => {
+      await expectError(
+        setFeeRecipient(recipient.publicKey, trader),
+        "Unauthorized"
+      );
+      await expectError(
+        setFeeRecipient(PublicKey.default),
+        "InvalidFeeRecipient"
+      );
+
+      // Whatever accrued before the rotation moves with it
+      await collect(payer, seeded.payerTokenA, seeded.payerTokenB);
+      const signature = await setFeeRecipient(recipient.publicKey);
+      const event = await getEvent(signature, "paramChangeAppliedEvent");
+      assert.equal(
+        event.previous.feeRecipient.feeRecipient.toBase58(),
+        payer.publicKey.toBase58()
+      );
+      assert.equal(
+        event.change.feeRecipient.feeRecipient.toBase58(),
+        recipient.publicKey.toBase58()
+      );
+      const changed = await getEvent(signature, "feeRecipientChangedEvent");
+      assert.equal(changed.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(changed.authority.toBase58(), payer.publicKey.toBase58());
+      assert.equal(
+        changed.previousFeeRecipient.toBase58(),
+        payer.publicKey.toBase58()
+      );
+      assert.equal(
+        changed.feeRecipient.toBase58(),
+        recipient.publicKey.toBase58()
+      );
+      assert.isAbove(changed.timestamp.toNumber(), 0);
+      assert.isAbove(changed.slot.toNumber(), 0);
+
+      await expectError(
+        collect(payer, seeded.payerTokenA, seeded.payerTokenB),
+        "NotFeeRecipient"
+      );
+      const recipientTokenA = await fundAccount(
+        seeded.mintA,
+        recipient.publicKey,
+        0
+      );
+      const recipientTokenB = await fundAccount(
+        seeded.mintB,
+        recipient.publicKey,
+        0
+      );
+      await expectFeesCollectedBy(recipient, recipientTokenA, recipientTokenB);
+    });
+  });
+
+  describe("LP Fee Share", () => {
+    type SeededPool = Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+
+    const setFeeSplit = (
+      seeded: SeededPool,
+      lpFeeBps: number,
+      protocolFeeBps: number,
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .setFeeSplit(lpFeeBps, protocolFeeBps)
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const swap = async (seeded: SeededPool, aToB: boolean, amount: number) => {
+      const signature = await program.methods
+        .swap(new BN(amount), new BN(0), new BN(0), new BN(0), new BN(0), null)
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      return getEvent(signature, "swapExecutedEvent");
+    };
+
+    // Constant-product invariant of the pool's reserves
+    const invariant = async (seeded: SeededPool) => {
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      return poolAccount.reserveA.mul(poolAccount.reserveB);
+    };
+
+    const createPool = async () => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+      return seeded;
+    };
+
+    it("Should only accept shares adding up to 10,000 bps", async () => {
+      const seeded = await createPool();
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.lpFeeBps, 0);
+      assert.equal(poolAccount.protocolFeeBps, 10_000);
+
+      await expectError(
+        setFeeSplit(seeded, 5_000, 5_000, outsider),
+        "Unauthorized"
+      );
+      await expectError(setFeeSplit(seeded, 5_000, 4_000), "InvalidFeeSplit");
+
+      const signature = await setFeeSplit(seeded, 2_500, 7_500);
+      const event = await getEvent(signature, "feeSplitSetEvent");
+      assert.equal(event.previousLpFeeBps, 0);
+      assert.equal(event.lpFeeBps, 2_500);
+      assert.equal(event.protocolFeeBps, 7_500);
+    });
+
+    it("Should leave the LP share of the fee in the pool", async () => {
+      const seeded = await createPool();
+      await setFeeSplit(seeded, 5_000, 5_000);
+      const vaultBefore = await getTokenBalance(seeded.vaultA);
+      const traderBefore = await getTokenBalance(seeded.payerTokenA);
+
+      // A 0.3% fee on 1,000,000, half of it for the LPs
+      const event = await swap(seeded, true, 1_000_000);
+      assert.equal(event.fee.toString(), "3000");
+      assert.equal(event.lpFee.toString(), "1500");
+      assert.equal(event.protocolFee.toString(), "1500");
+
+      // The whole input lands in the vault, but only the LP share of the
+      // fee joins the reserves; the rest is owed to the fee recipient
+      const vaultAfter = await getTokenBalance(seeded.vaultA);
+      assert.equal(vaultAfter.sub(vaultBefore).toString(), "1000000");
+      assert.equal(
+        traderBefore.sub(await getTokenBalance(seeded.payerTokenA)).toString(),
+        "1000000"
+      );
+
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.protocolFeesOwedA.toString(), "1500");
+      assert.equal(
+        poolAccount.reserveA.toString(),
+        vaultAfter.subn(1_500).toString()
+      );
+    });
+
+    it("Should grow LP token value with volume", async () => {
+      const withLpFee = await createPool();
+      const withoutLpFee = await createPool();
+      await setFeeSplit(withLpFee, 10_000, 0);
+
+      const before = await invariant(withLpFee);
+      for (const seeded of [withLpFee, withoutLpFee]) {
+        for (let i = 0; i < 3; i++) {
+          await swap(seeded, true, 10_000_000);
+          await swap(seeded, false, 10_000_000);
+        }
+      }
+
+      // Same LP supply in both, so a larger invariant is worth more per LP
+      const grown = await invariant(withLpFee);
+      assert.isTrue(grown.gt(before));
+      assert.isTrue(grown.gt(await invariant(withoutLpFee)));
+
+      const lpSupply = async (seeded: SeededPool) =>
+        (await getMint(provider.connection, seeded.lpMint)).supply.toString();
+      assert.equal(await lpSupply(withLpFee), await lpSupply(withoutLpFee));
+    });
+  });
+
+  describe("Protocol Fee Accrual", () => {
+    type SeededPool = Awaited<ReturnType<typeof createSeededPool>>;
+    const trader = Keypair.generate();
+    const BN = anchor.BN;
+
+    const createPool = async () => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      const traderTokenA = await fundAccount(
+        seeded.mintA,
+        trader.publicKey,
+        100_000_000
+      );
+      const traderTokenB = await fundAccount(
+        seeded.mintB,
+        trader.publicKey,
+        100_000_000
+      );
+      return { seeded, traderTokenA, traderTokenB };
+    };
+    type TradedPool = Awaited<ReturnType<typeof createPool>>;
+
+    // Swaps back and forth, returning the protocol fees charged on each side
+    const trade = async (traded: TradedPool) => {
+      const { seeded, traderTokenA, traderTokenB } = traded;
+      let feesA = new BN(0);
+      let feesB = new BN(0);
+      for (let i = 0; i < 6; i++) {
+        const aToB = i % 2 === 0;
+        const signature = await program.methods
+          .swap(
+            new BN(5_000_000),
+            new BN(0),
+            new BN(0),
+            new BN(0),
+            new BN(0),
+            null
+          )
+          .accounts({
+            pool: seeded.pool,
+            owner: trader.publicKey,
+            tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+            tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+            userTokenIn: aToB ? traderTokenA : traderTokenB,
+            userTokenOut: aToB ? traderTokenB : traderTokenA,
+            poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+            poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([trader])
+          .rpc();
+        const { protocolFee } = await getEvent(signature, "swapExecutedEvent");
+        if (aToB) {
+          feesA = feesA.add(protocolFee);
+        } else {
+          feesB = feesB.add(protocolFee);
+        }
+      }
+      return { feesA, feesB };
+    };
+
+    const collect = (seeded: SeededPool) =>
+      program.methods
+        .collectProtocolFees()
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          feeRecipient: payer.publicKey,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          recipientTokenA: seeded.payerTokenA,
+          recipientTokenB: seeded.payerTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(trader);
+    });
+
+    it("Should accrue fees in the pool until collected", async () => {
+      const traded = await createPool();
+      const { seeded } = traded;
+      const recipientA = await getTokenBalance(seeded.payerTokenA);
+      const recipientB = await getTokenBalance(seeded.payerTokenB);
+
+      const { feesA, feesB } = await trade(traded);
+      let poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.protocolFeesOwedA.toString(), feesA.toString());
+      assert.equal(poolAccount.protocolFeesOwedB.toString(), feesB.toString());
+      // Nothing is paid out per swap
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA)).toString(),
+        recipientA.toString()
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenB)).toString(),
+        recipientB.toString()
+      );
+
+      // The reserves exclude the owed fees
+      assert.equal(
+        poolAccount.reserveA.add(feesA).toString(),
+        (await getTokenBalance(seeded.vaultA)).toString()
+      );
+      assert.equal(
+        poolAccount.reserveB.add(feesB).toString(),
+        (await getTokenBalance(seeded.vaultB)).toString()
+      );
+
+      await collect(seeded);
+      poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.protocolFeesOwedA.toString(), "0");
+      assert.equal(poolAccount.protocolFeesOwedB.toString(), "0");
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA)).sub(recipientA).toString(),
+        feesA.toString()
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenB)).sub(recipientB).toString(),
+        feesB.toString()
+      );
+
+      // Collecting again pays nothing
+      const signature = await collect(seeded);
+      const event = await getEvent(signature, "protocolFeesCollectedEvent");
+      assert.equal(event.amountA.toString(), "0");
+      assert.equal(event.amountB.toString(), "0");
+    });
+
+    it("Should keep owed fees out of LP withdrawals", async () => {
+      const traded = await createPool();
+      const { seeded } = traded;
+      const { feesA, feesB } = await trade(traded);
+
+      await program.methods
+        .removeLiquidity(
+          await getTokenBalance(seeded.payerLp),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // Only the owed fees are left, and the fee recipient can still take them
+      assert.equal(
+        (await getTokenBalance(seeded.vaultA)).toString(),
+        feesA.toString()
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.vaultB)).toString(),
+        feesB.toString()
+      );
+      await collect(seeded);
+      assert.equal((await getTokenBalance(seeded.vaultA)).toString(), "0");
+      assert.equal((await getTokenBalance(seeded.vaultB)).toString(), "0");
+    });
+  });
+
+  describe("Program Config", () => {
+    type SeededPool = Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+    let seeded: SeededPool;
+
+    const swap = (seeded: SeededPool) =>
+      program.methods
+        .swap(new BN(10_000), new BN(0), new BN(0), new BN(0), new BN(0), null)
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      await disableParamChangeDelay();
+      // Created before any of the settings below, so it must keep its own
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+    });
+
+    after(async () => {
+      await updateConfig({
+        defaultProtocolFeeBps: 10_000,
+        paused: false,
+        poolCreation: { permissionless: {} },
+        feeTiers: [],
+      });
+    });
+
+    it("Should start from the defaults pools had without a config", async () => {
+      const config = await program.account.globalConfig.fetch(configAddress);
+      assert.equal(config.admin.toBase58(), payer.publicKey.toBase58());
+      assert.equal(config.defaultProtocolFeeBps, 10_000);
+      assert.isFalse(config.paused);
+      assert.deepEqual(config.poolCreation, { permissionless: {} });
+      assert.lengthOf(config.feeTiers, 0);
+    });
+
+    it("Should only let the admin update the config", async () => {
+      await expectError(
+        updateConfig({ paused: true }, outsider),
+        "Unauthorized"
+      );
+      await expectError(
+        updateConfig({ admin: PublicKey.default }),
+        "InvalidAdmin"
+      );
+      await expectError(
+        updateConfig({ defaultProtocolFeeBps: 10_001 }),
+        "InvalidFeeSplit"
+      );
+      await expectError(
+        updateConfig({ feeTiers: Array(9).fill({ feeBps: 30 }) }),
+        "InvalidFeeTiers"
+      );
+      await expectError(
+        updateConfig({ feeTiers: [{ feeBps: 1_001 }] }),
+        "InvalidFeeTiers"
+      );
+
+      // Fields left out stay as they are
+      const signature = await updateConfig({ defaultProtocolFeeBps: 8_000 });
+      const event = await getEvent(signature, "configUpdatedEvent");
+      assert.equal(event.admin.toBase58(), payer.publicKey.toBase58());
+      assert.equal(event.defaultProtocolFeeBps, 8_000);
+      assert.isFalse(event.paused);
+      assert.deepEqual(event.poolCreation, { permissionless: {} });
+      assert.isAbove(event.timestamp.toNumber(), 0);
+    });
+
+    it("Should pause swaps on every pool", async () => {
+      await updateConfig({ paused: true });
+      await expectError(swap(seeded), "ProtocolPaused");
+      const other = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await expectError(swap(other), "ProtocolPaused");
+
+      // LPs can still leave
+      await program.methods
+        .removeLiquidity(new BN(1_000), new BN(0), new BN(0), false, new BN(0))
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      await updateConfig({ paused: false });
+      await swap(seeded);
+      await swap(other);
+    });
+
+    it("Should restrict pool creation to the admin", async () => {
+      await updateConfig({ poolCreation: { adminOnly: {} } });
+      await expectError(createPoolAs(outsider), "PoolCreationRestricted");
+      await createPoolAs(payer);
+
+      await updateConfig({ poolCreation: { permissionless: {} } });
+      await createPoolAs(outsider);
+    });
+
+    it("Should hold new fees to the fee tiers", async () => {
+      await updateConfig({ feeTiers: [{ feeBps: 30 }, { feeBps: 100 }] });
+
+      await expectError(createPoolAs(payer, 40), "UnsupportedFeeTier");
+      await createPoolAs(payer, 30);
+
+      const updateFee = (totalFeeBps: number) =>
+        changeParam(seeded.pool, { fee: { totalFeeBps } });
+      await expectError(updateFee(40), "UnsupportedFeeTier");
+      await updateFee(100);
+
+      await updateConfig({ feeTiers: [] });
+      await updateFee(40);
+    });
+
+    it("Should give new pools the default protocol fee share", async () => {
+      await updateConfig({ defaultProtocolFeeBps: 7_500 });
+      const { pool: address } = await createPoolAs(payer);
+      const pool = await program.account.pool.fetch(address);
+      assert.equal(pool.lpFeeBps, 2_500);
+      assert.equal(pool.protocolFeeBps, 7_500);
+
+      const existing = await program.account.pool.fetch(seeded.pool);
+      assert.equal(existing.lpFeeBps, 0);
+      assert.equal(existing.protocolFeeBps, 10_000);
+    });
+  });
+
+  describe("Parameter Timelock", () => {
+    type SeededPool = Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+    const DELAY = 10;
+    let seeded: SeededPool;
+
+    const fee = (totalFeeBps: number) => ({ fee: { totalFeeBps } });
+
+    const propose = (
+      change: object,
+      pool: SeededPool = seeded,
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .proposeParamChange(change)
+        .accounts({ pool: pool.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    // Permissionless, so no authority is passed
+    const apply = (pool: SeededPool = seeded) =>
+      program.methods.applyParamChange().accounts({ pool: pool.pool }).rpc();
+
+    const cancel = (pool: SeededPool = seeded, authority: Keypair = payer) =>
+      program.methods
+        .cancelParamChange()
+        .accounts({ pool: pool.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+      await updateConfig({ paramChangeDelaySlots: new BN(DELAY) });
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+    });
+
+    after(async () => {
+      await updateConfig({ paramChangeDelaySlots: new BN(0) });
+    });
+
+    it("Should only apply a change once its delay has passed", async () => {
+      const signature = await propose(fee(100));
+      const event = await getEvent(signature, "paramChangeProposedEvent");
+      const { slot } = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const effectiveSlot = event.effectiveSlot.toNumber();
+      assert.equal(effectiveSlot, slot + DELAY);
+      assert.equal(event.change.fee.totalFeeBps, 100);
+      assert.isAbove(event.timestamp.toNumber(), 0);
+
+      let poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.pendingChangeSlot.toNumber(), effectiveSlot);
+      assert.equal(poolAccount.totalFeeBps, 30);
+
+      await expectError(apply(), "ParamChangeNotReady");
+
+      await waitForSlot(effectiveSlot);
+      const applied = await getEvent(await apply(), "paramChangeAppliedEvent");
+      assert.equal(applied.previous.fee.totalFeeBps, 30);
+      assert.equal(applied.change.fee.totalFeeBps, 100);
+
+      poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.totalFeeBps, 100);
+      assert.isNull(poolAccount.pendingChange);
+      await expectError(apply(), "NoPendingParamChange");
+    });
+
+    it("Should let the authority cancel a pending change", async () => {
+      const feeRecipient = {
+        feeRecipient: { feeRecipient: outsider.publicKey },
+      };
+      await expectError(
+        propose(feeRecipient, seeded, outsider),
+        "Unauthorized"
+      );
+      await expectError(
+        propose({ feeRecipient: { feeRecipient: PublicKey.default } }),
+        "InvalidFeeRecipient"
+      );
+      await propose(feeRecipient);
+      await expectError(propose(fee(50)), "ParamChangePending");
+
+      await expectError(cancel(seeded, outsider), "Unauthorized");
+      const signature = await cancel();
+      const event = await getEvent(signature, "paramChangeCancelledEvent");
+      assert.equal(
+        event.change.feeRecipient.feeRecipient.toBase58(),
+        outsider.publicKey.toBase58()
+      );
+
+      // Nothing is left to apply, even after the delay
+      await waitForSlot((await provider.connection.getSlot()) + DELAY);
+      await expectError(apply(), "NoPendingParamChange");
+      await expectError(cancel(), "NoPendingParamChange");
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        poolAccount.feeRecipient.toBase58(),
+        payer.publicKey.toBase58()
+      );
+    });
+
+    it("Should drop a pending change when the authority renounces", async () => {
+      const renounced = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await propose(fee(100), renounced);
+
+      const signature = await program.methods
+        .renounceAuthority()
+        .accounts({ pool: renounced.pool, authority: payer.publicKey })
+        .signers([payer])
+        .rpc();
+      const event = await getEvent(signature, "paramChangeCancelledEvent");
+      assert.equal(event.change.fee.totalFeeBps, 100);
+
+      await waitForSlot((await provider.connection.getSlot()) + DELAY);
+      await expectError(apply(renounced), "NoPendingParamChange");
+    });
+  });
+
+  describe("Emergency Withdraw", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const guardian = Keypair.generate();
+    const rescue = Keypair.generate();
+    const BN = anchor.BN;
+    const ALL_PAUSED = 7;
+    const GRACE = 10;
+    let rescueTokenA: PublicKey;
+    let rescueTokenB: PublicKey;
+
+    const setPoolStatus = (status: number) =>
+      program.methods
+        .setPoolStatus(status, status === ALL_PAUSED)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .signers([payer])
+        .rpc();
+
+    const emergencyWithdraw = (
+      amountA: number,
+      amountB: number,
+      authority: Keypair = payer,
+      cosigner: Keypair | null = null
+    ) =>
+      program.methods
+        .emergencyWithdraw(new BN(amountA), new BN(amountB))
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          authority: authority.publicKey,
+          guardian: cosigner?.publicKey ?? null,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          destinationA: rescueTokenA,
+          destinationB: rescueTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers(cosigner ? [authority, cosigner] : [authority])
+        .rpc();
+
+    // Pauses everything and waits out the grace period
+    const pauseAndWait = async () => {
+      await setPoolStatus(ALL_PAUSED);
+      const pool = await program.account.pool.fetch(seeded.pool);
+      await waitForSlot(pool.fullyPausedSlot.toNumber() + GRACE);
+    };
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+      await updateConfig({ emergencyGraceSlots: new BN(GRACE) });
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      rescueTokenA = await fundAccount(seeded.mintA, rescue.publicKey, 0);
+      rescueTokenB = await fundAccount(seeded.mintB, rescue.publicKey, 0);
+    });
+
+    afterEach(async () => {
+      await setPoolStatus(0);
+    });
+
+    after(async () => {
+      await updateConfig({
+        emergencyGraceSlots: new BN(9_000),
+        guardian: PublicKey.default,
+      });
+    });
+
+    it("Should refuse unless every operation is paused", async () => {
+      await expectError(emergencyWithdraw(1, 1), "PoolNotFullyPaused");
+      await setPoolStatus(3);
+      await expectError(emergencyWithdraw(1, 1), "PoolNotFullyPaused");
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(pool.fullyPausedSlot.toNumber(), 0);
+    });
+
+    it("Should refuse during the grace period and succeed after", async () => {
+      await setPoolStatus(ALL_PAUSED);
+      let pool = await program.account.pool.fetch(seeded.pool);
+      const fullyPausedSlot = pool.fullyPausedSlot.toNumber();
+      assert.isAbove(fullyPausedSlot, 0);
+
+      await expectError(emergencyWithdraw(1, 1), "EmergencyGracePeriod");
+      await waitForSlot(fullyPausedSlot + GRACE);
+      await expectError(emergencyWithdraw(1, 1, outsider), "Unauthorized");
+      await expectError(emergencyWithdraw(1_000_000_001, 0), "InvalidAmount");
+      await expectError(emergencyWithdraw(0, 0), "InvalidAmount");
+
+      const signature = await emergencyWithdraw(400_000_000, 1_000_000_000);
+      const event = await getEvent(signature, "emergencyWithdrawEvent");
+      assert.equal(event.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(event.authority.toBase58(), payer.publicKey.toBase58());
+      assert.equal(event.guardian.toBase58(), PublicKey.default.toBase58());
+      assert.equal(event.destinationA.toBase58(), rescueTokenA.toBase58());
+      assert.equal(event.destinationB.toBase58(), rescueTokenB.toBase58());
+      assert.equal(event.amountA.toString(), "400000000");
+      assert.equal(event.amountB.toString(), "1000000000");
+      assert.equal(event.remainingA.toString(), "600000000");
+      assert.equal(event.remainingB.toString(), "0");
+      assert.equal(event.reserveA.toString(), "600000000");
+      assert.equal(event.reserveB.toString(), "0");
+      assert.equal(event.fullyPausedSlot.toNumber(), fullyPausedSlot);
+      assert.isAtLeast(event.slot.toNumber(), fullyPausedSlot + GRACE);
+      assert.isAbove(event.timestamp.toNumber(), 0);
+
+      const rescuedA = await getTokenBalance(rescueTokenA);
+      const rescuedB = await getTokenBalance(rescueTokenB);
+      assert.equal(rescuedA.toString(), "400000000");
+      assert.equal(rescuedB.toString(), "1000000000");
+      pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(pool.reserveA.toString(), "600000000");
+      assert.equal(pool.reserveB.toString(), "0");
+    });
+
+    it("Should restart the grace period after an unpause", async () => {
+      await pauseAndWait();
+      await setPoolStatus(3);
+      let pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(pool.fullyPausedSlot.toNumber(), 0);
+
+      await setPoolStatus(ALL_PAUSED);
+      await expectError(emergencyWithdraw(1, 0), "EmergencyGracePeriod");
+      pool = await program.account.pool.fetch(seeded.pool);
+      await waitForSlot(pool.fullyPausedSlot.toNumber() + GRACE);
+      await emergencyWithdraw(1, 0);
+    });
+
+    it("Should need the config guardian's signature once set", async () => {
+      await updateConfig({ guardian: guardian.publicKey });
+      await pauseAndWait();
+
+      await expectError(emergencyWithdraw(1, 0), "GuardianSignatureRequired");
+      await expectError(
+        emergencyWithdraw(1, 0, payer, outsider),
+        "GuardianSignatureRequired"
+      );
+      const signature = await emergencyWithdraw(1, 0, payer, guardian);
+      const event = await getEvent(signature, "emergencyWithdrawEvent");
+      assert.equal(event.guardian.toBase58(), guardian.publicKey.toBase58());
+    });
+  });
+
+  describe("Fee Tiers", () => {
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+
+    const tier = (feeBps: number) => ({ feeBps });
+
+    const updateFeeTiers = (
+      add: number[],
+      remove: number[],
+      admin: Keypair = payer
+    ) =>
+      program.methods
+        .updateFeeTiers(add.map(tier), remove.map(tier))
+        .accounts({ config: configAddress, admin: admin.publicKey })
+        .signers([admin])
+        .rpc();
+
+    const createPool = (feeBps: number) =>
+      createSeededPool(1_000_000, 1_000_000, feeBps);
+
+    const tierBps = async () => {
+      const config = await program.account.globalConfig.fetch(configAddress);
+      return config.feeTiers.map((feeTier) => feeTier.feeBps);
+    };
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+      await updateConfig({ feeTiers: [] });
+    });
+
+    after(async () => {
+      await updateConfig({ feeTiers: [] });
+    });
+
+    it("Should only let the admin update the fee tiers", async () => {
+      await expectError(updateFeeTiers([30], [], outsider), "Unauthorized");
+      await expectError(updateFeeTiers([30, 30], []), "InvalidFeeTiers");
+      await expectError(updateFeeTiers([1_001], []), "InvalidFeeTiers");
+      await expectError(
+        updateFeeTiers([1, 2, 3, 4, 5, 6, 7, 8, 9], []),
+        "InvalidFeeTiers"
+      );
+      await expectError(updateFeeTiers([], [30]), "UnsupportedFeeTier");
+
+      const signature = await updateFeeTiers([1, 5, 30, 100], []);
+      const event = await getEvent(signature, "feeTiersUpdatedEvent");
+      assert.lengthOf(event.feeTiers, 4);
+      assert.isAbove(event.timestamp.toNumber(), 0);
+      assert.deepEqual(await tierBps(), [1, 5, 30, 100]);
+
+      await expectError(updateFeeTiers([30], []), "InvalidFeeTiers");
+      await updateFeeTiers([10], [5]);
+      assert.deepEqual(await tierBps(), [1, 30, 100, 10]);
+      await updateFeeTiers([5], [10]);
+      assert.deepEqual(await tierBps(), [1, 30, 100, 5]);
+    });
+
+    it("Should create pools at each allowed tier only", async () => {
+      for (const bps of [1, 5, 30, 100]) {
+        const { pool } = await createPool(bps);
+        const poolAccount = await program.account.pool.fetch(pool);
+        assert.equal(poolAccount.totalFeeBps, bps);
+      }
+      await expectError(createPool(2), "UnsupportedFeeTier");
+      await expectError(createPool(50), "UnsupportedFeeTier");
+    });
+
+    it("Should leave existing pools alone when a tier is removed", async () => {
+      const seeded = await createPool(100);
+      await updateFeeTiers([], [100]);
+      await expectError(createPool(100), "UnsupportedFeeTier");
+
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.totalFeeBps, 100);
+      await program.methods
+        .swap(new BN(10_000), new BN(0), new BN(0), new BN(0), new BN(0), null)
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // Clearing every tier allows any fee again
+      await updateFeeTiers([], [1, 5, 30]);
+      assert.lengthOf(await tierBps(), 0);
+      await createPool(50, 10_000);
+    });
+  });
+
+  describe("Pool Creation Fee", () => {
+    const creator = Keypair.generate();
+    const treasury = Keypair.generate();
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+    const FEE = 50_000_000;
+
+    const getBalance = (wallet: PublicKey) =>
+      provider.connection.getBalance(wallet);
+
+    before(async () => {
+      await ensureSolBalance(creator);
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+    });
+
+    after(async () => {
+      await updateConfig({ poolCreationFeeLamports: new BN(0) });
+    });
+
+    it("Should charge nothing while the fee is zero", async () => {
+      const before = await getBalance(treasury.publicKey);
+      const { signature } = await createPoolAs(creator);
+      const event = await getEvent(signature, "poolCreatedEvent");
+      assert.equal(event.creationFeeLamports.toNumber(), 0);
+      assert.equal(await getBalance(treasury.publicKey), before);
+    });
+
+    it("Should only let the admin set a fee with a treasury", async () => {
+      await expectError(
+        updateConfig({ poolCreationFeeLamports: new BN(FEE) }, outsider),
+        "Unauthorized"
+      );
+      await expectError(
+        updateConfig({
+          poolCreationFeeLamports: new BN(FEE),
+          treasury: PublicKey.default,
+        }),
+        "InvalidTreasury"
+      );
+
+      const signature = await updateConfig({
+        poolCreationFeeLamports: new BN(FEE),
+        treasury: treasury.publicKey,
+      });
+      const event = await getEvent(signature, "configUpdatedEvent");
+      assert.equal(event.poolCreationFeeLamports.toNumber(), FEE);
+      assert.equal(event.treasury.toBase58(), treasury.publicKey.toBase58());
+    });
+
+    it("Should pay the fee to the treasury", async () => {
+      await expectError(createPoolAs(creator), "InvalidTreasury");
+      await expectError(
+        createPoolAs(creator, 30, outsider.publicKey),
+        "InvalidTreasury"
+      );
+      // The config PDA is owned by the program, not the system program
+      await expectError(
+        createPoolAs(creator, 30, configAddress),
+        "AccountNotSystemOwned"
+      );
+
+      const before = await getBalance(treasury.publicKey);
+      const { signature } = await createPoolAs(
+        creator,
+        30,
+        treasury.publicKey
+      );
+      const event = await getEvent(signature, "poolCreatedEvent");
+      assert.equal(event.creationFeeLamports.toNumber(), FEE);
+      assert.equal(await getBalance(treasury.publicKey), before + FEE);
+    });
+
+    it("Should fail cleanly when the creator can't pay the fee", async () => {
+      const broke = Keypair.generate();
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: payer.publicKey,
+            toPubkey: broke.publicKey,
+            lamports: FEE / 2,
+          })
+        )
+      );
+
+      await expectError(
+        createPoolAs(broke, 30, treasury.publicKey),
+        "InsufficientCreationFee"
+      );
+      // The pool's rent was never taken, as its account was never created
+      assert.equal(await getBalance(broke.publicKey), FEE / 2);
+    });
+  });
+
+  describe("Pool Creation Modes", () => {
+    const creator = Keypair.generate();
+    const outsider = Keypair.generate();
+
+    const creatorEntry = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("creator_allowlist"), wallet.toBuffer()],
+        program.programId
+      )[0];
+
+    const setMode = (poolCreation: object) => updateConfig({ poolCreation });
+
+    const addPoolCreator = (wallet: PublicKey, admin: Keypair = payer) =>
+      program.methods
+        .addPoolCreator(wallet)
+        .accounts({
+          config: configAddress,
+          admin: admin.publicKey,
+          creatorAllowlistEntry: creatorEntry(wallet),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+
+    const removePoolCreator = (wallet: PublicKey, admin: Keypair = payer) =>
+      program.methods
+        .removePoolCreator()
+        .accounts({
+          config: configAddress,
+          admin: admin.publicKey,
+          creatorAllowlistEntry: creatorEntry(wallet),
+        })
+        .signers([admin])
+        .rpc();
+
+    const createAllowlisted = (wallet: Keypair) =>
+      createPoolAs(wallet, 30, null, creatorEntry(wallet.publicKey));
+
+    before(async () => {
+      await ensureSolBalance(creator);
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+    });
+
+    after(async () => {
+      await setMode({ permissionless: {} });
+    });
+
+    it("Should let anyone create pools while permissionless", async () => {
+      await setMode({ permissionless: {} });
+      await createPoolAs(creator);
+      await createPoolAs(outsider);
+    });
+
+    it("Should block an in-flight creator once admin-only", async () => {
+      const { builder } = await prepareCreatePool(outsider);
+      const tx = await builder.transaction();
+
+      const signature = await setMode({ adminOnly: {} });
+      const event = await getEvent(signature, "configUpdatedEvent");
+      assert.deepEqual(event.poolCreation, { adminOnly: {} });
+      await expectError(
+        provider.sendAndConfirm(tx, [outsider]),
+        "PoolCreationRestricted"
+      );
+      await createPoolAs(payer);
+    });
+
+    it("Should only let the admin manage pool creators", async () => {
+      await expectError(
+        addPoolCreator(creator.publicKey, outsider),
+        "Unauthorized"
+      );
+      const signature = await addPoolCreator(creator.publicKey);
+      const event = await getEvent(signature, "poolCreatorUpdatedEvent");
+      assert.equal(event.creator.toBase58(), creator.publicKey.toBase58());
+      assert.isTrue(event.allowed);
+      assert.isAbove(event.timestamp.toNumber(), 0);
+
+      await expectError(
+        removePoolCreator(creator.publicKey, outsider),
+        "Unauthorized"
+      );
+    });
+
+    it("Should only let allowlisted creators in allowlist mode", async () => {
+      await setMode({ allowlist: {} });
+      await createAllowlisted(creator);
+      await createPoolAs(payer);
+      await expectError(createPoolAs(creator), "PoolCreationRestricted");
+      await expectError(createPoolAs(outsider), "PoolCreationRestricted");
+      // Another creator's entry doesn't help
+      await expectError(
+        createPoolAs(outsider, 30, null, creatorEntry(creator.publicKey)),
+        "ConstraintSeeds"
+      );
+
+      const signature = await removePoolCreator(creator.publicKey);
+      const event = await getEvent(signature, "poolCreatorUpdatedEvent");
+      assert.isFalse(event.allowed);
+      await expectError(createPoolAs(creator), "PoolCreationRestricted");
+    });
+  });
+
+  describe("Governed Pools", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const governance = Keypair.generate();
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+    const [governanceAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("authority"), governance.publicKey.toBuffer()],
+      governanceProgram.programId
+    );
+
+    // Runs a pool instruction through the governance, which signs for its
+    // authority PDA by CPI
+    const execute = (
+      instruction: anchor.web3.TransactionInstruction,
+      admin: Keypair = payer
+    ) =>
+      governanceProgram.methods
+        .execute(instruction.data)
+        .accounts({
+          governance: governance.publicKey,
+          admin: admin.publicKey,
+          authority: governanceAuthority,
+          newSendSwapProgram: program.programId,
+        })
+        .remainingAccounts(
+          instruction.keys.map((key) => ({
+            ...key,
+            isSigner: key.isSigner && !key.pubkey.equals(governanceAuthority),
+          }))
+        )
+        .signers([admin])
+        .rpc();
+
+    const proposeFee = (totalFeeBps: number, authority: PublicKey) =>
+      program.methods
+        .proposeParamChange({ fee: { totalFeeBps } })
+        .accounts({ pool: seeded.pool, authority })
+        .instruction();
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      await disableParamChangeDelay();
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+
+      await governanceProgram.methods
+        .initialize()
+        .accounts({
+          governance: governance.publicKey,
+          authority: governanceAuthority,
+          admin: payer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([governance])
+        .rpc();
+      // Funds the PDA so it can pay rent, as a multisig vault would
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: payer.publicKey,
+            toPubkey: governanceAuthority,
+            lamports: anchor.web3.LAMPORTS_PER_SOL / 10,
+          })
+        )
+      );
+    });
+
+    it("Should hand the pool to a governance PDA", async () => {
+      await program.methods
+        .proposeAuthority(governanceAuthority)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+      await execute(
+        await program.methods
+          .acceptAuthority()
+          .accounts({ pool: seeded.pool, newAuthority: governanceAuthority })
+          .instruction()
+      );
+
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        poolAccount.authority.toBase58(),
+        governanceAuthority.toBase58()
+      );
+      await expectError(
+        program.methods
+          .proposeParamChange({
+            fee: { totalFeeBps: 50 },
+          })
+          .accounts({ pool: seeded.pool, authority: payer.publicKey })
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("Should update the fee by CPI from the governance", async () => {
+      await expectError(
+        execute(await proposeFee(50, governanceAuthority), outsider),
+        "NotAdmin"
+      );
+
+      await execute(await proposeFee(50, governanceAuthority));
+      await program.methods
+        .applyParamChange()
+        .accounts({ pool: seeded.pool })
+        .rpc();
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.totalFeeBps, 50);
+    });
+
+    it("Should let the governance PDA pause and pay rent", async () => {
+      await execute(
+        await program.methods
+          .setPoolStatus(1, false)
+          .accounts({ pool: seeded.pool, authority: governanceAuthority })
+          .instruction()
+      );
+      let poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.status, 1);
+      await execute(
+        await program.methods
+          .setPoolStatus(0, false)
+          .accounts({ pool: seeded.pool, authority: governanceAuthority })
+          .instruction()
+      );
+
+      // The allowlist entry's rent comes out of the PDA
+      const [allowlistEntry] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("allowlist"),
+          seeded.pool.toBuffer(),
+          outsider.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+      await execute(
+        await program.methods
+          .addToAllowlist(outsider.publicKey)
+          .accounts({
+            pool: seeded.pool,
+            authority: governanceAuthority,
+            allowlistEntry,
+            systemProgram: SystemProgram.programId,
+          })
+          .instruction()
+      );
+      const entry = await program.account.allowlistEntry.fetch(allowlistEntry);
+      assert.equal(entry.wallet.toBase58(), outsider.publicKey.toBase58());
+      poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.status, 0);
+    });
+  });
+
+  describe("Fee Recipient Splits", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const recipients = [
+      Keypair.generate(),
+      Keypair.generate(),
+      Keypair.generate(),
+    ];
+    const BN = anchor.BN;
+    const tokenAccounts = new Map<string, { a: PublicKey; b: PublicKey }>();
+
+    const accountsOf = (wallet: Keypair) =>
+      tokenAccounts.get(wallet.publicKey.toBase58());
+
+    const setFeeRecipients = (
+      shares: [Keypair, number][],
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .setFeeRecipients(
+          shares.map(([wallet, weightBps]) => ({
+            recipient: wallet.publicKey,
+            weightBps,
+          }))
+        )
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    // Pays out to `payees`, the first through the named accounts and the
+    // rest through the remaining accounts
+    const collect = (payees: Keypair[]) =>
+      program.methods
+        .collectProtocolFees()
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          feeRecipient: payer.publicKey,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          recipientTokenA: accountsOf(payees[0]).a,
+          recipientTokenB: accountsOf(payees[0]).b,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          payees.slice(1).flatMap((payee) =>
+            [accountsOf(payee).a, accountsOf(payee).b].map((pubkey) => ({
+              pubkey,
+              isSigner: false,
+              isWritable: true,
+            }))
+          )
+        )
+        .rpc();
+
+    const swap = (amountIn: number, aToB: boolean) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const payoutsOf = async (signature: string) =>
+      (await getEvent(signature, "protocolFeesCollectedEvent")).payouts.map(
+        (payout) => [
+          payout.recipient.toBase58(),
+          payout.amountA.toNumber(),
+          payout.amountB.toNumber(),
+        ]
+      );
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      for (const wallet of [payer, ...recipients]) {
+        tokenAccounts.set(wallet.publicKey.toBase58(), {
+          a: await fundAccount(seeded.mintA, wallet.publicKey, 0),
+          b: await fundAccount(seeded.mintB, wallet.publicKey, 0),
+        });
+      }
+    });
+
+    it("Should validate the fee recipients", async () => {
+      const [first, second, third] = recipients;
+      await expectError(
+        setFeeRecipients([[first, 10_000]], outsider),
+        "Unauthorized"
+      );
+      await expectError(
+        setFeeRecipients([
+          [first, 5_000],
+          [second, 4_999],
+        ]),
+        "InvalidFeeRecipients"
+      );
+      await expectError(
+        setFeeRecipients([
+          [first, 5_000],
+          [first, 5_000],
+        ]),
+        "InvalidFeeRecipients"
+      );
+      await expectError(
+        setFeeRecipients([
+          [first, 10_000],
+          [second, 0],
+        ]),
+        "InvalidFeeRecipients"
+      );
+      await expectError(
+        setFeeRecipients([
+          [first, 2_000],
+          [second, 2_000],
+          [third, 2_000],
+          [payer, 2_000],
+          [outsider, 2_000],
+        ]),
+        "InvalidFeeRecipients"
+      );
+    });
+
+    it("Should split awkward amounts exactly", async () => {
+      const signature = await setFeeRecipients([
+        [recipients[0], 3_334],
+        [recipients[1], 3_333],
+        [recipients[2], 3_333],
+      ]);
+      const event = await getEvent(signature, "feeRecipientsSetEvent");
+      assert.lengthOf(event.previous, 0);
+      assert.lengthOf(event.feeRecipients, 3);
+      assert.equal(event.authority.toBase58(), payer.publicKey.toBase58());
+
+      // A 2,334 unit swap at 0.3% owes 7 units of token A
+      await swap(2_334, true);
+      let poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.protocolFeesOwedA.toNumber(), 7);
+
+      await expectError(
+        collect(recipients.slice(0, 2)),
+        "InvalidFeeRecipientAccounts"
+      );
+      await expectError(
+        collect([recipients[0], recipients[2], recipients[1]]),
+        "InvalidFeeRecipientAccounts"
+      );
+      await expectError(
+        collect([payer, recipients[1], recipients[2]]),
+        "InvalidFeeRecipientAccounts"
+      );
+
+      assert.deepEqual(await payoutsOf(await collect(recipients)), [
+        [recipients[0].publicKey.toBase58(), 3, 0],
+        [recipients[1].publicKey.toBase58(), 2, 0],
+        [recipients[2].publicKey.toBase58(), 2, 0],
+      ]);
+      const balances = await Promise.all(
+        recipients.map((wallet) => getTokenBalance(accountsOf(wallet).a))
+      );
+      assert.deepEqual(
+        balances.map((balance) => balance.toNumber()),
+        [3, 2, 2]
+      );
+      poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.protocolFeesOwedA.toNumber(), 0);
+    });
+
+    it("Should pay only the current recipients after a rotation", async () => {
+      const signature = await setFeeRecipients([
+        [recipients[1], 5_000],
+        [recipients[2], 5_000],
+      ]);
+      const event = await getEvent(signature, "feeRecipientsSetEvent");
+      assert.lengthOf(event.previous, 3);
+
+      // 999 units of token A and 300 of token B owed
+      await swap(333_333, true);
+      await swap(100_000, false);
+      assert.deepEqual(await payoutsOf(await collect(recipients.slice(1))), [
+        [recipients[1].publicKey.toBase58(), 500, 150],
+        [recipients[2].publicKey.toBase58(), 499, 150],
+      ]);
+      const firstBalance = await getTokenBalance(accountsOf(recipients[0]).a);
+      assert.equal(firstBalance.toNumber(), 3);
+
+      // Without recipients everything goes where the fee recipient chooses
+      await setFeeRecipients([]);
+      await swap(100_000, false);
+      assert.deepEqual(await payoutsOf(await collect([payer])), [
+        [payer.publicKey.toBase58(), 0, 300],
+      ]);
+    });
+  });
+
+  describe("Vault Migration", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+    const DELAY = 10;
+    const migrationA = { vaultMigration: { side: { a: {} } } };
+
+    const findVault = (mint: PublicKey, migrations: number) => {
+      const index = Buffer.alloc(2);
+      index.writeUInt16LE(migrations);
+      return PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), seeded.pool.toBuffer(), mint.toBuffer(), index],
+        program.programId
+      )[0];
+    };
+
+    const propose = (change: object) =>
+      program.methods
+        .proposeParamChange(change)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+    const migrateA = (oldVault: PublicKey, authority: Keypair = payer) =>
+      program.methods
+        .migrateVault({ a: {} })
+        .accounts({
+          pool: seeded.pool,
+          authority: authority.publicKey,
+          mint: seeded.mintA,
+          oldVault,
+          newVault: findVault(seeded.mintA, 0),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+    const swapAToB = (vaultA: PublicKey) =>
+      program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+      await updateConfig({ paramChangeDelaySlots: new BN(DELAY) });
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 10_000_000);
+    });
+
+    after(async () => {
+      await updateConfig({ paramChangeDelaySlots: new BN(0) });
+    });
+
+    it("Should only migrate a vault once proposed and timelocked", async () => {
+      await expectError(migrateA(seeded.vaultA), "NoPendingParamChange");
+      await propose({ vaultMigration: { side: { b: {} } } });
+      await expectError(migrateA(seeded.vaultA), "NoPendingParamChange");
+      await program.methods
+        .cancelParamChange()
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+      const signature = await propose(migrationA);
+      const { effectiveSlot } = await getEvent(
+        signature,
+        "paramChangeProposedEvent"
+      );
+      await expectError(migrateA(seeded.vaultA), "ParamChangeNotReady");
+      await expectError(migrateA(seeded.vaultA, outsider), "Unauthorized");
+
+      // Only migrate_vault carries out a migration
+      await waitForSlot(effectiveSlot.toNumber());
+      await expectError(
+        program.methods
+          .applyParamChange()
+          .accounts({ pool: seeded.pool })
+          .rpc(),
+        "VaultMigrationPending"
+      );
+    });
+
+    it("Should move side A to a new vault that later flows use", async () => {
+      const newVault = findVault(seeded.mintA, 0);
+      const before = await program.account.pool.fetch(seeded.pool);
+      const balance = await getTokenBalance(seeded.vaultA);
+
+      const event = await getEvent(
+        await migrateA(seeded.vaultA),
+        "vaultMigratedEvent"
+      );
+      assert.deepEqual(event.side, { a: {} });
+      assert.equal(event.oldVault.toBase58(), seeded.vaultA.toBase58());
+      assert.equal(event.newVault.toBase58(), newVault.toBase58());
+      assert.equal(event.amount.toString(), balance.toString());
+      assert.isTrue(event.oldVaultClosed);
+      assert.equal(event.reserve.toString(), before.reserveA.toString());
+      assert.equal(event.vaultMigrations, 1);
+
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.tokenAAccount.toBase58(), newVault.toBase58());
+      assert.equal(
+        poolAccount.tokenBAccount.toBase58(),
+        seeded.vaultB.toBase58()
+      );
+      assert.isNull(poolAccount.pendingChange);
+      assert.equal(poolAccount.reserveA.toString(), before.reserveA.toString());
+      const newBalance = await getTokenBalance(newVault);
+      assert.equal(newBalance.toString(), balance.toString());
+      assert.isNull(await provider.connection.getAccountInfo(seeded.vaultA));
+
+      // The old vault is closed, and later flows follow the pool's new one
+      await expectError(swapAToB(seeded.vaultA), "AccountNotInitialized");
+      await swapAToB(newVault);
+
+      const lpBefore = await getTokenBalance(seeded.payerLp);
+      const tokenABefore = await getTokenBalance(seeded.payerTokenA);
+      await program.methods
+        .removeLiquidity(new BN(1_000), new BN(0), new BN(0), false, new BN(0))
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: newVault,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.equal(
+        lpBefore.sub(await getTokenBalance(seeded.payerLp)).toNumber(),
+        1_000
+      );
+      assert.isTrue(
+        (await getTokenBalance(seeded.payerTokenA)).gt(tokenABefore)
+      );
+    });
+  });
+
+  describe("Circuit Breaker", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+    const SWAPS_PAUSED = 1;
+
+    const setCircuitBreaker = (
+      breakerThresholdBps: number,
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .setCircuitBreaker(breakerThresholdBps)
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const swap = (amountIn: number) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    // Swaps A for B through `swap_v2`, which keeps a tripped breaker's pause
+    const swapV2 = (
+      amountIn: number,
+      user: Keypair = payer,
+      userTokenA: PublicKey = seeded.payerTokenA,
+      userTokenB: PublicKey = seeded.payerTokenB
+    ) =>
+      program.methods
+        .swapV2(new BN(amountIn), new BN(0), true, new BN(0))
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          user: user.publicKey,
+          userTokenA,
+          userTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    const setPoolStatus = (status: number) =>
+      program.methods
+        .setPoolStatus(status, false)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+    const poolStatus = async () =>
+      (await program.account.pool.fetch(seeded.pool)).status;
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000_000);
+    });
+
+    it("Should only let the authority set a valid threshold", async () => {
+      await expectError(setCircuitBreaker(500, outsider), "Unauthorized");
+      await expectError(setCircuitBreaker(10_000), "InvalidBreakerThreshold");
+
+      const event = await getEvent(
+        await setCircuitBreaker(500),
+        "circuitBreakerSetEvent"
+      );
+      assert.equal(event.previousThresholdBps, 0);
+      assert.equal(event.breakerThresholdBps, 500);
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.breakerThresholdBps, 500);
+    });
+
+    it("Should fail a plain swap past the threshold, leaving the pool unpaused", async () => {
+      // `swap` returns a `SwapResult`, so it can't succeed without trading
+      await expectError(swap(100_000_000), "CircuitBreakerTripped");
+      assert.equal(await poolStatus(), 0);
+    });
+
+    it("Should not let an unfunded trader trip the breaker", async () => {
+      const emptyTokenA = await fundAccount(
+        seeded.mintA,
+        outsider.publicKey,
+        0
+      );
+      const emptyTokenB = await fundAccount(
+        seeded.mintB,
+        outsider.publicKey,
+        0
+      );
+      await expectError(
+        swapV2(100_000_000, outsider, emptyTokenA, emptyTokenB),
+        "InsufficientInputBalance"
+      );
+      assert.equal(await poolStatus(), 0);
+    });
+
+    it("Should pause swaps instead of making an oversized one", async () => {
+      // Moves the price well under 5%
+      await swap(1_000_000);
+
+      const reservesBefore = await program.account.pool.fetch(seeded.pool);
+      const balanceA = await getTokenBalance(seeded.payerTokenA);
+      const balanceB = await getTokenBalance(seeded.payerTokenB);
+
+      // A tenth of the reserves moves it by about 17%
+      const signature = await swapV2(100_000_000);
+      const event = await getEvent(signature, "circuitBreakerTrippedEvent");
+      assert.equal(event.amountIn.toNumber(), 100_000_000);
+      assert.isAbove(event.amountOut.toNumber(), 0);
+      assert.equal(
+        event.reserveIn.toString(),
+        reservesBefore.reserveA.toString()
+      );
+      assert.isAbove(event.priceMoveBps, 1_500);
+      assert.isBelow(event.priceMoveBps, 2_000);
+      assert.equal(event.breakerThresholdBps, 500);
+      assert.equal(event.previousStatus, 0);
+      assert.equal(event.status, SWAPS_PAUSED);
+
+      // Nothing traded
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA)).toString(),
+        balanceA.toString()
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenB)).toString(),
+        balanceB.toString()
+      );
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.status, SWAPS_PAUSED);
+      assert.equal(
+        poolAccount.reserveA.toString(),
+        reservesBefore.reserveA.toString()
+      );
+
+      await expectError(swap(1_000_000), "PoolPaused");
+    });
+
+    it("Should trade again once the authority unpauses", async () => {
+      await setPoolStatus(0);
+      await getEvent(await swap(1_000_000), "swapExecutedEvent");
+
+      // A zero threshold turns the breaker off
+      await setCircuitBreaker(0);
+      await getEvent(await swap(100_000_000), "swapExecutedEvent");
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.status, 0);
+    });
+  });
+
+  describe("Treasury Skims", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const treasury = Keypair.generate();
+    const BN = anchor.BN;
+    let treasuryTokenA: PublicKey;
+    let treasuryTokenB: PublicKey;
+
+    const skimSurplus = (
+      authority: Keypair = payer,
+      destinations = { treasuryTokenA, treasuryTokenB }
+    ) =>
+      program.methods
+        .skimSurplus()
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          authority: authority.publicKey,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          ...destinations,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+    // What `lpAmount` LP tokens redeem for against the tracked reserves
+    const redemptionValue = async (lpAmount: number) => {
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      const { supply } = await getMint(provider.connection, seeded.lpMint);
+      const supplyBN = new BN(supply.toString());
+      return [
+        poolAccount.reserveA.muln(lpAmount).div(supplyBN).toNumber(),
+        poolAccount.reserveB.muln(lpAmount).div(supplyBN).toNumber(),
+      ];
+    };
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      treasuryTokenA = await fundAccount(seeded.mintA, treasury.publicKey, 0);
+      treasuryTokenB = await fundAccount(seeded.mintB, treasury.publicKey, 0);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000);
+
+      // Owe some protocol fees in token A
+      await program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    });
+
+    after(async () => {
+      await updateConfig({ treasury: PublicKey.default });
+    });
+
+    it("Should need the config's treasury to skim to", async () => {
+      await updateConfig({ treasury: PublicKey.default });
+      await expectError(skimSurplus(), "InvalidTreasuryAccount");
+
+      await updateConfig({ treasury: treasury.publicKey });
+      await expectError(skimSurplus(outsider), "Unauthorized");
+      await expectError(
+        skimSurplus(payer, {
+          treasuryTokenA: seeded.payerTokenA,
+          treasuryTokenB: seeded.payerTokenB,
+        }),
+        "InvalidTreasuryAccount"
+      );
+    });
+
+    it("Should skim a vault donation without touching reserves", async () => {
+      const before = await program.account.pool.fetch(seeded.pool);
+      assert.isAbove(before.protocolFeesOwedA.toNumber(), 0);
+      const valueBefore = await redemptionValue(1_000);
+
+      // Tokens sent straight to the vaults, outside any program flow
+      for (const [mint, vault, amount] of [
+        [seeded.mintA, seeded.vaultA, 5_000],
+        [seeded.mintB, seeded.vaultB, 7_000],
+      ] as [PublicKey, PublicKey, number][]) {
+        await mintTo(
+          provider.connection,
+          payer,
+          mint,
+          vault,
+          payer.publicKey,
+          amount
+        );
+      }
+
+      const event = await getEvent(await skimSurplus(), "skimEvent");
+      assert.equal(event.treasury.toBase58(), treasury.publicKey.toBase58());
+      assert.equal(event.amountA.toNumber(), 5_000);
+      assert.equal(event.amountB.toNumber(), 7_000);
+      assert.equal(event.reserveA.toString(), before.reserveA.toString());
+      assert.equal(
+        event.protocolFeesOwedA.toString(),
+        before.protocolFeesOwedA.toString()
+      );
+      assert.equal((await getTokenBalance(treasuryTokenA)).toNumber(), 5_000);
+      assert.equal((await getTokenBalance(treasuryTokenB)).toNumber(), 7_000);
+
+      const after = await program.account.pool.fetch(seeded.pool);
+      for (const [vault, reserve, owed, reserveBefore, owedBefore] of [
+        [
+          seeded.vaultA,
+          after.reserveA,
+          after.protocolFeesOwedA,
+          before.reserveA,
+          before.protocolFeesOwedA,
+        ],
+        [
+          seeded.vaultB,
+          after.reserveB,
+          after.protocolFeesOwedB,
+          before.reserveB,
+          before.protocolFeesOwedB,
+        ],
+      ] as [PublicKey, anchor.BN, anchor.BN, anchor.BN, anchor.BN][]) {
+        assert.equal(reserve.toString(), reserveBefore.toString());
+        assert.equal(owed.toString(), owedBefore.toString());
+        assert.equal(
+          (await getTokenBalance(vault)).toString(),
+          reserve.add(owed).toString()
+        );
+      }
+
+      // Nothing is left over, and LP tokens redeem as before the donation
+      const again = await getEvent(await skimSurplus(), "skimEvent");
+      assert.equal(again.amountA.toNumber(), 0);
+      assert.equal(again.amountB.toNumber(), 0);
+      assert.deepEqual(await redemptionValue(1_000), valueBefore);
+
+      const tokenABefore = await getTokenBalance(seeded.payerTokenA);
+      const tokenBBefore = await getTokenBalance(seeded.payerTokenB);
+      await program.methods
+        .removeLiquidity(new BN(1_000), new BN(0), new BN(0), false, new BN(0))
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const tokenAAfter = await getTokenBalance(seeded.payerTokenA);
+      const tokenBAfter = await getTokenBalance(seeded.payerTokenB);
+      assert.deepEqual(
+        [
+          tokenAAfter.sub(tokenABefore).toNumber(),
+          tokenBAfter.sub(tokenBBefore).toNumber(),
+        ],
+        valueBefore
+      );
+    });
+  });
+
+  describe("Pool Metadata", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let metadata: PublicKey;
+    const outsider = Keypair.generate();
+
+    const setPoolMetadata = (
+      name: string,
+      uri: string,
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .setPoolMetadata(name, uri)
+        .accounts({
+          pool: seeded.pool,
+          authority: authority.publicKey,
+          metadata,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      seeded = await createSeededPool(1_000_000, 1_000_000);
+      [metadata] = PublicKey.findProgramAddressSync(
+        [Buffer.from("metadata"), seeded.pool.toBuffer()],
+        program.programId
+      );
+    });
+
+    it("Should create and then update a pool's metadata", async () => {
+      let event = await getEvent(
+        await setPoolMetadata("SEND/USDC", "https://example.com/pool.json"),
+        "metadataUpdatedEvent"
+      );
+      assert.isTrue(event.created);
+      assert.equal(event.name, "SEND/USDC");
+
+      let account = await program.account.poolMetadata.fetch(metadata);
+      assert.equal(account.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(account.uri, "https://example.com/pool.json");
+
+      // Updating reuses the account, so the authority pays no more rent
+      const lamportsBefore = await provider.connection.getBalance(metadata);
+      event = await getEvent(
+        await setPoolMetadata("SEND/USDC 0.3%", ""),
+        "metadataUpdatedEvent"
+      );
+      assert.isFalse(event.created);
+      account = await program.account.poolMetadata.fetch(metadata);
+      assert.equal(account.name, "SEND/USDC 0.3%");
+      assert.equal(account.uri, "");
+      assert.equal(
+        await provider.connection.getBalance(metadata),
+        lamportsBefore
+      );
+    });
+
+    it("Should reject over-length fields and other signers", async () => {
+      await expectError(
+        setPoolMetadata("x".repeat(33), "https://example.com"),
+        "MetadataTooLong"
+      );
+      await expectError(
+        setPoolMetadata("SEND/USDC", "https://example.com/" + "x".repeat(181)),
+        "MetadataTooLong"
+      );
+      // At the limits exactly
+      await setPoolMetadata("x".repeat(32), "x".repeat(200));
+
+      await expectError(
+        setPoolMetadata("Not mine", "", outsider),
+        "Unauthorized"
+      );
+      const account = await program.account.poolMetadata.fetch(metadata);
+      assert.equal(account.name, "x".repeat(32));
+    });
+  });
+
+  describe("Pool Roles", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const feeAuthority = Keypair.generate();
+    const guardian = Keypair.generate();
+    const BN = anchor.BN;
+    const SWAPS_PAUSED = 1;
+    const DEPOSITS_PAUSED = 2;
+
+    const setPoolRoles = (
+      fee: PublicKey,
+      pause: PublicKey,
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .setPoolRoles(fee, pause)
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const setPoolStatus = (status: number, authority: Keypair) =>
+      program.methods
+        .setPoolStatus(status, false)
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const propose = (change: object, authority: Keypair) =>
+      program.methods
+        .proposeParamChange(change)
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const cancel = (authority: Keypair) =>
+      program.methods
+        .cancelParamChange()
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const setFeeRecipients = (authority: Keypair) =>
+      program.methods
+        .setFeeRecipients([])
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const setFeeSplit = (authority: Keypair) =>
+      program.methods
+        .setFeeSplit(5_000, 5_000)
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const collect = (
+      collector: Keypair,
+      recipientTokenA: PublicKey,
+      recipientTokenB: PublicKey
+    ) =>
+      program.methods
+        .collectProtocolFees()
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          feeRecipient: collector.publicKey,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          recipientTokenA,
+          recipientTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([collector])
+        .rpc();
+
+    // The pool's own fee, so the config's fee tiers allow it
+    const feeChange = { fee: { totalFeeBps: 30 } };
+    const feeRecipientChange = {
+      feeRecipient: { feeRecipient: feeAuthority.publicKey },
+    };
+
+    before(async () => {
+      for (const wallet of [outsider, feeAuthority, guardian]) {
+        await ensureSolBalance(wallet);
+      }
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000);
+    });
+
+    it("Should let only the authority rotate the roles", async () => {
+      await expectError(
+        setPoolRoles(feeAuthority.publicKey, guardian.publicKey, outsider),
+        "Unauthorized"
+      );
+      const event = await getEvent(
+        await setPoolRoles(feeAuthority.publicKey, guardian.publicKey),
+        "poolRolesSetEvent"
+      );
+      assert.isTrue(event.previousFeeAuthority.equals(PublicKey.default));
+      assert.equal(
+        event.feeAuthority.toBase58(),
+        feeAuthority.publicKey.toBase58()
+      );
+      assert.equal(
+        event.pauseGuardian.toBase58(),
+        guardian.publicKey.toBase58()
+      );
+
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        poolAccount.feeAuthority.toBase58(),
+        feeAuthority.publicKey.toBase58()
+      );
+      assert.equal(
+        poolAccount.pauseGuardian.toBase58(),
+        guardian.publicKey.toBase58()
+      );
+      assert.equal(
+        poolAccount.authority.toBase58(),
+        payer.publicKey.toBase58()
+      );
+    });
+
+    it("Should let the pause guardian pause but not unpause", async () => {
+      await setPoolStatus(SWAPS_PAUSED, guardian);
+      await setPoolStatus(SWAPS_PAUSED | DEPOSITS_PAUSED, guardian);
+      await expectError(
+        setPoolStatus(SWAPS_PAUSED, guardian),
+        "GuardianCannotUnpause"
+      );
+      await expectError(setPoolStatus(0, feeAuthority), "Unauthorized");
+
+      // Nothing beyond pausing
+      await expectError(setFeeSplit(guardian), "Unauthorized");
+      await expectError(propose(feeChange, guardian), "Unauthorized");
+      await expectError(setFeeRecipients(guardian), "Unauthorized");
+      await expectError(
+        collect(guardian, seeded.payerTokenA, seeded.payerTokenB),
+        "NotFeeRecipient"
+      );
+
+      await setPoolStatus(0, payer);
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.status, 0);
+    });
+
+    it("Should leave the fees to the fee authority", async () => {
+      // The authority keeps the other parameters but not the fee recipients
+      await expectError(setFeeRecipients(payer), "Unauthorized");
+      await expectError(propose(feeRecipientChange, payer), "Unauthorized");
+      await propose(feeChange, payer);
+      await expectError(cancel(feeAuthority), "Unauthorized");
+      await cancel(payer);
+
+      await setFeeRecipients(feeAuthority);
+      await expectError(propose(feeChange, feeAuthority), "Unauthorized");
+      await propose(feeRecipientChange, feeAuthority);
+      await expectError(cancel(payer), "Unauthorized");
+      await cancel(feeAuthority);
+      await expectError(setFeeSplit(feeAuthority), "Unauthorized");
+
+      // Owe some protocol fees in token A
+      await program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const owed = (await program.account.pool.fetch(seeded.pool))
+        .protocolFeesOwedA;
+      assert.isAbove(owed.toNumber(), 0);
+
+      // It collects, but only into the fee recipient's accounts
+      const ownTokenA = await fundAccount(
+        seeded.mintA,
+        feeAuthority.publicKey,
+        0
+      );
+      const ownTokenB = await fundAccount(
+        seeded.mintB,
+        feeAuthority.publicKey,
+        0
+      );
+      await expectError(
+        collect(feeAuthority, ownTokenA, ownTokenB),
+        "InvalidFeeRecipientAccounts"
+      );
+      const balanceBefore = await getTokenBalance(seeded.payerTokenA);
+      const event = await getEvent(
+        await collect(feeAuthority, seeded.payerTokenA, seeded.payerTokenB),
+        "protocolFeesCollectedEvent"
+      );
+      assert.equal(
+        event.collector.toBase58(),
+        feeAuthority.publicKey.toBase58()
+      );
+      assert.equal(event.feeRecipient.toBase58(), payer.publicKey.toBase58());
+      const balanceAfter = await getTokenBalance(seeded.payerTokenA);
+      assert.equal(balanceAfter.sub(balanceBefore).toString(), owed.toString());
+    });
+
+    it("Should hand the roles back with the default pubkey", async () => {
+      await setPoolRoles(PublicKey.default, PublicKey.default);
+      await setFeeRecipients(payer);
+      await expectError(setFeeRecipients(feeAuthority), "Unauthorized");
+      await expectError(setPoolStatus(SWAPS_PAUSED, guardian), "Unauthorized");
+      await setPoolStatus(SWAPS_PAUSED, payer);
+      await setPoolStatus(0, payer);
+    });
+  });
+  describe("Insurance Fund", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let recipientToken: PublicKey;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+    const DELAY = 10;
+    const SHARE_BPS = 2_500;
+
+    const findInsuranceVault = (mint: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("insurance"), seeded.pool.toBuffer(), mint.toBuffer()],
+        program.programId
+      )[0];
+
+    const propose = (change: object) =>
+      program.methods
+        .proposeParamChange(change)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+    // Proposes `change` and waits until it can be applied
+    const proposeAndWait = async (change: object) => {
+      const { effectiveSlot } = await getEvent(
+        await propose(change),
+        "paramChangeProposedEvent"
+      );
+      await waitForSlot(effectiveSlot.toNumber());
+    };
+
+    const createInsuranceVaults = (authority: Keypair = payer) =>
+      program.methods
+        .createInsuranceVaults()
+        .accounts({
+          pool: seeded.pool,
+          authority: authority.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          insuranceVaultA: findInsuranceVault(seeded.mintA),
+          insuranceVaultB: findInsuranceVault(seeded.mintB),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenBProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+    const collect = (withInsurance: boolean) =>
+      program.methods
+        .collectProtocolFees()
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          feeRecipient: payer.publicKey,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          recipientTokenA: seeded.payerTokenA,
+          recipientTokenB: seeded.payerTokenB,
+          insuranceVaultA: withInsurance
+            ? findInsuranceVault(seeded.mintA)
+            : null,
+          insuranceVaultB: withInsurance
+            ? findInsuranceVault(seeded.mintB)
+            : null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const disburse = (amount: number, authority: Keypair = payer) =>
+      program.methods
+        .disburseInsurance(new BN(amount), recipientToken)
+        .accounts({
+          pool: seeded.pool,
+          authority: authority.publicKey,
+          mint: seeded.mintA,
+          insuranceVault: findInsuranceVault(seeded.mintA),
+          recipientToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+    const getInsuranceBalance = () =>
+      program.methods
+        .getInsuranceBalance()
+        .accounts({
+          pool: seeded.pool,
+          insuranceVaultA: findInsuranceVault(seeded.mintA),
+          insuranceVaultB: findInsuranceVault(seeded.mintB),
+        })
+        .view();
+
+    const swapAToB = (amountIn: number) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+      await updateConfig({ paramChangeDelaySlots: new BN(DELAY) });
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      recipientToken = await fundAccount(seeded.mintA, outsider.publicKey, 0);
+    });
+
+    after(async () => {
+      await updateConfig({ paramChangeDelaySlots: new BN(0) });
+    });
+
+    it("Should only let the authority create the insurance vaults", async () => {
+      await expectError(createInsuranceVaults(outsider), "Unauthorized");
+      await createInsuranceVaults();
+
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        poolAccount.insuranceVaultA.toBase58(),
+        findInsuranceVault(seeded.mintA).toBase58()
+      );
+      assert.equal(
+        poolAccount.insuranceVaultB.toBase58(),
+        findInsuranceVault(seeded.mintB).toBase58()
+      );
+      assert.equal(poolAccount.insuranceShareBps, 0);
+    });
+
+    it("Should change the insurance share only through the timelock", async () => {
+      await expectError(
+        propose({ insuranceShare: { insuranceShareBps: 10_001 } }),
+        "InvalidInsuranceShare"
+      );
+
+      await proposeAndWait({
+        insuranceShare: { insuranceShareBps: SHARE_BPS },
+      });
+      let poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.insuranceShareBps, 0);
+
+      await program.methods
+        .applyParamChange()
+        .accounts({ pool: seeded.pool })
+        .rpc();
+      poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.equal(poolAccount.insuranceShareBps, SHARE_BPS);
+      assert.isNull(poolAccount.pendingChange);
+    });
+
+    it("Should set aside the insurance share at each collection", async () => {
+      await swapAToB(10_000_000);
+      const before = await program.account.pool.fetch(seeded.pool);
+      const owedA = before.protocolFeesOwedA;
+      assert.isTrue(owedA.gtn(0));
+      const insuranceA = owedA.muln(SHARE_BPS).divn(10_000);
+      const payerBefore = await getTokenBalance(seeded.payerTokenA);
+
+      // Fees can't be collected while their insurance share has nowhere to go
+      await expectError(collect(false), "InvalidInsuranceVault");
+
+      const event = await getEvent(
+        await collect(true),
+        "protocolFeesCollectedEvent"
+      );
+      assert.equal(event.amountA.toString(), owedA.toString());
+      assert.equal(event.insuranceA.toString(), insuranceA.toString());
+      assert.equal(event.insuranceB.toNumber(), 0);
+      assert.equal(
+        event.payouts[0].amountA.toString(),
+        owedA.sub(insuranceA).toString()
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.payerTokenA)).sub(payerBefore).toString(),
+        owedA.sub(insuranceA).toString()
+      );
+      assert.equal(
+        (await getTokenBalance(findInsuranceVault(seeded.mintA))).toString(),
+        insuranceA.toString()
+      );
+
+      const balance = await getInsuranceBalance();
+      assert.equal(balance.amountA.toString(), insuranceA.toString());
+      assert.equal(balance.amountB.toNumber(), 0);
+      assert.equal(balance.insuranceShareBps, SHARE_BPS);
+    });
+
+    it("Should never count insurance funds in the reserves", async () => {
+      const before = await program.account.pool.fetch(seeded.pool);
+      const insurance = await getTokenBalance(findInsuranceVault(seeded.mintA));
+      assert.isTrue(insurance.gtn(0));
+
+      // The vault holds only the reserve, so a sync leaves the reserves alone
+      assert.equal(
+        (await getTokenBalance(seeded.vaultA)).toString(),
+        before.reserveA.toString()
+      );
+      await program.methods
+        .sync()
+        .accounts({
+          pool: seeded.pool,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+        })
+        .rpc();
+      const after = await program.account.pool.fetch(seeded.pool);
+      assert.equal(after.reserveA.toString(), before.reserveA.toString());
+      assert.equal(after.reserveB.toString(), before.reserveB.toString());
+
+      // Quotes price against the reserves alone
+      const amountIn = new BN(1_000_000);
+      const quote = await program.methods
+        .quoteSwap(amountIn, true)
+        .accounts({
+          pool: seeded.pool,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+        })
+        .view();
+      const inAfterFee = amountIn.sub(amountIn.muln(3).divn(1000));
+      assert.equal(
+        quote.amountOut.toString(),
+        after.reserveB
+          .mul(inAfterFee)
+          .div(after.reserveA.add(inAfterFee))
+          .toString()
+      );
+    });
+
+    it("Should only disburse a proposed amount after the delay", async () => {
+      const insurance = await getTokenBalance(findInsuranceVault(seeded.mintA));
+      const amount = insurance.divn(2).toNumber();
+      await expectError(disburse(amount), "NoPendingParamChange");
+
+      const signature = await propose({
+        insuranceDisbursement: {
+          amount: new BN(amount),
+          recipient: recipientToken,
+        },
+      });
+      const { effectiveSlot } = await getEvent(
+        signature,
+        "paramChangeProposedEvent"
+      );
+      await expectError(disburse(amount), "ParamChangeNotReady");
+      await expectError(disburse(amount, outsider), "Unauthorized");
+
+      // Only disburse_insurance pays a disbursement, and only as proposed
+      await waitForSlot(effectiveSlot.toNumber());
+      await expectError(
+        program.methods
+          .applyParamChange()
+          .accounts({ pool: seeded.pool })
+          .rpc(),
+        "InsuranceDisbursementPending"
+      );
+      await expectError(disburse(amount + 1), "NoPendingParamChange");
+
+      const event = await getEvent(
+        await disburse(amount),
+        "insuranceDisbursedEvent"
+      );
+      assert.equal(event.mint.toBase58(), seeded.mintA.toBase58());
+      assert.equal(event.recipient.toBase58(), recipientToken.toBase58());
+      assert.equal(event.amount.toNumber(), amount);
+      assert.equal(
+        event.remaining.toString(),
+        insurance.subn(amount).toString()
+      );
+      assert.equal((await getTokenBalance(recipientToken)).toNumber(), amount);
+
+      const poolAccount = await program.account.pool.fetch(seeded.pool);
+      assert.isNull(poolAccount.pendingChange);
+      const balance = await getInsuranceBalance();
+      assert.equal(
+        balance.amountA.toString(),
+        insurance.subn(amount).toString()
+      );
+
+      // Each disbursement needs a proposal of its own
+      await expectError(disburse(amount), "NoPendingParamChange");
+    });
+  });
+  describe("Token Badges", () => {
+    let badgedA: PublicKey;
+    let badgedB: PublicKey;
+    let unbadged: PublicKey;
+    let existing: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+
+    const findTokenBadge = (mint: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("token_badge"), mint.toBuffer()],
+        program.programId
+      )[0];
+
+    const issueTokenBadge = (mint: PublicKey, admin: Keypair = payer) =>
+      program.methods
+        .issueTokenBadge()
+        .accounts({
+          config: configAddress,
+          admin: admin.publicKey,
+          mint,
+          tokenBadge: findTokenBadge(mint),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+
+    const revokeTokenBadge = (mint: PublicKey, admin: Keypair = payer) =>
+      program.methods
+        .revokeTokenBadge()
+        .accounts({
+          config: configAddress,
+          admin: admin.publicKey,
+          tokenBadge: findTokenBadge(mint),
+        })
+        .signers([admin])
+        .rpc();
+
+    // Creates an empty pool on `mintA` and `mintB`, passing the given badges
+    const createPool = async (
+      mintA: PublicKey,
+      mintB: PublicKey,
+      poolIndex: number,
+      tokenABadge: PublicKey | null,
+      tokenBBadge: PublicKey | null
+    ) => {
+      const pool = findPoolAddress(mintA, mintB, poolIndex);
+      const newVault = (mint: PublicKey) =>
+        createAccount(
+          provider.connection,
+          payer,
+          mint,
+          pool,
+          Keypair.generate()
+        );
+      return program.methods
+        .initializePool(30, poolIndex, new BN(0), noTradeLimits)
+        .accounts({
+          pool,
+          tokenAMint: mintA,
+          tokenBMint: mintB,
+          tokenAAccount: await newVault(mintA),
+          tokenBAccount: await newVault(mintB),
+          lpMint: await createMint(
+            provider.connection,
+            payer,
+            payer.publicKey,
+            null,
+            9
+          ),
+          authority: payer.publicKey,
+          registryEntry: await nextRegistryEntry(),
+          tokenABadge,
+          tokenBBadge,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+    };
+
+    const newMint = () =>
+      createMint(provider.connection, payer, payer.publicKey, null, 9);
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+      badgedA = await newMint();
+      badgedB = await newMint();
+      unbadged = await newMint();
+      existing = await createSeededPool(1_000_000_000, 1_000_000_000, 30, {
+        mintA: badgedA,
+        mintB: badgedB,
+        poolIndex: 0,
+      });
+      await fundAccount(badgedA, payer.publicKey, 1_000_000);
+    });
+
+    after(async () => {
+      await updateConfig({ requireBadge: false });
+    });
+
+    it("Should only let the admin issue and revoke badges", async () => {
+      await expectError(issueTokenBadge(badgedA, outsider), "Unauthorized");
+
+      const event = await getEvent(
+        await issueTokenBadge(badgedA),
+        "badgeIssuedEvent"
+      );
+      assert.equal(event.mint.toBase58(), badgedA.toBase58());
+      assert.equal(event.admin.toBase58(), payer.publicKey.toBase58());
+      await issueTokenBadge(badgedB);
+
+      const badge = await program.account.tokenBadge.fetch(
+        findTokenBadge(badgedA)
+      );
+      assert.equal(badge.mint.toBase58(), badgedA.toBase58());
+      await expectError(revokeTokenBadge(badgedA, outsider), "Unauthorized");
+    });
+
+    it("Should ignore badges while the config doesn't require them", async () => {
+      await updateConfig({ requireBadge: false });
+      await createPool(badgedA, unbadged, 0, null, null);
+      await createPool(badgedA, badgedB, 1, findTokenBadge(badgedA), null);
+    });
+
+    it("Should require badges for both mints once enabled", async () => {
+      const { requireBadge } = await getEvent(
+        await updateConfig({ requireBadge: true }),
+        "configUpdatedEvent"
+      );
+      assert.isTrue(requireBadge);
+
+      await expectError(
+        createPool(badgedA, unbadged, 1, null, null),
+        "MissingTokenBadge"
+      );
+      await expectError(
+        createPool(badgedA, unbadged, 1, findTokenBadge(badgedA), null),
+        "MissingTokenBadge"
+      );
+      // A badge only vouches for its own mint
+      await expectError(
+        createPool(
+          badgedA,
+          unbadged,
+          1,
+          findTokenBadge(badgedA),
+          findTokenBadge(badgedB)
+        ),
+        "ConstraintSeeds"
+      );
+
+      await createPool(
+        badgedA,
+        badgedB,
+        2,
+        findTokenBadge(badgedA),
+        findTokenBadge(badgedB)
+      );
+    });
+
+    it("Should block new pools but not existing ones once revoked", async () => {
+      const event = await getEvent(
+        await revokeTokenBadge(badgedB),
+        "badgeRevokedEvent"
+      );
+      assert.equal(event.mint.toBase58(), badgedB.toBase58());
+      assert.isNull(
+        await provider.connection.getAccountInfo(findTokenBadge(badgedB))
+      );
+
+      await expectError(
+        createPool(badgedA, badgedB, 3, findTokenBadge(badgedA), null),
+        "MissingTokenBadge"
+      );
+
+      // The pool made before the revocation still trades
+      const balanceBefore = await getTokenBalance(existing.payerTokenB);
+      await program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: existing.pool,
+          owner: payer.publicKey,
+          tokenInMint: badgedA,
+          tokenOutMint: badgedB,
+          userTokenIn: existing.payerTokenA,
+          userTokenOut: existing.payerTokenB,
+          poolTokenIn: existing.vaultA,
+          poolTokenOut: existing.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.isTrue(
+        (await getTokenBalance(existing.payerTokenB)).gt(balanceBefore)
+      );
+
+      // Reissuing the badge lets the mint into new pools again
+      await issueTokenBadge(badgedB);
+      await createPool(
+        badgedA,
+        badgedB,
+        3,
+        findTokenBadge(badgedA),
+        findTokenBadge(badgedB)
+      );
+    });
+  });
+  describe("Pool Reset", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+
+    const resetPool = (authority: Keypair = payer) =>
+      program.methods
+        .resetPool()
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          authority: authority.publicKey,
+          lpMint: seeded.lpMint,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          toTokenA: seeded.payerTokenA,
+          toTokenB: seeded.payerTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+    const addLiquidity = (amountA: number, amountB: number, minLp = 0) =>
+      program.methods
+        .addLiquidity(
+          new BN(amountA),
+          new BN(amountB),
+          new BN(minLp),
+          new BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const swapAToB = (amountIn: number) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 1_000_000_000);
+      await program.methods
+        .setWithdrawalFee(100)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+    });
+
+    it("Should only reset a drained pool for its authority", async () => {
+      await expectError(resetPool(), "PoolNotDrained");
+      await expectError(resetPool(outsider), "Unauthorized");
+    });
+
+    it("Should re-seed a drained pool as a fresh one", async () => {
+      await swapAToB(10_000_000);
+      const lp = await getTokenBalance(seeded.payerLp);
+      await program.methods
+        .removeLiquidity(lp, new BN(0), new BN(0), false, new BN(0))
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // The withdrawal fee stays behind with no LP tokens left to claim it,
+      // so a deposit would mint nothing
+      const drained = await program.account.pool.fetch(seeded.pool);
+      const { supply } = await getMint(provider.connection, seeded.lpMint);
+      assert.equal(supply.toString(), "0");
+      assert.isTrue(drained.reserveA.gtn(0));
+      await expectError(
+        addLiquidity(1_000_000, 1_000_000, 1),
+        "SlippageExceeded"
+      );
+
+      const event = await getEvent(await resetPool(), "poolResetEvent");
+      assert.equal(event.amountA.toString(), drained.reserveA.toString());
+      assert.equal(event.amountB.toString(), drained.reserveB.toString());
+      assert.equal(
+        event.reserveABefore.toString(),
+        drained.reserveA.toString()
+      );
+
+      // Only the owed protocol fees are left, and swaps have nothing to trade
+      const reset = await program.account.pool.fetch(seeded.pool);
+      assert.equal(reset.reserveA.toNumber(), 0);
+      assert.equal(reset.reserveB.toNumber(), 0);
+      assert.equal(reset.priceACumulative.toString(), "0");
+      assert.equal(reset.priceBCumulative.toString(), "0");
+      assert.equal(reset.lastOracleTimestamp.toNumber(), 0);
+      assert.equal(
+        (await getTokenBalance(seeded.vaultA)).toString(),
+        reset.protocolFeesOwedA.toString()
+      );
+      assert.equal(
+        reset.protocolFeesOwedA.toString(),
+        drained.protocolFeesOwedA.toString()
+      );
+      await expectError(swapAToB(1_000_000), "InsufficientLiquidity");
+
+      // The next deposit sets a new price on the initial path
+      await addLiquidity(100_000_000, 400_000_000);
+      assert.equal(
+        (await getTokenBalance(seeded.payerLp)).toNumber(),
+        1_000_000
+      );
+      const reseeded = await program.account.pool.fetch(seeded.pool);
+      assert.equal(reseeded.reserveA.toNumber(), 100_000_000);
+      assert.equal(reseeded.reserveB.toNumber(), 400_000_000);
+
+      const quote = await program.methods
+        .quoteSwap(new BN(1_000), true)
+        .accounts({
+          pool: seeded.pool,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+        })
+        .view();
+      assert.equal(quote.amountOut.toNumber(), 3_987);
+    });
+  });
+  describe("Price Oracle", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+    const U128 = new BN(1).shln(128);
+
+    // Q64.64 price of the `num` side in the `den` side
+    const q64Price = (num: anchor.BN, den: anchor.BN) => num.shln(64).div(den);
+    // Accumulator difference modulo 2^128, as consumers take it
+    const wrappingSub = (a: anchor.BN, b: anchor.BN) => a.sub(b).umod(U128);
+
+    const fetchPool = () => program.account.pool.fetch(seeded.pool);
+    type PoolAccount = Awaited<ReturnType<typeof fetchPool>>;
+
+    // Checks `after` added `before`'s prices for the seconds between them
+    const expectAccrued = (before: PoolAccount, after: PoolAccount) => {
+      const elapsed = after.lastOracleTimestamp.sub(before.lastOracleTimestamp);
+      assert.isTrue(elapsed.gtn(0));
+      assert.equal(
+        wrappingSub(after.priceACumulative, before.priceACumulative).toString(),
+        q64Price(before.reserveB, before.reserveA).mul(elapsed).toString()
+      );
+      assert.equal(
+        wrappingSub(after.priceBCumulative, before.priceBCumulative).toString(),
+        q64Price(before.reserveA, before.reserveB).mul(elapsed).toString()
+      );
+    };
+
+    const swapAToB = (amountIn: number) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const sync = () =>
+      program.methods
+        .sync()
+        .accounts({
+          pool: seeded.pool,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+        })
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 2_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000_000);
+    });
+
+    it("Should start the clock at the first deposit", async () => {
+      const poolAccount = await fetchPool();
+      assert.isTrue(poolAccount.lastOracleTimestamp.gtn(0));
+      assert.equal(poolAccount.priceACumulative.toString(), "0");
+      assert.equal(poolAccount.priceBCumulative.toString(), "0");
+    });
+
+    it("Should weight each price by the seconds it held", async () => {
+      let before = await fetchPool();
+      await sleep(2_000);
+      await swapAToB(100_000_000);
+      let after = await fetchPool();
+      expectAccrued(before, after);
+
+      // The next trade accrues the price the first one left
+      before = after;
+      await sleep(2_000);
+      await swapAToB(50_000_000);
+      after = await fetchPool();
+      expectAccrued(before, after);
+      assert.isTrue(after.reserveA.gt(before.reserveA));
+
+      before = after;
+      await sleep(2_000);
+      await program.methods
+        .removeLiquidity(new BN(1_000), new BN(0), new BN(0), false, new BN(0))
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      expectAccrued(before, await fetchPool());
+    });
+
+    it("Should give TWAPs from two samples, wrapping or not", async () => {
+      await sync();
+      const first = await fetchPool();
+      await sleep(2_000);
+      await swapAToB(200_000_000);
+      const middle = await fetchPool();
+      await sleep(2_000);
+      await sync();
+      const last = await fetchPool();
+
+      const firstPrice = q64Price(first.reserveB, first.reserveA);
+      const middlePrice = q64Price(middle.reserveB, middle.reserveA);
+      const firstSeconds = middle.lastOracleTimestamp.sub(
+        first.lastOracleTimestamp
+      );
+      const middleSeconds = last.lastOracleTimestamp.sub(
+        middle.lastOracleTimestamp
+      );
+      const window = firstSeconds.add(middleSeconds);
+      const expected = firstPrice
+        .mul(firstSeconds)
+        .add(middlePrice.mul(middleSeconds))
+        .div(window);
+      const twap = wrappingSub(
+        last.priceACumulative,
+        first.priceACumulative
+      ).div(window);
+      assert.equal(twap.toString(), expected.toString());
+      assert.isTrue(twap.lt(firstPrice) && twap.gt(middlePrice));
+
+      // Had the accumulator wrapped between the samples, the difference
+      // modulo 2^128 would be the same
+      const offset = U128.sub(first.priceACumulative).subn(1);
+      const wrappedFirst = first.priceACumulative.add(offset).umod(U128);
+      const wrappedLast = last.priceACumulative.add(offset).umod(U128);
+      assert.isTrue(wrappedLast.lt(wrappedFirst));
+      assert.equal(
+        wrappingSub(wrappedLast, wrappedFirst).div(window).toString(),
+        twap.toString()
+      );
+    });
+  });
+
+  describe("Oracle Observations", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let observations: PublicKey;
+    const BN = anchor.BN;
+
+    // Q64.64 price of the `num` side in the `den` side
+    const q64Price = (num: anchor.BN, den: anchor.BN) => num.shln(64).div(den);
+
+    const fetchPool = () => program.account.pool.fetch(seeded.pool);
+    const fetchObservations = () =>
+      program.account.observations.fetch(observations);
+
+    const swapIx = (amountIn: number) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          observations,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        });
+
+    // Swaps in a later slot than the newest observation
+    const swapAToB = async (amountIn: number) => {
+      const buffer = await fetchObservations();
+      const newest = buffer.observations[buffer.index];
+      await waitForSlot(newest.slot.toNumber() + 1);
+      await swapIx(amountIn).rpc();
+    };
+
+    const increaseCardinality = (newSize: number) =>
+      program.methods
+        .increaseObservationCardinality(newSize)
+        .accounts({
+          payer: payer.publicKey,
+          observations,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    const getTwap = (secondsAgo: number) =>
+      program.methods
+        .getTwap(secondsAgo)
+        .accounts({ pool: seeded.pool, observations });
+
+    // Checks the newest observation holds the pool's cumulative prices
+    const expectNewestIsPool = async () => {
+      const buffer = await fetchObservations();
+      const poolAccount = await fetchPool();
+      const newest = buffer.observations[buffer.index];
+      assert.equal(
+        newest.timestamp.toString(),
+        poolAccount.lastOracleTimestamp.toString()
+      );
+      assert.equal(
+        newest.priceACumulative.toString(),
+        poolAccount.priceACumulative.toString()
+      );
+      assert.equal(
+        newest.priceBCumulative.toString(),
+        poolAccount.priceBCumulative.toString()
+      );
+      return buffer;
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 2_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000_000);
+      [observations] = PublicKey.findProgramAddressSync(
+        [Buffer.from("observations"), seeded.pool.toBuffer()],
+        program.programId
+      );
+    });
+
+    it("Should create the buffer with one observation", async () => {
+      const signature = await program.methods
+        .initializeObservations()
+        .accounts({
+          pool: seeded.pool,
+          payer: payer.publicKey,
+          observations,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      const buffer = await expectNewestIsPool();
+      assert.equal(buffer.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(buffer.cardinality, 1);
+      assert.equal(buffer.index, 0);
+      assert.equal(buffer.observations.length, 1);
+
+      const event = await getEvent(
+        signature,
+        "observationCardinalityIncreasedEvent"
+      );
+      assert.equal(event.previousCardinality, 0);
+      assert.equal(event.cardinality, 1);
+    });
+
+    it("Should overwrite the only observation at cardinality one", async () => {
+      const before = await fetchObservations();
+      await swapAToB(10_000_000);
+
+      const buffer = await expectNewestIsPool();
+      assert.equal(buffer.index, 0);
+      assert.equal(buffer.observations.length, 1);
+      assert.isTrue(
+        buffer.observations[0].slot.gt(before.observations[0].slot)
+      );
+    });
+
+    it("Should grow the cardinality, and only grow it", async () => {
+      await increaseCardinality(3);
+      const buffer = await fetchObservations();
+      assert.equal(buffer.cardinality, 3);
+      // The new room fills as swaps come in
+      assert.equal(buffer.observations.length, 1);
+
+      await expectError(
+        increaseCardinality(3),
+        "InvalidObservationCardinality"
+      );
+      await expectError(
+        increaseCardinality(201),
+        "InvalidObservationCardinality"
+      );
+    });
+
+    it("Should fill the buffer and then wrap around", async () => {
+      await swapAToB(10_000_000);
+      let buffer = await expectNewestIsPool();
+      assert.equal(buffer.index, 1);
+      assert.equal(buffer.observations.length, 2);
+
+      await swapAToB(10_000_000);
+      buffer = await expectNewestIsPool();
+      assert.equal(buffer.index, 2);
+      assert.equal(buffer.observations.length, 3);
+      const oldest = buffer.observations[0];
+
+      // Full: the next write replaces the oldest
+      await swapAToB(10_000_000);
+      buffer = await expectNewestIsPool();
+      assert.equal(buffer.index, 0);
+      assert.equal(buffer.observations.length, 3);
+      assert.isTrue(buffer.observations[0].slot.gt(oldest.slot));
+      assert.isTrue(
+        buffer.observations[0].slot.gt(buffer.observations[2].slot)
+      );
+    });
+
+    it("Should record one observation per slot", async () => {
+      const before = await fetchObservations();
+      await waitForSlot(
+        before.observations[before.index].slot.toNumber() + 1
+      );
+      const tx = new Transaction().add(
+        await swapIx(10_000_000).instruction(),
+        await swapIx(10_000_000).instruction()
+      );
+      await provider.sendAndConfirm(tx);
+
+      const buffer = await fetchObservations();
+      assert.equal(buffer.index, (before.index + 1) % 3);
+    });
+
+    it("Should return the TWAP over a window between swaps", async () => {
+      await swapAToB(100_000_000);
+      const first = await fetchPool();
+      await sleep(4_000);
+      await swapAToB(100_000_000);
+      const second = await fetchPool();
+      await sleep(2_000);
+
+      // A window starting a couple of seconds after the first swap
+      const firstTimestamp = first.lastOracleTimestamp.toNumber();
+      const secondsAgo = (await getClusterTime()) - firstTimestamp - 2;
+      const twap = await getTwap(secondsAgo).view();
+
+      const now = twap.timestamp;
+      const start = now.subn(secondsAgo);
+      assert.isTrue(start.gt(first.lastOracleTimestamp));
+      assert.isTrue(start.lt(second.lastOracleTimestamp));
+      // Each price weighted by the part of the window it held for
+      const firstSeconds = second.lastOracleTimestamp.sub(start);
+      const secondSeconds = now.sub(second.lastOracleTimestamp);
+      const expectedA = q64Price(first.reserveB, first.reserveA)
+        .mul(firstSeconds)
+        .add(q64Price(second.reserveB, second.reserveA).mul(secondSeconds))
+        .divn(secondsAgo);
+      const expectedB = q64Price(first.reserveA, first.reserveB)
+        .mul(firstSeconds)
+        .add(q64Price(second.reserveA, second.reserveB).mul(secondSeconds))
+        .divn(secondsAgo);
+      assert.equal(twap.priceA.toString(), expectedA.toString());
+      assert.equal(twap.priceB.toString(), expectedB.toString());
+    });
+
+    it("Should reject windows older than the buffer", async () => {
+      await expectError(getTwap(1_000_000).rpc(), "OracleStale");
+      await expectError(getTwap(0).rpc(), "InvalidAmount");
+    });
+  });
+
+  describe("Tracked Reserves", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+    const donation = 500_000_000;
+
+    const fetchPool = () => program.account.pool.fetch(seeded.pool);
+    const vaults = () => ({
+      pool: seeded.pool,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+    });
+
+    const quoteSwap = (amountIn: number) =>
+      program.methods
+        .quoteSwap(new BN(amountIn), true)
+        .accounts(vaults())
+        .view();
+    const quoteAddLiquidity = (amountA: number, amountB: number) =>
+      program.methods
+        .quoteAddLiquidity(new BN(amountA), new BN(amountB))
+        .accounts({
+          ...vaults(),
+          lpMint: seeded.lpMint,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+        })
+        .view();
+    const quoteRemoveLiquidity = (lpAmount: number) =>
+      program.methods
+        .quoteRemoveLiquidity(new BN(lpAmount))
+        .accounts({ ...vaults(), lpMint: seeded.lpMint })
+        .view();
+
+    // Checks each vault covers its reserve and owed fees, with `surplus`
+    // left over
+    const expectBacked = async (surplusA: number, surplusB: number) => {
+      const poolAccount = await fetchPool();
+      for (const [vault, reserve, owed, surplus] of [
+        [
+          seeded.vaultA,
+          poolAccount.reserveA,
+          poolAccount.protocolFeesOwedA,
+          surplusA,
+        ],
+        [
+          seeded.vaultB,
+          poolAccount.reserveB,
+          poolAccount.protocolFeesOwedB,
+          surplusB,
+        ],
+      ] as [PublicKey, anchor.BN, anchor.BN, number][]) {
+        assert.equal(
+          (await getTokenBalance(vault)).toString(),
+          reserve.add(owed).addn(surplus).toString()
+        );
+      }
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 2_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 1_000_000_000);
+    });
+
+    it("Should ignore a donation when pricing swaps and LP tokens", async () => {
+      const swapQuote = await quoteSwap(10_000_000);
+      const depositQuote = await quoteAddLiquidity(10_000_000, 20_000_000);
+      const withdrawalQuote = await quoteRemoveLiquidity(1_000_000);
+      const before = await fetchPool();
+
+      // Tokens sent straight to the vault, as a price manipulation would
+      await mintTo(
+        provider.connection,
+        payer,
+        seeded.mintA,
+        seeded.vaultA,
+        payer.publicKey,
+        donation
+      );
+      await expectBacked(donation, 0);
+      const after = await fetchPool();
+      assert.equal(after.reserveA.toString(), before.reserveA.toString());
+
+      const unchanged = await quoteSwap(10_000_000);
+      assert.equal(
+        unchanged.amountOut.toString(),
+        swapQuote.amountOut.toString()
+      );
+      assert.equal(
+        (await quoteAddLiquidity(10_000_000, 20_000_000)).lpTokens.toString(),
+        depositQuote.lpTokens.toString()
+      );
+      const withdrawal = await quoteRemoveLiquidity(1_000_000);
+      assert.equal(
+        withdrawal.amountA.toString(),
+        withdrawalQuote.amountA.toString()
+      );
+      assert.equal(
+        withdrawal.amountB.toString(),
+        withdrawalQuote.amountB.toString()
+      );
+    });
+
+    it("Should trade and redeem at the reserves' price", async () => {
+      const swapQuote = await quoteSwap(10_000_000);
+      const tokenBBefore = await getTokenBalance(seeded.payerTokenB);
+      await program.methods
+        .swap(
+          new BN(10_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const tokenBAfter = await getTokenBalance(seeded.payerTokenB);
+      assert.equal(
+        tokenBAfter.sub(tokenBBefore).toString(),
+        swapQuote.amountOut.toString()
+      );
+      await expectBacked(donation, 0);
+
+      const liquidityAccounts = {
+        pool: seeded.pool,
+        tokenAMint: seeded.mintA,
+        tokenBMint: seeded.mintB,
+        userTokenA: seeded.payerTokenA,
+        userTokenB: seeded.payerTokenB,
+        poolTokenA: seeded.vaultA,
+        poolTokenB: seeded.vaultB,
+        lpMint: seeded.lpMint,
+        userLp: seeded.payerLp,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+      const depositQuote = await quoteAddLiquidity(10_000_000, 20_000_000);
+      const lpBefore = await getTokenBalance(seeded.payerLp);
+      await program.methods
+        .addLiquidity(
+          new BN(10_000_000),
+          new BN(20_000_000),
+          new BN(0),
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts, owner: payer.publicKey })
+        .rpc();
+      const lpAfter = await getTokenBalance(seeded.payerLp);
+      assert.equal(
+        lpAfter.sub(lpBefore).toString(),
+        depositQuote.lpTokens.toString()
+      );
+      await expectBacked(donation, 0);
+
+      const withdrawalQuote = await quoteRemoveLiquidity(1_000_000);
+      const tokenABefore = await getTokenBalance(seeded.payerTokenA);
+      await program.methods
+        .removeLiquidity(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts, user: payer.publicKey })
+        .rpc();
+      const tokenAAfter = await getTokenBalance(seeded.payerTokenA);
+      assert.equal(
+        tokenAAfter.sub(tokenABefore).toString(),
+        withdrawalQuote.amountA.toString()
+      );
+      // The donation stays out of the payout
+      await expectBacked(donation, 0);
+    });
+
+    it("Should move the price once sync credits the donation", async () => {
+      const swapQuote = await quoteSwap(10_000_000);
+      await syncReserves(seeded.pool, seeded.vaultA, seeded.vaultB);
+      await expectBacked(0, 0);
+
+      // More A in the reserves makes A cheaper
+      const synced = await quoteSwap(10_000_000);
+      assert.isTrue(synced.amountOut.lt(swapQuote.amountOut));
+    });
+  });
+
+  describe("Pool Statistics", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let stats: PublicKey;
+    const BN = anchor.BN;
+
+    const getStats = () =>
+      program.methods.getStats().accounts({ pool: seeded.pool, stats }).view();
+
+    // Swaps `amountIn`, passing the stats account unless `withStats` is false,
+    // and returns the executed swap's event
+    const swap = async (aToB: boolean, amountIn: number, withStats = true) => {
+      const signature = await program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          stats: withStats ? stats : null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      return getEvent(signature, "swapExecutedEvent");
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 2_000_000_000);
+      stats = findStatsAddress(seeded.pool);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+    });
+
+    it("Should create the stats account lazily", async () => {
+      // Swaps work as before while the pool has no stats account
+      await expectError(getStats(), "AccountNotInitialized");
+      await swap(true, 1_000_000, false);
+
+      await initStats(seeded.pool);
+      const account = await program.account.poolStats.fetch(stats);
+      assert.isTrue(account.pool.equals(seeded.pool));
+
+      // Nothing before it was created counts
+      const result = await getStats();
+      assert.equal(result.swapCount.toString(), "0");
+      assert.equal(result.liquidityEventCount.toString(), "0");
+      assert.equal(result.cumulativeVolumeA.toString(), "0");
+      assert.equal(result.cumulativeVolumeB.toString(), "0");
+      assert.equal(result.cumulativeFeesA.toString(), "0");
+      assert.equal(result.cumulativeFeesB.toString(), "0");
+
+      try {
+        await initStats(seeded.pool);
+        assert.fail("Second initialization should fail");
+      } catch (error) {
+        const logs = (error.logs ?? []).join("\n");
+        assert.include(logs + error.toString(), "already in use");
+      }
+    });
+
+    it("Should only count swaps passed the stats account", async () => {
+      const before = await getStats();
+      const reserveBefore = (await program.account.pool.fetch(seeded.pool))
+        .reserveA;
+
+      await swap(true, 1_000_000, false);
+      const skipped = await getStats();
+      assert.equal(skipped.swapCount.toString(), before.swapCount.toString());
+      assert.equal(
+        skipped.cumulativeVolumeA.toString(),
+        before.cumulativeVolumeA.toString()
+      );
+      // The swap itself went through
+      assert.isTrue(
+        (await program.account.pool.fetch(seeded.pool)).reserveA.gt(
+          reserveBefore
+        )
+      );
+
+      const event = await swap(true, 1_000_000);
+      const counted = await getStats();
+      assert.equal(
+        counted.swapCount.toString(),
+        before.swapCount.addn(1).toString()
+      );
+      assert.equal(
+        counted.cumulativeVolumeA.toString(),
+        before.cumulativeVolumeA.add(event.amountIn).toString()
+      );
+    });
+
+    it("Should total a scripted sequence exactly", async () => {
+      const before = await getStats();
+      const events = [
+        { aToB: true, event: await swap(true, 10_000_000) },
+        { aToB: false, event: await swap(false, 5_000_000) },
+        { aToB: true, event: await swap(true, 1_000_000) },
+      ];
+
+      const liquidityAccounts = {
+        pool: seeded.pool,
+        tokenAMint: seeded.mintA,
+        tokenBMint: seeded.mintB,
+        userTokenA: seeded.payerTokenA,
+        userTokenB: seeded.payerTokenB,
+        poolTokenA: seeded.vaultA,
+        poolTokenB: seeded.vaultB,
+        lpMint: seeded.lpMint,
+        userLp: seeded.payerLp,
+        stats,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+      await program.methods
+        .addLiquidity(
+          new BN(10_000_000),
+          new BN(20_000_000),
+          new BN(0),
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts, owner: payer.publicKey })
+        .rpc();
+      await program.methods
+        .removeLiquidity(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts, user: payer.publicKey })
+        .rpc();
+
+      const expected = {
+        volumeA: before.cumulativeVolumeA,
+        volumeB: before.cumulativeVolumeB,
+        feesA: before.cumulativeFeesA,
+        feesB: before.cumulativeFeesB,
+      };
+      for (const { aToB, event } of events) {
+        if (aToB) {
+          expected.volumeA = expected.volumeA.add(event.amountIn);
+          expected.volumeB = expected.volumeB.add(event.amountOut);
+          expected.feesA = expected.feesA.add(event.fee);
+        } else {
+          expected.volumeB = expected.volumeB.add(event.amountIn);
+          expected.volumeA = expected.volumeA.add(event.amountOut);
+          expected.feesB = expected.feesB.add(event.fee);
+        }
+      }
+
+      const after = await getStats();
+      assert.equal(
+        after.swapCount.toString(),
+        before.swapCount.addn(3).toString()
+      );
+      // The deposit and the withdrawal
+      assert.equal(
+        after.liquidityEventCount.toString(),
+        before.liquidityEventCount.addn(2).toString()
+      );
+      assert.equal(
+        after.cumulativeVolumeA.toString(),
+        expected.volumeA.toString()
+      );
+      assert.equal(
+        after.cumulativeVolumeB.toString(),
+        expected.volumeB.toString()
+      );
+      assert.equal(after.cumulativeFeesA.toString(), expected.feesA.toString());
+      assert.equal(after.cumulativeFeesB.toString(), expected.feesB.toString());
+      assert.isTrue(expected.feesB.gtn(0));
+    });
+  });
+
+  describe("Pool Migration", () => {
+    // Loaded at genesis from tests/fixtures/legacy_pool (see Anchor.toml):
+    // pools in the layout from before `Pool::version`, each holding 1000 A
+    // and 2000 B, and a trader with 1000 of each. The first charges 3/1000,
+    // the second 7/3000, which is no whole number of bps.
+    const legacy = {
+      pool: new PublicKey("CtWGcqRPd6YuV3mAcK5BxvgnJYej3kDuNDqb1gAxM2VF"),
+      mintA: new PublicKey("EYiNkxBZRp9vS7W4Gii9wnkdAzmiZLnVs7QnThmxS8ui"),
+      mintB: new PublicKey("SXeZ1zqRH2dBstvFVzVV5V6Lj7Rb3HXeZCFhavyGWJ9"),
+      vaultA: new PublicKey("55Uk9GCfgf1AFsohs61eREUpgGasiwGRA79aVdG7M2W4"),
+      vaultB: new PublicKey("4fcrBfGHAAwHkucjFAoGrnHZFUwBSKAk2nc3R1kMKKJS"),
+      traderA: new PublicKey("Fp9H1EGG2kiqS2fCaSjVu333H3YHTSS12vrUXfSnhdia"),
+      traderB: new PublicKey("Cv5eqEH5yiDRccfNqx1tXXoWFeyW7EM8M62oStUBVxEV"),
+      oddFeePool: new PublicKey("Qhxa93qeotbkerAQQNbWW53gkyBub3HyejvburNUPKm"),
+    };
+    const trader = Keypair.fromSecretKey(
+      Uint8Array.from(
+        JSON.parse(
+          readFileSync("tests/fixtures/legacy_pool/trader.json", "utf8")
+        )
+      )
+    );
+    const BN = anchor.BN;
+
+    const swap = () =>
+      program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: legacy.pool,
+          owner: trader.publicKey,
+          tokenInMint: legacy.mintA,
+          tokenOutMint: legacy.mintB,
+          userTokenIn: legacy.traderA,
+          userTokenOut: legacy.traderB,
+          poolTokenIn: legacy.vaultA,
+          poolTokenOut: legacy.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([trader])
+        .rpc();
+
+    const migrate = (
+      pool: PublicKey = legacy.pool,
+      roundFeeDown = false,
+      signer: Keypair = payer
+    ) =>
+      program.methods
+        .migratePool(roundFeeDown)
+        .accounts({ pool, payer: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    it("Should not load a pool in the old layout", async () => {
+      let decoded = true;
+      try {
+        await program.account.pool.fetch(legacy.pool);
+      } catch {
+        decoded = false;
+      }
+      assert.isFalse(decoded);
+      await expectError(swap(), "AccountDidNotDeserialize");
+    });
+
+    it("Should migrate the pool and keep its state", async () => {
+      const before = await provider.connection.getAccountInfo(legacy.pool);
+      const signature = await migrate();
+
+      const after = await provider.connection.getAccountInfo(legacy.pool);
+      // `version`, `registry_entry`, the activity timestamps,
+      // `total_fee_bps`, `trade_limits`, `lp_decimals`, `protocol_lp_amount`,
+      // the minimum inputs, `initial_price_set`, `sequence` and the reserved
+      // bytes
+      assert.equal(
+        after.data.length,
+        before.data.length + 1 + 32 + 32 + 2 + 10 + 1 + 8 + 16 + 1 + 8 + 18
+      );
+      assert.isAbove(after.lamports, before.lamports);
+      assert.isTrue(
+        after.data
+          .subarray(0, before.data.length)
+          .equals(before.data.subarray(0, before.data.length))
+      );
+
+      const pool = await program.account.pool.fetch(legacy.pool);
+      assert.equal(pool.version, 2);
+      assert.equal(pool.totalFeeBps, 30);
+      assert.equal(pool.feeNumerator.toNumber(), 0);
+      assert.equal(pool.feeDenominator.toNumber(), 0);
+      assert.equal(pool.reserveA.toNumber(), 1_000_000_000);
+      assert.equal(pool.reserveB.toNumber(), 2_000_000_000);
+      assert.isTrue(pool.authority.equals(trader.publicKey));
+      assert.equal(pool.swapCount.toString(), "0");
+
+      const event = await getEvent(signature, "poolMigratedEvent");
+      assert.equal(event.previousPoolVersion, 0);
+      assert.equal(event.poolVersion, 2);
+      assert.equal(event.totalFeeBps, 30);
+      assert.equal(event.previousLen.toNumber(), before.data.length);
+      assert.equal(event.len.toNumber(), after.data.length);
+    });
+
+    it("Should swap against the migrated pool", async () => {
+      const traderBBefore = await getTokenBalance(legacy.traderB);
+      await swap();
+
+      const received = (await getTokenBalance(legacy.traderB)).sub(
+        traderBBefore
+      );
+      assert.isTrue(received.gtn(0));
+      const pool = await program.account.pool.fetch(legacy.pool);
+      assert.equal(
+        pool.reserveA.add(pool.protocolFeesOwedA).toNumber(),
+        1_001_000_000
+      );
+      assert.equal(
+        pool.reserveB.toNumber(),
+        2_000_000_000 - received.toNumber()
+      );
+    });
+
+    it("Should reject migrating a pool twice", async () => {
+      await expectError(migrate(), "PoolAlreadyMigrated");
+    });
+
+    it("Should only round an odd fee down for the authority", async () => {
+      await expectError(migrate(legacy.oddFeePool), "FeeNotRepresentable");
+      await expectError(migrate(legacy.oddFeePool, true), "Unauthorized");
+
+      // 7/3000 is 23.33 bps
+      const signature = await migrate(legacy.oddFeePool, true, trader);
+      const event = await getEvent(signature, "poolMigratedEvent");
+      assert.equal(event.totalFeeBps, 23);
+      const pool = await program.account.pool.fetch(legacy.oddFeePool);
+      assert.equal(pool.version, 2);
+      assert.equal(pool.totalFeeBps, 23);
+      assert.equal(pool.feeNumerator.toNumber(), 0);
+    });
+
+    it("Should create new pools on the current version", async () => {
+      const seeded = await createSeededPool(1_000_000, 1_000_000);
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(pool.version, 2);
+      assert.equal(pool.totalFeeBps, 30);
+      assert.equal(pool.feeDenominator.toNumber(), 0);
+      await expectError(migrate(seeded.pool), "PoolAlreadyMigrated");
+    });
+  });
+
+  describe("Pool Registry", () => {
+    type SeededPool = Awaited<ReturnType<typeof createSeededPool>>;
+    const seededPools: SeededPool[] = [];
+    let firstIndex: number;
+
+    before(async () => {
+      firstIndex = await getPoolCount();
+      for (let i = 0; i < 3; i++) {
+        seededPools.push(await createSeededPool(1_000_000, 2_000_000));
+      }
+    });
+
+    it("Should register each new pool at the next index", async () => {
+      assert.equal(await getPoolCount(), firstIndex + 3);
+      for (const [i, seeded] of seededPools.entries()) {
+        assert.isTrue(
+          seeded.registryEntry.equals(findRegistryEntry(firstIndex + i))
+        );
+        const entry = await program.account.poolRegistryEntry.fetch(
+          seeded.registryEntry
+        );
+        assert.equal(entry.index.toNumber(), firstIndex + i);
+        assert.isTrue(entry.pool.equals(seeded.pool));
+        assert.isTrue(entry.tokenAMint.equals(seeded.mintA));
+        assert.isTrue(entry.tokenBMint.equals(seeded.mintB));
+        assert.isFalse(entry.closed);
+
+        const pool = await program.account.pool.fetch(seeded.pool);
+        assert.isTrue(pool.registryEntry.equals(seeded.registryEntry));
+      }
+    });
+
+    it("Should enumerate every pool by walking the registry", async () => {
+      const count = await getPoolCount();
+      const entries = await program.account.poolRegistryEntry.fetchMultiple(
+        Array.from({ length: count }, (_, index) => findRegistryEntry(index))
+      );
+
+      entries.forEach((entry, index) => {
+        assert.isNotNull(entry);
+        assert.equal(entry.index.toNumber(), index);
+      });
+      const pools = entries.map((entry) => entry.pool.toBase58());
+      for (const seeded of seededPools) {
+        assert.include(pools, seeded.pool.toBase58());
+      }
+    });
+
+    it("Should tombstone the entry of a closed pool", async () => {
+      const seeded = seededPools[1];
+      const closePool = (registryEntry: PublicKey) =>
+        program.methods
+          .closePool()
+          .accounts({
+            pool: seeded.pool,
+            authority: payer.publicKey,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+            lpMint: seeded.lpMint,
+            registryEntry,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+
+      await program.methods
+        .removeLiquidity(
+          await getTokenBalance(seeded.payerLp),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          false,
+          new anchor.BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          user: payer.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: seeded.payerTokenA,
+          userTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: seeded.payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      await expectError(
+        closePool(seededPools[0].registryEntry),
+        "InvalidRegistryEntry"
+      );
+      await closePool(seeded.registryEntry);
+
+      assert.isNull(await provider.connection.getAccountInfo(seeded.pool));
+      const entry = await program.account.poolRegistryEntry.fetch(
+        seeded.registryEntry
+      );
+      assert.isTrue(entry.closed);
+      assert.isTrue(entry.pool.equals(seeded.pool));
+      assert.equal(await getPoolCount(), firstIndex + 3);
+
+      // Its neighbours are untouched
+      for (const other of [seededPools[0], seededPools[2]]) {
+        const otherEntry = await program.account.poolRegistryEntry.fetch(
+          other.registryEntry
+        );
+        assert.isFalse(otherEntry.closed);
+      }
+    });
+  });
+
+  describe("Vault Validation", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let forgedVaultA: PublicKey;
+    const BN = anchor.BN;
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 10_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 10_000_000);
+      // A look-alike vault: the pool's mint and owner, with the same balance
+      forgedVaultA = await createAccount(
+        provider.connection,
+        payer,
+        seeded.mintA,
+        seeded.pool,
+        Keypair.generate()
+      );
+      await mintTo(
+        provider.connection,
+        payer,
+        seeded.mintA,
+        forgedVaultA,
+        payer,
+        1_000_000_000
+      );
+    });
+
+    const liquidityAccounts = () => ({
+      pool: seeded.pool,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      userTokenA: seeded.payerTokenA,
+      userTokenB: seeded.payerTokenB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+      userLp: seeded.payerLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    it("Should reject a forged vault in swaps", async () => {
+      await expectError(
+        program.methods
+          .swap(
+            new BN(1_000_000),
+            new BN(0),
+            new BN(0),
+            new BN(0),
+            new BN(0),
+            null
+          )
+          .accounts({
+            pool: seeded.pool,
+            owner: payer.publicKey,
+            tokenInMint: seeded.mintA,
+            tokenOutMint: seeded.mintB,
+            userTokenIn: seeded.payerTokenA,
+            userTokenOut: seeded.payerTokenB,
+            poolTokenIn: forgedVaultA,
+            poolTokenOut: seeded.vaultB,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc(),
+        "InvalidPoolTokenAccount"
+      );
+    });
+
+    it("Should reject a forged vault in deposits and withdrawals", async () => {
+      await expectError(
+        program.methods
+          .addLiquidity(
+            new BN(1_000_000),
+            new BN(1_000_000),
+            new BN(0),
+            new BN(0)
+          )
+          .accounts({
+            ...liquidityAccounts(),
+            owner: payer.publicKey,
+            poolTokenA: forgedVaultA,
+          })
+          .rpc(),
+        "InvalidPoolTokenAccount"
+      );
+      await expectError(
+        program.methods
+          .removeLiquidity(
+            new BN(1_000_000),
+            new BN(0),
+            new BN(0),
+            false,
+            new BN(0)
+          )
+          .accounts({
+            ...liquidityAccounts(),
+            user: payer.publicKey,
+            poolTokenA: forgedVaultA,
+          })
+          .rpc(),
+        "InvalidPoolTokenAccount"
+      );
+    });
+
+    it("Should reject a forged LP mint", async () => {
+      // Burning the only token of a mint the attacker controls must not
+      // withdraw the whole pool
+      const forgedLpMint = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const forgedLp = await fundAccount(forgedLpMint, payer.publicKey, 1);
+
+      await expectError(
+        program.methods
+          .removeLiquidity(new BN(1), new BN(0), new BN(0), false, new BN(0))
+          .accounts({
+            ...liquidityAccounts(),
+            user: payer.publicKey,
+            lpMint: forgedLpMint,
+            userLp: forgedLp,
+          })
+          .rpc(),
+        "ConstraintAddress"
+      );
+      await expectError(
+        program.methods
+          .addLiquidity(
+            new BN(1_000_000),
+            new BN(1_000_000),
+            new BN(0),
+            new BN(0)
+          )
+          .accounts({
+            ...liquidityAccounts(),
+            owner: payer.publicKey,
+            lpMint: forgedLpMint,
+            userLp: forgedLp,
+          })
+          .rpc(),
+        "ConstraintAddress"
+      );
+      assert.equal(
+        (await getTokenBalance(seeded.vaultA)).toString(),
+        "1000000000"
+      );
+    });
+  });
+
+  describe("Pool Activity", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let startTime: number;
+    const BN = anchor.BN;
+
+    const getStats = () =>
+      program.methods
+        .getStats()
+        .accounts({ pool: seeded.pool, stats: findStatsAddress(seeded.pool) })
+        .view();
+
+    const liquidityAccounts = () => ({
+      pool: seeded.pool,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      userTokenA: seeded.payerTokenA,
+      userTokenB: seeded.payerTokenB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+      userLp: seeded.payerLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    before(async () => {
+      startTime = await getClusterTime();
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await initStats(seeded.pool);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+    });
+
+    it("Should stamp the creation and the seeding deposit", async () => {
+      const stats = await getStats();
+      const createdAt = stats.createdAtTimestamp.toNumber();
+      // Block times and the Clock sysvar may disagree by a second or two
+      assert.isAtLeast(createdAt, startTime - 2);
+      assert.isAtMost(createdAt, (await getClusterTime()) + 2);
+      assert.isAbove(stats.createdAtSlot.toNumber(), 0);
+      assert.isAtMost(
+        stats.createdAtSlot.toNumber(),
+        await provider.connection.getSlot()
+      );
+      assert.isAtLeast(
+        stats.lastLiquidityChangeTimestamp.toNumber(),
+        createdAt
+      );
+      assert.equal(stats.lastSwapTimestamp.toNumber(), 0);
+    });
+
+    it("Should update the last swap time on swaps only", async () => {
+      const before = await getStats();
+      await waitUntil(before.lastLiquidityChangeTimestamp.toNumber() + 2);
+      await program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const after = await getStats();
+      assert.isAbove(
+        after.lastSwapTimestamp.toNumber(),
+        before.lastLiquidityChangeTimestamp.toNumber()
+      );
+      assert.equal(
+        after.lastLiquidityChangeTimestamp.toString(),
+        before.lastLiquidityChangeTimestamp.toString()
+      );
+      assert.equal(
+        after.createdAtTimestamp.toString(),
+        before.createdAtTimestamp.toString()
+      );
+      assert.equal(
+        after.createdAtSlot.toString(),
+        before.createdAtSlot.toString()
+      );
+    });
+
+    it("Should update the last liquidity change on deposits and withdrawals", async () => {
+      const before = await getStats();
+      await waitUntil(before.lastSwapTimestamp.toNumber() + 2);
+      await program.methods
+        .addLiquidity(
+          new BN(10_000_000),
+          new BN(10_000_000),
+          new BN(0),
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts(), owner: payer.publicKey })
+        .rpc();
+
+      const deposited = await getStats();
+      const depositTime = deposited.lastLiquidityChangeTimestamp.toNumber();
+      assert.isAbove(depositTime, before.lastSwapTimestamp.toNumber());
+      assert.equal(
+        deposited.lastSwapTimestamp.toString(),
+        before.lastSwapTimestamp.toString()
+      );
+
+      // Other instructions that touch the reserves leave both alone
+      await waitUntil(depositTime + 2);
+      await syncReserves(seeded.pool, seeded.vaultA, seeded.vaultB);
+      const synced = await getStats();
+      assert.equal(synced.lastLiquidityChangeTimestamp.toNumber(), depositTime);
+      assert.equal(
+        synced.lastSwapTimestamp.toString(),
+        before.lastSwapTimestamp.toString()
+      );
+
+      await program.methods
+        .removeLiquidity(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts(), user: payer.publicKey })
+        .rpc();
+      const withdrawn = await getStats();
+      assert.isAbove(
+        withdrawn.lastLiquidityChangeTimestamp.toNumber(),
+        depositTime
+      );
+      assert.equal(
+        withdrawn.lastSwapTimestamp.toString(),
+        before.lastSwapTimestamp.toString()
+      );
+    });
+  });
+
+  describe("Trade Limits", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+
+    const limits = (minTradeAmount: number, maxTradeBps: number) => ({
+      minTradeAmount: new BN(minTradeAmount),
+      maxTradeBps,
+    });
+
+    const setTradeLimits = (
+      minTradeAmount: number,
+      maxTradeBps: number,
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .setTradeLimits(limits(minTradeAmount, maxTradeBps))
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const swap = (amountIn: number | anchor.BN) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const swapExactOut = (amountOut: number) =>
+      program.methods
+        .swapExactOut(new BN(amountOut), new BN(100_000_000), false, new BN(0))
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 200_000_000);
+    });
+
+    it("Should take the trade limits at creation", async () => {
+      const limited = await createSeededPool(
+        1_000_000,
+        1_000_000,
+        30,
+        undefined,
+        0,
+        limits(500, 1_000)
+      );
+      const pool = await program.account.pool.fetch(limited.pool);
+      assert.equal(pool.tradeLimits.minTradeAmount.toNumber(), 500);
+      assert.equal(pool.tradeLimits.maxTradeBps, 1_000);
+
+      await expectError(
+        createSeededPool(
+          1_000_000,
+          1_000_000,
+          30,
+          undefined,
+          0,
+          limits(0, 10_001)
+        ),
+        "InvalidTradeLimits"
+      );
+    });
+
+    it("Should only let the authority set the trade limits", async () => {
+      await expectError(setTradeLimits(1_000, 100, outsider), "Unauthorized");
+      await expectError(setTradeLimits(1_000, 10_001), "InvalidTradeLimits");
+
+      const signature = await setTradeLimits(1_000, 100);
+      const event = await getEvent(signature, "tradeLimitsSetEvent");
+      assert.equal(event.previous.minTradeAmount.toNumber(), 0);
+      assert.equal(event.previous.maxTradeBps, 0);
+      assert.equal(event.tradeLimits.minTradeAmount.toNumber(), 1_000);
+      assert.equal(event.tradeLimits.maxTradeBps, 100);
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(pool.tradeLimits.minTradeAmount.toNumber(), 1_000);
+      assert.equal(pool.tradeLimits.maxTradeBps, 100);
+    });
+
+    it("Should reject swaps below the minimum", async () => {
+      await setTradeLimits(1_000, 0);
+      await expectError(swap(999), "TradeTooSmall");
+      await swap(1_000);
+    });
+
+    it("Should reject swaps above the maximum share of the reserve", async () => {
+      await setTradeLimits(0, 100);
+      const pool = await program.account.pool.fetch(seeded.pool);
+      // 1% of the input reserve
+      const maxTrade = pool.reserveA.muln(100).divn(10_000);
+      await expectError(swap(maxTrade.addn(1)), "TradeTooLarge");
+      await swap(maxTrade);
+    });
+
+    it("Should hold exact-out swaps to the limits", async () => {
+      // About 1% of the reserve goes in for 1% less out
+      await setTradeLimits(0, 100);
+      await expectError(swapExactOut(10_000_000), "TradeTooLarge");
+      await swapExactOut(5_000_000);
+
+      await setTradeLimits(1_000_000, 0);
+      await expectError(swapExactOut(500_000), "TradeTooSmall");
+      await swapExactOut(2_000_000);
+    });
+
+    it("Should allow swaps of any size with both limits off", async () => {
+      await setTradeLimits(0, 0);
+      await swap(100);
+      // 5% of the reserve
+      await swap(50_000_000);
+    });
+  });
+
+  describe("LP Mint Decimals", () => {
+    const BN = anchor.BN;
+
+    // Mirrors `lp_mint_decimals`
+    const lpMintDecimals = (decimalsA: number, decimalsB: number) =>
+      Math.max(decimalsA, decimalsB);
+
+    const newMint = (decimals: number) =>
+      createMint(provider.connection, payer, payer.publicKey, null, decimals);
+
+    // Creates a pool on new mints with the given decimals, its LP mint having
+    // `lpDecimals`, seeded with 10 whole tokens of each
+    const createPool = async (
+      decimalsA: number,
+      decimalsB: number,
+      lpDecimals = lpMintDecimals(decimalsA, decimalsB)
+    ) => {
+      const mintA = await newMint(decimalsA);
+      const mintB = await newMint(decimalsB);
+      const lpMint = await newMint(lpDecimals);
+      const pool = findPoolAddress(mintA, mintB);
+      const vaultA = await createAccount(
+        provider.connection,
+        payer,
+        mintA,
+        pool,
+        Keypair.generate()
+      );
+      const vaultB = await createAccount(
+        provider.connection,
+        payer,
+        mintB,
+        pool,
+        Keypair.generate()
+      );
+      await setAuthority(
+        provider.connection,
+        payer,
+        lpMint,
+        payer.publicKey,
+        AuthorityType.MintTokens,
+        pool
+      );
+
+      const seedA = 10 * 10 ** decimalsA;
+      const seedB = 10 * 10 ** decimalsB;
+      const userTokenA = await fundAccount(mintA, payer.publicKey, 2 * seedA);
+      const userTokenB = await fundAccount(mintB, payer.publicKey, 2 * seedB);
+      const userLp = await fundAccount(lpMint, payer.publicKey, 0);
+      await program.methods
+        .initializePoolWithLiquidity(
+          30,
+          new BN(seedA),
+          new BN(seedB),
+          0,
+          new BN(0),
+          noTradeLimits
+        )
+        .accounts({
+          pool,
+          tokenAMint: mintA,
+          tokenBMint: mintB,
+          tokenAAccount: vaultA,
+          tokenBAccount: vaultB,
+          lpMint,
+          authority: payer.publicKey,
+          registryEntry: await nextRegistryEntry(),
+          userTokenA,
+          userTokenB,
+          userLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+
+      const addLiquidity = (amountA: number, amountB: number) =>
+        program.methods
+          .addLiquidity(new BN(amountA), new BN(amountB), new BN(0), new BN(0))
+          .accounts({
+            pool,
+            owner: payer.publicKey,
+            tokenAMint: mintA,
+            tokenBMint: mintB,
+            userTokenA,
+            userTokenB,
+            poolTokenA: vaultA,
+            poolTokenB: vaultB,
+            lpMint,
+            userLp,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+
+      return { pool, lpMint, userLp, seedA, seedB, addLiquidity };
+    };
+
+    for (const [decimalsA, decimalsB] of [
+      [6, 6],
+      [6, 9],
+      [0, 9],
+    ]) {
+      it(`Should size the LP of a (${decimalsA}, ${decimalsB}) pair`, async () => {
+        const created = await createPool(decimalsA, decimalsB);
+        const expected = lpMintDecimals(decimalsA, decimalsB);
+        const pool = await program.account.pool.fetch(created.pool);
+        assert.equal(pool.lpDecimals, expected);
+        const lpMint = await getMint(provider.connection, created.lpMint);
+        assert.equal(lpMint.decimals, expected);
+
+        // The seeding deposit mints the initial LP supply, and half as much
+        // again mints half of it, whatever the decimals
+        assert.equal(
+          (await getTokenBalance(created.userLp)).toNumber(),
+          1_000_000
+        );
+        await created.addLiquidity(created.seedA / 2, created.seedB / 2);
+        assert.equal(
+          (await getTokenBalance(created.userLp)).toNumber(),
+          1_500_000
+        );
+      });
+    }
+
+    it("Should reject an LP mint with other decimals", async () => {
+      await expectError(createPool(6, 9, 6), "InvalidLpMintDecimals");
+      await expectError(createPool(6, 6, 9), "InvalidLpMintDecimals");
+    });
+  });
+
+  describe("Protocol-Owned Liquidity", () => {
+    const treasury = Keypair.generate();
+    const outsider = Keypair.generate();
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+
+    // Token accounts of `owner` in the pool's mints, funded with token A and B
+    const fundWallet = async (owner: PublicKey, amount: number) => ({
+      tokenA: await fundAccount(seeded.mintA, owner, amount),
+      tokenB: await fundAccount(seeded.mintB, owner, amount),
+      lp: await fundAccount(seeded.lpMint, owner, 0),
+    });
+    type Wallet = Awaited<ReturnType<typeof fundWallet>>;
+
+    const deposit = (owner: Keypair, wallet: Wallet, amount: number) =>
+      program.methods
+        .addLiquidity(new BN(amount), new BN(amount), new BN(0), new BN(0))
+        .accounts({
+          pool: seeded.pool,
+          owner: owner.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: wallet.tokenA,
+          userTokenB: wallet.tokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: wallet.lp,
+          config: configAddress,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+
+    const withdraw = (user: Keypair, wallet: Wallet, lpAmount: number) =>
+      program.methods
+        .removeLiquidity(
+          new BN(lpAmount),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({
+          pool: seeded.pool,
+          user: user.publicKey,
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          userTokenA: wallet.tokenA,
+          userTokenB: wallet.tokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          lpMint: seeded.lpMint,
+          userLp: wallet.lp,
+          config: configAddress,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    const protocolLiquidity = () =>
+      program.methods
+        .getProtocolLiquidity()
+        .accounts({ pool: seeded.pool, lpMint: seeded.lpMint })
+        .view();
+
+    let treasuryWallet: Wallet;
+    let outsiderWallet: Wallet;
+
+    before(async () => {
+      await ensureSolBalance(treasury);
+      await ensureSolBalance(outsider);
+      await ensureConfig();
+      await updateConfig({ treasury: treasury.publicKey });
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      treasuryWallet = await fundWallet(treasury.publicKey, 100_000_000);
+      outsiderWallet = await fundWallet(outsider.publicKey, 100_000_000);
+    });
+
+    after(async () => {
+      await updateConfig({ treasury: PublicKey.default });
+    });
+
+    it("Should count the treasury's deposits and withdrawals", async () => {
+      const before = await protocolLiquidity();
+      assert.equal(before.protocolLpAmount.toNumber(), 0);
+
+      const depositSignature = await deposit(
+        treasury,
+        treasuryWallet,
+        100_000_000
+      );
+      const added = await getEvent(depositSignature, "liquidityAddedEvent");
+      assert.isTrue(added.isProtocol);
+      const minted = (await getTokenBalance(treasuryWallet.lp)).toNumber();
+      assert.equal(added.lpTokensMinted.toNumber(), minted);
+
+      const afterDeposit = await protocolLiquidity();
+      assert.equal(afterDeposit.protocolLpAmount.toNumber(), minted);
+      assert.equal(
+        afterDeposit.externalLpAmount.toNumber(),
+        afterDeposit.lpSupply.toNumber() - minted
+      );
+
+      const withdrawSignature = await withdraw(
+        treasury,
+        treasuryWallet,
+        minted / 4
+      );
+      const removed = await getEvent(
+        withdrawSignature,
+        "liquidityRemovedEvent"
+      );
+      assert.isTrue(removed.isProtocol);
+      assert.equal(
+        (await protocolLiquidity()).protocolLpAmount.toNumber(),
+        minted - minted / 4
+      );
+    });
+
+    it("Should not count third parties' liquidity", async () => {
+      const before = await protocolLiquidity();
+
+      const depositSignature = await deposit(
+        outsider,
+        outsiderWallet,
+        100_000_000
+      );
+      const added = await getEvent(depositSignature, "liquidityAddedEvent");
+      assert.isFalse(added.isProtocol);
+      const minted = (await getTokenBalance(outsiderWallet.lp)).toNumber();
+
+      const afterDeposit = await protocolLiquidity();
+      assert.equal(
+        afterDeposit.protocolLpAmount.toNumber(),
+        before.protocolLpAmount.toNumber()
+      );
+      assert.equal(
+        afterDeposit.externalLpAmount.toNumber(),
+        before.externalLpAmount.toNumber() + minted
+      );
+
+      const withdrawSignature = await withdraw(
+        outsider,
+        outsiderWallet,
+        minted
+      );
+      const removed = await getEvent(
+        withdrawSignature,
+        "liquidityRemovedEvent"
+      );
+      assert.isFalse(removed.isProtocol);
+      assert.equal(
+        (await protocolLiquidity()).protocolLpAmount.toNumber(),
+        before.protocolLpAmount.toNumber()
+      );
+    });
+
+    it("Should not track LP the treasury transfers", async () => {
+      const before = await protocolLiquidity();
+      const treasuryLp = await getTokenBalance(treasuryWallet.lp);
+
+      // LP sent to the outsider still counts as protocol-owned, and burning
+      // more than was counted takes the amount to zero rather than failing
+      await transfer(
+        provider.connection,
+        treasury,
+        treasuryWallet.lp,
+        outsiderWallet.lp,
+        treasury,
+        treasuryLp.toNumber()
+      );
+      assert.equal(
+        (await protocolLiquidity()).protocolLpAmount.toNumber(),
+        before.protocolLpAmount.toNumber()
+      );
+
+      await deposit(outsider, outsiderWallet, 100_000_000);
+      const outsiderLp = await getTokenBalance(outsiderWallet.lp);
+      await transfer(
+        provider.connection,
+        outsider,
+        outsiderWallet.lp,
+        treasuryWallet.lp,
+        outsider,
+        outsiderLp.toNumber()
+      );
+      await withdraw(treasury, treasuryWallet, outsiderLp.toNumber());
+      assert.equal((await protocolLiquidity()).protocolLpAmount.toNumber(), 0);
+    });
+  });
+
+  describe("Minimum Trade Amount In", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const outsider = Keypair.generate();
+    const BN = anchor.BN;
+
+    const setMinTradeAmountIn = (
+      minA: number,
+      minB: number,
+      authority: Keypair = payer
+    ) =>
+      program.methods
+        .setMinTradeAmountIn(new BN(minA), new BN(minB))
+        .accounts({ pool: seeded.pool, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const swap = (aToB: boolean, amountIn: number) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      await ensureSolBalance(outsider);
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+    });
+
+    it("Should let any amount through by default", async () => {
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(pool.minTradeAmountInA.toNumber(), 0);
+      assert.equal(pool.minTradeAmountInB.toNumber(), 0);
+      await swap(true, 1);
+      await swap(false, 1);
+    });
+
+    it("Should only let the authority set the minimums", async () => {
+      await expectError(
+        setMinTradeAmountIn(1_000, 5_000, outsider),
+        "Unauthorized"
+      );
+
+      const signature = await setMinTradeAmountIn(1_000, 5_000);
+      const event = await getEvent(signature, "minTradeAmountInSetEvent");
+      assert.equal(event.previousA.toNumber(), 0);
+      assert.equal(event.previousB.toNumber(), 0);
+      assert.equal(event.minTradeAmountInA.toNumber(), 1_000);
+      assert.equal(event.minTradeAmountInB.toNumber(), 5_000);
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(pool.minTradeAmountInA.toNumber(), 1_000);
+      assert.equal(pool.minTradeAmountInB.toNumber(), 5_000);
+    });
+
+    it("Should reject swaps below each side's minimum", async () => {
+      await setMinTradeAmountIn(1_000, 5_000);
+      const reserves = async () => {
+        const pool = await program.account.pool.fetch(seeded.pool);
+        return [pool.reserveA.toString(), pool.reserveB.toString()];
+      };
+      const before = await reserves();
+
+      await expectError(swap(true, 1), "TradeTooSmall");
+      await expectError(swap(true, 999), "TradeTooSmall");
+      // Token A's minimum doesn't apply to token B
+      await expectError(swap(false, 1_000), "TradeTooSmall");
+      await expectError(swap(false, 4_999), "TradeTooSmall");
+      assert.deepEqual(await reserves(), before);
+
+      await swap(true, 1_000);
+      await swap(true, 1_001);
+      await swap(false, 5_000);
+      await swap(false, 5_001);
+    });
+
+    it("Should let dust through again once reset", async () => {
+      await setMinTradeAmountIn(0, 0);
+      await swap(true, 1);
+      await swap(false, 1);
+    });
+  });
+
+  describe("Event Timestamps", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+
+    const liquidityAccounts = () => ({
+      pool: seeded.pool,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      userTokenA: seeded.payerTokenA,
+      userTokenB: seeded.payerTokenB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+      userLp: seeded.payerLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+    });
+
+    it("Should stamp every event with the time and slot", async () => {
+      const startTime = await getClusterTime();
+      const startSlot = await provider.connection.getSlot();
+
+      const { signature: created } = await createPoolAs(payer);
+      const added = await program.methods
+        .addLiquidity(
+          new BN(10_000_000),
+          new BN(10_000_000),
+          new BN(0),
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts(), owner: payer.publicKey })
+        .rpc();
+      const swapped = await program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const removed = await program.methods
+        .removeLiquidity(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts(), user: payer.publicKey })
+        .rpc();
+      const donated = await program.methods
+        .donate(new BN(1_000_000), new BN(1_000_000))
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          donor: payer.publicKey,
+          donorTokenA: seeded.payerTokenA,
+          donorTokenB: seeded.payerTokenB,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const synced = await syncReserves(
+        seeded.pool,
+        seeded.vaultA,
+        seeded.vaultB
+      );
+      const breakerSet = await program.methods
+        .setCircuitBreaker(0)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+      const statusSet = await program.methods
+        .setPoolStatus(0, false)
+        .accounts({ pool: seeded.pool, authority: payer.publicKey })
+        .rpc();
+
+      const events = [
+        await getEvent(created, "poolCreatedEvent"),
+        await getEvent(added, "liquidityAddedEvent"),
+        await getEvent(swapped, "swapExecutedEvent"),
+        await getEvent(removed, "liquidityRemovedEvent"),
+        await getEvent(donated, "donationEvent"),
+        await getEvent(synced, "reservesSyncedEvent"),
+        await getEvent(breakerSet, "circuitBreakerSetEvent"),
+        await getEvent(statusSet, "poolStatusChangedEvent"),
+      ];
+      const endTime = await getClusterTime();
+      const endSlot = await provider.connection.getSlot();
+
+      let previous = { timestamp: startTime - 2, slot: startSlot };
+      for (const event of events) {
+        const timestamp = event.timestamp.toNumber();
+        const slot = event.slot.toNumber();
+        // Block times and the Clock sysvar may disagree by a second or two
+        assert.isAtLeast(timestamp, previous.timestamp);
+        assert.isAtMost(timestamp, endTime + 2);
+        assert.isAtLeast(slot, previous.slot);
+        assert.isAtMost(slot, endSlot);
+        previous = { timestamp, slot };
+      }
+    });
+  });
+
+  describe("Pool Created Event", () => {
+    const creator = Keypair.generate();
+
+    before(async () => {
+      await ensureSolBalance(creator);
+    });
+
+    it("Should describe the new pool in integers", async () => {
+      const { pool, signature } = await createPoolAs(creator, 25);
+      const event = await getEvent(signature, "poolCreatedEvent");
+      const account = await program.account.pool.fetch(pool);
+
+      assert.isTrue(event.pool.equals(pool));
+      assert.isTrue(event.tokenAMint.equals(account.tokenAMint));
+      assert.isTrue(event.tokenBMint.equals(account.tokenBMint));
+      // Neither is discoverable from the logs otherwise
+      assert.isTrue(event.authority.equals(creator.publicKey));
+      assert.isTrue(event.lpMint.equals(account.lpMint));
+      assert.strictEqual(event.totalFeeBps, 25);
+      assert.equal(event.tradeStartTimestamp.toNumber(), 0);
+      assert.equal(event.creationFeeLamports.toNumber(), 0);
+      assert.equal(
+        event.timestamp.toString(),
+        account.createdAtTimestamp.toString()
+      );
+      assert.equal(event.slot.toString(), account.createdAtSlot.toString());
+      assert.notProperty(event, "fee");
+    });
+  });
+
+  describe("Swap Event Reserves", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+
+    const swap = async (aToB: boolean, amountIn: number) => {
+      const signature = await program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      return getEvent(signature, "swapExecutedEvent");
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 3_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+    });
+
+    for (const aToB of [true, false]) {
+      it(`Should report the reserves after a swap ${aToB ? "A to B" : "B to A"}`, async () => {
+        const event = await swap(aToB, 7_000_000);
+        const pool = await program.account.pool.fetch(seeded.pool);
+        const [reserveIn, reserveOut] = aToB
+          ? [pool.reserveA, pool.reserveB]
+          : [pool.reserveB, pool.reserveA];
+        assert.equal(event.reserveInAfter.toString(), reserveIn.toString());
+        assert.equal(event.reserveOutAfter.toString(), reserveOut.toString());
+
+        // The vaults hold the reserves plus the protocol fees owed out of them
+        const [vaultIn, vaultOut] = aToB
+          ? [seeded.vaultA, seeded.vaultB]
+          : [seeded.vaultB, seeded.vaultA];
+        const [owedIn, owedOut] = aToB
+          ? [pool.protocolFeesOwedA, pool.protocolFeesOwedB]
+          : [pool.protocolFeesOwedB, pool.protocolFeesOwedA];
+        assert.equal(
+          (await getTokenBalance(vaultIn)).toString(),
+          event.reserveInAfter.add(owedIn).toString()
+        );
+        assert.equal(
+          (await getTokenBalance(vaultOut)).toString(),
+          event.reserveOutAfter.add(owedOut).toString()
+        );
+
+        // Q64.64 of reserve out per reserve in
+        assert.equal(
+          event.spotPriceAfter.toString(),
+          reserveOut.shln(64).div(reserveIn).toString()
+        );
+      });
+    }
+  });
+
+  describe("Event CPI", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+    const [eventAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("__event_authority")],
+      program.programId
+    );
+
+    const liquidityAccounts = () => ({
+      pool: seeded.pool,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      userTokenA: seeded.payerTokenA,
+      userTokenB: seeded.payerTokenB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+      userLp: seeded.payerLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    // The program's calls into itself in a transaction, and its logs
+    const selfCpis = async (signature: string) => {
+      const transaction = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const keys = transaction.transaction.message.getAccountKeys({
+        accountKeysFromLookups: transaction.meta.loadedAddresses,
+      });
+      const cpis = transaction.meta.innerInstructions
+        .flatMap((inner) => inner.instructions)
+        .filter((ix) => keys.get(ix.programIdIndex).equals(program.programId))
+        .map((ix) => ({
+          accounts: ix.accounts.map((index) => keys.get(index)),
+          data: Buffer.from(anchor.utils.bytes.bs58.decode(ix.data)),
+        }));
+      return { cpis, logs: transaction.meta.logMessages };
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+    });
+
+    it("Should emit the core events as self-CPIs", async () => {
+      const { signature: created } = await createPoolAs(payer);
+      const added = await program.methods
+        .addLiquidity(
+          new BN(10_000_000),
+          new BN(10_000_000),
+          new BN(0),
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts(), owner: payer.publicKey })
+        .rpc();
+      const swapped = await program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const removed = await program.methods
+        .removeLiquidity(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts(), user: payer.publicKey })
+        .rpc();
+
+      for (const [signature, name] of [
+        [created, "poolCreatedEvent"],
+        [added, "liquidityAddedEvent"],
+        [swapped, "swapExecutedEvent"],
+        [removed, "liquidityRemovedEvent"],
+      ]) {
+        const { cpis, logs } = await selfCpis(signature);
+        assert.lengthOf(cpis, 1);
+        const [{ accounts, data }] = cpis;
+        assert.deepEqual(accounts, [eventAuthority]);
+        assert.isTrue(data.subarray(0, 8).equals(EVENT_IX_TAG));
+
+        const event = program.coder.events.decode(
+          data.subarray(8).toString("base64")
+        );
+        assert.equal(event.name, name);
+        assert.deepEqual(await getEvent(signature, name), event.data);
+
+        // Without the `log-events` feature the event isn't logged as well
+        const parser = new anchor.EventParser(program.programId, program.coder);
+        const logged = [...parser.parseLogs(logs)].map((e) => e.name);
+        assert.notInclude(logged, name);
+      }
+    });
+
+    it("Should reject an event CPI the program didn't sign", async () => {
+      const forged = new Transaction().add({
+        programId: program.programId,
+        keys: [{ pubkey: eventAuthority, isSigner: false, isWritable: false }],
+        data: EVENT_IX_TAG,
+      });
+      await expectError(provider.sendAndConfirm(forged), "ConstraintSigner");
+    });
+  });
+
+  describe("Swap Event Price Impact", () => {
+    const BN = anchor.BN;
+    const RESERVE = 1_000_000_000;
+
+    // Swaps `amountIn` A into a fresh fee-free pool holding `RESERVE` of
+    // each token, returning the event
+    const swapFresh = async (amountIn: number) => {
+      const seeded = await createSeededPool(RESERVE, RESERVE, 0);
+      await fundAccount(seeded.mintA, payer.publicKey, amountIn);
+      const signature = await program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      return getEvent(signature, "swapExecutedEvent");
+    };
+
+    // The program's formula, for SDK quotes to match
+    const expectedImpact = (event: Awaited<ReturnType<typeof swapFresh>>) => {
+      const spotValue = event.amountIn.mul(event.reserveOutBefore);
+      const executionValue = event.amountOut.mul(event.reserveInBefore);
+      return spotValue.sub(executionValue).muln(10_000).div(spotValue);
+    };
+
+    for (const [name, amountIn, impactBps] of [
+      ["a small trade", 10_000, 1],
+      ["a trade of 10% of the reserves", RESERVE / 10, 909],
+      ["a trade of 50% of the reserves", RESERVE / 2, 3_333],
+    ] as [string, number, number][]) {
+      it(`Should report the price impact of ${name}`, async () => {
+        const event = await swapFresh(amountIn);
+        assert.equal(event.reserveInBefore.toNumber(), RESERVE);
+        assert.equal(event.reserveOutBefore.toNumber(), RESERVE);
+        assert.equal(
+          event.reserveInAfter.toNumber(),
+          RESERVE + event.amountIn.toNumber()
+        );
+        assert.equal(
+          event.reserveOutAfter.toNumber(),
+          RESERVE - event.amountOut.toNumber()
+        );
+
+        // Without a fee only the curve moves the price: x / (x + reserve)
+        assert.equal(event.priceImpactBps, expectedImpact(event).toNumber());
+        assert.equal(event.priceImpactBps, impactBps);
+      });
+    }
+  });
+
+  describe("Initial Price Event", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+
+    const liquidityAccounts = () => ({
+      pool: seeded.pool,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      userTokenA: seeded.payerTokenA,
+      userTokenB: seeded.payerTokenB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+      userLp: seeded.payerLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    const addLiquidity = (amountA: number, amountB: number) =>
+      program.methods
+        .addLiquidity(new BN(amountA), new BN(amountB), new BN(0), new BN(0))
+        .accounts({ ...liquidityAccounts(), owner: payer.publicKey })
+        .rpc();
+
+    const eventNames = async (signature: string) =>
+      (await getEvents(signature)).map((event) => event.name);
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 4_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 400_000_000);
+    });
+
+    it("Should mark the deposit that priced the pool", async () => {
+      const event = await getEvent(
+        seeded.seedSignature,
+        "initialPriceSetEvent"
+      );
+      assert.equal(event.pool.toBase58(), seeded.pool.toBase58());
+      assert.equal(event.depositor.toBase58(), payer.publicKey.toBase58());
+      assert.equal(event.amountA.toString(), "1000000000");
+      assert.equal(event.amountB.toString(), "4000000000");
+      const added = await getEvent(
+        seeded.seedSignature,
+        "liquidityAddedEvent"
+      );
+      assert.equal(event.lpMinted.toString(), added.lpTokensMinted.toString());
+      assert.isAbove(event.timestamp.toNumber(), 0);
+      assert.equal(event.slot.toString(), added.slot.toString());
+
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.isTrue(pool.initialPriceSet);
+    });
+
+    it("Should not fire for later deposits", async () => {
+      const names = await eventNames(await addLiquidity(1_000_000, 4_000_000));
+      assert.include(names, "liquidityAddedEvent");
+      assert.notInclude(names, "initialPriceSetEvent");
+    });
+
+    it("Should not fire again when a drained pool is re-seeded", async () => {
+      await program.methods
+        .removeLiquidity(
+          await getTokenBalance(seeded.payerLp),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts(), user: payer.publicKey })
+        .rpc();
+      await program.methods
+        .resetPool()
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          authority: payer.publicKey,
+          lpMint: seeded.lpMint,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          toTokenA: seeded.payerTokenA,
+          toTokenB: seeded.payerTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // The re-seed takes the initial path, but the pool was priced before
+      const signature = await addLiquidity(1_000_000, 1_000_000);
+      const added = await getEvent(signature, "liquidityAddedEvent");
+      // `INITIAL_LP_TOKENS`
+      assert.equal(added.lpTokensMinted.toString(), "1000000");
+      assert.notInclude(await eventNames(signature), "initialPriceSetEvent");
+    });
+
+    it("Should fire from initialize_pool_with_liquidity", async () => {
+      const newMint = () =>
+        createMint(provider.connection, payer, payer.publicKey, null, 9);
+      const mintA = await newMint();
+      const mintB = await newMint();
+      const lpMint = await newMint();
+      const pool = findPoolAddress(mintA, mintB, 0);
+      const newVault = (mint: PublicKey) =>
+        createAccount(
+          provider.connection,
+          payer,
+          mint,
+          pool,
+          Keypair.generate()
+        );
+      const vaultA = await newVault(mintA);
+      const vaultB = await newVault(mintB);
+      await setAuthority(
+        provider.connection,
+        payer,
+        lpMint,
+        payer.publicKey,
+        AuthorityType.MintTokens,
+        pool
+      );
+      const userTokenA = await fundAccount(mintA, payer.publicKey, 2_000_000);
+      const userTokenB = await fundAccount(mintB, payer.publicKey, 3_000_000);
+
+      const signature = await program.methods
+        .initializePoolWithLiquidity(
+          30,
+          new BN(2_000_000),
+          new BN(3_000_000),
+          0,
+          new BN(0),
+          noTradeLimits
+        )
+        .accounts({
+          pool,
+          tokenAMint: mintA,
+          tokenBMint: mintB,
+          tokenAAccount: vaultA,
+          tokenBAccount: vaultB,
+          lpMint,
+          authority: payer.publicKey,
+          registryEntry: await nextRegistryEntry(),
+          userTokenA,
+          userTokenB,
+          userLp: await fundAccount(lpMint, payer.publicKey, 0),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+
+      const names = await eventNames(signature);
+      assert.equal(
+        names.filter((name) => name === "initialPriceSetEvent").length,
+        1
+      );
+      const event = await getEvent(signature, "initialPriceSetEvent");
+      assert.equal(event.amountA.toString(), "2000000");
+      assert.equal(event.amountB.toString(), "3000000");
+    });
+  });
+
+  describe("Pool Sequence", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+
+    const liquidityAccounts = () => ({
+      pool: seeded.pool,
+      tokenAMint: seeded.mintA,
+      tokenBMint: seeded.mintB,
+      userTokenA: seeded.payerTokenA,
+      userTokenB: seeded.payerTokenB,
+      poolTokenA: seeded.vaultA,
+      poolTokenB: seeded.vaultB,
+      lpMint: seeded.lpMint,
+      userLp: seeded.payerLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    const swap = (amountIn: number, aToB: boolean) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const addLiquidity = (amount: number) =>
+      program.methods
+        .addLiquidity(new BN(amount), new BN(amount), new BN(0), new BN(0))
+        .accounts({ ...liquidityAccounts(), owner: payer.publicKey })
+        .rpc();
+
+    const removeLiquidity = (lpAmount: number) =>
+      program.methods
+        .removeLiquidity(
+          new BN(lpAmount),
+          new BN(0),
+          new BN(0),
+          false,
+          new BN(0)
+        )
+        .accounts({ ...liquidityAccounts(), user: payer.publicKey })
+        .rpc();
+
+    const sequence = async () =>
+      (await program.account.pool.fetch(seeded.pool)).sequence.toNumber();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+    });
+
+    it("Should stamp the first deposit's events with one sequence", async () => {
+      const events = await getEvents(seeded.seedSignature);
+      const priced = events.find((e) => e.name === "initialPriceSetEvent");
+      const added = events.find((e) => e.name === "liquidityAddedEvent");
+      assert.equal(priced.data.sequence.toNumber(), await sequence());
+      assert.equal(added.data.sequence.toNumber(), await sequence());
+    });
+
+    it("Should advance by one across swaps and liquidity changes", async () => {
+      for (const [step, name] of [
+        [() => swap(1_000_000, true), "swapExecutedEvent"],
+        [() => addLiquidity(2_000_000), "liquidityAddedEvent"],
+        [() => swap(500_000, false), "swapExecutedEvent"],
+        [() => removeLiquidity(1_000_000), "liquidityRemovedEvent"],
+        [() => swap(250_000, true), "swapExecutedEvent"],
+      ] as [() => Promise<string>, string][]) {
+        const before = await sequence();
+        const event = await getEvent(await step(), name);
+        assert.equal(event.sequence.toNumber(), before + 1, name);
+        assert.equal(await sequence(), before + 1, name);
+      }
+    });
+
+    it("Should not advance on a failed instruction", async () => {
+      const before = await sequence();
+      await expectError(
+        program.methods
+          .swap(new BN(0), new BN(0), new BN(0), new BN(0), new BN(0), null)
+          .accounts({
+            pool: seeded.pool,
+            owner: payer.publicKey,
+            tokenInMint: seeded.mintA,
+            tokenOutMint: seeded.mintB,
+            userTokenIn: seeded.payerTokenA,
+            userTokenOut: seeded.payerTokenB,
+            poolTokenIn: seeded.vaultA,
+            poolTokenOut: seeded.vaultB,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc(),
+        "InvalidAmount"
+      );
+      assert.equal(await sequence(), before);
+    });
+  });
+
+  describe("Swap Event Invariant", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+
+    const swap = (amountIn: number, aToB: boolean) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    // A fixed-seed LCG, so a failing sequence can be replayed
+    let state = 0x2545f491;
+    const random = () => {
+      state = (Math.imul(state, 1_103_515_245) + 12_345) >>> 0;
+      return state / 2 ** 32;
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 3_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 3_000_000_000);
+    });
+
+    it("Should never shrink k across a randomized series of swaps", async () => {
+      let previousK: anchor.BN | undefined;
+      for (let i = 0; i < 12; i++) {
+        const aToB = random() < 0.5;
+        const amountIn = 1_000 + Math.floor(random() * 50_000_000);
+        const event = await getEvent(
+          await swap(amountIn, aToB),
+          "swapExecutedEvent"
+        );
+
+        // Both match the reserves the event reports
+        assert.equal(
+          event.kBefore.toString(),
+          event.reserveInBefore.mul(event.reserveOutBefore).toString()
+        );
+        assert.equal(
+          event.kAfter.toString(),
+          event.reserveInAfter.mul(event.reserveOutAfter).toString()
+        );
+        assert.isTrue(event.kAfter.gte(event.kBefore), `swap ${i}`);
+        if (previousK) {
+          assert.equal(event.kBefore.toString(), previousK.toString());
+        }
+        previousK = event.kAfter;
+      }
+
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        pool.reserveA.mul(pool.reserveB).toString(),
+        previousK.toString()
+      );
+    });
+  });
+
+  describe("Protocol Fee Reconciliation", () => {
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const BN = anchor.BN;
+    let accruedA = new BN(0);
+    let accruedB = new BN(0);
+    let collectedA = new BN(0);
+    let collectedB = new BN(0);
+
+    // Swaps and books the transaction's accruals
+    const swap = async (amountIn: number, aToB: boolean) => {
+      const signature = await program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const events = await getEvents(signature);
+      const accrued = events.filter(
+        (e) => e.name === "protocolFeesAccruedEvent"
+      );
+      assert.lengthOf(accrued, 1);
+      const event = accrued[0].data;
+      const swapped = events.find((e) => e.name === "swapExecutedEvent").data;
+      assert.isTrue(event.mint.equals(aToB ? seeded.mintA : seeded.mintB));
+      assert.equal(event.amount.toString(), swapped.protocolFee.toString());
+      assert.equal(event.sequence.toString(), swapped.sequence.toString());
+      assert.equal(event.slot.toString(), swapped.slot.toString());
+      if (aToB) {
+        accruedA = accruedA.add(event.amount);
+        assert.equal(
+          event.totalOwed.toString(),
+          accruedA.sub(collectedA).toString()
+        );
+      } else {
+        accruedB = accruedB.add(event.amount);
+        assert.equal(
+          event.totalOwed.toString(),
+          accruedB.sub(collectedB).toString()
+        );
+      }
+    };
+
+    const collect = async () => {
+      const signature = await program.methods
+        .collectProtocolFees()
+        .accounts({
+          tokenAMint: seeded.mintA,
+          tokenBMint: seeded.mintB,
+          pool: seeded.pool,
+          feeRecipient: payer.publicKey,
+          poolTokenA: seeded.vaultA,
+          poolTokenB: seeded.vaultB,
+          recipientTokenA: seeded.payerTokenA,
+          recipientTokenB: seeded.payerTokenB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const events = await getEvents(signature);
+      const event = events.find(
+        (e) => e.name === "protocolFeesCollectedEvent"
+      ).data;
+      // The per-mint events add up to the two-sided one
+      const perMint = events
+        .filter((e) => e.name === "protocolFeesCollectedInMintEvent")
+        .map((e) => e.data);
+      const collectedIn = (mint: PublicKey) =>
+        perMint
+          .filter((e) => e.mint.equals(mint))
+          .reduce((sum, e) => sum.add(e.amount), new BN(0));
+      for (const e of perMint) {
+        assert.isTrue(e.recipient.equals(payer.publicKey));
+        assert.equal(e.sequence.toString(), event.sequence.toString());
+      }
+      const inMintA = collectedIn(seeded.mintA);
+      const inMintB = collectedIn(seeded.mintB);
+      assert.equal(inMintA.toString(), event.amountA.toString());
+      assert.equal(inMintB.toString(), event.amountB.toString());
+      collectedA = collectedA.add(inMintA);
+      collectedB = collectedB.add(inMintB);
+    };
+
+    // Everything accrued has been collected or is still owed
+    const expectReconciled = async () => {
+      const pool = await program.account.pool.fetch(seeded.pool);
+      assert.equal(
+        accruedA.toString(),
+        collectedA.add(pool.protocolFeesOwedA).toString()
+      );
+      assert.equal(
+        accruedB.toString(),
+        collectedB.add(pool.protocolFeesOwedB).toString()
+      );
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+    });
+
+    it("Should reconcile accruals with collections and owed fees", async () => {
+      for (const step of [
+        () => swap(3_000_000, true),
+        () => swap(7_000_000, false),
+        () => swap(1_000_000, true),
+        collect,
+        () => swap(5_000_000, true),
+        () => swap(2_500_000, false),
+        collect,
+        () => swap(4_000_000, false),
+      ]) {
+        await step();
+        await expectReconciled();
+      }
+      assert.isTrue(accruedA.gtn(0) && accruedB.gtn(0));
+      assert.isTrue(collectedA.gtn(0) && collectedB.gtn(0));
+    });
+  });
+
+  describe("Event Versioning", () => {
+    // `events::SCHEMA_VERSION`
+    const SCHEMA_VERSION = 1;
+
+    it("Should lead every event with the schema version", async () => {
+      const seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 1_000_000);
+      const signature = await program.methods
+        .swap(
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      const events = [
+        ...(await getEvents(seeded.seedSignature)),
+        ...(await getEvents(signature)),
+      ];
+      for (const name of [
+        "initialPriceSetEvent",
+        "liquidityAddedEvent",
+        "protocolFeesAccruedEvent",
+        "swapExecutedEvent",
+      ]) {
+        assert.include(
+          events.map((e) => e.name),
+          name
+        );
+      }
+      for (const event of events) {
+        assert.equal(event.data.version, SCHEMA_VERSION, event.name);
+      }
+    });
+  });
+
+  describe("Token-2022 Pools", () => {
+    const BN = anchor.BN;
+    let mintA: PublicKey;
+    let mintB: PublicKey;
+    let lpMint: PublicKey;
+    let pool: PublicKey;
+    let vaultA: PublicKey;
+    let vaultB: PublicKey;
+    let payerTokenA: PublicKey;
+    let payerTokenB: PublicKey;
+    let payerLp: PublicKey;
+
+    // Token B is a Token-2022 mint, so its accounts need that program
+    const fundToken2022 = async (owner: PublicKey, amount: number) => {
+      const account = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        mintB,
+        owner,
+        false,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      if (amount > 0) {
+        await mintTo(
+          provider.connection,
+          payer,
+          mintB,
+          account.address,
+          payer,
+          amount,
+          [],
+          undefined,
+          TOKEN_2022_PROGRAM_ID
+        );
+      }
+      return account.address;
+    };
+
+    const swap = (
+      amountIn: number,
+      aToB: boolean,
+      tokenProgramB: PublicKey | null = TOKEN_2022_PROGRAM_ID
+    ) =>
+      program.methods
+        .swap(
+          new BN(amountIn),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? mintA : mintB,
+          tokenOutMint: aToB ? mintB : mintA,
+          userTokenIn: aToB ? payerTokenA : payerTokenB,
+          userTokenOut: aToB ? payerTokenB : payerTokenA,
+          poolTokenIn: aToB ? vaultA : vaultB,
+          poolTokenOut: aToB ? vaultB : vaultA,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramB,
+        })
+        .rpc();
+
+    before(async () => {
+      mintA = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      mintB = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      lpMint = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      pool = findPoolAddress(mintA, mintB, 0);
+      vaultA = await createAccount(
+        provider.connection,
+        payer,
+        mintA,
+        pool,
+        Keypair.generate()
+      );
+      vaultB = await createAccount(
+        provider.connection,
+        payer,
+        mintB,
+        pool,
+        Keypair.generate(),
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      payerTokenA = await fundAccount(mintA, payer.publicKey, 2_000_000_000);
+      payerTokenB = await fundToken2022(payer.publicKey, 2_000_000_000);
+      payerLp = await fundAccount(lpMint, payer.publicKey, 0);
+    });
+
+    it("Should create and seed a pool pairing a classic and a Token-2022 mint", async () => {
+      await program.methods
+        .initializePool(30, 0, new BN(0), noTradeLimits)
+        .accounts({
+          pool,
+          tokenAMint: mintA,
+          tokenBMint: mintB,
+          tokenAAccount: vaultA,
+          tokenBAccount: vaultB,
+          lpMint,
+          authority: payer.publicKey,
+          registryEntry: await nextRegistryEntry(),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+      await setAuthority(
+        provider.connection,
+        payer,
+        lpMint,
+        payer.publicKey,
+        AuthorityType.MintTokens,
+        pool
+      );
+
+      await program.methods
+        .addLiquidity(
+          new BN(1_000_000_000),
+          new BN(1_000_000_000),
+          new BN(0),
+          new BN(0)
+        )
+        .accounts({
+          pool,
+          owner: payer.publicKey,
+          tokenAMint: mintA,
+          tokenBMint: mintB,
+          userTokenA: payerTokenA,
+          userTokenB: payerTokenB,
+          poolTokenA: vaultA,
+          poolTokenB: vaultB,
+          lpMint,
+          userLp: payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramB: TOKEN_2022_PROGRAM_ID,
+        })
+        .rpc();
+
+      assert.equal((await getTokenBalance(vaultA)).toString(), "1000000000");
+      assert.equal((await getTokenBalance(vaultB)).toString(), "1000000000");
+      assert.isTrue((await getTokenBalance(payerLp)).gtn(0));
+    });
+
+    it("Should swap both ways across the two token programs", async () => {
+      const balanceB = await getTokenBalance(payerTokenB);
+      await swap(10_000_000, true);
+      const received = (await getTokenBalance(payerTokenB)).sub(balanceB);
+      assert.isTrue(received.gtn(0));
+
+      const balanceA = await getTokenBalance(payerTokenA);
+      await swap(received.toNumber(), false);
+      assert.isTrue((await getTokenBalance(payerTokenA)).gt(balanceA));
+
+      // The tracked reserves still match the vaults
+      const poolAccount = await program.account.pool.fetch(pool);
+      assert.equal(
+        poolAccount.reserveA.toString(),
+        (await getTokenBalance(vaultA))
+          .sub(poolAccount.protocolFeesOwedA)
+          .toString()
+      );
+      assert.equal(
+        poolAccount.reserveB.toString(),
+        (await getTokenBalance(vaultB))
+          .sub(poolAccount.protocolFeesOwedB)
+          .toString()
+      );
+    });
+
+    it("Should reject a swap without token B's program", async () => {
+      await expectError(swap(1_000_000, true, null), "MissingTokenProgram");
+    });
+
+    it("Should withdraw both tokens through their own programs", async () => {
+      const lpAmount = (await getTokenBalance(payerLp)).divn(2);
+      const balanceA = await getTokenBalance(payerTokenA);
+      const balanceB = await getTokenBalance(payerTokenB);
+
+      await program.methods
+        .removeLiquidity(lpAmount, new BN(0), new BN(0), false, new BN(0))
+        .accounts({
+          pool,
+          user: payer.publicKey,
+          tokenAMint: mintA,
+          tokenBMint: mintB,
+          userTokenA: payerTokenA,
+          userTokenB: payerTokenB,
+          poolTokenA: vaultA,
+          poolTokenB: vaultB,
+          lpMint,
+          userLp: payerLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramB: TOKEN_2022_PROGRAM_ID,
+        })
+        .rpc();
+
+      assert.isTrue((await getTokenBalance(payerTokenA)).gt(balanceA));
+      assert.isTrue((await getTokenBalance(payerTokenB)).gt(balanceB));
+    });
+
+    it("Should reject a mint with a transfer fee", async () => {
+      const feeMint = Keypair.generate();
+      const space = getMintLen([ExtensionType.TransferFeeConfig]);
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.createAccount({
+            fromPubkey: payer.publicKey,
+            newAccountPubkey: feeMint.publicKey,
+            space,
+            lamports:
+              await provider.connection.getMinimumBalanceForRentExemption(
+                space
+              ),
+            programId: TOKEN_2022_PROGRAM_ID,
+          }),
+          createInitializeTransferFeeConfigInstruction(
+            feeMint.publicKey,
+            payer.publicKey,
+            payer.publicKey,
+            100,
+            BigInt(1_000_000),
+            TOKEN_2022_PROGRAM_ID
+          ),
+          createInitializeMintInstruction(
+            feeMint.publicKey,
+            9,
+            payer.publicKey,
+            null,
+            TOKEN_2022_PROGRAM_ID
+          )
+        ),
+        [feeMint]
+      );
+      const feePool = findPoolAddress(mintA, feeMint.publicKey, 0);
+      const feeVaultA = await createAccount(
+        provider.connection,
+        payer,
+        mintA,
+        feePool,
+        Keypair.generate()
+      );
+      const feeVaultB = await createAccount(
+        provider.connection,
+        payer,
+        feeMint.publicKey,
+        feePool,
+        Keypair.generate(),
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      const feeLpMint = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+
+      await expectError(
+        program.methods
+          .initializePool(30, 0, new BN(0), noTradeLimits)
+          .accounts({
+            pool: feePool,
+            tokenAMint: mintA,
+            tokenBMint: feeMint.publicKey,
+            tokenAAccount: feeVaultA,
+            tokenBAccount: feeVaultB,
+            lpMint: feeLpMint,
+            authority: payer.publicKey,
+            registryEntry: await nextRegistryEntry(),
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+            rent: SYSVAR_RENT_PUBKEY,
+          })
+          .rpc(),
+        "UnsupportedMintExtension"
+      );
+    });
+  });
+
+  describe("LP Token Metadata", () => {
+    const BN = anchor.BN;
+    const TOKEN_METADATA_PROGRAM_ID = new PublicKey(
+      "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+    );
+    const lpMetadataUri = "https://example.com/solamm-lp.json";
+
+    const findLpMetadata = (lpMint: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("metadata"),
+          TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+          lpMint.toBuffer(),
+        ],
+        TOKEN_METADATA_PROGRAM_ID
+      )[0];
+
+    // Reads the update authority, mint, name, symbol and URI off a Token
+    // Metadata account, dropping the padding the program writes
+    const readMetadata = (data: Buffer) => {
+      let offset = 1;
+      const readKey = () => {
+        const key = new PublicKey(data.subarray(offset, offset + 32));
+        offset += 32;
+        return key;
+      };
+      const readString = () => {
+        const length = data.readUInt32LE(offset);
+        const value = data
+          .subarray(offset + 4, offset + 4 + length)
+          .toString("utf8")
+          .replace(/\0/g, "");
+        offset += 4 + length;
+        return value;
+      };
+      return {
+        updateAuthority: readKey(),
+        mint: readKey(),
+        name: readString(),
+        symbol: readString(),
+        uri: readString(),
+      };
+    };
+
+    // Creates a Token-2022 LP mint for `pool` whose metadata pointer points
+    // at `metadataAddress`, or the mint itself
+    const createToken2022LpMint = async (
+      pool: PublicKey,
+      metadataAddress?: PublicKey
+    ) => {
+      const lpMint = Keypair.generate();
+      const space = getMintLen([ExtensionType.MetadataPointer]);
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.createAccount({
+            fromPubkey: payer.publicKey,
+            newAccountPubkey: lpMint.publicKey,
+            space,
+            lamports:
+              await provider.connection.getMinimumBalanceForRentExemption(
+                space
+              ),
+            programId: TOKEN_2022_PROGRAM_ID,
+          }),
+          createInitializeMetadataPointerInstruction(
+            lpMint.publicKey,
+            pool,
+            metadataAddress ?? lpMint.publicKey,
+            TOKEN_2022_PROGRAM_ID
+          ),
+          createInitializeMintInstruction(
+            lpMint.publicKey,
+            9,
+            pool,
+            null,
+            TOKEN_2022_PROGRAM_ID
+          )
+        ),
+        [lpMint]
+      );
+      return lpMint.publicKey;
+    };
+
+    // Creates mints and vaults for a new pool, with the LP mint's authority
+    // given to the pool up front so it can sign for the metadata
+    const preparePool = async (
+      createLpMint = (pool: PublicKey) =>
+        createMint(provider.connection, payer, pool, null, 9)
+    ) => {
+      const mintA = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const mintB = await createMint(
+        provider.connection,
+        payer,
+        payer.publicKey,
+        null,
+        9
+      );
+      const pool = findPoolAddress(mintA, mintB, 0);
+      const lpMint = await createLpMint(pool);
+      const vaultA = await createAccount(
+        provider.connection,
+        payer,
+        mintA,
+        pool,
+        Keypair.generate()
+      );
+      const vaultB = await createAccount(
+        provider.connection,
+        payer,
+        mintB,
+        pool,
+        Keypair.generate()
+      );
+      return { mintA, mintB, pool, lpMint, vaultA, vaultB };
+    };
+
+    const initializePool = async (
+      prepared: Awaited<ReturnType<typeof preparePool>>,
+      tokenProgramB: PublicKey | null = null
+    ) =>
+      program.methods
+        .initializePool(30, 0, new BN(0), noTradeLimits)
+        .accounts({
+          pool: prepared.pool,
+          tokenAMint: prepared.mintA,
+          tokenBMint: prepared.mintB,
+          tokenAAccount: prepared.vaultA,
+          tokenBAccount: prepared.vaultB,
+          lpMint: prepared.lpMint,
+          authority: payer.publicKey,
+          registryEntry: await nextRegistryEntry(),
+          lpMetadata: findLpMetadata(prepared.lpMint),
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenProgramB,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .rpc();
+
+    before(async () => {
+      await ensureConfig();
+      await updateConfig({ lpMetadataUri });
+    });
+
+    it("Should create the LP mint's metadata at pool initialization", async () => {
+      const prepared = await preparePool();
+      await initializePool(prepared);
+
+      const account = await provider.connection.getAccountInfo(
+        findLpMetadata(prepared.lpMint)
+      );
+      assert.isNotNull(account);
+      assert.isTrue(account.owner.equals(TOKEN_METADATA_PROGRAM_ID));
+      const metadata = readMetadata(account.data);
+      assert.isTrue(metadata.updateAuthority.equals(prepared.pool));
+      assert.isTrue(metadata.mint.equals(prepared.lpMint));
+      assert.equal(
+        metadata.name,
+        `SOLAMM LP ${prepared.mintA.toBase58().slice(0, 4)}/${prepared.mintB
+          .toBase58()
+          .slice(0, 4)}`
+      );
+      assert.equal(metadata.symbol, "SOLAMM-LP");
+      assert.equal(metadata.uri, lpMetadataUri);
+    });
+
+    it("Should need the pool as the LP mint's authority", async () => {
+      const prepared = await preparePool(() =>
+        createMint(provider.connection, payer, payer.publicKey, null, 9)
+      );
+      await expectError(initializePool(prepared), "InvalidLpMintAuthority");
+    });
+
+    it("Should write a Token-2022 LP mint's metadata into the mint itself", async () => {
+      const prepared = await preparePool((pool) =>
+        createToken2022LpMint(pool)
+      );
+      await initializePool(prepared, TOKEN_2022_PROGRAM_ID);
+
+      // No Token Metadata account, the mint holds its own
+      assert.isNull(
+        await provider.connection.getAccountInfo(
+          findLpMetadata(prepared.lpMint)
+        )
+      );
+      const metadata = await getTokenMetadata(
+        provider.connection,
+        prepared.lpMint,
+        "confirmed",
+        TOKEN_2022_PROGRAM_ID
+      );
+      assert.isTrue(metadata.updateAuthority.equals(prepared.pool));
+      assert.isTrue(metadata.mint.equals(prepared.lpMint));
+      assert.equal(
+        metadata.name,
+        `SOLAMM LP ${prepared.mintA.toBase58().slice(0, 4)}/${prepared.mintB
+          .toBase58()
+          .slice(0, 4)}`
+      );
+      assert.equal(metadata.symbol, "SOLAMM-LP");
+      assert.equal(metadata.uri, lpMetadataUri);
+      assert.lengthOf(metadata.additionalMetadata, 0);
+    });
+
+    it("Should reject a metadata pointer to another account", async () => {
+      const prepared = await preparePool((pool) =>
+        createToken2022LpMint(pool, Keypair.generate().publicKey)
+      );
+      await expectError(
+        initializePool(prepared, TOKEN_2022_PROGRAM_ID),
+        "InvalidMetadataPointer"
+      );
+    });
+
+    it("Should reject an over-long metadata URI", async () => {
+      await expectError(
+        updateConfig({ lpMetadataUri: "x".repeat(201) }),
+        "MetadataTooLong"
+      );
+    });
+  });
+
+  describe("Swap via CPI", () => {
+    const BN = anchor.BN;
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    const user = Keypair.generate();
+    let userTokenA: PublicKey;
+    let userTokenB: PublicKey;
+
+    const [eventAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from("__event_authority")],
+      program.programId
+    );
+    const [receipt] = PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt"), user.publicKey.toBuffer()],
+      cpiProgram.programId
+    );
+
+    const swapThrough = (amountIn: number, minAmountOut: number) =>
+      cpiProgram.methods
+        .swapThrough(new BN(amountIn), new BN(minAmountOut))
+        .accounts({
+          user: user.publicKey,
+          receipt,
+          pool: seeded.pool,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          config: configAddress,
+          eventAuthority,
+          swapProgram: program.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await ensureSolBalance(user);
+      userTokenA = await fundAccount(seeded.mintA, user.publicKey, 100_000_000);
+      userTokenB = await fundAccount(seeded.mintB, user.publicKey, 0);
+    });
+
+    it("Should return the executed trade to a program swapping by CPI", async () => {
+      const balanceA = await getTokenBalance(userTokenA);
+      const balanceB = await getTokenBalance(userTokenB);
+
+      const event = await getEvent(
+        await swapThrough(10_000_000, 1),
+        "swapExecutedEvent"
+      );
+
+      const { lastSwap, swapCount } = await cpiProgram.account.swapReceipt.fetch(
+        receipt
+      );
+      assert.equal(swapCount.toNumber(), 1);
+      assert.equal(
+        lastSwap.amountOut.toString(),
+        (await getTokenBalance(userTokenB)).sub(balanceB).toString()
+      );
+      assert.equal(
+        lastSwap.amountIn.toString(),
+        balanceA.sub(await getTokenBalance(userTokenA)).toString()
+      );
+      assert.equal(lastSwap.amountOut.toString(), event.amountOut.toString());
+      assert.equal(lastSwap.fee.toString(), event.fee.toString());
+    });
+
+    it("Should fail the caller when the swap fails", async () => {
+      await expectError(
+        swapThrough(1_000_000, 1_000_000_000),
+        "SlippageExceeded"
+      );
+      const { swapCount } = await cpiProgram.account.swapReceipt.fetch(receipt);
+      assert.equal(swapCount.toNumber(), 1);
+    });
+  });
+
+  describe("Swap Memos", () => {
+    const BN = anchor.BN;
+    const MEMO_PROGRAM_ID = new PublicKey(
+      "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"
+    );
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+
+    const swap = (memo: string | null, memoProgram: PublicKey | null) =>
+      program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          memo
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          memoProgram,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    // The memos a transaction wrote through the memo program
+    const getMemos = async (signature: string) => {
+      const transaction = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const keys = transaction.transaction.message.getAccountKeys({
+        accountKeysFromLookups: transaction.meta.loadedAddresses,
+      });
+      return (transaction.meta.innerInstructions ?? [])
+        .flatMap((inner) => inner.instructions)
+        .filter((ix) => keys.get(ix.programIdIndex).equals(MEMO_PROGRAM_ID))
+        .map((ix) =>
+          Buffer.from(anchor.utils.bytes.bs58.decode(ix.data)).toString("utf8")
+        );
+    };
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 1_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+    });
+
+    it("Should write the memo and carry it in the swap event", async () => {
+      const memo = "INV-2024-0042";
+      const signature = await swap(memo, MEMO_PROGRAM_ID);
+
+      assert.deepEqual(await getMemos(signature), [memo]);
+      const event = await getEvent(signature, "swapExecutedEvent");
+      assert.equal(event.memo, memo);
+    });
+
+    it("Should leave a swap without a memo unchanged", async () => {
+      const signature = await swap(null, null);
+
+      assert.deepEqual(await getMemos(signature), []);
+      const event = await getEvent(signature, "swapExecutedEvent");
+      assert.isNull(event.memo);
+      assert.isTrue(event.amountOut.gtn(0));
+    });
+
+    it("Should accept a memo of exactly 64 bytes", async () => {
+      const memo = "m".repeat(64);
+      const signature = await swap(memo, MEMO_PROGRAM_ID);
+      assert.deepEqual(await getMemos(signature), [memo]);
+    });
+
+    it("Should reject an over-long memo", async () => {
+      await expectError(swap("m".repeat(65), MEMO_PROGRAM_ID), "MemoTooLong");
+    });
+
+    it("Should reject a memo without the memo program", async () => {
+      await expectError(swap("INV-1", null), "MissingMemoProgram");
+    });
+  });
+
+  describe("Oracle Price Guard", () => {
+    const BN = anchor.BN;
+    // Price feeds loaded from tests/fixtures: token A at $2.00 and token B
+    // at $1.00, give or take 0.1%, published 2023-11-14
+    const pyth = {
+      kind: { pyth: {} },
+      feedA: new PublicKey("7SL7aRZ45S9EwrAP8TeKNCvoNRrtZRadVY4Su5jQk6FA"),
+      feedB: new PublicKey("cpFBVMeARPfkq7xUEEDTwm4x3s4Nfj5mCFWASFWuWee"),
+    };
+    const switchboard = {
+      kind: { switchboardOnDemand: {} },
+      feedA: new PublicKey("HwTXFjUY34ssmzVCtYRwnom97BKxqJhqt6556SPX3Erq"),
+      feedB: new PublicKey("9AsLBN25PStdD61aUMTo5Ny2AL5SzNWWodtvuCUtVT8Q"),
+    };
+    // Long enough for the fixtures to count as fresh
+    const noStalenessLimit = 4_294_967_295;
+    let inBand: Awaited<ReturnType<typeof createSeededPool>>;
+    let outOfBand: Awaited<ReturnType<typeof createSeededPool>>;
+
+    const findOracleGuard = (pool: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("oracle_guard"), pool.toBuffer()],
+        program.programId
+      )[0];
+
+    const setOracleGuard = (
+      pool: PublicKey,
+      oracle: typeof pyth,
+      maxDeviationBps: number,
+      maxStalenessSeconds: number
+    ) =>
+      program.methods
+        .setOracleGuard(oracle.kind, maxDeviationBps, maxStalenessSeconds)
+        .accounts({
+          pool,
+          authority: payer.publicKey,
+          priceFeedA: oracle.feedA,
+          priceFeedB: oracle.feedB,
+        })
+        .rpc();
+
+    // Swaps with the oracle's guard and feeds, or with none of them
+    const swap = (
+      seeded: Awaited<ReturnType<typeof createSeededPool>>,
+      aToB: boolean,
+      oracle: typeof pyth | null
+    ) =>
+      program.methods
+        .swap(
+          new BN(1_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: aToB ? seeded.mintA : seeded.mintB,
+          tokenOutMint: aToB ? seeded.mintB : seeded.mintA,
+          userTokenIn: aToB ? seeded.payerTokenA : seeded.payerTokenB,
+          userTokenOut: aToB ? seeded.payerTokenB : seeded.payerTokenA,
+          poolTokenIn: aToB ? seeded.vaultA : seeded.vaultB,
+          poolTokenOut: aToB ? seeded.vaultB : seeded.vaultA,
+          oracleGuard: oracle ? findOracleGuard(seeded.pool) : null,
+          priceFeedA: oracle?.feedA ?? null,
+          priceFeedB: oracle?.feedB ?? null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    before(async () => {
+      // Priced like the feeds, one A for two B, and at one for one
+      inBand = await createSeededPool(1_000_000_000, 2_000_000_000);
+      outOfBand = await createSeededPool(1_000_000_000, 1_000_000_000);
+      for (const seeded of [inBand, outOfBand]) {
+        await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+        await fundAccount(seeded.mintB, payer.publicKey, 100_000_000);
+      }
+    });
+
+    it("Should swap without feeds while the pool has no guard", async () => {
+      await swap(inBand, true, null);
+    });
+
+    it("Should set the guard and allow swaps within the band", async () => {
+      const event = await getEvent(
+        await setOracleGuard(inBand.pool, pyth, 100, noStalenessLimit),
+        "oracleGuardSetEvent"
+      );
+      assert.equal(event.maxDeviationBps, 100);
+      assert.isAbove(event.timestamp.toNumber(), 0);
+      assert.isAbove(event.slot.toNumber(), 0);
+
+      const guard = await program.account.oracleGuard.fetch(
+        findOracleGuard(inBand.pool)
+      );
+      assert.deepEqual(guard.oracleKind, pyth.kind);
+      assert.isTrue(guard.priceFeedA.equals(pyth.feedA));
+      assert.isTrue(guard.priceFeedB.equals(pyth.feedB));
+      assert.equal(guard.maxDeviationBps, 100);
+      assert.isTrue(
+        (await program.account.pool.fetch(inBand.pool)).oracleGuard
+      );
+
+      // The fee and price impact, about 40 bps, are within the band
+      const balanceB = await getTokenBalance(inBand.payerTokenB);
+      await swap(inBand, true, pyth);
+      assert.isTrue((await getTokenBalance(inBand.payerTokenB)).gt(balanceB));
+      await swap(inBand, false, pyth);
+    });
+
+    it("Should require the guard and its feeds once set", async () => {
+      await expectError(swap(inBand, true, null), "MissingOracleAccount");
+      // The guard's feeds, not another oracle's
+      await expectError(
+        swap(inBand, true, switchboard),
+        "MissingOracleAccount"
+      );
+    });
+
+    it("Should reject a swap beyond the band", async () => {
+      await setOracleGuard(outOfBand.pool, pyth, 100, noStalenessLimit);
+      // The pool prices A at one B, half the feeds' price
+      await expectError(swap(outOfBand, true, pyth), "OraclePriceDeviation");
+      await expectError(swap(outOfBand, false, pyth), "OraclePriceDeviation");
+    });
+
+    it("Should reject a swap on stale prices", async () => {
+      await setOracleGuard(inBand.pool, pyth, 100, 60);
+      await expectError(swap(inBand, true, pyth), "OracleStale");
+    });
+
+    it("Should reject prices less certain than the band", async () => {
+      // The feeds' 10 bps confidence is wider than a 5 bps band
+      await setOracleGuard(inBand.pool, pyth, 5, noStalenessLimit);
+      await expectError(swap(inBand, true, pyth), "OracleConfidenceTooWide");
+    });
+
+    it("Should guard swaps with Switchboard feeds", async () => {
+      await setOracleGuard(inBand.pool, switchboard, 100, noStalenessLimit);
+      await setOracleGuard(outOfBand.pool, switchboard, 100, noStalenessLimit);
+      assert.deepEqual(
+        (
+          await program.account.oracleGuard.fetch(findOracleGuard(inBand.pool))
+        ).oracleKind,
+        switchboard.kind
+      );
+
+      await swap(inBand, true, switchboard);
+      await swap(inBand, false, switchboard);
+      await expectError(
+        swap(outOfBand, true, switchboard),
+        "OraclePriceDeviation"
+      );
+
+      await setOracleGuard(inBand.pool, switchboard, 100, 60);
+      await expectError(swap(inBand, true, switchboard), "OracleStale");
+    });
+
+    it("Should reject a feed its oracle kind's program doesn't own", async () => {
+      // Switchboard feeds read as Pyth ones, and the other way round
+      await expectError(
+        setOracleGuard(
+          inBand.pool,
+          { ...switchboard, kind: pyth.kind },
+          100,
+          60
+        ),
+        "InvalidOracleAccount"
+      );
+      await expectError(
+        setOracleGuard(
+          inBand.pool,
+          { ...pyth, kind: switchboard.kind },
+          100,
+          60
+        ),
+        "InvalidOracleAccount"
+      );
+      await expectError(
+        setOracleGuard(
+          inBand.pool,
+          { ...pyth, feedA: inBand.vaultA },
+          100,
+          60
+        ),
+        "InvalidOracleAccount"
+      );
+      await expectError(
+        setOracleGuard(inBand.pool, pyth, 10_001, 60),
+        "InvalidOracleGuard"
+      );
+    });
+
+    it("Should skip the check once the guard is off", async () => {
+      await setOracleGuard(outOfBand.pool, pyth, 0, 0);
+      assert.isFalse(
+        (await program.account.pool.fetch(outOfBand.pool)).oracleGuard
+      );
+      await swap(outOfBand, true, null);
+    });
+  });
+
+  describe("Price Views", () => {
+    const BN = anchor.BN;
+    let seeded: Awaited<ReturnType<typeof createSeededPool>>;
+    let observations: PublicKey;
+    let record: PublicKey;
+
+    // Q64.64 price of the `num` side in the `den` side
+    const q64Price = (num: anchor.BN, den: anchor.BN) => num.shln(64).div(den);
+
+    const getSpotPrice = () =>
+      program.methods.getSpotPrice().accounts({ pool: seeded.pool }).view();
+
+    const getTwap = (secondsAgo: number) =>
+      program.methods
+        .getTwap(secondsAgo)
+        .accounts({ pool: seeded.pool, observations });
+
+    const recordPrices = (secondsAgo: number) =>
+      readerProgram.methods
+        .recordPrices(secondsAgo)
+        .accounts({
+          payer: payer.publicKey,
+          record,
+          pool: seeded.pool,
+          observations,
+          swapProgram: program.programId,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    before(async () => {
+      seeded = await createSeededPool(1_000_000_000, 2_000_000_000);
+      await fundAccount(seeded.mintA, payer.publicKey, 100_000_000);
+      [observations] = PublicKey.findProgramAddressSync(
+        [Buffer.from("observations"), seeded.pool.toBuffer()],
+        program.programId
+      );
+      [record] = PublicKey.findProgramAddressSync(
+        [Buffer.from("prices"), seeded.pool.toBuffer()],
+        readerProgram.programId
+      );
+      await program.methods
+        .initializeObservations()
+        .accounts({
+          pool: seeded.pool,
+          observations,
+          payer: payer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      // Move the price once, recording an observation, then let it hold
+      await program.methods
+        .swap(
+          new BN(100_000_000),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          new BN(0),
+          null
+        )
+        .accounts({
+          pool: seeded.pool,
+          owner: payer.publicKey,
+          tokenInMint: seeded.mintA,
+          tokenOutMint: seeded.mintB,
+          userTokenIn: seeded.payerTokenA,
+          userTokenOut: seeded.payerTokenB,
+          poolTokenIn: seeded.vaultA,
+          poolTokenOut: seeded.vaultB,
+          observations,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      const { lastOracleTimestamp } = await program.account.pool.fetch(
+        seeded.pool
+      );
+      await waitUntil(lastOracleTimestamp.toNumber() + 3);
+    });
+
+    it("Should take only the pool, read-only", () => {
+      const ix = program.idl.instructions.find(
+        (ix) => ix.name === "getSpotPrice"
+      );
+      assert.equal(ix.accounts.length, 1);
+      assert.notOk((ix.accounts[0] as { writable?: boolean }).writable);
+    });
+
+    it("Should return the reserve ratios as Q64.64", async () => {
+      const pool = await program.account.pool.fetch(seeded.pool);
+      const spot = await getSpotPrice();
+
+      assert.equal(
+        spot.priceA.toString(),
+        q64Price(pool.reserveB, pool.reserveA).toString()
+      );
+      assert.equal(
+        spot.priceB.toString(),
+        q64Price(pool.reserveA, pool.reserveB).toString()
+      );
+    });
+
+    it("Should average a price held over the whole window to itself", async () => {
+      const spot = await getSpotPrice();
+      const twap = await getTwap(2).view();
+
+      assert.equal(twap.priceA.toString(), spot.priceA.toString());
+      assert.equal(twap.priceB.toString(), spot.priceB.toString());
+    });
+
+    it("Should fail with OracleStale for a window the buffer doesn't cover", async () => {
+      await expectError(getTwap(1_000_000).rpc(), "OracleStale");
+    });
+
+    it("Should serve both views to a program by CPI", async () => {
+      await recordPrices(2);
+
+      const pool = await program.account.pool.fetch(seeded.pool);
+      const prices = await readerProgram.account.priceRecord.fetch(record);
+      assert.isTrue(prices.pool.equals(seeded.pool));
+      assert.equal(
+        prices.spotPriceA.toString(),
+        q64Price(pool.reserveB, pool.reserveA).toString()
+      );
+      assert.equal(
+        prices.spotPriceB.toString(),
+        q64Price(pool.reserveA, pool.reserveB).toString()
+      );
+      assert.equal(prices.twapPriceA.toString(), prices.spotPriceA.toString());
+      assert.equal(prices.twapPriceB.toString(), prices.spotPriceB.toString());
+      assert.equal(prices.secondsAgo, 2);
+
+      await expectError(recordPrices(1_000_000), "OracleStale");
+    });
+  });
+
+  describe("Math Parity", () => {
+    const BN = anchor.BN;
+    // The math crate's golden vectors; amounts are decimal strings
+    const vectors = JSON.parse(
+      readFileSync("crates/sol-amm-math/tests/vectors.json", "utf8")
+    );
+    const seededPools = new Map<
+      string,
+      Awaited<ReturnType<typeof createSeededPool>>
+    >();
+
+    // A pool seeded with the given reserves, shared by every vector quoting
+    // against them. Views don't move the reserves, so sharing is safe
+    const pooled = async (reserveA: string, reserveB: string) => {
+      const key = `${reserveA}:${reserveB}`;
+      if (!seededPools.has(key)) {
+        seededPools.set(
+          key,
+          await createSeededPool(Number(reserveA), Number(reserveB))
+        );
+      }
+      return seededPools.get(key);
+    };
+
+    // Vectors a freshly seeded 30 bps pool of 9 decimal mints reproduces:
+    // the program's default fee, its first deposit's LP supply and reserves
+    // small enough to mint from a JavaScript number
+    const onDefaultPool = (vector: Record<string, string | number>) =>
+      vector.error === undefined &&
+      (vector.fee_numerator ?? "30") === "30" &&
+      (vector.fee_denominator ?? "10000") === "10000" &&
+      (vector.lp_supply ?? "1000000") === "1000000" &&
+      [vector.lp_decimals, vector.token_a_decimals, vector.token_b_decimals]
+        .filter((decimals) => decimals !== undefined)
+        .every((decimals) => decimals === 9) &&
+      ["reserve_in", "reserve_out", "pool_a", "pool_b"]
+        .filter((key) => vector[key] !== undefined)
+        .every(
+          (key) =>
+            BigInt(vector[key]) > BigInt(0) &&
+            BigInt(vector[key]) <= BigInt(Number.MAX_SAFE_INTEGER)
+        );
+
+    it("Should quote exact-in swaps as the math crate computes them", async () => {
+      const cases = vectors.swap_exact_in.filter(onDefaultPool);
+      assert.isAbove(cases.length, 0);
+      for (const vector of cases) {
+        const seeded = await pooled(vector.reserve_in, vector.reserve_out);
+        const quote = await program.methods
+          .quoteSwap(new BN(vector.amount_in), true)
+          .accounts({
+            pool: seeded.pool,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+          })
+          .view();
+
+        assert.equal(quote.amountOut.toString(), vector.amount_out);
+        assert.equal(quote.fee.toString(), vector.fee);
+        assert.equal(quote.priceImpactBps, vector.price_impact_bps);
+      }
+    });
+
+    it("Should quote deposits as the math crate computes them", async () => {
+      const cases = vectors.add_liquidity.filter(onDefaultPool);
+      assert.isAbove(cases.length, 0);
+      for (const vector of cases) {
+        const seeded = await pooled(vector.pool_a, vector.pool_b);
+        const lpMint = await getMint(provider.connection, seeded.lpMint);
+        assert.equal(lpMint.supply.toString(), vector.lp_supply);
+        const quote = await program.methods
+          .quoteAddLiquidity(
+            new BN(vector.amount_a),
+            new BN(vector.amount_b)
+          )
+          .accounts({
+            pool: seeded.pool,
+            tokenAMint: seeded.mintA,
+            tokenBMint: seeded.mintB,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+            lpMint: seeded.lpMint,
+          })
+          .view();
+
+        assert.equal(quote.lpTokens.toString(), vector.lp_tokens);
+      }
+    });
+
+    it("Should quote withdrawals as the math crate computes them", async () => {
+      const cases = vectors.remove_liquidity.filter(
+        (vector) => onDefaultPool(vector) && vector.lp_amount !== "0"
+      );
+      assert.isAbove(cases.length, 0);
+      for (const vector of cases) {
+        const seeded = await pooled(vector.pool_a, vector.pool_b);
+        const quote = await program.methods
+          .quoteRemoveLiquidity(new BN(vector.lp_amount))
+          .accounts({
+            pool: seeded.pool,
+            poolTokenA: seeded.vaultA,
+            poolTokenB: seeded.vaultB,
+            lpMint: seeded.lpMint,
+          })
+          .view();
+
+        assert.equal(quote.amountA.toString(), vector.amount_a);
+        assert.equal(quote.amountB.toString(), vector.amount_b);
+      }
+    });
+
+    it("Should charge exact-out swaps what the math crate computes", async () => {
+      const cases = vectors.swap_exact_out.filter(
+        (vector) =>
+          onDefaultPool(vector) &&
+          BigInt(vector.amount_in) <= BigInt(Number.MAX_SAFE_INTEGER)
+      );
+      assert.isAbove(cases.length, 0);
+      for (const vector of cases) {
+        // A fresh pool each, as the swap moves its reserves
+        const seeded = await createSeededPool(
+          Number(vector.reserve_in),
+          Number(vector.reserve_out)
+        );
+        await fundAccount(
+          seeded.mintA,
+          payer.publicKey,
+          Number(vector.amount_in)
+        );
+        const inBefore = await getTokenBalance(seeded.payerTokenA);
+        const outBefore = await getTokenBalance(seeded.payerTokenB);
+
+        await program.methods
+          .swapExactOut(
+            new BN(vector.amount_out),
+            new BN(vector.amount_in),
+            false,
+            new BN(0)
+          )
+          .accounts({
+            pool: seeded.pool,
+            owner: payer.publicKey,
+            tokenInMint: seeded.mintA,
+            tokenOutMint: seeded.mintB,
+            userTokenIn: seeded.payerTokenA,
+            userTokenOut: seeded.payerTokenB,
+            poolTokenIn: seeded.vaultA,
+            poolTokenOut: seeded.vaultB,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+
+        assert.equal(
+          inBefore.sub(await getTokenBalance(seeded.payerTokenA)).toString(),
+          vector.amount_in
+        );
+        assert.isTrue(
+          (await getTokenBalance(seeded.payerTokenB))
+            .sub(outBefore)
+            .gte(new BN(vector.amount_out))
+        );
+      }
+    });
+  });
 });